@@ -0,0 +1,239 @@
+use anyhow::Result;
+use candle_core::Device;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+use tauri::{command, Manager, State};
+use crate::audio_capture::AudioCapture;
+use crate::audio_file;
+use crate::database::Database;
+use crate::transcription::{self, Transcriber, DEFAULT_MODEL_SIZE, SAMPLE_RATE};
+
+// Long enough for the decoder to dominate the timing, short enough that a
+// CPU run doesn't take minutes
+const DEFAULT_BENCHMARK_SECONDS: f64 = 30.0;
+const MAX_BENCHMARK_SECONDS: f64 = 300.0;
+
+// Where Whisper runs. The GPU backends are only there in builds with the
+// cuda, metal or vulkan feature, and on a machine with the hardware.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acceleration {
+    #[default]
+    Cpu,
+    Cuda,
+    Metal,
+    // whisper.cpp only, so builds with the whisper-cpp feature too
+    Vulkan,
+    // The first GPU backend available, else the CPU
+    Auto,
+}
+
+// The backends Auto tries, best first
+const GPU_BACKENDS: [Acceleration; 3] = [Acceleration::Cuda, Acceleration::Metal, Acceleration::Vulkan];
+
+impl Acceleration {
+    pub fn name(self) -> &'static str {
+        match self {
+            Acceleration::Cpu => "CPU",
+            Acceleration::Cuda => "CUDA",
+            Acceleration::Metal => "Metal",
+            Acceleration::Vulkan => "Vulkan",
+            Acceleration::Auto => "automatic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendSupport {
+    pub backend: Acceleration,
+    pub available: bool,
+    // Why it can't be used, when it can't
+    pub reason: Option<String>,
+}
+
+// What this build and machine can run Whisper on, worked out once at startup
+#[derive(Debug, Clone, Serialize)]
+pub struct AccelerationSupport {
+    pub backends: Vec<BackendSupport>,
+}
+
+#[cfg(feature = "vulkan")]
+fn vulkan_device() -> Result<(), String> {
+    match whisper_rs::vulkan::list_devices().is_empty() {
+        true => Err("no Vulkan GPU was found".to_string()),
+        false => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "vulkan"))]
+fn vulkan_device() -> Result<(), String> {
+    Err("not built in".to_string())
+}
+
+// candle finds CUDA and Metal devices for either engine, as the features
+// build them into both
+fn probe(backend: Acceleration) -> BackendSupport {
+    let (compiled, feature, device) = match backend {
+        Acceleration::Cuda => (cfg!(feature = "cuda"), "cuda", Device::new_cuda(0).map(|_| ()).map_err(|e| e.to_string())),
+        Acceleration::Metal => (cfg!(feature = "metal"), "metal", Device::new_metal(0).map(|_| ()).map_err(|e| e.to_string())),
+        Acceleration::Vulkan => (cfg!(feature = "vulkan"), "vulkan", vulkan_device()),
+        Acceleration::Cpu | Acceleration::Auto => return BackendSupport { backend, available: true, reason: None },
+    };
+    let reason = if !compiled {
+        Some(format!("This build doesn't include {}; build with the {} feature", backend.name(), feature))
+    } else {
+        device.err().map(|e| format!("No usable {} device: {}", backend.name(), e))
+    };
+    BackendSupport { backend, available: reason.is_none(), reason }
+}
+
+impl AccelerationSupport {
+    pub fn detect() -> Self {
+        let backends: Vec<BackendSupport> = [Acceleration::Cpu, Acceleration::Cuda, Acceleration::Metal, Acceleration::Vulkan]
+            .into_iter()
+            .map(probe)
+            .collect();
+        for backend in backends.iter().filter(|backend| backend.available && backend.backend != Acceleration::Cpu) {
+            println!("{} is available for transcription", backend.backend.name());
+        }
+        AccelerationSupport { backends }
+    }
+
+    fn support(&self, backend: Acceleration) -> Option<&BackendSupport> {
+        self.backends.iter().find(|support| support.backend == backend)
+    }
+
+    // The backend the setting comes to here, Auto made concrete
+    pub fn resolve(&self, acceleration: Acceleration) -> Result<Acceleration, String> {
+        if acceleration == Acceleration::Auto {
+            let gpu = GPU_BACKENDS.into_iter().find(|&backend| self.support(backend).is_some_and(|support| support.available));
+            return Ok(gpu.unwrap_or(Acceleration::Cpu));
+        }
+        match self.support(acceleration) {
+            Some(support) if !support.available => Err(format!(
+                "{} acceleration isn't available: {}",
+                acceleration.name(),
+                support.reason.clone().unwrap_or_default()
+            )),
+            _ => Ok(acceleration),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccelerationStatus {
+    pub setting: Acceleration,
+    // What the setting runs on here, or None when it isn't available
+    pub active: Option<Acceleration>,
+    pub backends: Vec<BackendSupport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionBenchmarkRun {
+    pub backend: Acceleration,
+    // 0 when the model was already loaded on the backend
+    pub load_ms: u64,
+    pub processing_ms: u64,
+    // Processing time over the audio's length; under 1 is faster than real time
+    pub realtime_factor: Option<f32>,
+    // How many times quicker than the CPU run
+    pub speedup: Option<f32>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionBenchmark {
+    pub model_size: String,
+    pub audio_seconds: f64,
+    pub runs: Vec<TranscriptionBenchmarkRun>,
+}
+
+// The acceleration setting, what it runs on, and what was found at startup
+#[command]
+pub async fn get_transcription_acceleration(
+    capture: State<'_, AudioCapture>,
+    transcriber: State<'_, Transcriber>,
+) -> Result<AccelerationStatus, String> {
+    let setting = capture.get_settings().transcription_acceleration;
+    Ok(AccelerationStatus {
+        setting,
+        active: transcriber.acceleration.resolve(setting).ok(),
+        backends: transcriber.acceleration.backends.clone(),
+    })
+}
+
+// Transcribes the start of a recording on the CPU and on each GPU backend
+// found, timing each so the realtime factors can be compared. Backends that
+// aren't available are listed with the reason. The model is left loaded on
+// the last backend tried and moves back on the next transcription.
+#[command]
+pub async fn benchmark_transcription(
+    recording_id: i64,
+    model_size: Option<String>,
+    seconds: Option<f64>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<TranscriptionBenchmark, String> {
+    let seconds = seconds.unwrap_or(DEFAULT_BENCHMARK_SECONDS);
+    if !(1.0..=MAX_BENCHMARK_SECONDS).contains(&seconds) {
+        return Err(format!("seconds must be between 1 and {}", MAX_BENCHMARK_SECONDS));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let quality = capture.get_settings().resample_quality;
+    let model_size = model_size.unwrap_or_else(|| DEFAULT_MODEL_SIZE.to_string());
+
+    let handle = app_handle.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut samples = audio_file::read_mono_between(Path::new(&record.file_path), 0.0, seconds, SAMPLE_RATE, quality)?;
+        samples.truncate((seconds * SAMPLE_RATE as f64) as usize);
+        let audio_seconds = samples.len() as f64 / SAMPLE_RATE as f64;
+        if samples.is_empty() {
+            return Err(anyhow::anyhow!("Recording {} has no audio", recording_id));
+        }
+
+        let backends = handle.state::<Transcriber>().acceleration.clone();
+        let mut runs: Vec<TranscriptionBenchmarkRun> = Vec::new();
+        for support in backends.backends {
+            let backend = support.backend;
+            if !support.available {
+                runs.push(TranscriptionBenchmarkRun {
+                    backend,
+                    load_ms: 0,
+                    processing_ms: 0,
+                    realtime_factor: None,
+                    speedup: None,
+                    error: support.reason,
+                });
+                continue;
+            }
+            let started = Instant::now();
+            let loaded = transcription::with_model_on(&handle, &model_size, backend, |_| Ok(()));
+            let load_ms = started.elapsed().as_millis() as u64;
+            let started = Instant::now();
+            let result = loaded.and_then(|_| transcription::with_model_on(&handle, &model_size, backend, |model| model.transcribe(&samples, None, &[])));
+            let processing_ms = started.elapsed().as_millis() as u64;
+            let realtime_factor = (processing_ms as f64 / 1000.0 / audio_seconds) as f32;
+            runs.push(TranscriptionBenchmarkRun {
+                backend,
+                load_ms,
+                processing_ms,
+                realtime_factor: result.is_ok().then_some(realtime_factor),
+                speedup: None,
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+
+        let cpu = runs.iter().find(|run| run.backend == Acceleration::Cpu).and_then(|run| run.realtime_factor);
+        for run in &mut runs {
+            run.speedup = cpu.zip(run.realtime_factor).filter(|(_, factor)| *factor > 0.0).map(|(cpu, factor)| cpu / factor);
+        }
+        Ok(TranscriptionBenchmark { model_size, audio_seconds, runs })
+    })
+    .await
+    .map_err(|e| format!("Transcription benchmark failed: {}", e))?
+    .map_err(|e| format!("Transcription benchmark failed: {}", e))
+}
@@ -0,0 +1,223 @@
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+use tauri::{command, State};
+use crate::audio_capture::AudioCapture;
+use crate::audio_file;
+use crate::database::Database;
+use crate::transcript_tracks::{self, TrackSelector};
+use crate::transcription::{self, MODEL_FILES, SAMPLE_RATE};
+use crate::vocabulary;
+
+// How a transcript's words line up with a reference's, by the fewest edits
+#[derive(Debug, Clone, Serialize)]
+pub struct WordErrorRate {
+    pub reference_words: usize,
+    pub hypothesis_words: usize,
+    pub substitutions: usize,
+    pub deletions: usize,
+    pub insertions: usize,
+    // Edits over reference words; can pass 1.0 when words were added
+    pub wer: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelAccuracy {
+    pub model_size: String,
+    pub result: Option<WordErrorRate>,
+    // Processing time over the audio's length; under 1 is faster than real time
+    pub realtime_factor: Option<f32>,
+    // 0 when the model was already loaded
+    pub load_ms: u64,
+    // Transcribing alone, without loading the model
+    pub processing_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccuracyBenchmark {
+    pub record_id: i64,
+    pub audio_seconds: f64,
+    pub language: Option<String>,
+    pub vocabulary: bool,
+    // Lowest word error rate first; failed runs last
+    pub runs: Vec<ModelAccuracy>,
+}
+
+// Lowercase, without punctuation, so "Hello," and "hello" are the same word.
+// Apostrophes inside a word stay, as "it's" isn't "its"; hyphenated words
+// count as their parts, however either side wrote them.
+fn normalize(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || c == '-' || c == '–' || c == '—')
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'' || *c == '’')
+                .map(|c| if c == '’' { '\'' } else { c })
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+                .trim_matches('\'')
+                .to_string()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+// Levenshtein distance over words, keeping the substitution, deletion and
+// insertion counts of one cheapest alignment. Only two rows are held, so an
+// hour's transcript needs kilobytes rather than gigabytes.
+pub fn word_error_rate(reference: &str, hypothesis: &str) -> WordErrorRate {
+    let reference = normalize(reference);
+    let hypothesis = normalize(hypothesis);
+    // (edits, substitutions, deletions, insertions)
+    let mut previous: Vec<(usize, usize, usize, usize)> = (0..=hypothesis.len()).map(|j| (j, 0, 0, j)).collect();
+    let mut current = previous.clone();
+    for (i, expected) in reference.iter().enumerate() {
+        current[0] = (i + 1, 0, i + 1, 0);
+        for (j, heard) in hypothesis.iter().enumerate() {
+            let (cost, s, d, ins) = previous[j];
+            let diagonal = match expected == heard {
+                true => (cost, s, d, ins),
+                false => (cost + 1, s + 1, d, ins),
+            };
+            let (cost, s, d, ins) = previous[j + 1];
+            let deletion = (cost + 1, s, d + 1, ins);
+            let (cost, s, d, ins) = current[j];
+            let insertion = (cost + 1, s, d, ins + 1);
+            current[j + 1] = [diagonal, deletion, insertion].into_iter().min_by_key(|cell| cell.0).unwrap_or(diagonal);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    let (edits, substitutions, deletions, insertions) = previous[hypothesis.len()];
+    WordErrorRate {
+        reference_words: reference.len(),
+        hypothesis_words: hypothesis.len(),
+        substitutions,
+        deletions,
+        insertions,
+        wer: match reference.is_empty() {
+            true => if hypothesis.is_empty() { 0.0 } else { 1.0 },
+            false => edits as f64 / reference.len() as f64,
+        },
+    }
+}
+
+// Sizes with every file in place, in name order
+fn installed_model_sizes(app_handle: &tauri::AppHandle) -> Result<Vec<String>, String> {
+    let dir = transcription::models_dir(app_handle).map_err(|e| format!("Whisper model error: {}", e))?;
+    let mut sizes: Vec<String> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Whisper model error: {}", e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| MODEL_FILES.iter().all(|file| entry.path().join(file).exists()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    sizes.sort();
+    Ok(sizes)
+}
+
+fn check_reference(reference: &str) -> Result<(), String> {
+    match normalize(reference).is_empty() {
+        true => Err("The reference transcript has no words".to_string()),
+        false => Ok(()),
+    }
+}
+
+// Word error rate of one of a recording's transcripts, its own by default,
+// against a reference transcript typed or pasted in by the user. Speaker
+// labels in either should be left out, as they count as words.
+#[command]
+pub async fn compute_word_error_rate(
+    recording_id: i64,
+    reference: String,
+    track: Option<TrackSelector>,
+    app_handle: tauri::AppHandle,
+) -> Result<WordErrorRate, String> {
+    check_reference(&reference)?;
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let track = transcript_tracks::require(&db, recording_id, track.unwrap_or(TrackSelector::Original))?;
+    // Lines hold the words without the labels diarization adds to the text
+    let hypothesis = match track.lines.is_empty() {
+        true => track.text,
+        false => track.lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join(" "),
+    };
+    Ok(word_error_rate(&reference, &hypothesis))
+}
+
+// Transcribes a recording with each model size, those installed by default,
+// and scores each against a reference transcript, so sizes and settings can
+// be compared on the user's own audio. Nothing is stored; the last model
+// tried is left loaded.
+#[command]
+pub async fn benchmark_word_error_rate(
+    recording_id: i64,
+    reference: String,
+    model_sizes: Option<Vec<String>>,
+    language: Option<String>,
+    use_vocabulary: Option<bool>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<AccuracyBenchmark, String> {
+    check_reference(&reference)?;
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let sizes = match model_sizes {
+        Some(sizes) => sizes,
+        None => installed_model_sizes(&app_handle)?,
+    };
+    if sizes.is_empty() {
+        return Err("No Whisper models are installed; download one first".to_string());
+    }
+    let use_vocabulary = use_vocabulary.unwrap_or(true);
+    let vocabulary = match use_vocabulary {
+        true => vocabulary::terms(&db).map_err(|e| format!("Database error: {}", e))?,
+        false => Vec::new(),
+    };
+    let quality = capture.get_settings().resample_quality;
+
+    let handle = app_handle.clone();
+    let spoken = language.clone();
+    let (audio_seconds, mut runs) = tokio::task::spawn_blocking(move || {
+        let samples = audio_file::read_mono(Path::new(&record.file_path), None, SAMPLE_RATE, quality)?;
+        let audio_seconds = samples.len() as f64 / SAMPLE_RATE as f64;
+        let runs: Vec<ModelAccuracy> = sizes.into_iter()
+            .map(|size| {
+                let started = Instant::now();
+                let loaded = transcription::with_model(&handle, &size, |_| Ok(()));
+                let load_ms = started.elapsed().as_millis() as u64;
+                let mut processing_ms = 0;
+                let transcribed = loaded.and_then(|_| {
+                    transcription::with_model(&handle, &size, |model| {
+                        let started = Instant::now();
+                        let result = model.transcribe(&samples, spoken.as_deref(), &vocabulary);
+                        processing_ms = started.elapsed().as_millis() as u64;
+                        result
+                    })
+                });
+                match transcribed {
+                    Ok(result) => ModelAccuracy {
+                        model_size: size,
+                        result: Some(word_error_rate(&reference, &result.text)),
+                        realtime_factor: (audio_seconds > 0.0).then(|| (processing_ms as f64 / 1000.0 / audio_seconds) as f32),
+                        load_ms,
+                        processing_ms,
+                        error: None,
+                    },
+                    Err(e) => ModelAccuracy { model_size: size, result: None, realtime_factor: None, load_ms, processing_ms, error: Some(e.to_string()) },
+                }
+            })
+            .collect();
+        Ok::<_, anyhow::Error>((audio_seconds, runs))
+    })
+    .await
+    .map_err(|e| format!("Accuracy benchmark failed: {}", e))?
+    .map_err(|e| format!("Accuracy benchmark failed: {}", e))?;
+
+    runs.sort_by(|a, b| match (&a.result, &b.result) {
+        (Some(a), Some(b)) => a.wer.total_cmp(&b.wer),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    Ok(AccuracyBenchmark { record_id: recording_id, audio_seconds, language, vocabulary: use_vocabulary, runs })
+}
@@ -0,0 +1,66 @@
+use crate::audio_capture::StreamFormat;
+
+// Gain is worked out per 10 ms block and ramped across it, so it never steps
+const BLOCK_MS: u32 = 10;
+
+// Below this the input is taken for room noise and the gain is held, so
+// pauses don't get boosted into a roar
+const NOISE_FLOOR_DB: f32 = -60.0;
+
+// Gain comes down quickly when it gets loud and goes back up slowly
+const ATTACK_DB_PER_SECOND: f32 = 200.0;
+const RELEASE_DB_PER_SECOND: f32 = 6.0;
+
+// Brings live capture to a steady RMS level. Gain only ever goes up to
+// `max_gain_db`, and peaks above full scale are clipped by the recorder.
+pub struct AutomaticGainControl {
+    channels: usize,
+    block_frames: usize,
+    block_seconds: f32,
+    gain_db: f32,
+}
+
+impl AutomaticGainControl {
+    pub fn new(format: StreamFormat) -> Self {
+        AutomaticGainControl {
+            channels: format.channels.max(1) as usize,
+            block_frames: (format.sample_rate * BLOCK_MS / 1000).max(1) as usize,
+            block_seconds: BLOCK_MS as f32 / 1000.0,
+            gain_db: 0.0,
+        }
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    // Applies the gain in place, adjusting it towards `target_db` as it goes
+    pub fn process(&mut self, samples: &mut [f32], target_db: f32, max_gain_db: f32) {
+        for block in samples.chunks_mut(self.block_frames * self.channels) {
+            let power = block.iter().map(|&sample| sample * sample).sum::<f32>() / block.len() as f32;
+            let level_db = if power > 0.0 { 10.0 * power.log10() } else { f32::NEG_INFINITY };
+
+            let start_gain = self.gain_db;
+            if level_db > NOISE_FLOOR_DB {
+                let wanted = (target_db - level_db).clamp(0.0, max_gain_db);
+                let seconds = self.block_seconds * block.len() as f32 / (self.block_frames * self.channels) as f32;
+                self.gain_db = if wanted < self.gain_db {
+                    (self.gain_db - ATTACK_DB_PER_SECOND * seconds).max(wanted)
+                } else {
+                    (self.gain_db + RELEASE_DB_PER_SECOND * seconds).min(wanted)
+                };
+            }
+            // The ceiling may have been lowered since the last block
+            self.gain_db = self.gain_db.min(max_gain_db);
+
+            let frames = block.len() / self.channels;
+            for (index, frame) in block.chunks_mut(self.channels).enumerate() {
+                let gain_db = start_gain + (self.gain_db - start_gain) * (index + 1) as f32 / frames as f32;
+                let gain = 10f32.powf(gain_db / 20.0);
+                for sample in frame {
+                    *sample *= gain;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use crate::ai_models::{GenerationOptions, LlamaResponse};
+
+// Entries kept in memory, and on disk when that is turned on; the oldest go
+// first beyond these
+const MEMORY_CAPACITY: usize = 256;
+const DISK_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    response: LlamaResponse,
+    // Unix timestamp (seconds) after which the entry is stale
+    expires_at: i64,
+}
+
+impl CacheEntry {
+    fn is_expired(&self) -> bool {
+        chrono::Utc::now().timestamp() >= self.expires_at
+    }
+}
+
+// Responses keyed by (model, prompt, resolved options). Lookups hit the
+// in-memory LRU first and, when persistence is on, fall back to one JSON
+// file per entry on disk so answers survive restarts. Those files hold the
+// prompts and answers, transcripts included, in plain text, so persistence
+// is off unless turned on, and turning it off deletes them.
+pub struct ResponseCache {
+    memory: Mutex<LruCache<String, CacheEntry>>,
+    disk_dir: RwLock<Option<PathBuf>>,
+    persistent: RwLock<bool>,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        ResponseCache {
+            memory: Mutex::new(LruCache::new(NonZeroUsize::new(MEMORY_CAPACITY).unwrap_or(NonZeroUsize::MIN))),
+            disk_dir: RwLock::new(None),
+            persistent: RwLock::new(false),
+            ttl,
+        }
+    }
+
+    // Called from setup once the app data directory is known
+    pub fn set_disk_dir(&self, dir: PathBuf) {
+        if let Ok(mut disk_dir) = self.disk_dir.write() {
+            *disk_dir = Some(dir);
+        }
+        self.sweep();
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.persistent.read().map(|persistent| *persistent).unwrap_or(false)
+    }
+
+    pub fn set_persistent(&self, persistent: bool) {
+        if let Ok(mut current) = self.persistent.write() {
+            *current = persistent;
+        }
+        self.sweep();
+    }
+
+    // Removes expired entries from disk, then the oldest beyond
+    // DISK_CAPACITY; all of them while persistence is off
+    fn sweep(&self) {
+        let Some(dir) = self.disk_dir.read().ok().and_then(|dir| dir.clone()) else { return };
+        if !self.is_persistent() {
+            remove_entries(&dir);
+            return;
+        }
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            eprintln!("Failed to create AI cache directory {}: {}", dir.display(), e);
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else { return };
+        let mut kept: Vec<(std::time::SystemTime, PathBuf)> = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(modified) = entry.metadata().ok().and_then(|metadata| metadata.modified().ok()) else { continue };
+            // Entries are written once, so their age is how long ago they were cached
+            if modified.elapsed().is_ok_and(|age| age >= self.ttl) {
+                let _ = std::fs::remove_file(&path);
+            } else {
+                kept.push((modified, path));
+            }
+        }
+        if kept.len() > DISK_CAPACITY {
+            kept.sort_by_key(|(modified, _)| *modified);
+            for (_, path) in &kept[..kept.len() - DISK_CAPACITY] {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    pub fn key(model: &str, prompt: &str, options: &GenerationOptions) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        hasher.update(prompt.as_bytes());
+        hasher.update([0]);
+        hasher.update(serde_json::to_string(options).unwrap_or_default().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn entry_path(&self, key: &str) -> Option<PathBuf> {
+        if !self.is_persistent() {
+            return None;
+        }
+        let disk_dir = self.disk_dir.read().ok()?;
+        disk_dir.as_ref().map(|dir| dir.join(format!("{}.json", key)))
+    }
+
+    pub fn get(&self, key: &str) -> Option<LlamaResponse> {
+        if let Ok(mut memory) = self.memory.lock() {
+            match memory.get(key) {
+                Some(entry) if !entry.is_expired() => return Some(entry.response.clone()),
+                Some(_) => {
+                    memory.pop(key);
+                }
+                None => {}
+            }
+        }
+
+        let path = self.entry_path(key)?;
+        let entry: CacheEntry = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        if entry.is_expired() {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+
+        let response = entry.response.clone();
+        if let Ok(mut memory) = self.memory.lock() {
+            memory.put(key.to_string(), entry);
+        }
+        Some(response)
+    }
+
+    pub fn put(&self, key: &str, response: &LlamaResponse) {
+        let entry = CacheEntry {
+            response: response.clone(),
+            expires_at: chrono::Utc::now().timestamp() + self.ttl.as_secs() as i64,
+        };
+
+        if let Some(path) = self.entry_path(key) {
+            match serde_json::to_string(&entry) {
+                Ok(json) => {
+                    if let Err(e) = std::fs::write(&path, json) {
+                        eprintln!("Failed to write AI cache entry: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to serialize AI cache entry: {}", e),
+            }
+            self.sweep();
+        }
+
+        if let Ok(mut memory) = self.memory.lock() {
+            memory.put(key.to_string(), entry);
+        }
+    }
+
+    // Drops every entry, in memory and on disk. Returns how many were removed.
+    pub fn clear(&self) -> usize {
+        let mut removed = match self.memory.lock() {
+            Ok(mut memory) => {
+                let count = memory.len();
+                memory.clear();
+                count
+            }
+            Err(_) => 0,
+        };
+
+        let disk_dir = self.disk_dir.read().ok().and_then(|dir| dir.clone());
+        if let Some(dir) = disk_dir {
+            // Disk holds a superset of what is in memory
+            removed = removed.max(remove_entries(&dir));
+        }
+
+        removed
+    }
+}
+
+// Deletes every entry file in `dir`, returning how many there were
+fn remove_entries(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json") && std::fs::remove_file(path).is_ok())
+        .count()
+}
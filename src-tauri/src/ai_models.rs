@@ -1,8 +1,48 @@
-use tauri::command;
+use tauri::{command, Emitter, State};
 use serde::{Deserialize, Serialize};
-use reqwest;
 use anyhow::Result;
 use std::collections::HashMap;
+use std::sync::RwLock;
+use crate::ai_cache::ResponseCache;
+use crate::context_window::{self, ContextReport, ScoredDocument};
+use crate::ai_requests::{ActiveRequest, AiRequestRegistry, AiRequestStarted, CancelledRequest, AI_REQUEST_STARTED_EVENT};
+use crate::cloud_backends::{self, AnthropicBackend};
+use crate::credentials::CredentialStore;
+use crate::demo_backend::MockBackend;
+use crate::dsp;
+use crate::embeddings::{LocalEmbedder, OllamaEmbedder};
+use crate::rag::{self, Chunk, Citation, ContextSource, RAGContext, RagSettings};
+use crate::reranker;
+use crate::query_expansion;
+use crate::database::Database;
+use crate::guardrails::{self, GuardrailSettings};
+use crate::llama_cpp_engine::LlamaCppBackend;
+use crate::prompt_templates::{PromptTemplates, AUDIO_ANALYSIS_JSON_TEMPLATE, AUDIO_ANALYSIS_TEMPLATE, ENHANCED_CHAT_TEMPLATE, RAG_QUERY_TEMPLATE};
+use crate::structured_output::{query_structured, AudioAnalysisReport};
+use crate::system_prompts::SystemPrompts;
+use crate::tokenizer;
+use crate::transcript_index::TRANSCRIPTS_INDEX;
+use crate::vector_store::{self, SearchHit, VectorStore};
+use crate::resilience::{BreakerStatus, CircuitBreaker, RetryPolicy};
+use crate::llm_backend::{probe_url, read_ndjson_stream, validate_endpoint, BackendKind, BackendOutput, GenerationRequest, LlmBackend, OllamaBackend, OpenAiCompatibleBackend};
+
+// Settings keys for persisted AI configuration
+const GENERATION_DEFAULTS_KEY: &str = "generation_defaults";
+const MODEL_CONFIGS_KEY: &str = "model_configs";
+const RETRY_POLICY_KEY: &str = "retry_policy";
+const OLLAMA_HOST_KEY: &str = "ollama_host";
+const GUARDRAILS_KEY: &str = "guardrails";
+const FALLBACK_CHAIN_KEY: &str = "model_fallback_chain";
+const DEMO_MODE_KEY: &str = "demo_mode";
+const RAG_SETTINGS_KEY: &str = "rag_settings";
+const AI_CACHE_ON_DISK_KEY: &str = "ai_cache_on_disk";
+
+// Models tried in order when a chat command isn't given a specific model
+const DEFAULT_FALLBACK_CHAIN: [&str; 8] = ["llama3.2:1b", "llama3.2", "llama3:8b", "llama3", "llama3-8b", "llama2:7b", "llama2", "llama"];
+
+// How long a cached answer is reused for an identical query. Only replies at
+// temperature 0 are cached, as any other would differ if asked again.
+const CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
@@ -11,27 +51,295 @@ pub struct ModelConfig {
     pub api_endpoint: Option<String>,
     pub local_path: Option<String>,
     pub enabled: bool,
+    #[serde(default)]
+    pub backend: BackendKind,
+    // Send the bearer token / headers stored in the keychain for this model
+    #[serde(default)]
+    pub authenticated: bool,
+    // Defaults for this model; per-request options take precedence
+    #[serde(default)]
+    pub runtime: OllamaRuntimeOptions,
+}
+
+// Ollama server-side knobs. llama.cpp models honour num_ctx; other backends ignore them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaRuntimeOptions {
+    // Context window in tokens
+    pub num_ctx: Option<u32>,
+    // Layers offloaded to the GPU (0 = CPU only)
+    pub num_gpu: Option<i32>,
+    // How long the model stays loaded after a request, e.g. "10m", "1h", "0" or "-1" (forever)
+    pub keep_alive: Option<String>,
+}
+
+impl OllamaRuntimeOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.num_ctx == Some(0) {
+            return Err("num_ctx must be greater than 0".to_string());
+        }
+        if self.num_gpu.is_some_and(|layers| layers < -1) {
+            return Err("num_gpu must be -1 (all layers) or a layer count".to_string());
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            let number = keep_alive.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let unit = &keep_alive[number.len()..];
+            if number.parse::<f64>().is_err() || !["", "ms", "s", "m", "h"].contains(&unit) {
+                return Err(format!("keep_alive must be a duration like \"10m\" or \"-1\", got \"{}\"", keep_alive));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn or(&self, fallback: &OllamaRuntimeOptions) -> OllamaRuntimeOptions {
+        OllamaRuntimeOptions {
+            num_ctx: self.num_ctx.or(fallback.num_ctx),
+            num_gpu: self.num_gpu.or(fallback.num_gpu),
+            keep_alive: self.keep_alive.clone().or_else(|| fallback.keep_alive.clone()),
+        }
+    }
+}
+
+impl ModelConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.runtime.validate()?;
+        match self.backend {
+            BackendKind::LlamaCpp => {
+                if self.local_path.as_deref().is_none_or(|path| path.trim().is_empty()) {
+                    return Err(format!("Model '{}' needs a local GGUF path for llama.cpp", self.name));
+                }
+            }
+            BackendKind::Ollama | BackendKind::OpenAiCompatible => {
+                let endpoint = self.api_endpoint.as_deref()
+                    .ok_or_else(|| format!("Model '{}' needs an API endpoint", self.name))?;
+                validate_endpoint(endpoint)?;
+            }
+            BackendKind::Mock => {}
+            // The provider's public endpoint is used unless another one is set
+            BackendKind::OpenAi | BackendKind::Anthropic => {
+                if let Some(endpoint) = self.api_endpoint.as_deref() {
+                    if validate_endpoint(endpoint)?.scheme() != "https" {
+                        return Err(format!("Cloud endpoint for model '{}' must use https", self.name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sampling parameters for a single request. Anything left as None falls back to
+// the per-model defaults, and then to the built-in values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    // Ask the backend to constrain output to a JSON object
+    pub json: Option<bool>,
+    // Refuse cloud models for this query, e.g. when the prompt carries a sensitive recording
+    pub local_only: Option<bool>,
+    // False to always ask the model, even when an identical query is cached
+    pub cache: Option<bool>,
+    #[serde(flatten, default)]
+    pub runtime: OllamaRuntimeOptions,
+}
+
+impl GenerationOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(format!("temperature must be between 0.0 and 2.0, got {}", temperature));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(format!("top_p must be between 0.0 and 1.0, got {}", top_p));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be greater than 0".to_string());
+        }
+        self.runtime.validate()
+    }
+    
+    // Fill unset fields from `fallback`
+    pub fn or(&self, fallback: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions {
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            json: self.json.or(fallback.json),
+            local_only: self.local_only.or(fallback.local_only),
+            cache: self.cache.or(fallback.cache),
+            runtime: self.runtime.or(&fallback.runtime),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlamaResponse {
     pub text: String,
+    // prompt_tokens + completion_tokens
     pub tokens_used: usize,
+    #[serde(default)]
+    pub prompt_tokens: usize,
+    #[serde(default)]
+    pub completion_tokens: usize,
     pub processing_time_ms: u64,
-    pub confidence: f32,
+    // None when the backend didn't return token probabilities; see confidence_source
+    pub confidence: Option<f32>,
+    #[serde(default)]
+    pub confidence_source: ConfidenceSource,
+    // True when served from the response cache instead of the model
+    #[serde(default)]
+    pub cached: bool,
+    // Guardrail rules that changed the text
+    #[serde(default)]
+    pub guardrails_applied: Vec<String>,
+    // Which backend produced the text; None for answers cached before this was recorded
+    #[serde(default)]
+    pub backend: Option<BackendKind>,
+    // True when the demo backend answered instead of a real model
+    #[serde(default)]
+    pub simulated: bool,
+    // Set when history or documents had to be cut to fit the context window
+    #[serde(default)]
+    pub context: Option<ContextReport>,
+    // Context chunks the answer cited; only set by RAG queries
+    #[serde(default)]
+    pub citations: Vec<Citation>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RAGContext {
-    pub query: String,
-    pub documents: Vec<String>,
-    pub embeddings: Vec<Vec<f32>>,
-    pub similarity_scores: Vec<f32>,
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceSource {
+    // Geometric mean of the generated tokens' probabilities (1 / perplexity)
+    TokenLogprobs,
+    // The backend gave no probabilities; don't base decisions on this response's confidence
+    #[default]
+    Unknown,
+}
+
+fn confidence_from_logprobs(logprobs: &[f32]) -> (Option<f32>, ConfidenceSource) {
+    let finite: Vec<f32> = logprobs.iter().copied().filter(|lp| lp.is_finite()).collect();
+    if finite.is_empty() {
+        return (None, ConfidenceSource::Unknown);
+    }
+    
+    let mean = finite.iter().sum::<f32>() / finite.len() as f32;
+    (Some(mean.exp().clamp(0.0, 1.0)), ConfidenceSource::TokenLogprobs)
+}
+
+// Event names used when streaming chat output to the frontend
+pub const CHAT_TOKEN_EVENT: &str = "dwight://chat-token";
+pub const CHAT_COMPLETE_EVENT: &str = "dwight://chat-complete";
+pub const MODEL_PULL_PROGRESS_EVENT: &str = "dwight://model-pull-progress";
+
+// Used for model management and health checks until the user picks another host
+const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatTokenEvent {
+    pub request_id: String,
+    pub model: String,
+    pub token: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatCompleteEvent {
+    pub request_id: String,
+    pub model: String,
+    pub text: String,
+    pub tokens_used: usize,
+    pub processing_time_ms: u64,
+    pub prompt_eval_count: Option<u64>,
+    pub eval_count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelPullProgress {
+    pub model: String,
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub percent: Option<f32>,
+}
+
+// Upper bound on models queried at once by compare_models
+const MAX_COMPARED_MODELS: usize = 8;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelComparison {
+    pub model: String,
+    pub response: Option<LlamaResponse>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonResult {
+    pub prompt: String,
+    pub results: Vec<ModelComparison>,
+    // Wall-clock time for the whole fan-out
+    pub total_time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointTest {
+    pub endpoint: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupResult {
+    pub model: String,
+    pub ok: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiHealth {
+    pub reachable: bool,
+    pub endpoint: String,
+    // Round trip of the /api/tags request
+    pub latency_ms: Option<u64>,
+    pub version: Option<String>,
+    pub installed_models: Vec<String>,
+    // Models currently loaded in memory (/api/ps)
+    pub loaded_models: Vec<String>,
+    pub warmup: Option<WarmupResult>,
+    pub error: Option<String>,
+}
+
+// One instance lives in Tauri managed state for the whole app lifetime, so the
+// HTTP connection pool and any updated model config are shared by every command.
 pub struct AdvancedAI {
-    models: HashMap<String, ModelConfig>,
+    models: RwLock<HashMap<String, ModelConfig>>,
+    generation_defaults: RwLock<HashMap<String, GenerationOptions>>,
     client: reqwest::Client,
+    ollama: OllamaBackend,
+    openai_compatible: OpenAiCompatibleBackend,
+    llama_cpp: LlamaCppBackend,
+    anthropic: AnthropicBackend,
+    mock: MockBackend,
+    embedder: OllamaEmbedder,
+    local_embedder: LocalEmbedder,
+    requests: AiRequestRegistry,
+    retry_policy: RwLock<RetryPolicy>,
+    breaker: CircuitBreaker,
+    cache: ResponseCache,
+    system_prompts: SystemPrompts,
+    prompt_templates: PromptTemplates,
+    ollama_host: RwLock<String>,
+    credentials: CredentialStore,
+    guardrails: RwLock<GuardrailSettings>,
+    fallback_chain: RwLock<Vec<String>>,
+    demo_mode: RwLock<bool>,
+    rag_settings: RwLock<RagSettings>,
 }
 
 impl AdvancedAI {
@@ -45,6 +353,9 @@ impl AdvancedAI {
             api_endpoint: Some("http://localhost:11434/api/generate".to_string()), // Ollama endpoint
             local_path: None,
             enabled: true,
+            backend: BackendKind::Ollama,
+            authenticated: false,
+            runtime: OllamaRuntimeOptions::default(),
         });
         
         models.insert("llama3-70b".to_string(), ModelConfig {
@@ -53,6 +364,9 @@ impl AdvancedAI {
             api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
             local_path: None,
             enabled: false, // Disabled by default due to resource requirements
+            backend: BackendKind::Ollama,
+            authenticated: false,
+            runtime: OllamaRuntimeOptions::default(),
         });
         
         // Configure Mixtral models
@@ -62,6 +376,9 @@ impl AdvancedAI {
             api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
             local_path: None,
             enabled: true,
+            backend: BackendKind::Ollama,
+            authenticated: false,
+            runtime: OllamaRuntimeOptions::default(),
         });
         
         // Configure Mistral models
@@ -71,81 +388,815 @@ impl AdvancedAI {
             api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
             local_path: None,
             enabled: true,
+            backend: BackendKind::Ollama,
+            authenticated: false,
+            runtime: OllamaRuntimeOptions::default(),
         });
         
         let client = reqwest::Client::new();
         
-        AdvancedAI { models, client }
+        AdvancedAI {
+            models: RwLock::new(models),
+            generation_defaults: RwLock::new(HashMap::new()),
+            ollama: OllamaBackend::new(client.clone()),
+            openai_compatible: OpenAiCompatibleBackend::new(client.clone()),
+            llama_cpp: LlamaCppBackend::new(),
+            anthropic: AnthropicBackend::new(client.clone()),
+            mock: MockBackend,
+            embedder: OllamaEmbedder::new(client.clone()),
+            local_embedder: LocalEmbedder::new(),
+            requests: AiRequestRegistry::new(),
+            retry_policy: RwLock::new(RetryPolicy::default()),
+            breaker: CircuitBreaker::new(),
+            cache: ResponseCache::new(CACHE_TTL),
+            system_prompts: SystemPrompts::new(),
+            prompt_templates: PromptTemplates::new(),
+            ollama_host: RwLock::new(DEFAULT_OLLAMA_HOST.to_string()),
+            credentials: CredentialStore::new(),
+            guardrails: RwLock::new(GuardrailSettings::default()),
+            fallback_chain: RwLock::new(DEFAULT_FALLBACK_CHAIN.iter().map(|model| model.to_string()).collect()),
+            demo_mode: RwLock::new(false),
+            rag_settings: RwLock::new(RagSettings::default()),
+            client,
+        }
     }
     
-    pub async fn query_llama(&self, prompt: &str, model: &str) -> Result<LlamaResponse> {
+    // Restore persisted settings; called once from setup after the database is ready
+    pub fn load_settings(&self, db: &Database) {
+        match db.get_setting(GENERATION_DEFAULTS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<HashMap<String, GenerationOptions>>(&json) {
+                Ok(defaults) => {
+                    if let Ok(mut current) = self.generation_defaults.write() {
+                        *current = defaults;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid generation defaults: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load generation defaults: {}", e),
+        }
+        
+        // User-configured models are merged over the built-in ones
+        match db.get_setting(MODEL_CONFIGS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<HashMap<String, ModelConfig>>(&json) {
+                Ok(configs) => {
+                    if let Ok(mut models) = self.models.write() {
+                        models.extend(configs);
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid model configs: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load model configs: {}", e),
+        }
+        
+        match db.get_setting(RETRY_POLICY_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<RetryPolicy>(&json) {
+                Ok(policy) => {
+                    if let Ok(mut current) = self.retry_policy.write() {
+                        *current = policy;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid retry policy: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load retry policy: {}", e),
+        }
+        
+        match db.get_setting(OLLAMA_HOST_KEY) {
+            Ok(Some(host)) => match validate_endpoint(&host) {
+                Ok(_) => {
+                    if let Ok(mut current) = self.ollama_host.write() {
+                        *current = host.trim_end_matches('/').to_string();
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid Ollama host: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load Ollama host: {}", e),
+        }
+        
+        match db.get_setting(GUARDRAILS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<GuardrailSettings>(&json) {
+                Ok(settings) => {
+                    if let Ok(mut current) = self.guardrails.write() {
+                        *current = settings;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid guardrail settings: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load guardrail settings: {}", e),
+        }
+        
+        match db.get_setting(FALLBACK_CHAIN_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<Vec<String>>(&json) {
+                Ok(chain) if validate_fallback_chain(&chain).is_ok() => {
+                    if let Ok(mut current) = self.fallback_chain.write() {
+                        *current = chain;
+                    }
+                }
+                Ok(_) => eprintln!("Ignoring invalid model fallback chain"),
+                Err(e) => eprintln!("Ignoring invalid model fallback chain: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load model fallback chain: {}", e),
+        }
+        
+        match db.get_setting(DEMO_MODE_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<bool>(&json) {
+                Ok(enabled) => {
+                    if let Ok(mut current) = self.demo_mode.write() {
+                        *current = enabled;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid demo mode setting: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load demo mode setting: {}", e),
+        }
+        
+        match db.get_setting(RAG_SETTINGS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<RagSettings>(&json) {
+                Ok(settings) => {
+                    if let Ok(mut current) = self.rag_settings.write() {
+                        *current = settings;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid RAG settings: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load RAG settings: {}", e),
+        }
+        
+        match db.get_setting(AI_CACHE_ON_DISK_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<bool>(&json) {
+                Ok(enabled) => self.cache.set_persistent(enabled),
+                Err(e) => eprintln!("Ignoring invalid AI cache setting: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load AI cache setting: {}", e),
+        }
+        
+        self.system_prompts.load(db);
+        self.prompt_templates.load(db);
+    }
+    
+    // Add or replace a model config and persist it alongside the other user configs
+    pub fn set_model_config(&self, model: &str, config: ModelConfig, db: &Database) -> Result<()> {
+        let mut saved: HashMap<String, ModelConfig> = match db.get_setting(MODEL_CONFIGS_KEY)? {
+            Some(json) => serde_json::from_str(&json).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        saved.insert(model.to_string(), config.clone());
+        
+        db.set_setting(MODEL_CONFIGS_KEY, &serde_json::to_string(&saved)?)?;
+        
+        if let Ok(mut models) = self.models.write() {
+            models.insert(model.to_string(), config);
+        }
+        Ok(())
+    }
+    
+    pub fn ollama_host(&self) -> String {
+        self.ollama_host.read()
+            .map(|host| host.clone())
+            .unwrap_or_else(|_| DEFAULT_OLLAMA_HOST.to_string())
+    }
+    
+    fn ollama_url(&self, path: &str) -> String {
+        format!("{}{}", self.ollama_host(), path)
+    }
+    
+    pub fn set_ollama_host(&self, host: &str, db: &Database) -> Result<()> {
+        let host = host.trim().trim_end_matches('/');
+        validate_endpoint(host).map_err(|e| anyhow::anyhow!(e))?;
+        
+        db.set_setting(OLLAMA_HOST_KEY, host)?;
+        
+        if let Ok(mut current) = self.ollama_host.write() {
+            *current = host.to_string();
+        }
+        Ok(())
+    }
+    
+    // Checks that the server behind an endpoint answers, without generating anything
+    pub async fn test_endpoint(&self, kind: BackendKind, endpoint: &str, headers: reqwest::header::HeaderMap) -> EndpointTest {
+        let mut result = EndpointTest {
+            endpoint: endpoint.to_string(),
+            reachable: false,
+            latency_ms: None,
+            status: None,
+            error: None,
+        };
+        
+        let url = match probe_url(kind, endpoint) {
+            Ok(url) => url,
+            Err(e) => {
+                result.error = Some(e);
+                return result;
+            }
+        };
+        
         let start_time = std::time::Instant::now();
+        match self.client.get(&url).headers(headers).timeout(std::time::Duration::from_secs(5)).send().await {
+            Ok(response) => {
+                result.latency_ms = Some(start_time.elapsed().as_millis() as u64);
+                result.status = Some(response.status().as_u16());
+                result.reachable = response.status().is_success();
+                if !result.reachable {
+                    result.error = Some(format!("{} returned status {}", url, response.status()));
+                }
+            }
+            Err(e) => result.error = Some(format!("Failed to connect to {}: {}", url, e)),
+        }
+        result
+    }
+    
+    pub fn get_guardrail_settings(&self) -> GuardrailSettings {
+        self.guardrails.read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+    
+    pub fn set_guardrail_settings(&self, settings: GuardrailSettings, db: &Database) -> Result<()> {
+        db.set_setting(GUARDRAILS_KEY, &serde_json::to_string(&settings)?)?;
+        
+        if let Ok(mut current) = self.guardrails.write() {
+            *current = settings;
+        }
+        Ok(())
+    }
+    
+    // Runs on every response before it leaves AdvancedAI. The cache keeps the raw
+    // text, so changed settings also apply to cached answers.
+    fn apply_guardrails(&self, response: &mut LlamaResponse, options: &GenerationOptions) {
+        let settings = self.get_guardrail_settings();
+        response.guardrails_applied = guardrails::apply_to_response(&settings, &mut response.text, options.json.unwrap_or(false));
+    }
+    
+    pub fn get_fallback_chain(&self) -> Vec<String> {
+        self.fallback_chain.read()
+            .map(|chain| chain.clone())
+            .unwrap_or_default()
+    }
+    
+    pub fn set_fallback_chain(&self, chain: Vec<String>, db: &Database) -> Result<()> {
+        db.set_setting(FALLBACK_CHAIN_KEY, &serde_json::to_string(&chain)?)?;
+        
+        if let Ok(mut current) = self.fallback_chain.write() {
+            *current = chain;
+        }
+        Ok(())
+    }
+    
+    // Tries each model of the fallback chain until one answers. A cancelled
+    // request stops at the model it was on; otherwise the last model's error is returned.
+    pub(crate) async fn query_fallback_chain(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        stream: bool,
+        active: &ActiveRequest<'_>,
+        app_handle: &tauri::AppHandle,
+    ) -> Result<LlamaResponse> {
+        let mut last_error = anyhow::anyhow!("The model fallback chain is empty");
+        for model_name in self.get_fallback_chain() {
+            // A cancel hands back only what the model it stopped on wrote
+            active.reset_partial();
+            match query_maybe_streaming(self, prompt, &model_name, options, stream, active, app_handle).await {
+                Ok(response) => return Ok(response),
+                Err(e) if active.is_cancelled() => return Err(e),
+                Err(e) => {
+                    last_error = anyhow::anyhow!("Model '{}' failed: {}", model_name, e);
+                    println!("Trying next model after error: {}", last_error);
+                }
+            }
+        }
+        Err(last_error)
+    }
+    
+    pub fn get_rag_settings(&self) -> RagSettings {
+        self.rag_settings.read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+    
+    pub fn set_rag_settings(&self, settings: RagSettings, db: &Database) -> Result<()> {
+        db.set_setting(RAG_SETTINGS_KEY, &serde_json::to_string(&settings)?)?;
+        
+        if let Ok(mut current) = self.rag_settings.write() {
+            *current = settings;
+        }
+        Ok(())
+    }
+    
+    // Embeddings from the configured embedding model, one per text. Falls back to
+    // the local ONNX model when Ollama is down or the model isn't pulled.
+    pub async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(self.embed_with_model(texts).await?.1)
+    }
+    
+    // Like embed_texts, along with the name of the model that produced the vectors
+    pub async fn embed_with_model(&self, texts: &[String]) -> Result<(String, Vec<Vec<f32>>)> {
+        let model = self.get_rag_settings().embedding_model;
+        match self.embedder.embed(&self.ollama_host(), &model, texts).await {
+            Ok(embeddings) => Ok((model, embeddings)),
+            Err(ollama_error) => {
+                let local_model = self.local_embedder.model_name();
+                eprintln!("Ollama embedding failed, trying {}: {}", local_model, ollama_error);
+                self.local_embedder.embed(texts).await
+                    .map(|embeddings| (local_model.to_string(), embeddings))
+                    .map_err(|local_error| anyhow::anyhow!("{}; local fallback: {}", ollama_error, local_error))
+            }
+        }
+    }
+    
+    // The `k` stored chunks that best match the query, by vector similarity and
+    // keywords. Falls back to keywords only when nothing can embed the query.
+    pub async fn retrieve(&self, query: &str, index: &str, k: usize, app_handle: &tauri::AppHandle) -> Result<Vec<SearchHit>> {
+        let keyword_weight = self.get_rag_settings().keyword_weight;
+        let embedded = if keyword_weight < 1.0 {
+            match self.embed_with_model(&[query.to_string()]).await {
+                Ok((model, mut embeddings)) => Some((model, embeddings.pop().unwrap_or_default())),
+                Err(e) => {
+                    eprintln!("Embedding failed, using keyword search only: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        
+        let embedding = embedded.as_ref().map(|(model, embedding)| (model.as_str(), embedding.as_slice()));
+        VectorStore::new(app_handle)?.hybrid_search(index, query, embedding, k, keyword_weight)
+    }
+    
+    // Retrieves for the query and for paraphrases of it, fusing the rankings.
+    // A chunk keeps its best similarity over all the queries. Returns the hits
+    // and the paraphrases that were searched.
+    pub async fn retrieve_expanded(
+        &self,
+        query: &str,
+        index: &str,
+        k: usize,
+        model: &str,
+        local_only: Option<bool>,
+        app_handle: &tauri::AppHandle,
+    ) -> Result<(Vec<SearchHit>, Vec<String>)> {
+        let count = self.get_rag_settings().expansion_queries;
+        let paraphrases = match query_expansion::expand_query(self, model, local_only, query, count).await {
+            Ok(paraphrases) => paraphrases,
+            Err(e) => {
+                eprintln!("Query expansion failed, searching with the question only: {}", e);
+                Vec::new()
+            }
+        };
         
-        if let Some(config) = self.models.get(model) {
-            if let Some(endpoint) = &config.api_endpoint {
-                let payload = serde_json::json!({
-                    "model": model,
-                    "prompt": prompt,
-                    "stream": false,
-                    "options": {
-                        "temperature": 0.7,
-                        "top_p": 0.9,
-                        "max_tokens": 512,
+        let mut rankings: Vec<Vec<i64>> = Vec::new();
+        let mut found: HashMap<i64, SearchHit> = HashMap::new();
+        for search_query in std::iter::once(query).chain(paraphrases.iter().map(String::as_str)) {
+            let hits = self.retrieve(search_query, index, k, app_handle).await?;
+            rankings.push(hits.iter().map(|hit| hit.chunk.id).collect());
+            for hit in hits {
+                match found.get_mut(&hit.chunk.id) {
+                    Some(existing) => {
+                        existing.similarity = match (existing.similarity, hit.similarity) {
+                            (Some(a), Some(b)) => Some(a.max(b)),
+                            (a, b) => a.or(b),
+                        };
+                    }
+                    None => {
+                        found.insert(hit.chunk.id, hit);
                     }
-                });
-                
-                // Add timeout to prevent hanging
-                let response = self.client
-                    .post(endpoint)
-                    .json(&payload)
-                    .timeout(std::time::Duration::from_secs(30))
-                    .send()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to connect to Ollama at {}: {}. Make sure Ollama is running with 'ollama serve'", endpoint, e))?;
-                
-                if response.status().is_success() {
-                    let result: serde_json::Value = response.json().await?;
-                    let text = result["response"].as_str().unwrap_or("No response").to_string();
-                    
-                    return Ok(LlamaResponse {
-                        text,
-                        tokens_used: prompt.split_whitespace().count(),
-                        processing_time_ms: start_time.elapsed().as_millis() as u64,
-                        confidence: 0.85,
-                    });
-                } else {
-                    return Err(anyhow::anyhow!("Ollama returned error status: {}. Model '{}' may not be available. Try 'ollama pull {}'", response.status(), model, model));
                 }
             }
         }
         
-        // If no config found for model, return error instead of fallback
-        Err(anyhow::anyhow!("Model '{}' not configured. Available models can be checked with 'ollama list'", model))
+        let weighted: Vec<(&[i64], f32)> = rankings.iter().map(|ranking| (ranking.as_slice(), 1.0)).collect();
+        let hits = rag::reciprocal_rank_fusion(&weighted)
+            .into_iter()
+            .take(k)
+            .filter_map(|(id, score)| found.remove(&id).map(|hit| SearchHit { score, ..hit }))
+            .collect();
+        Ok((hits, paraphrases))
+    }
+    
+    pub fn demo_mode(&self) -> bool {
+        self.demo_mode.read().map(|enabled| *enabled).unwrap_or(false)
     }
     
-    pub async fn rag_query(&self, query: &str, context_docs: Vec<String>) -> Result<LlamaResponse> {
-        // Simplified RAG implementation
-        let mut enriched_prompt = format!("Context documents:\n");
+    pub fn set_demo_mode(&self, enabled: bool, db: &Database) -> Result<()> {
+        db.set_setting(DEMO_MODE_KEY, &serde_json::to_string(&enabled)?)?;
         
-        for (i, doc) in context_docs.iter().enumerate() {
-            enriched_prompt.push_str(&format!("Document {}: {}\n", i + 1, doc));
+        if let Ok(mut current) = self.demo_mode.write() {
+            *current = enabled;
         }
+        Ok(())
+    }
+    
+    pub fn credentials(&self) -> &CredentialStore {
+        &self.credentials
+    }
+    
+    // Auth headers for a model; empty unless credentials were stored for it
+    fn request_headers(&self, model: &str, config: &ModelConfig) -> Result<reqwest::header::HeaderMap> {
+        let headers = if config.authenticated {
+            self.credentials.headers(model)?
+        } else {
+            reqwest::header::HeaderMap::new()
+        };
+        Ok(match config.backend {
+            BackendKind::Anthropic => cloud_backends::anthropic_headers(&headers),
+            _ => headers,
+        })
+    }
+    
+    pub fn model_config(&self, model: &str) -> Option<ModelConfig> {
+        self.models.read().ok()?.get(model).cloned()
+    }
+    
+    pub fn get_generation_defaults(&self) -> HashMap<String, GenerationOptions> {
+        self.generation_defaults.read()
+            .map(|defaults| defaults.clone())
+            .unwrap_or_default()
+    }
+    
+    pub fn set_generation_defaults(&self, model: &str, options: GenerationOptions, db: &Database) -> Result<()> {
+        let mut defaults = self.get_generation_defaults();
+        defaults.insert(model.to_string(), options);
+        
+        db.set_setting(GENERATION_DEFAULTS_KEY, &serde_json::to_string(&defaults)?)?;
+        
+        if let Ok(mut current) = self.generation_defaults.write() {
+            *current = defaults;
+        }
+        Ok(())
+    }
+    
+    pub fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.read()
+            .map(|policy| policy.clone())
+            .unwrap_or_default()
+    }
+    
+    pub fn set_retry_policy(&self, policy: RetryPolicy, db: &Database) -> Result<()> {
+        db.set_setting(RETRY_POLICY_KEY, &serde_json::to_string(&policy)?)?;
+        
+        if let Ok(mut current) = self.retry_policy.write() {
+            *current = policy;
+        }
+        Ok(())
+    }
+    
+    pub fn system_prompts(&self) -> &SystemPrompts {
+        &self.system_prompts
+    }
+    
+    pub fn prompt_templates(&self) -> &PromptTemplates {
+        &self.prompt_templates
+    }
+    
+    // Where cached answers are kept when they are kept on disk
+    pub fn set_cache_dir(&self, dir: std::path::PathBuf) {
+        self.cache.set_disk_dir(dir);
+    }
+    
+    pub fn cache_on_disk(&self) -> bool {
+        self.cache.is_persistent()
+    }
+    
+    pub fn set_cache_on_disk(&self, enabled: bool, db: &Database) -> Result<()> {
+        db.set_setting(AI_CACHE_ON_DISK_KEY, &serde_json::to_string(&enabled)?)?;
+        self.cache.set_persistent(enabled);
+        Ok(())
+    }
+    
+    // Where the local embedding model is downloaded to on first use
+    pub fn set_embedding_model_dir(&self, dir: std::path::PathBuf) {
+        self.local_embedder.set_cache_dir(dir);
+    }
+    
+    pub fn clear_cache(&self) -> usize {
+        self.cache.clear()
+    }
+    
+    pub fn get_breaker_status(&self) -> Vec<BreakerStatus> {
+        self.breaker.status(&self.get_retry_policy())
+    }
+    
+    // Request options override the per-model defaults, which override the model
+    // config's runtime options and then the built-in values
+    fn resolve_options(&self, model: &str, config: &ModelConfig, options: &GenerationOptions) -> GenerationOptions {
+        let built_in = GenerationOptions {
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            max_tokens: Some(512),
+            json: None,
+            local_only: None,
+            cache: None,
+            runtime: config.runtime.clone(),
+        };
+        let model_defaults = self.generation_defaults.read()
+            .ok()
+            .and_then(|defaults| defaults.get(model).cloned())
+            .unwrap_or_default();
+        options.or(&model_defaults).or(&built_in)
+    }
+    
+    // Options as they will be sent for `model`, for sizing prompts ahead of the request
+    pub fn effective_options(&self, model: &str, options: &GenerationOptions) -> GenerationOptions {
+        match self.model_config(model) {
+            Some(config) => self.resolve_options(model, &config, options),
+            None => options.clone(),
+        }
+    }
+    
+    fn backend(&self, kind: BackendKind) -> &dyn LlmBackend {
+        match kind {
+            BackendKind::Ollama => &self.ollama,
+            // OpenAI speaks the protocol the compatible servers copied
+            BackendKind::OpenAiCompatible | BackendKind::OpenAi => &self.openai_compatible,
+            BackendKind::LlamaCpp => &self.llama_cpp,
+            BackendKind::Anthropic => &self.anthropic,
+            BackendKind::Mock => &self.mock,
+        }
+    }
+    
+    fn backend_target(&self, model: &str) -> Result<ModelConfig> {
+        // Demo mode answers for every model name, configured or not
+        if self.demo_mode() {
+            return Ok(ModelConfig {
+                name: model.to_string(),
+                model_type: "demo".to_string(),
+                api_endpoint: None,
+                local_path: None,
+                enabled: true,
+                backend: BackendKind::Mock,
+                authenticated: false,
+                runtime: OllamaRuntimeOptions::default(),
+            });
+        }
+        
+        let mut config = self.model_config(model)
+            .ok_or_else(|| anyhow::anyhow!("Model '{}' not configured. Available models can be checked with 'ollama list'", model))?;
+        
+        if config.api_endpoint.is_none() {
+            config.api_endpoint = config.backend.default_endpoint().map(str::to_string);
+        }
+        
+        match config.backend {
+            BackendKind::OpenAi | BackendKind::Anthropic if !config.authenticated => {
+                Err(anyhow::anyhow!("Cloud model '{}' has no API key. Store one with set_model_credentials", model))
+            }
+            BackendKind::LlamaCpp if config.local_path.is_none() => {
+                Err(anyhow::anyhow!("Model '{}' has no local GGUF path configured", model))
+            }
+            BackendKind::Ollama | BackendKind::OpenAiCompatible if config.api_endpoint.is_none() => {
+                Err(anyhow::anyhow!("Model '{}' has no API endpoint configured", model))
+            }
+            _ => Ok(config),
+        }
+    }
+    
+    pub async fn query_llama(&self, prompt: &str, model: &str, options: &GenerationOptions) -> Result<LlamaResponse> {
+        self.query_llama_accepting(prompt, model, options, |_| true).await
+    }
+    
+    // Same as query_llama, but a reply is only cached, or taken from the cache,
+    // when `accept` passes its text, so one the caller rejects isn't kept
+    pub async fn query_llama_accepting(
+        &self,
+        prompt: &str,
+        model: &str,
+        options: &GenerationOptions,
+        accept: impl Fn(&str) -> bool,
+    ) -> Result<LlamaResponse> {
+        let start_time = std::time::Instant::now();
+        
+        let config = self.backend_target(model)?;
+        let options = self.resolve_options(model, &config, options);
+        check_local_only(model, &config, &options)?;
+        
+        // Demo answers are never cached, so turning demo mode off can't serve one
+        let cacheable = config.backend != BackendKind::Mock
+            && options.temperature == Some(0.0)
+            && options.cache != Some(false);
+        let cache_key = ResponseCache::key(model, prompt, &options);
+        let cached = cacheable.then(|| self.cache.get(&cache_key)).flatten().filter(|cached| accept(&cached.text));
+        if let Some(mut cached) = cached {
+            cached.cached = true;
+            cached.processing_time_ms = start_time.elapsed().as_millis() as u64;
+            self.apply_guardrails(&mut cached, &options);
+            return Ok(cached);
+        }
+        
+        let headers = self.request_headers(model, &config)?;
+        let request = GenerationRequest {
+            endpoint: config.api_endpoint.as_deref().unwrap_or_default(),
+            model_path: config.local_path.as_deref(),
+            headers: &headers,
+            model,
+            prompt,
+            options: &options,
+        };
+        
+        let backend = self.backend(config.backend);
+        let output = self.breaker
+            .call_with_retry(&breaker_key(&config, model), &self.get_retry_policy(), || backend.generate(&request))
+            .await?;
+        
+        let mut response = build_response(prompt, &output, config.backend, start_time);
+        if cacheable && accept(&response.text) {
+            self.cache.put(&cache_key, &response);
+        }
+        self.apply_guardrails(&mut response, &options);
+        
+        Ok(response)
+    }
+    
+    // Same as query_llama, but each partial token is handed to `on_token` as it
+    // arrives. Also returns the backend's token counts for the completion event.
+    pub async fn query_llama_stream<F>(&self, prompt: &str, model: &str, options: &GenerationOptions, mut on_token: F) -> Result<(LlamaResponse, BackendOutput)>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let start_time = std::time::Instant::now();
+        
+        let config = self.backend_target(model)?;
+        let options = self.resolve_options(model, &config, options);
+        check_local_only(model, &config, &options)?;
+        let headers = self.request_headers(model, &config)?;
+        let request = GenerationRequest {
+            endpoint: config.api_endpoint.as_deref().unwrap_or_default(),
+            model_path: config.local_path.as_deref(),
+            headers: &headers,
+            model,
+            prompt,
+            options: &options,
+        };
+        
+        // Tokens may already have reached the frontend, so streams are never retried
+        let backend = self.backend(config.backend);
+        let output = self.breaker
+            .call(&breaker_key(&config, model), &self.get_retry_policy(), backend.generate_stream(&request, &mut on_token))
+            .await?;
         
-        enriched_prompt.push_str(&format!("\nQuery: {}\n\nPlease answer the query based on the provided context.", query));
+        // Tokens were streamed unfiltered; the final text is filtered like any other response
+        let mut response = build_response(prompt, &output, config.backend, start_time);
+        self.apply_guardrails(&mut response, &options);
         
+        Ok((response, output))
+    }
+    
+    // Answers `query` from the request's documents or from chunks retrieved from an index
+    pub async fn rag_query(
+        &self,
+        query: &str,
+        source: ContextSource<'_>,
+        options: &GenerationOptions,
+        app_handle: &tauri::AppHandle,
+    ) -> Result<LlamaResponse> {
+        let rag_settings = self.get_rag_settings();
+        let top_k = rag_settings.top_k;
+        // With reranking, retrieval casts a wider net and the reranker narrows it down
+        let candidates = if rag_settings.rerank { rag_settings.rerank_candidates } else { top_k };
         // Use the best available model for RAG
-        self.query_llama(&enriched_prompt, "llama3-8b").await
+        let model = "llama3-8b";
+        
+        let mut expanded_queries = Vec::new();
+        let (context_docs, hits, relevance_query) = match source {
+            ContextSource::Documents(documents) => (documents, Vec::new(), query),
+            ContextSource::Index { name, query: search_query } if rag_settings.query_expansion => {
+                let (hits, paraphrases) = self.retrieve_expanded(search_query, name, candidates, model, options.local_only, app_handle).await?;
+                expanded_queries = paraphrases;
+                (Vec::new(), hits, search_query)
+            }
+            ContextSource::Index { name, query: search_query } => {
+                (Vec::new(), self.retrieve(search_query, name, candidates, app_handle).await?, search_query)
+            }
+        };
+        
+        let settings = self.get_guardrail_settings();
+        let documents: Vec<String> = context_docs.iter()
+            .map(String::as_str)
+            .chain(hits.iter().map(|hit| hit.chunk.text.as_str()))
+            .map(|doc| guardrails::sanitize_document(&settings, doc))
+            .collect();
+        
+        // Rank documents by similarity to the query and keep the top k. Without
+        // an embedding model every document is used, in the given order.
+        let mut rag_context = RAGContext::new(relevance_query, documents);
+        if !hits.is_empty() {
+            rag_context.similarity_scores = hits.iter().map(|hit| hit.score).collect();
+        } else if rag_context.documents.len() > 1 {
+            let mut texts = vec![query.to_string()];
+            texts.extend(rag_context.documents.iter().cloned());
+            match self.embed_texts(&texts).await {
+                Ok(mut embeddings) => {
+                    let query_embedding = embeddings.remove(0);
+                    rag_context.embeddings = embeddings;
+                    rag_context.score(&query_embedding);
+                }
+                Err(e) => eprintln!("Embedding failed, using all context documents: {}", e),
+            }
+        }
+        // Cosine similarity to the query where one is known
+        let similarities: Vec<Option<f32>> = if !hits.is_empty() {
+            hits.iter().map(|hit| hit.similarity).collect()
+        } else if rag_context.similarity_scores.len() == rag_context.documents.len() {
+            rag_context.similarity_scores.iter().copied().map(Some).collect()
+        } else {
+            vec![None; rag_context.documents.len()]
+        };
+        
+        let mut below_threshold = Vec::new();
+        let mut selected: Vec<usize> = rag_context.top_k(rag_context.documents.len())
+            .into_iter()
+            .filter(|&index| match similarities[index] {
+                Some(similarity) if similarity < rag_settings.min_similarity => {
+                    below_threshold.push(ScoredDocument {
+                        document: index,
+                        chunk_id: hits.get(index).map(|hit| hit.chunk.id),
+                        similarity,
+                    });
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        selected.truncate(candidates);
+        if rag_settings.rerank && selected.len() > top_k {
+            let passages: Vec<String> = selected.iter().map(|&index| rag_context.documents[index].clone()).collect();
+            match reranker::rerank(self, model, options.local_only, relevance_query, &passages, top_k).await {
+                Ok(order) => selected = order.into_iter().map(|rank| selected[rank]).collect(),
+                Err(e) => {
+                    eprintln!("Reranking failed, keeping retrieval order: {}", e);
+                    selected.truncate(top_k);
+                }
+            }
+        }
+        let documents: Vec<String> = selected.iter().map(|&index| rag_context.documents[index].clone()).collect();
+        
+        // Documents get whatever the template and query leave of the window
+        let mut report = ContextReport::new(context_window::prompt_budget(&self.effective_options(model, options)));
+        report.below_threshold = below_threshold;
+        report.expanded_queries = expanded_queries;
+        let fixed_tokens = tokenizer::count_tokens(&self.prompt_templates.render(RAG_QUERY_TEMPLATE, serde_json::json!({
+            "documents": Vec::<String>::new(),
+            "query": rag_context.query,
+        }))?);
+        let documents = context_window::fit_documents(documents, report.budget_tokens.saturating_sub(fixed_tokens), &mut report);
+        
+        // Each document is one chunk, numbered in the prompt so the answer can cite it
+        let chunks: Vec<Chunk> = documents.into_iter()
+            .enumerate()
+            .map(|(rank, text)| {
+                let end = if report.truncated_documents.contains(&rank) {
+                    text.trim_end_matches('…').len()
+                } else {
+                    text.len()
+                };
+                let stored = hits.get(selected[rank]).map(|hit| &hit.chunk);
+                Chunk {
+                    document: selected[rank],
+                    start: 0,
+                    end,
+                    text,
+                    chunk_id: stored.map(|chunk| chunk.id),
+                    source: stored.map(|chunk| chunk.source.clone()),
+                }
+            })
+            .collect();
+        
+        // The report refers to documents by their index in the request
+        for index in report.truncated_documents.iter_mut().chain(report.dropped_documents.iter_mut()) {
+            *index = selected[*index];
+        }
+        
+        let enriched_prompt = self.prompt_templates.render(RAG_QUERY_TEMPLATE, serde_json::json!({
+            "documents": chunks.iter().map(|chunk| chunk.text.as_str()).collect::<Vec<_>>(),
+            "query": rag_context.query,
+        }))?;
+        report.prompt_tokens = tokenizer::count_tokens(&enriched_prompt);
+        
+        let mut response = self.query_llama(&enriched_prompt, model, options).await?;
+        response.citations = rag::parse_citations(&response.text, &chunks);
+        response.context = Some(report);
+        Ok(response)
     }
     
-    pub fn _get_available_models(&self) -> Vec<&ModelConfig> {
-        self.models.values().filter(|config| config.enabled).collect()
+    pub fn _get_available_models(&self) -> Vec<ModelConfig> {
+        self.models.read()
+            .map(|models| models.values().filter(|config| config.enabled).cloned().collect())
+            .unwrap_or_default()
     }
     
     // Check what models are actually available in Ollama
     pub async fn get_ollama_models(&self) -> Result<Vec<String>> {
         // Try to connect to Ollama and get list of available models
         let response = self.client
-            .get("http://localhost:11434/api/tags")
+            .get(self.ollama_url("/api/tags"))
             .timeout(std::time::Duration::from_secs(5))
             .send()
             .await
@@ -169,40 +1220,304 @@ impl AdvancedAI {
             
         Ok(models)
     }
+    
+    // GET an Ollama endpoint and return the JSON body; used for optional health details
+    async fn ollama_get(&self, path: &str) -> Result<serde_json::Value> {
+        let response = self.client
+            .get(self.ollama_url(path))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+        
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Ollama returned status: {}", response.status()));
+        }
+        Ok(response.json().await?)
+    }
+    
+    // (size, size_vram) in bytes of a model currently loaded by Ollama
+    pub async fn loaded_model_memory(&self, model: &str) -> Option<(u64, u64)> {
+        let running = self.ollama_get("/api/ps").await.ok()?;
+        running["models"].as_array()?
+            .iter()
+            .find(|m| {
+                let name = m["name"].as_str().unwrap_or_default();
+                name == model || name.strip_suffix(":latest") == Some(model)
+            })
+            .map(|m| (m["size"].as_u64().unwrap_or(0), m["size_vram"].as_u64().unwrap_or(0)))
+    }
+    
+    // Reachability, version and model lists, plus an optional one-token
+    // generation to load `warmup_model` (default: first installed model)
+    pub async fn check_health(&self, warmup: bool, warmup_model: Option<String>) -> AiHealth {
+        let mut health = AiHealth {
+            reachable: false,
+            endpoint: self.ollama_host(),
+            latency_ms: None,
+            version: None,
+            installed_models: Vec::new(),
+            loaded_models: Vec::new(),
+            warmup: None,
+            error: None,
+        };
+        
+        let start_time = std::time::Instant::now();
+        match self.get_ollama_models().await {
+            Ok(models) => {
+                health.reachable = true;
+                health.latency_ms = Some(start_time.elapsed().as_millis() as u64);
+                health.installed_models = models;
+            }
+            Err(e) => {
+                health.error = Some(e.to_string());
+                return health;
+            }
+        }
+        
+        if let Ok(version) = self.ollama_get("/api/version").await {
+            health.version = version["version"].as_str().map(|s| s.to_string());
+        }
+        if let Ok(running) = self.ollama_get("/api/ps").await {
+            health.loaded_models = running["models"]
+                .as_array()
+                .map(|models| models.iter().filter_map(|m| m["name"].as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default();
+        }
+        
+        if warmup {
+            let model = warmup_model.or_else(|| health.installed_models.first().cloned());
+            if let Some(model) = model {
+                let options = GenerationOptions {
+                    max_tokens: Some(1),
+                    ..GenerationOptions::default()
+                };
+                let request = GenerationRequest {
+                    endpoint: &self.ollama_url("/api/generate"),
+                    model_path: None,
+                    headers: &reqwest::header::HeaderMap::new(),
+                    model: &model,
+                    prompt: "Hi",
+                    options: &options,
+                };
+                
+                // Straight to the backend: no cache, retries or breaker accounting
+                let start_time = std::time::Instant::now();
+                let result = self.ollama.generate(&request).await;
+                health.warmup = Some(WarmupResult {
+                    model,
+                    ok: result.is_ok(),
+                    latency_ms: start_time.elapsed().as_millis() as u64,
+                    error: result.err().map(|e| e.to_string()),
+                });
+            }
+        }
+        
+        health
+    }
+    
+    // Download a model through Ollama's /api/pull, reporting each progress line
+    pub async fn pull_ollama_model<F>(&self, name: &str, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(ModelPullProgress),
+    {
+        let mut response = self.client
+            .post(self.ollama_url("/api/pull"))
+            .json(&serde_json::json!({ "model": name, "stream": true }))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Ollama connection failed: {}. Ensure Ollama is running with 'ollama serve'", e))?;
+        
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Ollama returned status: {} while pulling '{}'", response.status(), name));
+        }
+        
+        // Verifying large layers can take a while without any output
+        read_ndjson_stream(&mut response, std::time::Duration::from_secs(300), |value| {
+            let total = value["total"].as_u64();
+            let completed = value["completed"].as_u64();
+            let percent = match (total, completed) {
+                (Some(total), Some(completed)) if total > 0 => Some(completed as f32 / total as f32 * 100.0),
+                _ => None,
+            };
+            
+            on_progress(ModelPullProgress {
+                model: name.to_string(),
+                status: value["status"].as_str().unwrap_or("").to_string(),
+                digest: value["digest"].as_str().map(|s| s.to_string()),
+                total,
+                completed,
+                percent,
+            });
+        }).await
+    }
+    
+    pub async fn delete_ollama_model(&self, name: &str) -> Result<()> {
+        let response = self.client
+            .delete(self.ollama_url("/api/delete"))
+            .json(&serde_json::json!({ "model": name }))
+            .timeout(std::time::Duration::from_secs(30))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Ollama connection failed: {}. Ensure Ollama is running with 'ollama serve'", e))?;
+        
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::NOT_FOUND => Err(anyhow::anyhow!("Model '{}' is not installed", name)),
+            status => Err(anyhow::anyhow!("Ollama returned status: {} while deleting '{}'", status, name)),
+        }
+    }
+}
+
+// Token counts come from the backend when it reports them, otherwise they are
+// counted locally
+fn build_response(prompt: &str, output: &BackendOutput, backend: BackendKind, start_time: std::time::Instant) -> LlamaResponse {
+    let prompt_tokens = output.prompt_tokens
+        .map(|count| count as usize)
+        .unwrap_or_else(|| tokenizer::count_tokens(prompt));
+    let completion_tokens = output.completion_tokens
+        .map(|count| count as usize)
+        .unwrap_or_else(|| tokenizer::count_tokens(&output.text));
+    let (confidence, confidence_source) = confidence_from_logprobs(&output.token_logprobs);
+    
+    LlamaResponse {
+        text: output.text.clone(),
+        tokens_used: prompt_tokens + completion_tokens,
+        prompt_tokens,
+        completion_tokens,
+        processing_time_ms: start_time.elapsed().as_millis() as u64,
+        confidence,
+        confidence_source,
+        cached: false,
+        guardrails_applied: Vec::new(),
+        backend: Some(backend),
+        simulated: backend == BackendKind::Mock,
+        context: None,
+        citations: Vec::new(),
+    }
+}
+
+// A hard stop: local-only prompts never leave the machine, even as a fallback
+fn check_local_only(model: &str, config: &ModelConfig, options: &GenerationOptions) -> Result<()> {
+    if options.local_only.unwrap_or(false) && config.backend.is_cloud() {
+        return Err(anyhow::anyhow!("Model '{}' runs on a cloud provider and this query is local-only", model));
+    }
+    Ok(())
+}
+
+fn validate_fallback_chain(chain: &[String]) -> Result<(), String> {
+    if chain.is_empty() {
+        return Err("The model fallback chain needs at least one model".to_string());
+    }
+    if chain.iter().any(|model| model.trim().is_empty()) {
+        return Err("Model names in the fallback chain must not be empty".to_string());
+    }
+    if let Some((index, model)) = chain.iter().enumerate().find(|(index, model)| chain[..*index].contains(model)) {
+        return Err(format!("Model '{}' appears more than once in the fallback chain (position {})", model, index + 1));
+    }
+    Ok(())
+}
+
+// Failures are tracked per server (or per GGUF file for embedded models)
+fn breaker_key(config: &ModelConfig, model: &str) -> String {
+    config.api_endpoint.clone()
+        .or_else(|| config.local_path.clone())
+        .unwrap_or_else(|| model.to_string())
+}
+
+// Registers an in-flight request and tells the frontend which id to cancel it with
+pub(crate) fn begin_request<'a>(
+    ai: &'a AdvancedAI,
+    request_id: Option<String>,
+    command: &str,
+    app_handle: &tauri::AppHandle,
+) -> Result<ActiveRequest<'a>, String> {
+    let active = ai.requests.register(request_id).map_err(|e| e.to_string())?;
+    
+    let started = AiRequestStarted {
+        request_id: active.id.clone(),
+        command: command.to_string(),
+    };
+    if let Err(e) = app_handle.emit(AI_REQUEST_STARTED_EVENT, started) {
+        eprintln!("Failed to emit request start: {}", e);
+    }
+    
+    Ok(active)
+}
+
+// Runs a single query, optionally streaming tokens to the frontend as events.
+// The completion event is only emitted once the whole answer has arrived.
+pub(crate) async fn query_maybe_streaming(
+    ai: &AdvancedAI,
+    prompt: &str,
+    model: &str,
+    options: &GenerationOptions,
+    stream: bool,
+    active: &ActiveRequest<'_>,
+    app_handle: &tauri::AppHandle,
+) -> Result<LlamaResponse> {
+    if !stream {
+        return active.run(ai.query_llama(prompt, model, options)).await;
+    }
+    
+    let (response, output) = active.run(ai.query_llama_stream(prompt, model, options, |token| {
+        active.push_partial(token);
+        
+        let event = ChatTokenEvent {
+            request_id: active.id.clone(),
+            model: model.to_string(),
+            token: token.to_string(),
+        };
+        if let Err(e) = app_handle.emit(CHAT_TOKEN_EVENT, event) {
+            eprintln!("Failed to emit chat token: {}", e);
+        }
+    })).await?;
+    
+    let complete = ChatCompleteEvent {
+        request_id: active.id.clone(),
+        model: model.to_string(),
+        text: response.text.clone(),
+        tokens_used: response.tokens_used,
+        processing_time_ms: response.processing_time_ms,
+        prompt_eval_count: output.prompt_tokens,
+        eval_count: output.completion_tokens,
+    };
+    if let Err(e) = app_handle.emit(CHAT_COMPLETE_EVENT, complete) {
+        eprintln!("Failed to emit chat completion: {}", e);
+    }
+    
+    Ok(response)
 }
 
 #[command]
 pub async fn chat_with_llama(
     prompt: String,
     model: Option<String>,
+    options: Option<GenerationOptions>,
+    stream: Option<bool>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
 ) -> Result<LlamaResponse, String> {
-    let ai = AdvancedAI::new();
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let stream = stream.unwrap_or(false);
+    let active = begin_request(&ai, request_id, "chat_with_llama", &app_handle)?;
     
     if let Some(specific_model) = model {
         // If user specified a model, try it directly
-        ai.query_llama(&prompt, &specific_model)
+        query_maybe_streaming(&ai, &prompt, &specific_model, &options, stream, &active, &app_handle)
             .await
             .map_err(|e| format!("Model '{}' error: {}", specific_model, e))
     } else {
-        // Try different model names in order of preference  
-        let model_candidates = ["llama3.2:1b", "llama3.2", "llama3:8b", "llama3", "llama3-8b", "llama2:7b", "llama2", "llama"];
-        
-        let mut last_error = String::new();
-        for model_name in model_candidates.iter() {
-            match ai.query_llama(&prompt, model_name).await {
-                Ok(response) => return Ok(response),
-                Err(e) => {
-                    last_error = format!("Model '{}' failed: {}", model_name, e);
-                    println!("Trying next model after error: {}", last_error);
-                }
-            }
+        match ai.query_fallback_chain(&prompt, &options, stream, &active, &app_handle).await {
+            Ok(response) => Ok(response),
+            Err(e) if active.is_cancelled() => Err(e.to_string()),
+            // If all attempts failed, return a helpful error message
+            Err(e) => Err(format!(
+                "❌ Ollama AI models not available. Last error: {}\n\n🔧 To fix this:\n1. Install Ollama from https://ollama.ai\n2. Run: ollama serve\n3. Pull models: ollama pull llama3\n4. Restart this application\n\n💡 Turn on demo mode (set_demo_mode) to try Dwight without AI models.",
+                e
+            )),
         }
-        
-        // If all attempts failed, return a helpful error message
-        Err(format!(
-            "❌ Ollama AI models not available. Last error: {}\n\n🔧 To fix this:\n1. Install Ollama from https://ollama.ai\n2. Run: ollama serve\n3. Pull models: ollama pull llama3\n4. Restart this application\n\n💡 The chat works in demo mode without AI models.",
-            last_error
-        ))
     }
 }
 
@@ -210,18 +1525,29 @@ pub async fn chat_with_llama(
 pub async fn rag_search(
     query: String,
     context_documents: Vec<String>,
+    index: Option<String>,
+    options: Option<GenerationOptions>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
 ) -> Result<LlamaResponse, String> {
-    let ai = AdvancedAI::new();
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let active = begin_request(&ai, request_id, "rag_search", &app_handle)?;
     
-    ai.rag_query(&query, context_documents)
+    // Without documents, answer from the vector store
+    let source = if context_documents.is_empty() {
+        ContextSource::Index { name: index.as_deref().unwrap_or(vector_store::DEFAULT_INDEX), query: &query }
+    } else {
+        ContextSource::Documents(context_documents)
+    };
+    active.run(ai.rag_query(&query, source, &options, &app_handle))
         .await
         .map_err(|e| format!("RAG error: {}", e))
 }
 
 #[command]
-pub async fn get_ai_models() -> Result<Vec<ModelConfig>, String> {
-    let ai = AdvancedAI::new();
-    
+pub async fn get_ai_models(ai: State<'_, AdvancedAI>) -> Result<Vec<ModelConfig>, String> {
     // First, try to get actual models from Ollama
     match ai.get_ollama_models().await {
         Ok(ollama_models) => {
@@ -229,6 +1555,7 @@ pub async fn get_ai_models() -> Result<Vec<ModelConfig>, String> {
             
             // Create model configs based on what's actually available in Ollama
             let mut available_models = Vec::new();
+            let generate_endpoint = ai.ollama_url("/api/generate");
             
             for model_name in ollama_models {
                 let name_lower = model_name.to_lowercase();
@@ -237,34 +1564,46 @@ pub async fn get_ai_models() -> Result<Vec<ModelConfig>, String> {
                     available_models.push(ModelConfig {
                         name: format!("Llama ({})", model_name),
                         model_type: "llama".to_string(),
-                        api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
+                        api_endpoint: Some(generate_endpoint.clone()),
                         local_path: None,
                         enabled: true,
+                        backend: BackendKind::Ollama,
+                        authenticated: false,
+                        runtime: OllamaRuntimeOptions::default(),
                     });
                 } else if name_lower.contains("mistral") || name_lower.contains("mixtral") {
                     available_models.push(ModelConfig {
                         name: format!("Mistral ({})", model_name),
                         model_type: "mistral".to_string(),
-                        api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
+                        api_endpoint: Some(generate_endpoint.clone()),
                         local_path: None,
                         enabled: true,
+                        backend: BackendKind::Ollama,
+                        authenticated: false,
+                        runtime: OllamaRuntimeOptions::default(),
                     });
                 } else if name_lower.contains("gemma") {
                     available_models.push(ModelConfig {
                         name: format!("Gemma ({})", model_name),
                         model_type: "gemma".to_string(),
-                        api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
+                        api_endpoint: Some(generate_endpoint.clone()),
                         local_path: None,
                         enabled: true,
+                        backend: BackendKind::Ollama,
+                        authenticated: false,
+                        runtime: OllamaRuntimeOptions::default(),
                     });
                 } else {
                     // Add other models as generic
                     available_models.push(ModelConfig {
                         name: model_name.clone(),
                         model_type: "other".to_string(),
-                        api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
+                        api_endpoint: Some(generate_endpoint.clone()),
                         local_path: None,
                         enabled: true,
+                        backend: BackendKind::Ollama,
+                        authenticated: false,
+                        runtime: OllamaRuntimeOptions::default(),
                     });
                 }
             }
@@ -274,9 +1613,12 @@ pub async fn get_ai_models() -> Result<Vec<ModelConfig>, String> {
                 available_models.push(ModelConfig {
                     name: "RAG Search".to_string(),
                     model_type: "rag".to_string(),
-                    api_endpoint: Some("http://localhost:11434/api/generate".to_string()),
+                    api_endpoint: Some(generate_endpoint.clone()),
                     local_path: None,
                     enabled: true,
+                    backend: BackendKind::Ollama,
+                    authenticated: false,
+                    runtime: OllamaRuntimeOptions::default(),
                 });
             }
             
@@ -290,90 +1632,443 @@ pub async fn get_ai_models() -> Result<Vec<ModelConfig>, String> {
     }
 }
 
+// Status indicator for the UI; never fails, unreachable backends are reported in the result
+#[command]
+pub async fn check_ai_health(
+    warmup: Option<bool>,
+    model: Option<String>,
+    ai: State<'_, AdvancedAI>,
+) -> Result<AiHealth, String> {
+    Ok(ai.check_health(warmup.unwrap_or(false), model).await)
+}
+
+#[command]
+pub async fn pull_ollama_model(
+    name: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<String, String> {
+    ai.pull_ollama_model(&name, |progress| {
+        if let Err(e) = app_handle.emit(MODEL_PULL_PROGRESS_EVENT, progress) {
+            eprintln!("Failed to emit pull progress: {}", e);
+        }
+    })
+    .await
+    .map_err(|e| format!("Failed to pull model '{}': {}", name, e))?;
+    
+    Ok(format!("Model '{}' installed", name))
+}
+
+#[command]
+pub async fn delete_ollama_model(name: String, ai: State<'_, AdvancedAI>) -> Result<String, String> {
+    ai.delete_ollama_model(&name)
+        .await
+        .map_err(|e| format!("Failed to delete model '{}': {}", name, e))?;
+    
+    Ok(format!("Model '{}' deleted", name))
+}
+
+#[command]
+pub async fn get_retry_policy(ai: State<'_, AdvancedAI>) -> Result<RetryPolicy, String> {
+    Ok(ai.get_retry_policy())
+}
+
+#[command]
+pub async fn set_retry_policy(
+    policy: RetryPolicy,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    policy.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_retry_policy(policy, &db)
+        .map_err(|e| format!("Failed to save retry policy: {}", e))
+}
+
+#[command]
+pub async fn get_demo_mode(ai: State<'_, AdvancedAI>) -> Result<bool, String> {
+    Ok(ai.demo_mode())
+}
+
+// While on, every query is answered by the canned demo backend and marked simulated
+#[command]
+pub async fn set_demo_mode(
+    enabled: bool,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_demo_mode(enabled, &db)
+        .map_err(|e| format!("Failed to save demo mode: {}", e))
+}
+
+#[command]
+pub async fn get_model_fallback_chain(ai: State<'_, AdvancedAI>) -> Result<Vec<String>, String> {
+    Ok(ai.get_fallback_chain())
+}
+
+#[command]
+pub async fn set_model_fallback_chain(
+    chain: Vec<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let chain: Vec<String> = chain.iter().map(|model| model.trim().to_string()).collect();
+    validate_fallback_chain(&chain)?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_fallback_chain(chain, &db)
+        .map_err(|e| format!("Failed to save model fallback chain: {}", e))
+}
+
+// Sends the same prompt to several models concurrently. A failing model doesn't
+// fail the comparison; its error is reported in its slot instead.
+#[command]
+pub async fn compare_models(
+    prompt: String,
+    models: Vec<String>,
+    options: Option<GenerationOptions>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<ComparisonResult, String> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    
+    let mut unique_models: Vec<String> = Vec::new();
+    for model in models {
+        if !unique_models.contains(&model) {
+            unique_models.push(model);
+        }
+    }
+    if unique_models.is_empty() {
+        return Err("Select at least one model to compare".to_string());
+    }
+    if unique_models.len() > MAX_COMPARED_MODELS {
+        return Err(format!("At most {} models can be compared at once", MAX_COMPARED_MODELS));
+    }
+    
+    let active = begin_request(&ai, request_id, "compare_models", &app_handle)?;
+    let start_time = std::time::Instant::now();
+    
+    let queries = unique_models.iter().map(|model| ai.query_llama(&prompt, model, &options));
+    let outcomes = active.run(async { Ok(futures::future::join_all(queries).await) })
+        .await
+        .map_err(|e| format!("Model comparison error: {}", e))?;
+    
+    let results = unique_models.into_iter()
+        .zip(outcomes)
+        .map(|(model, outcome)| match outcome {
+            Ok(response) => ModelComparison { model, response: Some(response), error: None },
+            Err(e) => ModelComparison { model, response: None, error: Some(e.to_string()) },
+        })
+        .collect();
+    
+    Ok(ComparisonResult {
+        prompt,
+        results,
+        total_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+#[command]
+pub async fn get_guardrail_settings(ai: State<'_, AdvancedAI>) -> Result<GuardrailSettings, String> {
+    Ok(ai.get_guardrail_settings())
+}
+
+#[command]
+pub async fn set_guardrail_settings(
+    settings: GuardrailSettings,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    settings.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_guardrail_settings(settings, &db)
+        .map_err(|e| format!("Failed to save guardrail settings: {}", e))
+}
+
+#[command]
+pub async fn get_rag_settings(ai: State<'_, AdvancedAI>) -> Result<RagSettings, String> {
+    Ok(ai.get_rag_settings())
+}
+
+#[command]
+pub async fn set_rag_settings(
+    settings: RagSettings,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    settings.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_rag_settings(settings, &db)
+        .map_err(|e| format!("Failed to save RAG settings: {}", e))
+}
+
+#[command]
+pub async fn embed_texts(texts: Vec<String>, ai: State<'_, AdvancedAI>) -> Result<Vec<Vec<f32>>, String> {
+    ai.embed_texts(&texts).await.map_err(|e| format!("Embedding error: {}", e))
+}
+
+// Forget all cached answers, e.g. after switching model weights
+#[command]
+pub async fn clear_ai_cache(ai: State<'_, AdvancedAI>) -> Result<usize, String> {
+    Ok(ai.clear_cache())
+}
+
+#[command]
+pub async fn get_ai_cache_on_disk(ai: State<'_, AdvancedAI>) -> Result<bool, String> {
+    Ok(ai.cache_on_disk())
+}
+
+// While on, cached answers are also kept on disk for an hour, so they
+// survive a restart. They hold prompts and answers, transcripts included,
+// unencrypted; turning this off deletes them.
+#[command]
+pub async fn set_ai_cache_on_disk(
+    enabled: bool,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_cache_on_disk(enabled, &db)
+        .map_err(|e| format!("Failed to save AI cache setting: {}", e))
+}
+
+// Reports each backend's breaker so the UI can show "backend unavailable"
+#[command]
+pub async fn get_circuit_breaker_status(ai: State<'_, AdvancedAI>) -> Result<Vec<BreakerStatus>, String> {
+    Ok(ai.get_breaker_status())
+}
+
+#[command]
+pub async fn get_model_configs(ai: State<'_, AdvancedAI>) -> Result<HashMap<String, ModelConfig>, String> {
+    ai.models.read()
+        .map(|models| models.clone())
+        .map_err(|e| format!("Model config unavailable: {}", e))
+}
+
+#[command]
+pub async fn set_model_config(
+    model: String,
+    config: ModelConfig,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    if model.trim().is_empty() {
+        return Err("Model id must not be empty".to_string());
+    }
+    config.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_model_config(&model, config, &db)
+        .map_err(|e| format!("Failed to save model config: {}", e))
+}
+
+// Point one model at a different server, e.g. Ollama on another machine in the LAN
+#[command]
+pub async fn set_model_endpoint(
+    model: String,
+    endpoint: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<ModelConfig, String> {
+    let mut config = ai.model_config(&model)
+        .ok_or_else(|| format!("Model '{}' not configured", model))?;
+    config.api_endpoint = Some(endpoint.trim().to_string());
+    config.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_model_config(&model, config.clone(), &db)
+        .map_err(|e| format!("Failed to save model config: {}", e))?;
+    Ok(config)
+}
+
+// Tests the model's configured endpoint, or `endpoint` if given (to check an
+// address before saving it). The stored credentials only go to the endpoint
+// they were saved for, so another address is tested without them.
+#[command]
+pub async fn test_model_endpoint(
+    model: String,
+    endpoint: Option<String>,
+    ai: State<'_, AdvancedAI>,
+) -> Result<EndpointTest, String> {
+    let config = ai.model_config(&model)
+        .ok_or_else(|| format!("Model '{}' not configured", model))?;
+    let configured = config.api_endpoint.clone()
+        .or_else(|| config.backend.default_endpoint().map(str::to_string));
+    let same = |a: &str, b: &str| a.trim().trim_end_matches('/') == b.trim().trim_end_matches('/');
+    let overridden = match (&endpoint, &configured) {
+        (Some(endpoint), Some(configured)) => !same(endpoint, configured),
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    let headers = match overridden {
+        true => ai.request_headers(&model, &ModelConfig { authenticated: false, ..config.clone() }),
+        false => ai.request_headers(&model, &config),
+    }
+    .map_err(|e| e.to_string())?;
+    let endpoint = endpoint.or(configured)
+        .ok_or_else(|| format!("Model '{}' has no API endpoint configured", model))?;
+    
+    Ok(ai.test_endpoint(config.backend, endpoint.trim(), headers).await)
+}
+
+#[command]
+pub async fn get_ollama_host(ai: State<'_, AdvancedAI>) -> Result<String, String> {
+    Ok(ai.ollama_host())
+}
+
+// Host used for listing, pulling and deleting models and for health checks
+#[command]
+pub async fn set_ollama_host(
+    host: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_ollama_host(&host, &db)
+        .map_err(|e| format!("Failed to save Ollama host: {}", e))
+}
+
+#[command]
+pub async fn get_generation_defaults(ai: State<'_, AdvancedAI>) -> Result<HashMap<String, GenerationOptions>, String> {
+    Ok(ai.get_generation_defaults())
+}
+
+#[command]
+pub async fn set_generation_defaults(
+    model: String,
+    options: GenerationOptions,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    options.validate()?;
+    
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.set_generation_defaults(&model, options, &db)
+        .map_err(|e| format!("Failed to save generation defaults: {}", e))
+}
+
+// Aborts an in-flight request. Streaming requests hand back what was generated
+// before the cancel; the original command call fails with a cancellation error.
+#[command]
+pub async fn cancel_ai_request(request_id: String, ai: State<'_, AdvancedAI>) -> Result<CancelledRequest, String> {
+    ai.requests.cancel(&request_id)
+        .ok_or_else(|| format!("No running AI request with id '{}'", request_id))
+}
+
+#[command]
+pub async fn get_active_ai_requests(ai: State<'_, AdvancedAI>) -> Result<Vec<String>, String> {
+    Ok(ai.requests.active_ids())
+}
+
+#[allow(clippy::too_many_arguments)]
 #[command]
 pub async fn enhanced_dwight_chat(
     user_input: String,
     use_advanced_model: Option<bool>,
     context_documents: Option<Vec<String>>,
+    options: Option<GenerationOptions>,
+    stream: Option<bool>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
 ) -> Result<LlamaResponse, String> {
-    let ai = AdvancedAI::new();
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let stream = stream.unwrap_or(false);
+    let active = begin_request(&ai, request_id, "enhanced_dwight_chat", &app_handle)?;
     
     // Enhanced Dwight prompt with personality and capabilities
-    let dwight_prompt = format!(
-        "You are Dwight, an advanced AI assistant specialized in audio analysis, surveillance, and security systems. \
-        You are brilliant, analytical, loyal, and technically proficient. You help users with:\n\
-        - Audio transcription and analysis\n\
-        - Sound pattern recognition\n\
-        - Security monitoring and alerts\n\
-        - Forensic audio investigation\n\
-        - Real-time audio processing\n\n\
-        User input: {}\n\n\
-        Respond as Dwight with technical expertise and helpful guidance:",
-        user_input
-    );
-    
-    if use_advanced_model.unwrap_or(false) && context_documents.is_some() {
-        // Use RAG for context-aware responses
-        ai.rag_query(&dwight_prompt, context_documents.unwrap()).await
-    } else {
-        // Try different model names in order of preference
-        let model_candidates = ["llama3.2:1b", "llama3.2", "llama3:8b", "llama3", "llama3-8b", "llama2:7b", "llama2", "llama"];
-        
-        let mut last_error = String::new();
-        for model_name in model_candidates.iter() {
-            match ai.query_llama(&dwight_prompt, model_name).await {
-                Ok(response) => return Ok(response),
-                Err(e) => {
-                    last_error = format!("Model '{}' failed: {}", model_name, e);
-                    println!("Trying next model after error: {}", last_error);
-                }
+    let dwight_prompt = ai.prompt_templates()
+        .render(ENHANCED_CHAT_TEMPLATE, serde_json::json!({
+            "system_prompt": ai.system_prompts().active().prompt,
+            "user_input": user_input,
+        }))
+        .map_err(|e| format!("Enhanced chat error: {}", e))?;
+    
+    if use_advanced_model.unwrap_or(false) {
+        // Use RAG for context-aware responses, over the stored transcripts unless documents were given
+        let source = match context_documents {
+            Some(documents) if !documents.is_empty() => ContextSource::Documents(documents),
+            _ => {
+                // Normally created by the first indexed transcript; until then the search finds nothing
+                VectorStore::new(&app_handle)
+                    .and_then(|store| store.ensure_index(TRANSCRIPTS_INDEX))
+                    .map_err(|e| format!("Vector store error: {}", e))?;
+                ContextSource::Index { name: TRANSCRIPTS_INDEX, query: &user_input }
             }
+        };
+        active.run(ai.rag_query(&dwight_prompt, source, &options, &app_handle)).await
+    } else {
+        match ai.query_fallback_chain(&dwight_prompt, &options, stream, &active, &app_handle).await {
+            Err(e) if !active.is_cancelled() => Err(anyhow::anyhow!(
+                "All Llama models failed. Last error: {}. Please ensure Ollama is running and models are available.", e
+            )),
+            result => result,
         }
-        
-        // If all model attempts failed, return the last error
-        Err(anyhow::anyhow!("All Llama models failed. Last error: {}. Please ensure Ollama is running and models are available.", last_error))
     }
     .map_err(|e| format!("Enhanced chat error: {}", e))
 }
 
-// Audio-specific AI analysis
+// Audio-specific AI analysis. With `structured`, the model must answer in JSON
+// and the result carries a validated AudioAnalysisReport under "report".
 #[command]
 pub async fn ai_audio_analysis(
     audio_features: Vec<f32>,
     audio_metadata: serde_json::Value,
+    structured: Option<bool>,
+    ai: State<'_, AdvancedAI>,
 ) -> Result<serde_json::Value, String> {
-    let ai = AdvancedAI::new();
-    
-    // Convert audio features to a descriptive prompt
-    let avg_amplitude = audio_features.iter().sum::<f32>() / audio_features.len() as f32;
-    let max_amplitude = audio_features.iter().fold(0.0f32, |a, &b| a.max(b));
-    let zero_crossings = audio_features.windows(2).filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0)).count();
-    
-    let analysis_prompt = format!(
-        "Analyze this audio data:\n\
-        - Average amplitude: {:.3}\n\
-        - Peak amplitude: {:.3}\n\
-        - Zero crossings: {}\n\
-        - Sample count: {}\n\
-        - Metadata: {}\n\n\
-        Provide a detailed analysis of what this audio might contain, \
-        potential sounds or speech patterns, and any security-relevant observations.",
-        avg_amplitude, max_amplitude, zero_crossings, audio_features.len(), audio_metadata
-    );
-    
-    let response = ai.query_llama(&analysis_prompt, "mixtral-8x7b").await
+    // `audio_features` are mono samples; the rate comes with the metadata
+    let sample_rate = audio_metadata.get("sample_rate").and_then(|rate| rate.as_u64()).unwrap_or(16000) as u32;
+    let features = dsp::analyze(&audio_features, sample_rate);
+    
+    let mut template_context = serde_json::to_value(&features).map_err(|e| format!("Audio analysis error: {}", e))?;
+    template_context["metadata"] = serde_json::Value::String(audio_metadata.to_string());
+    
+    if structured.unwrap_or(false) {
+        let analysis_prompt = ai.prompt_templates()
+            .render(AUDIO_ANALYSIS_JSON_TEMPLATE, template_context)
+            .map_err(|e| format!("Audio analysis error: {}", e))?;
+        
+        let (report, response) = query_structured::<AudioAnalysisReport>(&ai, &analysis_prompt, "mixtral-8x7b", &GenerationOptions::default())
+            .await
+            .map_err(|e| format!("Audio analysis error: {}", e))?;
+        
+        return Ok(serde_json::json!({
+            "analysis": report.summary,
+            "report": report,
+            "confidence": response.confidence,
+            "confidence_source": response.confidence_source,
+            "processing_time_ms": response.processing_time_ms,
+            "audio_features": features,
+            "recommendations": report.recommended_actions,
+        }));
+    }
+    
+    let analysis_prompt = ai.prompt_templates()
+        .render(AUDIO_ANALYSIS_TEMPLATE, template_context)
+        .map_err(|e| format!("Audio analysis error: {}", e))?;
+    
+    // At temperature 0, so the same audio is answered from the cache
+    let options = GenerationOptions {
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    let response = ai.query_llama(&analysis_prompt, "mixtral-8x7b", &options).await
         .map_err(|e| format!("Audio analysis error: {}", e))?;
     
     Ok(serde_json::json!({
         "analysis": response.text,
         "confidence": response.confidence,
+        "confidence_source": response.confidence_source,
         "processing_time_ms": response.processing_time_ms,
-        "audio_features": {
-            "avg_amplitude": avg_amplitude,
-            "peak_amplitude": max_amplitude,
-            "zero_crossings": zero_crossings,
-            "sample_count": audio_features.len()
-        },
+        "audio_features": features,
         "recommendations": [
             "Consider applying noise reduction if background noise is high",
             "Use trigger detection for automated monitoring",
@@ -0,0 +1,149 @@
+use serde::Serialize;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+// Emitted when an AI command starts, so the frontend learns the id to cancel with
+pub const AI_REQUEST_STARTED_EVENT: &str = "dwight://ai-request-started";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiRequestStarted {
+    pub request_id: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CancelledRequest {
+    pub request_id: String,
+    pub partial_output: Option<String>,
+}
+
+struct RequestEntry {
+    cancel: watch::Sender<bool>,
+    partial: Arc<Mutex<String>>,
+}
+
+// Tracks in-flight AI requests so they can be aborted from the frontend
+pub struct AiRequestRegistry {
+    requests: Mutex<HashMap<String, RequestEntry>>,
+    next_id: AtomicU64,
+}
+
+impl AiRequestRegistry {
+    pub fn new() -> Self {
+        AiRequestRegistry {
+            requests: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    // Registers a request under the caller's id, or a generated one. The entry
+    // is removed again when the returned handle is dropped. An id already in
+    // use is refused, as the second request would take over the first's entry.
+    pub fn register(&self, request_id: Option<String>) -> Result<ActiveRequest<'_>> {
+        let id = request_id.unwrap_or_else(|| {
+            format!(
+                "ai-{}-{}",
+                chrono::Utc::now().timestamp_millis(),
+                self.next_id.fetch_add(1, Ordering::Relaxed)
+            )
+        });
+
+        let (cancel, cancelled) = watch::channel(false);
+        let partial = Arc::new(Mutex::new(String::new()));
+
+        let mut requests = self.requests.lock().map_err(|_| anyhow::anyhow!("Request registry is poisoned"))?;
+        if requests.contains_key(&id) {
+            return Err(anyhow::anyhow!("Request {} is already running", id));
+        }
+        requests.insert(id.clone(), RequestEntry {
+            cancel,
+            partial: partial.clone(),
+        });
+
+        Ok(ActiveRequest {
+            id,
+            cancelled,
+            partial,
+            registry: self,
+        })
+    }
+
+    // Signals cancellation and returns whatever output was produced so far.
+    // Returns None if no request with that id is running.
+    pub fn cancel(&self, request_id: &str) -> Option<CancelledRequest> {
+        let requests = self.requests.lock().ok()?;
+        let entry = requests.get(request_id)?;
+
+        let _ = entry.cancel.send(true);
+        let partial = entry.partial.lock().map(|p| p.clone()).unwrap_or_default();
+
+        Some(CancelledRequest {
+            request_id: request_id.to_string(),
+            partial_output: if partial.is_empty() { None } else { Some(partial) },
+        })
+    }
+
+    pub fn active_ids(&self) -> Vec<String> {
+        self.requests.lock()
+            .map(|requests| requests.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+pub struct ActiveRequest<'a> {
+    pub id: String,
+    cancelled: watch::Receiver<bool>,
+    partial: Arc<Mutex<String>>,
+    registry: &'a AiRequestRegistry,
+}
+
+impl ActiveRequest<'_> {
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    // Record streamed output so a cancel can hand it back
+    pub fn push_partial(&self, token: &str) {
+        if let Ok(mut partial) = self.partial.lock() {
+            partial.push_str(token);
+        }
+    }
+
+    // Forget streamed output, before another model starts on the request
+    pub fn reset_partial(&self) {
+        if let Ok(mut partial) = self.partial.lock() {
+            partial.clear();
+        }
+    }
+
+    // Drives `future` until it finishes or the request is cancelled. Dropping the
+    // future on cancel aborts the underlying HTTP request or stream.
+    pub async fn run<T, F>(&self, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if self.is_cancelled() {
+            return Err(anyhow::anyhow!("Request {} was cancelled", self.id));
+        }
+
+        let mut cancelled = self.cancelled.clone();
+        tokio::select! {
+            result = future => result,
+            _ = cancelled.wait_for(|cancelled| *cancelled) => {
+                Err(anyhow::anyhow!("Request {} was cancelled", self.id))
+            }
+        }
+    }
+}
+
+impl Drop for ActiveRequest<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut requests) = self.registry.requests.lock() {
+            requests.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use tauri::{command, Emitter, State};
+use serde::{Deserialize, Serialize};
+use crate::ai_models::{self, AdvancedAI, GenerationOptions, LlamaResponse};
+use crate::database::Database;
+use crate::structured_output::extract_json;
+
+// Tool calls per user turn before Dwight has to give an answer
+const MAX_TOOL_ITERATIONS: usize = 5;
+
+// Model used for agent chat when the caller doesn't pick one
+const DEFAULT_AGENT_MODEL: &str = "llama3-8b";
+
+// Recording runs in the frontend, so the start_recording tool asks it to start
+pub const START_RECORDING_EVENT: &str = "dwight://start-recording";
+
+type ToolHandler = fn(&tauri::AppHandle, &serde_json::Value) -> Result<serde_json::Value, String>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    // JSON schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+struct Tool {
+    definition: ToolDefinition,
+    handler: ToolHandler,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentResponse {
+    pub answer: String,
+    pub tool_calls: Vec<ToolCallRecord>,
+    // The model response that produced the final answer
+    pub response: LlamaResponse,
+}
+
+// What the model may reply with on each step
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ModelTurn {
+    ToolCall {
+        tool: String,
+        #[serde(default)]
+        arguments: serde_json::Value,
+    },
+    Answer {
+        answer: String,
+    },
+}
+
+fn registry() -> Vec<Tool> {
+    vec![
+        Tool {
+            definition: ToolDefinition {
+                name: "search_recordings",
+                description: "Search saved recordings by title, transcript or trigger text, optionally only those spoken in a language (a code such as \"en\" or \"es\")",
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string" },
+                        "language": { "type": "string" },
+                        "limit": { "type": "integer", "minimum": 1, "maximum": 50 }
+                    },
+                    "required": ["query"]
+                }),
+            },
+            handler: search_recordings,
+        },
+        Tool {
+            definition: ToolDefinition {
+                name: "get_transcript",
+                description: "Fetch the transcript of a saved recording by its id",
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "record_id": { "type": "integer" }
+                    },
+                    "required": ["record_id"]
+                }),
+            },
+            handler: get_transcript,
+        },
+        Tool {
+            definition: ToolDefinition {
+                name: "start_recording",
+                description: "Start a new audio recording, optionally stopping after duration_seconds",
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "duration_seconds": { "type": "integer", "minimum": 1 }
+                    }
+                }),
+            },
+            handler: start_recording,
+        },
+    ]
+}
+
+fn open_db(app_handle: &tauri::AppHandle) -> Result<Database, String> {
+    Database::new(app_handle).map_err(|e| format!("Database error: {}", e))
+}
+
+fn search_recordings(app_handle: &tauri::AppHandle, arguments: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let query = arguments["query"].as_str().ok_or("'query' must be a string")?;
+    let language = arguments["language"].as_str();
+    let limit = arguments["limit"].as_u64().unwrap_or(10).clamp(1, 50) as usize;
+
+    let records = open_db(app_handle)?
+        .search_audio_records(query, language, limit)
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    // Transcripts can be long; the model can fetch one with get_transcript
+    Ok(serde_json::json!(records.iter().map(|record| serde_json::json!({
+        "id": record.id,
+        "title": record.title,
+        "duration": record.duration,
+        "created_at": record.created_at,
+        "has_transcript": record.transcript.as_deref().is_some_and(|t| !t.is_empty()),
+    })).collect::<Vec<_>>()))
+}
+
+fn get_transcript(app_handle: &tauri::AppHandle, arguments: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let record_id = arguments["record_id"].as_i64().ok_or("'record_id' must be an integer")?;
+
+    let record = open_db(app_handle)?
+        .get_audio_record(record_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", record_id))?;
+
+    Ok(serde_json::json!({
+        "id": record.id,
+        "title": record.title,
+        "transcript": record.transcript.unwrap_or_default(),
+    }))
+}
+
+fn start_recording(app_handle: &tauri::AppHandle, arguments: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let request = serde_json::json!({
+        "title": arguments["title"].as_str(),
+        "duration_seconds": arguments["duration_seconds"].as_u64(),
+    });
+
+    app_handle.emit(START_RECORDING_EVENT, request.clone())
+        .map_err(|e| format!("Failed to request recording: {}", e))?;
+
+    Ok(serde_json::json!({ "status": "recording requested", "request": request }))
+}
+
+fn build_agent_prompt(system_prompt: &str, tools: &[Tool], user_input: &str, transcript: &[String]) -> String {
+    let definitions: Vec<&ToolDefinition> = tools.iter().map(|tool| &tool.definition).collect();
+
+    let mut prompt = format!(
+        "{}\n\n\
+        You can use these tools:\n{}\n\n\
+        Reply with only a JSON object. To call a tool: {{\"tool\": name, \"arguments\": {{...}}}}. \
+        To answer the user: {{\"answer\": text}}.\n\n\
+        User: {}\n",
+        system_prompt,
+        serde_json::to_string_pretty(&definitions).unwrap_or_default(),
+        user_input
+    );
+    for step in transcript {
+        prompt.push_str(step);
+        prompt.push('\n');
+    }
+    prompt
+}
+
+#[command]
+pub async fn list_ai_tools() -> Result<Vec<ToolDefinition>, String> {
+    Ok(registry().into_iter().map(|tool| tool.definition).collect())
+}
+
+// Chat turn where Dwight may call app tools. Each tool result is appended to
+// the prompt and the model is asked again, until it answers.
+#[command]
+pub async fn dwight_agent_chat(
+    user_input: String,
+    model: Option<String>,
+    options: Option<GenerationOptions>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<AgentResponse, String> {
+    let options = GenerationOptions {
+        json: Some(true),
+        ..options.unwrap_or_default()
+    };
+    options.validate()?;
+
+    let model = model.unwrap_or_else(|| DEFAULT_AGENT_MODEL.to_string());
+    let tools = registry();
+    let system_prompt = ai.system_prompts().active().prompt;
+    let active = ai_models::begin_request(&ai, request_id, "dwight_agent_chat", &app_handle)?;
+
+    let mut transcript: Vec<String> = Vec::new();
+    let mut tool_calls: Vec<ToolCallRecord> = Vec::new();
+
+    for _ in 0..=MAX_TOOL_ITERATIONS {
+        let prompt = build_agent_prompt(&system_prompt, &tools, &user_input, &transcript);
+        let response = active.run(ai.query_llama(&prompt, &model, &options))
+            .await
+            .map_err(|e| format!("Agent chat error: {}", e))?;
+
+        let turn = match serde_json::from_str::<ModelTurn>(extract_json(&response.text)) {
+            Ok(turn) => turn,
+            // Not the JSON protocol; treat the text as Dwight's answer
+            Err(_) => ModelTurn::Answer { answer: response.text.clone() },
+        };
+
+        let (tool, arguments) = match turn {
+            ModelTurn::Answer { answer } => return Ok(AgentResponse { answer, tool_calls, response }),
+            ModelTurn::ToolCall { tool, arguments } => (tool, arguments),
+        };
+
+        if tool_calls.len() == MAX_TOOL_ITERATIONS {
+            break;
+        }
+
+        let outcome = match tools.iter().find(|t| t.definition.name == tool) {
+            Some(t) => (t.handler)(&app_handle, &arguments),
+            None => Err(format!("Unknown tool '{}'", tool)),
+        };
+
+        transcript.push(format!("Assistant: {}", serde_json::json!({ "tool": tool, "arguments": arguments })));
+        transcript.push(match &outcome {
+            Ok(result) => format!("Tool result ({}): {}", tool, result),
+            Err(e) => format!("Tool error ({}): {}", tool, e),
+        });
+
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        tool_calls.push(ToolCallRecord { tool, arguments, result, error });
+    }
+
+    Err(format!("Agent chat error: no answer after {} tool calls", MAX_TOOL_ITERATIONS))
+}
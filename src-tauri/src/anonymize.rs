@@ -0,0 +1,263 @@
+use anyhow::Result;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{command, State};
+use crate::audio_capture::{AudioCapture, StreamFormat};
+use crate::audio_file::{self, WavOutput};
+use crate::clips;
+use crate::database::Database;
+use crate::resampler::{FormatConverter, ResampleQuality};
+use crate::timestretch::TimeStretcher;
+
+// Far enough to stop a voice being recognised by ear, not so far it stops
+// sounding like speech
+const PITCH_DOWN_SEMITONES: f32 = -5.0;
+const PITCH_UP_SEMITONES: f32 = 4.0;
+
+// The monotone the robot voice buzzes at
+const ROBOT_HZ: f64 = 100.0;
+
+// Hann windows overlapped at a quarter of their length, applied on the way
+// in and out, add up to this
+const ROBOT_WINDOW_GAIN: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnonymizeMethod {
+    PitchDown,
+    PitchUp,
+    // Replaces the voice's pitch with a flat buzz, which unlike a pitch
+    // shift can't be undone by shifting it back
+    Robot,
+}
+
+impl AnonymizeMethod {
+    fn name(self) -> &'static str {
+        match self {
+            AnonymizeMethod::PitchDown => "pitch-down",
+            AnonymizeMethod::PitchUp => "pitch-up",
+            AnonymizeMethod::Robot => "robot",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnonymizedClip {
+    pub record_id: i64,
+    pub file_path: String,
+    pub method: AnonymizeMethod,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+// Shifts pitch while keeping the length: the stretcher changes the length
+// by the pitch ratio, and reading the result back at a rate scaled by the
+// same ratio puts the length back and moves the pitch
+struct PitchShifter {
+    stretcher: TimeStretcher,
+    converter: FormatConverter,
+}
+
+impl PitchShifter {
+    fn new(sample_rate: u32, semitones: f32, quality: ResampleQuality) -> Self {
+        let ratio = 2f32.powf(semitones / 12.0);
+        let format = StreamFormat { sample_rate, channels: 1 };
+        let read_as = StreamFormat { sample_rate: (sample_rate as f32 * ratio).round() as u32, channels: 1 };
+        PitchShifter {
+            stretcher: TimeStretcher::new(format, 1.0 / ratio),
+            converter: FormatConverter::new(read_as, format, quality),
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let stretched = self.stretcher.process(samples);
+        self.converter.convert(&stretched)
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        let stretched = self.stretcher.flush();
+        let mut output = self.converter.convert(&stretched);
+        output.extend(self.converter.flush());
+        output
+    }
+}
+
+// Robotises a voice with a phase vocoder: every frame keeps its spectrum's
+// shape but loses its phases, so the frames line up into a buzz at the hop rate
+struct Robotizer {
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    size: usize,
+    hop: usize,
+    window: Vec<f32>,
+    pending: Vec<f32>,
+    output: Vec<f32>,
+    input_samples: usize,
+    output_samples: usize,
+}
+
+impl Robotizer {
+    fn new(sample_rate: u32) -> Self {
+        let hop = ((sample_rate as f64 / ROBOT_HZ).round() as usize).max(1);
+        let size = hop * 4;
+        let mut planner = FftPlanner::new();
+        Robotizer {
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size),
+            size,
+            hop,
+            window: (0..size).map(|index| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / size as f32).cos()).collect(),
+            pending: Vec::new(),
+            output: vec![0.0; size],
+            input_samples: 0,
+            output_samples: 0,
+        }
+    }
+
+    fn frames(&mut self, output: &mut Vec<f32>) {
+        while self.pending.len() >= self.size {
+            let mut spectrum: Vec<Complex<f32>> = self.pending[..self.size]
+                .iter()
+                .zip(&self.window)
+                .map(|(&sample, &weight)| Complex::new(sample * weight, 0.0))
+                .collect();
+            self.forward.process(&mut spectrum);
+            // Zero phase, turned half a frame so each frame's pulse sits in
+            // the middle of the window instead of at its edges
+            for (bin, value) in spectrum.iter_mut().enumerate() {
+                let sign = if bin % 2 == 0 { 1.0 } else { -1.0 };
+                *value = Complex::new(value.norm() * sign, 0.0);
+            }
+            self.inverse.process(&mut spectrum);
+
+            let scale = 1.0 / (self.size as f32 * ROBOT_WINDOW_GAIN);
+            for ((sum, value), &weight) in self.output.iter_mut().zip(&spectrum).zip(&self.window) {
+                *sum += value.re * weight * scale;
+            }
+            output.extend(self.output.drain(..self.hop));
+            self.output.resize(self.size, 0.0);
+            self.output_samples += self.hop;
+            self.pending.drain(..self.hop);
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.pending.extend_from_slice(samples);
+        self.input_samples += samples.len();
+        let mut output = Vec::new();
+        self.frames(&mut output);
+        output
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        self.pending.resize(self.pending.len() + self.size, 0.0);
+        let mut output = Vec::new();
+        self.frames(&mut output);
+        output.append(&mut self.output);
+        let excess = (self.output_samples + self.size).saturating_sub(self.input_samples);
+        output.truncate(output.len().saturating_sub(excess));
+        output
+    }
+}
+
+enum Anonymizer {
+    Pitch(PitchShifter),
+    Robot(Robotizer),
+}
+
+impl Anonymizer {
+    fn new(method: AnonymizeMethod, sample_rate: u32, quality: ResampleQuality) -> Self {
+        match method {
+            AnonymizeMethod::PitchDown => Anonymizer::Pitch(PitchShifter::new(sample_rate, PITCH_DOWN_SEMITONES, quality)),
+            AnonymizeMethod::PitchUp => Anonymizer::Pitch(PitchShifter::new(sample_rate, PITCH_UP_SEMITONES, quality)),
+            AnonymizeMethod::Robot => Anonymizer::Robot(Robotizer::new(sample_rate)),
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        match self {
+            Anonymizer::Pitch(shifter) => shifter.process(samples),
+            Anonymizer::Robot(robotizer) => robotizer.process(samples),
+        }
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        match self {
+            Anonymizer::Pitch(shifter) => shifter.flush(),
+            Anonymizer::Robot(robotizer) => robotizer.flush(),
+        }
+    }
+}
+
+// The clip is mixed down to mono first, which also drops where in the
+// room a voice came from
+fn write_anonymized(
+    source: &Path,
+    output_path: &Path,
+    start_seconds: f64,
+    end_seconds: f64,
+    method: AnonymizeMethod,
+    quality: ResampleQuality,
+) -> Result<()> {
+    let (format, _) = audio_file::audio_info(source)?;
+    let mut mono = FormatConverter::new(format, StreamFormat { sample_rate: format.sample_rate, channels: 1 }, quality);
+    let mut anonymizer = Anonymizer::new(method, format.sample_rate, quality);
+    let mut output = WavOutput::create(output_path, hound::WavSpec {
+        channels: 1,
+        sample_rate: format.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    })?;
+    audio_file::for_each_chunk_between(source, start_seconds, end_seconds, |chunk| {
+        output.write(&anonymizer.process(&mono.convert(chunk)))
+    })?;
+    output.write(&anonymizer.flush())?;
+    output.finish()
+}
+
+// Writes part of a recording to the clips folder with the voices disguised,
+// so it can be shared without giving away who is speaking. The recording
+// itself is untouched.
+#[command]
+pub async fn export_anonymized_clip(
+    id: i64,
+    start: f64,
+    end: f64,
+    method: AnonymizeMethod,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<AnonymizedClip, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (_, duration) = audio_file::audio_info(&source).map_err(|e| format!("Export error: {}", e))?;
+    let end = end.min(duration);
+    if start < 0.0 || start >= end {
+        return Err(format!("Clip must start before it ends, within the recording's {:.1} seconds", duration));
+    }
+
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let name = format!("{}-clip-{:.1}s-{:.1}s-{}.wav", stem, start, end, method.name());
+    let output_path = clips::clips_dir(&app_handle).map_err(|e| format!("Export error: {}", e))?.join(name);
+    let quality = capture.get_settings().resample_quality;
+
+    let written = output_path.clone();
+    tokio::task::spawn_blocking(move || write_anonymized(&source, &written, start, end, method, quality))
+        .await
+        .map_err(|e| format!("Export error: {}", e))?
+        .map_err(|e| format!("Export error: {}", e))?;
+
+    Ok(AnonymizedClip {
+        record_id: id,
+        file_path: output_path.to_string_lossy().to_string(),
+        method,
+        start_seconds: start,
+        end_seconds: end,
+    })
+}
@@ -0,0 +1,2398 @@
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tauri::{command, Emitter, Manager, State};
+use tokio::sync::oneshot;
+use crate::database::{AudioRecord, Database, DtmfDigit, InaudibleEvent, RecordingGap, RecordingMetadata, RecordingSegment, RecordingSession, RecordingTrack};
+use crate::acceleration::Acceleration;
+use crate::agc::AutomaticGainControl;
+use crate::channel_policy::{ChannelMap, ChannelPolicy};
+use crate::audio_devices::{is_system_audio, LOOPBACK_PREFIX, SYSTEM_AUDIO_SUPPORTED, SYSTEM_AUDIO_UNSUPPORTED};
+use crate::audio_file;
+use crate::recovery;
+use crate::resampler::{FormatConverter, ResampleQuality};
+use crate::segmentation::{self, SilenceSplit};
+use crate::transcription;
+use crate::transcription_queue::MAX_CONCURRENT_JOBS;
+use crate::denoise::Denoiser;
+use crate::filters::{validate_filters, Filter, FilterChain};
+use crate::input_gain::{InputGain, MAX_INPUT_GAIN_DB, MIN_INPUT_GAIN_DB};
+use crate::dtmf::{DtmfDetection, DtmfDetector, DTMF_DETECTED_EVENT};
+use crate::inaudible::{InaudibleDetector, InaudibleLog, InaudibleSettings, INAUDIBLE_ACTIVITY_EVENT};
+use crate::levels::{LevelMeter, AUDIO_LEVELS_EVENT};
+use crate::live_transcription::LiveTranscriber;
+use crate::loudness::{LoudnessMeter, LIVE_LOUDNESS_EVENT};
+use crate::keyword_spotter::{KeywordDetection, KeywordSpotter, SpottedPhrase, KEYWORD_DETECTED_EVENT};
+use crate::sound_events::{EventTracker, SoundEvent, SoundEventClassifier, SoundEventDetection, SOUND_EVENT_DETECTED_EVENT};
+use crate::vad::{SpeechRegion, VoiceActivityDetector, VOICE_ACTIVITY_EVENT};
+#[cfg(target_os = "linux")]
+use crate::pulse_monitor::{self, MonitorSource, MonitorStream};
+
+pub const AUDIO_DEVICE_LOST_EVENT: &str = "dwight://audio-device-lost";
+pub const AUDIO_DEVICE_CHANGED_EVENT: &str = "dwight://audio-device-changed";
+// The system audio source, lost earlier, is captured again
+pub const SYSTEM_AUDIO_RESTORED_EVENT: &str = "dwight://system-audio-restored";
+// Sent for every recording the capture thread saves, including triggered ones
+pub const RECORDING_SAVED_EVENT: &str = "dwight://recording-saved";
+
+// Some hosts never report an unplugged device, its callbacks just stop coming
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+// How often each stream is checked for a stall
+const STALL_CHECK: Duration = Duration::from_millis(500);
+
+// System audio runs on its own clock; what it gets ahead of the microphone
+// by beyond this is dropped, and when it falls behind the gap is silence
+const MAX_SYSTEM_AUDIO_LEAD_SECONDS: f64 = 0.25;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceLost {
+    pub device: String,
+    pub error: String,
+    // Device capture continues on, if one could be opened
+    pub fallback: Option<String>,
+}
+
+// The longest pre-roll that can be buffered, in seconds
+const MAX_PRE_ROLL_SECONDS: u32 = 300;
+
+const MAX_BUFFER_MEMORY_MB: u32 = 16384;
+
+const CAPTURE_SETTINGS_KEY: &str = "capture_settings";
+
+// Lead-in kept before speech when recordings are gated by the VAD
+const GATE_LEAD_SECONDS: u32 = 1;
+
+const LIVE_LOUDNESS_SECONDS: f64 = 1.0;
+
+// How often a recording's WAV header is brought up to date, so a crash
+// loses at most this much even before startup recovery repairs the file
+const CHECKPOINT_SECONDS: u64 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    // Audio kept from before a recording starts while monitoring; 0 turns it off
+    pub pre_roll_seconds: u32,
+    // Channels the pre-roll holds, for interfaces with many inputs; the rest
+    // are silent in a recording's pre-roll
+    pub max_buffered_channels: u16,
+    // Memory the pre-roll may take, which cuts it short when the length and
+    // channels would need more. It is also kept to half the memory free when
+    // capture starts.
+    pub buffer_memory_mb: u32,
+    // Voice activity detection on the live stream. Changes to these apply the
+    // next time capture starts, except for gating.
+    pub vad_enabled: bool,
+    // 0 calls most sounds speech, 3 only clear speech
+    pub vad_aggressiveness: u8,
+    // Silence after speech before the speech counts as ended
+    pub vad_hangover_ms: u32,
+    // Only write speech to recordings, so silent hours don't fill the disk
+    pub vad_gate_recording: bool,
+    // Listen for the active speech triggers while monitoring. Hearing one
+    // starts a recording that runs `trigger_record_seconds` past the last
+    // detection; during a recording it tags the recording instead.
+    pub keyword_spotting: bool,
+    // Directory of a Vosk model, e.g. vosk-model-small-en-us
+    pub keyword_model_path: Option<String>,
+    pub keyword_min_confidence: f32,
+    pub trigger_record_seconds: u32,
+    // Also write an RNNoise-denoised copy of recordings; the original is always
+    // kept as captured. Recordings started from the UI can override this.
+    pub denoise: bool,
+    // Automatic gain control on live capture, towards an RMS level in dBFS.
+    // Level meters show the input before it.
+    pub agc_enabled: bool,
+    pub agc_target_db: f32,
+    pub agc_max_gain_db: f32,
+    // Channels to capture from interfaces with more than one input; the
+    // device's default without it. Recordings keep every channel. A device
+    // with a channel policy is opened for what the policy takes instead.
+    pub channels: Option<u16>,
+    // Used wherever recorded audio changes rate: fallback devices, denoising,
+    // transcription and imports
+    pub resample_quality: ResampleQuality,
+    // How saved recordings are kept; recordings started from the UI can
+    // override it. Audio is always captured to WAV first, so a crash loses
+    // nothing, and is encoded when the recording stops.
+    pub storage_profile: StorageProfile,
+    // Format of forensic recordings
+    pub storage_codec: StorageCodec,
+    // Opus bitrate of monitoring recordings
+    pub opus_bitrate_kbps: u32,
+    // Also split recordings at silences at least this long, saving each part
+    // as a recording linked to the whole one; off without it
+    pub segment_silence_seconds: Option<u32>,
+    // Level below which audio counts as silence for splitting, in dBFS
+    pub segment_silence_db: f32,
+    // EBU R128 loudness of the live stream after gain control, sent every
+    // second. It covers the recording in progress, otherwise all of capture;
+    // applies the next time capture starts.
+    pub live_loudness: bool,
+    // Classify sounds on the live stream with an ONNX model such as YAMNet.
+    // Sounds matching an active sound trigger start or tag a recording, as
+    // trigger phrases do. Applies the next time capture starts.
+    pub sound_events: bool,
+    pub sound_event_model_path: Option<String>,
+    // The model's class names, one per line or as YAMNet's class_map.csv
+    pub sound_event_labels_path: Option<String>,
+    // Rate the model takes audio at: 16000 for YAMNet, 32000 for PANNs
+    pub sound_event_sample_rate: u32,
+    pub sound_event_min_confidence: f32,
+    // Listen for phone dialing tones. Digits heard during a recording are
+    // logged with it. Applies the next time capture starts.
+    pub dtmf_detection: bool,
+    // Flag energy above `ultrasonic_hz`, e.g. tracking beacons, and below
+    // `infrasonic_hz`, e.g. machinery rumble, louder than the threshold. The
+    // ultrasonic band needs a sample rate of about 44.1 kHz or more. Events
+    // during a recording are stored with it. Applies the next time capture
+    // starts.
+    pub inaudible_detection: bool,
+    pub ultrasonic_hz: f32,
+    pub infrasonic_hz: f32,
+    pub inaudible_threshold_db: f32,
+    // A loopback source recorded alongside the microphone, to document both
+    // sides of a call; Windows, and Linux with PulseAudio or PipeWire.
+    // Applies the next time capture starts.
+    pub system_audio_source: Option<String>,
+    pub source_mix: SourceMix,
+    // Share of system audio in a mono mix: 0 is only the microphone, 1 only
+    // the system audio, 0.5 both at full level
+    pub system_audio_balance: f32,
+    // Filters run in order, e.g. a 60 Hz notch for mains hum then a 100 Hz
+    // high-pass for rumble. They are the default chain for filtered copies of
+    // recordings; `filter_live` also runs them on live capture, before gain
+    // control, so recordings and detectors hear the filtered audio. Applies
+    // the next time capture starts.
+    pub filters: Vec<Filter>,
+    pub filter_live: bool,
+    // Digital gain in dB by device id, applied to each source as it arrives,
+    // before anything else hears it. Devices not listed get none.
+    pub input_gains: BTreeMap<String, f32>,
+    // Which channels to take from a device, by device id, before its gain.
+    // Live analysis and recordings both get only those. Devices not listed
+    // are captured with every channel; applies the next time capture starts.
+    pub channel_policies: BTreeMap<String, ChannelPolicy>,
+    // Transcribe the live stream with a local Whisper model, sending text as
+    // live transcript events a few seconds behind speech. The language is
+    // detected from the first speech without one. Applies the next time
+    // capture starts.
+    pub live_transcription: bool,
+    pub live_transcription_model: String,
+    pub live_transcription_language: Option<String>,
+    // Also translate transcribed recordings that aren't in English, with
+    // Whisper's translate task, keeping the English beside the original
+    pub translate_to_english: bool,
+    // Diarize recordings straight after transcribing them, labelling each
+    // line with its speaker
+    pub diarize_speakers: bool,
+    // An ONNX speaker-embedding model, such as ECAPA-TDNN or an x-vector
+    // network, that diarization and speaker identification tell voices by
+    pub speaker_model_path: Option<String>,
+    // Cosine similarity of two prints at which the model takes them for the
+    // same voice; calibrate it for the model, e.g. at its equal error rate
+    pub speaker_match_threshold: f32,
+    // Where Whisper runs; a GPU backend must have been found at startup.
+    // Applies to the next transcription.
+    pub transcription_acceleration: Acceleration,
+    // How many batch transcription jobs run at once. Each holds a Whisper
+    // model of its own, so memory goes up with it.
+    pub transcription_concurrency: u32,
+    // Post-processing for the clean transcript kept beside each verbatim
+    // one: sentences broken at pauses and capitalized, "um"s and "uh"s
+    // taken out, and swear words starred. Custom find/replace rules apply too.
+    pub restore_punctuation: bool,
+    pub remove_filler_words: bool,
+    pub mask_profanity: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageProfile {
+    // Bit-exact audio in `storage_codec`
+    #[default]
+    ForensicLossless,
+    // Opus, a small fraction of the size, for round-the-clock monitoring
+    MonitoringCompressed,
+}
+
+// How the microphone and system audio share a recording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMix {
+    // Two channels, the microphone left and the system audio right, each
+    // transcribed on its own
+    #[default]
+    Tracks,
+    Mono,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageCodec {
+    #[default]
+    Wav,
+    // Lossless, about half the size of WAV
+    Flac,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        CaptureSettings {
+            pre_roll_seconds: 30,
+            max_buffered_channels: 8,
+            buffer_memory_mb: 256,
+            vad_enabled: true,
+            vad_aggressiveness: 2,
+            vad_hangover_ms: 500,
+            vad_gate_recording: false,
+            keyword_spotting: false,
+            keyword_model_path: None,
+            keyword_min_confidence: 0.7,
+            trigger_record_seconds: 60,
+            denoise: false,
+            agc_enabled: false,
+            agc_target_db: -20.0,
+            agc_max_gain_db: 30.0,
+            channels: None,
+            resample_quality: ResampleQuality::default(),
+            storage_profile: StorageProfile::default(),
+            storage_codec: StorageCodec::default(),
+            opus_bitrate_kbps: 24,
+            segment_silence_seconds: None,
+            segment_silence_db: -50.0,
+            live_loudness: false,
+            sound_events: false,
+            sound_event_model_path: None,
+            sound_event_labels_path: None,
+            sound_event_sample_rate: 16000,
+            sound_event_min_confidence: 0.5,
+            dtmf_detection: false,
+            inaudible_detection: false,
+            ultrasonic_hz: 18000.0,
+            infrasonic_hz: 20.0,
+            inaudible_threshold_db: -60.0,
+            system_audio_source: None,
+            source_mix: SourceMix::default(),
+            system_audio_balance: 0.5,
+            filters: Vec::new(),
+            filter_live: false,
+            input_gains: BTreeMap::new(),
+            channel_policies: BTreeMap::new(),
+            live_transcription: false,
+            live_transcription_model: transcription::DEFAULT_MODEL_SIZE.to_string(),
+            live_transcription_language: None,
+            translate_to_english: false,
+            diarize_speakers: false,
+            speaker_model_path: None,
+            speaker_match_threshold: 0.5,
+            transcription_acceleration: Acceleration::default(),
+            transcription_concurrency: 1,
+            restore_punctuation: false,
+            remove_filler_words: false,
+            mask_profanity: false,
+        }
+    }
+}
+
+impl CaptureSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pre_roll_seconds > MAX_PRE_ROLL_SECONDS {
+            return Err(format!("pre_roll_seconds must be at most {}", MAX_PRE_ROLL_SECONDS));
+        }
+        if !(1..=32).contains(&self.max_buffered_channels) {
+            return Err("max_buffered_channels must be between 1 and 32".to_string());
+        }
+        if !(1..=MAX_BUFFER_MEMORY_MB).contains(&self.buffer_memory_mb) {
+            return Err(format!("buffer_memory_mb must be between 1 and {}", MAX_BUFFER_MEMORY_MB));
+        }
+        if self.vad_aggressiveness > 3 {
+            return Err("vad_aggressiveness must be between 0 and 3".to_string());
+        }
+        if self.vad_hangover_ms > 10_000 {
+            return Err("vad_hangover_ms must be at most 10000".to_string());
+        }
+        if self.keyword_spotting && self.keyword_model_path.as_deref().is_none_or(|path| path.trim().is_empty()) {
+            return Err("keyword_model_path is required for keyword spotting".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.keyword_min_confidence) {
+            return Err("keyword_min_confidence must be between 0.0 and 1.0".to_string());
+        }
+        if !(5..=3600).contains(&self.trigger_record_seconds) {
+            return Err("trigger_record_seconds must be between 5 and 3600".to_string());
+        }
+        if !(-40.0..=-3.0).contains(&self.agc_target_db) {
+            return Err("agc_target_db must be between -40 and -3".to_string());
+        }
+        if !(0.0..=40.0).contains(&self.agc_max_gain_db) {
+            return Err("agc_max_gain_db must be between 0 and 40".to_string());
+        }
+        if self.channels.is_some_and(|channels| !(1..=32).contains(&channels)) {
+            return Err("channels must be between 1 and 32".to_string());
+        }
+        if !(6..=256).contains(&self.opus_bitrate_kbps) {
+            return Err("opus_bitrate_kbps must be between 6 and 256".to_string());
+        }
+        if self.segment_silence_seconds.is_some_and(|seconds| !(1..=3600).contains(&seconds)) {
+            return Err("segment_silence_seconds must be between 1 and 3600".to_string());
+        }
+        if !(-90.0..=-10.0).contains(&self.segment_silence_db) {
+            return Err("segment_silence_db must be between -90 and -10".to_string());
+        }
+        if self.sound_events && self.sound_event_model_path.as_deref().is_none_or(|path| path.trim().is_empty()) {
+            return Err("sound_event_model_path is required for sound event classification".to_string());
+        }
+        if self.sound_events && self.sound_event_labels_path.as_deref().is_none_or(|path| path.trim().is_empty()) {
+            return Err("sound_event_labels_path is required for sound event classification".to_string());
+        }
+        if self.diarize_speakers && self.speaker_model_path.as_deref().is_none_or(|path| path.trim().is_empty()) {
+            return Err("speaker_model_path is required for diarization".to_string());
+        }
+        if !(self.speaker_match_threshold > 0.0 && self.speaker_match_threshold < 1.0) {
+            return Err("speaker_match_threshold must be between 0.0 and 1.0".to_string());
+        }
+        if !(8000..=48000).contains(&self.sound_event_sample_rate) {
+            return Err("sound_event_sample_rate must be between 8000 and 48000".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.sound_event_min_confidence) {
+            return Err("sound_event_min_confidence must be between 0.0 and 1.0".to_string());
+        }
+        if !(15000.0..=40000.0).contains(&self.ultrasonic_hz) {
+            return Err("ultrasonic_hz must be between 15000 and 40000".to_string());
+        }
+        if !(5.0..=40.0).contains(&self.infrasonic_hz) {
+            return Err("infrasonic_hz must be between 5 and 40".to_string());
+        }
+        if !(-100.0..=-20.0).contains(&self.inaudible_threshold_db) {
+            return Err("inaudible_threshold_db must be between -100 and -20".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.system_audio_balance) {
+            return Err("system_audio_balance must be between 0.0 and 1.0".to_string());
+        }
+        if let Some(source) = self.system_audio_source.as_deref() {
+            if !SYSTEM_AUDIO_SUPPORTED {
+                return Err(SYSTEM_AUDIO_UNSUPPORTED.to_string());
+            }
+            if !is_system_audio(source) {
+                return Err(format!("system_audio_source '{}' is not a loopback device", source));
+            }
+        }
+        validate_filters(&self.filters)?;
+        if self.input_gains.values().any(|gain| !(MIN_INPUT_GAIN_DB..=MAX_INPUT_GAIN_DB).contains(gain)) {
+            return Err(format!("Input gains must be between {} and {} dB", MIN_INPUT_GAIN_DB, MAX_INPUT_GAIN_DB));
+        }
+        for (device, policy) in &self.channel_policies {
+            policy.validate().map_err(|e| format!("Channel policy for '{}': {}", device, e))?;
+        }
+        if !transcription::is_model_size(&self.live_transcription_model) {
+            return Err(format!("'{}' is not a Whisper model size", self.live_transcription_model));
+        }
+        if self.live_transcription_language.as_deref().is_some_and(|language| !transcription::is_language(language)) {
+            return Err("live_transcription_language must be a Whisper language code such as \"en\"".to_string());
+        }
+        if !(1..=MAX_CONCURRENT_JOBS).contains(&self.transcription_concurrency) {
+            return Err(format!("transcription_concurrency must be between 1 and {}", MAX_CONCURRENT_JOBS));
+        }
+        Ok(())
+    }
+
+    pub fn input_gain_db(&self, device_id: &str) -> f32 {
+        self.input_gains.get(device_id).copied().unwrap_or(0.0)
+    }
+
+    fn channel_policy(&self, device_id: &str) -> Option<ChannelPolicy> {
+        self.channel_policies.get(device_id).cloned()
+    }
+
+    // Turns down a budget the machine can't spare right now, rather than
+    // have it quietly cut when capture starts
+    pub fn check_memory(&self) -> Result<(), String> {
+        let ceiling = buffer_memory_ceiling();
+        if self.buffer_memory_mb as u64 * MB > ceiling {
+            return Err(format!(
+                "buffer_memory_mb is more than half the memory free right now; at most {} MB can be given to buffering",
+                ceiling / MB
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn storage(&self, profile: StorageProfile) -> Storage {
+        match (profile, self.storage_codec) {
+            (StorageProfile::ForensicLossless, StorageCodec::Wav) => Storage::Wav,
+            (StorageProfile::ForensicLossless, StorageCodec::Flac) => Storage::Flac,
+            (StorageProfile::MonitoringCompressed, _) => Storage::Opus {
+                bitrate_kbps: self.opus_bitrate_kbps,
+                quality: self.resample_quality,
+            },
+        }
+    }
+
+    pub fn inaudible_settings(&self) -> InaudibleSettings {
+        InaudibleSettings {
+            ultrasonic_hz: self.ultrasonic_hz,
+            infrasonic_hz: self.infrasonic_hz,
+            threshold_db: self.inaudible_threshold_db,
+        }
+    }
+}
+
+const MB: u64 = 1024 * 1024;
+
+// Half of the memory free now, leaving room for the rest of the app and
+// system. Without a reading, buffering is only held to its own budget.
+fn buffer_memory_ceiling() -> u64 {
+    let mut system = System::new();
+    system.refresh_memory();
+    match system.available_memory() {
+        0 => u64::MAX,
+        available => available / 2,
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureStatus {
+    pub monitoring: bool,
+    pub recording: bool,
+    // Whether the VAD hears speech right now
+    pub speaking: bool,
+    pub device: Option<String>,
+    // Format of the capture; audio from a fallback device is converted to it
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub file_path: Option<String>,
+    pub started_at: Option<String>,
+    pub duration_seconds: f64,
+    // Pre-roll audio buffered for the next recording
+    pub pre_roll_seconds: f64,
+    // The most pre-roll the buffer can hold within its memory budget
+    pub pre_roll_capacity_seconds: f64,
+    // Times capture moved to another device because the current one was lost
+    pub failovers: usize,
+    // Gain automatic gain control is applying, while it's on
+    pub agc_gain_db: Option<f32>,
+    // The interrupted recording the one in progress will be appended to
+    pub resumes_record_id: Option<i64>,
+}
+
+// What whoever starts a recording can note about it, kept in its metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordingNotes {
+    pub location: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingSaved {
+    pub record_id: i64,
+    pub file_path: String,
+    pub duration: f64,
+    // Leading part of the recording that was captured before it started
+    pub pre_roll_seconds: f64,
+    // Where the VAD heard speech, in seconds into the file
+    pub speech_regions: Vec<SpeechRegion>,
+    // Trigger phrases heard during the recording, also stored on the record
+    pub triggers: Vec<String>,
+    // Dialed digits heard during the recording, also logged with the record
+    pub dtmf_digits: Vec<DtmfDigit>,
+    // Ultrasonic and infrasonic activity during the recording, also stored
+    pub inaudible_events: Vec<InaudibleEvent>,
+    // What each channel holds, when the microphone and system audio were
+    // recorded as separate tracks
+    pub tracks: Vec<RecordingTrack>,
+    // The denoised copy, saved as a version of the record
+    pub denoised_file_path: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Every device that contributed audio, in order
+    pub devices: Vec<String>,
+    // Parts the recording was split into at its silences, if it was
+    pub segments: Vec<RecordingSegment>,
+    // Where the recording was interrupted and later resumed
+    pub gaps: Vec<RecordingGap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+enum CaptureMessage {
+    // Interleaved samples from the stream with this generation
+    Samples(u64, Vec<f32>),
+    StreamError(u64, String),
+    StartRecording {
+        title: String,
+        file_path: PathBuf,
+        denoise: bool,
+        storage_profile: StorageProfile,
+        resumes: Option<Resume>,
+        notes: RecordingNotes,
+        reply: oneshot::Sender<Result<CaptureStatus, String>>,
+    },
+    StopRecording(oneshot::Sender<Result<RecordingSaved, String>>),
+    SetMonitoring(bool, oneshot::Sender<CaptureStatus>),
+}
+
+pub fn recordings_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle.path().app_data_dir()
+        .map_err(|e| anyhow::anyhow!("Failed to get app data directory: {}", e))?
+        .join("recordings");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub const RECORDING_FILE_PREFIX: &str = "recording-";
+pub const RECORDING_TIME_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+pub fn new_recording_path(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let name = format!("{}{}.wav", RECORDING_FILE_PREFIX, chrono::Local::now().format(RECORDING_TIME_FORMAT));
+    Ok(recordings_dir(app_handle)?.join(name))
+}
+
+// The input device with this id, or the default input without one
+// An input by name, or an output device for a "loopback:" id
+pub fn find_input_device(device_id: Option<&str>) -> Result<cpal::Device> {
+    let host = cpal::default_host();
+    match device_id.map(|id| (id, id.strip_prefix(LOOPBACK_PREFIX))) {
+        None => host.default_input_device().ok_or_else(|| anyhow::anyhow!("No default audio input device")),
+        Some((_, Some(output))) if cfg!(target_os = "windows") => host.output_devices()?
+            .find(|device| device.name().is_ok_and(|name| name == output))
+            .ok_or_else(|| anyhow::anyhow!("Audio output device '{}' not found", output)),
+        Some((_, Some(_))) => Err(anyhow::anyhow!(SYSTEM_AUDIO_UNSUPPORTED)),
+        Some((id, None)) => host.input_devices()?
+            .find(|device| device.name().is_ok_and(|name| name == id))
+            .ok_or_else(|| anyhow::anyhow!("Audio input device '{}' not found", id)),
+    }
+}
+
+// What capture opens: a cpal device or, on Linux, a monitor source of the
+// sound server, which ALSA doesn't list
+enum CaptureSource {
+    Device(cpal::Device),
+    #[cfg(target_os = "linux")]
+    Monitor(MonitorSource),
+}
+
+// Kept alive for as long as capture runs on it
+enum SourceStream {
+    Device { _stream: cpal::Stream },
+    #[cfg(target_os = "linux")]
+    Monitor { _stream: MonitorStream },
+}
+
+impl CaptureSource {
+    // Id the source was found by, as stored on recordings
+    fn id(&self) -> Result<String> {
+        match self {
+            CaptureSource::Device(device) => source_id(device),
+            #[cfg(target_os = "linux")]
+            CaptureSource::Monitor(source) => Ok(format!("{}{}", LOOPBACK_PREFIX, source.name)),
+        }
+    }
+
+    // What the machine plays, which is silent rather than lost while nothing plays
+    fn is_loopback(&self) -> bool {
+        match self {
+            CaptureSource::Device(device) => is_output_device(device),
+            #[cfg(target_os = "linux")]
+            CaptureSource::Monitor(_) => true,
+        }
+    }
+
+    // The format opening it with `channels` gives
+    fn format(&self, channels: Option<u16>) -> Result<StreamFormat> {
+        match self {
+            CaptureSource::Device(device) => Ok(config_format(&stream_config(device, channels)?)),
+            #[cfg(target_os = "linux")]
+            CaptureSource::Monitor(source) => Ok(StreamFormat { sample_rate: source.sample_rate, channels: channels.unwrap_or(source.channels) }),
+        }
+    }
+
+    fn open(&self, channels: Option<u16>, generation: u64, sender: mpsc::Sender<CaptureMessage>) -> Result<(SourceStream, StreamFormat)> {
+        match self {
+            CaptureSource::Device(device) => {
+                let (stream, format) = open_stream(device, channels, generation, sender)?;
+                Ok((SourceStream::Device { _stream: stream }, format))
+            }
+            #[cfg(target_os = "linux")]
+            CaptureSource::Monitor(source) => {
+                let errors = sender.clone();
+                let (stream, format) = MonitorStream::open(
+                    source,
+                    channels,
+                    move |samples| {
+                        let _ = sender.send(CaptureMessage::Samples(generation, samples));
+                    },
+                    move |e| {
+                        let _ = errors.send(CaptureMessage::StreamError(generation, e));
+                    },
+                )?;
+                Ok((SourceStream::Monitor { _stream: stream }, format))
+            }
+        }
+    }
+}
+
+// The source with this id: an input by name or, for a "loopback:" id, an
+// output device on Windows or a monitor source on Linux
+fn find_source(device_id: Option<&str>) -> Result<CaptureSource> {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = device_id.and_then(|id| id.strip_prefix(LOOPBACK_PREFIX)) {
+        return pulse_monitor::monitor_sources()?
+            .into_iter()
+            .find(|source| source.name == name)
+            .map(CaptureSource::Monitor)
+            .ok_or_else(|| anyhow::anyhow!("Monitor source '{}' not found", name));
+    }
+    find_input_device(device_id).map(CaptureSource::Device)
+}
+
+// System audio to fall back to: the default sink's monitor
+#[cfg(target_os = "linux")]
+fn default_loopback_source() -> Result<CaptureSource> {
+    pulse_monitor::monitor_sources()?
+        .into_iter()
+        .find(|source| source.is_default)
+        .map(CaptureSource::Monitor)
+        .ok_or_else(|| anyhow::anyhow!("No monitor source of the default sound output"))
+}
+
+// System audio to fall back to: the default output, on Windows
+#[cfg(not(target_os = "linux"))]
+fn default_loopback_source() -> Result<CaptureSource> {
+    if !cfg!(target_os = "windows") {
+        return Err(anyhow::anyhow!(SYSTEM_AUDIO_UNSUPPORTED));
+    }
+    cpal::default_host().default_output_device()
+        .map(CaptureSource::Device)
+        .ok_or_else(|| anyhow::anyhow!("No default audio output device"))
+}
+
+// Output devices have no input config; capturing one is WASAPI loopback
+fn is_output_device(device: &cpal::Device) -> bool {
+    device.default_input_config().is_err() && device.default_output_config().is_ok()
+}
+
+// Id the device was found by, as stored on recordings
+fn source_id(device: &cpal::Device) -> Result<String> {
+    let name = device.name()?;
+    Ok(if is_output_device(device) { format!("{}{}", LOOPBACK_PREFIX, name) } else { name })
+}
+
+fn build_typed_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    generation: u64,
+    sender: mpsc::Sender<CaptureMessage>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let errors = sender.clone();
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let samples = data.iter().map(|sample| sample.to_sample::<f32>()).collect();
+            let _ = sender.send(CaptureMessage::Samples(generation, samples));
+        },
+        move |e| {
+            let _ = errors.send(CaptureMessage::StreamError(generation, e.to_string()));
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+// A few seconds straight from a device, on a stream of its own. Only the
+// channels its channel policy takes are kept; there is no gain or anything
+// else. Returns the device's id with the audio.
+pub fn capture_raw(device_id: Option<&str>, seconds: f64, settings: &CaptureSettings) -> Result<(String, StreamFormat, Vec<f32>)> {
+    let source = find_source(device_id)?;
+    let id = source.id()?;
+    let policy = settings.channel_policy(&id);
+    let (sender, receiver) = mpsc::channel();
+    let (_stream, device_format) = source.open(policy.as_ref().and_then(ChannelPolicy::required_channels), 0, sender)?;
+    let map = policy.map(|policy| ChannelMap::new(policy, device_format.channels))
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Audio device '{}': {}", id, e))?;
+    let format = StreamFormat {
+        sample_rate: device_format.sample_rate,
+        channels: map.as_ref().map_or(device_format.channels, ChannelMap::output_channels),
+    };
+    let wanted = (seconds * format.sample_rate as f64) as usize * format.channels as usize;
+    let mut samples = Vec::with_capacity(wanted);
+    while samples.len() < wanted {
+        match receiver.recv_timeout(STALL_TIMEOUT) {
+            Ok(CaptureMessage::Samples(_, chunk)) => match map.as_ref() {
+                Some(map) => samples.extend(map.apply(&chunk)),
+                None => samples.extend(chunk),
+            },
+            Ok(CaptureMessage::StreamError(_, error)) => return Err(anyhow::anyhow!("Audio device '{}': {}", id, error)),
+            Ok(_) => {}
+            Err(_) => return Err(anyhow::anyhow!("No audio received from '{}'", id)),
+        }
+    }
+    samples.truncate(wanted);
+    Ok((id, format, samples))
+}
+
+// The device's default input config, with `channels` instead of its default
+// channel count when it supports that. The default rate is kept if possible.
+fn stream_config(device: &cpal::Device, channels: Option<u16>) -> Result<cpal::SupportedStreamConfig> {
+    let (default, output) = match device.default_input_config() {
+        Ok(config) => (config, false),
+        // Loopback captures an output device in its mix format
+        Err(e) => (device.default_output_config().map_err(|_| e)?, true),
+    };
+    let Some(channels) = channels.filter(|&channels| channels != default.channels()) else {
+        return Ok(default);
+    };
+    let rate = default.sample_rate();
+    let ranges: Vec<cpal::SupportedStreamConfigRange> = if output {
+        device.supported_output_configs()?.collect()
+    } else {
+        device.supported_input_configs()?.collect()
+    };
+    let config = ranges
+        .into_iter()
+        .filter(|range| range.channels() == channels)
+        .min_by_key(|range| {
+            let has_rate = (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate);
+            (!has_rate, range.sample_format() != default.sample_format())
+        })
+        .map(|range| {
+            let rate = rate.clamp(range.min_sample_rate(), range.max_sample_rate());
+            range.with_sample_rate(rate)
+        });
+    Ok(config.unwrap_or_else(|| {
+        eprintln!("Audio input device doesn't support {} channels, using {}", channels, default.channels());
+        default
+    }))
+}
+
+fn config_format(config: &cpal::SupportedStreamConfig) -> StreamFormat {
+    StreamFormat { sample_rate: config.sample_rate().0, channels: config.channels() }
+}
+
+// Opens the device and starts it, in `channels` if it can. Messages carry
+// `generation` so the ones a replaced stream still sends can be told apart.
+fn open_stream(
+    device: &cpal::Device,
+    channels: Option<u16>,
+    generation: u64,
+    sender: mpsc::Sender<CaptureMessage>,
+) -> Result<(cpal::Stream, StreamFormat)> {
+    let supported = stream_config(device, channels)?;
+    let format = config_format(&supported);
+    let config = supported.config();
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_typed_stream::<f32>(device, &config, generation, sender),
+        SampleFormat::F64 => build_typed_stream::<f64>(device, &config, generation, sender),
+        SampleFormat::I8 => build_typed_stream::<i8>(device, &config, generation, sender),
+        SampleFormat::I16 => build_typed_stream::<i16>(device, &config, generation, sender),
+        SampleFormat::I32 => build_typed_stream::<i32>(device, &config, generation, sender),
+        SampleFormat::U8 => build_typed_stream::<u8>(device, &config, generation, sender),
+        SampleFormat::U16 => build_typed_stream::<u16>(device, &config, generation, sender),
+        SampleFormat::U32 => build_typed_stream::<u32>(device, &config, generation, sender),
+        other => Err(anyhow::anyhow!("Unsupported sample format {:?}", other)),
+    }?;
+    stream.play()?;
+    Ok((stream, format))
+}
+
+// The WAV file a recording is written to as the audio arrives
+struct Recorder {
+    writer: hound::WavWriter<BufWriter<File>>,
+    path: PathBuf,
+    format: StreamFormat,
+    samples: u64,
+    checkpointed: u64,
+}
+
+impl Recorder {
+    fn create(path: &Path, format: StreamFormat) -> Result<Self> {
+        let spec = hound::WavSpec {
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        Ok(Recorder { writer: hound::WavWriter::create(path, spec)?, path: path.to_path_buf(), format, samples: 0, checkpointed: 0 })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        for &sample in samples {
+            self.writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        self.samples += samples.len() as u64;
+        let checkpoint_samples = CHECKPOINT_SECONDS * self.format.sample_rate as u64 * self.format.channels as u64;
+        if self.samples - self.checkpointed >= checkpoint_samples {
+            self.writer.flush()?;
+            self.checkpointed = self.samples;
+        }
+        Ok(())
+    }
+
+    fn duration(&self) -> f64 {
+        self.samples as f64 / self.format.channels as f64 / self.format.sample_rate as f64
+    }
+
+    fn finish(self) -> Result<(PathBuf, f64)> {
+        let duration = self.duration();
+        self.writer.finalize()?;
+        Ok((self.path, duration))
+    }
+}
+
+// An interrupted recording that a new one carries on
+#[derive(Debug, Clone, Copy)]
+struct Resume {
+    record_id: i64,
+    // Since the interrupted recording's last audio
+    gap_seconds: f64,
+}
+
+// A recording in progress on the capture thread
+struct Recording {
+    recorder: Recorder,
+    title: String,
+    // Every device that contributed audio, in order
+    devices: Vec<String>,
+    pre_roll_seconds: f64,
+    speech_regions: Vec<SpeechRegion>,
+    // Start of the speech region still open
+    speech_start: Option<f64>,
+    triggers: Vec<String>,
+    // Dialed digits, in seconds into the file
+    dtmf_digits: Vec<DtmfDigit>,
+    // Ultrasonic and infrasonic activity, in seconds into the file
+    inaudible: InaudibleLog,
+    // What each channel holds, when the sources are on tracks of their own
+    tracks: Vec<RecordingTrack>,
+    // Capture time at which a triggered recording stops by itself
+    stop_at: Option<f64>,
+    denoised: Option<DenoisedCopy>,
+    storage_profile: StorageProfile,
+    resumes: Option<Resume>,
+    // Filled in with the devices and saved along with the recording
+    metadata: RecordingMetadata,
+}
+
+// The denoised file written alongside a recording
+struct DenoisedCopy {
+    denoiser: Denoiser,
+    recorder: Recorder,
+}
+
+impl Recording {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.recorder.write(samples)?;
+        if let Some(copy) = self.denoised.as_mut() {
+            let denoised = copy.denoiser.process(samples);
+            copy.recorder.write(&denoised)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn denoised_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}-denoised.wav", stem))
+}
+
+// The last few seconds of monitored audio, so a recording can start before
+// the moment someone pressed record or a trigger fired
+struct PreRollBuffer {
+    samples: VecDeque<f32>,
+    // Always whole frames, so dropping the excess keeps channels aligned
+    capacity: usize,
+    // Channels of the capture, and how many of them are held
+    channels: usize,
+    kept: usize,
+}
+
+impl PreRollBuffer {
+    fn new() -> Self {
+        PreRollBuffer { samples: VecDeque::new(), capacity: 0, channels: 1, kept: 1 }
+    }
+
+    fn set_length(&mut self, seconds: u32, format: StreamFormat) {
+        self.set_limits(seconds, format, format.channels as usize, usize::MAX);
+    }
+
+    // Holds the first `max_channels` channels, in no more than `max_bytes`
+    fn set_limits(&mut self, seconds: u32, format: StreamFormat, max_channels: usize, max_bytes: usize) {
+        let channels = format.channels.max(1) as usize;
+        let kept = max_channels.clamp(1, channels);
+        if (channels, kept) != (self.channels, self.kept) {
+            self.samples.clear();
+            (self.channels, self.kept) = (channels, kept);
+        }
+        let frames = (seconds as usize * format.sample_rate as usize).min(max_bytes / std::mem::size_of::<f32>() / kept);
+        self.capacity = frames * kept;
+        self.trim();
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        if self.kept == self.channels {
+            self.samples.extend(samples);
+        } else {
+            for frame in samples.chunks_exact(self.channels) {
+                self.samples.extend(&frame[..self.kept]);
+            }
+        }
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.samples.len().saturating_sub(self.capacity);
+        self.samples.drain(..excess);
+    }
+
+    // Whole frames; channels that weren't held are silent
+    fn take(&mut self) -> Vec<f32> {
+        let held: Vec<f32> = self.samples.drain(..).collect();
+        if self.kept == self.channels {
+            return held;
+        }
+        let mut frames = Vec::with_capacity(held.len() / self.kept * self.channels);
+        for frame in held.chunks_exact(self.kept) {
+            frames.extend_from_slice(frame);
+            frames.resize(frames.len() + self.channels - self.kept, 0.0);
+        }
+        frames
+    }
+
+    fn seconds(&self, format: StreamFormat) -> f64 {
+        self.samples.len() as f64 / self.kept as f64 / format.sample_rate as f64
+    }
+
+    fn capacity_seconds(&self, format: StreamFormat) -> f64 {
+        self.capacity as f64 / self.kept as f64 / format.sample_rate as f64
+    }
+}
+
+// The stream currently feeding the capture thread
+struct ActiveStream {
+    _stream: SourceStream,
+    device: String,
+    generation: u64,
+    // The channels the device's policy takes, before conversion
+    channel_map: Option<ChannelMap>,
+    converter: FormatConverter,
+    gain: InputGain,
+    // Format of a loopback source. WASAPI sends nothing while nothing plays,
+    // which is silence rather than a lost device.
+    loopback: Option<StreamFormat>,
+    // When its audio last arrived
+    last_audio: Instant,
+}
+
+// The system audio recorded alongside the microphone, converted to mono at
+// the capture rate and mixed in as the microphone's audio arrives
+struct SystemAudio {
+    // None while the source is lost; its track is silent till it is back
+    stream: Option<ActiveStream>,
+    // When the lost source was last looked for
+    retried_at: Instant,
+    device: String,
+    buffer: VecDeque<f32>,
+    mix: SourceMix,
+    balance: f32,
+}
+
+// State owned by the capture thread. It runs while monitoring or recording
+// and keeps one format for its whole life: that of the first device, which
+// audio from fallback devices is converted to.
+struct CaptureThread {
+    app_handle: tauri::AppHandle,
+    sender: mpsc::Sender<CaptureMessage>,
+    status: Arc<Mutex<CaptureStatus>>,
+    settings: Arc<RwLock<CaptureSettings>>,
+    format: StreamFormat,
+    active: Option<ActiveStream>,
+    generation: u64,
+    monitoring: bool,
+    pre_roll: PreRollBuffer,
+    // Half the memory free when capture started; the pre-roll never takes more
+    buffer_memory_ceiling: u64,
+    recording: Option<Recording>,
+    vad: Option<VoiceActivityDetector>,
+    spotter: Option<KeywordSpotter>,
+    classifier: Option<SoundEventClassifier>,
+    live_transcriber: Option<LiveTranscriber>,
+    sound_events: EventTracker,
+    // Lowercase values of the active sound triggers
+    sound_triggers: Vec<String>,
+    dtmf: Option<DtmfDetector>,
+    inaudible: Option<InaudibleDetector>,
+    meter: LevelMeter,
+    filters: Option<FilterChain>,
+    agc: AutomaticGainControl,
+    loudness: Option<LoudnessMeter>,
+    // Capture time of the next live loudness update
+    loudness_due: f64,
+    // Silence held back while gating, so speech starts with a short lead-in
+    gate: PreRollBuffer,
+    // Frames since capture started, in the capture format
+    captured_frames: u64,
+    // Capturing what the machine plays rather than a microphone
+    capturing_system_audio: bool,
+    system_audio: Option<SystemAudio>,
+    // When capture, left without a device, last tried a fallback
+    retried_at: Instant,
+}
+
+impl CaptureThread {
+    fn update_status(&self, update: impl FnOnce(&mut CaptureStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            update(&mut status);
+        }
+    }
+
+    fn status(&self) -> CaptureStatus {
+        self.status.lock().map(|status| status.clone()).unwrap_or_default()
+    }
+
+    fn resample_quality(&self) -> ResampleQuality {
+        self.settings.read().map(|settings| settings.resample_quality).unwrap_or_default()
+    }
+
+    fn storage(&self, profile: StorageProfile) -> Storage {
+        self.settings.read().map(|settings| settings.storage(profile)).unwrap_or(Storage::Wav)
+    }
+
+    fn silence_split(&self) -> Option<SilenceSplit> {
+        let settings = self.settings.read().ok()?;
+        settings.segment_silence_seconds.map(|seconds| SilenceSplit {
+            min_silence_seconds: seconds as f64,
+            threshold_db: settings.segment_silence_db,
+        })
+    }
+
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(e) = self.app_handle.emit(event, payload) {
+            eprintln!("Failed to emit {}: {}", event, e);
+        }
+    }
+
+    fn open_device(&mut self, source: &CaptureSource) -> Result<ActiveStream> {
+        self.generation += 1;
+        let name = source.id()?;
+        let policy = self.settings.read().ok().and_then(|settings| settings.channel_policy(&name));
+        // Fallback devices are asked for the capture's channel count, so
+        // there's less to convert, unless their policy picks channels
+        let target = self.source_format();
+        let channels = match policy.as_ref() {
+            Some(policy) => policy.required_channels(),
+            None => Some(target.channels),
+        };
+        let (stream, format) = source.open(channels, self.generation, self.sender.clone())?;
+        let channel_map = policy.map(|policy| ChannelMap::new(policy, format.channels))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Audio device '{}': {}", name, e))?;
+        let loopback = source.is_loopback().then_some(format);
+        let source = StreamFormat {
+            sample_rate: format.sample_rate,
+            channels: channel_map.as_ref().map_or(format.channels, ChannelMap::output_channels),
+        };
+        Ok(ActiveStream {
+            _stream: stream,
+            device: name,
+            generation: self.generation,
+            channel_map,
+            converter: FormatConverter::new(source, target, self.resample_quality()),
+            gain: InputGain::new(target),
+            loopback,
+            last_audio: Instant::now(),
+        })
+    }
+
+    // Each source is converted to this before they are mixed: the capture
+    // format, or mono when system audio is mixed in
+    fn source_format(&self) -> StreamFormat {
+        match self.system_audio {
+            Some(_) => StreamFormat { sample_rate: self.format.sample_rate, channels: 1 },
+            None => self.format,
+        }
+    }
+
+    fn open_system_audio(&mut self, device_id: &str, mix: SourceMix, balance: f32) -> Result<()> {
+        if !is_system_audio(device_id) {
+            return Err(anyhow::anyhow!("'{}' is not a loopback device", device_id));
+        }
+        let source = find_source(Some(device_id))?;
+        self.system_audio = Some(SystemAudio {
+            stream: None,
+            retried_at: Instant::now(),
+            device: source.id()?,
+            buffer: VecDeque::new(),
+            mix,
+            balance,
+        });
+        let stream = self.open_device(&source)?;
+        if let Some(system_audio) = self.system_audio.as_mut() {
+            system_audio.stream = Some(stream);
+        }
+        Ok(())
+    }
+
+    // Mono microphone audio with the system audio that arrived with it,
+    // either side by side or mixed by the balance
+    fn mix_system_audio(&mut self, microphone: Vec<f32>) -> Vec<f32> {
+        let Some(system_audio) = self.system_audio.as_mut() else {
+            return microphone;
+        };
+        let max_lead = (self.format.sample_rate as f64 * MAX_SYSTEM_AUDIO_LEAD_SECONDS) as usize + microphone.len();
+        if system_audio.buffer.len() > max_lead {
+            let excess = system_audio.buffer.len() - max_lead;
+            system_audio.buffer.drain(..excess);
+        }
+        let system_gain = (2.0 * system_audio.balance).min(1.0);
+        let microphone_gain = (2.0 * (1.0 - system_audio.balance)).min(1.0);
+        let mut mixed = Vec::with_capacity(microphone.len() * self.format.channels as usize);
+        for sample in microphone {
+            let system = system_audio.buffer.pop_front().unwrap_or(0.0);
+            match system_audio.mix {
+                SourceMix::Tracks => mixed.extend([sample, system]),
+                SourceMix::Mono => mixed.push((sample * microphone_gain + system * system_gain).clamp(-1.0, 1.0)),
+            }
+        }
+        mixed
+    }
+
+    // Keeps the system audio until the microphone's audio comes to mix it with
+    fn handle_system_audio(&mut self, generation: u64, samples: &[f32]) -> bool {
+        let Some(system_audio) = self.system_audio.as_mut() else {
+            return false;
+        };
+        let Some(stream) = system_audio.stream.as_mut().filter(|stream| stream.generation == generation) else {
+            return false;
+        };
+        stream.last_audio = Instant::now();
+        let mut converted = match stream.channel_map.as_ref() {
+            Some(map) => stream.converter.convert(&map.apply(samples)),
+            None => stream.converter.convert(samples),
+        };
+        let gain_db = self.settings.read().map_or(0.0, |settings| settings.input_gain_db(&system_audio.device));
+        stream.gain.process(&mut converted, gain_db);
+        system_audio.buffer.extend(converted);
+        true
+    }
+
+    fn lose_system_audio(&mut self, generation: u64, error: String) -> bool {
+        let Some(system_audio) = self.system_audio.as_mut().filter(|system_audio| {
+            system_audio.stream.as_ref().is_some_and(|stream| stream.generation == generation)
+        }) else {
+            return false;
+        };
+        system_audio.stream = None;
+        system_audio.retried_at = Instant::now();
+        system_audio.buffer.clear();
+        let device = system_audio.device.clone();
+        eprintln!("System audio source '{}' lost: {}", device, error);
+        self.emit(AUDIO_DEVICE_LOST_EVENT, DeviceLost { device, error, fallback: None });
+        true
+    }
+
+    // Each stream is checked on its own, so system audio still arriving
+    // can't hide a microphone gone quiet, or the other way round
+    fn check_stalls(&mut self) {
+        let now = Instant::now();
+        match self.active.as_ref().map(|stream| (stream.generation, stream.loopback, now.saturating_duration_since(stream.last_audio))) {
+            Some((_, _, quiet)) if quiet < STALL_TIMEOUT => {}
+            Some((generation, Some(format), quiet)) => {
+                let frames = (format.sample_rate as f64 * quiet.as_secs_f64()) as usize;
+                self.handle_samples(generation, &vec![0.0; frames * format.channels as usize]);
+            }
+            Some((_, None, _)) => self.fail_over("No audio received from the device".to_string()),
+            None if now.saturating_duration_since(self.retried_at) >= STALL_TIMEOUT => {
+                self.retried_at = now;
+                self.fail_over("No audio received from the device".to_string());
+            }
+            None => {}
+        }
+
+        let Some(system_audio) = self.system_audio.as_mut() else {
+            return;
+        };
+        match system_audio.stream.as_ref() {
+            // A loopback source is silent rather than lost while nothing plays
+            Some(stream) if stream.loopback.is_none() && now.saturating_duration_since(stream.last_audio) >= STALL_TIMEOUT => {
+                let generation = stream.generation;
+                self.lose_system_audio(generation, "No audio received from the system audio source".to_string());
+            }
+            Some(_) => {}
+            None if now.saturating_duration_since(system_audio.retried_at) >= STALL_TIMEOUT => {
+                system_audio.retried_at = now;
+                let device_id = system_audio.device.clone();
+                self.reopen_system_audio(&device_id);
+            }
+            None => {}
+        }
+    }
+
+    // A lost system audio source is looked for again every stall timeout, as
+    // a lost microphone is
+    fn reopen_system_audio(&mut self, device_id: &str) {
+        let Ok(stream) = find_source(Some(device_id)).and_then(|source| self.open_device(&source)) else {
+            return;
+        };
+        if let Some(system_audio) = self.system_audio.as_mut() {
+            system_audio.stream = Some(stream);
+            system_audio.buffer.clear();
+        }
+        self.emit(SYSTEM_AUDIO_RESTORED_EVENT, device_id.to_string());
+    }
+
+    fn captured_seconds(&self) -> f64 {
+        self.captured_frames as f64 / self.format.sample_rate as f64
+    }
+
+    fn handle_samples(&mut self, generation: u64, samples: &[f32]) {
+        if self.handle_system_audio(generation, samples) {
+            return;
+        }
+        let Some(stream) = self.active.as_mut().filter(|stream| stream.generation == generation) else {
+            return;
+        };
+        stream.last_audio = Instant::now();
+        let mut converted = match stream.channel_map.as_ref() {
+            Some(map) => stream.converter.convert(&map.apply(samples)),
+            None => stream.converter.convert(samples),
+        };
+        let gain_db = self.settings.read().map_or(0.0, |settings| settings.input_gain_db(&stream.device));
+        stream.gain.process(&mut converted, gain_db);
+        let mut converted = self.mix_system_audio(converted);
+        self.captured_frames += (converted.len() / self.format.channels as usize) as u64;
+        let settings = self.settings.read().map(|settings| settings.clone()).unwrap_or_default();
+
+        // A large callback buffer can finish several windows; only the newest is shown
+        if let Some(levels) = self.meter.process(&converted).pop() {
+            self.emit(AUDIO_LEVELS_EVENT, levels);
+        }
+        if let Some(filters) = self.filters.as_mut() {
+            filters.process(&mut converted);
+        }
+        if settings.agc_enabled {
+            self.agc.process(&mut converted, settings.agc_target_db, settings.agc_max_gain_db);
+        }
+        let agc_gain_db = settings.agc_enabled.then(|| self.agc.gain_db());
+        let loudness_due = self.captured_seconds() >= self.loudness_due;
+        if let Some(meter) = self.loudness.as_mut() {
+            meter.push(&converted);
+            if loudness_due {
+                self.loudness_due += LIVE_LOUDNESS_SECONDS;
+                let report = meter.report();
+                self.emit(LIVE_LOUDNESS_EVENT, report);
+            }
+        }
+
+        let changes = self.vad.as_mut().map(|vad| vad.process(&converted)).unwrap_or_default();
+        let speaking = self.vad.as_ref().is_some_and(VoiceActivityDetector::is_speaking);
+        let inaudible = self.inaudible.as_mut().map(|detector| detector.process(&converted)).unwrap_or_default();
+        let gated = settings.vad_gate_recording && self.vad.is_some() && !speaking && changes.is_empty();
+
+        let now = self.captured_seconds();
+        if let Some(recording) = self.recording.as_mut() {
+            if gated {
+                self.gate.set_length(GATE_LEAD_SECONDS, self.format);
+                self.gate.push(&converted);
+            } else {
+                let lead = self.gate.take();
+                if let Err(e) = recording.write(&lead).and_then(|_| recording.write(&converted)) {
+                    eprintln!("Failed to write recording: {}", e);
+                }
+            }
+            // Map capture time to file time; with gating the file skips the silences
+            let position = recording.recorder.duration();
+            for change in &changes {
+                let at = (position - (now - change.at_seconds)).max(0.0);
+                match (change.speaking, recording.speech_start.take()) {
+                    (true, _) => recording.speech_start = Some(at),
+                    (false, Some(start)) => recording.speech_regions.push(SpeechRegion { start, end: at }),
+                    (false, None) => {}
+                }
+            }
+            for change in &inaudible {
+                recording.inaudible.apply(change, (position - (now - change.at_seconds)).max(0.0));
+            }
+        }
+        if self.monitoring {
+            let budget = (settings.buffer_memory_mb as u64 * MB).min(self.buffer_memory_ceiling);
+            self.pre_roll.set_limits(settings.pre_roll_seconds, self.format, settings.max_buffered_channels as usize, budget as usize);
+            self.pre_roll.push(&converted);
+        }
+        for change in changes {
+            self.emit(VOICE_ACTIVITY_EVENT, change);
+        }
+        for change in inaudible {
+            self.emit(INAUDIBLE_ACTIVITY_EVENT, change);
+        }
+
+        let spotted = self.spotter.as_mut().map(|spotter| spotter.process(&converted)).unwrap_or_default();
+        for phrase in spotted {
+            self.on_trigger_phrase(phrase, &settings);
+        }
+        if let Some(live) = self.live_transcriber.as_mut() {
+            live.process(&converted);
+        }
+        let digits = self.dtmf.as_mut().map(|dtmf| dtmf.process(&converted)).unwrap_or_default();
+        for digit in digits {
+            self.on_dtmf_digit(digit, now);
+        }
+        let windows = self.classifier.as_mut().map(|classifier| classifier.process(&converted)).unwrap_or_default();
+        for window in windows {
+            let (started, _) = self.sound_events.update(&window);
+            for event in started {
+                self.on_sound_event(event, &settings);
+            }
+        }
+        if self.recording.as_ref().and_then(|recording| recording.stop_at).is_some_and(|stop_at| now >= stop_at) {
+            self.stop_recording(None);
+        }
+
+        let duration = self.recording.as_ref().map_or(0.0, |recording| recording.recorder.duration());
+        let buffered = self.pre_roll.seconds(self.format);
+        let capacity = if self.monitoring { self.pre_roll.capacity_seconds(self.format) } else { 0.0 };
+        self.update_status(|status| {
+            status.duration_seconds = duration;
+            status.pre_roll_seconds = buffered;
+            status.pre_roll_capacity_seconds = capacity;
+            status.speaking = speaking;
+            status.agc_gain_db = agc_gain_db;
+        });
+    }
+
+    // Starts a recording for a trigger while monitoring, or tags the one
+    // running. Returns the file of the recording it went to, if any.
+    fn trigger_recording(&mut self, trigger: &str, at_seconds: f64, settings: &CaptureSettings) -> Option<String> {
+        let stop_at = at_seconds + settings.trigger_record_seconds as f64;
+        if self.recording.is_none() && self.monitoring {
+            let started = new_recording_path(&self.app_handle)
+                .map_err(|e| format!("Audio capture error: {}", e))
+                .and_then(|path| {
+                    let title = format!("Trigger: {}", trigger);
+                    self.start_recording(title, &path, settings.denoise, settings.storage_profile, None, RecordingNotes::default())
+                });
+            if let Err(e) = started {
+                eprintln!("Failed to start recording for trigger '{}': {}", trigger, e);
+            }
+            if let Some(recording) = self.recording.as_mut() {
+                recording.stop_at = Some(stop_at);
+            }
+        }
+
+        self.recording.as_mut().map(|recording| {
+            if !recording.triggers.iter().any(|existing| existing == trigger) {
+                recording.triggers.push(trigger.to_string());
+            }
+            // Another detection keeps a triggered recording going
+            if let Some(current) = recording.stop_at.as_mut() {
+                *current = current.max(stop_at);
+            }
+            recording.recorder.path.to_string_lossy().to_string()
+        })
+    }
+
+    fn on_trigger_phrase(&mut self, spotted: SpottedPhrase, settings: &CaptureSettings) {
+        let recording_file = self.trigger_recording(&spotted.phrase, spotted.at_seconds, settings);
+        self.emit(KEYWORD_DETECTED_EVENT, KeywordDetection {
+            phrase: spotted.phrase,
+            confidence: spotted.confidence,
+            at_seconds: spotted.at_seconds,
+            recording_file,
+        });
+    }
+
+    fn on_dtmf_digit(&mut self, digit: DtmfDigit, now: f64) {
+        let recording_file = self.recording.as_mut().map(|recording| {
+            // Same mapping from capture time to file time as speech regions
+            let position = recording.recorder.duration();
+            recording.dtmf_digits.push(DtmfDigit {
+                digit: digit.digit.clone(),
+                start_seconds: (position - (now - digit.start_seconds)).max(0.0),
+                end_seconds: (position - (now - digit.end_seconds)).max(0.0),
+            });
+            recording.recorder.path.to_string_lossy().to_string()
+        });
+        self.emit(DTMF_DETECTED_EVENT, DtmfDetection {
+            digit: digit.digit,
+            start_seconds: digit.start_seconds,
+            end_seconds: digit.end_seconds,
+            recording_file,
+        });
+    }
+
+    // Every sound is reported; only those matching a sound trigger start or
+    // tag a recording
+    fn on_sound_event(&mut self, event: SoundEvent, settings: &CaptureSettings) {
+        let label = event.label.to_lowercase();
+        let triggered = self.sound_triggers.iter().any(|trigger| label.contains(trigger.as_str()));
+        let recording_file = if triggered {
+            self.trigger_recording(&event.label, event.start_seconds, settings)
+        } else {
+            self.recording.as_ref().map(|recording| recording.recorder.path.to_string_lossy().to_string())
+        };
+        self.emit(SOUND_EVENT_DETECTED_EVENT, SoundEventDetection {
+            label: event.label,
+            confidence: event.confidence,
+            at_seconds: event.start_seconds,
+            triggered,
+            recording_file,
+        });
+    }
+
+    // Moves capture to the default input, or to the default system audio when
+    // that's what was captured. Without one, capture pauses and this is tried
+    // again every stall timeout, whether or not system audio still arrives.
+    fn fail_over(&mut self, error: String) {
+        let lost_device = self.active.take().map(|stream| stream.device);
+        let fallback = if self.capturing_system_audio { default_loopback_source() } else { find_source(None) };
+        match fallback.and_then(|source| self.open_device(&source)) {
+            Ok(stream) => {
+                let fallback = stream.device.clone();
+                match lost_device {
+                    Some(device) => self.emit(AUDIO_DEVICE_LOST_EVENT, DeviceLost { device, error, fallback: Some(fallback.clone()) }),
+                    None => self.emit(AUDIO_DEVICE_CHANGED_EVENT, fallback.clone()),
+                }
+                if let Some(recording) = self.recording.as_mut() {
+                    recording.devices.push(fallback.clone());
+                }
+                self.update_status(|status| {
+                    status.device = Some(fallback);
+                    status.failovers += 1;
+                });
+                self.active = Some(stream);
+            }
+            Err(e) => {
+                if let Some(device) = lost_device {
+                    eprintln!("Audio device '{}' lost and no fallback available: {}", device, e);
+                    self.emit(AUDIO_DEVICE_LOST_EVENT, DeviceLost { device, error, fallback: None });
+                    self.update_status(|status| status.device = None);
+                }
+            }
+        }
+    }
+
+    // The recording starts with whatever pre-roll audio is buffered
+    fn start_recording(
+        &mut self,
+        title: String,
+        file_path: &Path,
+        denoise: bool,
+        storage_profile: StorageProfile,
+        resumes: Option<Resume>,
+        notes: RecordingNotes,
+    ) -> Result<CaptureStatus, String> {
+        if self.recording.is_some() {
+            return Err("A recording is already in progress".to_string());
+        }
+        let recorder = Recorder::create(file_path, self.format).map_err(|e| format!("Audio capture error: {}", e))?;
+        // What recovery goes by should the app close before the recording is saved
+        let session = RecordingSession {
+            file_path: file_path.to_string_lossy().to_string(),
+            title: title.clone(),
+            resumes_record_id: resumes.map(|resume| resume.record_id),
+            gap_seconds: resumes.map_or(0.0, |resume| resume.gap_seconds),
+            started_at: String::new(),
+        };
+        if let Err(e) = Database::new(&self.app_handle).and_then(|db| db.save_recording_session(&session)) {
+            eprintln!("Failed to log recording session, it can't be resumed if interrupted: {}", e);
+        }
+        let denoised = if denoise {
+            let recorder = Recorder::create(&denoised_path(file_path), self.format).map_err(|e| format!("Audio capture error: {}", e))?;
+            Some(DenoisedCopy { denoiser: Denoiser::new(self.format, self.resample_quality()), recorder })
+        } else {
+            None
+        };
+
+        let devices = self.active.iter()
+            .map(|stream| stream.device.clone())
+            .chain(self.system_audio.iter().map(|system_audio| system_audio.device.clone()))
+            .collect();
+        // Speech already under way counts from the start of the file
+        let speech_start = self.vad.as_ref().is_some_and(VoiceActivityDetector::is_speaking).then_some(0.0);
+        let tracks = self.system_audio.as_ref()
+            .filter(|system_audio| system_audio.mix == SourceMix::Tracks)
+            .map(|system_audio| vec![
+                RecordingTrack { record_id: 0, channel: 0, source: "microphone".to_string(), device: self.active.as_ref().map(|stream| stream.device.clone()).unwrap_or_default() },
+                RecordingTrack { record_id: 0, channel: 1, source: "system_audio".to_string(), device: system_audio.device.clone() },
+            ])
+            .unwrap_or_default();
+        let mut inaudible = InaudibleLog::default();
+        for (band, peak_db) in self.inaudible.as_ref().map(InaudibleDetector::active_bands).unwrap_or_default() {
+            inaudible.open(band, 0.0, peak_db);
+        }
+        let (agc_target_db, agc_max_gain_db) = self.settings.read().ok()
+            .filter(|settings| settings.agc_enabled)
+            .map(|settings| (settings.agc_target_db, settings.agc_max_gain_db))
+            .unzip();
+        let channel_layout = match (tracks.is_empty(), self.format.channels) {
+            (false, _) => tracks.iter().map(|track| track.source.as_str()).collect::<Vec<_>>().join(" + "),
+            (true, 1) => "mono".to_string(),
+            (true, 2) => "stereo".to_string(),
+            (true, channels) => format!("{} channels", channels),
+        };
+        let channel_layout = match self.active.as_ref().and_then(|stream| stream.channel_map.as_ref()) {
+            Some(map) if tracks.is_empty() => format!("{} ({})", channel_layout, map.describe()),
+            _ => channel_layout,
+        };
+        let metadata = RecordingMetadata {
+            record_id: 0,
+            devices: Vec::new(),
+            sample_rate: self.format.sample_rate,
+            channels: self.format.channels,
+            channel_layout,
+            agc_target_db,
+            agc_max_gain_db,
+            app_version: self.app_handle.package_info().version.to_string(),
+            location: notes.location.filter(|location| !location.trim().is_empty()),
+            notes: notes.notes.filter(|notes| !notes.trim().is_empty()),
+            captured_at: chrono::Utc::now().to_rfc3339(),
+        };
+        self.gate.take();
+        let mut recording = Recording {
+            recorder,
+            title,
+            devices,
+            pre_roll_seconds: 0.0,
+            speech_regions: Vec::new(),
+            speech_start,
+            triggers: Vec::new(),
+            dtmf_digits: Vec::new(),
+            inaudible,
+            tracks,
+            stop_at: None,
+            denoised,
+            storage_profile,
+            resumes,
+            metadata,
+        };
+        let pre_roll = self.pre_roll.take();
+        // Live loudness starts over with each recording, pre-roll included
+        if self.loudness.is_some() {
+            let mut meter = LoudnessMeter::new(self.format);
+            meter.push(&pre_roll);
+            self.loudness = Some(meter);
+        }
+        recording.write(&pre_roll).map_err(|e| format!("Audio capture error: {}", e))?;
+        recording.pre_roll_seconds = recording.recorder.duration();
+        let pre_roll_seconds = recording.pre_roll_seconds;
+        self.recording = Some(recording);
+        self.update_status(|status| {
+            status.recording = true;
+            status.file_path = Some(file_path.to_string_lossy().to_string());
+            status.started_at = Some(chrono::Utc::now().to_rfc3339());
+            status.duration_seconds = pre_roll_seconds;
+            status.pre_roll_seconds = 0.0;
+            status.resumes_record_id = resumes.map(|resume| resume.record_id);
+        });
+        Ok(self.status())
+    }
+
+    // Encoding a long recording takes a while, so it is saved on a thread of
+    // its own and capture carries on meanwhile. Should that thread fail to
+    // start, the WAV and its session are left for recovery. Without `reply`
+    // a failure is only logged.
+    fn stop_recording(&mut self, reply: Option<oneshot::Sender<Result<RecordingSaved, String>>>) {
+        let Some(recording) = self.recording.take() else {
+            if let Some(reply) = reply {
+                let _ = reply.send(Err("No recording in progress".to_string()));
+            }
+            return;
+        };
+        self.update_status(|status| {
+            status.recording = false;
+            status.file_path = None;
+            status.started_at = None;
+            status.duration_seconds = 0.0;
+            status.resumes_record_id = None;
+        });
+        let storage = self.storage(recording.storage_profile);
+        let app_handle = self.app_handle.clone();
+        let format = self.format;
+        let split = self.silence_split();
+        let spawned = std::thread::Builder::new()
+            .name("recording-save".to_string())
+            .spawn(move || {
+                let saved = save_recording(&app_handle, recording, format, storage, split);
+                if let Ok(saved) = &saved {
+                    if let Err(e) = app_handle.emit(RECORDING_SAVED_EVENT, saved.clone()) {
+                        eprintln!("Failed to emit {}: {}", RECORDING_SAVED_EVENT, e);
+                    }
+                }
+                match (reply, saved) {
+                    (Some(reply), saved) => {
+                        let _ = reply.send(saved);
+                    }
+                    (None, Err(e)) => eprintln!("Failed to save recording: {}", e),
+                    (None, Ok(_)) => {}
+                }
+            });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start saving the recording: {}", e);
+        }
+    }
+
+    fn set_monitoring(&mut self, monitoring: bool) -> CaptureStatus {
+        self.monitoring = monitoring;
+        if !monitoring {
+            self.pre_roll.take();
+        }
+        self.update_status(|status| {
+            status.monitoring = monitoring;
+            status.pre_roll_seconds = 0.0;
+        });
+        self.status()
+    }
+
+    fn idle(&self) -> bool {
+        !self.monitoring && self.recording.is_none()
+    }
+
+    // Runs until neither monitoring nor recording is left
+    fn run(mut self, receiver: mpsc::Receiver<CaptureMessage>) {
+        loop {
+            match receiver.recv_timeout(STALL_CHECK) {
+                Ok(CaptureMessage::Samples(generation, samples)) => self.handle_samples(generation, &samples),
+                Ok(CaptureMessage::StreamError(generation, error)) => {
+                    if !self.lose_system_audio(generation, error.clone())
+                        && self.active.as_ref().is_some_and(|stream| stream.generation == generation)
+                    {
+                        self.fail_over(error);
+                    }
+                }
+                Ok(CaptureMessage::StartRecording { title, file_path, denoise, storage_profile, resumes, notes, reply }) => {
+                    let _ = reply.send(self.start_recording(title, &file_path, denoise, storage_profile, resumes, notes));
+                }
+                Ok(CaptureMessage::StopRecording(reply)) => self.stop_recording(Some(reply)),
+                Ok(CaptureMessage::SetMonitoring(monitoring, reply)) => {
+                    let _ = reply.send(self.set_monitoring(monitoring));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.check_stalls();
+            if self.idle() {
+                break;
+            }
+        }
+
+        if let Some(recording) = self.recording.take() {
+            let storage = self.storage(recording.storage_profile);
+            if let Err(e) = save_recording(&self.app_handle, recording, self.format, storage, self.silence_split()) {
+                eprintln!("Failed to save recording: {}", e);
+            }
+        }
+        self.update_status(|status| *status = CaptureStatus::default());
+    }
+}
+
+// The format a finished recording ends up in, from its profile and the settings
+#[derive(Clone, Copy)]
+pub enum Storage {
+    Wav,
+    Flac,
+    Opus { bitrate_kbps: u32, quality: ResampleQuality },
+}
+
+// Re-encodes a finished WAV for storage. If that fails the WAV is kept, so
+// the audio is never lost to an encoder problem.
+pub fn store(path: PathBuf, storage: Storage) -> PathBuf {
+    let encoded = match storage {
+        Storage::Wav => return path,
+        Storage::Flac => audio_file::encode_flac(&path),
+        Storage::Opus { bitrate_kbps, quality } => audio_file::encode_opus(&path, bitrate_kbps, quality),
+    };
+    match encoded {
+        Ok(encoded_path) => {
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("Failed to remove {} after encoding it: {}", path.display(), e);
+            }
+            encoded_path
+        }
+        Err(e) => {
+            eprintln!("Failed to encode {}, keeping the WAV: {}", path.display(), e);
+            path
+        }
+    }
+}
+
+fn save_recording(
+    app_handle: &tauri::AppHandle,
+    mut recording: Recording,
+    format: StreamFormat,
+    storage: Storage,
+    split: Option<SilenceSplit>,
+) -> Result<RecordingSaved, String> {
+    if let Some(start) = recording.speech_start.take() {
+        recording.speech_regions.push(SpeechRegion { start, end: recording.recorder.duration() });
+    }
+    let session = recording.recorder.path.to_string_lossy().to_string();
+    let saved = match recording.resumes.take() {
+        Some(resume) => save_resumed(app_handle, recording, resume, format, storage)?,
+        None => save_new_recording(app_handle, recording, format, storage, split)?,
+    };
+    // Nothing is left for recovery to find
+    if let Err(e) = Database::new(app_handle).and_then(|db| db.delete_recording_session(&session)) {
+        eprintln!("Failed to clear recording session {}: {}", session, e);
+    }
+    Ok(saved)
+}
+
+// Finishes the continuation of an interrupted recording and appends it, so
+// the two stay one recording with a gap where capture stopped. Everything
+// logged during the continuation is moved along by the length before it.
+// Its parts aren't split out: the recording keeps the ones it had.
+fn save_resumed(
+    app_handle: &tauri::AppHandle,
+    mut recording: Recording,
+    resume: Resume,
+    format: StreamFormat,
+    storage: Storage,
+) -> Result<RecordingSaved, String> {
+    let record_id = resume.record_id;
+    let (path, _) = recording.recorder.finish().map_err(|e| format!("Failed to finalize recording: {}", e))?;
+    if let Some(mut copy) = recording.denoised.take() {
+        let rest = copy.denoiser.flush();
+        if let Err(e) = copy.recorder.write(&rest).and_then(|_| copy.recorder.finish()) {
+            eprintln!("Failed to finalize denoised recording: {}", e);
+        }
+    }
+
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(record_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", record_id))?;
+    let quality = app_handle.state::<AudioCapture>().get_settings().resample_quality;
+    let stitched = recovery::stitch(&db, record_id, &path, quality)
+        .map_err(|e| format!("Failed to append to recording {}: {}", record_id, e))?;
+    let (_, duration) = audio_file::audio_info(&stitched.file_path).map_err(|e| format!("Failed to finalize recording: {}", e))?;
+    let file_path = store(stitched.file_path, storage).to_string_lossy().to_string();
+    let denoised_file_path = stitched.denoised_file_path.map(|path| store(path, storage).to_string_lossy().to_string());
+    let offset = stitched.at_seconds;
+
+    let mut triggers: Vec<String> = record.triggers.iter()
+        .flat_map(|triggers| triggers.split(", ").map(str::to_string))
+        .collect();
+    for trigger in &recording.triggers {
+        if !triggers.contains(trigger) {
+            triggers.push(trigger.clone());
+        }
+    }
+    db.update_audio_file(record_id, &file_path, duration, (!triggers.is_empty()).then(|| triggers.join(", ")).as_deref())
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(denoised) = denoised_file_path.as_deref() {
+        db.save_audio_version(record_id, "denoised", denoised).map_err(|e| format!("Database error: {}", e))?;
+    }
+    recovery::remove_replaced(&stitched.replaced);
+    let dtmf_digits: Vec<DtmfDigit> = recording.dtmf_digits.iter()
+        .map(|digit| DtmfDigit { start_seconds: digit.start_seconds + offset, end_seconds: digit.end_seconds + offset, ..digit.clone() })
+        .collect();
+    if !dtmf_digits.is_empty() {
+        let mut all = db.get_dtmf_digits(record_id).map_err(|e| format!("Database error: {}", e))?;
+        all.extend(dtmf_digits.iter().cloned());
+        db.save_dtmf_digits(record_id, &all).map_err(|e| format!("Database error: {}", e))?;
+    }
+    for track in &recording.tracks {
+        db.save_recording_track(&RecordingTrack { record_id, ..track.clone() }).map_err(|e| format!("Database error: {}", e))?;
+    }
+    // A recording recovered after a crash has none of its own; the resumed
+    // part's is the closest there is
+    if db.get_recording_metadata(record_id).map_err(|e| format!("Database error: {}", e))?.is_none() {
+        let metadata = RecordingMetadata { record_id, devices: recording.devices.clone(), ..recording.metadata.clone() };
+        db.save_recording_metadata(&metadata).map_err(|e| format!("Database error: {}", e))?;
+    }
+    let inaudible_events: Vec<InaudibleEvent> = recording.inaudible.finish(duration - offset).into_iter()
+        .map(|event| InaudibleEvent { start_seconds: event.start_seconds + offset, end_seconds: event.end_seconds + offset, ..event })
+        .collect();
+    if !inaudible_events.is_empty() {
+        let mut all = db.get_inaudible_events(record_id).map_err(|e| format!("Database error: {}", e))?;
+        all.extend(inaudible_events.iter().cloned());
+        db.save_inaudible_events(record_id, &all).map_err(|e| format!("Database error: {}", e))?;
+    }
+    db.save_recording_gap(&RecordingGap { record_id, at_seconds: offset, gap_seconds: resume.gap_seconds })
+        .map_err(|e| format!("Database error: {}", e))?;
+    db.delete_resumable_recording(record_id).map_err(|e| format!("Database error: {}", e))?;
+    let gaps = db.get_recording_gaps(record_id).map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(RecordingSaved {
+        record_id,
+        file_path,
+        duration,
+        // The file starts with the interrupted recording, not pre-roll
+        pre_roll_seconds: 0.0,
+        speech_regions: recording.speech_regions.iter()
+            .map(|region| SpeechRegion { start: region.start + offset, end: region.end + offset })
+            .collect(),
+        triggers: recording.triggers,
+        dtmf_digits,
+        inaudible_events,
+        tracks: recording.tracks.into_iter().map(|track| RecordingTrack { record_id, ..track }).collect(),
+        denoised_file_path,
+        sample_rate: format.sample_rate,
+        channels: format.channels,
+        devices: recording.devices,
+        segments: Vec::new(),
+        gaps,
+    })
+}
+
+fn save_new_recording(
+    app_handle: &tauri::AppHandle,
+    mut recording: Recording,
+    format: StreamFormat,
+    storage: Storage,
+    split: Option<SilenceSplit>,
+) -> Result<RecordingSaved, String> {
+    let (path, duration) = recording.recorder.finish().map_err(|e| format!("Failed to finalize recording: {}", e))?;
+    // Split from the WAV before it is encoded; the whole recording is kept
+    // either way, so a failed split only loses the parts
+    let parts = split.map_or_else(Vec::new, |split| match segmentation::split_recording(&path, split) {
+        Ok(parts) => parts,
+        Err(e) => {
+            eprintln!("Failed to split {} at silences: {}", path.display(), e);
+            Vec::new()
+        }
+    });
+    let file_path = store(path, storage).to_string_lossy().to_string();
+    // A broken denoised copy doesn't cost the original
+    let denoised_file_path = recording.denoised.take().and_then(|mut copy| {
+        let rest = copy.denoiser.flush();
+        match copy.recorder.write(&rest).and_then(|_| copy.recorder.finish()) {
+            Ok((path, _)) => Some(store(path, storage).to_string_lossy().to_string()),
+            Err(e) => {
+                eprintln!("Failed to finalize denoised recording: {}", e);
+                None
+            }
+        }
+    });
+
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record_id = db.save_audio_record(&AudioRecord {
+            id: None,
+            title: recording.title.clone(),
+            file_path: file_path.clone(),
+            transcript: None,
+            duration,
+            created_at: String::new(),
+            triggers: (!recording.triggers.is_empty()).then(|| recording.triggers.join(", ")),
+        })
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(denoised) = denoised_file_path.as_deref() {
+        db.save_audio_version(record_id, "denoised", denoised).map_err(|e| format!("Database error: {}", e))?;
+    }
+    if !recording.dtmf_digits.is_empty() {
+        db.save_dtmf_digits(record_id, &recording.dtmf_digits).map_err(|e| format!("Database error: {}", e))?;
+    }
+    for track in &recording.tracks {
+        db.save_recording_track(&RecordingTrack { record_id, ..track.clone() }).map_err(|e| format!("Database error: {}", e))?;
+    }
+    let metadata = RecordingMetadata { record_id, devices: recording.devices.clone(), ..recording.metadata.clone() };
+    db.save_recording_metadata(&metadata).map_err(|e| format!("Database error: {}", e))?;
+    let inaudible_events = recording.inaudible.finish(duration);
+    if !inaudible_events.is_empty() {
+        db.save_inaudible_events(record_id, &inaudible_events).map_err(|e| format!("Database error: {}", e))?;
+    }
+    let mut segments = Vec::new();
+    for (index, part) in parts.into_iter().enumerate() {
+        let segment_id = db.save_audio_record(&AudioRecord {
+                id: None,
+                title: format!("{} (part {})", recording.title, index + 1),
+                file_path: store(part.path, storage).to_string_lossy().to_string(),
+                transcript: None,
+                duration: part.end_seconds - part.start_seconds,
+                created_at: String::new(),
+                triggers: None,
+            })
+            .map_err(|e| format!("Database error: {}", e))?;
+        let segment = RecordingSegment {
+            record_id: segment_id,
+            parent_id: record_id,
+            segment_index: index as i64,
+            start_seconds: part.start_seconds,
+            end_seconds: part.end_seconds,
+        };
+        db.save_recording_segment(&segment).map_err(|e| format!("Database error: {}", e))?;
+        segments.push(segment);
+    }
+
+    Ok(RecordingSaved {
+        record_id,
+        file_path,
+        duration,
+        pre_roll_seconds: recording.pre_roll_seconds,
+        speech_regions: recording.speech_regions,
+        triggers: recording.triggers,
+        dtmf_digits: recording.dtmf_digits,
+        inaudible_events,
+        tracks: recording.tracks.into_iter().map(|track| RecordingTrack { record_id, ..track }).collect(),
+        denoised_file_path,
+        sample_rate: format.sample_rate,
+        channels: format.channels,
+        devices: recording.devices,
+        segments,
+        gaps: Vec::new(),
+    })
+}
+
+// A spotter for the active speech triggers, if keyword spotting is on
+fn start_spotter(app_handle: &tauri::AppHandle, settings: &RwLock<CaptureSettings>, format: StreamFormat) -> Option<KeywordSpotter> {
+    let settings = settings.read().ok().filter(|settings| settings.keyword_spotting)?.clone();
+    let phrases: Vec<String> = match Database::new(app_handle).and_then(|db| db.get_active_triggers()) {
+        Ok(triggers) => triggers.into_iter()
+            .filter(|trigger| trigger.trigger_type == "speech")
+            .map(|trigger| trigger.trigger_value)
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to load speech triggers: {}", e);
+            return None;
+        }
+    };
+    if phrases.is_empty() {
+        return None;
+    }
+
+    let model_path = settings.keyword_model_path.unwrap_or_default();
+    match KeywordSpotter::new(&model_path, &phrases, format, settings.keyword_min_confidence) {
+        Ok(spotter) => Some(spotter),
+        Err(e) => {
+            eprintln!("Keyword spotting unavailable: {}", e);
+            None
+        }
+    }
+}
+
+// A classifier for live sound events, if they are on, and the active sound
+// triggers to match what it hears against
+fn start_classifier(app_handle: &tauri::AppHandle, settings: &RwLock<CaptureSettings>, format: StreamFormat) -> (Option<SoundEventClassifier>, Vec<String>) {
+    let Some(settings) = settings.read().ok().filter(|settings| settings.sound_events).map(|settings| settings.clone()) else {
+        return (None, Vec::new());
+    };
+    let triggers = match Database::new(app_handle).and_then(|db| db.get_active_triggers()) {
+        Ok(triggers) => triggers.into_iter()
+            .filter(|trigger| trigger.trigger_type == "sound")
+            .map(|trigger| trigger.trigger_value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to load sound triggers: {}", e);
+            Vec::new()
+        }
+    };
+
+    let classifier = SoundEventClassifier::new(
+        &settings.sound_event_model_path.unwrap_or_default(),
+        &settings.sound_event_labels_path.unwrap_or_default(),
+        settings.sound_event_sample_rate,
+        format,
+        settings.sound_event_min_confidence,
+    );
+    match classifier {
+        Ok(classifier) => (Some(classifier), triggers),
+        Err(e) => {
+            eprintln!("Sound event classification unavailable: {}", e);
+            (None, Vec::new())
+        }
+    }
+}
+
+// A worker transcribing live capture, if live transcription is on
+fn start_live_transcriber(app_handle: &tauri::AppHandle, settings: &RwLock<CaptureSettings>, format: StreamFormat) -> Option<LiveTranscriber> {
+    let settings = settings.read().ok().filter(|settings| settings.live_transcription)?.clone();
+    match LiveTranscriber::new(app_handle, format, settings.live_transcription_model, settings.live_transcription_language) {
+        Ok(transcriber) => Some(transcriber),
+        Err(e) => {
+            eprintln!("Live transcription unavailable: {}", e);
+            None
+        }
+    }
+}
+
+// Opens the device and hands it to a new capture thread
+async fn spawn_capture_thread(
+    app_handle: &tauri::AppHandle,
+    device_id: Option<&str>,
+    settings: Arc<RwLock<CaptureSettings>>,
+) -> Result<CaptureSession, String> {
+    let (sender, receiver) = mpsc::channel();
+    let status = Arc::new(Mutex::new(CaptureStatus::default()));
+    let (ready, started) = oneshot::channel();
+    let app_handle = app_handle.clone();
+    let device_id = device_id.map(str::to_string);
+    let thread_sender = sender.clone();
+    let thread_status = status.clone();
+
+    std::thread::Builder::new()
+        .name("audio-capture".to_string())
+        .spawn(move || {
+            let opened = find_source(device_id.as_deref()).and_then(|source| {
+                let (channels, policy, system_audio) = settings.read()
+                    .map(|settings| (
+                        settings.channels,
+                        source.id().ok().and_then(|id| settings.channel_policy(&id)),
+                        settings.system_audio_source.clone().map(|source| (source, settings.source_mix, settings.system_audio_balance)),
+                    ))
+                    .unwrap_or_default();
+                // The policy's channels are captured in place of the channels setting
+                let mut format = match policy.as_ref() {
+                    Some(policy) => StreamFormat { channels: policy.output_channels(), ..source.format(policy.required_channels())? },
+                    None => source.format(channels)?,
+                };
+                if let Some((_, mix, _)) = system_audio.as_ref() {
+                    format.channels = if *mix == SourceMix::Tracks { 2 } else { 1 };
+                }
+                let vad = settings.read().ok()
+                    .filter(|settings| settings.vad_enabled)
+                    .map(|settings| VoiceActivityDetector::new(format, settings.vad_aggressiveness, settings.vad_hangover_ms));
+                let spotter = start_spotter(&app_handle, &settings, format);
+                let (classifier, sound_triggers) = start_classifier(&app_handle, &settings, format);
+                let live_transcriber = start_live_transcriber(&app_handle, &settings, format);
+                let dtmf = settings.read().is_ok_and(|settings| settings.dtmf_detection).then(|| DtmfDetector::new(format));
+                let inaudible = settings.read().ok()
+                    .filter(|settings| settings.inaudible_detection)
+                    .map(|settings| InaudibleDetector::new(format, settings.inaudible_settings()));
+                let loudness = settings.read().is_ok_and(|settings| settings.live_loudness).then(|| LoudnessMeter::new(format));
+                let filters = settings.read().ok()
+                    .filter(|settings| settings.filter_live && !settings.filters.is_empty())
+                    .map(|settings| FilterChain::new(&settings.filters, format));
+                let mut thread = CaptureThread {
+                    app_handle,
+                    sender: thread_sender,
+                    status: thread_status,
+                    settings,
+                    format,
+                    active: None,
+                    generation: 0,
+                    monitoring: false,
+                    pre_roll: PreRollBuffer::new(),
+                    buffer_memory_ceiling: buffer_memory_ceiling(),
+                    recording: None,
+                    vad,
+                    spotter,
+                    classifier,
+                    live_transcriber,
+                    sound_events: EventTracker::default(),
+                    sound_triggers,
+                    dtmf,
+                    inaudible,
+                    meter: LevelMeter::new(format),
+                    filters,
+                    agc: AutomaticGainControl::new(format),
+                    loudness,
+                    loudness_due: LIVE_LOUDNESS_SECONDS,
+                    gate: PreRollBuffer::new(),
+                    captured_frames: 0,
+                    capturing_system_audio: device_id.as_deref().is_some_and(is_system_audio),
+                    system_audio: None,
+                    retried_at: Instant::now(),
+                };
+                if let Some((source, mix, balance)) = system_audio {
+                    thread.open_system_audio(&source, mix, balance)
+                        .map_err(|e| anyhow::anyhow!("System audio source '{}': {}", source, e))?;
+                }
+                thread.active = Some(thread.open_device(&source)?);
+                Ok(thread)
+            });
+
+            match opened {
+                Ok(thread) => {
+                    let (format, device) = (thread.format, thread.active.as_ref().map(|stream| stream.device.clone()));
+                    thread.update_status(|status| {
+                        status.device = device;
+                        status.sample_rate = format.sample_rate;
+                        status.channels = format.channels;
+                    });
+                    let _ = ready.send(Ok(()));
+                    thread.run(receiver);
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(format!("Audio capture error: {}", e)));
+                }
+            }
+        })
+        .map_err(|e| format!("Audio capture error: {}", e))?;
+
+    started.await.unwrap_or_else(|_| Err("Audio capture thread stopped unexpectedly".to_string()))?;
+    Ok(CaptureSession { sender, status })
+}
+
+struct CaptureSession {
+    sender: mpsc::Sender<CaptureMessage>,
+    status: Arc<Mutex<CaptureStatus>>,
+}
+
+impl CaptureSession {
+    fn status(&self) -> CaptureStatus {
+        self.status.lock().map(|status| status.clone()).unwrap_or_default()
+    }
+
+    async fn request<T>(&self, message: impl FnOnce(oneshot::Sender<T>) -> CaptureMessage) -> Result<T, String> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(message(reply)).map_err(|_| "Audio capture thread stopped unexpectedly".to_string())?;
+        response.await.map_err(|_| "Audio capture thread stopped unexpectedly".to_string())
+    }
+}
+
+// Backend capture from one input device at a time. Monitoring keeps the
+// device open and buffers pre-roll; recordings can start with or without it.
+pub struct AudioCapture {
+    // Held across awaits so control commands run one at a time
+    session: tokio::sync::Mutex<Option<CaptureSession>>,
+    settings: Arc<RwLock<CaptureSettings>>,
+}
+
+impl AudioCapture {
+    pub fn new() -> Self {
+        AudioCapture {
+            session: tokio::sync::Mutex::new(None),
+            settings: Arc::new(RwLock::new(CaptureSettings::default())),
+        }
+    }
+
+    // Restore persisted settings; called once from setup after the database is ready
+    pub fn load_settings(&self, db: &Database) {
+        match db.get_setting(CAPTURE_SETTINGS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<CaptureSettings>(&json) {
+                Ok(settings) => {
+                    if let Ok(mut current) = self.settings.write() {
+                        *current = settings;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid capture settings: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load capture settings: {}", e),
+        }
+    }
+
+    pub fn get_settings(&self) -> CaptureSettings {
+        self.settings.read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_settings(&self, settings: CaptureSettings, db: &Database) -> Result<()> {
+        db.set_setting(CAPTURE_SETTINGS_KEY, &serde_json::to_string(&settings)?)?;
+
+        if let Ok(mut current) = self.settings.write() {
+            *current = settings;
+        }
+        Ok(())
+    }
+
+    // Reuses the running capture, which must then be on the requested device
+    fn check_device(session: &CaptureSession, device_id: Option<&str>) -> Result<(), String> {
+        let current = session.status().device;
+        match device_id {
+            Some(id) if current.as_deref() != Some(id) => Err(format!(
+                "Capture is already running on '{}'; stop it before switching devices",
+                current.unwrap_or_default()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn start_recording(
+        &self,
+        app_handle: &tauri::AppHandle,
+        device_id: Option<&str>,
+        title: Option<String>,
+        denoise: Option<bool>,
+        storage_profile: Option<StorageProfile>,
+        notes: RecordingNotes,
+    ) -> Result<CaptureStatus, String> {
+        let title = title.unwrap_or_else(|| format!("Recording {}", chrono::Local::now().format("%Y-%m-%d %H:%M")));
+        let settings = self.get_settings();
+        let denoise = denoise.unwrap_or(settings.denoise);
+        let storage_profile = storage_profile.unwrap_or(settings.storage_profile);
+        self.begin_recording(app_handle, device_id, title, denoise, storage_profile, None, notes).await
+    }
+
+    // Carries on a recording that was cut off, under its title and with a
+    // denoised copy if it had one. Once stopped, the new audio is appended
+    // to it.
+    pub async fn resume_recording(
+        &self,
+        app_handle: &tauri::AppHandle,
+        record_id: i64,
+        device_id: Option<&str>,
+        storage_profile: Option<StorageProfile>,
+    ) -> Result<CaptureStatus, String> {
+        let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+        let resumable = db.get_resumable_recordings()
+            .map_err(|e| format!("Database error: {}", e))?
+            .into_iter()
+            .find(|resumable| resumable.record_id == record_id)
+            .ok_or_else(|| format!("Recording {} can't be resumed", record_id))?;
+        let denoise = db.get_audio_versions(record_id)
+            .map_err(|e| format!("Database error: {}", e))?
+            .iter()
+            .any(|version| version.kind == "denoised");
+        let gap_seconds = chrono::DateTime::parse_from_rfc3339(&resumable.interrupted_at)
+            .map(|interrupted| (chrono::Utc::now() - interrupted.with_timezone(&chrono::Utc)).num_milliseconds().max(0) as f64 / 1000.0)
+            .unwrap_or(0.0);
+        let storage_profile = storage_profile.unwrap_or(self.get_settings().storage_profile);
+        let resume = Resume { record_id, gap_seconds };
+        self.begin_recording(app_handle, device_id, resumable.title, denoise, storage_profile, Some(resume), RecordingNotes::default()).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn begin_recording(
+        &self,
+        app_handle: &tauri::AppHandle,
+        device_id: Option<&str>,
+        title: String,
+        denoise: bool,
+        storage_profile: StorageProfile,
+        resumes: Option<Resume>,
+        notes: RecordingNotes,
+    ) -> Result<CaptureStatus, String> {
+        let file_path = new_recording_path(app_handle).map_err(|e| format!("Audio capture error: {}", e))?;
+        let mut session = self.session.lock().await;
+        match session.as_ref() {
+            Some(running) => Self::check_device(running, device_id)?,
+            None => *session = Some(spawn_capture_thread(app_handle, device_id, self.settings.clone()).await?),
+        }
+        let running = session.as_ref().expect("capture session was just checked");
+        let started = running.request(|reply| CaptureMessage::StartRecording { title, file_path, denoise, storage_profile, resumes, notes, reply }).await;
+        // A recording that fails to start leaves nothing for a thread that wasn't monitoring
+        if !matches!(started, Ok(Ok(_))) && !running.status().monitoring {
+            *session = None;
+        }
+        started?
+    }
+
+    pub async fn stop_recording(&self) -> Result<RecordingSaved, String> {
+        let mut session = self.session.lock().await;
+        let running = session.as_ref().ok_or_else(|| "No recording in progress".to_string())?;
+        let saved = running.request(CaptureMessage::StopRecording).await;
+        if saved.is_err() || !running.status().monitoring {
+            *session = None;
+        }
+        saved?
+    }
+
+    pub async fn start_monitoring(&self, app_handle: &tauri::AppHandle, device_id: Option<&str>) -> Result<CaptureStatus, String> {
+        let mut session = self.session.lock().await;
+        match session.as_ref() {
+            Some(running) => Self::check_device(running, device_id)?,
+            None => *session = Some(spawn_capture_thread(app_handle, device_id, self.settings.clone()).await?),
+        }
+        let running = session.as_ref().expect("capture session was just checked");
+        let status = running.request(|reply| CaptureMessage::SetMonitoring(true, reply)).await;
+        if status.is_err() {
+            *session = None;
+        }
+        status
+    }
+
+    pub async fn stop_monitoring(&self) -> Result<CaptureStatus, String> {
+        let mut session = self.session.lock().await;
+        let Some(running) = session.as_ref() else {
+            return Ok(CaptureStatus::default());
+        };
+        let status = running.request(|reply| CaptureMessage::SetMonitoring(false, reply)).await;
+        if status.as_ref().map_or(true, |status| !status.recording) {
+            *session = None;
+        }
+        status
+    }
+
+    pub async fn status(&self) -> CaptureStatus {
+        self.session.lock().await
+            .as_ref()
+            .map(CaptureSession::status)
+            .unwrap_or_default()
+    }
+}
+
+// `location` and `notes` are kept in the recording's metadata
+#[allow(clippy::too_many_arguments)]
+#[command]
+pub async fn start_recording(
+    device_id: Option<String>,
+    title: Option<String>,
+    denoise: Option<bool>,
+    storage_profile: Option<StorageProfile>,
+    location: Option<String>,
+    notes: Option<String>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<CaptureStatus, String> {
+    let notes = RecordingNotes { location, notes };
+    capture.start_recording(&app_handle, device_id.as_deref(), title, denoise, storage_profile, notes).await
+}
+
+// Carries on a recording from get_resumable_recordings as the same
+// recording, with a gap marker where it was cut off
+#[command]
+pub async fn resume_recording(
+    record_id: i64,
+    device_id: Option<String>,
+    storage_profile: Option<StorageProfile>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<CaptureStatus, String> {
+    capture.resume_recording(&app_handle, record_id, device_id.as_deref(), storage_profile).await
+}
+
+#[command]
+pub async fn stop_recording(capture: State<'_, AudioCapture>) -> Result<RecordingSaved, String> {
+    capture.stop_recording().await
+}
+
+// Keeps the device open and the last `pre_roll_seconds` of audio buffered,
+// so recordings started while monitoring include what came just before
+#[command]
+pub async fn start_monitoring(
+    device_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<CaptureStatus, String> {
+    capture.start_monitoring(&app_handle, device_id.as_deref()).await
+}
+
+#[command]
+pub async fn stop_monitoring(capture: State<'_, AudioCapture>) -> Result<CaptureStatus, String> {
+    capture.stop_monitoring().await
+}
+
+#[command]
+pub async fn get_capture_status(capture: State<'_, AudioCapture>) -> Result<CaptureStatus, String> {
+    Ok(capture.status().await)
+}
+
+#[command]
+pub async fn get_capture_settings(capture: State<'_, AudioCapture>) -> Result<CaptureSettings, String> {
+    Ok(capture.get_settings())
+}
+
+#[command]
+pub async fn set_capture_settings(
+    settings: CaptureSettings,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<(), String> {
+    settings.validate()?;
+    settings.check_memory()?;
+    app_handle.state::<transcription::Transcriber>().acceleration.resolve(settings.transcription_acceleration)?;
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    capture.set_settings(settings, &db)
+        .map_err(|e| format!("Failed to save capture settings: {}", e))
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::{Deserialize, Serialize};
+use tauri::command;
+
+// Prefixes the id of a loopback source that captures an output device, so
+// it can't be mistaken for an input of the same name
+pub const LOOPBACK_PREFIX: &str = "loopback:";
+
+// What the machine plays is captured through WASAPI loopback on Windows and
+// from PulseAudio or PipeWire monitor sources on Linux, which ALSA doesn't
+// list. macOS has no loopback without a separate driver.
+pub const SYSTEM_AUDIO_SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "linux"));
+pub const SYSTEM_AUDIO_UNSUPPORTED: &str =
+    "Capturing system audio is only supported on Windows, and on Linux with PulseAudio or PipeWire";
+
+// Rates reported when a device supports a continuous range
+const COMMON_SAMPLE_RATES: [u32; 11] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Microphone,
+    // What the machine is playing: a WASAPI output device on Windows, a
+    // monitor source on Linux
+    Loopback,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioDeviceInfo {
+    // cpal has no stable device ids, so the name doubles as the id
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub kind: DeviceKind,
+    pub is_default: bool,
+    pub default_sample_rate: Option<u32>,
+    pub default_channels: Option<u16>,
+    // Ascending, deduplicated
+    pub sample_rates: Vec<u32>,
+    pub channel_counts: Vec<u16>,
+}
+
+// Whether an id names a loopback source
+pub fn is_system_audio(device_id: &str) -> bool {
+    device_id.starts_with(LOOPBACK_PREFIX)
+}
+
+// Formats the device captures in; output devices captured as loopback use
+// their output formats
+fn describe(device: &cpal::Device, host: &cpal::Host, default_name: Option<&str>, output: bool) -> Result<AudioDeviceInfo> {
+    let name = device.name()?;
+    let mut sample_rates = Vec::new();
+    let mut channel_counts = Vec::new();
+
+    let ranges: Vec<cpal::SupportedStreamConfigRange> = if output {
+        device.supported_output_configs()?.collect()
+    } else {
+        device.supported_input_configs()?.collect()
+    };
+    for range in ranges {
+        let (min, max) = (range.min_sample_rate().0, range.max_sample_rate().0);
+        sample_rates.push(min);
+        sample_rates.push(max);
+        sample_rates.extend(COMMON_SAMPLE_RATES.iter().copied().filter(|rate| (min..=max).contains(rate)));
+        channel_counts.push(range.channels());
+    }
+    sample_rates.sort_unstable();
+    sample_rates.dedup();
+    channel_counts.sort_unstable();
+    channel_counts.dedup();
+
+    let default_config = if output { device.default_output_config().ok() } else { device.default_input_config().ok() };
+    let kind = if output { DeviceKind::Loopback } else { DeviceKind::Microphone };
+    Ok(AudioDeviceInfo {
+        id: if output { format!("{}{}", LOOPBACK_PREFIX, name) } else { name.clone() },
+        is_default: default_name == Some(name.as_str()),
+        name,
+        host: host.id().name().to_string(),
+        kind,
+        default_sample_rate: default_config.as_ref().map(|config| config.sample_rate().0),
+        default_channels: default_config.as_ref().map(|config| config.channels()),
+        sample_rates,
+        channel_counts,
+    })
+}
+
+pub fn input_devices() -> Result<Vec<AudioDeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+
+    let mut devices = Vec::new();
+    for device in host.input_devices()? {
+        // A device that vanishes or can't be queried mid-listing is left out
+        match describe(&device, &host, default_name.as_deref(), false) {
+            Ok(info) => devices.push(info),
+            Err(e) => eprintln!("Skipping audio input device: {}", e),
+        }
+    }
+    Ok(devices)
+}
+
+// The sound server's monitor sources, with the source's name in the id
+#[cfg(target_os = "linux")]
+pub fn loopback_devices() -> Result<Vec<AudioDeviceInfo>> {
+    Ok(crate::pulse_monitor::monitor_sources()?
+        .into_iter()
+        .map(|source| AudioDeviceInfo {
+            id: format!("{}{}", LOOPBACK_PREFIX, source.name),
+            name: source.description,
+            host: "PulseAudio".to_string(),
+            kind: DeviceKind::Loopback,
+            is_default: source.is_default,
+            default_sample_rate: Some(source.sample_rate),
+            default_channels: Some(source.channels),
+            sample_rates: vec![source.sample_rate],
+            channel_counts: vec![source.channels],
+        })
+        .collect())
+}
+
+// Output devices that can be captured as loopback. Only WASAPI can do that,
+// so there are none on macOS.
+#[cfg(not(target_os = "linux"))]
+pub fn loopback_devices() -> Result<Vec<AudioDeviceInfo>> {
+    if !cfg!(target_os = "windows") {
+        return Ok(Vec::new());
+    }
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|device| device.name().ok());
+
+    let mut devices = Vec::new();
+    for device in host.output_devices()? {
+        match describe(&device, &host, default_name.as_deref(), true) {
+            Ok(info) => devices.push(info),
+            Err(e) => eprintln!("Skipping audio output device: {}", e),
+        }
+    }
+    Ok(devices)
+}
+
+// Inputs, then the sources of system audio
+#[command]
+pub async fn list_audio_devices() -> Result<Vec<AudioDeviceInfo>, String> {
+    // Enumerating can block for a while on some hosts (ALSA probes each card)
+    tokio::task::spawn_blocking(|| -> Result<Vec<AudioDeviceInfo>> {
+        let mut devices = input_devices()?;
+        devices.extend(loopback_devices()?);
+        Ok(devices)
+    })
+        .await
+        .map_err(|e| format!("Audio device error: {}", e))?
+        .map_err(|e| format!("Audio device error: {}", e))
+}
@@ -0,0 +1,636 @@
+use anyhow::Result;
+use flacenc::component::{BitRepr, StreamInfo};
+use flacenc::error::Verify;
+use flacenc::source::{Fill, FrameBuf};
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::audio_capture::StreamFormat;
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+// Samples read from a file at a time, so hours of audio never sit in memory
+const CHUNK_SAMPLES: usize = 65536;
+
+// Opus always decodes at 48 kHz; packets are 20 ms, the size it is tuned for
+const OPUS_SAMPLE_RATE: u32 = 48000;
+const OPUS_FRAME_SAMPLES: usize = 960;
+// 120 ms, the longest packet Opus allows
+const OPUS_MAX_FRAME_SAMPLES: usize = 5760;
+const OPUS_MAX_PACKET_BYTES: usize = 4000;
+
+fn has_extension(path: &Path, wanted: &str) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(wanted))
+}
+
+pub fn is_flac(path: &Path) -> bool {
+    has_extension(path, "flac")
+}
+
+pub fn is_opus(path: &Path) -> bool {
+    has_extension(path, "opus")
+}
+
+// What decoding an Ogg Opus file needs from its identification header
+struct OpusHead {
+    channels: u16,
+    // Samples of encoder delay at the start of the stream to throw away
+    pre_skip: u64,
+}
+
+fn open_opus(path: &Path) -> Result<(PacketReader<BufReader<File>>, OpusHead)> {
+    let mut reader = PacketReader::new(BufReader::new(File::open(path)?));
+    let head = reader.read_packet_expected()?.data;
+    if head.len() < 19 || &head[..8] != b"OpusHead" {
+        return Err(anyhow::anyhow!("{} is not an Ogg Opus file", path.display()));
+    }
+    if head[18] != 0 {
+        return Err(anyhow::anyhow!("Only mono and stereo Opus files are supported"));
+    }
+    // The comment header comes next and holds nothing needed here
+    reader.read_packet_expected()?;
+    Ok((reader, OpusHead { channels: head[9] as u16, pre_skip: u16::from_le_bytes([head[10], head[11]]) as u64 }))
+}
+
+// The file's format as a WAV spec; FLAC is always integer samples
+pub fn audio_spec(path: &Path) -> Result<hound::WavSpec> {
+    if is_flac(path) {
+        let info = claxon::FlacReader::open(path)?.streaminfo();
+        return Ok(hound::WavSpec {
+            channels: info.channels as u16,
+            sample_rate: info.sample_rate,
+            bits_per_sample: info.bits_per_sample as u16,
+            sample_format: hound::SampleFormat::Int,
+        });
+    }
+    if is_opus(path) {
+        return opus_spec(path);
+    }
+    Ok(hound::WavReader::open(path)?.spec())
+}
+
+// Opus has no bit depth of its own; it decodes to 16-bit
+pub fn opus_spec(path: &Path) -> Result<hound::WavSpec> {
+    let (_, head) = open_opus(path)?;
+    Ok(hound::WavSpec {
+        channels: head.channels,
+        sample_rate: OPUS_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    })
+}
+
+pub fn spec_format(spec: &hound::WavSpec) -> StreamFormat {
+    StreamFormat { sample_rate: spec.sample_rate, channels: spec.channels }
+}
+
+// Format and length in seconds of a WAV, FLAC or Opus file. The first two
+// come from the header; Opus keeps its length in the last page, so that file
+// is scanned without decoding it.
+pub fn audio_info(path: &Path) -> Result<(StreamFormat, f64)> {
+    if is_flac(path) {
+        let info = claxon::FlacReader::open(path)?.streaminfo();
+        let format = StreamFormat { sample_rate: info.sample_rate, channels: info.channels as u16 };
+        return Ok((format, info.samples.unwrap_or(0) as f64 / info.sample_rate as f64));
+    }
+    if is_opus(path) {
+        let (mut reader, head) = open_opus(path)?;
+        let mut end = 0;
+        while let Some(packet) = reader.read_packet()? {
+            end = packet.absgp_page();
+        }
+        let format = StreamFormat { sample_rate: OPUS_SAMPLE_RATE, channels: head.channels };
+        return Ok((format, end.saturating_sub(head.pre_skip) as f64 / OPUS_SAMPLE_RATE as f64));
+    }
+    let reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    Ok((spec_format(&spec), reader.duration() as f64 / spec.sample_rate as f64))
+}
+
+// Where a WAV file's frames are: the byte its samples start at and the bytes
+// each frame takes. None for FLAC and Opus, whose frames aren't at fixed
+// offsets.
+pub fn wav_layout(path: &Path) -> Result<Option<(u64, u64)>> {
+    if is_flac(path) || is_opus(path) {
+        return Ok(None);
+    }
+    let reader = hound::WavReader::new(BufReader::new(File::open(path)?))?;
+    let spec = reader.spec();
+    let frame_bytes = spec.channels as u64 * spec.bits_per_sample.div_ceil(8) as u64;
+    // The header has been read, so the reader is at the first sample
+    let data_offset = reader.into_inner().stream_position()?;
+    Ok(Some((data_offset, frame_bytes)))
+}
+
+fn chunked<E>(
+    samples: impl Iterator<Item = std::result::Result<f32, E>>,
+    channels: u16,
+    on_chunk: &mut impl FnMut(&[f32]) -> Result<()>,
+) -> Result<()>
+where
+    anyhow::Error: From<E>,
+{
+    let chunk_len = CHUNK_SAMPLES - CHUNK_SAMPLES % channels.max(1) as usize;
+    let mut chunk = Vec::with_capacity(chunk_len);
+    for sample in samples {
+        chunk.push(sample?);
+        if chunk.len() == chunk_len {
+            on_chunk(&chunk)?;
+            chunk.clear();
+        }
+    }
+    if !chunk.is_empty() {
+        on_chunk(&chunk)?;
+    }
+    Ok(())
+}
+
+// Like `for_each_chunk`, for the frames from `start_seconds` up to
+// `end_seconds` only
+pub fn for_each_chunk_between(
+    path: &Path,
+    start_seconds: f64,
+    end_seconds: f64,
+    mut on_chunk: impl FnMut(&[f32]) -> Result<()>,
+) -> Result<StreamFormat> {
+    let spec = audio_spec(path)?;
+    let format = spec_format(&spec);
+    let channels = format.channels.max(1) as usize;
+    let start = (start_seconds * format.sample_rate as f64).round() as u64;
+    let end = (end_seconds * format.sample_rate as f64).round() as u64;
+    if !is_flac(path) && !is_opus(path) {
+        // A WAV can seek straight to the first frame rather than read up to it
+        let mut reader = hound::WavReader::open(path)?;
+        let frames = reader.duration() as u64;
+        reader.seek(start.min(frames) as u32)?;
+        let count = end.min(frames).saturating_sub(start) as usize * channels;
+        let scale = (1i64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+        match spec.sample_format {
+            hound::SampleFormat::Float => chunked(reader.samples::<f32>().take(count), spec.channels, &mut on_chunk)?,
+            hound::SampleFormat::Int => chunked(
+                reader.samples::<i32>().take(count).map(|sample| sample.map(|sample| sample as f32 / scale)),
+                spec.channels,
+                &mut on_chunk,
+            )?,
+        }
+        return Ok(format);
+    }
+
+    let mut position = 0;
+    for_each_chunk(path, |chunk| {
+        let frames = (chunk.len() / channels) as u64;
+        let from = start.clamp(position, position + frames);
+        let to = end.clamp(position, position + frames);
+        if to > from {
+            on_chunk(&chunk[(from - position) as usize * channels..(to - position) as usize * channels])?;
+        }
+        position += frames;
+        Ok(())
+    })
+}
+
+// Hands over each decoded packet, less the encoder delay at the start and
+// the padding the last page's granule position says to drop at the end
+fn decode_opus(path: &Path, on_chunk: &mut impl FnMut(&[f32]) -> Result<()>) -> Result<()> {
+    let (mut reader, head) = open_opus(path)?;
+    let channels = head.channels as usize;
+    let mut decoder = opus::Decoder::new(OPUS_SAMPLE_RATE, if channels == 1 { opus::Channels::Mono } else { opus::Channels::Stereo })?;
+    let mut decoded = vec![0.0; OPUS_MAX_FRAME_SAMPLES * channels];
+    // Frames decoded so far, counting the pre-skip
+    let mut position = 0;
+    while let Some(packet) = reader.read_packet()? {
+        let frames = decoder.decode_float(&packet.data, &mut decoded, false)? as u64;
+        let mut end = position + frames;
+        if packet.last_in_stream() {
+            end = end.min(packet.absgp_page());
+        }
+        let start = position.max(head.pre_skip);
+        if end > start {
+            on_chunk(&decoded[(start - position) as usize * channels..(end - position) as usize * channels])?;
+        }
+        position += frames;
+    }
+    Ok(())
+}
+
+// Like `for_each_chunk` for an Ogg Opus file, whatever it is called
+pub fn for_each_opus_chunk(path: &Path, mut on_chunk: impl FnMut(&[f32]) -> Result<()>) -> Result<()> {
+    decode_opus(path, &mut on_chunk)
+}
+
+// Reads a WAV, FLAC or Opus file as interleaved f32 in [-1, 1], whatever
+// its sample format, handing it over in whole-frame chunks
+pub fn for_each_chunk(path: &Path, mut on_chunk: impl FnMut(&[f32]) -> Result<()>) -> Result<StreamFormat> {
+    let spec = audio_spec(path)?;
+    if is_opus(path) {
+        decode_opus(path, &mut on_chunk)?;
+        return Ok(spec_format(&spec));
+    }
+    let scale = (1i64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+    if is_flac(path) {
+        let mut reader = claxon::FlacReader::open(path)?;
+        chunked(reader.samples().map(|sample| sample.map(|sample| sample as f32 / scale)), spec.channels, &mut on_chunk)?;
+        return Ok(spec_format(&spec));
+    }
+
+    let mut reader = hound::WavReader::open(path)?;
+    match spec.sample_format {
+        hound::SampleFormat::Float => chunked(reader.samples::<f32>(), spec.channels, &mut on_chunk)?,
+        hound::SampleFormat::Int => chunked(
+            reader.samples::<i32>().map(|sample| sample.map(|sample| sample as f32 / scale)),
+            spec.channels,
+            &mut on_chunk,
+        )?,
+    }
+    Ok(spec_format(&spec))
+}
+
+// Encodes an integer WAV file losslessly to FLAC next to it, returning the
+// new path. The WAV is left for the caller to remove. Each frame goes to the
+// file as soon as it is encoded; the stream info, whose frame sizes and
+// length are only known at the end, is written over its placeholder last.
+// Frames are built one block at a time so the last one holds only what is
+// left, rather than the zero padding the library's own fixed-size loop would add.
+pub fn encode_flac(wav_path: &Path) -> Result<PathBuf> {
+    let reader = hound::WavReader::open(wav_path)?;
+    if reader.spec().sample_format != hound::SampleFormat::Int {
+        return Err(anyhow::anyhow!("FLAC only stores integer samples"));
+    }
+    let flac_path = wav_path.with_extension("flac");
+    let written = write_flac(reader, &flac_path);
+    if written.is_err() {
+        let _ = std::fs::remove_file(&flac_path);
+    }
+    written.map(|_| flac_path)
+}
+
+fn write_flac(mut reader: hound::WavReader<BufReader<File>>, flac_path: &Path) -> Result<()> {
+    let spec = reader.spec();
+    let flac_error = |e: &dyn std::fmt::Debug| anyhow::anyhow!("FLAC encoding failed: {:?}", e);
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| flac_error(&e))?;
+    let channels = spec.channels as usize;
+    let mut info = StreamInfo::new(spec.sample_rate as usize, channels, spec.bits_per_sample as usize)
+        .map_err(|e| flac_error(&e))?;
+
+    let mut output = BufWriter::new(File::create(flac_path)?);
+    // The marker, then the stream info as the last and only metadata block
+    output.write_all(b"fLaC")?;
+    output.write_all(&[0x80, 0, 0, (info.count_bits() / 8) as u8])?;
+    let info_offset = 8;
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    info.write(&mut sink).map_err(|e| flac_error(&e))?;
+    output.write_all(sink.as_slice())?;
+
+    let mut samples = reader.samples::<i32>();
+    let mut block = Vec::with_capacity(config.block_size * channels);
+    let mut frame_number = 0;
+    loop {
+        block.clear();
+        for sample in samples.by_ref().take(config.block_size * channels) {
+            block.push(sample?);
+        }
+        let frames = block.len() / channels;
+        if frames == 0 {
+            break;
+        }
+        let mut framebuf = FrameBuf::with_size(channels, frames).map_err(|e| flac_error(&e))?;
+        framebuf.fill_interleaved(&block[..frames * channels]).map_err(|e| flac_error(&e))?;
+        let frame = flacenc::encode_fixed_size_frame(&config, &framebuf, frame_number, &info)
+            .map_err(|e| flac_error(&e))?;
+        info.update_frame_info(&frame);
+        sink.clear();
+        frame.write(&mut sink).map_err(|e| flac_error(&e))?;
+        output.write_all(sink.as_slice())?;
+        frame_number += 1;
+    }
+
+    let mut file = output.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(info_offset))?;
+    sink.clear();
+    info.write(&mut sink).map_err(|e| flac_error(&e))?;
+    file.write_all(sink.as_slice())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn opus_head(channels: u16, pre_skip: u16, input_sample_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels as u8);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_sample_rate.to_le_bytes());
+    // No output gain, and channel mapping family 0 for mono or stereo
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+fn opus_tags() -> Vec<u8> {
+    let vendor = concat!("dwight ", env!("CARGO_PKG_VERSION"));
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+// Encodes interleaved f32 at 48 kHz into the packets of an Ogg Opus stream
+struct OpusWriter {
+    encoder: opus::Encoder,
+    writer: PacketWriter<'static, BufWriter<File>>,
+    serial: u32,
+    channels: usize,
+    pre_skip: u64,
+    // Audio waiting for a full 20 ms frame
+    pending: Vec<f32>,
+    // The newest packet is held back, so the last one can close the stream
+    held: Option<Vec<u8>>,
+    // Frames in the packets encoded so far, and frames of audio given
+    encoded_frames: u64,
+    input_frames: u64,
+}
+
+impl OpusWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.pending.extend_from_slice(samples);
+        self.input_frames += (samples.len() / self.channels) as u64;
+        let frame_len = OPUS_FRAME_SAMPLES * self.channels;
+        while self.pending.len() >= frame_len {
+            let frame: Vec<f32> = self.pending.drain(..frame_len).collect();
+            self.encode(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, frame: &[f32]) -> Result<()> {
+        let packet = self.encoder.encode_vec_float(frame, OPUS_MAX_PACKET_BYTES)?;
+        if let Some(previous) = self.held.replace(packet) {
+            self.writer.write_packet(previous, self.serial, PacketWriteEndInfo::NormalPacket, self.encoded_frames)?;
+        }
+        self.encoded_frames += OPUS_FRAME_SAMPLES as u64;
+        Ok(())
+    }
+
+    // Encodes silence until the encoder delay is flushed out too, and marks
+    // where the real audio ends so decoders drop the padding
+    fn finish(mut self) -> Result<()> {
+        let end = self.pre_skip + self.input_frames;
+        while self.encoded_frames < end {
+            let mut frame = std::mem::take(&mut self.pending);
+            frame.resize(OPUS_FRAME_SAMPLES * self.channels, 0.0);
+            self.encode(&frame)?;
+        }
+        if let Some(last) = self.held.take() {
+            self.writer.write_packet(last, self.serial, PacketWriteEndInfo::EndStream, end)?;
+        }
+        self.writer.into_inner().flush()?;
+        Ok(())
+    }
+}
+
+// Encodes a WAV or FLAC file to Ogg Opus at `bitrate_kbps` next to it,
+// returning the new path. Audio is converted to 48 kHz on the way; files with
+// more than two channels keep the first two. The original is left for the
+// caller to remove.
+pub fn encode_opus(path: &Path, bitrate_kbps: u32, quality: ResampleQuality) -> Result<PathBuf> {
+    let opus_path = path.with_extension("opus");
+    let written = write_opus(path, &opus_path, bitrate_kbps, quality);
+    if written.is_err() {
+        let _ = std::fs::remove_file(&opus_path);
+    }
+    written.map(|_| opus_path)
+}
+
+fn write_opus(path: &Path, opus_path: &Path, bitrate_kbps: u32, quality: ResampleQuality) -> Result<()> {
+    let (format, _) = audio_info(path)?;
+    let channels = format.channels.clamp(1, 2);
+    let mut encoder = opus::Encoder::new(
+        OPUS_SAMPLE_RATE,
+        if channels == 1 { opus::Channels::Mono } else { opus::Channels::Stereo },
+        opus::Application::Audio,
+    )?;
+    encoder.set_bitrate(opus::Bitrate::Bits(bitrate_kbps as i32 * 1000))?;
+    let pre_skip = encoder.get_lookahead()? as u16;
+
+    let mut writer = PacketWriter::new(BufWriter::new(File::create(opus_path)?));
+    // Any serial will do for a file holding a single stream
+    let serial = chrono::Utc::now().timestamp_subsec_nanos();
+    writer.write_packet(opus_head(channels, pre_skip, format.sample_rate), serial, PacketWriteEndInfo::EndPage, 0)?;
+    writer.write_packet(opus_tags(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    let mut output = OpusWriter {
+        encoder,
+        writer,
+        serial,
+        channels: channels as usize,
+        pre_skip: pre_skip as u64,
+        pending: Vec::new(),
+        held: None,
+        encoded_frames: 0,
+        input_frames: 0,
+    };
+    let mut converter = FormatConverter::new(format, StreamFormat { sample_rate: OPUS_SAMPLE_RATE, channels }, quality);
+    for_each_chunk(path, |chunk| output.write(&converter.convert(chunk)))?;
+    output.write(&converter.flush())?;
+    output.finish()
+}
+
+// Rewrites the RIFF and data sizes of a WAV file from its length on disk,
+// for a file whose writer stopped before it could finalise the header. A
+// partly written last frame is cut off. Returns whether anything changed.
+pub fn repair_wav_header(path: &Path) -> Result<bool> {
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut riff = [0u8; 12];
+    file.read_exact(&mut riff)?;
+    if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return Err(anyhow::anyhow!("{} is not a WAV file", path.display()));
+    }
+
+    // Walk the chunks up to the audio, noting the frame size on the way
+    let (mut offset, mut block_align) = (12, 0);
+    loop {
+        let mut chunk = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut chunk).map_err(|_| anyhow::anyhow!("{} has no audio data", path.display()))?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut format = [0u8; 16];
+                file.read_exact(&mut format)?;
+                block_align = u16::from_le_bytes([format[12], format[13]]) as u64;
+            }
+            b"data" => break,
+            _ => {}
+        }
+        offset += 8 + size + size % 2;
+    }
+    if block_align == 0 {
+        return Err(anyhow::anyhow!("{} has no usable format chunk", path.display()));
+    }
+
+    let data_start = offset + 8;
+    let max_data = u32::MAX as u64 - (data_start - 8);
+    let data_len = (len.saturating_sub(data_start).min(max_data) / block_align) * block_align;
+    let mut size = [0u8; 4];
+    file.seek(SeekFrom::Start(offset + 4))?;
+    file.read_exact(&mut size)?;
+    if u32::from_le_bytes(size) as u64 == data_len && u32::from_le_bytes([riff[4], riff[5], riff[6], riff[7]]) as u64 == data_start + data_len - 8 {
+        return Ok(false);
+    }
+
+    file.seek(SeekFrom::Start(offset + 4))?;
+    file.write_all(&(data_len as u32).to_le_bytes())?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((data_start + data_len - 8) as u32).to_le_bytes())?;
+    file.set_len(data_start + data_len)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+// Writes interleaved f32 in [-1, 1] in any WAV sample format
+pub struct WavOutput {
+    writer: hound::WavWriter<BufWriter<File>>,
+    spec: hound::WavSpec,
+}
+
+impl WavOutput {
+    pub fn create(path: &Path, spec: hound::WavSpec) -> Result<Self> {
+        Ok(WavOutput { writer: hound::WavWriter::create(path, spec)?, spec })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        match self.spec.sample_format {
+            hound::SampleFormat::Float => {
+                for &sample in samples {
+                    self.writer.write_sample(sample)?;
+                }
+            }
+            hound::SampleFormat::Int => {
+                let max = ((1i64 << (self.spec.bits_per_sample - 1)) - 1) as f32;
+                for &sample in samples {
+                    self.writer.write_sample((sample.clamp(-1.0, 1.0) * max) as i32)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.writer.finalize()?;
+        Ok(())
+    }
+}
+
+// Hands over the audio as mono at `sample_rate`, from one channel or the
+// average of all of them, as speech recognition wants it
+fn for_each_mono_chunk(
+    path: &Path,
+    channel: Option<u16>,
+    sample_rate: u32,
+    quality: ResampleQuality,
+    mut on_chunk: impl FnMut(&[f32]) -> Result<()>,
+) -> Result<()> {
+    let spec = audio_spec(path)?;
+    if let Some(channel) = channel.filter(|&channel| channel >= spec.channels) {
+        return Err(anyhow::anyhow!("Channel {} out of range, the file has {} channels", channel, spec.channels));
+    }
+    let picked = StreamFormat { sample_rate: spec.sample_rate, channels: if channel.is_some() { 1 } else { spec.channels } };
+    let mut converter = FormatConverter::new(picked, StreamFormat { sample_rate, channels: 1 }, quality);
+    for_each_chunk(path, |chunk| {
+        let samples = match channel {
+            Some(channel) => chunk.iter().skip(channel as usize).step_by(spec.channels as usize).copied().collect(),
+            None => chunk.to_vec(),
+        };
+        on_chunk(&converter.convert(&samples))
+    })?;
+    on_chunk(&converter.flush())
+}
+
+// Writes a WAV file as mono 16-bit at `sample_rate`
+pub fn export_mono(path: &Path, output_path: &Path, channel: Option<u16>, sample_rate: u32, quality: ResampleQuality) -> Result<()> {
+    let mut output = WavOutput::create(output_path, hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    })?;
+    let written = for_each_mono_chunk(path, channel, sample_rate, quality, |chunk| output.write(chunk)).and_then(|_| output.finish());
+    if written.is_err() {
+        let _ = std::fs::remove_file(output_path);
+    }
+    written
+}
+
+// All of the audio as mono at `sample_rate`, in memory
+pub fn read_mono(path: &Path, channel: Option<u16>, sample_rate: u32, quality: ResampleQuality) -> Result<Vec<f32>> {
+    let mut samples = Vec::new();
+    for_each_mono_chunk(path, channel, sample_rate, quality, |chunk| {
+        samples.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    Ok(samples)
+}
+
+// Like `read_mono`, for the audio from `start_seconds` up to `end_seconds`
+// only; a WAV isn't read before the start
+pub fn read_mono_between(path: &Path, start_seconds: f64, end_seconds: f64, sample_rate: u32, quality: ResampleQuality) -> Result<Vec<f32>> {
+    let format = spec_format(&audio_spec(path)?);
+    let mut converter = FormatConverter::new(format, StreamFormat { sample_rate, channels: 1 }, quality);
+    let mut samples = Vec::new();
+    for_each_chunk_between(path, start_seconds, end_seconds, |chunk| {
+        samples.extend(converter.convert(chunk));
+        Ok(())
+    })?;
+    samples.extend(converter.flush());
+    Ok(samples)
+}
+
+// Like `read_mono_between` for each of `spans`, (start, end) in seconds, from
+// one pass through a FLAC or Opus file. Only the spans' audio is held, so
+// the lines of hours of audio don't need all of it in memory.
+pub fn read_mono_spans(path: &Path, spans: &[(f64, f64)], sample_rate: u32, quality: ResampleQuality) -> Result<Vec<Vec<f32>>> {
+    if !is_flac(path) && !is_opus(path) {
+        return spans.iter().map(|&(start, end)| read_mono_between(path, start, end, sample_rate, quality)).collect();
+    }
+    let format = spec_format(&audio_spec(path)?);
+    let channels = format.channels.max(1) as usize;
+    let frame = |seconds: f64| (seconds.max(0.0) * format.sample_rate as f64).round() as u64;
+    let mut order: Vec<usize> = (0..spans.len()).collect();
+    order.sort_by(|&a, &b| spans[a].0.total_cmp(&spans[b].0));
+    let mut converters: Vec<Option<FormatConverter>> = spans.iter().map(|_| None).collect();
+    let mut audio = vec![Vec::new(); spans.len()];
+    // Spans before `done` in start order have been read to their end
+    let (mut done, mut position) = (0, 0);
+    for_each_chunk(path, |chunk| {
+        let chunk_end = position + (chunk.len() / channels) as u64;
+        for &index in &order[done..] {
+            let (start, end) = (frame(spans[index].0), frame(spans[index].1));
+            if start >= chunk_end {
+                break;
+            }
+            let (from, to) = (start.clamp(position, chunk_end), end.clamp(position, chunk_end));
+            if to > from {
+                let converter = converters[index]
+                    .get_or_insert_with(|| FormatConverter::new(format, StreamFormat { sample_rate, channels: 1 }, quality));
+                audio[index].extend(converter.convert(&chunk[(from - position) as usize * channels..(to - position) as usize * channels]));
+            }
+        }
+        while done < order.len() && frame(spans[order[done]].1) <= chunk_end {
+            if let Some(mut converter) = converters[order[done]].take() {
+                audio[order[done]].extend(converter.flush());
+            }
+            done += 1;
+        }
+        position = chunk_end;
+        Ok(())
+    })?;
+    for (samples, converter) in audio.iter_mut().zip(converters) {
+        if let Some(mut converter) = converter {
+            samples.extend(converter.flush());
+        }
+    }
+    Ok(audio)
+}
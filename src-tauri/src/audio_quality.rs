@@ -0,0 +1,203 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_file;
+use crate::database::{Database, RecordingQuality};
+
+// Samples this close to full scale count as clipped, and it takes a few in
+// a row to call it clipping rather than a loud peak
+const CLIP_LEVEL: f32 = 0.999;
+const CLIP_RUN_SAMPLES: u32 = 3;
+
+// Exact digital silence on every channel, in the middle of a recording,
+// is audio that never arrived rather than a quiet room
+const DROPOUT_MIN_SECONDS: f64 = 0.002;
+
+// A jump between samples this big, straight after a smooth stretch, is a
+// splice where buffers were lost or reordered
+const DISCONTINUITY_JUMP: f32 = 0.5;
+const SMOOTH_STEP: f32 = 0.05;
+
+// Problems this close together are reported as one
+const MERGE_SECONDS: f64 = 0.05;
+
+// Reported problems of each kind; the counts cover all of them
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityEvent {
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    pub recording_id: i64,
+    pub clipped_samples: u64,
+    pub clipping: Vec<QualityEvent>,
+    pub clipping_count: usize,
+    pub dropouts: Vec<QualityEvent>,
+    pub dropout_count: usize,
+    pub discontinuities: Vec<QualityEvent>,
+    pub discontinuity_count: usize,
+    // Any of the above; stored on the recording
+    pub compromised: bool,
+}
+
+// Problems of one kind, merged as they are found
+#[derive(Default)]
+struct Events {
+    events: Vec<QualityEvent>,
+    count: usize,
+    last: Option<QualityEvent>,
+}
+
+impl Events {
+    fn add(&mut self, start_seconds: f64, end_seconds: f64) {
+        if let Some(last) = self.last.as_mut().filter(|last| start_seconds - last.end_seconds <= MERGE_SECONDS) {
+            last.end_seconds = last.end_seconds.max(end_seconds);
+            return;
+        }
+        self.flush();
+        self.last = Some(QualityEvent { start_seconds, end_seconds });
+    }
+
+    fn flush(&mut self) {
+        if let Some(last) = self.last.take() {
+            self.count += 1;
+            if self.events.len() < MAX_EVENTS {
+                self.events.push(last);
+            }
+        }
+    }
+
+    fn finish(mut self) -> (Vec<QualityEvent>, usize) {
+        self.flush();
+        (self.events, self.count)
+    }
+}
+
+struct Scan {
+    clipped_samples: u64,
+    clipping: Events,
+    dropouts: Events,
+    discontinuities: Events,
+}
+
+fn scan(path: &Path) -> Result<Scan> {
+    let spec = audio_file::audio_spec(path)?;
+    let channels = spec.channels.max(1) as usize;
+    let sample_rate = spec.sample_rate.max(1) as f64;
+    let seconds = |frame: u64| frame as f64 / sample_rate;
+
+    let mut result = Scan { clipped_samples: 0, clipping: Events::default(), dropouts: Events::default(), discontinuities: Events::default() };
+    // Per channel: the clipped run so far with its first frame, the last sample and step
+    let mut clip_runs = vec![(0u32, 0u64); channels];
+    let mut previous = vec![(0.0f32, 0.0f32); channels];
+    let mut frame = 0u64;
+    // Frame a run of digital silence started at; none until audio has been heard
+    let mut silence_start: Option<u64> = None;
+    let mut heard = false;
+
+    audio_file::for_each_chunk(path, |chunk| {
+        for samples in chunk.chunks_exact(channels) {
+            let mut silent = true;
+            for (channel, &sample) in samples.iter().enumerate() {
+                silent &= sample == 0.0;
+
+                let (run, run_start) = &mut clip_runs[channel];
+                if sample.abs() >= CLIP_LEVEL {
+                    if *run == 0 {
+                        *run_start = frame;
+                    }
+                    *run += 1;
+                    result.clipped_samples += 1;
+                } else {
+                    if *run >= CLIP_RUN_SAMPLES {
+                        result.clipping.add(seconds(*run_start), seconds(frame));
+                    }
+                    *run = 0;
+                }
+
+                let (last, last_step) = previous[channel];
+                let step = sample - last;
+                if heard && step.abs() >= DISCONTINUITY_JUMP && last_step.abs() <= SMOOTH_STEP {
+                    result.discontinuities.add(seconds(frame), seconds(frame + 1));
+                }
+                previous[channel] = (sample, step);
+            }
+
+            match (silent, silence_start) {
+                (true, None) if heard => silence_start = Some(frame),
+                (false, Some(start)) => {
+                    if seconds(frame - start) >= DROPOUT_MIN_SECONDS {
+                        result.dropouts.add(seconds(start), seconds(frame));
+                    }
+                    silence_start = None;
+                }
+                _ => {}
+            }
+            heard |= !silent;
+            frame += 1;
+        }
+        Ok(())
+    })?;
+
+    // Clipping held to the very end still counts; silence there is just the end
+    for &(run, run_start) in &clip_runs {
+        if run >= CLIP_RUN_SAMPLES {
+            result.clipping.add(seconds(run_start), seconds(frame));
+        }
+    }
+    Ok(result)
+}
+
+// Scans a recording for clipping, dropouts and discontinuities, and stores
+// whether it has any so the recording can be flagged wherever it's listed
+#[command]
+pub async fn analyze_recording_quality(recording_id: i64, app_handle: tauri::AppHandle) -> Result<QualityReport, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let scan = tokio::task::spawn_blocking(move || scan(&source))
+        .await
+        .map_err(|e| format!("Quality analysis error: {}", e))?
+        .map_err(|e| format!("Quality analysis error: {}", e))?;
+
+    let (clipping, clipping_count) = scan.clipping.finish();
+    let (dropouts, dropout_count) = scan.dropouts.finish();
+    let (discontinuities, discontinuity_count) = scan.discontinuities.finish();
+    let report = QualityReport {
+        recording_id,
+        clipped_samples: scan.clipped_samples,
+        clipping,
+        clipping_count,
+        dropouts,
+        dropout_count,
+        discontinuities,
+        discontinuity_count,
+        compromised: clipping_count + dropout_count + discontinuity_count > 0,
+    };
+    db.save_recording_quality(&RecordingQuality {
+            record_id: recording_id,
+            clipped_samples: report.clipped_samples as i64,
+            clipping_count: clipping_count as i64,
+            dropout_count: dropout_count as i64,
+            discontinuity_count: discontinuity_count as i64,
+            compromised: report.compromised,
+            checked_at: String::new(),
+        })
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(report)
+}
+
+// What the last quality analysis found, or None if it was never analyzed
+#[command]
+pub async fn get_recording_quality(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Option<RecordingQuality>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_recording_quality(recording_id).map_err(|e| format!("Database error: {}", e))
+}
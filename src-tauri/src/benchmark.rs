@@ -0,0 +1,139 @@
+use tauri::{command, State};
+use serde::Serialize;
+use sysinfo::System;
+use std::time::Instant;
+use crate::ai_models::{self, AdvancedAI, GenerationOptions};
+
+// Covers a short answer, structured reasoning and a longer generation
+const STANDARD_PROMPTS: [&str; 4] = [
+    "Reply with one short sentence confirming you are ready.",
+    "List three common causes of background hum in audio recordings.",
+    "An audio clip has an average amplitude of 0.02, a peak of 0.9 and frequent zero crossings. What might it contain?",
+    "Write a detailed paragraph explaining how voice activity detection works.",
+];
+
+// Keeps each run bounded so a benchmark doesn't take minutes
+const BENCHMARK_MAX_TOKENS: u32 = 128;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkRun {
+    pub prompt: String,
+    pub time_to_first_token_ms: Option<u64>,
+    pub total_time_ms: u64,
+    pub completion_tokens: usize,
+    // Generation speed after the first token, so prompt processing isn't counted
+    pub tokens_per_second: Option<f32>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemorySnapshot {
+    pub system_total_bytes: u64,
+    pub system_available_before_bytes: u64,
+    pub system_available_after_bytes: u64,
+    // Reported by Ollama for the loaded model, when it is served by Ollama
+    pub model_size_bytes: Option<u64>,
+    pub model_vram_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkResult {
+    pub model: String,
+    pub runs: Vec<BenchmarkRun>,
+    pub avg_time_to_first_token_ms: Option<u64>,
+    pub avg_tokens_per_second: Option<f32>,
+    pub memory: MemorySnapshot,
+}
+
+fn average<T: Into<f64> + Copy>(values: &[T]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().map(|&v| v.into()).sum::<f64>() / values.len() as f64)
+}
+
+// Runs each prompt through the streaming path (never the cache) and times the
+// first token and the overall generation
+#[command]
+pub async fn benchmark_model(
+    model: String,
+    prompt_set: Option<Vec<String>>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<BenchmarkResult, String> {
+    let prompts = prompt_set
+        .filter(|prompts| !prompts.is_empty())
+        .unwrap_or_else(|| STANDARD_PROMPTS.iter().map(|p| p.to_string()).collect());
+    let options = GenerationOptions {
+        max_tokens: Some(BENCHMARK_MAX_TOKENS),
+        ..GenerationOptions::default()
+    };
+
+    let active = ai_models::begin_request(&ai, request_id, "benchmark_model", &app_handle)?;
+
+    let mut system = System::new();
+    system.refresh_memory();
+    let system_total_bytes = system.total_memory();
+    let system_available_before_bytes = system.available_memory();
+
+    let mut runs = Vec::new();
+    for prompt in prompts {
+        let start_time = Instant::now();
+        let mut first_token: Option<Instant> = None;
+
+        let result = active.run(ai.query_llama_stream(&prompt, &model, &options, |_| {
+            first_token.get_or_insert_with(Instant::now);
+        })).await;
+        let total_time = start_time.elapsed();
+
+        if active.is_cancelled() {
+            return Err(format!("Benchmark of '{}' was cancelled", model));
+        }
+
+        let run = match result {
+            Ok((response, _)) => {
+                let ttft = first_token.map(|t| t.duration_since(start_time));
+                let decode_secs = ttft.map(|ttft| (total_time - ttft).as_secs_f32()).unwrap_or(0.0);
+                BenchmarkRun {
+                    prompt,
+                    time_to_first_token_ms: ttft.map(|d| d.as_millis() as u64),
+                    total_time_ms: total_time.as_millis() as u64,
+                    completion_tokens: response.completion_tokens,
+                    tokens_per_second: (decode_secs > 0.0 && response.completion_tokens > 1)
+                        .then(|| (response.completion_tokens - 1) as f32 / decode_secs),
+                    error: None,
+                }
+            }
+            Err(e) => BenchmarkRun {
+                prompt,
+                time_to_first_token_ms: None,
+                total_time_ms: total_time.as_millis() as u64,
+                completion_tokens: 0,
+                tokens_per_second: None,
+                error: Some(e.to_string()),
+            },
+        };
+        runs.push(run);
+    }
+
+    system.refresh_memory();
+    let model_memory = ai.loaded_model_memory(&model).await;
+
+    let ttfts: Vec<f64> = runs.iter().filter_map(|r| r.time_to_first_token_ms).map(|ms| ms as f64).collect();
+    let speeds: Vec<f32> = runs.iter().filter_map(|r| r.tokens_per_second).collect();
+
+    Ok(BenchmarkResult {
+        model,
+        avg_time_to_first_token_ms: average(&ttfts).map(|ms| ms as u64),
+        avg_tokens_per_second: average(&speeds).map(|tps| tps as f32),
+        runs,
+        memory: MemorySnapshot {
+            system_total_bytes,
+            system_available_before_bytes,
+            system_available_after_bytes: system.available_memory(),
+            model_size_bytes: model_memory.map(|(size, _)| size),
+            model_vram_bytes: model_memory.map(|(_, vram)| vram),
+        },
+    })
+}
@@ -0,0 +1,584 @@
+use anyhow::Result;
+use candle_core::{safetensors::MmapedSafetensors, Device, IndexOp, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::whisper::{self as m, audio, model::Whisper, Config};
+use std::collections::HashMap;
+use std::path::Path;
+use tokenizers::Tokenizer;
+use crate::acceleration::Acceleration;
+use crate::database::TranscriptWord;
+use crate::transcription::LANGUAGES;
+use crate::whisper::{main_language, TranscriptionResult, TranscriptionSegment};
+use crate::whisper_alignment::{self, Aligner};
+
+// What transcripts from these models are stored as coming from
+pub const ENGINE: &str = "whisper";
+
+// A model is a folder of the Hugging Face openai/whisper-* files
+pub const MODEL_FILES: [&str; 4] = ["config.json", "tokenizer.json", "model.safetensors", whisper_alignment::GENERATION_CONFIG];
+
+// Where a size's files are published
+pub fn model_url(size: &str, file: &str) -> String {
+    format!("https://huggingface.co/openai/whisper-{}/resolve/main/{}", size, file)
+}
+
+// Multilingual vocabularies are at least this big; English-only ones one less
+const MULTILINGUAL_VOCAB: usize = 51865;
+
+// Timestamp tokens count in steps of 20 ms, two mel frames each
+const SECONDS_PER_TIMESTAMP: f64 = 0.02;
+const FRAMES_PER_TIMESTAMP: usize = 2;
+
+// The first timestamp of a window may be at most this far in
+const MAX_INITIAL_TIMESTAMP: u32 = 50;
+
+// Text before this token is taken as what was said before the window,
+// which is how custom vocabulary reaches the decoder
+const SOT_PREV_TOKEN: &str = "<|startofprev|>";
+
+// How byte-level BPE writes the space a token starts with
+const BPE_SPACE: char = '\u{120}';
+
+struct SpecialTokens {
+    sot: u32,
+    sot_prev: u32,
+    eot: u32,
+    transcribe: u32,
+    translate: u32,
+    no_timestamps: u32,
+    no_speech: Option<u32>,
+    // Token ids of the languages the model knows
+    languages: Vec<(&'static str, u32)>,
+}
+
+impl SpecialTokens {
+    fn new(tokenizer: &Tokenizer, multilingual: bool) -> Result<Self> {
+        let token = |name: &str| tokenizer.token_to_id(name).ok_or_else(|| anyhow::anyhow!("The tokenizer has no {} token", name));
+        let languages = if multilingual {
+            LANGUAGES.iter().filter_map(|&code| tokenizer.token_to_id(&format!("<|{}|>", code)).map(|id| (code, id))).collect()
+        } else {
+            Vec::new()
+        };
+        Ok(SpecialTokens {
+            sot: token(m::SOT_TOKEN)?,
+            sot_prev: token(SOT_PREV_TOKEN)?,
+            eot: token(m::EOT_TOKEN)?,
+            transcribe: token(m::TRANSCRIBE_TOKEN)?,
+            translate: token(m::TRANSLATE_TOKEN)?,
+            no_timestamps: token(m::NO_TIMESTAMPS_TOKEN)?,
+            no_speech: m::NO_SPEECH_TOKENS.iter().find_map(|name| tokenizer.token_to_id(name)),
+            languages,
+        })
+    }
+
+    // The timestamp tokens come straight after <|notimestamps|>
+    fn timestamp_begin(&self) -> u32 {
+        self.no_timestamps + 1
+    }
+
+    fn is_timestamp(&self, token: u32) -> bool {
+        token >= self.timestamp_begin()
+    }
+}
+
+// What Whisper is asked to write down: the speech as spoken, or its
+// English translation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Task {
+    Transcribe,
+    Translate,
+}
+
+// A Whisper model loaded onto the CPU or a GPU, with what decoding it needs
+pub struct WhisperModel {
+    size: String,
+    backend: Acceleration,
+    model: Whisper,
+    // Times the words of each window
+    aligner: Aligner,
+    tokenizer: Tokenizer,
+    tokens: SpecialTokens,
+    // Added to the logits of every step; -inf for tokens never sampled
+    suppress: Vec<f32>,
+    mel_filters: Vec<f32>,
+    device: Device,
+}
+
+// One decoded 30 second window
+struct Window {
+    // The sampled tokens, without the prompt or <|endoftext|>
+    tokens: Vec<u32>,
+    logprobs: Vec<f64>,
+    no_speech_prob: f64,
+}
+
+impl Window {
+    fn avg_logprob(&self) -> f64 {
+        if self.logprobs.is_empty() {
+            return 0.0;
+        }
+        self.logprobs.iter().sum::<f64>() / self.logprobs.len() as f64
+    }
+}
+
+// The device for a backend `AccelerationSupport::resolve` returned
+fn device(backend: Acceleration) -> Result<Device> {
+    match backend {
+        Acceleration::Cuda => Ok(Device::new_cuda(0)?),
+        Acceleration::Metal => Ok(Device::new_metal(0)?),
+        Acceleration::Vulkan => Err(anyhow::anyhow!("candle has no Vulkan backend; build with the whisper-cpp feature for it")),
+        Acceleration::Cpu | Acceleration::Auto => Ok(Device::Cpu),
+    }
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    // Slaney's scale: linear to 1 kHz, logarithmic above
+    if hz < 1000.0 {
+        hz * 3.0 / 200.0
+    } else {
+        15.0 + (hz / 1000.0).ln() * 27.0 / 6.4f64.ln()
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    if mel < 15.0 {
+        mel * 200.0 / 3.0
+    } else {
+        1000.0 * ((mel - 15.0) * 6.4f64.ln() / 27.0).exp()
+    }
+}
+
+// The mel filterbank Whisper was trained with, as librosa makes it:
+// triangles over the FFT bins, each scaled to the same area
+fn mel_filters(n_mels: usize) -> Vec<f32> {
+    let bins = m::N_FFT / 2 + 1;
+    let nyquist = m::SAMPLE_RATE as f64 / 2.0;
+    let max_mel = hz_to_mel(nyquist);
+    let edges: Vec<f64> = (0..n_mels + 2).map(|index| mel_to_hz(max_mel * index as f64 / (n_mels + 1) as f64)).collect();
+    let mut filters = vec![0.0f32; n_mels * bins];
+    for mel in 0..n_mels {
+        let (lower, centre, upper) = (edges[mel], edges[mel + 1], edges[mel + 2]);
+        let scale = 2.0 / (upper - lower);
+        for bin in 0..bins {
+            let hz = bin as f64 * nyquist / (bins - 1) as f64;
+            let weight = ((hz - lower) / (centre - lower)).min((upper - hz) / (upper - centre)).max(0.0);
+            filters[mel * bins + bin] = (weight * scale) as f32;
+        }
+    }
+    filters
+}
+
+fn log_softmax(logits: &[f32]) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max) as f64;
+    let sum: f64 = logits.iter().map(|&logit| (logit as f64 - max).exp()).sum();
+    let log_sum = max + sum.ln();
+    logits.iter().map(|&logit| logit as f64 - log_sum).collect()
+}
+
+// Geometric mean of the tokens' probabilities
+fn mean_probability(logprobs: &[f64]) -> f32 {
+    if logprobs.is_empty() {
+        return 0.0;
+    }
+    (logprobs.iter().sum::<f64>() / logprobs.len() as f64).exp() as f32
+}
+
+fn argmax(values: &[f32]) -> u32 {
+    values.iter().enumerate().max_by(|(_, a), (_, b)| a.total_cmp(b)).map_or(0, |(index, _)| index as u32)
+}
+
+impl WhisperModel {
+    pub fn load(dir: &Path, size: &str, backend: Acceleration) -> Result<Self> {
+        let config: Config = serde_json::from_str(&std::fs::read_to_string(dir.join("config.json"))?)?;
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| anyhow::anyhow!("Failed to load the Whisper tokenizer: {}", e))?;
+        let device = device(backend)?;
+        // SAFETY: the weights are mapped read-only and the app never writes
+        // to a model's files while it is loaded
+        let safetensors = unsafe { MmapedSafetensors::new(dir.join("model.safetensors"))? };
+        // Loaded once and shared by the model and the aligner
+        let tensors = safetensors.tensors().into_iter()
+            .map(|(name, _)| Ok((name.clone(), safetensors.load(&name, &device)?.to_dtype(m::DTYPE)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let weights = VarBuilder::from_tensors(tensors, m::DTYPE, &device);
+        let tokens = SpecialTokens::new(&tokenizer, config.vocab_size >= MULTILINGUAL_VOCAB)?;
+
+        // Special tokens are never sampled: everything between
+        // <|endoftext|> and the timestamps, as well as the config's list
+        let mut suppress = vec![0.0f32; config.vocab_size];
+        for token in config.suppress_tokens.iter().copied().chain(tokens.eot + 1..tokens.timestamp_begin()) {
+            if let Some(logit) = suppress.get_mut(token as usize) {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+        let mel_filters = mel_filters(config.num_mel_bins);
+        let aligner = Aligner::load(weights.pp("model.decoder"), &config, &whisper_alignment::alignment_heads(dir, &config))?;
+        let model = Whisper::load(&weights, config)?;
+        Ok(WhisperModel { size: size.to_string(), backend, model, aligner, tokenizer, tokens, suppress, mel_filters, device })
+    }
+
+    // Logits for the token after `tokens`, and on the first step of a window
+    // for the one after <|startoftranscript|> at `sot_at`, which is where
+    // no-speech is read from
+    fn next_logits(&mut self, tokens: &[u32], audio_features: &Tensor, sot_at: Option<usize>) -> Result<(Vec<f32>, Option<Vec<f32>>)> {
+        let input = Tensor::new(tokens, &self.device)?.unsqueeze(0)?;
+        let hidden = self.model.decoder.forward(&input, audio_features, sot_at.is_some())?;
+        let (_, length, _) = hidden.dims3()?;
+        let last = self.model.decoder.final_linear(&hidden.i((..1, length - 1..))?)?.i(0)?.i(0)?.to_vec1::<f32>()?;
+        let sot = match sot_at {
+            Some(at) => Some(self.model.decoder.final_linear(&hidden.i((..1, at..at + 1))?)?.i(0)?.i(0)?.to_vec1::<f32>()?),
+            None => None,
+        };
+        Ok((last, sot))
+    }
+
+    // Picks the language the window is most likely spoken in
+    fn detect_language(&mut self, audio_features: &Tensor) -> Result<&'static str> {
+        let (logits, _) = self.next_logits(&[self.tokens.sot], audio_features, Some(0))?;
+        let (code, _) = self.tokens.languages.iter()
+            .max_by(|(_, a), (_, b)| logits[*a as usize].total_cmp(&logits[*b as usize]))
+            .ok_or_else(|| anyhow::anyhow!("The model knows no languages"))?;
+        Ok(code)
+    }
+
+    // Whisper's timestamp rules, so timestamps come in pairs around text,
+    // never go backwards and the first one is near the window's start
+    fn apply_timestamp_rules(&self, logits: &mut [f32], sampled: &[u32]) {
+        let timestamp_begin = self.tokens.timestamp_begin() as usize;
+        let last_was_timestamp = sampled.last().is_some_and(|&token| self.tokens.is_timestamp(token));
+        let penultimate_was_timestamp = sampled.len() < 2 || self.tokens.is_timestamp(sampled[sampled.len() - 2]);
+        if last_was_timestamp {
+            if penultimate_was_timestamp {
+                // A segment just closed; text or the end comes next
+                logits[timestamp_begin..].fill(f32::NEG_INFINITY);
+            } else {
+                // Text was just closed by a timestamp; another timestamp opens the next segment, or it ends
+                logits[..self.tokens.eot as usize].fill(f32::NEG_INFINITY);
+            }
+        }
+        if let Some(&last_timestamp) = sampled.iter().rev().find(|&&token| self.tokens.is_timestamp(token)) {
+            // A closed segment's end may start the next one; otherwise timestamps move forwards
+            let floor = if last_was_timestamp && !penultimate_was_timestamp { last_timestamp } else { last_timestamp + 1 };
+            let floor = (floor as usize).min(logits.len());
+            logits[timestamp_begin..floor].fill(f32::NEG_INFINITY);
+        }
+        if sampled.is_empty() {
+            logits[..timestamp_begin].fill(f32::NEG_INFINITY);
+            let latest = timestamp_begin + MAX_INITIAL_TIMESTAMP as usize + 1;
+            if latest < logits.len() {
+                logits[latest..].fill(f32::NEG_INFINITY);
+            }
+        }
+        // A timestamp is taken when all of them together are likelier than any one text token
+        let logprobs = log_softmax(logits);
+        let timestamp_logprob = logprobs[timestamp_begin..].iter().map(|logprob| logprob.exp()).sum::<f64>().ln();
+        let max_text_logprob = logprobs[..timestamp_begin].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if timestamp_logprob > max_text_logprob {
+            logits[..timestamp_begin].fill(f32::NEG_INFINITY);
+        }
+    }
+
+    // The vocabulary as text said before each window, so the decoder leans
+    // towards writing the terms when it hears them. Terms past what the
+    // prompt has room for are left out.
+    fn context(&self, vocabulary: &[String]) -> Result<Vec<u32>> {
+        let room = self.model.config.max_target_positions / 2 - 1;
+        let mut context = Vec::new();
+        for count in (1..=vocabulary.len()).rev() {
+            let text = format!(" {}", vocabulary[..count].join(", "));
+            let encoding = self.tokenizer.encode(text, false).map_err(|e| anyhow::anyhow!("Failed to tokenize the vocabulary: {}", e))?;
+            if encoding.get_ids().len() <= room {
+                context.push(self.tokens.sot_prev);
+                context.extend_from_slice(encoding.get_ids());
+                break;
+            }
+        }
+        Ok(context)
+    }
+
+    // Starts decoding after the context, in the language and task on
+    // multilingual models. English-only ones have no language or task tokens.
+    fn prompt(&self, language: &str, task: Task, context: &[u32]) -> Result<Vec<u32>> {
+        let mut prompt = context.to_vec();
+        prompt.push(self.tokens.sot);
+        if !self.tokens.languages.is_empty() {
+            let token = self.tokens.languages.iter()
+                .find(|(code, _)| *code == language)
+                .map(|(_, token)| *token)
+                .ok_or_else(|| anyhow::anyhow!("The {} model doesn't know the language '{}'", self.size, language))?;
+            let task = match task {
+                Task::Transcribe => self.tokens.transcribe,
+                Task::Translate => self.tokens.translate,
+            };
+            prompt.extend([token, task]);
+        }
+        Ok(prompt)
+    }
+
+    // Greedy decoding of one encoded window
+    fn decode(&mut self, audio_features: &Tensor, prompt: &[u32]) -> Result<Window> {
+        let max_tokens = self.model.config.max_target_positions / 2;
+        let sot_at = prompt.iter().position(|&token| token == self.tokens.sot).unwrap_or(0);
+        let mut tokens = prompt.to_vec();
+        let mut window = Window { tokens: Vec::new(), logprobs: Vec::new(), no_speech_prob: 0.0 };
+        for step in 0..max_tokens {
+            let (mut logits, sot_logits) = self.next_logits(&tokens, audio_features, (step == 0).then_some(sot_at))?;
+            if let (Some(sot_logits), Some(no_speech)) = (sot_logits, self.tokens.no_speech) {
+                window.no_speech_prob = log_softmax(&sot_logits)[no_speech as usize].exp();
+            }
+            for (logit, suppress) in logits.iter_mut().zip(&self.suppress) {
+                *logit += suppress;
+            }
+            self.apply_timestamp_rules(&mut logits, &window.tokens);
+            let next = argmax(&logits);
+            if next == self.tokens.eot || tokens.len() >= self.model.config.max_target_positions {
+                break;
+            }
+            window.logprobs.push(log_softmax(&logits)[next as usize]);
+            window.tokens.push(next);
+            tokens.push(next);
+        }
+        Ok(window)
+    }
+
+    // When each of the window's text tokens starts, then when its text ends,
+    // in seconds into the window. The decoder reads the text again with no
+    // context and no timestamps, as openai's whisper does to align words.
+    fn token_times(&self, audio_features: &Tensor, window: &Window, language: &str, frames: usize) -> Result<Vec<f64>> {
+        let mut tokens = self.prompt(language, Task::Transcribe, &[])?;
+        let first = tokens.len();
+        tokens.push(self.tokens.no_timestamps);
+        tokens.extend(window.tokens.iter().filter(|&&token| !self.tokens.is_timestamp(token)));
+        tokens.push(self.tokens.eot);
+        self.aligner.token_times(&tokens, first, audio_features, frames)
+    }
+
+    fn text(&self, tokens: &[u32]) -> Result<String> {
+        let text = self.tokenizer.decode(tokens, true).map_err(|e| anyhow::anyhow!("Failed to decode tokens: {}", e))?;
+        Ok(text.trim().to_string())
+    }
+
+    // A word starts with every token that starts with a space, and runs
+    // from when its first token is said to when the next word's is, by
+    // `times`: when each token starts and, last, when the text ends, in
+    // seconds into the window at `offset`. Without times there are no words.
+    fn words(&self, tokens: &[u32], logprobs: &[f64], times: Option<&[f64]>, offset: f64, start: f64, end: f64) -> Result<Vec<TranscriptWord>> {
+        let Some(times) = times else { return Ok(Vec::new()) };
+        let mut groups: Vec<(usize, Vec<u32>, Vec<f64>)> = Vec::new();
+        for (index, (&token, &logprob)) in tokens.iter().zip(logprobs).enumerate() {
+            let starts_word = self.tokenizer.id_to_token(token).is_some_and(|piece| piece.starts_with(BPE_SPACE) || piece.starts_with(' '));
+            match groups.last_mut() {
+                Some((_, tokens, logprobs)) if !starts_word => {
+                    tokens.push(token);
+                    logprobs.push(logprob);
+                }
+                _ => groups.push((index, vec![token], vec![logprob])),
+            }
+        }
+        let at = |index: usize| (offset + times[index.min(times.len() - 1)]).clamp(start, end);
+        let mut words = Vec::new();
+        for (first, tokens, logprobs) in groups {
+            let word = self.text(&tokens)?;
+            if !word.is_empty() {
+                words.push(TranscriptWord {
+                    start_seconds: at(first),
+                    end_seconds: at(first + tokens.len()),
+                    word,
+                    confidence: mean_probability(&logprobs),
+                });
+            }
+        }
+        Ok(words)
+    }
+
+    fn segment(&self, tokens: &[u32], logprobs: &[f64], times: Option<&[f64]>, offset: f64, start: f64, end: f64) -> Result<Option<TranscriptionSegment>> {
+        let text = self.text(tokens)?;
+        if text.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(TranscriptionSegment {
+            start,
+            end,
+            text,
+            confidence: mean_probability(logprobs),
+            words: self.words(tokens, logprobs, times, offset, start, end)?,
+            language: None,
+            avg_logprob: Some(logprobs.iter().sum::<f64>() / logprobs.len().max(1) as f64),
+        }))
+    }
+
+    // Segments of a window between its timestamp pairs, `offset` seconds into
+    // the audio, with its text tokens timed by `times` as from token_times.
+    // Returns them with how many frames the window really covered: one cut
+    // off mid-sentence is decoded again from its last closed segment.
+    fn segments(&self, window: &Window, times: Option<&[f64]>, offset: f64, frames: usize) -> Result<(Vec<TranscriptionSegment>, usize)> {
+        let seconds = |token: u32| offset + (token - self.tokens.timestamp_begin()) as f64 * SECONDS_PER_TIMESTAMP;
+        // The times of a segment's text, from its first token on
+        let from = |first: usize| times.map(|times| &times[first..]);
+        let mut segments = Vec::new();
+        let mut start: Option<u32> = None;
+        // How many text tokens came before the open segment's
+        let mut first = 0;
+        let mut text_tokens: Vec<u32> = Vec::new();
+        let mut logprobs: Vec<f64> = Vec::new();
+        let mut closed_at = None;
+        for (&token, &logprob) in window.tokens.iter().zip(&window.logprobs) {
+            if !self.tokens.is_timestamp(token) {
+                text_tokens.push(token);
+                logprobs.push(logprob);
+                continue;
+            }
+            match start {
+                Some(opened) if !text_tokens.is_empty() => {
+                    segments.extend(self.segment(&text_tokens, &logprobs, from(first), offset, seconds(opened), seconds(token))?);
+                    closed_at = Some(token);
+                    first += text_tokens.len();
+                    text_tokens.clear();
+                    logprobs.clear();
+                    start = None;
+                }
+                _ => start = Some(token),
+            }
+        }
+
+        let window_seconds = frames as f64 * m::HOP_LENGTH as f64 / m::SAMPLE_RATE as f64;
+        let left_open = start.is_some() || !text_tokens.is_empty();
+        match (closed_at, left_open) {
+            // Text without a closing timestamp spans the window
+            (None, true) if !text_tokens.is_empty() => {
+                segments.extend(self.segment(&text_tokens, &logprobs, from(first), offset, start.map_or(offset, seconds), offset + window_seconds)?);
+                Ok((segments, frames))
+            }
+            (Some(closed), true) => {
+                let covered = (closed - self.tokens.timestamp_begin()) as usize * FRAMES_PER_TIMESTAMP;
+                Ok((segments, covered.clamp(1, frames)))
+            }
+            _ => Ok((segments, frames)),
+        }
+    }
+
+    // Transcribes 16 kHz mono audio window by window, in `language` or, on
+    // multilingual models, the one detected for each window, so audio that
+    // switches language is decoded in each. The result's language is the one
+    // spoken longest. `vocabulary` biases decoding towards those terms.
+    pub fn transcribe(&mut self, samples: &[f32], language: Option<&str>, vocabulary: &[String]) -> Result<TranscriptionResult> {
+        self.run(samples, language, vocabulary, Task::Transcribe, &mut |_| Ok(()))
+    }
+
+    // Like transcribe, but the text is an English translation of the speech.
+    // The result's language is still the one spoken.
+    pub fn translate(&mut self, samples: &[f32], language: Option<&str>, vocabulary: &[String]) -> Result<TranscriptionResult> {
+        self.translate_with_progress(samples, language, vocabulary, &mut |_| Ok(()))
+    }
+
+    // As transcribe, with `on_progress` given the fraction of the audio done
+    // after each window; an error from it stops the transcription
+    pub fn transcribe_with_progress(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        self.run(samples, language, vocabulary, Task::Transcribe, on_progress)
+    }
+
+    pub fn translate_with_progress(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        if self.tokens.languages.is_empty() {
+            return Err(anyhow::anyhow!("The {} model is English-only and can't translate; use a multilingual model", self.size));
+        }
+        self.run(samples, language, vocabulary, Task::Translate, on_progress)
+    }
+
+    pub fn size(&self) -> &str {
+        &self.size
+    }
+
+    pub fn backend(&self) -> Acceleration {
+        self.backend
+    }
+
+    fn run(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        task: Task,
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        let started = std::time::Instant::now();
+        let n_mels = self.model.config.num_mel_bins;
+        let mel = audio::pcm_to_mel(&self.model.config, samples, &self.mel_filters);
+        let mel_frames = mel.len() / n_mels;
+        let mel = Tensor::from_vec(mel, (1, n_mels, mel_frames), &self.device)?;
+        let content_frames = samples.len() / m::HOP_LENGTH;
+
+        let fixed = match (language, self.tokens.languages.is_empty()) {
+            (Some(language), true) if language != "en" => {
+                return Err(anyhow::anyhow!("The {} model only transcribes English; use a multilingual model for '{}'", self.size, language));
+            }
+            (_, true) => Some("en".to_string()),
+            (Some(language), false) => Some(language.to_string()),
+            (None, false) => None,
+        };
+        let context = self.context(vocabulary)?;
+        let fixed_prompt = fixed.as_deref().map(|language| self.prompt(language, task, &context)).transpose()?;
+
+        let mut segments: Vec<TranscriptionSegment> = Vec::new();
+        let mut first_detected = None;
+        let mut seek = 0;
+        while seek < content_frames {
+            let frames = m::N_FRAMES.min(content_frames - seek);
+            let window_mel = mel.narrow(2, seek, m::N_FRAMES.min(mel_frames - seek))?;
+            let offset = (seek * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
+            let audio_features = self.model.encoder.forward(&window_mel, true)?;
+            let (window_language, window) = match (&fixed, &fixed_prompt) {
+                (Some(language), Some(prompt)) => (language.clone(), self.decode(&audio_features, prompt)?),
+                _ => {
+                    let detected = self.detect_language(&audio_features)?;
+                    first_detected.get_or_insert(detected);
+                    let prompt = self.prompt(detected, task, &context)?;
+                    (detected.to_string(), self.decode(&audio_features, &prompt)?)
+                }
+            };
+            // Whisper's own test for a window with nobody talking
+            if window.no_speech_prob > m::NO_SPEECH_THRESHOLD && window.avg_logprob() < m::LOGPROB_THRESHOLD {
+                seek += frames;
+                on_progress(seek as f64 / content_frames as f64)?;
+                continue;
+            }
+            // Translations are stored without word timings
+            let times = match task {
+                Task::Transcribe => Some(self.token_times(&audio_features, &window, &window_language, frames)?),
+                Task::Translate => None,
+            };
+            let (found, covered) = self.segments(&window, times.as_deref(), offset, frames)?;
+            segments.extend(found.into_iter().map(|segment| TranscriptionSegment { language: Some(window_language.clone()), ..segment }));
+            seek += covered;
+            on_progress(seek as f64 / content_frames as f64)?;
+        }
+        let language = fixed
+            .or_else(|| main_language(&segments))
+            .or_else(|| first_detected.map(str::to_string))
+            .unwrap_or_else(|| "en".to_string());
+
+        let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ");
+        let confidence = match segments.is_empty() {
+            true => 0.0,
+            false => segments.iter().map(|segment| segment.confidence).sum::<f32>() / segments.len() as f32,
+        };
+        Ok(TranscriptionResult {
+            text,
+            segments,
+            language,
+            processing_time_ms: started.elapsed().as_millis() as u64,
+            confidence,
+        })
+    }
+}
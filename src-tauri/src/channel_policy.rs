@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use crate::audio_capture::{self, AudioCapture};
+use crate::database::Database;
+
+// The most channels a selection can name, as many as capture takes
+const MAX_CHANNEL: u16 = 32;
+
+// Which of a device's channels are captured, set per device in the capture
+// settings. Devices without one are captured with every channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "mode", content = "channels")]
+pub enum ChannelPolicy {
+    // Mono from the first channel
+    Left,
+    // Mono from the second channel
+    Right,
+    // Mono, the mean of every channel
+    Average,
+    // These channels, numbered from 1, in this order
+    Select(Vec<u16>),
+}
+
+impl ChannelPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if let ChannelPolicy::Select(channels) = self {
+            if channels.is_empty() {
+                return Err("A channel selection needs at least one channel".to_string());
+            }
+            if channels.iter().any(|channel| !(1..=MAX_CHANNEL).contains(channel)) {
+                return Err(format!("Selected channels must be between 1 and {}", MAX_CHANNEL));
+            }
+        }
+        Ok(())
+    }
+
+    // Channels the device has to send for the policy to apply, if it needs
+    // more than one
+    pub fn required_channels(&self) -> Option<u16> {
+        match self {
+            ChannelPolicy::Left | ChannelPolicy::Average => None,
+            ChannelPolicy::Right => Some(2),
+            ChannelPolicy::Select(channels) => channels.iter().max().copied().filter(|&channel| channel > 1),
+        }
+    }
+
+    pub fn output_channels(&self) -> u16 {
+        match self {
+            ChannelPolicy::Select(channels) => channels.len() as u16,
+            _ => 1,
+        }
+    }
+
+    // How the policy took a device's channels, for a recording's metadata
+    pub fn describe(&self, device_channels: u16) -> String {
+        match self {
+            ChannelPolicy::Left => format!("channel 1 of {}", device_channels),
+            ChannelPolicy::Right => format!("channel 2 of {}", device_channels),
+            ChannelPolicy::Average => format!("average of {} channels", device_channels),
+            ChannelPolicy::Select(channels) => {
+                let channels: Vec<String> = channels.iter().map(u16::to_string).collect();
+                format!("channels {} of {}", channels.join(", "), device_channels)
+            }
+        }
+    }
+}
+
+// A policy applied to the audio of a device sending `device_channels`
+#[derive(Debug, Clone)]
+pub struct ChannelMap {
+    policy: ChannelPolicy,
+    device_channels: usize,
+}
+
+impl ChannelMap {
+    // Fails when the device doesn't send a channel the policy takes
+    pub fn new(policy: ChannelPolicy, device_channels: u16) -> Result<Self, String> {
+        if let Some(required) = policy.required_channels().filter(|&required| required > device_channels) {
+            return Err(format!(
+                "The device sends {} channel{}, but its channel policy needs {}",
+                device_channels,
+                if device_channels == 1 { "" } else { "s" },
+                required
+            ));
+        }
+        Ok(ChannelMap { policy, device_channels: device_channels.max(1) as usize })
+    }
+
+    pub fn output_channels(&self) -> u16 {
+        self.policy.output_channels()
+    }
+
+    pub fn describe(&self) -> String {
+        self.policy.describe(self.device_channels as u16)
+    }
+
+    pub fn apply(&self, samples: &[f32]) -> Vec<f32> {
+        let frames = samples.chunks_exact(self.device_channels);
+        match &self.policy {
+            ChannelPolicy::Left => frames.map(|frame| frame[0]).collect(),
+            ChannelPolicy::Right => frames.map(|frame| frame[1]).collect(),
+            ChannelPolicy::Average => frames.map(|frame| frame.iter().sum::<f32>() / frame.len() as f32).collect(),
+            ChannelPolicy::Select(channels) => {
+                let mut selected = Vec::with_capacity(samples.len() / self.device_channels * channels.len());
+                for frame in frames {
+                    selected.extend(channels.iter().map(|&channel| frame[channel as usize - 1]));
+                }
+                selected
+            }
+        }
+    }
+}
+
+// Sets which channels are captured from a device, or the default input
+// without one, and keeps it in the capture settings. No policy captures
+// every channel again. It applies the next time capture starts on the device.
+#[command]
+pub async fn set_channel_policy(
+    device_id: Option<String>,
+    policy: Option<ChannelPolicy>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<(), String> {
+    if let Some(policy) = policy.as_ref() {
+        policy.validate()?;
+    }
+    let device_id = match device_id {
+        Some(id) => id,
+        None => audio_capture::find_input_device(None)
+            .and_then(|device| Ok(cpal::traits::DeviceTrait::name(&device)?))
+            .map_err(|e| format!("Audio device error: {}", e))?,
+    };
+
+    let mut settings = capture.get_settings();
+    match policy {
+        Some(policy) => {
+            settings.channel_policies.insert(device_id, policy);
+        }
+        None => {
+            settings.channel_policies.remove(&device_id);
+        }
+    }
+    let database = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    capture.set_settings(settings, &database).map_err(|e| format!("Failed to save capture settings: {}", e))
+}
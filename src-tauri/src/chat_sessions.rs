@@ -0,0 +1,221 @@
+use tauri::{command, State};
+use crate::ai_models::{self, AdvancedAI, GenerationOptions, LlamaResponse};
+use crate::context_window::{self, ContextReport};
+use crate::database::{ChatMessage, ChatSession, Database, SessionSummary};
+use crate::tokenizer;
+
+// Only the most recent turns are replayed into the prompt
+const MAX_HISTORY_MESSAGES: usize = 20;
+
+const VALID_ROLES: [&str; 3] = ["system", "user", "assistant"];
+
+// Model used for session chat when the caller doesn't pick one
+const DEFAULT_SESSION_MODEL: &str = "llama3-8b";
+
+fn format_message(message: &ChatMessage) -> String {
+    let speaker = match message.role.as_str() {
+        "assistant" => "Dwight",
+        "system" => "System",
+        _ => "User",
+    };
+    format!("{}: {}\n", speaker, message.content)
+}
+
+pub fn build_session_prompt(system_prompt: &str, summary: Option<&str>, history: &[ChatMessage], user_input: &str) -> String {
+    let mut prompt = format!("{}\n\n", system_prompt);
+
+    if let Some(summary) = summary {
+        prompt.push_str(&format!("Summary of earlier conversation:\n{}\n\n", summary));
+    }
+
+    if !history.is_empty() {
+        prompt.push_str("Conversation so far:\n");
+        for message in history {
+            prompt.push_str(&format_message(message));
+        }
+        prompt.push('\n');
+    }
+
+    prompt.push_str(&format!("User: {}\nDwight:", user_input));
+    prompt
+}
+
+// Index of the first of the newest `max_messages` messages that fit in `available` tokens
+fn history_start(history: &[ChatMessage], max_messages: usize, available: usize) -> usize {
+    let recent_start = history.len().saturating_sub(max_messages);
+    let costs: Vec<usize> = history[recent_start..].iter()
+        .map(|message| tokenizer::count_tokens(&format_message(message)))
+        .collect();
+    recent_start + context_window::fit_newest(&costs, available)
+}
+
+fn open_db(app_handle: &tauri::AppHandle) -> Result<Database, String> {
+    Database::new(app_handle).map_err(|e| format!("Database error: {}", e))
+}
+
+fn require_session(db: &Database, session_id: i64) -> Result<ChatSession, String> {
+    db.get_chat_session(session_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Chat session {} not found", session_id))
+}
+
+#[command]
+pub async fn create_session(
+    title: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<ChatSession, String> {
+    let db = open_db(&app_handle)?;
+
+    let title = title
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| "New conversation".to_string());
+
+    let session_id = db.create_chat_session(&title).map_err(|e| format!("Database error: {}", e))?;
+    require_session(&db, session_id)
+}
+
+#[command]
+pub async fn append_message(
+    session_id: i64,
+    role: String,
+    content: String,
+    app_handle: tauri::AppHandle,
+) -> Result<i64, String> {
+    if !VALID_ROLES.contains(&role.as_str()) {
+        return Err(format!("Invalid role '{}'. Expected one of: {}", role, VALID_ROLES.join(", ")));
+    }
+
+    let db = open_db(&app_handle)?;
+    require_session(&db, session_id)?;
+
+    let message = ChatMessage {
+        id: None,
+        session_id,
+        role,
+        content,
+        created_at: String::new(), // Will be set by database
+    };
+
+    db.save_chat_message(&message).map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn list_sessions(app_handle: tauri::AppHandle) -> Result<Vec<ChatSession>, String> {
+    let db = open_db(&app_handle)?;
+
+    db.get_chat_sessions().map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn get_session_messages(
+    session_id: i64,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<ChatMessage>, String> {
+    let db = open_db(&app_handle)?;
+    require_session(&db, session_id)?;
+
+    db.get_chat_messages(session_id).map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn delete_session(session_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let db = open_db(&app_handle)?;
+
+    db.delete_chat_session(session_id).map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn get_session_summary(
+    session_id: i64,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<SessionSummary>, String> {
+    let db = open_db(&app_handle)?;
+    require_session(&db, session_id)?;
+
+    db.get_session_summary(session_id).map_err(|e| format!("Database error: {}", e))
+}
+
+// Sends one user turn within a session: the prompt is built from the running
+// summary and the recent history, and both the user message and Dwight's reply
+// are appended to it.
+#[allow(clippy::too_many_arguments)]
+#[command]
+pub async fn session_chat(
+    session_id: i64,
+    user_input: String,
+    model: Option<String>,
+    options: Option<GenerationOptions>,
+    stream: Option<bool>,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<LlamaResponse, String> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+
+    let (history, stored_summary) = {
+        let db = open_db(&app_handle)?;
+        require_session(&db, session_id)?;
+        let history = db.get_chat_messages(session_id).map_err(|e| format!("Database error: {}", e))?;
+        let summary = db.get_session_summary(session_id).map_err(|e| format!("Database error: {}", e))?;
+        (history, summary)
+    };
+
+    let model = model.unwrap_or_else(|| DEFAULT_SESSION_MODEL.to_string());
+    let system_prompt = ai.system_prompts().active().prompt;
+    let active = ai_models::begin_request(&ai, request_id, "session_chat", &app_handle)?;
+
+    let mut report = ContextReport::new(context_window::prompt_budget(&ai.effective_options(&model, &options)));
+    let mut summarized_through = stored_summary.as_ref().map_or(0, |stored| stored.summarized_through);
+    let mut summary = stored_summary.map(|stored| stored.summary);
+
+    // Turns after the running summary are sent verbatim while they fit
+    let first = history.partition_point(|message| message.id.unwrap_or(0) <= summarized_through);
+    let fixed_tokens = tokenizer::count_tokens(&build_session_prompt(&system_prompt, summary.as_deref(), &[], &user_input));
+    let mut start = first + history_start(&history[first..], MAX_HISTORY_MESSAGES, report.budget_tokens.saturating_sub(fixed_tokens));
+
+    if start > first {
+        // Fold the overflow into the summary. Only about half the window is kept
+        // verbatim, so the next few turns fit without summarizing again.
+        let base_tokens = tokenizer::count_tokens(&build_session_prompt(&system_prompt, None, &[], &user_input));
+        let available = report.budget_tokens.saturating_sub(base_tokens + context_window::SUMMARY_RESERVE_TOKENS) / 2;
+        start = first + history_start(&history[first..], MAX_HISTORY_MESSAGES / 2, available);
+
+        let lines: Vec<String> = history[first..start].iter().map(format_message).collect();
+        match active.run(context_window::summarize(&ai, &model, options.local_only, summary.as_deref(), &lines)).await {
+            Ok(text) => {
+                summarized_through = history[start - 1].id.unwrap_or(summarized_through);
+                open_db(&app_handle)?
+                    .save_session_summary(session_id, &text, summarized_through)
+                    .map_err(|e| format!("Database error: {}", e))?;
+                summary = Some(text);
+            }
+            Err(e) if active.is_cancelled() => return Err(format!("Session chat error: {}", e)),
+            Err(e) => eprintln!("Failed to update the session summary, dropping older turns: {}", e),
+        }
+    }
+    report.dropped_messages = start;
+    report.summarized_messages = history.partition_point(|message| message.id.unwrap_or(0) <= summarized_through);
+
+    let prompt = build_session_prompt(&system_prompt, summary.as_deref(), &history[start..], &user_input);
+    report.prompt_tokens = tokenizer::count_tokens(&prompt);
+
+    let mut response = ai_models::query_maybe_streaming(&ai, &prompt, &model, &options, stream.unwrap_or(false), &active, &app_handle)
+        .await
+        .map_err(|e| format!("Session chat error: {}", e))?;
+    response.context = Some(report);
+
+    let db = open_db(&app_handle)?;
+    for (role, content) in [("user", user_input), ("assistant", response.text.clone())] {
+        let message = ChatMessage {
+            id: None,
+            session_id,
+            role: role.to_string(),
+            content,
+            created_at: String::new(),
+        };
+        db.save_chat_message(&message).map_err(|e| format!("Database error: {}", e))?;
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use mp3lame_encoder::{Bitrate, Builder, FlushNoGap, Id3Tag, InterleavedPcm, MonoPcm, Quality};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+use crate::audio_capture::{self, AudioCapture, StreamFormat};
+use crate::audio_file::{self, WavOutput};
+use crate::database::{AudioRecord, Database};
+use crate::resampler::{FormatConverter, ResampleQuality};
+use crate::timestretch::{TimeStretcher, MAX_STRETCH_SPEED, MIN_STRETCH_SPEED};
+
+// Constant bitrate for MP3 clips; plenty for speech and small enough to share
+const MP3_BITRATE: Bitrate = Bitrate::Kbps192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipFormat {
+    Wav,
+    Flac,
+    // For people who can't open the others
+    Mp3,
+}
+
+impl ClipFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ClipFormat::Wav => "wav",
+            ClipFormat::Flac => "flac",
+            ClipFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedClip {
+    pub record_id: i64,
+    pub file_path: String,
+    pub format: ClipFormat,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    // Playing speed the clip was stretched to, at its own pitch
+    pub speed: f32,
+}
+
+pub fn clips_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = audio_capture::recordings_dir(app_handle)?.join("clips");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Changes the speed of the clip without changing its pitch, unless it's 1.0
+fn stretcher(format: StreamFormat, speed: f32) -> Option<TimeStretcher> {
+    (speed != 1.0).then(|| TimeStretcher::new(format, speed))
+}
+
+pub fn write_wav(source: &Path, output_path: &Path, start_seconds: f64, end_seconds: f64, speed: f32, lossless_int: bool) -> Result<()> {
+    let mut spec = audio_file::audio_spec(source)?;
+    // FLAC only stores integer samples
+    if lossless_int && spec.sample_format == hound::SampleFormat::Float {
+        spec.sample_format = hound::SampleFormat::Int;
+        spec.bits_per_sample = 24;
+    }
+    let mut stretcher = stretcher(audio_file::spec_format(&spec), speed);
+    let mut output = WavOutput::create(output_path, spec)?;
+    audio_file::for_each_chunk_between(source, start_seconds, end_seconds, |chunk| match stretcher.as_mut() {
+        Some(stretcher) => output.write(&stretcher.process(chunk)),
+        None => output.write(chunk),
+    })?;
+    if let Some(stretcher) = stretcher.as_mut() {
+        output.write(&stretcher.flush())?;
+    }
+    output.finish()
+}
+
+// MP3 holds at most two channels, so wider recordings keep the first two.
+// LAME takes the recording's own rate and picks the nearest MP3 one.
+fn write_mp3(
+    source: &Path,
+    output_path: &Path,
+    start_seconds: f64,
+    end_seconds: f64,
+    speed: f32,
+    record: &AudioRecord,
+    quality: ResampleQuality,
+) -> Result<()> {
+    let (format, _) = audio_file::audio_info(source)?;
+    let channels = format.channels.clamp(1, 2);
+    let mp3_error = |e: &dyn std::fmt::Debug| anyhow::anyhow!("MP3 encoder error: {:?}", e);
+
+    let mut builder = Builder::new().ok_or_else(|| anyhow::anyhow!("Failed to start the MP3 encoder"))?;
+    builder.set_num_channels(channels as u8)?;
+    builder.set_sample_rate(format.sample_rate)?;
+    builder.set_brate(MP3_BITRATE)?;
+    builder.set_quality(Quality::NearBest)?;
+    let year = record.created_at.get(..4).unwrap_or_default();
+    let comment = format!("Recorded {}", record.created_at);
+    builder.set_id3_tag(Id3Tag {
+        title: record.title.as_bytes(),
+        artist: b"",
+        album: b"",
+        album_art: &[],
+        year: year.as_bytes(),
+        comment: comment.as_bytes(),
+    }).map_err(|e| mp3_error(&e))?;
+    let mut encoder = builder.build()?;
+
+    let encoded = StreamFormat { sample_rate: format.sample_rate, channels };
+    let mut converter = FormatConverter::new(format, encoded, quality);
+    let mut stretcher = stretcher(encoded, speed);
+    let mut file = BufWriter::new(File::create(output_path)?);
+    let mut mp3 = Vec::new();
+    let mut encode = |samples: Vec<f32>, file: &mut BufWriter<File>| -> Result<()> {
+        mp3.clear();
+        mp3.reserve(mp3lame_encoder::max_required_buffer_size(samples.len()));
+        if channels == 1 {
+            encoder.encode_to_vec(MonoPcm(samples.as_slice()), &mut mp3)?;
+        } else {
+            encoder.encode_to_vec(InterleavedPcm(samples.as_slice()), &mut mp3)?;
+        }
+        file.write_all(&mp3)?;
+        Ok(())
+    };
+    audio_file::for_each_chunk_between(source, start_seconds, end_seconds, |chunk| {
+        let samples = converter.convert(chunk);
+        match stretcher.as_mut() {
+            Some(stretcher) => encode(stretcher.process(&samples), &mut file),
+            None => encode(samples, &mut file),
+        }
+    })?;
+    if let Some(stretcher) = stretcher.as_mut() {
+        encode(stretcher.flush(), &mut file)?;
+    }
+    mp3.clear();
+    mp3.reserve(mp3lame_encoder::max_required_buffer_size(0));
+    encoder.flush_to_vec::<FlushNoGap>(&mut mp3)?;
+    file.write_all(&mp3)?;
+    file.flush()?;
+    Ok(())
+}
+
+// Writes the part of a recording between two times to its own file in the
+// clips folder; the recording itself is untouched. A `speed` other than 1.0
+// slows the clip down or speeds it up without changing its pitch.
+#[command]
+pub async fn export_clip(
+    id: i64,
+    start: f64,
+    end: f64,
+    format: ClipFormat,
+    speed: Option<f32>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<ExportedClip, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (_, duration) = audio_file::audio_info(&source).map_err(|e| format!("Export error: {}", e))?;
+    let end = end.min(duration);
+    if start < 0.0 || start >= end {
+        return Err(format!("Clip must start before it ends, within the recording's {:.1} seconds", duration));
+    }
+    let speed = speed.unwrap_or(1.0);
+    if !(MIN_STRETCH_SPEED..=MAX_STRETCH_SPEED).contains(&speed) {
+        return Err(format!("speed must be between {} and {}", MIN_STRETCH_SPEED, MAX_STRETCH_SPEED));
+    }
+
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let rate = if speed == 1.0 { String::new() } else { format!("-{}x", speed) };
+    let name = format!("{}-clip-{:.1}s-{:.1}s{}.{}", stem, start, end, rate, format.extension());
+    let output_path = clips_dir(&app_handle).map_err(|e| format!("Export error: {}", e))?.join(name);
+    let quality = capture.get_settings().resample_quality;
+
+    let written = output_path.clone();
+    tokio::task::spawn_blocking(move || match format {
+        ClipFormat::Wav => write_wav(&source, &written, start, end, speed, false),
+        ClipFormat::Flac => {
+            let wav_path = written.with_extension("wav");
+            write_wav(&source, &wav_path, start, end, speed, true)?;
+            let encoded = audio_file::encode_flac(&wav_path);
+            let _ = std::fs::remove_file(&wav_path);
+            encoded.map(|_| ())
+        }
+        ClipFormat::Mp3 => write_mp3(&source, &written, start, end, speed, &record, quality),
+    })
+    .await
+    .map_err(|e| format!("Export error: {}", e))?
+    .map_err(|e| format!("Export error: {}", e))?;
+
+    Ok(ExportedClip {
+        record_id: id,
+        file_path: output_path.to_string_lossy().to_string(),
+        format,
+        start_seconds: start,
+        end_seconds: end,
+        speed,
+    })
+}
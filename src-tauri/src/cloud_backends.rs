@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use crate::llm_backend::{read_stream_lines, transient, BackendOutput, GenerationRequest, LlmBackend};
+
+// Used when a cloud model config leaves api_endpoint empty
+pub const OPENAI_ENDPOINT: &str = "https://api.openai.com/v1/chat/completions";
+pub const ANTHROPIC_ENDPOINT: &str = "https://api.anthropic.com/v1/messages";
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// The Messages API requires max_tokens on every request
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 1024;
+
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+const STREAM_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// The stored API key is a bearer token like for every other endpoint;
+// Anthropic wants it in x-api-key instead, along with the API version
+pub fn anthropic_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut converted = headers.clone();
+
+    if let Some(key) = converted.remove(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")).map(str::to_string))
+    {
+        if let Ok(mut value) = HeaderValue::from_str(&key) {
+            value.set_sensitive(true);
+            converted.entry(HeaderName::from_static("x-api-key")).or_insert(value);
+        }
+    }
+    converted.insert(HeaderName::from_static("anthropic-version"), HeaderValue::from_static(ANTHROPIC_VERSION));
+    converted
+}
+
+pub struct AnthropicBackend {
+    client: reqwest::Client,
+}
+
+impl AnthropicBackend {
+    pub fn new(client: reqwest::Client) -> Self {
+        AnthropicBackend { client }
+    }
+
+    fn payload(request: &GenerationRequest<'_>, stream: bool) -> serde_json::Value {
+        let mut messages = vec![serde_json::json!({ "role": "user", "content": request.prompt })];
+        // No JSON mode in the Messages API; starting the reply with "{" keeps it to an object
+        if request.options.json.unwrap_or(false) {
+            messages.push(serde_json::json!({ "role": "assistant", "content": "{" }));
+        }
+
+        // Only temperature is sent: newer models reject temperature and top_p together,
+        // and the API caps temperature at 1.0
+        serde_json::json!({
+            "model": request.model,
+            "messages": messages,
+            "stream": stream,
+            "max_tokens": request.options.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+            "temperature": request.options.temperature.map(|t| t.min(1.0)),
+        })
+    }
+
+    async fn send(&self, request: &GenerationRequest<'_>, stream: bool) -> Result<reqwest::Response> {
+        let mut builder = self.client
+            .post(request.endpoint)
+            .headers(request.headers.clone())
+            .json(&Self::payload(request, stream));
+
+        if !stream {
+            builder = builder.timeout(REQUEST_TIMEOUT);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| transient(format!("Failed to connect to Anthropic at {}: {}", request.endpoint, e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("Anthropic returned error status: {} for model '{}': {}", status, request.model, body);
+            // 429 (rate limited) and 529 (overloaded) clear up on their own, so they're retried
+            let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+            return Err(if retryable { transient(message) } else { anyhow::anyhow!(message) });
+        }
+
+        Ok(response)
+    }
+
+    fn prefill(request: &GenerationRequest<'_>) -> &'static str {
+        if request.options.json.unwrap_or(false) { "{" } else { "" }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for AnthropicBackend {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+
+        let blocks = result["content"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Invalid Anthropic response: missing content"))?;
+        let text: String = blocks.iter().filter_map(|block| block["text"].as_str()).collect();
+
+        Ok(BackendOutput {
+            text: format!("{}{}", Self::prefill(request), text),
+            prompt_tokens: result["usage"]["input_tokens"].as_u64(),
+            completion_tokens: result["usage"]["output_tokens"].as_u64(),
+            // The API doesn't expose token probabilities
+            token_logprobs: Vec::new(),
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        let mut response = self.send(request, true).await?;
+        let mut output = BackendOutput::default();
+
+        let prefill = Self::prefill(request);
+        if !prefill.is_empty() {
+            output.text.push_str(prefill);
+            on_token(prefill);
+        }
+
+        // Server-sent events; the event type is repeated in each data object
+        read_stream_lines(&mut response, STREAM_IDLE_TIMEOUT, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(());
+            };
+
+            let value: serde_json::Value = serde_json::from_str(data)
+                .map_err(|e| anyhow::anyhow!("Failed to parse Anthropic stream: {}", e))?;
+
+            match value["type"].as_str() {
+                Some("message_start") => {
+                    output.prompt_tokens = value["message"]["usage"]["input_tokens"].as_u64();
+                }
+                Some("content_block_delta") => {
+                    if let Some(token) = value["delta"]["text"].as_str() {
+                        if !token.is_empty() {
+                            output.text.push_str(token);
+                            on_token(token);
+                        }
+                    }
+                }
+                Some("message_delta") => {
+                    output.completion_tokens = value["usage"]["output_tokens"].as_u64();
+                }
+                Some("error") => {
+                    let message = value["error"]["message"].as_str().unwrap_or("unknown error");
+                    return Err(anyhow::anyhow!("Anthropic stream error: {}", message));
+                }
+                _ => {}
+            }
+            Ok(())
+        }).await?;
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use crate::ai_models::{AdvancedAI, GenerationOptions};
+use crate::prompt_templates::CONVERSATION_SUMMARY_TEMPLATE;
+use crate::tokenizer;
+
+// Window assumed when the model config doesn't set num_ctx
+pub const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+
+// Below this a document is more noise than context, so it's dropped instead
+const MIN_DOCUMENT_TOKENS: usize = 64;
+
+// The "[N] " prefix the RAG template puts in front of each document
+const DOCUMENT_OVERHEAD_TOKENS: usize = 6;
+
+// Room kept free for the summary of turns that didn't fit
+pub const SUMMARY_RESERVE_TOKENS: usize = 256;
+const SUMMARY_MAX_TOKENS: u32 = 200;
+
+// What was left out to make a prompt fit the model's window
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextReport {
+    // Window minus the tokens reserved for the reply
+    pub budget_tokens: usize,
+    pub prompt_tokens: usize,
+    // Oldest session turns left out of the prompt
+    pub dropped_messages: usize,
+    // Turns covered by the session's running summary
+    pub summarized_messages: usize,
+    // Indexes into the context documents
+    pub truncated_documents: Vec<usize>,
+    pub dropped_documents: Vec<usize>,
+    // Documents left out for being less similar to the query than the threshold
+    #[serde(default)]
+    pub below_threshold: Vec<ScoredDocument>,
+    // Paraphrases of the question that were searched as well
+    #[serde(default)]
+    pub expanded_queries: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredDocument {
+    // Index into the context documents
+    pub document: usize,
+    // Set for chunks from the vector store
+    pub chunk_id: Option<i64>,
+    pub similarity: f32,
+}
+
+impl ContextReport {
+    pub fn new(budget_tokens: usize) -> Self {
+        ContextReport {
+            budget_tokens,
+            ..Default::default()
+        }
+    }
+}
+
+// Tokens the prompt may use with these (resolved) options
+pub fn prompt_budget(options: &GenerationOptions) -> usize {
+    let window = options.runtime.num_ctx.map(|n| n as usize).unwrap_or(DEFAULT_CONTEXT_TOKENS);
+    window.saturating_sub(options.max_tokens.unwrap_or(0) as usize)
+}
+
+// Index of the first item to keep so that the newest items fit in `available`
+pub fn fit_newest(costs: &[usize], available: usize) -> usize {
+    let mut used = 0;
+    let mut start = costs.len();
+    for (index, &cost) in costs.iter().enumerate().rev() {
+        if used + cost > available {
+            break;
+        }
+        used += cost;
+        start = index;
+    }
+    start
+}
+
+// Largest per-document size at which all documents fit, or None if they fit as they are
+fn document_cap(costs: &[usize], available: usize) -> Option<usize> {
+    let mut sorted = costs.to_vec();
+    sorted.sort_unstable();
+
+    let mut remaining = available;
+    for (index, &cost) in sorted.iter().enumerate() {
+        let left = sorted.len() - index;
+        if cost * left > remaining {
+            return Some(remaining / left);
+        }
+        remaining -= cost;
+    }
+    None
+}
+
+// Shares `available` tokens between documents, which come in order of relevance.
+// Short documents stay whole and long ones are cut to an even share; if that
+// share gets too small, the least relevant documents are dropped.
+pub fn fit_documents(documents: Vec<String>, available: usize, report: &mut ContextReport) -> Vec<String> {
+    let costs: Vec<usize> = documents.iter()
+        .map(|doc| tokenizer::count_tokens(doc) + DOCUMENT_OVERHEAD_TOKENS)
+        .collect();
+
+    let mut kept = documents.len();
+    let cap = loop {
+        match document_cap(&costs[..kept], available) {
+            Some(cap) if cap < MIN_DOCUMENT_TOKENS && kept > 0 => kept -= 1,
+            cap => break cap,
+        }
+    };
+    report.dropped_documents = (kept..documents.len()).collect();
+
+    documents.into_iter()
+        .take(kept)
+        .enumerate()
+        .map(|(index, doc)| match cap {
+            Some(cap) if costs[index] > cap => {
+                report.truncated_documents.push(index);
+                format!("{}…", tokenizer::truncate_to_tokens(&doc, cap.saturating_sub(DOCUMENT_OVERHEAD_TOKENS)))
+            }
+            _ => doc,
+        })
+        .collect()
+}
+
+// Folds conversation lines into the running summary. When even the summary
+// prompt would overflow, only the most recent lines are included.
+pub async fn summarize(ai: &AdvancedAI, model: &str, local_only: Option<bool>, previous: Option<&str>, lines: &[String]) -> Result<String> {
+    let options = GenerationOptions {
+        temperature: Some(0.2),
+        max_tokens: Some(SUMMARY_MAX_TOKENS),
+        local_only,
+        ..Default::default()
+    };
+    let budget = prompt_budget(&ai.effective_options(model, &options));
+
+    let templates = ai.prompt_templates();
+    let overhead = tokenizer::count_tokens(&templates.render(CONVERSATION_SUMMARY_TEMPLATE, serde_json::json!({
+        "summary": previous,
+        "conversation": "",
+    }))?);
+
+    let costs: Vec<usize> = lines.iter().map(|line| tokenizer::count_tokens(line)).collect();
+    let start = fit_newest(&costs, budget.saturating_sub(overhead));
+
+    let prompt = templates.render(CONVERSATION_SUMMARY_TEMPLATE, serde_json::json!({
+        "summary": previous,
+        "conversation": lines[start..].concat(),
+    }))?;
+
+    let response = ai.query_llama(&prompt, model, &options).await?;
+    Ok(response.text.trim().to_string())
+}
@@ -0,0 +1,173 @@
+use tauri::{command, State};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use crate::ai_models::AdvancedAI;
+use crate::database::Database;
+
+// Keychain service name; each model's credentials are stored under its model id
+const KEYCHAIN_SERVICE: &str = "dwight-ai-endpoints";
+
+// Secrets for an authenticated endpoint (nginx proxy, hosted inference).
+// Never persisted in the database and never sent back to the frontend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EndpointCredentials {
+    pub bearer_token: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl EndpointCredentials {
+    pub fn to_header_map(&self) -> Result<HeaderMap, String> {
+        let mut map = HeaderMap::new();
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("Invalid header name '{}'", name))?;
+            let mut value = HeaderValue::from_str(value.trim())
+                .map_err(|_| format!("Invalid value for header '{}'", name))?;
+            value.set_sensitive(true);
+            map.insert(name, value);
+        }
+
+        if let Some(token) = self.bearer_token.as_deref().filter(|t| !t.trim().is_empty()) {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token.trim()))
+                .map_err(|_| "Bearer token contains invalid characters".to_string())?;
+            value.set_sensitive(true);
+            map.insert(AUTHORIZATION, value);
+        }
+
+        Ok(map)
+    }
+}
+
+// What the UI may know about stored credentials
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialsInfo {
+    pub model: String,
+    pub has_bearer_token: bool,
+    pub header_names: Vec<String>,
+}
+
+// Keychain-backed credentials with an in-memory cache of the resulting headers,
+// so requests don't hit the keychain every time
+pub struct CredentialStore {
+    headers: RwLock<HashMap<String, HeaderMap>>,
+}
+
+impl CredentialStore {
+    pub fn new() -> Self {
+        CredentialStore {
+            headers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn entry(model: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(KEYCHAIN_SERVICE, model)
+            .map_err(|e| anyhow::anyhow!("Keychain unavailable: {}", e))
+    }
+
+    fn load(model: &str) -> Result<Option<EndpointCredentials>> {
+        match Self::entry(model)?.get_password() {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Failed to read credentials for '{}' from keychain: {}", model, e)),
+        }
+    }
+
+    pub fn headers(&self, model: &str) -> Result<HeaderMap> {
+        if let Some(headers) = self.headers.read().ok().and_then(|cache| cache.get(model).cloned()) {
+            return Ok(headers);
+        }
+
+        let headers = match Self::load(model)? {
+            Some(credentials) => credentials.to_header_map().map_err(|e| anyhow::anyhow!(e))?,
+            None => HeaderMap::new(),
+        };
+        if let Ok(mut cache) = self.headers.write() {
+            cache.insert(model.to_string(), headers.clone());
+        }
+        Ok(headers)
+    }
+
+    pub fn info(&self, model: &str) -> Result<CredentialsInfo> {
+        let credentials = Self::load(model)?.unwrap_or_default();
+        Ok(CredentialsInfo {
+            model: model.to_string(),
+            has_bearer_token: credentials.bearer_token.is_some(),
+            header_names: credentials.headers.keys().cloned().collect(),
+        })
+    }
+
+    pub fn save(&self, model: &str, credentials: &EndpointCredentials) -> Result<()> {
+        let headers = credentials.to_header_map().map_err(|e| anyhow::anyhow!(e))?;
+
+        Self::entry(model)?
+            .set_password(&serde_json::to_string(credentials)?)
+            .map_err(|e| anyhow::anyhow!("Failed to store credentials in keychain: {}", e))?;
+
+        if let Ok(mut cache) = self.headers.write() {
+            cache.insert(model.to_string(), headers);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, model: &str) -> Result<()> {
+        match Self::entry(model)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(anyhow::anyhow!("Failed to remove credentials from keychain: {}", e)),
+        }
+
+        if let Ok(mut cache) = self.headers.write() {
+            cache.remove(model);
+        }
+        Ok(())
+    }
+}
+
+#[command]
+pub async fn set_model_credentials(
+    model: String,
+    credentials: EndpointCredentials,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<CredentialsInfo, String> {
+    let mut config = ai.model_config(&model)
+        .ok_or_else(|| format!("Model '{}' not configured", model))?;
+    credentials.to_header_map()?;
+
+    ai.credentials().save(&model, &credentials).map_err(|e| e.to_string())?;
+
+    if !config.authenticated {
+        config.authenticated = true;
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+        ai.set_model_config(&model, config, &db)
+            .map_err(|e| format!("Failed to save model config: {}", e))?;
+    }
+
+    ai.credentials().info(&model).map_err(|e| e.to_string())
+}
+
+#[command]
+pub async fn get_model_credentials(model: String, ai: State<'_, AdvancedAI>) -> Result<CredentialsInfo, String> {
+    ai.credentials().info(&model).map_err(|e| e.to_string())
+}
+
+#[command]
+pub async fn clear_model_credentials(
+    model: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    ai.credentials().delete(&model).map_err(|e| e.to_string())?;
+
+    if let Some(mut config) = ai.model_config(&model).filter(|config| config.authenticated) {
+        config.authenticated = false;
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+        ai.set_model_config(&model, config, &db)
+            .map_err(|e| format!("Failed to save model config: {}", e))?;
+    }
+    Ok(())
+}
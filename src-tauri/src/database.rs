@@ -1,5 +1,7 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result, Transaction, TransactionBehavior};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
 use tauri::Manager;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,77 +27,1008 @@ pub struct DwightMemory {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SoundTrigger {
     pub id: Option<i32>,
-    pub trigger_type: String, // "sound" or "speech"
+    pub trigger_type: String, // "sound", "speech" or "speaker", an enrolled speaker's name
     pub trigger_value: String,
     pub is_active: bool,
     pub created_at: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: Option<i64>,
+    pub title: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// Running summary of a session's older turns, which are no longer sent verbatim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: i64,
+    pub summary: String,
+    // Id of the newest message the summary covers
+    pub summarized_through: i64,
+    pub updated_at: String,
+}
+
+// Another file derived from a recording, kept next to the original
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioVersion {
+    pub id: Option<i64>,
+    pub record_id: i64,
+    pub kind: String, // e.g. "denoised"
+    pub file_path: String,
+    pub created_at: String,
+}
+
+// One part of a recording split at its silences, itself a recording of its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingSegment {
+    pub record_id: i64,
+    // The whole recording the segment was cut from
+    pub parent_id: i64,
+    // From 0, in order through the parent for the parts of a split; clips
+    // extracted later are numbered after them
+    pub segment_index: i64,
+    // Where the segment lies in the parent, in seconds
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+// How and where a recording was captured, noted when it is saved so it can
+// be documented later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingMetadata {
+    pub record_id: i64,
+    // Every device that contributed audio, in order
+    pub devices: Vec<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // "mono", "stereo", or the sources of each channel when they have tracks
+    pub channel_layout: String,
+    // Automatic gain control's target and limit, when it was on
+    pub agc_target_db: Option<f32>,
+    pub agc_max_gain_db: Option<f32>,
+    pub app_version: String,
+    // Entered by whoever made the recording
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    // When the recording started, as RFC 3339
+    pub captured_at: String,
+}
+
+// What the last quality analysis of a recording found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingQuality {
+    pub record_id: i64,
+    pub clipped_samples: i64,
+    pub clipping_count: i64,
+    pub dropout_count: i64,
+    pub discontinuity_count: i64,
+    // Whether any of them were found, so the audio can't be taken as captured cleanly
+    pub compromised: bool,
+    pub checked_at: String,
+}
+
+// A dialed digit heard in a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DtmfDigit {
+    // 0-9, *, # or A-D
+    pub digit: String,
+    // In seconds into the file
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+// Ultrasonic or infrasonic activity in a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InaudibleEvent {
+    // "ultrasonic" or "infrasonic"
+    pub band: String,
+    // In seconds into the file
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    // Loudest level of the band during the event, in dBFS
+    pub peak_db: f32,
+}
+
+// One timed line of a recording's transcript, as transcription produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptLine {
+    // In seconds into the file
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub text: String,
+    pub confidence: f32,
+    // Empty for transcripts from before words were timed
+    #[serde(default)]
+    pub words: Vec<TranscriptWord>,
+    // Language code detected for the line, so code-switched audio keeps
+    // track of which language each part is in. None when it wasn't detected.
+    #[serde(default)]
+    pub language: Option<String>,
+    // Who spoke the line, numbered from 1 by diarization in order of first
+    // speaking. None until the recording is diarized.
+    #[serde(default)]
+    pub speaker: Option<u32>,
+    // Mean log-probability of the line's tokens, for transcripts from after
+    // it was kept; confidence is its exp
+    #[serde(default)]
+    pub avg_logprob: Option<f64>,
+}
+
+// A recording's transcript after post-processing, kept beside the verbatim
+// one, which it never replaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanTranscript {
+    pub record_id: i64,
+    pub text: String,
+    // The cleaned text of each of the recording's transcript lines, in order
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A find/replace applied to clean transcripts, as typed rather than a regex
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplacementRule {
+    pub id: Option<i64>,
+    pub find: String,
+    pub replacement: String,
+    pub whole_word: bool,
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A transcript line worth listening to again, as Whisper wasn't sure of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LowConfidenceLine {
+    pub record_id: i64,
+    pub title: String,
+    pub line_index: i64,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub text: String,
+    pub confidence: f32,
+    pub avg_logprob: Option<f64>,
+}
+
+// What a transcript search is narrowed to; everything when empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranscriptSearchFilters {
+    pub record_id: Option<i64>,
+    // The line's detected language, else the recording's
+    pub language: Option<String>,
+    // Name of the enrolled speaker the line was attributed to, in any case
+    pub speaker: Option<String>,
+    // Recordings made at or after, and before, these RFC 3339 times or dates
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub min_confidence: Option<f32>,
+}
+
+// A transcript or translation line a search found, best first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptMatch {
+    pub record_id: i64,
+    pub title: String,
+    pub created_at: String,
+    pub line_index: i64,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub text: String,
+    // The text around the match, with matched words in <mark></mark>
+    pub snippet: String,
+    pub speaker: Option<String>,
+    // BM25 relevance; higher is better
+    pub score: f64,
+    // Whether the line is from the recording's English translation
+    pub translated: bool,
+}
+
+// The English translation of a recording's transcript, from Whisper's
+// translate task. Its lines carry no word timings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptTranslation {
+    pub record_id: i64,
+    // What the recording was spoken in
+    pub source_language: String,
+    pub text: String,
+    pub lines: Vec<TranscriptLine>,
+}
+
+// One word of a transcript line, with its punctuation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptWord {
+    // In seconds into the file
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub word: String,
+    pub confidence: f32,
+}
+
+// What one channel of a recording holds, when its sources were recorded as
+// tracks of their own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingTrack {
+    pub record_id: i64,
+    pub channel: i64,
+    // "microphone" or "system_audio"
+    pub source: String,
+    pub device: String,
+}
+
+// Chromaprint fingerprint of a recording's audio file
+#[derive(Debug, Clone)]
+pub struct AudioFingerprint {
+    pub record_id: i64,
+    // Size and modification time of the file it was taken from, in seconds
+    // since the epoch; a file that changed needs a new fingerprint
+    pub source_len: i64,
+    pub source_modified: i64,
+    pub fingerprint: Vec<u32>,
+}
+
+// A name or term transcription should expect to hear, such as a street
+// name or product code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VocabularyTerm {
+    pub id: Option<i64>,
+    pub term: String,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A known voice, enrolled from sample clips of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrolledSpeaker {
+    pub id: Option<i64>,
+    pub name: String,
+    // The voice print lines are compared with
+    #[serde(skip_serializing, default)]
+    pub embedding: Vec<f32>,
+    // The speaker-embedding model that took the print; only lines embedded
+    // by the same model are compared with it
+    #[serde(default)]
+    pub model: String,
+    // How much speech the print was taken from
+    pub sample_seconds: f64,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A transcript line taken to be spoken by an enrolled speaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeakerAttribution {
+    pub record_id: i64,
+    pub line_index: i64,
+    pub speaker_id: i64,
+    pub speaker_name: String,
+    // Cosine similarity of the line's voice to the print, 0 to 1
+    pub confidence: f32,
+}
+
+// A recording waiting for, or given, a batch transcription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionJob {
+    pub id: i64,
+    pub record_id: i64,
+    pub model_size: String,
+    pub language: Option<String>,
+    // The translate_to_english setting when the job runs without it
+    pub translate: Option<bool>,
+    // "queued", "running", "paused", "completed", "failed" or "cancelled"
+    pub status: String,
+    // 0 to 1, of the job as a whole
+    pub progress: f64,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+// A recording's transcript as one transcription produced it, kept when the
+// recording is transcribed again so it can be compared or put back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptVersion {
+    pub id: Option<i64>,
+    pub record_id: i64,
+    // "whisper" for the built-in model, "whisper.cpp" for the external one
+    pub engine: String,
+    // The model size, e.g. "base" or "large-v3"
+    pub model: String,
+    // What else it was transcribed with, e.g. the language asked for and
+    // the custom vocabulary
+    pub settings: serde_json::Value,
+    pub text: String,
+    pub language: Option<String>,
+    pub lines: Vec<TranscriptLine>,
+    // The version a rollback put back, which this one is a copy of
+    pub restored_from: Option<i64>,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A transcript kept beside the recording's own, e.g. from another engine or
+// model, or in another language, without replacing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptTrack {
+    pub id: Option<i64>,
+    pub record_id: i64,
+    pub name: String,
+    // As with transcript versions
+    pub engine: String,
+    pub model: String,
+    pub language: Option<String>,
+    // An English translation rather than what was said
+    pub translated: bool,
+    pub text: String,
+    pub lines: Vec<TranscriptLine>,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A recurring window in which a recording runs by itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingSchedule {
+    pub id: Option<i64>,
+    pub name: String,
+    // Days a window starts on; one that ends after midnight runs on into the next day
+    pub days: Vec<chrono::Weekday>,
+    // Local times as HH:MM; an end before the start is on the next day
+    pub start_time: String,
+    pub end_time: String,
+    // The default input without it
+    pub device_id: Option<String>,
+    pub enabled: bool,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A folder new audio files are imported from as they appear, such as where
+// a dashcam or handheld recorder is emptied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchFolder {
+    pub id: Option<i64>,
+    pub path: String,
+    // Also transcribe what is imported
+    pub transcribe: bool,
+    pub enabled: bool,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+// A file found in a watch folder, imported or not. Files are only imported
+// once; one that changes afterwards is imported again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedFile {
+    pub source_path: String,
+    // Size and modification time in seconds since the epoch when imported
+    pub source_len: i64,
+    pub source_modified: i64,
+    pub folder_id: Option<i64>,
+    // None when the import failed, or its recording was deleted since
+    pub record_id: Option<i64>,
+    pub error: Option<String>,
+    pub imported_at: String,
+}
+
+// A recording being written by the capture thread. It is removed once the
+// recording is saved, so one still here at startup was cut off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingSession {
+    pub file_path: String,
+    pub title: String,
+    // The interrupted recording this one is appended to when it stops
+    pub resumes_record_id: Option<i64>,
+    // How long capture was stopped before this session resumed it
+    pub gap_seconds: f64,
+    pub started_at: String,
+}
+
+// A recording recovered after being cut off, which can be carried on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumableRecording {
+    pub record_id: i64,
+    pub title: String,
+    pub duration: f64,
+    // When the last audio reached the file, as RFC 3339
+    pub interrupted_at: String,
+}
+
+// Where a resumed recording carries on, and how long capture was stopped there
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingGap {
+    pub record_id: i64,
+    // In seconds into the file
+    pub at_seconds: f64,
+    pub gap_seconds: f64,
+}
+
+// A recording whose transcript chunks in the RAG index are out of date
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptChange {
+    // Grows with every queued change, so a change queued while the old one
+    // was being processed isn't cleared with it
+    pub id: i64,
+    pub recording_id: i64,
+    pub change: String, // "upsert" or "delete"
+    // JSON segments from transcription, for chunks with timestamps
+    pub segments: Option<String>,
+    pub queued_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: Option<i64>,
+    pub session_id: i64,
+    pub role: String, // "system", "user" or "assistant"
+    pub content: String,
+    pub created_at: String,
+}
+
 pub struct Database {
     connection: Connection,
 }
 
-impl Database {
-    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self> {
-        let app_data_path = app_handle.path().app_data_dir()
-            .map_err(|e| rusqlite::Error::SqliteFailure(
-                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
-                Some(format!("Failed to get app data directory: {}", e))
-            ))?;
-        
-        std::fs::create_dir_all(&app_data_path).map_err(|e| rusqlite::Error::SqliteFailure(
+// A change to the schema. Each is applied once, in order, and PRAGMA
+// user_version holds the last one applied. One that has shipped is never
+// edited; a change, ALTER TABLE included, goes in a new one.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    sql: &'static str,
+}
+
+// Every table as it was when migrations began. IF NOT EXISTS throughout, as
+// databases from before then already have most of them.
+const BASELINE_SCHEMA: &str = "
+    -- Audio records table
+    CREATE TABLE IF NOT EXISTS audio_records (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        file_path TEXT NOT NULL,
+        transcript TEXT,
+        duration REAL NOT NULL,
+        created_at TEXT NOT NULL,
+        triggers TEXT
+    );
+
+    -- Dwight's memory/conversation history
+    CREATE TABLE IF NOT EXISTS dwight_memory (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        context TEXT NOT NULL,
+        response TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        user_input TEXT NOT NULL
+    );
+
+    -- Sound and speech triggers
+    CREATE TABLE IF NOT EXISTS sound_triggers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        trigger_type TEXT NOT NULL,
+        trigger_value TEXT NOT NULL,
+        is_active BOOLEAN NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL
+    );
+
+    -- Multi-turn chat sessions and their messages
+    CREATE TABLE IF NOT EXISTS chat_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS chat_messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        session_id INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS chat_session_summaries (
+        session_id INTEGER PRIMARY KEY,
+        summary TEXT NOT NULL,
+        summarized_through INTEGER NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+
+    -- Application settings, stored as JSON values by key
+    CREATE TABLE IF NOT EXISTS app_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+
+    -- Recordings waiting to be re-indexed for RAG, at most one change each
+    CREATE TABLE IF NOT EXISTS transcript_index_changes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        recording_id INTEGER NOT NULL UNIQUE,
+        change TEXT NOT NULL,
+        segments TEXT,
+        queued_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS audio_versions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        record_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        file_path TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE(record_id, kind)
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_segments (
+        record_id INTEGER PRIMARY KEY,
+        parent_id INTEGER NOT NULL,
+        segment_index INTEGER NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_quality (
+        record_id INTEGER PRIMARY KEY,
+        clipped_samples INTEGER NOT NULL,
+        clipping_count INTEGER NOT NULL,
+        dropout_count INTEGER NOT NULL,
+        discontinuity_count INTEGER NOT NULL,
+        compromised INTEGER NOT NULL,
+        checked_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS audio_fingerprints (
+        record_id INTEGER PRIMARY KEY,
+        source_len INTEGER NOT NULL,
+        source_modified INTEGER NOT NULL,
+        fingerprint BLOB NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS dtmf_digits (
+        record_id INTEGER NOT NULL,
+        digit_index INTEGER NOT NULL,
+        digit TEXT NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL,
+        PRIMARY KEY (record_id, digit_index)
+    );
+
+    CREATE TABLE IF NOT EXISTS inaudible_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        record_id INTEGER NOT NULL,
+        band TEXT NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL,
+        peak_db REAL NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_tracks (
+        record_id INTEGER NOT NULL,
+        channel INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        device TEXT NOT NULL,
+        PRIMARY KEY (record_id, channel)
+    );
+
+    -- An INTEGER PRIMARY KEY, unlike an implicit rowid, is never renumbered
+    -- by VACUUM, so the search index can join back to the lines by it
+    CREATE TABLE IF NOT EXISTS transcript_lines (
+        id INTEGER PRIMARY KEY,
+        record_id INTEGER NOT NULL,
+        line_index INTEGER NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL,
+        text TEXT NOT NULL,
+        confidence REAL NOT NULL,
+        language TEXT,
+        speaker INTEGER,
+        avg_logprob REAL,
+        UNIQUE (record_id, line_index)
+    );
+
+    -- Full-text index over the line text, kept in sync by triggers
+    CREATE VIRTUAL TABLE IF NOT EXISTS transcript_lines_fts USING fts5(
+        text, content='transcript_lines', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS transcript_lines_fts_insert AFTER INSERT ON transcript_lines BEGIN
+        INSERT INTO transcript_lines_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS transcript_lines_fts_delete AFTER DELETE ON transcript_lines BEGIN
+        INSERT INTO transcript_lines_fts (transcript_lines_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS transcript_lines_fts_update AFTER UPDATE OF text ON transcript_lines BEGIN
+        INSERT INTO transcript_lines_fts (transcript_lines_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        INSERT INTO transcript_lines_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+
+    CREATE TABLE IF NOT EXISTS transcript_words (
+        record_id INTEGER NOT NULL,
+        line_index INTEGER NOT NULL,
+        word_index INTEGER NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL,
+        word TEXT NOT NULL,
+        confidence REAL NOT NULL,
+        PRIMARY KEY (record_id, line_index, word_index)
+    );
+
+    CREATE TABLE IF NOT EXISTS vocabulary_terms (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        term TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS enrolled_speakers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        embedding BLOB NOT NULL,
+        model TEXT NOT NULL,
+        sample_seconds REAL NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS speaker_attributions (
+        record_id INTEGER NOT NULL,
+        line_index INTEGER NOT NULL,
+        speaker_id INTEGER NOT NULL,
+        confidence REAL NOT NULL,
+        PRIMARY KEY (record_id, line_index)
+    );
+
+    CREATE TABLE IF NOT EXISTS transcript_translations (
+        record_id INTEGER PRIMARY KEY,
+        source_language TEXT NOT NULL,
+        text TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS translation_lines (
+        id INTEGER PRIMARY KEY,
+        record_id INTEGER NOT NULL,
+        line_index INTEGER NOT NULL,
+        start_seconds REAL NOT NULL,
+        end_seconds REAL NOT NULL,
+        text TEXT NOT NULL,
+        confidence REAL NOT NULL,
+        UNIQUE (record_id, line_index)
+    );
+
+    -- Indexed as the transcript lines are, so translations are searched too
+    CREATE VIRTUAL TABLE IF NOT EXISTS translation_lines_fts USING fts5(
+        text, content='translation_lines', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS translation_lines_fts_insert AFTER INSERT ON translation_lines BEGIN
+        INSERT INTO translation_lines_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS translation_lines_fts_delete AFTER DELETE ON translation_lines BEGIN
+        INSERT INTO translation_lines_fts (translation_lines_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS translation_lines_fts_update AFTER UPDATE OF text ON translation_lines BEGIN
+        INSERT INTO translation_lines_fts (translation_lines_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        INSERT INTO translation_lines_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+
+    CREATE TABLE IF NOT EXISTS clean_transcripts (
+        record_id INTEGER PRIMARY KEY,
+        text TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS clean_transcript_lines (
+        record_id INTEGER NOT NULL,
+        line_index INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        PRIMARY KEY (record_id, line_index)
+    );
+
+    CREATE TABLE IF NOT EXISTS replacement_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        find TEXT NOT NULL,
+        replacement TEXT NOT NULL,
+        whole_word INTEGER NOT NULL,
+        case_sensitive INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS transcription_jobs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        record_id INTEGER NOT NULL,
+        model_size TEXT NOT NULL,
+        language TEXT,
+        translate INTEGER,
+        status TEXT NOT NULL,
+        progress REAL NOT NULL DEFAULT 0,
+        error TEXT,
+        created_at TEXT NOT NULL,
+        started_at TEXT,
+        finished_at TEXT
+    );
+
+    -- Settings and lines are JSON, as they were when the version was made
+    CREATE TABLE IF NOT EXISTS transcript_versions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        record_id INTEGER NOT NULL,
+        engine TEXT NOT NULL,
+        model TEXT NOT NULL,
+        settings TEXT NOT NULL,
+        text TEXT NOT NULL,
+        language TEXT,
+        lines TEXT NOT NULL,
+        restored_from INTEGER,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS transcript_tracks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        record_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        engine TEXT NOT NULL,
+        model TEXT NOT NULL,
+        language TEXT,
+        translated INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        lines TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    -- The language most of a recording's transcript is in
+    CREATE TABLE IF NOT EXISTS recording_languages (
+        record_id INTEGER PRIMARY KEY,
+        language TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS watch_folders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        path TEXT NOT NULL UNIQUE,
+        transcribe INTEGER NOT NULL,
+        enabled INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS imported_files (
+        source_path TEXT PRIMARY KEY,
+        source_len INTEGER NOT NULL,
+        source_modified INTEGER NOT NULL,
+        folder_id INTEGER,
+        record_id INTEGER,
+        error TEXT,
+        imported_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_sessions (
+        file_path TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        resumes_record_id INTEGER,
+        gap_seconds REAL NOT NULL,
+        started_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS resumable_recordings (
+        record_id INTEGER PRIMARY KEY,
+        interrupted_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_gaps (
+        record_id INTEGER NOT NULL,
+        at_seconds REAL NOT NULL,
+        gap_seconds REAL NOT NULL,
+        PRIMARY KEY (record_id, at_seconds)
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_metadata (
+        record_id INTEGER PRIMARY KEY,
+        devices TEXT NOT NULL,
+        sample_rate INTEGER NOT NULL,
+        channels INTEGER NOT NULL,
+        channel_layout TEXT NOT NULL,
+        agc_target_db REAL,
+        agc_max_gain_db REAL,
+        app_version TEXT NOT NULL,
+        location TEXT,
+        notes TEXT,
+        captured_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recording_schedules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        days TEXT NOT NULL,
+        start_time TEXT NOT NULL,
+        end_time TEXT NOT NULL,
+        device_id TEXT,
+        enabled INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    -- Per-recording lookups
+    CREATE INDEX IF NOT EXISTS idx_transcript_versions_record ON transcript_versions (record_id, id);
+    CREATE INDEX IF NOT EXISTS idx_transcript_tracks_record ON transcript_tracks (record_id, id);
+    CREATE INDEX IF NOT EXISTS idx_transcription_jobs_status ON transcription_jobs (status, id);
+";
+
+const MIGRATIONS: [Migration; 1] = [
+    Migration {
+        version: 1,
+        name: "Baseline schema",
+        sql: BASELINE_SCHEMA,
+    },
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+// Copies of the database from before a migration, newest kept
+const MAX_SCHEMA_BACKUPS: usize = 5;
+const SCHEMA_BACKUP_PREFIX: &str = "dwight-schema-v";
+
+// Connections are opened on many threads at once; one migrates at a time
+static MIGRATING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub name: String,
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaStatus {
+    pub version: u32,
+    // What this build of the app migrates to
+    pub latest: u32,
+    pub migrations: Vec<MigrationStatus>,
+    // Copies taken before migrating, newest first
+    pub backups: Vec<String>,
+}
+
+fn schema_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR), Some(message))
+}
+
+fn schema_backups_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = database_path(app_handle)?.with_file_name("backups");
+    std::fs::create_dir_all(&dir).map_err(|e| schema_error(format!("Failed to create the database backups folder: {}", e)))?;
+    Ok(dir)
+}
+
+// Backups taken before migrating, newest first
+pub fn schema_backups(app_handle: &tauri::AppHandle) -> Result<Vec<PathBuf>> {
+    let dir = schema_backups_dir(app_handle)?;
+    let mut backups: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(&dir)
+        .map_err(|e| schema_error(format!("Failed to read the database backups folder: {}", e)))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(SCHEMA_BACKUP_PREFIX))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    backups.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+// FTS5 query matching lines with all of the query's words. "Quoted words"
+// stay together as a phrase and a trailing * matches any ending; the rest is
+// quoted so punctuation isn't read as query syntax.
+fn search_query(query: &str) -> String {
+    let mut terms = Vec::new();
+    for (index, part) in query.split('"').enumerate() {
+        let phrase = index % 2 == 1;
+        let words: Vec<&str> = match phrase {
+            true => vec![part],
+            false => part.split_whitespace().collect(),
+        };
+        for word in words {
+            let prefix = !phrase && word.ends_with('*');
+            let word = word.split_whitespace().collect::<Vec<_>>().join(" ");
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+            if word.is_empty() {
+                continue;
+            }
+            terms.push(format!("\"{}\"{}", word, if prefix { "*" } else { "" }));
+        }
+    }
+    terms.join(" ")
+}
+
+// Location of dwight.db, creating the app data directory if needed
+pub fn database_path(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf> {
+    let app_data_path = app_handle.path().app_data_dir()
+        .map_err(|e| rusqlite::Error::SqliteFailure(
             rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
-            Some(format!("Failed to create app data directory: {}", e))
+            Some(format!("Failed to get app data directory: {}", e))
         ))?;
-        
-        let db_path = app_data_path.join("dwight.db");
-        let connection = Connection::open(db_path)?;
+    
+    std::fs::create_dir_all(&app_data_path).map_err(|e| rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+        Some(format!("Failed to create app data directory: {}", e))
+    ))?;
+    
+    Ok(app_data_path.join("dwight.db"))
+}
+
+impl Database {
+    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self> {
+        let connection = Connection::open(database_path(app_handle)?)?;
         
         let db = Database { connection };
-        db.initialize_tables()?;
+        let version = db.schema_version()?;
+        if version > SCHEMA_VERSION {
+            return Err(schema_error(format!(
+                "The database is at schema version {}, newer than this version of the app knows ({}); update the app",
+                version, SCHEMA_VERSION
+            )));
+        }
+        // Anything already there is someone's data, copied aside before it's changed
+        let existing = version < SCHEMA_VERSION
+            && db.connection.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'", [], |row| row.get::<_, i64>(0))? > 0;
+        if version < SCHEMA_VERSION {
+            db.migrate(app_handle, existing)?;
+        }
         Ok(db)
     }
 
-    fn initialize_tables(&self) -> Result<()> {
-        // Audio records table
-        self.connection.execute(
-            "CREATE TABLE IF NOT EXISTS audio_records (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                title TEXT NOT NULL,
-                file_path TEXT NOT NULL,
-                transcript TEXT,
-                duration REAL NOT NULL,
-                created_at TEXT NOT NULL,
-                triggers TEXT
-            )",
-            [],
-        )?;
+    pub fn schema_version(&self) -> Result<u32> {
+        self.connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+    }
 
-        // Dwight's memory/conversation history
-        self.connection.execute(
-            "CREATE TABLE IF NOT EXISTS dwight_memory (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                context TEXT NOT NULL,
-                response TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                user_input TEXT NOT NULL
-            )",
-            [],
-        )?;
+    // Applies the migrations not yet applied, all or none, after backing the
+    // database up when it had data
+    fn migrate(&self, app_handle: &tauri::AppHandle, backup: bool) -> Result<()> {
+        let _migrating = MIGRATING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Another connection may have migrated while this one waited
+        let from = self.schema_version()?;
+        if from >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        let backup = match backup {
+            true => Some(self.backup_schema(app_handle, from)?),
+            false => None,
+        };
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        for migration in MIGRATIONS.iter().filter(|migration| migration.version > from) {
+            transaction.execute_batch(migration.sql).map_err(|e| schema_error(format!(
+                "Database migration {} ({}) failed, so none were applied: {}{}",
+                migration.version,
+                migration.name,
+                e,
+                backup.as_ref().map(|path| format!("; a copy from before is at {}", path.display())).unwrap_or_default()
+            )))?;
+            transaction.pragma_update(None, "user_version", migration.version)?;
+        }
+        transaction.commit()?;
+        println!("Migrated the database schema from version {} to {}", from, SCHEMA_VERSION);
+        Ok(())
+    }
 
-        // Sound and speech triggers
-        self.connection.execute(
-            "CREATE TABLE IF NOT EXISTS sound_triggers (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                trigger_type TEXT NOT NULL,
-                trigger_value TEXT NOT NULL,
-                is_active BOOLEAN NOT NULL DEFAULT 1,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )?;
+    // A consistent copy of the database as it is, then only the newest few
+    // copies are kept
+    fn backup_schema(&self, app_handle: &tauri::AppHandle, version: u32) -> Result<PathBuf> {
+        let name = format!("{}{}-{}.db", SCHEMA_BACKUP_PREFIX, version, chrono::Utc::now().format("%Y%m%dT%H%M%SZ"));
+        let path = schema_backups_dir(app_handle)?.join(name);
+        self.connection.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+        for old in schema_backups(app_handle)?.into_iter().skip(MAX_SCHEMA_BACKUPS) {
+            if let Err(e) = std::fs::remove_file(&old) {
+                eprintln!("Failed to remove old database backup {}: {}", old.display(), e);
+            }
+        }
+        println!("Backed up the database to {} before migrating", path.display());
+        Ok(path)
+    }
 
-        Ok(())
+    pub fn schema_status(&self, app_handle: &tauri::AppHandle) -> Result<SchemaStatus> {
+        let version = self.schema_version()?;
+        Ok(SchemaStatus {
+            version,
+            latest: SCHEMA_VERSION,
+            migrations: MIGRATIONS.iter()
+                .map(|migration| MigrationStatus {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                    applied: migration.version <= version,
+                })
+                .collect(),
+            backups: schema_backups(app_handle)?.iter().map(|path| path.to_string_lossy().to_string()).collect(),
+        })
     }
 
     pub fn save_audio_record(&self, record: &AudioRecord) -> Result<i64> {
@@ -130,11 +1063,152 @@ impl Database {
         self.connection.execute(
             "INSERT INTO sound_triggers (trigger_type, trigger_value, is_active, created_at)
              VALUES (?1, ?2, ?3, ?4)",
-            [&trigger.trigger_type, &trigger.trigger_value, &trigger.is_active.to_string(), &now],
+            rusqlite::params![trigger.trigger_type, trigger.trigger_value, trigger.is_active, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    // Returns false when there is no such recording
+    pub fn update_audio_triggers(&self, record_id: i64, triggers: Option<&str>) -> Result<bool> {
+        let updated = self.connection.execute(
+            "UPDATE audio_records SET triggers = ?1 WHERE id = ?2",
+            rusqlite::params![triggers, record_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // Returns false when there is no such recording
+    pub fn update_audio_transcript(&self, record_id: i64, transcript: &str) -> Result<bool> {
+        let updated = self.connection.execute(
+            "UPDATE audio_records SET transcript = ?1 WHERE id = ?2",
+            rusqlite::params![transcript, record_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // For a recording whose audio was replaced, e.g. by appending to it.
+    // Returns false when there is no such recording.
+    pub fn update_audio_file(&self, record_id: i64, file_path: &str, duration: f64, triggers: Option<&str>) -> Result<bool> {
+        let updated = self.connection.execute(
+            "UPDATE audio_records SET file_path = ?1, duration = ?2, triggers = ?3 WHERE id = ?4",
+            rusqlite::params![file_path, duration, triggers, record_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // Returns false when there is no such recording
+    pub fn delete_audio_record(&self, record_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM audio_records WHERE id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM audio_versions WHERE record_id = ?1", [record_id])?;
+        // The segments of a deleted parent stay, as recordings of their own
+        self.connection.execute("DELETE FROM recording_segments WHERE record_id = ?1 OR parent_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM recording_quality WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM audio_fingerprints WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM dtmf_digits WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM inaudible_events WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM recording_tracks WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM transcript_lines WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM transcript_words WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM speaker_attributions WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM recording_languages WHERE record_id = ?1", [record_id])?;
+        self.delete_transcript_translation(record_id)?;
+        self.delete_clean_transcript(record_id)?;
+        self.connection.execute("DELETE FROM transcription_jobs WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM transcript_versions WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM transcript_tracks WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM resumable_recordings WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM recording_gaps WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM recording_metadata WHERE record_id = ?1", [record_id])?;
+        // Kept, so the watcher doesn't import the file again
+        self.connection.execute("UPDATE imported_files SET record_id = NULL WHERE record_id = ?1", [record_id])?;
+        Ok(deleted > 0)
+    }
+
+    // Replaces the recording's previous version of the same kind
+    pub fn save_audio_version(&self, record_id: i64, kind: &str, file_path: &str) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO audio_versions (record_id, kind, file_path, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![record_id, kind, file_path, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get_audio_versions(&self, record_id: i64) -> Result<Vec<AudioVersion>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, record_id, kind, file_path, created_at FROM audio_versions WHERE record_id = ?1 ORDER BY id"
+        )?;
+        let versions = stmt.query_map([record_id], |row| {
+            Ok(AudioVersion {
+                id: Some(row.get(0)?),
+                record_id: row.get(1)?,
+                kind: row.get(2)?,
+                file_path: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        versions.collect()
+    }
+
+    pub fn save_recording_segment(&self, segment: &RecordingSegment) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_segments (record_id, parent_id, segment_index, start_seconds, end_seconds)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![segment.record_id, segment.parent_id, segment.segment_index, segment.start_seconds, segment.end_seconds],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_segments(&self, parent_id: i64) -> Result<Vec<RecordingSegment>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT record_id, parent_id, segment_index, start_seconds, end_seconds FROM recording_segments
+             WHERE parent_id = ?1 ORDER BY segment_index"
+        )?;
+        let segments = stmt.query_map([parent_id], |row| {
+            Ok(RecordingSegment {
+                record_id: row.get(0)?,
+                parent_id: row.get(1)?,
+                segment_index: row.get(2)?,
+                start_seconds: row.get(3)?,
+                end_seconds: row.get(4)?,
+            })
+        })?;
+        segments.collect()
+    }
+
+    // Replaces any change still queued for the recording, since only the latest state matters
+    pub fn queue_transcript_change(&self, recording_id: i64, change: &str, segments: Option<&str>) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO transcript_index_changes (recording_id, change, segments, queued_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![recording_id, change, segments, now],
         )?;
         Ok(self.connection.last_insert_rowid())
     }
 
+    pub fn pending_transcript_changes(&self) -> Result<Vec<TranscriptChange>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, recording_id, change, segments, queued_at FROM transcript_index_changes ORDER BY id"
+        )?;
+        let changes = stmt.query_map([], |row| {
+            Ok(TranscriptChange {
+                id: row.get(0)?,
+                recording_id: row.get(1)?,
+                change: row.get(2)?,
+                segments: row.get(3)?,
+                queued_at: row.get(4)?,
+            })
+        })?;
+        changes.collect()
+    }
+
+    pub fn clear_transcript_change(&self, change_id: i64) -> Result<()> {
+        self.connection.execute("DELETE FROM transcript_index_changes WHERE id = ?1", [change_id])?;
+        Ok(())
+    }
+
     pub fn get_all_audio_records(&self) -> Result<Vec<AudioRecord>> {
         let mut stmt = self.connection.prepare(
             "SELECT id, title, file_path, transcript, duration, created_at, triggers FROM audio_records ORDER BY created_at DESC"
@@ -159,6 +1233,68 @@ impl Database {
         Ok(records)
     }
 
+    // Every file a recording or one of its versions is stored in
+    pub fn get_audio_file_paths(&self) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT file_path FROM audio_records UNION SELECT file_path FROM audio_versions"
+        )?;
+        let paths = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>>>()?;
+        Ok(paths)
+    }
+
+    pub fn get_audio_record(&self, record_id: i64) -> Result<Option<AudioRecord>> {
+        self.connection.query_row(
+            "SELECT id, title, file_path, transcript, duration, created_at, triggers FROM audio_records WHERE id = ?1",
+            [record_id],
+            |row| {
+                Ok(AudioRecord {
+                    id: Some(row.get(0)?),
+                    title: row.get(1)?,
+                    file_path: row.get(2)?,
+                    transcript: row.get::<_, Option<String>>(3)?,
+                    duration: row.get(4)?,
+                    created_at: row.get(5)?,
+                    triggers: row.get::<_, Option<String>>(6)?,
+                })
+            },
+        ).optional()
+    }
+
+    // Case-insensitive substring match on title, transcript, its English
+    // translation and triggers. With a language, only recordings mostly in it
+    // or with a line in it.
+    pub fn search_audio_records(&self, query: &str, language: Option<&str>, limit: usize) -> Result<Vec<AudioRecord>> {
+        let pattern = format!("%{}%", query);
+        let mut stmt = self.connection.prepare(
+            "SELECT id, title, file_path, transcript, duration, created_at, triggers FROM audio_records
+             WHERE (title LIKE ?1 OR transcript LIKE ?1 OR triggers LIKE ?1
+                    OR id IN (SELECT record_id FROM transcript_translations WHERE text LIKE ?1))
+               AND (?3 IS NULL OR id IN (
+                   SELECT record_id FROM recording_languages WHERE language = ?3
+                   UNION SELECT record_id FROM transcript_lines WHERE language = ?3
+               ))
+             ORDER BY created_at DESC LIMIT ?2"
+        )?;
+
+        let record_iter = stmt.query_map(rusqlite::params![pattern, limit as i64, language], |row| {
+            Ok(AudioRecord {
+                id: Some(row.get(0)?),
+                title: row.get(1)?,
+                file_path: row.get(2)?,
+                transcript: row.get::<_, Option<String>>(3)?,
+                duration: row.get(4)?,
+                created_at: row.get(5)?,
+                triggers: row.get::<_, Option<String>>(6)?,
+            })
+        })?;
+
+        let mut records = Vec::new();
+        for record in record_iter {
+            records.push(record?);
+        }
+        Ok(records)
+    }
+
     pub fn get_dwight_memory_context(&self, limit: usize) -> Result<Vec<DwightMemory>> {
         let mut stmt = self.connection.prepare(
             "SELECT id, context, response, created_at, user_input FROM dwight_memory ORDER BY created_at DESC LIMIT ?1"
@@ -183,7 +1319,9 @@ impl Database {
 
     pub fn get_active_triggers(&self) -> Result<Vec<SoundTrigger>> {
         let mut stmt = self.connection.prepare(
-            "SELECT id, trigger_type, trigger_value, is_active, created_at FROM sound_triggers WHERE is_active = 1"
+            // Older rows stored the flag as the text 'true'
+            "SELECT id, trigger_type, trigger_value, is_active IN (1, 'true'), created_at FROM sound_triggers
+             WHERE is_active IN (1, 'true')"
         )?;
         
         let trigger_iter = stmt.query_map([], |row| {
@@ -202,4 +1340,1211 @@ impl Database {
         }
         Ok(triggers)
     }
-}
\ No newline at end of file
+
+    // Replaces the findings of any earlier analysis
+    pub fn save_recording_quality(&self, quality: &RecordingQuality) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_quality
+             (record_id, clipped_samples, clipping_count, dropout_count, discontinuity_count, compromised, checked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                quality.record_id,
+                quality.clipped_samples,
+                quality.clipping_count,
+                quality.dropout_count,
+                quality.discontinuity_count,
+                quality.compromised,
+                now,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_quality(&self, record_id: i64) -> Result<Option<RecordingQuality>> {
+        self.connection
+            .query_row(
+                "SELECT record_id, clipped_samples, clipping_count, dropout_count, discontinuity_count, compromised, checked_at
+                 FROM recording_quality WHERE record_id = ?1",
+                [record_id],
+                |row| {
+                    Ok(RecordingQuality {
+                        record_id: row.get(0)?,
+                        clipped_samples: row.get(1)?,
+                        clipping_count: row.get(2)?,
+                        dropout_count: row.get(3)?,
+                        discontinuity_count: row.get(4)?,
+                        compromised: row.get(5)?,
+                        checked_at: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn save_fingerprint(&self, fingerprint: &AudioFingerprint) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let bytes: Vec<u8> = fingerprint.fingerprint.iter().flat_map(|item| item.to_le_bytes()).collect();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO audio_fingerprints (record_id, source_len, source_modified, fingerprint, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![fingerprint.record_id, fingerprint.source_len, fingerprint.source_modified, bytes, now],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_fingerprint(&self, record_id: i64) -> Result<Option<AudioFingerprint>> {
+        self.connection
+            .query_row(
+                "SELECT record_id, source_len, source_modified, fingerprint FROM audio_fingerprints WHERE record_id = ?1",
+                [record_id],
+                |row| {
+                    let bytes: Vec<u8> = row.get(3)?;
+                    Ok(AudioFingerprint {
+                        record_id: row.get(0)?,
+                        source_len: row.get(1)?,
+                        source_modified: row.get(2)?,
+                        fingerprint: bytes
+                            .chunks_exact(4)
+                            .map(|item| u32::from_le_bytes([item[0], item[1], item[2], item[3]]))
+                            .collect(),
+                    })
+                },
+            )
+            .optional()
+    }
+
+    // Replaces the recording's logged digits
+    pub fn save_dtmf_digits(&self, record_id: i64, digits: &[DtmfDigit]) -> Result<()> {
+        self.connection.execute("DELETE FROM dtmf_digits WHERE record_id = ?1", [record_id])?;
+        for (index, digit) in digits.iter().enumerate() {
+            self.connection.execute(
+                "INSERT INTO dtmf_digits (record_id, digit_index, digit, start_seconds, end_seconds)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![record_id, index as i64, digit.digit, digit.start_seconds, digit.end_seconds],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_dtmf_digits(&self, record_id: i64) -> Result<Vec<DtmfDigit>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT digit, start_seconds, end_seconds FROM dtmf_digits WHERE record_id = ?1 ORDER BY digit_index"
+        )?;
+        let digits = stmt.query_map([record_id], |row| {
+            Ok(DtmfDigit {
+                digit: row.get(0)?,
+                start_seconds: row.get(1)?,
+                end_seconds: row.get(2)?,
+            })
+        })?;
+        digits.collect()
+    }
+
+    // Replaces the recording's ultrasonic and infrasonic events
+    pub fn save_inaudible_events(&self, record_id: i64, events: &[InaudibleEvent]) -> Result<()> {
+        self.connection.execute("DELETE FROM inaudible_events WHERE record_id = ?1", [record_id])?;
+        for event in events {
+            self.connection.execute(
+                "INSERT INTO inaudible_events (record_id, band, start_seconds, end_seconds, peak_db)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![record_id, event.band, event.start_seconds, event.end_seconds, event.peak_db],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_inaudible_events(&self, record_id: i64) -> Result<Vec<InaudibleEvent>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT band, start_seconds, end_seconds, peak_db FROM inaudible_events
+             WHERE record_id = ?1 ORDER BY start_seconds"
+        )?;
+        let events = stmt.query_map([record_id], |row| {
+            Ok(InaudibleEvent {
+                band: row.get(0)?,
+                start_seconds: row.get(1)?,
+                end_seconds: row.get(2)?,
+                peak_db: row.get(3)?,
+            })
+        })?;
+        events.collect()
+    }
+
+    // Replaces the recording's timed transcript lines and their words in one
+    // transaction, so a long transcript's thousands of rows are written at
+    // once and a failure leaves the old lines in place
+    pub fn save_transcript_lines(&self, record_id: i64, lines: &[TranscriptLine]) -> Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute("DELETE FROM transcript_lines WHERE record_id = ?1", [record_id])?;
+        transaction.execute("DELETE FROM transcript_words WHERE record_id = ?1", [record_id])?;
+        // Attributions are by line, so they go with the lines they were for
+        transaction.execute("DELETE FROM speaker_attributions WHERE record_id = ?1", [record_id])?;
+        for (index, line) in lines.iter().enumerate() {
+            transaction.prepare_cached(
+                "INSERT INTO transcript_lines (record_id, line_index, start_seconds, end_seconds, text, confidence, language, speaker, avg_logprob)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(rusqlite::params![
+                record_id,
+                index as i64,
+                line.start_seconds,
+                line.end_seconds,
+                line.text,
+                line.confidence,
+                line.language,
+                line.speaker,
+                line.avg_logprob,
+            ])?;
+            for (word_index, word) in line.words.iter().enumerate() {
+                transaction.prepare_cached(
+                    "INSERT INTO transcript_words (record_id, line_index, word_index, start_seconds, end_seconds, word, confidence)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?
+                .execute(rusqlite::params![record_id, index as i64, word_index as i64, word.start_seconds, word.end_seconds, word.word, word.confidence])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    // Puts `lines` in place of the `removed` lines from `first`, in one
+    // transaction. The lines after keep their rows, speakers and attributions,
+    // renumbered to follow the new ones.
+    pub fn replace_transcript_lines(&self, record_id: i64, first: usize, removed: usize, lines: &[TranscriptLine]) -> Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let (first, end, shift) = (first as i64, (first + removed) as i64, lines.len() as i64 - removed as i64);
+        for table in ["transcript_lines", "transcript_words", "speaker_attributions"] {
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE record_id = ?1 AND line_index >= ?2 AND line_index < ?3", table),
+                rusqlite::params![record_id, first, end],
+            )?;
+            // Through negative indexes, so no row takes another's place midway
+            transaction.execute(
+                &format!("UPDATE {} SET line_index = -1 - (line_index + ?3) WHERE record_id = ?1 AND line_index >= ?2", table),
+                rusqlite::params![record_id, end, shift],
+            )?;
+            transaction.execute(
+                &format!("UPDATE {} SET line_index = -1 - line_index WHERE record_id = ?1 AND line_index < 0", table),
+                [record_id],
+            )?;
+        }
+        for (offset, line) in lines.iter().enumerate() {
+            let index = first + offset as i64;
+            transaction.prepare_cached(
+                "INSERT INTO transcript_lines (record_id, line_index, start_seconds, end_seconds, text, confidence, language, speaker, avg_logprob)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(rusqlite::params![
+                record_id,
+                index,
+                line.start_seconds,
+                line.end_seconds,
+                line.text,
+                line.confidence,
+                line.language,
+                line.speaker,
+                line.avg_logprob,
+            ])?;
+            for (word_index, word) in line.words.iter().enumerate() {
+                transaction.prepare_cached(
+                    "INSERT INTO transcript_words (record_id, line_index, word_index, start_seconds, end_seconds, word, confidence)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?
+                .execute(rusqlite::params![record_id, index, word_index as i64, word.start_seconds, word.end_seconds, word.word, word.confidence])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    pub fn get_transcript_lines(&self, record_id: i64) -> Result<Vec<TranscriptLine>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT start_seconds, end_seconds, text, confidence, language, speaker, avg_logprob FROM transcript_lines
+             WHERE record_id = ?1 ORDER BY line_index"
+        )?;
+        let lines = stmt.query_map([record_id], |row| {
+            Ok(TranscriptLine {
+                start_seconds: row.get(0)?,
+                end_seconds: row.get(1)?,
+                text: row.get(2)?,
+                confidence: row.get(3)?,
+                words: Vec::new(),
+                language: row.get(4)?,
+                speaker: row.get(5)?,
+                avg_logprob: row.get(6)?,
+            })
+        })?;
+        let mut lines = lines.collect::<Result<Vec<_>>>()?;
+
+        let mut stmt = self.connection.prepare(
+            "SELECT line_index, start_seconds, end_seconds, word, confidence FROM transcript_words
+             WHERE record_id = ?1 ORDER BY line_index, word_index"
+        )?;
+        let words = stmt.query_map([record_id], |row| {
+            Ok((row.get::<_, i64>(0)?, TranscriptWord {
+                start_seconds: row.get(1)?,
+                end_seconds: row.get(2)?,
+                word: row.get(3)?,
+                confidence: row.get(4)?,
+            }))
+        })?;
+        for word in words {
+            let (line_index, word) = word?;
+            if let Some(line) = lines.get_mut(line_index as usize) {
+                line.words.push(word);
+            }
+        }
+
+        Ok(lines)
+    }
+
+    // Lines transcribed with less confidence than `below`, least sure first,
+    // in one recording or across all of them
+    pub fn get_low_confidence_lines(&self, record_id: Option<i64>, below: f32, limit: usize) -> Result<Vec<LowConfidenceLine>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT l.record_id, r.title, l.line_index, l.start_seconds, l.end_seconds, l.text, l.confidence, l.avg_logprob
+             FROM transcript_lines l
+             JOIN audio_records r ON r.id = l.record_id
+             WHERE l.confidence < ?1 AND (?2 IS NULL OR l.record_id = ?2)
+             ORDER BY l.confidence, l.record_id, l.line_index
+             LIMIT ?3"
+        )?;
+        let lines = stmt.query_map(rusqlite::params![below, record_id, limit as i64], |row| {
+            Ok(LowConfidenceLine {
+                record_id: row.get(0)?,
+                title: row.get(1)?,
+                line_index: row.get(2)?,
+                start_seconds: row.get(3)?,
+                end_seconds: row.get(4)?,
+                text: row.get(5)?,
+                confidence: row.get(6)?,
+                avg_logprob: row.get(7)?,
+            })
+        })?;
+        lines.collect()
+    }
+
+    // Lines whose text matches every word in the query, ranked by BM25
+    pub fn search_transcripts(&self, query: &str, filters: &TranscriptSearchFilters, limit: usize) -> Result<Vec<TranscriptMatch>> {
+        let query = search_query(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.connection.prepare(
+            "SELECT * FROM (
+                 SELECT l.record_id, r.title, r.created_at, l.line_index, l.start_seconds, l.end_seconds, l.text,
+                        snippet(transcript_lines_fts, 0, '<mark>', '</mark>', '…', 16), s.name, -bm25(transcript_lines_fts) AS score, 0
+                 FROM transcript_lines_fts
+                 JOIN transcript_lines l ON l.id = transcript_lines_fts.rowid
+                 JOIN audio_records r ON r.id = l.record_id
+                 LEFT JOIN recording_languages rl ON rl.record_id = l.record_id
+                 LEFT JOIN speaker_attributions a ON a.record_id = l.record_id AND a.line_index = l.line_index
+                 LEFT JOIN enrolled_speakers s ON s.id = a.speaker_id
+                 WHERE transcript_lines_fts MATCH ?1
+                   AND (?2 IS NULL OR l.record_id = ?2)
+                   AND (?3 IS NULL OR COALESCE(l.language, rl.language) = ?3)
+                   AND (?4 IS NULL OR s.name = ?4 COLLATE NOCASE)
+                   AND (?5 IS NULL OR r.created_at >= ?5)
+                   AND (?6 IS NULL OR r.created_at < ?6)
+                   AND (?7 IS NULL OR l.confidence >= ?7)
+                 UNION ALL
+                 -- Translations are English and their lines aren't attributed to speakers
+                 SELECT t.record_id, r.title, r.created_at, t.line_index, t.start_seconds, t.end_seconds, t.text,
+                        snippet(translation_lines_fts, 0, '<mark>', '</mark>', '…', 16), NULL, -bm25(translation_lines_fts) AS score, 1
+                 FROM translation_lines_fts
+                 JOIN translation_lines t ON t.id = translation_lines_fts.rowid
+                 JOIN audio_records r ON r.id = t.record_id
+                 WHERE translation_lines_fts MATCH ?1
+                   AND (?2 IS NULL OR t.record_id = ?2)
+                   AND (?3 IS NULL OR ?3 = 'en')
+                   AND ?4 IS NULL
+                   AND (?5 IS NULL OR r.created_at >= ?5)
+                   AND (?6 IS NULL OR r.created_at < ?6)
+                   AND (?7 IS NULL OR t.confidence >= ?7)
+             )
+             ORDER BY score DESC, created_at DESC, line_index
+             LIMIT ?8"
+        )?;
+        let matches = stmt.query_map(
+            rusqlite::params![
+                query,
+                filters.record_id,
+                filters.language,
+                filters.speaker,
+                filters.after,
+                filters.before,
+                filters.min_confidence,
+                limit as i64,
+            ],
+            |row| {
+                Ok(TranscriptMatch {
+                    record_id: row.get(0)?,
+                    title: row.get(1)?,
+                    created_at: row.get(2)?,
+                    line_index: row.get(3)?,
+                    start_seconds: row.get(4)?,
+                    end_seconds: row.get(5)?,
+                    text: row.get(6)?,
+                    snippet: row.get(7)?,
+                    speaker: row.get(8)?,
+                    score: row.get(9)?,
+                    translated: row.get(10)?,
+                })
+            },
+        )?;
+        matches.collect()
+    }
+
+    // Replaces the recording's translation and its lines
+    pub fn save_transcript_translation(&self, translation: &TranscriptTranslation) -> Result<()> {
+        self.delete_transcript_translation(translation.record_id)?;
+        self.connection.execute(
+            "INSERT INTO transcript_translations (record_id, source_language, text) VALUES (?1, ?2, ?3)",
+            rusqlite::params![translation.record_id, translation.source_language, translation.text],
+        )?;
+        for (index, line) in translation.lines.iter().enumerate() {
+            self.connection.execute(
+                "INSERT INTO translation_lines (record_id, line_index, start_seconds, end_seconds, text, confidence)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![translation.record_id, index as i64, line.start_seconds, line.end_seconds, line.text, line.confidence],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_transcript_translation(&self, record_id: i64) -> Result<Option<TranscriptTranslation>> {
+        let translation = self.connection.query_row(
+            "SELECT source_language, text FROM transcript_translations WHERE record_id = ?1",
+            [record_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        ).optional()?;
+        let Some((source_language, text)) = translation else {
+            return Ok(None);
+        };
+        let mut stmt = self.connection.prepare(
+            "SELECT start_seconds, end_seconds, text, confidence FROM translation_lines WHERE record_id = ?1 ORDER BY line_index"
+        )?;
+        let lines = stmt.query_map([record_id], |row| {
+            Ok(TranscriptLine {
+                start_seconds: row.get(0)?,
+                end_seconds: row.get(1)?,
+                text: row.get(2)?,
+                confidence: row.get(3)?,
+                words: Vec::new(),
+                language: Some("en".to_string()),
+                speaker: None,
+                avg_logprob: None,
+            })
+        })?;
+        Ok(Some(TranscriptTranslation { record_id, source_language, text, lines: lines.collect::<Result<Vec<_>>>()? }))
+    }
+
+    pub fn delete_transcript_translation(&self, record_id: i64) -> Result<()> {
+        self.connection.execute("DELETE FROM transcript_translations WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM translation_lines WHERE record_id = ?1", [record_id])?;
+        Ok(())
+    }
+
+    // Replaces the recording's clean transcript and its lines
+    pub fn save_clean_transcript(&self, cleaned: &CleanTranscript) -> Result<()> {
+        self.delete_clean_transcript(cleaned.record_id)?;
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO clean_transcripts (record_id, text, created_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![cleaned.record_id, cleaned.text, now],
+        )?;
+        for (index, text) in cleaned.lines.iter().enumerate() {
+            self.connection.execute(
+                "INSERT INTO clean_transcript_lines (record_id, line_index, text) VALUES (?1, ?2, ?3)",
+                rusqlite::params![cleaned.record_id, index as i64, text],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_clean_transcript(&self, record_id: i64) -> Result<Option<CleanTranscript>> {
+        let cleaned = self.connection.query_row(
+            "SELECT text, created_at FROM clean_transcripts WHERE record_id = ?1",
+            [record_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        ).optional()?;
+        let Some((text, created_at)) = cleaned else {
+            return Ok(None);
+        };
+        let mut stmt = self.connection.prepare(
+            "SELECT text FROM clean_transcript_lines WHERE record_id = ?1 ORDER BY line_index"
+        )?;
+        let lines = stmt.query_map([record_id], |row| row.get(0))?;
+        Ok(Some(CleanTranscript { record_id, text, lines: lines.collect::<Result<Vec<_>>>()?, created_at }))
+    }
+
+    pub fn delete_clean_transcript(&self, record_id: i64) -> Result<()> {
+        self.connection.execute("DELETE FROM clean_transcripts WHERE record_id = ?1", [record_id])?;
+        self.connection.execute("DELETE FROM clean_transcript_lines WHERE record_id = ?1", [record_id])?;
+        Ok(())
+    }
+
+    pub fn save_replacement_rule(&self, rule: &ReplacementRule) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO replacement_rules (find, replacement, whole_word, case_sensitive, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![rule.find, rule.replacement, rule.whole_word, rule.case_sensitive, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    // In the order they were added, which is the order they apply in
+    pub fn get_replacement_rules(&self) -> Result<Vec<ReplacementRule>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, find, replacement, whole_word, case_sensitive, created_at FROM replacement_rules ORDER BY id"
+        )?;
+        let rules = stmt.query_map([], |row| {
+            Ok(ReplacementRule {
+                id: Some(row.get(0)?),
+                find: row.get(1)?,
+                replacement: row.get(2)?,
+                whole_word: row.get(3)?,
+                case_sensitive: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?;
+        rules.collect()
+    }
+
+    pub fn delete_replacement_rule(&self, rule_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM replacement_rules WHERE id = ?1", [rule_id])?;
+        Ok(deleted > 0)
+    }
+
+    // A term already added, in any case, keeps its id
+    pub fn save_vocabulary_term(&self, term: &str) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR IGNORE INTO vocabulary_terms (term, created_at) VALUES (?1, ?2)",
+            rusqlite::params![term, now],
+        )?;
+        self.connection.query_row("SELECT id FROM vocabulary_terms WHERE term = ?1", [term], |row| row.get(0))
+    }
+
+    // In the order they were added
+    pub fn get_vocabulary_terms(&self) -> Result<Vec<VocabularyTerm>> {
+        let mut stmt = self.connection.prepare("SELECT id, term, created_at FROM vocabulary_terms ORDER BY id")?;
+        let terms = stmt.query_map([], |row| {
+            Ok(VocabularyTerm {
+                id: Some(row.get(0)?),
+                term: row.get(1)?,
+                created_at: row.get(2)?,
+            })
+        })?;
+        terms.collect()
+    }
+
+    pub fn delete_vocabulary_term(&self, term_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM vocabulary_terms WHERE id = ?1", [term_id])?;
+        Ok(deleted > 0)
+    }
+
+    // Enrolling a name again replaces its voice print
+    pub fn save_enrolled_speaker(&self, speaker: &EnrolledSpeaker) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let bytes: Vec<u8> = speaker.embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+        self.connection.execute(
+            "INSERT INTO enrolled_speakers (name, embedding, model, sample_seconds, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(name) DO UPDATE SET embedding = excluded.embedding, model = excluded.model, sample_seconds = excluded.sample_seconds",
+            rusqlite::params![speaker.name, bytes, speaker.model, speaker.sample_seconds, now],
+        )?;
+        self.connection.query_row("SELECT id FROM enrolled_speakers WHERE name = ?1", [&speaker.name], |row| row.get(0))
+    }
+
+    pub fn get_enrolled_speakers(&self) -> Result<Vec<EnrolledSpeaker>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, name, embedding, model, sample_seconds, created_at FROM enrolled_speakers ORDER BY name"
+        )?;
+        let speakers = stmt.query_map([], |row| {
+            let bytes: Vec<u8> = row.get(2)?;
+            Ok(EnrolledSpeaker {
+                id: Some(row.get(0)?),
+                name: row.get(1)?,
+                embedding: bytes
+                    .chunks_exact(4)
+                    .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+                    .collect(),
+                model: row.get(3)?,
+                sample_seconds: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?;
+        speakers.collect()
+    }
+
+    // Attributions to the speaker go with them
+    pub fn delete_enrolled_speaker(&self, speaker_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM enrolled_speakers WHERE id = ?1", [speaker_id])?;
+        self.connection.execute("DELETE FROM speaker_attributions WHERE speaker_id = ?1", [speaker_id])?;
+        Ok(deleted > 0)
+    }
+
+    // Replaces the recording's attributions
+    // Replaces the attributions of the lines in `lines`
+    pub fn save_speaker_attributions(&self, record_id: i64, lines: std::ops::Range<usize>, attributions: &[SpeakerAttribution]) -> Result<()> {
+        self.connection.execute(
+            "DELETE FROM speaker_attributions WHERE record_id = ?1 AND line_index >= ?2 AND line_index < ?3",
+            rusqlite::params![record_id, lines.start as i64, lines.end as i64],
+        )?;
+        for attribution in attributions {
+            self.connection.execute(
+                "INSERT INTO speaker_attributions (record_id, line_index, speaker_id, confidence) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![record_id, attribution.line_index, attribution.speaker_id, attribution.confidence],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_speaker_attributions(&self, record_id: i64) -> Result<Vec<SpeakerAttribution>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT a.record_id, a.line_index, a.speaker_id, s.name, a.confidence FROM speaker_attributions a
+             JOIN enrolled_speakers s ON s.id = a.speaker_id
+             WHERE a.record_id = ?1 ORDER BY a.line_index"
+        )?;
+        let attributions = stmt.query_map([record_id], |row| {
+            Ok(SpeakerAttribution {
+                record_id: row.get(0)?,
+                line_index: row.get(1)?,
+                speaker_id: row.get(2)?,
+                speaker_name: row.get(3)?,
+                confidence: row.get(4)?,
+            })
+        })?;
+        attributions.collect()
+    }
+
+    pub fn queue_transcription_job(&self, record_id: i64, model_size: &str, language: Option<&str>, translate: Option<bool>) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO transcription_jobs (record_id, model_size, language, translate, status, created_at)
+             VALUES (?1, ?2, ?3, ?4, 'queued', ?5)",
+            rusqlite::params![record_id, model_size, language, translate, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn transcription_job(row: &rusqlite::Row) -> rusqlite::Result<TranscriptionJob> {
+        Ok(TranscriptionJob {
+            id: row.get(0)?,
+            record_id: row.get(1)?,
+            model_size: row.get(2)?,
+            language: row.get(3)?,
+            translate: row.get(4)?,
+            status: row.get(5)?,
+            progress: row.get(6)?,
+            error: row.get(7)?,
+            created_at: row.get(8)?,
+            started_at: row.get(9)?,
+            finished_at: row.get(10)?,
+        })
+    }
+
+    // Oldest first, which is the order they run in
+    pub fn get_transcription_jobs(&self) -> Result<Vec<TranscriptionJob>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, record_id, model_size, language, translate, status, progress, error, created_at, started_at, finished_at
+             FROM transcription_jobs ORDER BY id"
+        )?;
+        let jobs = stmt.query_map([], Self::transcription_job)?;
+        jobs.collect()
+    }
+
+    pub fn get_transcription_job(&self, job_id: i64) -> Result<Option<TranscriptionJob>> {
+        self.connection.query_row(
+            "SELECT id, record_id, model_size, language, translate, status, progress, error, created_at, started_at, finished_at
+             FROM transcription_jobs WHERE id = ?1",
+            [job_id],
+            Self::transcription_job,
+        ).optional()
+    }
+
+    // Marks the oldest queued job running and returns it
+    pub fn claim_transcription_job(&self) -> Result<Option<TranscriptionJob>> {
+        let next: Option<i64> = self.connection.query_row(
+            "SELECT id FROM transcription_jobs WHERE status = 'queued' ORDER BY id LIMIT 1",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        let Some(job_id) = next else {
+            return Ok(None);
+        };
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "UPDATE transcription_jobs SET status = 'running', progress = 0, error = NULL, started_at = ?2, finished_at = NULL WHERE id = ?1",
+            rusqlite::params![job_id, now],
+        )?;
+        self.get_transcription_job(job_id)
+    }
+
+    // Finished jobs get the time; queued and paused ones start over
+    pub fn set_transcription_job_status(&self, job_id: i64, status: &str, error: Option<&str>) -> Result<()> {
+        let finished = matches!(status, "completed" | "failed" | "cancelled").then(|| chrono::Utc::now().to_rfc3339());
+        let progress = match status {
+            "completed" => "1",
+            "queued" | "paused" => "0",
+            _ => "progress",
+        };
+        self.connection.execute(
+            &format!("UPDATE transcription_jobs SET status = ?2, error = ?3, finished_at = ?4, progress = {} WHERE id = ?1", progress),
+            rusqlite::params![job_id, status, error, finished],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_transcription_job_progress(&self, job_id: i64, progress: f64) -> Result<()> {
+        self.connection.execute("UPDATE transcription_jobs SET progress = ?2 WHERE id = ?1", rusqlite::params![job_id, progress])?;
+        Ok(())
+    }
+
+    // Jobs that were running when the app last stopped go back in the queue
+    pub fn requeue_running_transcription_jobs(&self) -> Result<usize> {
+        self.connection.execute("UPDATE transcription_jobs SET status = 'queued', progress = 0 WHERE status = 'running'", [])
+    }
+
+    pub fn delete_finished_transcription_jobs(&self) -> Result<usize> {
+        self.connection.execute("DELETE FROM transcription_jobs WHERE status IN ('completed', 'failed', 'cancelled')", [])
+    }
+
+    // Dated now unless the version has a date of its own
+    pub fn save_transcript_version(&self, version: &TranscriptVersion) -> Result<i64> {
+        let now = match version.created_at.is_empty() {
+            true => chrono::Utc::now().to_rfc3339(),
+            false => version.created_at.clone(),
+        };
+        let settings = version.settings.to_string();
+        let lines = serde_json::to_string(&version.lines).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.connection.execute(
+            "INSERT INTO transcript_versions (record_id, engine, model, settings, text, language, lines, restored_from, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![version.record_id, version.engine, version.model, settings, version.text, version.language, lines, version.restored_from, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn json_column<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, index: usize) -> rusqlite::Result<T> {
+        let text: String = row.get(index)?;
+        serde_json::from_str(&text)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+    }
+
+    fn transcript_version(row: &rusqlite::Row) -> rusqlite::Result<TranscriptVersion> {
+        Ok(TranscriptVersion {
+            id: Some(row.get(0)?),
+            record_id: row.get(1)?,
+            engine: row.get(2)?,
+            model: row.get(3)?,
+            settings: Self::json_column(row, 4)?,
+            text: row.get(5)?,
+            language: row.get(6)?,
+            lines: Self::json_column(row, 7)?,
+            restored_from: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    }
+
+    pub fn has_transcript_versions(&self, record_id: i64) -> Result<bool> {
+        self.connection.query_row("SELECT EXISTS (SELECT 1 FROM transcript_versions WHERE record_id = ?1)", [record_id], |row| row.get(0))
+    }
+
+    // Oldest first; the last is the recording's current transcript
+    pub fn get_transcript_versions(&self, record_id: i64) -> Result<Vec<TranscriptVersion>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, record_id, engine, model, settings, text, language, lines, restored_from, created_at
+             FROM transcript_versions WHERE record_id = ?1 ORDER BY id"
+        )?;
+        let versions = stmt.query_map([record_id], Self::transcript_version)?;
+        versions.collect()
+    }
+
+    pub fn get_transcript_version(&self, version_id: i64) -> Result<Option<TranscriptVersion>> {
+        self.connection.query_row(
+            "SELECT id, record_id, engine, model, settings, text, language, lines, restored_from, created_at
+             FROM transcript_versions WHERE id = ?1",
+            [version_id],
+            Self::transcript_version,
+        ).optional()
+    }
+
+    pub fn save_transcript_track(&self, track: &TranscriptTrack) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let lines = serde_json::to_string(&track.lines).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.connection.execute(
+            "INSERT INTO transcript_tracks (record_id, name, engine, model, language, translated, text, lines, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![track.record_id, track.name, track.engine, track.model, track.language, track.translated, track.text, lines, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn transcript_track(row: &rusqlite::Row) -> rusqlite::Result<TranscriptTrack> {
+        Ok(TranscriptTrack {
+            id: Some(row.get(0)?),
+            record_id: row.get(1)?,
+            name: row.get(2)?,
+            engine: row.get(3)?,
+            model: row.get(4)?,
+            language: row.get(5)?,
+            translated: row.get(6)?,
+            text: row.get(7)?,
+            lines: Self::json_column(row, 8)?,
+            created_at: row.get(9)?,
+        })
+    }
+
+    // In the order they were added
+    pub fn get_transcript_tracks(&self, record_id: i64) -> Result<Vec<TranscriptTrack>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, record_id, name, engine, model, language, translated, text, lines, created_at
+             FROM transcript_tracks WHERE record_id = ?1 ORDER BY id"
+        )?;
+        let tracks = stmt.query_map([record_id], Self::transcript_track)?;
+        tracks.collect()
+    }
+
+    pub fn get_transcript_track(&self, track_id: i64) -> Result<Option<TranscriptTrack>> {
+        self.connection.query_row(
+            "SELECT id, record_id, name, engine, model, language, translated, text, lines, created_at
+             FROM transcript_tracks WHERE id = ?1",
+            [track_id],
+            Self::transcript_track,
+        ).optional()
+    }
+
+    pub fn delete_transcript_track(&self, track_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM transcript_tracks WHERE id = ?1", [track_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn save_recording_language(&self, record_id: i64, language: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_languages (record_id, language) VALUES (?1, ?2)",
+            rusqlite::params![record_id, language],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_language(&self, record_id: i64) -> Result<Option<String>> {
+        self.connection.query_row(
+            "SELECT language FROM recording_languages WHERE record_id = ?1",
+            [record_id],
+            |row| row.get(0),
+        ).optional()
+    }
+
+    pub fn save_recording_track(&self, track: &RecordingTrack) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_tracks (record_id, channel, source, device) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![track.record_id, track.channel, track.source, track.device],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_tracks(&self, record_id: i64) -> Result<Vec<RecordingTrack>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT record_id, channel, source, device FROM recording_tracks WHERE record_id = ?1 ORDER BY channel"
+        )?;
+        let tracks = stmt.query_map([record_id], |row| {
+            Ok(RecordingTrack {
+                record_id: row.get(0)?,
+                channel: row.get(1)?,
+                source: row.get(2)?,
+                device: row.get(3)?,
+            })
+        })?;
+        tracks.collect()
+    }
+
+    // Devices are stored one per line
+    pub fn save_recording_metadata(&self, metadata: &RecordingMetadata) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_metadata
+             (record_id, devices, sample_rate, channels, channel_layout, agc_target_db, agc_max_gain_db, app_version, location, notes, captured_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params![
+                metadata.record_id,
+                metadata.devices.join("\n"),
+                metadata.sample_rate,
+                metadata.channels,
+                metadata.channel_layout,
+                metadata.agc_target_db,
+                metadata.agc_max_gain_db,
+                metadata.app_version,
+                metadata.location,
+                metadata.notes,
+                metadata.captured_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_metadata(&self, record_id: i64) -> Result<Option<RecordingMetadata>> {
+        self.connection
+            .query_row(
+                "SELECT record_id, devices, sample_rate, channels, channel_layout, agc_target_db, agc_max_gain_db, app_version, location, notes, captured_at
+                 FROM recording_metadata WHERE record_id = ?1",
+                [record_id],
+                |row| {
+                    let devices: String = row.get(1)?;
+                    Ok(RecordingMetadata {
+                        record_id: row.get(0)?,
+                        devices: devices.lines().map(str::to_string).collect(),
+                        sample_rate: row.get(2)?,
+                        channels: row.get(3)?,
+                        channel_layout: row.get(4)?,
+                        agc_target_db: row.get(5)?,
+                        agc_max_gain_db: row.get(6)?,
+                        app_version: row.get(7)?,
+                        location: row.get(8)?,
+                        notes: row.get(9)?,
+                        captured_at: row.get(10)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    // Returns false when the recording has no metadata
+    pub fn update_recording_notes(&self, record_id: i64, location: Option<&str>, notes: Option<&str>) -> Result<bool> {
+        let updated = self.connection.execute(
+            "UPDATE recording_metadata SET location = ?1, notes = ?2 WHERE record_id = ?3",
+            rusqlite::params![location, notes, record_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn save_recording_session(&self, session: &RecordingSession) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_sessions (file_path, title, resumes_record_id, gap_seconds, started_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![session.file_path, session.title, session.resumes_record_id, session.gap_seconds, now],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_session(&self, file_path: &str) -> Result<Option<RecordingSession>> {
+        self.connection
+            .query_row(
+                "SELECT file_path, title, resumes_record_id, gap_seconds, started_at FROM recording_sessions WHERE file_path = ?1",
+                [file_path],
+                |row| {
+                    Ok(RecordingSession {
+                        file_path: row.get(0)?,
+                        title: row.get(1)?,
+                        resumes_record_id: row.get(2)?,
+                        gap_seconds: row.get(3)?,
+                        started_at: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn delete_recording_session(&self, file_path: &str) -> Result<()> {
+        self.connection.execute("DELETE FROM recording_sessions WHERE file_path = ?1", [file_path])?;
+        Ok(())
+    }
+
+    pub fn save_resumable_recording(&self, record_id: i64, interrupted_at: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO resumable_recordings (record_id, interrupted_at) VALUES (?1, ?2)",
+            rusqlite::params![record_id, interrupted_at],
+        )?;
+        Ok(())
+    }
+
+    // Most recently interrupted first
+    pub fn get_resumable_recordings(&self) -> Result<Vec<ResumableRecording>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT r.record_id, a.title, a.duration, r.interrupted_at FROM resumable_recordings r
+             JOIN audio_records a ON a.id = r.record_id ORDER BY r.interrupted_at DESC"
+        )?;
+        let recordings = stmt.query_map([], |row| {
+            Ok(ResumableRecording {
+                record_id: row.get(0)?,
+                title: row.get(1)?,
+                duration: row.get(2)?,
+                interrupted_at: row.get(3)?,
+            })
+        })?;
+        recordings.collect()
+    }
+
+    // Returns false when the recording wasn't resumable
+    pub fn delete_resumable_recording(&self, record_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM resumable_recordings WHERE record_id = ?1", [record_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn save_recording_gap(&self, gap: &RecordingGap) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO recording_gaps (record_id, at_seconds, gap_seconds) VALUES (?1, ?2, ?3)",
+            rusqlite::params![gap.record_id, gap.at_seconds, gap.gap_seconds],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_recording_gaps(&self, record_id: i64) -> Result<Vec<RecordingGap>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT record_id, at_seconds, gap_seconds FROM recording_gaps WHERE record_id = ?1 ORDER BY at_seconds"
+        )?;
+        let gaps = stmt.query_map([record_id], |row| {
+            Ok(RecordingGap {
+                record_id: row.get(0)?,
+                at_seconds: row.get(1)?,
+                gap_seconds: row.get(2)?,
+            })
+        })?;
+        gaps.collect()
+    }
+
+    pub fn save_schedule(&self, schedule: &RecordingSchedule) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        // Stored as e.g. "Mon,Tue"
+        let days: Vec<String> = schedule.days.iter().map(|day| day.to_string()).collect();
+        self.connection.execute(
+            "INSERT INTO recording_schedules (name, days, start_time, end_time, device_id, enabled, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![schedule.name, days.join(","), schedule.start_time, schedule.end_time, schedule.device_id, schedule.enabled, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get_schedules(&self) -> Result<Vec<RecordingSchedule>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, name, days, start_time, end_time, device_id, enabled, created_at FROM recording_schedules ORDER BY id"
+        )?;
+        let schedules = stmt.query_map([], |row| {
+            let days: String = row.get(2)?;
+            Ok(RecordingSchedule {
+                id: Some(row.get(0)?),
+                name: row.get(1)?,
+                days: days.split(',').filter_map(|day| day.parse().ok()).collect(),
+                start_time: row.get(3)?,
+                end_time: row.get(4)?,
+                device_id: row.get(5)?,
+                enabled: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?;
+        schedules.collect()
+    }
+
+    // Returns false when there is no such schedule
+    pub fn delete_schedule(&self, schedule_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM recording_schedules WHERE id = ?1", [schedule_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn save_watch_folder(&self, folder: &WatchFolder) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO watch_folders (path, transcribe, enabled, created_at) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![folder.path, folder.transcribe, folder.enabled, now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get_watch_folders(&self) -> Result<Vec<WatchFolder>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, path, transcribe, enabled, created_at FROM watch_folders ORDER BY id"
+        )?;
+        let folders = stmt.query_map([], |row| {
+            Ok(WatchFolder {
+                id: Some(row.get(0)?),
+                path: row.get(1)?,
+                transcribe: row.get(2)?,
+                enabled: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        folders.collect()
+    }
+
+    // Returns false when there is no such folder. What it imported stays.
+    pub fn delete_watch_folder(&self, folder_id: i64) -> Result<bool> {
+        let deleted = self.connection.execute("DELETE FROM watch_folders WHERE id = ?1", [folder_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn save_imported_file(&self, file: &ImportedFile) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO imported_files (source_path, source_len, source_modified, folder_id, record_id, error, imported_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![file.source_path, file.source_len, file.source_modified, file.folder_id, file.record_id, file.error, now],
+        )?;
+        Ok(())
+    }
+
+    fn imported_file(row: &rusqlite::Row) -> rusqlite::Result<ImportedFile> {
+        Ok(ImportedFile {
+            source_path: row.get(0)?,
+            source_len: row.get(1)?,
+            source_modified: row.get(2)?,
+            folder_id: row.get(3)?,
+            record_id: row.get(4)?,
+            error: row.get(5)?,
+            imported_at: row.get(6)?,
+        })
+    }
+
+    pub fn get_imported_file(&self, source_path: &str) -> Result<Option<ImportedFile>> {
+        self.connection
+            .query_row(
+                "SELECT source_path, source_len, source_modified, folder_id, record_id, error, imported_at
+                 FROM imported_files WHERE source_path = ?1",
+                [source_path],
+                Self::imported_file,
+            )
+            .optional()
+    }
+
+    // Newest first
+    pub fn get_imported_files(&self, folder_id: i64) -> Result<Vec<ImportedFile>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT source_path, source_len, source_modified, folder_id, record_id, error, imported_at
+             FROM imported_files WHERE folder_id = ?1 ORDER BY imported_at DESC"
+        )?;
+        let files = stmt.query_map([folder_id], Self::imported_file)?;
+        files.collect()
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row("SELECT value FROM app_settings WHERE key = ?1", [key], |row| row.get(0))
+            .optional()
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO app_settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+            [key, value, &now],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_chat_session(&self, title: &str) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO chat_sessions (title, created_at, updated_at) VALUES (?1, ?2, ?3)",
+            [title, &now, &now],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get_chat_session(&self, session_id: i64) -> Result<Option<ChatSession>> {
+        self.connection
+            .query_row(
+                "SELECT id, title, created_at, updated_at FROM chat_sessions WHERE id = ?1",
+                [session_id],
+                |row| {
+                    Ok(ChatSession {
+                        id: Some(row.get(0)?),
+                        title: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn get_chat_sessions(&self) -> Result<Vec<ChatSession>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, title, created_at, updated_at FROM chat_sessions ORDER BY updated_at DESC"
+        )?;
+
+        let session_iter = stmt.query_map([], |row| {
+            Ok(ChatSession {
+                id: Some(row.get(0)?),
+                title: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+            })
+        })?;
+
+        let mut sessions = Vec::new();
+        for session in session_iter {
+            sessions.push(session?);
+        }
+        Ok(sessions)
+    }
+
+    pub fn delete_chat_session(&self, session_id: i64) -> Result<bool> {
+        self.connection.execute("DELETE FROM chat_messages WHERE session_id = ?1", [session_id])?;
+        self.connection.execute("DELETE FROM chat_session_summaries WHERE session_id = ?1", [session_id])?;
+        let deleted = self.connection.execute("DELETE FROM chat_sessions WHERE id = ?1", [session_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn save_chat_message(&self, message: &ChatMessage) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO chat_messages (session_id, role, content, created_at) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![message.session_id, message.role, message.content, now],
+        )?;
+        let id = self.connection.last_insert_rowid();
+
+        self.connection.execute(
+            "UPDATE chat_sessions SET updated_at = ?1 WHERE id = ?2",
+            rusqlite::params![now, message.session_id],
+        )?;
+        Ok(id)
+    }
+
+    pub fn get_chat_messages(&self, session_id: i64) -> Result<Vec<ChatMessage>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, session_id, role, content, created_at FROM chat_messages WHERE session_id = ?1 ORDER BY id ASC"
+        )?;
+
+        let message_iter = stmt.query_map([session_id], |row| {
+            Ok(ChatMessage {
+                id: Some(row.get(0)?),
+                session_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+
+        let mut messages = Vec::new();
+        for message in message_iter {
+            messages.push(message?);
+        }
+        Ok(messages)
+    }
+
+    pub fn get_session_summary(&self, session_id: i64) -> Result<Option<SessionSummary>> {
+        self.connection
+            .query_row(
+                "SELECT session_id, summary, summarized_through, updated_at FROM chat_session_summaries WHERE session_id = ?1",
+                [session_id],
+                |row| {
+                    Ok(SessionSummary {
+                        session_id: row.get(0)?,
+                        summary: row.get(1)?,
+                        summarized_through: row.get(2)?,
+                        updated_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn save_session_summary(&self, session_id: i64, summary: &str, summarized_through: i64) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO chat_session_summaries (session_id, summary, summarized_through, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(session_id) DO UPDATE SET summary = excluded.summary,
+                summarized_through = excluded.summarized_through, updated_at = excluded.updated_at",
+            rusqlite::params![session_id, summary, summarized_through, now],
+        )?;
+        Ok(())
+    }
+}
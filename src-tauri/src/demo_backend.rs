@@ -0,0 +1,98 @@
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::llm_backend::{BackendOutput, GenerationRequest, LlmBackend};
+
+// Plain-text replies say so themselves, on top of the `simulated` flag
+const DEMO_NOTICE: &str = "(Demo mode: this is a canned response, no AI model was used.)";
+
+// Keyword → reply, checked in order against the user's part of the prompt
+const CANNED_REPLIES: [(&[&str], &str); 6] = [
+    (
+        &["hello", "hey", "greetings", "good morning", "good day"],
+        "Good day! I'm Dwight, your audio analysis assistant. How may I help with your recordings today?",
+    ),
+    (
+        &["transcri"],
+        "I can transcribe your recordings and search their transcripts. Connect a model through Ollama to have me summarize or analyze them.",
+    ),
+    (
+        &["record"],
+        "Recording is ready. Start a recording from the dashboard and I'll keep it with your other recordings for later review.",
+    ),
+    (
+        &["sound", "audio", "noise"],
+        "For audio analysis I look at amplitude, zero crossings and spectral features. With a model connected I can describe what a recording likely contains.",
+    ),
+    (
+        &["ollama", "model", "setup", "install"],
+        "To use real models: install Ollama from https://ollama.ai, run 'ollama serve', pull a model such as 'ollama pull llama3', then turn demo mode off.",
+    ),
+    (
+        &["?"],
+        "That's a good question. In demo mode I can only give prepared answers; connect a model through Ollama for a real one.",
+    ),
+];
+
+const DEFAULT_REPLY: &str = "Understood. I'm running in demo mode, so my answers are prepared in advance. Connect a model through Ollama for full responses.";
+
+// Deterministic stand-in for a model, used when demo mode is on. The same
+// prompt always gets the same reply, and nothing leaves the machine.
+pub struct MockBackend;
+
+impl MockBackend {
+    // Prompt templates put the system prompt first; only match on what the user wrote
+    fn user_part(prompt: &str) -> String {
+        ["User input:", "User:", "Query:"].iter()
+            .filter_map(|marker| prompt.rfind(marker).map(|index| &prompt[index + marker.len()..]))
+            .min_by_key(|rest| rest.len())
+            .unwrap_or(prompt)
+            .to_lowercase()
+    }
+
+    fn reply(request: &GenerationRequest<'_>) -> String {
+        let user_part = Self::user_part(request.prompt);
+        let reply = CANNED_REPLIES.iter()
+            .find(|(keywords, _)| keywords.iter().any(|keyword| user_part.contains(keyword)))
+            .map_or(DEFAULT_REPLY, |(_, reply)| reply);
+
+        if request.options.json.unwrap_or(false) {
+            // Fits both the agent protocol (answer) and AudioAnalysisReport
+            serde_json::json!({
+                "answer": reply,
+                "summary": reply,
+                "speech_present": false,
+                "detected_sounds": [],
+                "security_concerns": [],
+                "recommended_actions": [],
+            })
+            .to_string()
+        } else {
+            format!("{}\n\n{}", reply, DEMO_NOTICE)
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockBackend {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput> {
+        Ok(BackendOutput {
+            text: Self::reply(request),
+            ..BackendOutput::default()
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        let text = Self::reply(request);
+        for word in text.split_inclusive(' ') {
+            on_token(word);
+        }
+        Ok(BackendOutput {
+            text,
+            ..BackendOutput::default()
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use nnnoiseless::DenoiseState;
+use crate::audio_capture::StreamFormat;
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+// RNNoise only works on 48 kHz audio
+const DENOISE_SAMPLE_RATE: u32 = 48000;
+
+// Suppresses steady background noise (fans, hum, hiss) with RNNoise, one
+// model state per channel. Output is in the same format as the input.
+pub struct Denoiser {
+    states: Vec<Box<DenoiseState<'static>>>,
+    to_model: FormatConverter,
+    from_model: FormatConverter,
+    // Per-channel 48 kHz samples waiting for a full frame, in i16 range
+    pending: Vec<Vec<f32>>,
+}
+
+impl Denoiser {
+    pub fn new(format: StreamFormat, quality: ResampleQuality) -> Self {
+        let channels = format.channels.max(1);
+        let model_format = StreamFormat { sample_rate: DENOISE_SAMPLE_RATE, channels };
+        Denoiser {
+            states: (0..channels).map(|_| DenoiseState::new()).collect(),
+            to_model: FormatConverter::new(format, model_format, quality),
+            from_model: FormatConverter::new(model_format, format, quality),
+            pending: vec![Vec::with_capacity(DenoiseState::FRAME_SIZE); channels as usize],
+        }
+    }
+
+    fn denoise_pending(&mut self) -> Vec<f32> {
+        let channels = self.states.len();
+        let frames = self.pending[0].len() / DenoiseState::FRAME_SIZE;
+        let mut output = vec![0.0; frames * DenoiseState::FRAME_SIZE * channels];
+        let mut denoised = [0.0; DenoiseState::FRAME_SIZE];
+
+        for (channel, (state, pending)) in self.states.iter_mut().zip(&mut self.pending).enumerate() {
+            for (frame, input) in pending.chunks_exact(DenoiseState::FRAME_SIZE).enumerate() {
+                state.process_frame(&mut denoised, input);
+                let start = frame * DenoiseState::FRAME_SIZE;
+                for (offset, &sample) in denoised.iter().enumerate() {
+                    output[(start + offset) * channels + channel] = sample / i16::MAX as f32;
+                }
+            }
+            pending.drain(..frames * DenoiseState::FRAME_SIZE);
+        }
+        self.from_model.convert(&output)
+    }
+
+    // Feeds interleaved audio and returns what has been denoised so far,
+    // which trails the input by up to one 10 ms frame
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let channels = self.states.len();
+        for frame in self.to_model.convert(samples).chunks_exact(channels) {
+            for (pending, &sample) in self.pending.iter_mut().zip(frame) {
+                pending.push(sample.clamp(-1.0, 1.0) * i16::MAX as f32);
+            }
+        }
+        self.denoise_pending()
+    }
+
+    // Pads out the last partial frame so the output is as long as the input
+    pub fn flush(&mut self) -> Vec<f32> {
+        let remaining = self.pending[0].len();
+        if remaining == 0 {
+            return Vec::new();
+        }
+        for pending in &mut self.pending {
+            pending.resize(DenoiseState::FRAME_SIZE, 0.0);
+        }
+        let mut output = self.denoise_pending();
+        let frames = remaining * output.len() / (DenoiseState::FRAME_SIZE * self.states.len());
+        output.truncate(frames * self.states.len());
+        output
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use tauri::{command, State};
+use crate::audio_capture::{AudioCapture, CaptureSettings};
+use crate::audio_file;
+use crate::database::{Database, TranscriptLine};
+use crate::resampler::ResampleQuality;
+use crate::speaker_embedding::SpeakerEncoder;
+use crate::transcript_cleanup;
+use crate::transcript_index;
+use crate::whisper::TranscriptionSegment;
+
+pub const MAX_SPEAKERS: u32 = 16;
+
+// Speaker-embedding models are trained on 16 kHz speech, Whisper's rate too
+pub const SAMPLE_RATE: u32 = 16000;
+
+// Too short to tell a voice by; these lines go to whichever speaker they
+// sound closest to once the rest are grouped
+pub const MIN_LINE_SECONDS: f64 = 1.0;
+
+// A re-transcribed region's lines are matched to the speakers of the lines
+// up to this far either side of it
+pub const CONTEXT_SECONDS: f64 = 120.0;
+
+// Clustering takes time with the cube of the lines it's given, so beyond
+// this many the longest are clustered and the rest go to the closest group
+const MAX_CLUSTERED_LINES: usize = 400;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diarization {
+    pub speakers: u32,
+    pub lines: Vec<TranscriptLine>,
+}
+
+pub fn speaker_label(speaker: u32) -> String {
+    format!("Speaker {}", speaker)
+}
+
+// The transcript with a speaker label on each turn, a line per turn
+pub fn labelled_text(lines: &[TranscriptLine]) -> String {
+    let mut turns: Vec<(Option<u32>, Vec<&str>)> = Vec::new();
+    for line in lines {
+        match turns.last_mut() {
+            Some((speaker, texts)) if *speaker == line.speaker => texts.push(line.text.trim()),
+            _ => turns.push((line.speaker, vec![line.text.trim()])),
+        }
+    }
+    turns.iter()
+        .map(|(speaker, texts)| match speaker {
+            Some(speaker) => format!("{}: {}", speaker_label(*speaker), texts.join(" ")),
+            None => texts.join(" "),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return 1.0;
+    }
+    1.0 - dot / norm
+}
+
+// Average-linkage clustering: the two closest groups merge until `speakers`
+// are left, or without it until none are closer than `max_distance`, the
+// distance the speaker model tells voices apart at. Returns the group of
+// each embedding.
+fn cluster(embeddings: &[Vec<f32>], speakers: Option<u32>, max_distance: f32) -> Vec<usize> {
+    let count = embeddings.len();
+    let mut distances: Vec<Vec<f32>> = embeddings.iter()
+        .map(|a| embeddings.iter().map(|b| cosine_distance(a, b)).collect())
+        .collect();
+    let mut members: Vec<Vec<usize>> = (0..count).map(|index| vec![index]).collect();
+    let mut alive: Vec<usize> = (0..count).collect();
+    let target = speakers.map_or(1, |speakers| speakers as usize).max(1);
+
+    while alive.len() > target {
+        let mut closest = (f32::INFINITY, 0, 0);
+        for (position, &a) in alive.iter().enumerate() {
+            for &b in &alive[position + 1..] {
+                if distances[a][b] < closest.0 {
+                    closest = (distances[a][b], a, b);
+                }
+            }
+        }
+        let (distance, a, b) = closest;
+        if speakers.is_none() && distance >= max_distance && alive.len() <= MAX_SPEAKERS as usize {
+            break;
+        }
+        let (size_a, size_b) = (members[a].len() as f32, members[b].len() as f32);
+        for &other in &alive {
+            if other != a && other != b {
+                let merged = (size_a * distances[a][other] + size_b * distances[b][other]) / (size_a + size_b);
+                distances[a][other] = merged;
+                distances[other][a] = merged;
+            }
+        }
+        let moved = std::mem::take(&mut members[b]);
+        members[a].extend(moved);
+        alive.retain(|&index| index != b);
+    }
+
+    let mut groups = vec![0; count];
+    for (group, &index) in alive.iter().enumerate() {
+        for &member in &members[index] {
+            groups[member] = group;
+        }
+    }
+    groups
+}
+
+// The audio of each line, 16 kHz mono, for diarizing and identifying
+// speakers from one read of the file. Only the lines' spans are kept.
+pub fn read_lines(path: &Path, lines: &[TranscriptLine], quality: ResampleQuality) -> Result<Vec<Vec<f32>>> {
+    let spans: Vec<(f64, f64)> = lines.iter().map(|line| (line.start_seconds, line.end_seconds.max(line.start_seconds))).collect();
+    audio_file::read_mono_spans(path, &spans, SAMPLE_RATE, quality)
+}
+
+// Labels each line with its speaker from its audio, as read_lines reads it,
+// by the encoder's print of each line, numbering speakers in the order they
+// first speak. Returns how many were heard.
+pub fn diarize_lines(encoder: &SpeakerEncoder, audio: &[Vec<f32>], lines: &mut [TranscriptLine], speakers: Option<u32>) -> Result<u32> {
+    let span = |index: usize| audio[index].as_slice();
+    let min_samples = (MIN_LINE_SECONDS * SAMPLE_RATE as f64) as usize;
+    let long: Vec<usize> = (0..lines.len()).filter(|&index| span(index).len() >= min_samples).collect();
+    if long.is_empty() {
+        // Nothing long enough to tell voices apart by; take it as one speaker
+        for line in lines.iter_mut() {
+            line.speaker = Some(1);
+        }
+        return Ok(if lines.is_empty() { 0 } else { 1 });
+    }
+
+    let prints = audio.iter().map(|samples| encoder.embed(samples)).collect::<Result<Vec<_>>>()?;
+    let dims = prints[long[0]].len();
+
+    let mut clustered = long.clone();
+    if clustered.len() > MAX_CLUSTERED_LINES {
+        clustered.sort_by_key(|&index| std::cmp::Reverse(span(index).len()));
+        clustered.truncate(MAX_CLUSTERED_LINES);
+        clustered.sort_unstable();
+    }
+    let embeddings: Vec<Vec<f32>> = clustered.iter().map(|&index| prints[index].clone()).collect();
+    let groups = cluster(&embeddings, speakers, encoder.max_distance());
+    let group_count = groups.iter().max().map_or(0, |max| max + 1);
+    // Sums rather than means; cosine distance doesn't see the difference
+    let mut centroids = vec![vec![0.0f32; dims]; group_count];
+    for (embedding, &group) in embeddings.iter().zip(&groups) {
+        for (total, value) in centroids[group].iter_mut().zip(embedding) {
+            *total += value;
+        }
+    }
+
+    let mut assigned: Vec<Option<usize>> = vec![None; lines.len()];
+    for (&index, &group) in clustered.iter().zip(&groups) {
+        assigned[index] = Some(group);
+    }
+    for index in 0..lines.len() {
+        if assigned[index].is_some() {
+            continue;
+        }
+        let closest = (0..group_count)
+            .min_by(|&a, &b| cosine_distance(&prints[index], &centroids[a]).total_cmp(&cosine_distance(&prints[index], &centroids[b])));
+        assigned[index] = Some(closest.unwrap_or(0));
+    }
+    let assigned: Vec<usize> = assigned.into_iter().map(|group| group.unwrap_or(0)).collect();
+
+    // Speaker 1 is whoever speaks first
+    let mut order: Vec<usize> = Vec::new();
+    for (line, &group) in lines.iter_mut().zip(&assigned) {
+        let position = match order.iter().position(|&seen| seen == group) {
+            Some(position) => position,
+            None => {
+                order.push(group);
+                order.len() - 1
+            }
+        };
+        line.speaker = Some(position as u32 + 1);
+    }
+    Ok(order.len() as u32)
+}
+
+// Labels the lines at `unlabelled` with whichever speaker already on the
+// other lines they sound closest to, so part of a transcript can be
+// diarized without the rest and keep its numbering. Lines and audio are as
+// diarize_lines takes them. Returns false, leaving the lines alone, when no
+// other line is labelled and long enough to compare with.
+pub fn assign_speakers(encoder: &SpeakerEncoder, audio: &[Vec<f32>], lines: &mut [TranscriptLine], unlabelled: &[usize]) -> Result<bool> {
+    let min_samples = (MIN_LINE_SECONDS * SAMPLE_RATE as f64) as usize;
+    let references: Vec<usize> = (0..lines.len())
+        .filter(|index| !unlabelled.contains(index) && lines[*index].speaker.is_some() && audio[*index].len() >= min_samples)
+        .collect();
+    if references.is_empty() {
+        return Ok(false);
+    }
+    // Sums rather than means, as in diarize_lines
+    let mut centroids: Vec<(u32, Vec<f32>)> = Vec::new();
+    for &index in &references {
+        let print = encoder.embed(&audio[index])?;
+        let speaker = lines[index].speaker.unwrap_or(1);
+        let position = match centroids.iter().position(|(known, _)| *known == speaker) {
+            Some(position) => position,
+            None => {
+                centroids.push((speaker, vec![0.0; print.len()]));
+                centroids.len() - 1
+            }
+        };
+        for (total, value) in centroids[position].1.iter_mut().zip(&print) {
+            *total += value;
+        }
+    }
+    for &index in unlabelled {
+        let print = encoder.embed(&audio[index])?;
+        lines[index].speaker = centroids.iter()
+            .min_by(|(_, a), (_, b)| cosine_distance(&print, a).total_cmp(&cosine_distance(&print, b)))
+            .map(|(speaker, _)| *speaker);
+    }
+    Ok(true)
+}
+
+// Diarizes a transcribed recording and stores the speakers on its lines. The
+// recording's transcript, and what the RAG index holds of it, are rewritten
+// with a label on each turn. Voices are told apart by the speaker model in
+// `settings`.
+pub async fn diarize(app_handle: &tauri::AppHandle, recording_id: i64, speakers: Option<u32>, settings: CaptureSettings) -> Result<Diarization, String> {
+    if speakers.is_some_and(|speakers| !(1..=MAX_SPEAKERS).contains(&speakers)) {
+        return Err(format!("The number of speakers must be between 1 and {}", MAX_SPEAKERS));
+    }
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let mut lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    if lines.is_empty() {
+        return Err(format!("Recording {} has no timed transcript to diarize; transcribe it first", recording_id));
+    }
+
+    let (lines, found) = tokio::task::spawn_blocking(move || {
+        let encoder = SpeakerEncoder::load(&settings)?;
+        let audio = read_lines(Path::new(&record.file_path), &lines, settings.resample_quality)?;
+        let found = diarize_lines(&encoder, &audio, &mut lines, speakers)?;
+        Ok::<_, anyhow::Error>((lines, found))
+    })
+    .await
+    .map_err(|e| format!("Diarization failed: {}", e))?
+    .map_err(|e| format!("Diarization failed: {}", e))?;
+    store(app_handle, &db, recording_id, &lines)?;
+    Ok(Diarization { speakers: found, lines })
+}
+
+// Saves diarized lines, and rewrites the recording's transcript, and what
+// the RAG index holds of it, with a label on each turn
+pub fn store(app_handle: &tauri::AppHandle, db: &Database, recording_id: i64, lines: &[TranscriptLine]) -> Result<(), String> {
+    db.save_transcript_lines(recording_id, lines).map_err(|e| format!("Database error: {}", e))?;
+    db.update_audio_transcript(recording_id, &labelled_text(lines)).map_err(|e| format!("Database error: {}", e))?;
+    transcript_cleanup::refresh(app_handle, recording_id)?;
+    transcript_index::queue_recording_update(app_handle, recording_id, &labelled_segments(lines));
+    Ok(())
+}
+
+// The lines as segments for the RAG index, each with its speaker's label
+pub fn labelled_segments(lines: &[TranscriptLine]) -> Vec<TranscriptionSegment> {
+    lines.iter()
+        .map(|line| TranscriptionSegment {
+            text: match line.speaker {
+                Some(speaker) => format!("{}: {}", speaker_label(speaker), line.text.trim()),
+                None => line.text.clone(),
+            },
+            ..TranscriptionSegment::from_line(line)
+        })
+        .collect()
+}
+
+// Works out who said each line of a recording's transcript, as Speaker 1, 2,
+// 3 and so on, from how their voices sound. Give `speakers` when the number
+// is known; otherwise it is guessed.
+#[command]
+pub async fn diarize_recording(
+    recording_id: i64,
+    speakers: Option<u32>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Diarization, String> {
+    diarize(&app_handle, recording_id, speakers, capture.get_settings()).await
+}
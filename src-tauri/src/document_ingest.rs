@@ -0,0 +1,146 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::{command, State};
+use crate::ai_models::AdvancedAI;
+use crate::vector_store::{self, NewChunk, VectorStore, DEFAULT_INDEX};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub index: String,
+    pub source: String,
+    pub format: String,
+    pub chunk_ids: Vec<i64>,
+    pub characters: usize,
+    // Chunks stored under this source by an earlier ingest and now replaced
+    pub replaced_chunks: usize,
+    pub embedding_model: String,
+}
+
+// Extracted text, one entry per page for PDFs and a single entry otherwise
+struct ExtractedDocument {
+    format: &'static str,
+    pages: Vec<String>,
+}
+
+fn extract_text(path: &Path) -> Result<ExtractedDocument> {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "txt" | "text" | "log" => Ok(ExtractedDocument { format: "text", pages: vec![std::fs::read_to_string(path)?] }),
+        "md" | "markdown" => Ok(ExtractedDocument { format: "markdown", pages: vec![std::fs::read_to_string(path)?] }),
+        "pdf" => {
+            let pages = pdf_extract::extract_text_by_pages(path)
+                .map_err(|e| anyhow::anyhow!("Failed to extract text from PDF: {}", e))?;
+            Ok(ExtractedDocument { format: "pdf", pages })
+        }
+        _ => Err(anyhow::anyhow!("Unsupported document type '{}'. Supported: txt, md, pdf", extension)),
+    }
+}
+
+// Byte ranges of `text` split into chunks of about `chunk_size` characters that
+// overlap by `overlap` characters. Chunks end at whitespace where possible.
+pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_at = |index: usize| chars.get(index).map_or(text.len(), |&(byte, _)| byte);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        while start < chars.len() && chars[start].1.is_whitespace() {
+            start += 1;
+        }
+        if start == chars.len() {
+            break;
+        }
+
+        let mut end = (start + chunk_size).min(chars.len());
+        if end < chars.len() {
+            // Break at the last whitespace in the second half of the chunk
+            if let Some(space) = (start + chunk_size / 2..end).rev().find(|&i| chars[i].1.is_whitespace()) {
+                end = space;
+            }
+        }
+
+        let chunk = text[byte_at(start)..byte_at(end)].trim_end();
+        if !chunk.is_empty() {
+            ranges.push((byte_at(start), byte_at(start) + chunk.len()));
+        }
+        if end == chars.len() {
+            break;
+        }
+
+        // Step back for the overlap, then forward to the start of a word
+        let mut next = end.saturating_sub(overlap).max(start + 1);
+        while next < end && !chars[next - 1].1.is_whitespace() {
+            next += 1;
+        }
+        start = next;
+    }
+    ranges
+}
+
+#[command]
+pub async fn ingest_document(
+    path: String,
+    index: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<IngestReport, String> {
+    let settings = ai.get_rag_settings();
+    let index = index.unwrap_or_else(|| DEFAULT_INDEX.to_string());
+    let file_path = std::path::PathBuf::from(&path);
+    if !file_path.is_file() {
+        return Err(format!("Document not found: {}", path));
+    }
+    vector_store::require_index(&app_handle, &index)?;
+
+    // PDF parsing is CPU-bound
+    let document = tokio::task::spawn_blocking(move || extract_text(&file_path))
+        .await
+        .map_err(|e| format!("Ingest error: {}", e))?
+        .map_err(|e| format!("Ingest error: {}", e))?;
+
+    let file_name = Path::new(&path).file_name().and_then(|name| name.to_str()).unwrap_or(&path).to_string();
+    let mut chunks = Vec::new();
+    for (page_index, page) in document.pages.iter().enumerate() {
+        for (start, end) in chunk_text(page, settings.chunk_size, settings.chunk_overlap) {
+            let mut metadata = serde_json::json!({
+                "file_name": file_name,
+                "format": document.format,
+                "chunk_index": chunks.len(),
+                "start": start,
+                "end": end,
+            });
+            if document.format == "pdf" {
+                metadata["page"] = serde_json::json!(page_index + 1);
+            }
+            chunks.push(NewChunk { text: page[start..end].to_string(), source: path.clone(), metadata });
+        }
+    }
+    if chunks.is_empty() {
+        return Err(format!("No text could be extracted from {}", path));
+    }
+
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+    let (embedding_model, embeddings) = vector_store::embed_in_batches(&ai, &texts).await
+        .map_err(|e| format!("Embedding error: {}", e))?;
+
+    let mut store = VectorStore::new(&app_handle).map_err(|e| format!("Vector store error: {}", e))?;
+    let replaced_chunks = store.delete_source(&index, &path).map_err(|e| format!("Vector store error: {}", e))?;
+    let chunk_ids = store.insert(&index, &embedding_model, &chunks, &embeddings)
+        .map_err(|e| format!("Vector store error: {}", e))?;
+
+    Ok(IngestReport {
+        index,
+        source: path,
+        format: document.format.to_string(),
+        chunk_ids,
+        characters: document.pages.iter().map(|page| page.chars().count()).sum(),
+        replaced_chunks,
+        embedding_model,
+    })
+}
@@ -0,0 +1,237 @@
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use std::f32::consts::PI;
+use crate::filters::FilterKind;
+
+// About 43 ms at 48 kHz with half-frame overlap; long enough to resolve
+// low bands, short enough to follow speech
+const FRAME_SIZE: usize = 2048;
+const HOP_SIZE: usize = 1024;
+
+const MEL_FILTERS: usize = 26;
+const MFCC_COEFFICIENTS: usize = 13;
+
+// Share of the spectral energy below the rolloff frequency
+const ROLLOFF_FRACTION: f32 = 0.85;
+
+// Frames quieter than this are left out of the spectral shape averages
+const SILENT_FRAME_POWER: f32 = 1e-10;
+
+// Named bands for describing a sound's balance, in Hz
+const BANDS: [(&str, f32, f32); 7] = [
+    ("sub_bass", 20.0, 60.0),
+    ("bass", 60.0, 250.0),
+    ("low_mid", 250.0, 500.0),
+    ("mid", 500.0, 2000.0),
+    ("high_mid", 2000.0, 4000.0),
+    ("presence", 4000.0, 6000.0),
+    ("brilliance", 6000.0, 20000.0),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BandEnergy {
+    pub name: String,
+    pub low_hz: f32,
+    pub high_hz: f32,
+    // Mean power in the band, in dB relative to full scale
+    pub energy_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioFeatures {
+    pub sample_count: usize,
+    pub duration: f64,
+    pub avg_amplitude: f32,
+    pub peak_amplitude: f32,
+    pub rms_energy: f32,
+    pub zero_crossings: usize,
+    // Crossings per sample
+    pub zero_crossing_rate: f32,
+    // Where the spectrum's energy is centred, in Hz; brighter sounds are higher
+    pub spectral_centroid: f32,
+    pub spectral_rolloff: f32,
+    // About 0.56 for white noise and near 0 for a pure tone
+    pub spectral_flatness: f32,
+    pub band_energies: Vec<BandEnergy>,
+    pub mfcc: Vec<f32>,
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// Triangular filters evenly spaced on the mel scale up to Nyquist, as a
+// weight for every FFT bin
+fn mel_filterbank(sample_rate: u32, bins: usize) -> Vec<Vec<f32>> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let top = hz_to_mel(nyquist);
+    let edges: Vec<f32> = (0..MEL_FILTERS + 2)
+        .map(|index| mel_to_hz(top * index as f32 / (MEL_FILTERS + 1) as f32))
+        .collect();
+    let hz_per_bin = nyquist / (bins - 1) as f32;
+    (0..MEL_FILTERS)
+        .map(|filter| {
+            let (low, centre, high) = (edges[filter], edges[filter + 1], edges[filter + 2]);
+            (0..bins)
+                .map(|bin| {
+                    let hz = bin as f32 * hz_per_bin;
+                    if hz <= low || hz >= high {
+                        0.0
+                    } else if hz <= centre {
+                        (hz - low) / (centre - low)
+                    } else {
+                        (high - hz) / (high - centre)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn to_db(power: f32) -> f32 {
+    10.0 * power.max(SILENT_FRAME_POWER).log10()
+}
+
+// Amplitude, spectral shape, band energies and MFCCs of a mono signal. The
+// spectral measures are averaged over Hann-windowed frames.
+pub fn analyze(samples: &[f32], sample_rate: u32) -> AudioFeatures {
+    let sample_rate = sample_rate.max(1);
+    let count = samples.len().max(1) as f32;
+    let peak_amplitude = samples.iter().fold(0.0f32, |peak, &sample| peak.max(sample.abs()));
+    let avg_amplitude = samples.iter().map(|sample| sample.abs()).sum::<f32>() / count;
+    let rms_energy = (samples.iter().map(|sample| sample * sample).sum::<f32>() / count).sqrt();
+    let zero_crossings = samples.windows(2).filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0)).count();
+
+    let window: Vec<f32> = (0..FRAME_SIZE).map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / (FRAME_SIZE - 1) as f32).cos()).collect();
+    // Turns |X|² summed over bins into mean-square signal power
+    let power_scale = 2.0 / (FRAME_SIZE as f32 * window.iter().map(|weight| weight * weight).sum::<f32>());
+    let bins = FRAME_SIZE / 2 + 1;
+    let hz_per_bin = sample_rate as f32 / FRAME_SIZE as f32;
+    let filterbank = mel_filterbank(sample_rate, bins);
+    let fft = FftPlanner::new().plan_fft_forward(FRAME_SIZE);
+
+    let mut band_power = [0.0f32; BANDS.len()];
+    let (mut centroid, mut rolloff, mut flatness) = (0.0, 0.0, 0.0);
+    let mut mfcc = vec![0.0f32; MFCC_COEFFICIENTS];
+    let (mut frames, mut voiced_frames) = (0, 0);
+    let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+
+    let mut start = 0;
+    loop {
+        // A signal shorter than a frame is zero-padded into one
+        let frame = &samples[start.min(samples.len())..(start + FRAME_SIZE).min(samples.len())];
+        for (index, value) in buffer.iter_mut().enumerate() {
+            *value = Complex::new(frame.get(index).copied().unwrap_or(0.0) * window[index], 0.0);
+        }
+        fft.process(&mut buffer);
+        let power: Vec<f32> = buffer[..bins].iter().map(|value| value.norm_sqr() * power_scale).collect();
+        frames += 1;
+
+        for (total, &(_, low, high)) in band_power.iter_mut().zip(&BANDS) {
+            let (from, to) = ((low / hz_per_bin).ceil() as usize, ((high / hz_per_bin).floor() as usize).min(bins - 1));
+            *total += power.get(from..=to).map_or(0.0, |band| band.iter().sum());
+        }
+
+        let total: f32 = power.iter().sum();
+        if total > SILENT_FRAME_POWER {
+            voiced_frames += 1;
+            centroid += power.iter().enumerate().map(|(bin, &p)| bin as f32 * hz_per_bin * p).sum::<f32>() / total;
+            let mut cumulative = 0.0;
+            let rolloff_bin = power.iter().position(|&p| {
+                cumulative += p;
+                cumulative >= ROLLOFF_FRACTION * total
+            });
+            rolloff += rolloff_bin.unwrap_or(bins - 1) as f32 * hz_per_bin;
+            let log_mean = power.iter().map(|&p| p.max(SILENT_FRAME_POWER).ln()).sum::<f32>() / bins as f32;
+            flatness += log_mean.exp() / (total / bins as f32);
+
+            let log_energies: Vec<f32> = filterbank
+                .iter()
+                .map(|weights| weights.iter().zip(&power).map(|(weight, p)| weight * p).sum::<f32>().max(SILENT_FRAME_POWER).ln())
+                .collect();
+            for (coefficient, value) in mfcc.iter_mut().enumerate() {
+                *value += log_energies
+                    .iter()
+                    .enumerate()
+                    .map(|(filter, energy)| energy * (PI * coefficient as f32 * (filter as f32 + 0.5) / MEL_FILTERS as f32).cos())
+                    .sum::<f32>();
+            }
+        }
+
+        start += HOP_SIZE;
+        if start + FRAME_SIZE > samples.len() {
+            break;
+        }
+    }
+
+    let voiced = voiced_frames.max(1) as f32;
+    let nyquist = sample_rate as f32 / 2.0;
+    AudioFeatures {
+        sample_count: samples.len(),
+        duration: samples.len() as f64 / sample_rate as f64,
+        avg_amplitude,
+        peak_amplitude,
+        rms_energy,
+        zero_crossings,
+        zero_crossing_rate: zero_crossings as f32 / count,
+        spectral_centroid: centroid / voiced,
+        spectral_rolloff: rolloff / voiced,
+        spectral_flatness: flatness / voiced,
+        band_energies: BANDS
+            .iter()
+            .zip(band_power)
+            .filter(|(&(_, low, _), _)| low < nyquist)
+            .map(|(&(name, low, high), power)| BandEnergy {
+                name: name.to_string(),
+                low_hz: low,
+                high_hz: high.min(nyquist),
+                energy_db: to_db(power / frames as f32),
+            })
+            .collect(),
+        mfcc: mfcc.into_iter().map(|value| value / voiced).collect(),
+    }
+}
+
+// A biquad in direct form I, on f64 so narrow notches stay stable at low
+// frequencies and the K-weighting exact at low levels
+#[derive(Clone)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    // RBJ cookbook coefficients
+    pub fn new(kind: FilterKind, frequency_hz: f64, q: f64, sample_rate: f64) -> Self {
+        let w0 = 2.0 * std::f64::consts::PI * frequency_hz / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        let b = match kind {
+            FilterKind::HighPass => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            FilterKind::LowPass => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            FilterKind::Notch => [1.0, -2.0 * cos, 1.0],
+        };
+        Self::from_coefficients(b.map(|coefficient| coefficient / a0), [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    // Coefficients already divided by a0, which is left out
+    pub fn from_coefficients(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
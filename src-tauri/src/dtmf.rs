@@ -0,0 +1,220 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_capture::StreamFormat;
+use crate::audio_file;
+use crate::database::{Database, DtmfDigit};
+
+pub const DTMF_DETECTED_EVENT: &str = "dwight://dtmf-detected";
+
+const ROW_HZ: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+const COLUMN_HZ: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+// The classic 205 samples at 8 kHz, fine enough to tell neighbouring rows
+// apart. Blocks overlap by half.
+const BLOCK_SECONDS: f64 = 0.0256;
+
+// Blocks quieter than this don't hold a tone
+const MIN_POWER_DB: f32 = -40.0;
+
+// The pair must carry most of the block's energy, which rules out speech and
+// music, and each tone must beat the rest of its group by 6 dB
+const MIN_TONE_SHARE: f32 = 0.5;
+const MIN_GROUP_RATIO: f32 = 4.0;
+
+// Most the row and column tones may differ by, 8 dB
+const MAX_TWIST: f32 = 6.3;
+
+// A digit starts after this many blocks in a row agree, about 40 ms, and
+// ends after this many without it
+const START_BLOCKS: u32 = 2;
+const END_BLOCKS: u32 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DtmfDetection {
+    pub digit: String,
+    // In seconds since capture started
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    // File of the recording running when the digit was heard
+    pub recording_file: Option<String>,
+}
+
+// A digit still sounding, in mono frames
+struct OpenDigit {
+    digit: char,
+    start: u64,
+    end: u64,
+    misses: u32,
+}
+
+// Finds dialed digits with the Goertzel algorithm, which measures just the
+// eight DTMF frequencies instead of a whole spectrum
+pub struct DtmfDetector {
+    channels: usize,
+    sample_rate: f64,
+    block_size: usize,
+    hop: usize,
+    // Rows then columns
+    coefficients: [f32; 8],
+    // Mono audio not yet measured, and the frame it starts at
+    pending: Vec<f32>,
+    pending_start: u64,
+    // Digit heard in the last blocks, in how many, and where the first started
+    candidate: Option<(char, u32, u64)>,
+    current: Option<OpenDigit>,
+}
+
+impl DtmfDetector {
+    pub fn new(format: StreamFormat) -> Self {
+        let sample_rate = format.sample_rate.max(1) as f64;
+        let block_size = ((sample_rate * BLOCK_SECONDS).round() as usize).max(2);
+        let mut coefficients = [0.0f32; 8];
+        for (coefficient, &hz) in coefficients.iter_mut().zip(ROW_HZ.iter().chain(&COLUMN_HZ)) {
+            *coefficient = 2.0 * (2.0 * std::f64::consts::PI * hz as f64 / sample_rate).cos() as f32;
+        }
+        DtmfDetector {
+            channels: format.channels.max(1) as usize,
+            sample_rate,
+            block_size,
+            hop: block_size / 2,
+            coefficients,
+            pending: Vec::new(),
+            pending_start: 0,
+            candidate: None,
+            current: None,
+        }
+    }
+
+    // Energy of one frequency over the block
+    fn goertzel(block: &[f32], coefficient: f32) -> f32 {
+        let (mut previous, mut before) = (0.0f32, 0.0f32);
+        for &sample in block {
+            let current = sample + coefficient * previous - before;
+            before = previous;
+            previous = current;
+        }
+        // Scaled to the energy the tone adds to the block
+        (previous * previous + before * before - coefficient * previous * before) * 2.0 / block.len() as f32
+    }
+
+    fn classify(&self, block: &[f32]) -> Option<char> {
+        let energy: f32 = block.iter().map(|sample| sample * sample).sum();
+        if energy / (block.len() as f32) < 10f32.powf(MIN_POWER_DB / 10.0) {
+            return None;
+        }
+        let powers: Vec<f32> = self.coefficients.iter().map(|&coefficient| Self::goertzel(block, coefficient)).collect();
+        let strongest = |group: &[f32]| -> Option<(usize, f32)> {
+            let (index, &power) = group.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+            let clear = group.iter().enumerate().all(|(other, &rest)| other == index || rest * MIN_GROUP_RATIO <= power);
+            clear.then_some((index, power))
+        };
+        let (row, row_power) = strongest(&powers[..4])?;
+        let (column, column_power) = strongest(&powers[4..])?;
+
+        let twist = row_power / column_power.max(f32::MIN_POSITIVE);
+        if !(1.0 / MAX_TWIST..=MAX_TWIST).contains(&twist) || row_power + column_power < MIN_TONE_SHARE * energy {
+            return None;
+        }
+        Some(KEYS[row][column])
+    }
+
+    fn digit(&self, open: &OpenDigit) -> DtmfDigit {
+        DtmfDigit {
+            digit: open.digit.to_string(),
+            start_seconds: open.start as f64 / self.sample_rate,
+            end_seconds: open.end as f64 / self.sample_rate,
+        }
+    }
+
+    fn advance(&mut self, heard: Option<char>, start: u64, end: u64, digits: &mut Vec<DtmfDigit>) {
+        if let Some(open) = self.current.as_mut() {
+            if heard == Some(open.digit) {
+                open.end = end;
+                open.misses = 0;
+                return;
+            }
+            open.misses += 1;
+            if open.misses < END_BLOCKS {
+                return;
+            }
+            if let Some(open) = self.current.take() {
+                digits.push(self.digit(&open));
+            }
+        }
+
+        self.candidate = match (heard, self.candidate) {
+            (Some(digit), Some((candidate, count, first))) if digit == candidate => Some((digit, count + 1, first)),
+            (Some(digit), _) => Some((digit, 1, start)),
+            (None, _) => None,
+        };
+        if let Some((digit, _, first)) = self.candidate.filter(|&(_, count, _)| count >= START_BLOCKS) {
+            self.current = Some(OpenDigit { digit, start: first, end, misses: 0 });
+            self.candidate = None;
+        }
+    }
+
+    // Feeds audio and returns the digits that ended in it, timed from the
+    // first sample fed
+    pub fn process(&mut self, samples: &[f32]) -> Vec<DtmfDigit> {
+        let channels = self.channels;
+        self.pending.extend(samples.chunks_exact(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32));
+        let mut digits = Vec::new();
+        while self.pending.len() >= self.block_size {
+            let heard = self.classify(&self.pending[..self.block_size]);
+            let start = self.pending_start;
+            self.advance(heard, start, start + self.block_size as u64, &mut digits);
+            self.pending.drain(..self.hop);
+            self.pending_start += self.hop as u64;
+        }
+        digits
+    }
+
+    // The digit still sounding when the audio ends
+    pub fn finish(&mut self) -> Option<DtmfDigit> {
+        self.current.take().map(|open| self.digit(&open))
+    }
+}
+
+fn scan(path: &Path) -> Result<Vec<DtmfDigit>> {
+    let mut detector = DtmfDetector::new(audio_file::spec_format(&audio_file::audio_spec(path)?));
+    let mut digits = Vec::new();
+    audio_file::for_each_chunk(path, |chunk| {
+        digits.extend(detector.process(chunk));
+        Ok(())
+    })?;
+    digits.extend(detector.finish());
+    Ok(digits)
+}
+
+// Finds the digits dialed in a recording and stores them in its log,
+// replacing what an earlier scan or live capture found
+#[command]
+pub async fn detect_dtmf(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<DtmfDigit>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let digits = tokio::task::spawn_blocking(move || scan(&source))
+        .await
+        .map_err(|e| format!("DTMF error: {}", e))?
+        .map_err(|e| format!("DTMF error: {}", e))?;
+    db.save_dtmf_digits(recording_id, &digits).map_err(|e| format!("Database error: {}", e))?;
+    Ok(digits)
+}
+
+// The logged digits of a recording, in order
+#[command]
+pub async fn get_dtmf_digits(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<DtmfDigit>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_dtmf_digits(recording_id).map_err(|e| format!("Database error: {}", e))
+}
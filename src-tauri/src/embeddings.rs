@@ -0,0 +1,164 @@
+#[cfg(feature = "local-embeddings")]
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+
+#[cfg(feature = "local-embeddings")]
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Batches of transcript chunks can take a while on CPU-only machines
+const EMBED_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Sentence embeddings from an Ollama embedding model such as nomic-embed-text
+pub struct OllamaEmbedder {
+    client: reqwest::Client,
+}
+
+impl OllamaEmbedder {
+    pub fn new(client: reqwest::Client) -> Self {
+        OllamaEmbedder { client }
+    }
+
+    // One vector per text, in order. Uses the batch /api/embed endpoint and
+    // falls back to one /api/embeddings call per text on older Ollama versions.
+    pub async fn embed(&self, host: &str, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self.client
+            .post(format!("{}/api/embed", host))
+            .json(&serde_json::json!({ "model": model, "input": texts }))
+            .timeout(EMBED_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Ollama connection failed: {}. Ensure Ollama is running with 'ollama serve'", e))?;
+
+        // Older versions don't have /api/embed; a missing model is also a 404 and fails again below
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            let mut embeddings = Vec::with_capacity(texts.len());
+            for text in texts {
+                embeddings.push(self.embed_one(host, model, text).await?);
+            }
+            return Ok(embeddings);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Ollama returned status: {} while embedding with '{}'", response.status(), model));
+        }
+
+        let result: serde_json::Value = response.json().await?;
+        let embeddings: Vec<Vec<f32>> = serde_json::from_value(result["embeddings"].clone())
+            .map_err(|e| anyhow::anyhow!("Invalid Ollama embedding response: {}", e))?;
+
+        if embeddings.len() != texts.len() {
+            return Err(anyhow::anyhow!("Ollama returned {} embeddings for {} texts", embeddings.len(), texts.len()));
+        }
+        Ok(embeddings)
+    }
+
+    async fn embed_one(&self, host: &str, model: &str, text: &str) -> Result<Vec<f32>> {
+        let response = self.client
+            .post(format!("{}/api/embeddings", host))
+            .json(&serde_json::json!({ "model": model, "prompt": text }))
+            .timeout(EMBED_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Ollama connection failed: {}. Ensure Ollama is running with 'ollama serve'", e))?;
+
+        match response.status() {
+            status if status.is_success() => {
+                let result: serde_json::Value = response.json().await?;
+                serde_json::from_value(result["embedding"].clone())
+                    .map_err(|e| anyhow::anyhow!("Invalid Ollama embedding response: {}", e))
+            }
+            reqwest::StatusCode::NOT_FOUND => {
+                Err(anyhow::anyhow!("Embedding model '{}' is not installed. Try 'ollama pull {}'", model, model))
+            }
+            status => Err(anyhow::anyhow!("Ollama returned status: {} while embedding with '{}'", status, model)),
+        }
+    }
+}
+
+// Offline fallback: a small ONNX sentence-embedding model run in-process. The
+// model files are downloaded once into the app data dir on first use.
+pub struct LocalEmbedder {
+    #[cfg(feature = "local-embeddings")]
+    cache_dir: RwLock<Option<PathBuf>>,
+    #[cfg(feature = "local-embeddings")]
+    model: tokio::sync::OnceCell<Arc<TextEmbedding>>,
+}
+
+impl LocalEmbedder {
+    pub fn new() -> Self {
+        LocalEmbedder {
+            #[cfg(feature = "local-embeddings")]
+            cache_dir: RwLock::new(None),
+            #[cfg(feature = "local-embeddings")]
+            model: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    // Name recorded alongside vectors produced by this embedder
+    pub fn model_name(&self) -> &'static str {
+        "fastembed/all-MiniLM-L6-v2"
+    }
+
+    #[cfg(feature = "local-embeddings")]
+    pub fn set_cache_dir(&self, dir: PathBuf) {
+        if let Ok(mut cache_dir) = self.cache_dir.write() {
+            *cache_dir = Some(dir);
+        }
+    }
+
+    #[cfg(not(feature = "local-embeddings"))]
+    pub fn set_cache_dir(&self, _dir: PathBuf) {}
+
+    // Loading and inference are blocking, so both run on the blocking thread pool
+    #[cfg(feature = "local-embeddings")]
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = self.model
+            .get_or_try_init(|| async {
+                let cache_dir = self.cache_dir.read().ok().and_then(|dir| dir.clone());
+                tokio::task::spawn_blocking(move || {
+                    let mut options = InitOptions::new(EmbeddingModel::AllMiniLML6V2).with_show_download_progress(false);
+                    if let Some(dir) = cache_dir {
+                        options = options.with_cache_dir(dir);
+                    }
+                    TextEmbedding::try_new(options)
+                        .map(Arc::new)
+                        .map_err(|e| anyhow::anyhow!("Failed to load local embedding model: {}", e))
+                })
+                .await?
+            })
+            .await?
+            .clone();
+
+        let texts = texts.to_vec();
+        tokio::task::spawn_blocking(move || model.embed(texts, None))
+            .await?
+            .map_err(|e| anyhow::anyhow!("Local embedding failed: {}", e))
+    }
+
+    #[cfg(not(feature = "local-embeddings"))]
+    pub async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        // Fallback implementation when local embeddings are disabled
+        Err(anyhow::anyhow!("Local embeddings not enabled. Please compile with 'local-embeddings' feature."))
+    }
+}
+
+// 0.0 for mismatched or zero-length vectors rather than NaN
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+use crate::audio_capture::{AudioCapture, StreamFormat};
+use crate::audio_file::{self, WavOutput};
+use crate::database::Database;
+use crate::dsp::Biquad;
+
+// Longer chains add little but cost on every sample of live capture
+const MAX_FILTERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    // Cuts below the frequency, e.g. rumble and handling noise under 100 Hz
+    HighPass,
+    // Cuts above it, e.g. hiss over what speech needs
+    LowPass,
+    // Cuts a narrow band around it, e.g. 50 or 60 Hz mains hum
+    Notch,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub frequency_hz: f32,
+    // 0.707 is the flattest high- or low-pass; a notch gets narrower as it rises
+    pub q: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            kind: FilterKind::HighPass,
+            frequency_hz: 100.0,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+        }
+    }
+}
+
+pub fn validate_filters(filters: &[Filter]) -> Result<(), String> {
+    if filters.len() > MAX_FILTERS {
+        return Err(format!("At most {} filters can be chained", MAX_FILTERS));
+    }
+    for filter in filters {
+        if !(10.0..=20000.0).contains(&filter.frequency_hz) {
+            return Err("Filter frequency_hz must be between 10 and 20000".to_string());
+        }
+        if !(0.1..=50.0).contains(&filter.q) {
+            return Err("Filter q must be between 0.1 and 50".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Runs audio through filters in order, each channel with its own state
+pub struct FilterChain {
+    // One chain of biquads per channel
+    channels: Vec<Vec<Biquad>>,
+}
+
+impl FilterChain {
+    // Filters at or above Nyquist have nothing to act on and are left out
+    pub fn new(filters: &[Filter], format: StreamFormat) -> Self {
+        let sample_rate = format.sample_rate.max(1) as f64;
+        let biquads: Vec<Biquad> = filters.iter()
+            .filter(|filter| (filter.frequency_hz as f64) < sample_rate / 2.0)
+            .map(|filter| Biquad::new(filter.kind, filter.frequency_hz as f64, filter.q as f64, sample_rate))
+            .collect();
+        FilterChain { channels: vec![biquads; format.channels.max(1) as usize] }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.channels.len();
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, biquads) in frame.iter_mut().zip(self.channels.iter_mut()) {
+                let mut value = *sample as f64;
+                for biquad in biquads.iter_mut() {
+                    value = biquad.process(value);
+                }
+                *sample = value as f32;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilteredRecording {
+    pub record_id: i64,
+    pub file_path: String,
+    pub filters: Vec<Filter>,
+}
+
+fn filtered_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}-filtered.wav", stem))
+}
+
+// Writes a filtered copy of the recording; the original file is never modified
+fn filter_file(path: &Path, filters: &[Filter]) -> Result<PathBuf> {
+    let spec = audio_file::audio_spec(path)?;
+    let mut chain = FilterChain::new(filters, audio_file::spec_format(&spec));
+    let output_path = filtered_path(path);
+    let mut output = WavOutput::create(&output_path, spec)?;
+    audio_file::for_each_chunk(path, |chunk| {
+        let mut filtered = chunk.to_vec();
+        chain.process(&mut filtered);
+        output.write(&filtered)
+    })?;
+    output.finish()?;
+    Ok(output_path)
+}
+
+// Saves a copy of a recording run through `filters`, or the chain in the
+// capture settings without them, as its "filtered" version, e.g. to clear
+// up speech before transcribing it
+#[command]
+pub async fn filter_recording(
+    record_id: i64,
+    filters: Option<Vec<Filter>>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<FilteredRecording, String> {
+    let filters = filters.unwrap_or_else(|| capture.get_settings().filters);
+    if filters.is_empty() {
+        return Err("No filters to apply".to_string());
+    }
+    validate_filters(&filters)?;
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(record_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", record_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let chain = filters.clone();
+    let path = tokio::task::spawn_blocking(move || filter_file(&source, &chain))
+        .await
+        .map_err(|e| format!("Filter error: {}", e))?
+        .map_err(|e| format!("Filter error: {}", e))?;
+
+    let file_path = path.to_string_lossy().to_string();
+    db.save_audio_version(record_id, "filtered", &file_path).map_err(|e| format!("Database error: {}", e))?;
+    Ok(FilteredRecording { record_id, file_path, filters })
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use rusty_chromaprint::{match_fingerprints, Configuration, Fingerprinter};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tauri::command;
+use crate::audio_file;
+use crate::database::{AudioFingerprint, Database};
+
+// Only the start of longer recordings is fingerprinted, which keeps
+// comparing every pair of an overnight archive affordable
+const MAX_FINGERPRINT_SECONDS: f64 = 1800.0;
+
+// Less than this can't be told apart from a chance resemblance
+const MIN_FINGERPRINT_SECONDS: f32 = 3.0;
+
+// Matched stretches scoring above this many differing bits out of 32 are
+// too weak to count
+const MAX_SEGMENT_SCORE: f64 = 10.0;
+
+// Matching over this much of the longer recording makes it the same audio
+const DUPLICATE_COVERAGE: f64 = 0.9;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateMatch {
+    pub record_id: i64,
+    pub other_id: i64,
+    // Share of the shorter recording also found in the other, from 0 to 1
+    pub similarity: f64,
+    pub matched_seconds: f64,
+    // Where the longest match starts in each recording
+    pub offset_seconds: f64,
+    pub other_offset_seconds: f64,
+    // "duplicate" for the same audio, e.g. a re-encoded copy; "near_duplicate"
+    // when one holds part of the other
+    pub kind: String,
+}
+
+fn compute(path: &Path, config: &Configuration) -> Result<Vec<u32>> {
+    let format = audio_file::audio_spec(path)?;
+    let mut fingerprinter = Fingerprinter::new(config);
+    fingerprinter.start(format.sample_rate, format.channels.max(1) as u32)
+        .map_err(|e| anyhow::anyhow!("Can't fingerprint this audio: {:?}", e))?;
+    audio_file::for_each_chunk_between(path, 0.0, MAX_FINGERPRINT_SECONDS, |chunk| {
+        let samples: Vec<i16> = chunk.iter().map(|&sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).collect();
+        fingerprinter.consume(&samples);
+        Ok(())
+    })?;
+    fingerprinter.finish();
+    Ok(fingerprinter.fingerprint().to_vec())
+}
+
+// The stored fingerprint of a recording, taken again if its file changed
+fn fingerprint(db: &Database, record_id: i64, path: &Path, config: &Configuration) -> Result<Vec<u32>> {
+    let metadata = std::fs::metadata(path)?;
+    let source_len = metadata.len() as i64;
+    let source_modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+    if let Some(stored) = db.get_fingerprint(record_id)?
+        .filter(|stored| stored.source_len == source_len && stored.source_modified == source_modified)
+    {
+        return Ok(stored.fingerprint);
+    }
+
+    let fingerprint = compute(path, config)?;
+    db.save_fingerprint(&AudioFingerprint { record_id, source_len, source_modified, fingerprint: fingerprint.clone() })?;
+    Ok(fingerprint)
+}
+
+fn compare(
+    (record_id, first): (i64, &[u32]),
+    (other_id, second): (i64, &[u32]),
+    config: &Configuration,
+    min_similarity: f64,
+) -> Result<Option<DuplicateMatch>> {
+    let item_seconds = config.item_duration_in_seconds() as f64;
+    let segments: Vec<_> = match_fingerprints(first, second, config)?
+        .into_iter()
+        .filter(|segment| segment.score <= MAX_SEGMENT_SCORE)
+        .collect();
+    let matched_seconds: f64 = segments.iter().map(|segment| segment.duration(config) as f64).sum();
+    let (shorter, longer) = {
+        let (first, second) = (first.len() as f64 * item_seconds, second.len() as f64 * item_seconds);
+        (first.min(second), first.max(second))
+    };
+    let similarity = (matched_seconds / shorter).min(1.0);
+    let Some(longest) = segments.iter().max_by_key(|segment| segment.items_count) else {
+        return Ok(None);
+    };
+    if similarity < min_similarity {
+        return Ok(None);
+    }
+
+    let duplicate = matched_seconds / longer >= DUPLICATE_COVERAGE;
+    Ok(Some(DuplicateMatch {
+        record_id,
+        other_id,
+        similarity,
+        matched_seconds,
+        offset_seconds: longest.start1(config) as f64,
+        other_offset_seconds: longest.start2(config) as f64,
+        kind: if duplicate { "duplicate" } else { "near_duplicate" }.to_string(),
+    }))
+}
+
+fn find(db: &Database, recording_id: Option<i64>, min_similarity: f64) -> Result<Vec<DuplicateMatch>> {
+    let config = Configuration::preset_test2();
+    let mut fingerprints = Vec::new();
+    // Segments split from a recording are its near-duplicates by design
+    let mut segments = HashSet::new();
+    for record in db.get_all_audio_records()? {
+        let Some(record_id) = record.id.map(i64::from) else {
+            continue;
+        };
+        match fingerprint(db, record_id, Path::new(&record.file_path), &config) {
+            Ok(fingerprint) if fingerprint.len() as f32 * config.item_duration_in_seconds() >= MIN_FINGERPRINT_SECONDS => {
+                fingerprints.push((record_id, fingerprint));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to fingerprint recording {}: {}", record_id, e),
+        }
+        for segment in db.get_recording_segments(record_id)? {
+            segments.insert((record_id, segment.record_id));
+            segments.insert((segment.record_id, record_id));
+        }
+    }
+    if let Some(recording_id) = recording_id {
+        if !fingerprints.iter().any(|(record_id, _)| *record_id == recording_id) {
+            return Err(anyhow::anyhow!("Recording {} has no audio long enough to fingerprint", recording_id));
+        }
+    }
+
+    let mut matches = Vec::new();
+    for (index, (record_id, first)) in fingerprints.iter().enumerate() {
+        for (other_id, second) in &fingerprints[index + 1..] {
+            let wanted = recording_id.is_none_or(|id| id == *record_id || id == *other_id);
+            if !wanted || segments.contains(&(*record_id, *other_id)) {
+                continue;
+            }
+            if let Some(found) = compare((*record_id, first), (*other_id, second), &config, min_similarity)? {
+                matches.push(found);
+            }
+        }
+    }
+    matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    Ok(matches)
+}
+
+// Recordings that share audio, most alike first: all pairs, or those with
+// `recording_id` such as a new import. Fingerprints are kept in the
+// database, so only new or changed files are read.
+#[command]
+pub async fn find_duplicates(
+    recording_id: Option<i64>,
+    min_similarity: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<DuplicateMatch>, String> {
+    let min_similarity = min_similarity.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&min_similarity) {
+        return Err("min_similarity must be between 0 and 1".to_string());
+    }
+
+    tokio::task::spawn_blocking(move || -> Result<Vec<DuplicateMatch>> {
+        let db = Database::new(&app_handle)?;
+        find(&db, recording_id, min_similarity)
+    })
+    .await
+    .map_err(|e| format!("Fingerprint error: {}", e))?
+    .map_err(|e| format!("Fingerprint error: {}", e))
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::sync::OnceLock;
+
+// Each rule can be switched on or off on its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuardrailSettings {
+    // Mask emails, phone numbers, SSNs, card numbers and IP addresses in responses
+    pub redact_pii: bool,
+    // Drop instruction-like lines and chat-template markers from RAG documents
+    pub strip_prompt_injection: bool,
+    // Cut responses down to this many characters
+    pub max_response_chars: Option<usize>,
+    // Withhold responses mentioning any of these (case-insensitive)
+    pub blocked_topics: Vec<String>,
+}
+
+impl Default for GuardrailSettings {
+    fn default() -> Self {
+        GuardrailSettings {
+            redact_pii: false,
+            strip_prompt_injection: true,
+            max_response_chars: None,
+            blocked_topics: Vec::new(),
+        }
+    }
+}
+
+impl GuardrailSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_response_chars == Some(0) {
+            return Err("max_response_chars must be greater than 0".to_string());
+        }
+        if self.blocked_topics.iter().any(|topic| topic.trim().is_empty()) {
+            return Err("Blocked topics must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn pii_patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "[REDACTED EMAIL]"),
+            (r"\b\d{3}-\d{2}-\d{4}\b", "[REDACTED SSN]"),
+            (r"\b(?:\d[ -]?){13,16}\b", "[REDACTED CARD]"),
+            (r"\b(?:\d{1,3}\.){3}\d{1,3}\b", "[REDACTED IP]"),
+            (r"(?:\+\d{1,3}[ .-]?)?\(?\b\d{3}\)?[ .-]?\d{3}[ .-]?\d{4}\b", "[REDACTED PHONE]"),
+        ]
+        .into_iter()
+        .filter_map(|(pattern, replacement)| Regex::new(pattern).ok().map(|regex| (regex, replacement)))
+        .collect()
+    })
+}
+
+fn injection_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            r"(?i)\b(ignore|disregard|forget)\b.{0,40}\b(instructions|prompt|rules)\b",
+            r"(?i)^\s*(system|assistant)\s*:",
+            r"(?i)\byou are now\b",
+            r"(?i)^\s*#{2,}\s*(instruction|system)",
+        ]
+        .into_iter()
+        .filter_map(|pattern| Regex::new(pattern).ok())
+        .collect()
+    })
+}
+
+// Chat-template tokens that could close the document and open a new turn
+const TEMPLATE_MARKERS: [&str; 8] = [
+    "<|im_start|>", "<|im_end|>", "<|system|>", "<|user|>", "<|assistant|>",
+    "[INST]", "[/INST]", "<<SYS>>",
+];
+
+fn redact_pii(text: &str) -> String {
+    pii_patterns().iter().fold(text.to_string(), |text, (regex, replacement)| {
+        regex.replace_all(&text, *replacement).into_owned()
+    })
+}
+
+pub fn sanitize_document(settings: &GuardrailSettings, document: &str) -> String {
+    if !settings.strip_prompt_injection {
+        return document.to_string();
+    }
+
+    let without_markers = TEMPLATE_MARKERS.iter().fold(document.to_string(), |text, marker| text.replace(marker, ""));
+    without_markers
+        .lines()
+        .filter(|line| !injection_patterns().iter().any(|regex| regex.is_match(line)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Prefer ending on a sentence boundary inside the limit
+fn truncate(text: &str, max_chars: usize) -> Option<String> {
+    let cut = text.char_indices().nth(max_chars)?.0;
+    let head = &text[..cut];
+    let end = head.rfind(['.', '!', '?']).map(|i| i + 1).filter(|&i| i > cut / 2).unwrap_or(cut);
+    Some(format!("{}…", head[..end].trim_end()))
+}
+
+// Applies the response rules in order and returns the names of rules that
+// changed the text. In JSON mode only redaction runs, since cutting or
+// replacing the text would break the structure.
+pub fn apply_to_response(settings: &GuardrailSettings, text: &mut String, json_mode: bool) -> Vec<String> {
+    let mut applied = Vec::new();
+
+    if settings.redact_pii {
+        let redacted = redact_pii(text);
+        if redacted != *text {
+            *text = redacted;
+            applied.push("redact_pii".to_string());
+        }
+    }
+
+    if json_mode {
+        return applied;
+    }
+
+    let lower = text.to_lowercase();
+    if let Some(topic) = settings.blocked_topics.iter().find(|topic| lower.contains(&topic.to_lowercase())) {
+        *text = format!("Response withheld: it touched on a blocked topic ({}).", topic);
+        applied.push("blocked_topics".to_string());
+        return applied;
+    }
+
+    if let Some(truncated) = settings.max_response_chars.and_then(|max| truncate(text, max)) {
+        *text = truncated;
+        applied.push("max_response_chars".to_string());
+    }
+
+    applied
+}
@@ -0,0 +1,326 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tauri::{command, Emitter, Manager, State};
+use crate::audio_capture::{self, AudioCapture, CaptureSettings, Storage};
+use crate::audio_file::{self, WavOutput};
+use crate::database::{AudioRecord, Database};
+
+pub const DROP_IMPORT_EVENT: &str = "dwight://drop-import";
+
+// Files with these extensions are taken by watch folders; anything dropped
+// or imported by hand is sniffed whatever it is called
+pub const AUDIO_EXTENSIONS: [&str; 12] = [
+    "wav", "flac", "opus", "ogg", "oga", "m4a", "mp4", "aac", "mp3", "aif", "aiff", "webm",
+];
+
+// Enough of the start of a file to tell its container apart
+const SNIFF_BYTES: usize = 64;
+
+// Containers told apart by their first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioContainer {
+    Wav,
+    Flac,
+    OggOpus,
+    OggVorbis,
+    OggFlac,
+    Mp4,
+    AdtsAac,
+    Mp3,
+    Asf,
+    Aiff,
+    Matroska,
+}
+
+impl AudioContainer {
+    fn name(self) -> &'static str {
+        match self {
+            AudioContainer::Wav => "WAV",
+            AudioContainer::Flac => "FLAC",
+            AudioContainer::OggOpus => "Ogg Opus",
+            AudioContainer::OggVorbis => "Ogg Vorbis",
+            AudioContainer::OggFlac => "Ogg FLAC",
+            AudioContainer::Mp4 => "MP4/M4A (AAC or ALAC)",
+            AudioContainer::AdtsAac => "AAC",
+            AudioContainer::Mp3 => "MP3",
+            AudioContainer::Asf => "WMA",
+            AudioContainer::Aiff => "AIFF",
+            AudioContainer::Matroska => "Matroska/WebM",
+        }
+    }
+
+    // Decoded here when the extension says so, since the readers go by it
+    fn native_extension(self) -> Option<&'static str> {
+        match self {
+            AudioContainer::Wav => Some("wav"),
+            AudioContainer::Flac => Some("flac"),
+            AudioContainer::OggOpus => Some("opus"),
+            _ => None,
+        }
+    }
+}
+
+pub fn sniff(path: &Path) -> Result<AudioContainer> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    std::fs::File::open(path)?.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    let starts = |magic: &[u8]| head.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    let contains = |magic: &[u8]| head.windows(magic.len()).any(|window| window == magic);
+
+    let container = if starts(b"RIFF") && at(8, b"WAVE") {
+        AudioContainer::Wav
+    } else if starts(b"fLaC") {
+        AudioContainer::Flac
+    } else if starts(b"OggS") {
+        // The first packet of the first page names the codec
+        if contains(b"OpusHead") {
+            AudioContainer::OggOpus
+        } else if contains(b"\x01vorbis") {
+            AudioContainer::OggVorbis
+        } else if contains(b"\x7FFLAC") {
+            AudioContainer::OggFlac
+        } else {
+            return Err(anyhow::anyhow!("{} is an Ogg file without Opus, Vorbis or FLAC audio", path.display()));
+        }
+    } else if at(4, b"ftyp") {
+        AudioContainer::Mp4
+    } else if starts(b"ID3") {
+        AudioContainer::Mp3
+    } else if head.len() >= 2 && head[0] == 0xFF && head[1] & 0xF0 == 0xF0 && head[1] & 0x06 == 0 {
+        // An ADTS frame header: the sync word and layer 0
+        AudioContainer::AdtsAac
+    } else if head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0 {
+        AudioContainer::Mp3
+    } else if starts(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        AudioContainer::Asf
+    } else if starts(b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        AudioContainer::Aiff
+    } else if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        AudioContainer::Matroska
+    } else if head.is_empty() {
+        return Err(anyhow::anyhow!("{} is empty", path.display()));
+    } else {
+        let shown: Vec<String> = head.iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+        return Err(anyhow::anyhow!("{} is not an audio format that can be imported (it starts {})", path.display(), shown.join(" ")));
+    };
+    Ok(container)
+}
+
+// A file open in symphonia, with the track to import
+struct SymphoniaSource {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    // Stored at 24 bits, whatever the codec decodes to
+    spec: hound::WavSpec,
+}
+
+fn open_with_symphonia(source: &Path, container: AudioContainer) -> Result<SymphoniaSource> {
+    if container == AudioContainer::Asf {
+        return Err(anyhow::anyhow!("{} is WMA, which can't be imported; convert it to FLAC or WAV first", source.display()));
+    }
+    let mut hint = Hint::new();
+    if let Some(extension) = source.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+    let stream = MediaSourceStream::new(Box::new(std::fs::File::open(source)?), Default::default());
+    let reader = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())?
+        .format;
+    let track = reader.tracks().iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow::anyhow!("{} has no audio track", source.display()))?;
+    let (Some(sample_rate), Some(channels)) = (track.codec_params.sample_rate, track.codec_params.channels) else {
+        return Err(anyhow::anyhow!("{} doesn't say its sample rate and channels", source.display()));
+    };
+    let decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| anyhow::anyhow!("{}'s audio can't be decoded: {}", source.display(), e))?;
+    Ok(SymphoniaSource {
+        track_id: track.id,
+        spec: hound::WavSpec {
+            channels: channels.count() as u16,
+            sample_rate,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        },
+        reader,
+        decoder,
+    })
+}
+
+// Decodes each packet of the track in turn. A corrupt packet is skipped, as
+// players do; the stream ends at the end of the file.
+fn decode_with_symphonia(mut source: SymphoniaSource, on_chunk: &mut dyn FnMut(&[f32]) -> Result<()>) -> Result<()> {
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match source.reader.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != source.track_id {
+            continue;
+        }
+        let decoded = match source.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                eprintln!("Skipped an undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if decoded.spec().channels.count() != source.spec.channels as usize || decoded.spec().rate != source.spec.sample_rate {
+            return Err(anyhow::anyhow!("The audio changes format partway through"));
+        }
+        let buffer = match &mut buffer {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * decoded.spec().channels.count() => buffer,
+            _ => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, *decoded.spec())),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        on_chunk(buffer.samples())?;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedRecording {
+    pub record_id: i64,
+    pub file_path: String,
+    pub duration: f64,
+}
+
+// Writes what `decode` hands over to a WAV in the recordings folder, then
+// stores it as the capture settings say
+fn copy_to_storage(
+    app_handle: &tauri::AppHandle,
+    mut spec: hound::WavSpec,
+    stem: &str,
+    settings: &CaptureSettings,
+    decode: impl FnOnce(&mut dyn FnMut(&[f32]) -> Result<()>) -> Result<()>,
+) -> Result<(String, f64)> {
+    let storage = settings.storage(settings.storage_profile);
+    // FLAC only stores integer samples
+    if matches!(storage, Storage::Flac) && spec.sample_format == hound::SampleFormat::Float {
+        spec.sample_format = hound::SampleFormat::Int;
+        spec.bits_per_sample = 24;
+    }
+
+    let name = format!("import-{}-{}.wav", chrono::Local::now().format(audio_capture::RECORDING_TIME_FORMAT), stem);
+    let wav_path = audio_capture::recordings_dir(app_handle)?.join(name);
+    let mut output = WavOutput::create(&wav_path, spec)?;
+    let written = decode(&mut |chunk| output.write(chunk)).and_then(|_| output.finish());
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&wav_path);
+        return Err(e);
+    }
+    let (_, duration) = audio_file::audio_info(&wav_path)?;
+    Ok((audio_capture::store(wav_path, storage).to_string_lossy().to_string(), duration))
+}
+
+// Copies an audio file into the recordings folder and saves it as a
+// recording, stored as the capture settings store recordings. WAV, FLAC and
+// Ogg Opus are read directly; other containers are decoded by symphonia
+// straight into the copy. The file itself is left where it is.
+pub fn import_file(app_handle: &tauri::AppHandle, source: &Path, title: &str, settings: &CaptureSettings) -> Result<ImportedRecording> {
+    let container = sniff(source)?;
+    let named_for_it = container.native_extension()
+        .is_some_and(|wanted| source.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(wanted)));
+
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let copied = if named_for_it {
+        audio_file::audio_spec(source).and_then(|spec| {
+            copy_to_storage(app_handle, spec, &stem, settings, |on_chunk| audio_file::for_each_chunk(source, on_chunk).map(|_| ()))
+        })
+    } else if container == AudioContainer::OggOpus {
+        // Symphonia has no Opus decoder, but ours doesn't need the extension
+        audio_file::opus_spec(source).and_then(|spec| {
+            copy_to_storage(app_handle, spec, &stem, settings, |on_chunk| audio_file::for_each_opus_chunk(source, on_chunk))
+        })
+    } else {
+        open_with_symphonia(source, container).and_then(|decoded| {
+            copy_to_storage(app_handle, decoded.spec, &stem, settings, |on_chunk| decode_with_symphonia(decoded, on_chunk))
+        })
+    };
+    let (file_path, duration) = copied.map_err(|e| anyhow::anyhow!("Failed to read {} as {}: {}", source.display(), container.name(), e))?;
+
+    let record_id = Database::new(app_handle)?.save_audio_record(&AudioRecord {
+        id: None,
+        title: title.to_string(),
+        file_path: file_path.clone(),
+        transcript: None,
+        duration,
+        created_at: String::new(),
+        triggers: None,
+    })?;
+    Ok(ImportedRecording { record_id, file_path, duration })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileImport {
+    pub source_path: String,
+    pub recording: Option<ImportedRecording>,
+    // Why the file was turned away
+    pub error: Option<String>,
+}
+
+// Imports each file in turn, titled after it; one that can't be imported
+// doesn't stop the rest
+pub async fn import_files(app_handle: &tauri::AppHandle, paths: Vec<PathBuf>, settings: CaptureSettings) -> Vec<FileImport> {
+    let mut imports = Vec::new();
+    for path in paths {
+        let handle = app_handle.clone();
+        let settings = settings.clone();
+        let source = path.clone();
+        let imported = tokio::task::spawn_blocking(move || {
+            let title = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            import_file(&handle, &source, &title, &settings)
+        })
+        .await
+        .map_err(|e| format!("Import error: {}", e))
+        .and_then(|imported| imported.map_err(|e| e.to_string()));
+        imports.push(FileImport {
+            source_path: path.to_string_lossy().to_string(),
+            error: imported.as_ref().err().cloned(),
+            recording: imported.ok(),
+        });
+    }
+    imports
+}
+
+// Files dropped on the window are imported in the background, each result
+// sent as a drop-import event
+pub fn import_dropped(app_handle: &tauri::AppHandle, paths: Vec<PathBuf>) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let settings = app_handle.state::<AudioCapture>().get_settings();
+        for import in import_files(&app_handle, paths, settings).await {
+            if let Some(error) = import.error.as_deref() {
+                eprintln!("Failed to import dropped file: {}", error);
+            }
+            if let Err(e) = app_handle.emit(DROP_IMPORT_EVENT, import) {
+                eprintln!("Failed to emit drop import event: {}", e);
+            }
+        }
+    });
+}
+
+#[command]
+pub async fn import_audio_files(
+    paths: Vec<String>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Vec<FileImport>, String> {
+    if paths.is_empty() {
+        return Err("No files to import".to_string());
+    }
+    Ok(import_files(&app_handle, paths.into_iter().map(PathBuf::from).collect(), capture.get_settings()).await)
+}
@@ -0,0 +1,302 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+use crate::audio_capture::{AudioCapture, StreamFormat};
+use crate::audio_file;
+use crate::database::{Database, InaudibleEvent};
+use crate::dsp::Biquad;
+use crate::filters::FilterKind;
+use crate::levels::to_db;
+
+pub const INAUDIBLE_ACTIVITY_EVENT: &str = "dwight://inaudible-activity";
+
+// Band levels are measured over blocks this long
+const BLOCK_SECONDS: f64 = 0.1;
+
+// Activity starts after this many loud blocks in a row and ends after this
+// many quiet ones, so a beacon's short gaps don't split it
+const START_BLOCKS: u32 = 2;
+const END_BLOCKS: u32 = 5;
+
+// The ultrasonic band needs this much room below Nyquist, where converters
+// roll off, or it only measures their noise
+const NYQUIST_MARGIN: f64 = 1.1;
+
+// Q of the two stages of a 4th-order Butterworth, steep enough that speech
+// and music don't leak into either band
+const BUTTERWORTH_Q: [f64; 2] = [0.541_196_1, 1.306_563];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InaudibleBand {
+    // Above about 18 kHz: tracking beacons, pest repellers, failing electronics
+    Ultrasonic,
+    // Below about 20 Hz: machinery, HVAC, traffic rumble
+    Infrasonic,
+}
+
+impl InaudibleBand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InaudibleBand::Ultrasonic => "ultrasonic",
+            InaudibleBand::Infrasonic => "infrasonic",
+        }
+    }
+}
+
+// Where the bands start and how loud they must be
+#[derive(Debug, Clone, Copy)]
+pub struct InaudibleSettings {
+    pub ultrasonic_hz: f32,
+    pub infrasonic_hz: f32,
+    pub threshold_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InaudibleChange {
+    pub band: InaudibleBand,
+    pub active: bool,
+    // In seconds since the first sample fed; where activity started, or
+    // where it was last heard when it ends
+    pub at_seconds: f64,
+    // Loudest block so far, in dBFS
+    pub peak_db: f32,
+}
+
+// One band's filter and the state of its activity
+struct BandTracker {
+    band: InaudibleBand,
+    filters: [Biquad; 2],
+    loud_blocks: u32,
+    quiet_blocks: u32,
+    // Start of the loud blocks in a row, in frames
+    loud_start: u64,
+    // Where the open activity was last heard, in frames, and its loudest block
+    active: Option<(u64, f32)>,
+    peak_db: f32,
+}
+
+impl BandTracker {
+    fn new(band: InaudibleBand, cutoff_hz: f64, sample_rate: f64) -> Self {
+        let kind = match band {
+            InaudibleBand::Ultrasonic => FilterKind::HighPass,
+            _ => FilterKind::LowPass,
+        };
+        BandTracker {
+            band,
+            filters: BUTTERWORTH_Q.map(|q| Biquad::new(kind, cutoff_hz, q, sample_rate)),
+            loud_blocks: 0,
+            quiet_blocks: 0,
+            loud_start: 0,
+            active: None,
+            peak_db: f32::MIN,
+        }
+    }
+
+    fn filter(&mut self, sample: f64) -> f64 {
+        self.filters.iter_mut().fold(sample, |sample, filter| filter.process(sample))
+    }
+
+    // Takes the level of the block from `start` to `end` and returns the change
+    // it makes, if any, in frames
+    fn update(&mut self, level_db: f32, threshold_db: f32, start: u64, end: u64) -> Option<(bool, u64, f32)> {
+        if level_db >= threshold_db {
+            if self.loud_blocks == 0 {
+                self.loud_start = start;
+            }
+            self.loud_blocks += 1;
+            self.quiet_blocks = 0;
+            self.peak_db = self.peak_db.max(level_db);
+            if let Some((last_heard, peak)) = self.active.as_mut() {
+                *last_heard = end;
+                *peak = peak.max(level_db);
+                return None;
+            }
+            if self.loud_blocks >= START_BLOCKS {
+                self.active = Some((end, self.peak_db));
+                return Some((true, self.loud_start, self.peak_db));
+            }
+            return None;
+        }
+
+        self.loud_blocks = 0;
+        self.quiet_blocks += 1;
+        if self.active.is_none() {
+            self.peak_db = f32::MIN;
+            return None;
+        }
+        if self.quiet_blocks < END_BLOCKS {
+            return None;
+        }
+        self.peak_db = f32::MIN;
+        self.active.take().map(|(last_heard, peak)| (false, last_heard, peak))
+    }
+}
+
+// Watches the energy above the ultrasonic cutoff and below the infrasonic
+// one. The ultrasonic band is left out at sample rates too low to hold it.
+pub struct InaudibleDetector {
+    channels: usize,
+    sample_rate: f64,
+    block_frames: u64,
+    threshold_db: f32,
+    bands: Vec<BandTracker>,
+    // Squared band levels of the block so far
+    sum_squares: Vec<f64>,
+    frames: u64,
+    block_start: u64,
+}
+
+impl InaudibleDetector {
+    pub fn new(format: StreamFormat, settings: InaudibleSettings) -> Self {
+        let sample_rate = format.sample_rate.max(1) as f64;
+        let mut bands = vec![BandTracker::new(InaudibleBand::Infrasonic, settings.infrasonic_hz as f64, sample_rate)];
+        if Self::ultrasonic_supported(format.sample_rate, settings.ultrasonic_hz) {
+            bands.push(BandTracker::new(InaudibleBand::Ultrasonic, settings.ultrasonic_hz as f64, sample_rate));
+        }
+        InaudibleDetector {
+            channels: format.channels.max(1) as usize,
+            sample_rate,
+            block_frames: ((sample_rate * BLOCK_SECONDS) as u64).max(1),
+            threshold_db: settings.threshold_db,
+            sum_squares: vec![0.0; bands.len()],
+            bands,
+            frames: 0,
+            block_start: 0,
+        }
+    }
+
+    pub fn ultrasonic_supported(sample_rate: u32, ultrasonic_hz: f32) -> bool {
+        sample_rate as f64 / 2.0 > ultrasonic_hz as f64 * NYQUIST_MARGIN
+    }
+
+    fn change(&self, band: InaudibleBand, (active, at, peak_db): (bool, u64, f32)) -> InaudibleChange {
+        InaudibleChange { band, active, at_seconds: at as f64 / self.sample_rate, peak_db }
+    }
+
+    // Feeds interleaved audio and returns the changes in activity it brings
+    pub fn process(&mut self, samples: &[f32]) -> Vec<InaudibleChange> {
+        let mut changes = Vec::new();
+        for frame in samples.chunks_exact(self.channels) {
+            // Bands are measured on the mono mix
+            let mono = frame.iter().map(|&sample| sample as f64).sum::<f64>() / self.channels as f64;
+            for (band, squares) in self.bands.iter_mut().zip(self.sum_squares.iter_mut()) {
+                let filtered = band.filter(mono);
+                *squares += filtered * filtered;
+            }
+            self.frames += 1;
+            if self.frames - self.block_start < self.block_frames {
+                continue;
+            }
+
+            let (start, end) = (self.block_start, self.frames);
+            for index in 0..self.bands.len() {
+                let level_db = to_db((self.sum_squares[index] / (end - start) as f64).sqrt() as f32);
+                self.sum_squares[index] = 0.0;
+                if let Some(change) = self.bands[index].update(level_db, self.threshold_db, start, end) {
+                    changes.push(self.change(self.bands[index].band, change));
+                }
+            }
+            self.block_start = end;
+        }
+        changes
+    }
+
+    // Bands active right now and their loudest block so far
+    pub fn active_bands(&self) -> Vec<(InaudibleBand, f32)> {
+        self.bands.iter().filter_map(|band| band.active.map(|(_, peak)| (band.band, peak))).collect()
+    }
+
+    // Ends the activity still open when the audio does
+    pub fn finish(&mut self) -> Vec<InaudibleChange> {
+        let ended: Vec<(InaudibleBand, (bool, u64, f32))> = self.bands
+            .iter_mut()
+            .filter_map(|band| band.active.take().map(|(last_heard, peak)| (band.band, (false, last_heard, peak))))
+            .collect();
+        ended.into_iter().map(|(band, change)| self.change(band, change)).collect()
+    }
+}
+
+// Pairs starts with ends into events
+#[derive(Default)]
+pub struct InaudibleLog {
+    // Band, start and loudest block of the activity still open
+    open: Vec<(InaudibleBand, f64, f32)>,
+    events: Vec<InaudibleEvent>,
+}
+
+impl InaudibleLog {
+    // `at_seconds` is the change's time on the log's own clock
+    pub fn apply(&mut self, change: &InaudibleChange, at_seconds: f64) {
+        if change.active {
+            self.open.push((change.band, at_seconds, change.peak_db));
+        } else if let Some(index) = self.open.iter().position(|(band, _, _)| *band == change.band) {
+            let (band, start_seconds, _) = self.open.remove(index);
+            self.events.push(InaudibleEvent {
+                band: band.as_str().to_string(),
+                start_seconds,
+                end_seconds: at_seconds,
+                peak_db: change.peak_db,
+            });
+        }
+    }
+
+    pub fn open(&mut self, band: InaudibleBand, at_seconds: f64, peak_db: f32) {
+        self.open.push((band, at_seconds, peak_db));
+    }
+
+    // Closes what is still open at `end_seconds`; events in order of where they start
+    pub fn finish(mut self, end_seconds: f64) -> Vec<InaudibleEvent> {
+        for (band, start_seconds, peak_db) in self.open.drain(..) {
+            self.events.push(InaudibleEvent { band: band.as_str().to_string(), start_seconds, end_seconds, peak_db });
+        }
+        self.events.sort_by(|a, b| a.start_seconds.total_cmp(&b.start_seconds));
+        self.events
+    }
+}
+
+fn scan(path: &Path, settings: InaudibleSettings) -> Result<Vec<InaudibleEvent>> {
+    let (format, duration) = audio_file::audio_info(path)?;
+    let mut detector = InaudibleDetector::new(format, settings);
+    let mut log = InaudibleLog::default();
+    audio_file::for_each_chunk(path, |chunk| {
+        for change in detector.process(chunk) {
+            log.apply(&change, change.at_seconds);
+        }
+        Ok(())
+    })?;
+    for change in detector.finish() {
+        log.apply(&change, change.at_seconds);
+    }
+    Ok(log.finish(duration))
+}
+
+// Scans a recording for ultrasonic and infrasonic activity with the bands
+// from the capture settings, replacing the events stored for it
+#[command]
+pub async fn detect_inaudible_activity(
+    recording_id: i64,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Vec<InaudibleEvent>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let settings = capture.get_settings().inaudible_settings();
+    let source = PathBuf::from(&record.file_path);
+    let events = tokio::task::spawn_blocking(move || scan(&source, settings))
+        .await
+        .map_err(|e| format!("Inaudible activity error: {}", e))?
+        .map_err(|e| format!("Inaudible activity error: {}", e))?;
+    db.save_inaudible_events(recording_id, &events).map_err(|e| format!("Database error: {}", e))?;
+    Ok(events)
+}
+
+#[command]
+pub async fn get_inaudible_events(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<InaudibleEvent>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_inaudible_events(recording_id).map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,166 @@
+use serde::Serialize;
+use tauri::{command, State};
+use crate::audio_capture::{self, AudioCapture, StreamFormat};
+use crate::database::Database;
+use crate::levels::to_db;
+
+pub const MIN_INPUT_GAIN_DB: f32 = -20.0;
+pub const MAX_INPUT_GAIN_DB: f32 = 30.0;
+
+// Gain changes are ramped over this long, so they don't click
+const RAMP_MS: u32 = 20;
+
+// Above this the limiter bends peaks towards full scale, so boosted audio
+// rounds off instead of clipping hard
+const LIMITER_KNEE: f32 = 0.8;
+
+const DEFAULT_CALIBRATION_SECONDS: u32 = 5;
+
+// Calibration measures in 100 ms windows and takes the ambient level from
+// the quieter ones, so a cough or a door doesn't count as the room
+const CALIBRATION_WINDOW_MS: u32 = 100;
+const AMBIENT_PERCENTILE: f32 = 0.2;
+
+// Where calibration aims to put a room's ambient level, which leaves speech
+// around -20 dBFS, and the most its loudest peak may be brought to
+const AMBIENT_TARGET_DB: f32 = -55.0;
+const PEAK_CEILING_DB: f32 = -6.0;
+
+// Digital gain on one capture source, set per device in the capture settings
+pub struct InputGain {
+    channels: usize,
+    ramp_frames: usize,
+    gain_db: f32,
+}
+
+impl InputGain {
+    pub fn new(format: StreamFormat) -> Self {
+        InputGain {
+            channels: format.channels.max(1) as usize,
+            ramp_frames: (format.sample_rate * RAMP_MS / 1000).max(1) as usize,
+            gain_db: 0.0,
+        }
+    }
+
+    // Applies `gain_db` in place, ramping from the gain of the last chunk.
+    // Unity gain leaves the audio untouched.
+    pub fn process(&mut self, samples: &mut [f32], gain_db: f32) {
+        if gain_db == 0.0 && self.gain_db == 0.0 {
+            return;
+        }
+        let start_db = self.gain_db;
+        self.gain_db = gain_db;
+        for (index, frame) in samples.chunks_mut(self.channels).enumerate() {
+            let progress = ((index + 1) as f32 / self.ramp_frames as f32).min(1.0);
+            let gain = 10f32.powf((start_db + (gain_db - start_db) * progress) / 20.0);
+            for sample in frame {
+                *sample = limit(*sample * gain);
+            }
+        }
+    }
+}
+
+// A soft knee: unchanged up to the knee, then curving towards full scale
+fn limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= LIMITER_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - LIMITER_KNEE;
+    (LIMITER_KNEE + headroom * ((magnitude - LIMITER_KNEE) / headroom).tanh()).copysign(sample)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GainCalibration {
+    pub device_id: String,
+    pub seconds: f64,
+    // RMS level of the room without any gain, in dBFS
+    pub ambient_db: f32,
+    pub peak_db: f32,
+    pub current_gain_db: f32,
+    // Brings the ambient level to about -55 dBFS, less if that would push
+    // the loudest peak above -6 dBFS
+    pub suggested_gain_db: f32,
+}
+
+// Ambient and peak level of captured audio, and the gain to suggest for it.
+// Returns None for digital silence, which no gain can help.
+pub fn calibrate(samples: &[f32], format: StreamFormat) -> Option<(f32, f32, f32)> {
+    let channels = format.channels.max(1) as usize;
+    let window = (format.sample_rate * CALIBRATION_WINDOW_MS / 1000).max(1) as usize * channels;
+    let mut levels: Vec<f32> = samples.chunks_exact(window)
+        .map(|chunk| (chunk.iter().map(|sample| sample * sample).sum::<f32>() / chunk.len() as f32).sqrt())
+        .collect();
+    let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    if levels.is_empty() || peak == 0.0 {
+        return None;
+    }
+    levels.sort_by(f32::total_cmp);
+    let ambient_db = to_db(levels[((levels.len() - 1) as f32 * AMBIENT_PERCENTILE) as usize]);
+    let peak_db = to_db(peak);
+    let suggested = (AMBIENT_TARGET_DB - ambient_db)
+        .min(PEAK_CEILING_DB - peak_db)
+        .clamp(MIN_INPUT_GAIN_DB, MAX_INPUT_GAIN_DB);
+    // Half a decibel is as fine as anyone sets gain by ear
+    Some((ambient_db, peak_db, (suggested * 2.0).round() / 2.0))
+}
+
+// Sets the gain for a device, or the default input without one, and keeps
+// it in the capture settings. Applies straight away if capture is running
+// on it; 0 dB takes the device's gain off.
+#[command]
+pub async fn set_input_gain(
+    device_id: Option<String>,
+    db: f32,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<(), String> {
+    if !(MIN_INPUT_GAIN_DB..=MAX_INPUT_GAIN_DB).contains(&db) {
+        return Err(format!("Input gain must be between {} and {} dB", MIN_INPUT_GAIN_DB, MAX_INPUT_GAIN_DB));
+    }
+    let device_id = match device_id {
+        Some(id) => id,
+        None => audio_capture::find_input_device(None)
+            .and_then(|device| Ok(cpal::traits::DeviceTrait::name(&device)?))
+            .map_err(|e| format!("Audio device error: {}", e))?,
+    };
+
+    let mut settings = capture.get_settings();
+    if db == 0.0 {
+        settings.input_gains.remove(&device_id);
+    } else {
+        settings.input_gains.insert(device_id, db);
+    }
+    let database = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    capture.set_settings(settings, &database).map_err(|e| format!("Failed to save capture settings: {}", e))
+}
+
+// Listens to a few seconds of the room on the device and suggests a gain
+// for it. Nothing is changed; the suggestion can be passed to set_input_gain.
+#[command]
+pub async fn calibrate_input_gain(
+    device_id: Option<String>,
+    seconds: Option<u32>,
+    capture: State<'_, AudioCapture>,
+) -> Result<GainCalibration, String> {
+    let seconds = seconds.unwrap_or(DEFAULT_CALIBRATION_SECONDS);
+    if !(1..=30).contains(&seconds) {
+        return Err("Calibration must listen for between 1 and 30 seconds".to_string());
+    }
+    let settings = capture.get_settings();
+    let (device_id, format, samples) = tokio::task::spawn_blocking(move || audio_capture::capture_raw(device_id.as_deref(), seconds as f64, &settings))
+        .await
+        .map_err(|e| format!("Calibration error: {}", e))?
+        .map_err(|e| format!("Calibration error: {}", e))?;
+    let (ambient_db, peak_db, suggested_gain_db) = calibrate(&samples, format)
+        .ok_or_else(|| format!("'{}' sent only digital silence; check it isn't muted", device_id))?;
+    let current_gain_db = capture.get_settings().input_gain_db(&device_id);
+    Ok(GainCalibration {
+        device_id,
+        seconds: seconds as f64,
+        ambient_db,
+        peak_db,
+        current_gain_db,
+        suggested_gain_db,
+    })
+}
@@ -0,0 +1,130 @@
+#[cfg(feature = "keyword-spotting")]
+use vosk::{DecodingState, Model, Recognizer};
+use anyhow::Result;
+use serde::Serialize;
+use crate::audio_capture::StreamFormat;
+#[cfg(feature = "keyword-spotting")]
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+pub const KEYWORD_DETECTED_EVENT: &str = "dwight://keyword-detected";
+
+#[cfg(feature = "keyword-spotting")]
+const SPOTTER_SAMPLE_RATE: u32 = 16000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeywordDetection {
+    pub phrase: String,
+    pub confidence: f32,
+    // When the phrase was said, in seconds since capture started
+    pub at_seconds: f64,
+    // File of the recording the detection started or was tagged onto
+    pub recording_file: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpottedPhrase {
+    pub phrase: String,
+    pub confidence: f32,
+    pub at_seconds: f64,
+}
+
+// Lowercase words without punctuation, as the recognizer reports them
+#[cfg(feature = "keyword-spotting")]
+fn normalize_phrase(phrase: &str) -> String {
+    phrase.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Listens for a fixed list of phrases with a Vosk recognizer restricted to
+// them, which is far lighter than transcribing everything with Whisper
+pub struct KeywordSpotter {
+    #[cfg(feature = "keyword-spotting")]
+    recognizer: Recognizer,
+    // The recognizer refers to the model, so it has to outlive it
+    #[cfg(feature = "keyword-spotting")]
+    _model: Model,
+    #[cfg(feature = "keyword-spotting")]
+    converter: FormatConverter,
+    // Each phrase split into normalized words
+    #[cfg(feature = "keyword-spotting")]
+    phrases: Vec<Vec<String>>,
+    #[cfg(feature = "keyword-spotting")]
+    min_confidence: f32,
+}
+
+impl KeywordSpotter {
+    #[cfg(feature = "keyword-spotting")]
+    pub fn new(model_path: &str, phrases: &[String], format: StreamFormat, min_confidence: f32) -> Result<Self> {
+        let phrases: Vec<String> = phrases.iter().map(|phrase| normalize_phrase(phrase)).filter(|phrase| !phrase.is_empty()).collect();
+        if phrases.is_empty() {
+            return Err(anyhow::anyhow!("No trigger phrases to listen for"));
+        }
+
+        let model = Model::new(model_path).ok_or_else(|| anyhow::anyhow!("Failed to load Vosk model from '{}'", model_path))?;
+        // Anything else is heard as [unk] instead of being forced onto a phrase
+        let mut grammar: Vec<&str> = phrases.iter().map(String::as_str).collect();
+        grammar.push("[unk]");
+        let mut recognizer = Recognizer::new_with_grammar(&model, SPOTTER_SAMPLE_RATE as f32, &grammar)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create keyword recognizer"))?;
+        recognizer.set_words(true);
+
+        Ok(KeywordSpotter {
+            recognizer,
+            _model: model,
+            converter: FormatConverter::new(format, StreamFormat { sample_rate: SPOTTER_SAMPLE_RATE, channels: 1 }, ResampleQuality::Fast),
+            phrases: phrases.iter().map(|phrase| phrase.split(' ').map(str::to_string).collect()).collect(),
+            min_confidence,
+        })
+    }
+
+    #[cfg(not(feature = "keyword-spotting"))]
+    pub fn new(_model_path: &str, _phrases: &[String], _format: StreamFormat, _min_confidence: f32) -> Result<Self> {
+        Err(anyhow::anyhow!("Keyword spotting not enabled. Please compile with 'keyword-spotting' feature."))
+    }
+
+    // Feeds capture audio and returns the phrases heard in utterances that
+    // ended within it
+    #[cfg(feature = "keyword-spotting")]
+    pub fn process(&mut self, samples: &[f32]) -> Vec<SpottedPhrase> {
+        let pcm: Vec<i16> = self.converter.convert(samples)
+            .into_iter()
+            .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .collect();
+        match self.recognizer.accept_waveform(&pcm) {
+            Ok(DecodingState::Finalized) => {}
+            _ => return Vec::new(),
+        }
+        let Some(result) = self.recognizer.result().single() else {
+            return Vec::new();
+        };
+
+        let mut spotted = Vec::new();
+        for phrase in &self.phrases {
+            for start in 0..result.result.len() {
+                let Some(words) = result.result.get(start..start + phrase.len()) else {
+                    break;
+                };
+                if !words.iter().zip(phrase).all(|(heard, expected)| heard.word == expected) {
+                    continue;
+                }
+                let confidence = words.iter().map(|word| word.conf).fold(1.0, f32::min);
+                if confidence >= self.min_confidence {
+                    spotted.push(SpottedPhrase {
+                        phrase: phrase.join(" "),
+                        confidence,
+                        at_seconds: words[0].start as f64,
+                    });
+                }
+            }
+        }
+        spotted
+    }
+
+    #[cfg(not(feature = "keyword-spotting"))]
+    pub fn process(&mut self, _samples: &[f32]) -> Vec<SpottedPhrase> {
+        Vec::new()
+    }
+}
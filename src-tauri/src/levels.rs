@@ -0,0 +1,158 @@
+use serde::Serialize;
+use std::f32::consts::PI;
+use crate::audio_capture::StreamFormat;
+
+pub const AUDIO_LEVELS_EVENT: &str = "dwight://audio-levels";
+
+// 20 updates a second is smooth enough for a meter without flooding the UI
+const METER_INTERVAL_MS: u32 = 50;
+
+// Reported for digital silence, where the level would be -inf
+const SILENCE_DB: f32 = -96.0;
+
+// Crossovers between the low, mid and high bands
+const LOW_MID_HZ: f32 = 250.0;
+const MID_HIGH_HZ: f32 = 4000.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BandLevels {
+    // Up to 250 Hz: rumble, hum, the body of voices
+    pub low_db: f32,
+    // 250 Hz to 4 kHz, where most of speech is
+    pub mid_db: f32,
+    // Above 4 kHz: sibilance, hiss
+    pub high_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelLevels {
+    pub rms_db: f32,
+    pub peak_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioLevels {
+    // Over all channels, in dBFS
+    pub rms_db: f32,
+    pub peak_db: f32,
+    pub bands: BandLevels,
+    // Each channel of the capture in order, so a dead input on a
+    // multichannel interface shows up
+    pub channels: Vec<ChannelLevels>,
+    // End of the measured window, in seconds since capture started
+    pub at_seconds: f64,
+}
+
+pub fn to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+// One-pole low-pass, cheap enough to run on every sample
+struct LowPass {
+    coefficient: f32,
+    state: f32,
+}
+
+impl LowPass {
+    fn new(cutoff_hz: f32, sample_rate: u32) -> Self {
+        LowPass {
+            coefficient: 1.0 - (-2.0 * PI * cutoff_hz / sample_rate as f32).exp(),
+            state: 0.0,
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        self.state += self.coefficient * (sample - self.state);
+        self.state
+    }
+}
+
+// Sums squares and peaks over a window and reports them once it's full
+pub struct LevelMeter {
+    channels: usize,
+    window_frames: u64,
+    low: LowPass,
+    mid: LowPass,
+    frames: u64,
+    total_frames: u64,
+    sample_rate: u32,
+    sum_squares: Vec<f64>,
+    peaks: Vec<f32>,
+    band_squares: [f64; 3],
+}
+
+impl LevelMeter {
+    pub fn new(format: StreamFormat) -> Self {
+        let channels = format.channels.max(1) as usize;
+        LevelMeter {
+            channels,
+            window_frames: (format.sample_rate * METER_INTERVAL_MS / 1000).max(1) as u64,
+            low: LowPass::new(LOW_MID_HZ, format.sample_rate),
+            mid: LowPass::new(MID_HIGH_HZ, format.sample_rate),
+            frames: 0,
+            total_frames: 0,
+            sample_rate: format.sample_rate,
+            sum_squares: vec![0.0; channels],
+            peaks: vec![0.0; channels],
+            band_squares: [0.0; 3],
+        }
+    }
+
+    fn band_db(&self, band: usize) -> f32 {
+        to_db((self.band_squares[band] / self.frames as f64).sqrt() as f32)
+    }
+
+    // Feeds interleaved audio and returns a reading for each window it completes
+    pub fn process(&mut self, samples: &[f32]) -> Vec<AudioLevels> {
+        let mut readings = Vec::new();
+        for frame in samples.chunks_exact(self.channels) {
+            let mut mono = 0.0;
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.sum_squares[channel] += (sample * sample) as f64;
+                self.peaks[channel] = self.peaks[channel].max(sample.abs());
+                mono += sample;
+            }
+            // Bands are split on the mono mix
+            let mono = mono / self.channels as f32;
+            let low = self.low.process(mono);
+            let below_high = self.mid.process(mono);
+            for (band, level) in [low, below_high - low, mono - below_high].into_iter().enumerate() {
+                self.band_squares[band] += (level * level) as f64;
+            }
+
+            self.frames += 1;
+            self.total_frames += 1;
+            if self.frames < self.window_frames {
+                continue;
+            }
+
+            let frames = self.frames as f64;
+            let total_squares: f64 = self.sum_squares.iter().sum();
+            readings.push(AudioLevels {
+                rms_db: to_db((total_squares / (frames * self.channels as f64)).sqrt() as f32),
+                peak_db: to_db(self.peaks.iter().copied().fold(0.0, f32::max)),
+                bands: BandLevels {
+                    low_db: self.band_db(0),
+                    mid_db: self.band_db(1),
+                    high_db: self.band_db(2),
+                },
+                channels: self.sum_squares.iter().zip(&self.peaks)
+                    .map(|(&squares, &peak)| ChannelLevels {
+                        rms_db: to_db((squares / frames).sqrt() as f32),
+                        peak_db: to_db(peak),
+                    })
+                    .collect(),
+                at_seconds: self.total_frames as f64 / self.sample_rate as f64,
+            });
+            self.frames = 0;
+            self.sum_squares.fill(0.0);
+            self.peaks.fill(0.0);
+            self.band_squares = [0.0; 3];
+        }
+        readings
+    }
+}
@@ -0,0 +1,273 @@
+use serde::Serialize;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tauri::Emitter;
+use crate::audio_capture::StreamFormat;
+use crate::database::{Database, TranscriptWord};
+use crate::resampler::{FormatConverter, ResampleQuality};
+use crate::transcription::{self, WhisperModel, SAMPLE_RATE};
+use crate::vocabulary;
+use crate::whisper::{self, TranscriptionSegment};
+
+pub const LIVE_TRANSCRIPT_EVENT: &str = "dwight://live-transcript";
+
+// The pending audio is transcribed again each time this much more arrives,
+// so text shows up a couple of seconds after it is spoken
+const STEP_SECONDS: f64 = 2.0;
+
+// A segment is final once it ends this far before the newest audio and
+// another follows it; until then a later pass, hearing more, may change it
+const HOLDBACK_SECONDS: f64 = 3.0;
+
+// Pending audio is never left longer than this. At the limit everything but
+// the last segment is taken as final, or all of it when speech runs on.
+const MAX_PENDING_SECONDS: f64 = 24.0;
+
+// Kept when a pass hears nothing, in case a word was starting at the end
+const SILENCE_OVERLAP_SECONDS: f64 = 1.0;
+
+// Audio goes to the worker this much at a time, and at most this much waits
+// for it. When the model is slower than real time the oldest waiting audio
+// is kept and what comes after it skipped, so the worker jumps to the newest.
+const SEND_SECONDS: f64 = 0.5;
+const BACKLOG_SECONDS: f64 = 8.0;
+
+// Text heard on the live stream. Partials cover what hasn't settled and are
+// replaced by the next event; final segments won't change.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveTranscript {
+    pub text: String,
+    // In seconds since capture started
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub confidence: f32,
+    pub words: Vec<TranscriptWord>,
+    pub language: Option<String>,
+    pub is_final: bool,
+}
+
+// Audio for the worker, after `skipped` samples it was too far behind for
+struct Chunk {
+    skipped: usize,
+    samples: Vec<f32>,
+}
+
+// Feeds live capture audio to a Whisper worker thread, which transcribes it
+// in overlapping windows with a model of its own, so batch transcriptions
+// neither wait for it nor make it reload. Dropping it finishes the pending
+// audio and stops the worker.
+pub struct LiveTranscriber {
+    converter: FormatConverter,
+    sender: mpsc::SyncSender<Chunk>,
+    // Converted audio not yet sent, and how much was skipped before it
+    unsent: Vec<f32>,
+    skipped: usize,
+}
+
+impl LiveTranscriber {
+    pub fn new(app_handle: &tauri::AppHandle, format: StreamFormat, model_size: String, language: Option<String>) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel((BACKLOG_SECONDS / SEND_SECONDS) as usize);
+        let vocabulary = Database::new(app_handle)
+            .and_then(|db| vocabulary::terms(&db))
+            .unwrap_or_else(|e| {
+                eprintln!("Live transcription is going without the custom vocabulary: {}", e);
+                Vec::new()
+            });
+        let worker = Worker {
+            app_handle: app_handle.clone(),
+            model_size,
+            model: None,
+            vocabulary,
+            language,
+            pending: Vec::new(),
+            pending_start: 0,
+            since_pass: 0,
+        };
+        std::thread::Builder::new()
+            .name("live-transcription".to_string())
+            .spawn(move || worker.run(receiver))?;
+        Ok(LiveTranscriber {
+            converter: FormatConverter::new(format, StreamFormat { sample_rate: SAMPLE_RATE, channels: 1 }, ResampleQuality::Fast),
+            sender,
+            unsent: Vec::new(),
+            skipped: 0,
+        })
+    }
+
+    pub fn process(&mut self, captured: &[f32]) {
+        self.unsent.extend(self.converter.convert(captured));
+        if self.unsent.len() < samples(SEND_SECONDS) {
+            return;
+        }
+        let chunk = Chunk { skipped: self.skipped, samples: std::mem::take(&mut self.unsent) };
+        match self.sender.try_send(chunk) {
+            Ok(()) => self.skipped = 0,
+            Err(mpsc::TrySendError::Full(chunk)) => self.skipped += chunk.samples.len(),
+            Err(mpsc::TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+impl Drop for LiveTranscriber {
+    fn drop(&mut self) {
+        if !self.unsent.is_empty() {
+            let _ = self.sender.try_send(Chunk { skipped: self.skipped, samples: std::mem::take(&mut self.unsent) });
+        }
+    }
+}
+
+struct Worker {
+    app_handle: tauri::AppHandle,
+    model_size: String,
+    // Loaded on the first pass
+    model: Option<WhisperModel>,
+    // The custom vocabulary as it was when capture started
+    vocabulary: Vec<String>,
+    // Detected from the first speech heard when not set
+    language: Option<String>,
+    // 16 kHz mono audio not yet final, and where it starts since capture started
+    pending: Vec<f32>,
+    pending_start: u64,
+    since_pass: usize,
+}
+
+fn seconds(samples: usize) -> f64 {
+    samples as f64 / SAMPLE_RATE as f64
+}
+
+fn samples(seconds: f64) -> usize {
+    (seconds.max(0.0) * SAMPLE_RATE as f64) as usize
+}
+
+impl Worker {
+    fn run(mut self, receiver: mpsc::Receiver<Chunk>) {
+        let step = samples(STEP_SECONDS);
+        loop {
+            let chunk = match receiver.recv_timeout(Duration::from_secs_f64(STEP_SECONDS)) {
+                Ok(chunk) => chunk,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            // Catch up on whatever arrived during the last pass before the next one
+            let chunks = std::iter::once(chunk).chain(std::iter::from_fn(|| receiver.try_recv().ok())).collect::<Vec<_>>();
+            for chunk in chunks {
+                if let Err(e) = self.push(chunk) {
+                    eprintln!("Live transcription stopped: {}", e);
+                    return;
+                }
+            }
+            if self.since_pass < step {
+                continue;
+            }
+            if let Err(e) = self.pass(false) {
+                eprintln!("Live transcription stopped: {}", e);
+                return;
+            }
+        }
+        // Capture stopped; what's left is as final as it will get
+        if !self.pending.is_empty() {
+            if let Err(e) = self.pass(true) {
+                eprintln!("Live transcription failed: {}", e);
+            }
+        }
+    }
+
+    // After a gap the audio before it is finished off, as it won't go on
+    fn push(&mut self, chunk: Chunk) -> anyhow::Result<()> {
+        if chunk.skipped > 0 {
+            eprintln!("Live transcription fell behind and skipped {:.1} seconds of audio", seconds(chunk.skipped));
+            if !self.pending.is_empty() {
+                self.pass(true)?;
+            }
+            self.pending_start += (self.pending.len() + chunk.skipped) as u64;
+            self.pending.clear();
+        }
+        self.since_pass += chunk.samples.len();
+        self.pending.extend(chunk.samples);
+        Ok(())
+    }
+
+    fn emit(&self, segment: &TranscriptionSegment, is_final: bool) {
+        let offset = seconds(self.pending_start as usize);
+        let transcript = LiveTranscript {
+            text: segment.text.clone(),
+            start_seconds: offset + segment.start,
+            end_seconds: offset + segment.end,
+            confidence: segment.confidence,
+            words: segment.words.iter()
+                .map(|word| TranscriptWord {
+                    start_seconds: offset + word.start_seconds,
+                    end_seconds: offset + word.end_seconds,
+                    ..word.clone()
+                })
+                .collect(),
+            language: segment.language.clone(),
+            is_final,
+        };
+        if let Err(e) = self.app_handle.emit(LIVE_TRANSCRIPT_EVENT, transcript) {
+            eprintln!("Failed to emit live transcript: {}", e);
+        }
+    }
+
+    // Drops pending audio up to `seconds` into it
+    fn consume(&mut self, seconds: f64) {
+        let consumed = samples(seconds).min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.pending_start += consumed as u64;
+    }
+
+    fn pass(&mut self, finish: bool) -> anyhow::Result<()> {
+        self.since_pass = 0;
+        if self.model.is_none() {
+            self.model = Some(transcription::load_model(&self.app_handle, &self.model_size)?);
+        }
+        let model = self.model.as_mut().ok_or_else(|| anyhow::anyhow!("No Whisper model loaded"))?;
+        let result = model.transcribe(&self.pending, self.language.as_deref(), &self.vocabulary)?;
+        let pending_seconds = seconds(self.pending.len());
+        let segments = result.segments;
+        if segments.is_empty() {
+            if !finish {
+                self.consume(pending_seconds - SILENCE_OVERLAP_SECONDS);
+            }
+            return Ok(());
+        }
+        if self.language.is_none() {
+            self.language = Some(result.language);
+        }
+
+        // Segments settle once the next one has started and they are old enough
+        let overflowing = pending_seconds >= MAX_PENDING_SECONDS;
+        let mut settled = segments.iter()
+            .take(segments.len() - 1)
+            .take_while(|segment| segment.end <= pending_seconds - HOLDBACK_SECONDS)
+            .count();
+        if finish || (overflowing && segments.len() == 1) {
+            settled = segments.len();
+        } else if overflowing {
+            settled = segments.len() - 1;
+        }
+
+        for segment in &segments[..settled] {
+            self.emit(segment, true);
+        }
+        let (open, consumed_to) = match settled {
+            0 => (&segments[..], 0.0),
+            settled if settled == segments.len() => (&segments[..0], pending_seconds),
+            settled => (&segments[settled..], segments[settled - 1].end),
+        };
+        if let (Some(first), Some(last)) = (open.first(), open.last()) {
+            let partial = TranscriptionSegment {
+                start: first.start,
+                end: last.end,
+                text: open.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" "),
+                confidence: open.iter().map(|segment| segment.confidence).sum::<f32>() / open.len() as f32,
+                words: open.iter().flat_map(|segment| segment.words.iter().cloned()).collect(),
+                language: whisper::main_language(open),
+                avg_logprob: open.iter().map(|segment| segment.avg_logprob).sum::<Option<f64>>().map(|total| total / open.len() as f64),
+            };
+            self.emit(&partial, false);
+        }
+        self.consume(consumed_to);
+        Ok(())
+    }
+}
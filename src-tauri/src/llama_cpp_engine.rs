@@ -0,0 +1,250 @@
+#[cfg(feature = "llama-cpp")]
+use llama_cpp_2::{
+    context::params::LlamaContextParams,
+    llama_backend::LlamaBackend,
+    llama_batch::LlamaBatch,
+    model::{params::LlamaModelParams, LlamaModel},
+    sampling::LlamaSampler,
+};
+
+#[cfg(feature = "llama-cpp")]
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::llm_backend::{BackendOutput, GenerationRequest, LlmBackend};
+
+#[cfg(feature = "llama-cpp")]
+use crate::ai_models::GenerationOptions;
+
+// Context window used for embedded inference unless num_ctx is set
+#[cfg(feature = "llama-cpp")]
+const CONTEXT_SIZE: u32 = 4096;
+
+// Serves generation from a GGUF file on disk (ModelConfig.local_path) without any
+// HTTP server. Loaded models are kept in memory and reused across requests.
+pub struct LlamaCppBackend {
+    #[cfg(feature = "llama-cpp")]
+    cache: Arc<ModelCache>,
+}
+
+#[cfg(feature = "llama-cpp")]
+struct ModelCache {
+    backend: Mutex<Option<Arc<LlamaBackend>>>,
+    models: Mutex<HashMap<String, Arc<LlamaModel>>>,
+}
+
+#[cfg(feature = "llama-cpp")]
+impl ModelCache {
+    fn load(&self, model_path: &str) -> Result<(Arc<LlamaBackend>, Arc<LlamaModel>)> {
+        // llama.cpp may only be initialized once per process
+        let backend = {
+            let mut backend = self.backend.lock().map_err(|_| anyhow::anyhow!("llama.cpp backend lock poisoned"))?;
+            match backend.as_ref() {
+                Some(existing) => existing.clone(),
+                None => {
+                    let initialized = Arc::new(LlamaBackend::init()?);
+                    *backend = Some(initialized.clone());
+                    initialized
+                }
+            }
+        };
+
+        let mut models = self.models.lock().map_err(|_| anyhow::anyhow!("llama.cpp model cache lock poisoned"))?;
+        if let Some(model) = models.get(model_path) {
+            return Ok((backend, model.clone()));
+        }
+
+        if !std::path::Path::new(model_path).exists() {
+            return Err(anyhow::anyhow!("GGUF model not found: {}", model_path));
+        }
+
+        let model = LlamaModel::load_from_file(&backend, model_path, &LlamaModelParams::default())
+            .map_err(|e| anyhow::anyhow!("Failed to load GGUF model {}: {}", model_path, e))?;
+        let model = Arc::new(model);
+        models.insert(model_path.to_string(), model.clone());
+        Ok((backend, model))
+    }
+}
+
+impl LlamaCppBackend {
+    pub fn new() -> Self {
+        LlamaCppBackend {
+            #[cfg(feature = "llama-cpp")]
+            cache: Arc::new(ModelCache {
+                backend: Mutex::new(None),
+                models: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    // Loading and inference are blocking, so both run on the blocking thread pool.
+    // Pieces of text are sent back over a channel as they are produced.
+    #[cfg(feature = "llama-cpp")]
+    async fn run(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        let model_path = request.model_path
+            .ok_or_else(|| anyhow::anyhow!("Model '{}' has no local_path set for llama.cpp", request.model))?
+            .to_string();
+        let prompt = request.prompt.to_string();
+        let options = request.options.clone();
+        let cache = self.cache.clone();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let worker = tokio::task::spawn_blocking(move || {
+            let (backend, model) = cache.load(&model_path)?;
+            // A closed channel means the request was dropped (e.g. cancelled)
+            run_inference(&backend, &model, &prompt, &options, |piece| {
+                tx.send(piece.to_string()).is_ok()
+            })
+        });
+
+        while let Some(piece) = rx.recv().await {
+            on_token(&piece);
+        }
+
+        worker.await?
+    }
+
+    #[cfg(not(feature = "llama-cpp"))]
+    async fn run(
+        &self,
+        request: &GenerationRequest<'_>,
+        _on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        // Fallback implementation when embedded inference is disabled
+        Err(anyhow::anyhow!(
+            "Embedded llama.cpp inference not enabled, cannot load '{}'. Please compile with 'llama-cpp' feature.",
+            request.model_path.unwrap_or(request.model)
+        ))
+    }
+}
+
+#[async_trait]
+impl LlmBackend for LlamaCppBackend {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput> {
+        self.run(request, &mut |_| {}).await
+    }
+
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        self.run(request, on_token).await
+    }
+}
+
+#[cfg(feature = "llama-cpp")]
+fn run_inference<F>(
+    backend: &LlamaBackend,
+    model: &LlamaModel,
+    prompt: &str,
+    options: &GenerationOptions,
+    mut on_piece: F,
+) -> Result<BackendOutput>
+where
+    F: FnMut(&str) -> bool,
+{
+    let ctx_params = LlamaContextParams::default()
+        .with_n_ctx(std::num::NonZeroU32::new(options.runtime.num_ctx.unwrap_or(CONTEXT_SIZE)));
+    let mut ctx = model.new_context(backend, ctx_params)
+        .map_err(|e| anyhow::anyhow!("Failed to create llama.cpp context: {}", e))?;
+
+    let vocab = model.vocab();
+    let prompt_tokens = vocab.tokenize(prompt.as_bytes(), true, false);
+    if prompt_tokens.is_empty() {
+        return Err(anyhow::anyhow!("Prompt is empty"));
+    }
+
+    let n_ctx = ctx.n_ctx() as usize;
+    if prompt_tokens.len() >= n_ctx {
+        return Err(anyhow::anyhow!("Prompt is {} tokens, which exceeds the {} token context window", prompt_tokens.len(), n_ctx));
+    }
+    let max_tokens = (options.max_tokens.unwrap_or(512) as usize).min(n_ctx - prompt_tokens.len());
+
+    // Evaluate the whole prompt in one batch; only the last position needs logits
+    let mut batch = LlamaBatch::new(prompt_tokens.len().max(512), 1);
+    let last_index = prompt_tokens.len() - 1;
+    for (i, token) in prompt_tokens.iter().enumerate() {
+        batch.add(*token, i as i32, &[0], i == last_index)?;
+    }
+    ctx.decode(&mut batch)?;
+
+    let temperature = options.temperature.unwrap_or(0.7);
+    let mut sampler = if temperature <= 0.0 {
+        LlamaSampler::greedy()
+    } else {
+        LlamaSampler::chain_simple([
+            LlamaSampler::top_p(options.top_p.unwrap_or(0.9), 1),
+            LlamaSampler::temp(temperature),
+            LlamaSampler::dist(rand_seed()),
+        ])
+    };
+
+    let mut text = String::new();
+    // Tokens can end in the middle of a multi-byte character
+    let mut pending: Vec<u8> = Vec::new();
+    let mut position = prompt_tokens.len() as i32;
+    let mut generated = 0usize;
+    let mut token_logprobs = Vec::new();
+
+    while generated < max_tokens {
+        // sample() also accepts the token into the sampler chain
+        let token = sampler.sample(&ctx, batch.n_tokens() - 1);
+        if vocab.is_eog(token) {
+            break;
+        }
+        if let Some(logprob) = token_logprob(ctx.get_logits_ith(batch.n_tokens() - 1), token.0) {
+            token_logprobs.push(logprob);
+        }
+
+        pending.extend(vocab.token_to_piece(token, false, None));
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        if valid > 0 {
+            let piece: Vec<u8> = pending.drain(..valid).collect();
+            let piece = String::from_utf8_lossy(&piece);
+            text.push_str(&piece);
+            if !on_piece(&piece) {
+                break;
+            }
+        }
+
+        batch.clear();
+        batch.add(token, position, &[0], true)?;
+        ctx.decode(&mut batch)?;
+        position += 1;
+        generated += 1;
+    }
+
+    Ok(BackendOutput {
+        text,
+        prompt_tokens: Some(prompt_tokens.len() as u64),
+        completion_tokens: Some(generated as u64),
+        token_logprobs,
+    })
+}
+
+// Log-softmax of the raw logits at the sampled token, i.e. the model's own
+// probability before temperature and top-p are applied
+#[cfg(feature = "llama-cpp")]
+fn token_logprob(logits: &[f32], token: i32) -> Option<f32> {
+    let logit = *logits.get(usize::try_from(token).ok()?)?;
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let sum: f32 = logits.iter().map(|l| (l - max).exp()).sum();
+    Some(logit - max - sum.ln())
+}
+
+#[cfg(feature = "llama-cpp")]
+fn rand_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0)
+}
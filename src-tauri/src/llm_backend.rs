@@ -0,0 +1,421 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use std::time::Duration;
+use crate::ai_models::GenerationOptions;
+use crate::cloud_backends;
+
+// Which wire protocol a configured model speaks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    // LM Studio, vLLM, llamafile and anything else serving /v1/chat/completions
+    OpenAiCompatible,
+    // Embedded llama.cpp loading the GGUF file at ModelConfig.local_path
+    LlamaCpp,
+    // Hosted providers; prompts leave the machine, so local-only queries refuse them
+    OpenAi,
+    Anthropic,
+    // Canned offline answers for demo mode; never a real model
+    Mock,
+}
+
+impl BackendKind {
+    pub fn is_cloud(self) -> bool {
+        matches!(self, BackendKind::OpenAi | BackendKind::Anthropic)
+    }
+
+    // Where cloud models are sent when their config has no api_endpoint
+    pub fn default_endpoint(self) -> Option<&'static str> {
+        match self {
+            BackendKind::OpenAi => Some(cloud_backends::OPENAI_ENDPOINT),
+            BackendKind::Anthropic => Some(cloud_backends::ANTHROPIC_ENDPOINT),
+            _ => None,
+        }
+    }
+}
+
+// Failures worth retrying: the server could not be reached, timed out,
+// rate-limited the request or reported a server-side error. Anything else (unknown model, bad request)
+// means the backend is up and answered.
+#[derive(Debug)]
+pub struct TransientError(pub String);
+
+impl std::fmt::Display for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if error.downcast_ref::<TransientError>().is_some() {
+        return true;
+    }
+    error.downcast_ref::<reqwest::Error>()
+        .map(|e| e.is_timeout() || e.is_connect())
+        .unwrap_or(false)
+}
+
+pub(crate) fn transient(message: String) -> anyhow::Error {
+    anyhow::Error::new(TransientError(message))
+}
+
+// Endpoints may point at remote hosts and non-default ports, but must be
+// absolute http(s) URLs
+pub fn validate_endpoint(endpoint: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(endpoint.trim())
+        .map_err(|e| format!("Invalid endpoint URL '{}': {}", endpoint, e))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("Endpoint '{}' must use http or https", endpoint));
+    }
+    if url.host_str().is_none_or(|host| host.is_empty()) {
+        return Err(format!("Endpoint '{}' has no host", endpoint));
+    }
+    Ok(url)
+}
+
+// A cheap GET that tells whether the server behind a generation endpoint is up:
+// Ollama's model list, or /v1/models for OpenAI-compatible servers and the cloud providers
+pub fn probe_url(kind: BackendKind, endpoint: &str) -> Result<String, String> {
+    let url = validate_endpoint(endpoint)?;
+    let path = match kind {
+        BackendKind::Ollama => "/api/tags".to_string(),
+        BackendKind::OpenAiCompatible | BackendKind::OpenAi | BackendKind::Anthropic => {
+            let base = url.path().trim_end_matches('/').trim_end_matches("/chat/completions").trim_end_matches("/messages");
+            format!("{}/models", base)
+        }
+        BackendKind::LlamaCpp => return Err("llama.cpp models are local files, not endpoints".to_string()),
+        BackendKind::Mock => return Err("The demo backend has no endpoint".to_string()),
+    };
+    url.join(&path).map(|probe| probe.to_string()).map_err(|e| e.to_string())
+}
+
+pub struct GenerationRequest<'a> {
+    // Empty for backends that don't talk HTTP
+    pub endpoint: &'a str,
+    pub model_path: Option<&'a str>,
+    // Auth headers from the keychain; empty for unauthenticated endpoints
+    pub headers: &'a reqwest::header::HeaderMap,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub options: &'a GenerationOptions,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackendOutput {
+    pub text: String,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    // Natural-log probability of each generated token, when the backend reports them
+    pub token_logprobs: Vec<f32>,
+}
+
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput>;
+
+    // Streams partial tokens through `on_token`; the returned output holds the full text
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput>;
+}
+
+// Non-streaming requests must finish within this; streams only need to keep
+// producing chunks within STREAM_IDLE_TIMEOUT.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct OllamaBackend {
+    client: reqwest::Client,
+}
+
+impl OllamaBackend {
+    pub fn new(client: reqwest::Client) -> Self {
+        OllamaBackend { client }
+    }
+
+    fn payload(request: &GenerationRequest<'_>, stream: bool) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "model": request.model,
+            "prompt": request.prompt,
+            "stream": stream,
+            // Ignored by Ollama versions without logprob support
+            "logprobs": true,
+            "options": {
+                "temperature": request.options.temperature,
+                "top_p": request.options.top_p,
+                // Ollama calls the output token limit num_predict
+                "num_predict": request.options.max_tokens,
+                "num_ctx": request.options.runtime.num_ctx,
+                "num_gpu": request.options.runtime.num_gpu,
+            }
+        });
+        if let Some(keep_alive) = &request.options.runtime.keep_alive {
+            // Plain numbers are seconds and must be sent as JSON numbers
+            payload["keep_alive"] = match keep_alive.parse::<i64>() {
+                Ok(seconds) => serde_json::json!(seconds),
+                Err(_) => serde_json::json!(keep_alive),
+            };
+        }
+        if request.options.json.unwrap_or(false) {
+            payload["format"] = serde_json::json!("json");
+        }
+        payload
+    }
+
+    async fn send(&self, request: &GenerationRequest<'_>, stream: bool) -> Result<reqwest::Response> {
+        let mut builder = self.client
+            .post(request.endpoint)
+            .headers(request.headers.clone())
+            .json(&Self::payload(request, stream));
+
+        if !stream {
+            builder = builder.timeout(REQUEST_TIMEOUT);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| transient(format!("Failed to connect to Ollama at {}: {}. Make sure Ollama is running with 'ollama serve'", request.endpoint, e)))?;
+
+        if response.status().is_server_error() {
+            return Err(transient(format!("Ollama returned error status: {} for model '{}'", response.status(), request.model)));
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Ollama returned error status: {}. Model '{}' may not be available. Try 'ollama pull {}'", response.status(), request.model, request.model));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaBackend {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+
+        let mut token_logprobs = Vec::new();
+        collect_logprobs(&result["logprobs"], &mut token_logprobs);
+        
+        Ok(BackendOutput {
+            text: result["response"].as_str().unwrap_or("No response").to_string(),
+            prompt_tokens: result["prompt_eval_count"].as_u64(),
+            completion_tokens: result["eval_count"].as_u64(),
+            token_logprobs,
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        let mut response = self.send(request, true).await?;
+        let mut output = BackendOutput::default();
+
+        read_ndjson_stream(&mut response, STREAM_IDLE_TIMEOUT, |value| {
+            if let Some(token) = value["response"].as_str() {
+                if !token.is_empty() {
+                    output.text.push_str(token);
+                    on_token(token);
+                }
+            }
+            collect_logprobs(&value["logprobs"], &mut output.token_logprobs);
+
+            // The final line (done = true) carries the eval stats
+            if value["done"].as_bool().unwrap_or(false) {
+                output.prompt_tokens = value["prompt_eval_count"].as_u64();
+                output.completion_tokens = value["eval_count"].as_u64();
+            }
+        }).await?;
+
+        Ok(output)
+    }
+}
+
+pub struct OpenAiCompatibleBackend {
+    client: reqwest::Client,
+}
+
+impl OpenAiCompatibleBackend {
+    pub fn new(client: reqwest::Client) -> Self {
+        OpenAiCompatibleBackend { client }
+    }
+
+    fn payload(request: &GenerationRequest<'_>, stream: bool) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "model": request.model,
+            "messages": [
+                { "role": "user", "content": request.prompt }
+            ],
+            "stream": stream,
+            "temperature": request.options.temperature,
+            "top_p": request.options.top_p,
+            "max_tokens": request.options.max_tokens,
+            "logprobs": true,
+        });
+        if request.options.json.unwrap_or(false) {
+            payload["response_format"] = serde_json::json!({ "type": "json_object" });
+        }
+        payload
+    }
+
+    async fn send(&self, request: &GenerationRequest<'_>, stream: bool) -> Result<reqwest::Response> {
+        let mut builder = self.client
+            .post(request.endpoint)
+            .headers(request.headers.clone())
+            .json(&Self::payload(request, stream));
+
+        if !stream {
+            builder = builder.timeout(REQUEST_TIMEOUT);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| transient(format!("Failed to connect to OpenAI-compatible server at {}: {}", request.endpoint, e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("Server returned error status: {} for model '{}': {}", status, request.model, body);
+            let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+            return Err(if retryable { transient(message) } else { anyhow::anyhow!(message) });
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiCompatibleBackend {
+    async fn generate(&self, request: &GenerationRequest<'_>) -> Result<BackendOutput> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+
+        let text = result["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid chat completion response: missing choices[0].message.content"))?
+            .to_string();
+
+        let mut token_logprobs = Vec::new();
+        collect_logprobs(&result["choices"][0]["logprobs"]["content"], &mut token_logprobs);
+        
+        Ok(BackendOutput {
+            text,
+            prompt_tokens: result["usage"]["prompt_tokens"].as_u64(),
+            completion_tokens: result["usage"]["completion_tokens"].as_u64(),
+            token_logprobs,
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<BackendOutput> {
+        let mut response = self.send(request, true).await?;
+        let mut output = BackendOutput::default();
+
+        // Server-sent events: "data: {json}" lines, terminated by "data: [DONE]"
+        read_stream_lines(&mut response, STREAM_IDLE_TIMEOUT, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(());
+            };
+            if data == "[DONE]" {
+                return Ok(());
+            }
+
+            let value: serde_json::Value = serde_json::from_str(data)
+                .map_err(|e| anyhow::anyhow!("Failed to parse completion stream: {}", e))?;
+
+            if let Some(token) = value["choices"][0]["delta"]["content"].as_str() {
+                if !token.is_empty() {
+                    output.text.push_str(token);
+                    on_token(token);
+                }
+            }
+            collect_logprobs(&value["choices"][0]["logprobs"]["content"], &mut output.token_logprobs);
+
+            // Some servers send usage with the last chunk
+            if let Some(prompt_tokens) = value["usage"]["prompt_tokens"].as_u64() {
+                output.prompt_tokens = Some(prompt_tokens);
+            }
+            if let Some(completion_tokens) = value["usage"]["completion_tokens"].as_u64() {
+                output.completion_tokens = Some(completion_tokens);
+            }
+            Ok(())
+        }).await?;
+
+        Ok(output)
+    }
+}
+
+// Both Ollama and OpenAI-style servers report logprobs as an array of
+// { "token": ..., "logprob": ... } objects
+fn collect_logprobs(value: &serde_json::Value, logprobs: &mut Vec<f32>) {
+    if let Some(entries) = value.as_array() {
+        logprobs.extend(entries.iter().filter_map(|entry| entry["logprob"].as_f64()).map(|lp| lp as f32));
+    }
+}
+
+// Reads a streaming body line by line. Lines may be split across chunks, so
+// bytes are buffered until a full line is available. Each chunk has to arrive
+// within `idle_timeout`; there is no limit on the total duration.
+pub async fn read_stream_lines<F>(response: &mut reqwest::Response, idle_timeout: Duration, mut on_line: F) -> Result<()>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let mut buffer: Vec<u8> = Vec::new();
+
+    loop {
+        let chunk = tokio::time::timeout(idle_timeout, response.chunk())
+            .await
+            .map_err(|_| transient(format!("Server stopped responding for {} seconds", idle_timeout.as_secs())))??;
+
+        let Some(chunk) = chunk else { break };
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() {
+                on_line(line)?;
+            }
+        }
+    }
+
+    // A final line without a trailing newline
+    let rest = String::from_utf8_lossy(&buffer);
+    let rest = rest.trim();
+    if !rest.is_empty() {
+        on_line(rest)?;
+    }
+
+    Ok(())
+}
+
+// Ollama streams newline-delimited JSON objects
+pub async fn read_ndjson_stream<F>(response: &mut reqwest::Response, idle_timeout: Duration, mut on_value: F) -> Result<()>
+where
+    F: FnMut(serde_json::Value),
+{
+    read_stream_lines(response, idle_timeout, |line| {
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| anyhow::anyhow!("Failed to parse Ollama stream: {}", e))?;
+
+        if let Some(error) = value["error"].as_str() {
+            return Err(anyhow::anyhow!("Ollama stream error: {}", error));
+        }
+
+        on_value(value);
+        Ok(())
+    }).await
+}
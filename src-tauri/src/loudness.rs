@@ -0,0 +1,350 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_capture::StreamFormat;
+use crate::audio_file::{self, WavOutput};
+use crate::database::Database;
+use crate::dsp::Biquad;
+use crate::levels;
+
+pub const LIVE_LOUDNESS_EVENT: &str = "dwight://live-loudness";
+
+// ITU-R BS.1770 measures loudness over 400 ms blocks that overlap by 75%
+const BLOCK_MS: u32 = 400;
+const STEP_MS: u32 = 100;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+// EBU Tech 3342 loudness range: the spread of 3 s short-term loudness
+// between its 10th and 95th percentiles, gated 20 LU below the mean
+const SHORT_TERM_MS: u32 = 3000;
+const RANGE_GATE_LU: f64 = -20.0;
+const RANGE_LOW_PERCENTILE: f64 = 0.10;
+const RANGE_HIGH_PERCENTILE: f64 = 0.95;
+
+// Taps of each interpolation phase for true peak
+const TAPS_PER_PHASE: usize = 12;
+
+// Normalizing never pushes sample peaks above this, quieter targets or not
+const PEAK_CEILING_DB: f64 = -1.0;
+
+// The two BS.1770 K-weighting stages, a high shelf modelling the head and a
+// high-pass, derived for any sample rate rather than the tabled 48 kHz ones
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::from_coefficients(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::from_coefficients([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+    [shelf, high_pass]
+}
+
+// Surround channels count for more; the LFE of a 5.1 mix doesn't count at all
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn power_to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+// Peak of the signal between its samples, from polyphase interpolation to
+// 4x the rate below 96 kHz and 2x below 192 kHz, as BS.1770 annex 2 does
+struct TruePeakMeter {
+    // One windowed-sinc filter per interpolated point between two samples
+    phases: Vec<[f64; TAPS_PER_PHASE]>,
+    // Recent samples of each channel, newest first
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+    peak: f64,
+}
+
+impl TruePeakMeter {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let factor = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        let length = TAPS_PER_PHASE * factor;
+        let centre = (length - 1) as f64 / 2.0;
+        // At 192 kHz and up the samples are taken as they are
+        let phases = (0..factor)
+            .filter(|_| factor > 1)
+            .map(|phase| {
+                let mut taps = [0.0; TAPS_PER_PHASE];
+                for (tap, value) in taps.iter_mut().enumerate() {
+                    let n = tap * factor + phase;
+                    let x = (n as f64 - centre) / factor as f64;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / length as f64).cos();
+                    *value = sinc * window;
+                }
+                // Unity gain at DC, so a constant signal reads as itself
+                let sum: f64 = taps.iter().sum();
+                taps.map(|value| value / sum)
+            })
+            .collect();
+        TruePeakMeter { phases, history: vec![[0.0; TAPS_PER_PHASE]; channels], peak: 0.0 }
+    }
+
+    fn push(&mut self, channel: usize, sample: f64) {
+        self.peak = self.peak.max(sample.abs());
+        let history = &mut self.history[channel];
+        history.copy_within(..TAPS_PER_PHASE - 1, 1);
+        history[0] = sample;
+        for taps in &self.phases {
+            let interpolated: f64 = taps.iter().zip(history.iter()).map(|(tap, value)| tap * value).sum();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoudnessReport {
+    // None for audio too short or too quiet to measure
+    pub integrated_lufs: Option<f64>,
+    pub loudness_range_lu: Option<f64>,
+    // Over the last 400 ms and the last 3 s
+    pub momentary_lufs: Option<f64>,
+    pub short_term_lufs: Option<f64>,
+    pub true_peak_dbtp: f32,
+    pub sample_peak_dbfs: f32,
+    pub duration_seconds: f64,
+}
+
+// EBU R128 loudness of a stream, fed in chunks of any size
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    step_frames: u64,
+    frames: u64,
+    // Weighted power summed over the current 100 ms step
+    step_power: f64,
+    // Mean power of every completed step
+    steps: Vec<f64>,
+    peak: f32,
+    true_peak: TruePeakMeter,
+    sample_rate: u32,
+    total_frames: u64,
+}
+
+impl LoudnessMeter {
+    pub fn new(format: StreamFormat) -> Self {
+        let channels = format.channels.max(1) as usize;
+        LoudnessMeter {
+            channels,
+            filters: vec![k_weighting(format.sample_rate); channels],
+            step_frames: (format.sample_rate * STEP_MS / 1000).max(1) as u64,
+            frames: 0,
+            step_power: 0.0,
+            steps: Vec::new(),
+            peak: 0.0,
+            true_peak: TruePeakMeter::new(format.sample_rate, channels),
+            sample_rate: format.sample_rate.max(1),
+            total_frames: 0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, (&sample, filters)) in frame.iter().zip(&mut self.filters).enumerate() {
+                self.peak = self.peak.max(sample.abs());
+                self.true_peak.push(channel, sample as f64);
+                let shelved = filters[0].process(sample as f64);
+                let weighted = filters[1].process(shelved);
+                self.step_power += channel_weight(channel, self.channels) * weighted * weighted;
+            }
+            self.frames += 1;
+            self.total_frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.step_power / self.step_frames as f64);
+                self.frames = 0;
+                self.step_power = 0.0;
+            }
+        }
+    }
+
+    // Largest absolute sample so far
+    pub fn sample_peak(&self) -> f32 {
+        self.peak
+    }
+
+    // Mean power of every block `block_ms` long, a step apart, that is above
+    // the absolute gate
+    fn gated_blocks(&self, block_ms: u32) -> Vec<f64> {
+        let steps_per_block = (block_ms / STEP_MS) as usize;
+        self.steps
+            .windows(steps_per_block)
+            .map(|steps| steps.iter().sum::<f64>() / steps_per_block as f64)
+            .filter(|&power| power > 0.0 && power_to_lufs(power) > ABSOLUTE_GATE_LUFS)
+            .collect()
+    }
+
+    // Loudness of the last `block_ms` of the stream
+    fn recent(&self, block_ms: u32) -> Option<f64> {
+        let steps_per_block = (block_ms / STEP_MS) as usize;
+        let recent = self.steps.get(self.steps.len().checked_sub(steps_per_block)?..)?;
+        let power = recent.iter().sum::<f64>() / steps_per_block as f64;
+        (power > 0.0).then(|| power_to_lufs(power))
+    }
+
+    // Gated integrated loudness in LUFS, or None for audio that is too short
+    // or too quiet to measure
+    pub fn integrated(&self) -> Option<f64> {
+        let blocks = self.gated_blocks(BLOCK_MS);
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let relative_gate = power_to_lufs(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks.into_iter().filter(|&power| power_to_lufs(power) > relative_gate).collect();
+        (!gated.is_empty()).then(|| power_to_lufs(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+
+    // Loudness range in LU, or None for audio under 3 s or too quiet
+    pub fn loudness_range(&self) -> Option<f64> {
+        let blocks = self.gated_blocks(SHORT_TERM_MS);
+        if blocks.is_empty() {
+            return None;
+        }
+        let relative_gate = power_to_lufs(blocks.iter().sum::<f64>() / blocks.len() as f64) + RANGE_GATE_LU;
+        let mut loudness: Vec<f64> = blocks.into_iter().map(power_to_lufs).filter(|&lufs| lufs > relative_gate).collect();
+        loudness.sort_by(f64::total_cmp);
+        let percentile = |fraction: f64| loudness[((loudness.len() - 1) as f64 * fraction).round() as usize];
+        Some(percentile(RANGE_HIGH_PERCENTILE) - percentile(RANGE_LOW_PERCENTILE))
+    }
+
+    pub fn report(&self) -> LoudnessReport {
+        LoudnessReport {
+            integrated_lufs: self.integrated(),
+            loudness_range_lu: self.loudness_range(),
+            momentary_lufs: self.recent(BLOCK_MS),
+            short_term_lufs: self.recent(SHORT_TERM_MS),
+            true_peak_dbtp: levels::to_db(self.true_peak.peak as f32),
+            sample_peak_dbfs: levels::to_db(self.peak),
+            duration_seconds: self.total_frames as f64 / self.sample_rate as f64,
+        }
+    }
+}
+
+pub fn measure_file(path: &Path) -> Result<LoudnessMeter> {
+    let spec = audio_file::audio_spec(path)?;
+    let mut meter = LoudnessMeter::new(audio_file::spec_format(&spec));
+    audio_file::for_each_chunk(path, |chunk| {
+        meter.push(chunk);
+        Ok(())
+    })?;
+    Ok(meter)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizedRecording {
+    pub record_id: i64,
+    pub file_path: String,
+    pub measured_lufs: f64,
+    pub target_lufs: f64,
+    pub gain_db: f64,
+    // Below the requested gain when the peak ceiling held it back
+    pub limited_by_peak: bool,
+}
+
+fn normalized_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}-normalized.wav", stem))
+}
+
+// Measures the recording and writes a copy with the gain that brings it to
+// `target_lufs`; the original file is never modified
+pub fn normalize_file(path: &Path, target_lufs: f64) -> Result<(PathBuf, f64, f64, bool)> {
+    let meter = measure_file(path)?;
+    let measured = meter.integrated().ok_or_else(|| anyhow::anyhow!("Recording is too short or too quiet to measure its loudness"))?;
+
+    let mut gain_db = target_lufs - measured;
+    let peak_db = 20.0 * (meter.sample_peak() as f64).log10();
+    let limited = peak_db + gain_db > PEAK_CEILING_DB;
+    if limited {
+        gain_db = PEAK_CEILING_DB - peak_db;
+    }
+    let gain = 10f64.powf(gain_db / 20.0) as f32;
+
+    let output_path = normalized_path(path);
+    let mut output = WavOutput::create(&output_path, audio_file::audio_spec(path)?)?;
+    audio_file::for_each_chunk(path, |chunk| {
+        let scaled: Vec<f32> = chunk.iter().map(|&sample| sample * gain).collect();
+        output.write(&scaled)
+    })?;
+    output.finish()?;
+    Ok((output_path, measured, gain_db, limited))
+}
+
+#[command]
+pub async fn normalize_recording(record_id: i64, target_lufs: Option<f64>, app_handle: tauri::AppHandle) -> Result<NormalizedRecording, String> {
+    // The EBU R128 broadcast target
+    let target_lufs = target_lufs.unwrap_or(-23.0);
+    if !(-70.0..=0.0).contains(&target_lufs) {
+        return Err("target_lufs must be between -70 and 0".to_string());
+    }
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(record_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", record_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (path, measured_lufs, gain_db, limited_by_peak) = tokio::task::spawn_blocking(move || normalize_file(&source, target_lufs))
+        .await
+        .map_err(|e| format!("Normalization error: {}", e))?
+        .map_err(|e| format!("Normalization error: {}", e))?;
+
+    let file_path = path.to_string_lossy().to_string();
+    db.save_audio_version(record_id, "normalized", &file_path).map_err(|e| format!("Database error: {}", e))?;
+    Ok(NormalizedRecording {
+        record_id,
+        file_path,
+        measured_lufs,
+        target_lufs,
+        gain_db,
+        limited_by_peak,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingLoudness {
+    pub recording_id: i64,
+    #[serde(flatten)]
+    pub report: LoudnessReport,
+}
+
+// EBU R128 figures for a recording, e.g. for its documentation or to pick a
+// normalization target
+#[command]
+pub async fn analyze_loudness(recording_id: i64, app_handle: tauri::AppHandle) -> Result<RecordingLoudness, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let meter = tokio::task::spawn_blocking(move || measure_file(&source))
+        .await
+        .map_err(|e| format!("Loudness error: {}", e))?
+        .map_err(|e| format!("Loudness error: {}", e))?;
+    Ok(RecordingLoudness { recording_id, report: meter.report() })
+}
@@ -3,32 +3,232 @@
     windows_subsystem = "windows"
 )]
 
-use tauri::WindowEvent;
+use tauri::{Manager, WindowEvent};
 
 mod whisper;
+mod transcription;
+#[cfg(not(feature = "whisper-cpp"))]
+mod candle_whisper;
+#[cfg(not(feature = "whisper-cpp"))]
+mod whisper_alignment;
+#[cfg(feature = "whisper-cpp")]
+mod whisper_cpp_model;
+mod whisper_models;
+mod live_transcription;
+mod diarization;
+mod speaker_embedding;
+mod speaker_id;
+mod vocabulary;
+mod audio_devices;
+#[cfg(target_os = "linux")]
+mod pulse_monitor;
+mod audio_capture;
+mod vad;
+mod resampler;
+mod levels;
+mod denoise;
+mod agc;
+mod loudness;
+mod audio_quality;
+mod fingerprint;
+mod dsp;
+mod filters;
+mod audio_file;
+mod clips;
+mod anonymize;
+mod spectrogram;
+mod waveform;
+mod recovery;
+mod segmentation;
+mod scheduler;
+mod import;
+mod input_gain;
+mod channel_policy;
+mod watch_folders;
+mod keyword_spotter;
+mod sound_events;
+mod dtmf;
+mod inaudible;
+mod playback;
+mod timestretch;
 mod database;
 mod ai;
 mod ai_models;
+mod ai_cache;
+mod ai_requests;
+mod llm_backend;
+mod cloud_backends;
+mod demo_backend;
+mod embeddings;
+mod rag;
+mod reranker;
+mod query_expansion;
+mod vector_store;
+mod document_ingest;
+mod transcript_index;
+mod llama_cpp_engine;
+mod resilience;
+mod tokenizer;
+mod context_window;
+mod structured_output;
 mod python_integration;
+mod chat_sessions;
+mod system_prompts;
+mod prompt_templates;
+mod credentials;
+mod ai_tools;
+mod guardrails;
+mod benchmark;
+mod acceleration;
+mod transcription_queue;
+mod subtitles;
+mod transcript_cleanup;
+mod transcript_versions;
+mod transcript_audio;
+mod transcript_tracks;
+mod accuracy;
 
 fn main() {
     tauri::Builder::default()
+        .manage(ai_models::AdvancedAI::new())
+        .manage(transcript_index::TranscriptIndexer::new())
+        .manage(audio_capture::AudioCapture::new())
+        .manage(transcription::Transcriber::new())
+        .manage(whisper_models::WhisperDownloads::new())
+        .manage(transcription_queue::TranscriptionQueue::new())
+        .manage(scheduler::Scheduler::new())
+        .manage(watch_folders::FolderWatcher::new())
+        .manage(playback::Player::new())
         .setup(|app| {
             // Initialize database on startup
             let app_handle = app.handle();
-            match database::Database::new(&app_handle) {
-                Ok(_) => println!("Database initialized successfully"),
+            match database::Database::new(app_handle) {
+                Ok(db) => {
+                    println!("Database initialized successfully");
+                    app.state::<ai_models::AdvancedAI>().load_settings(&db);
+                    app.state::<audio_capture::AudioCapture>().load_settings(&db);
+                    match recovery::recover_recordings(app_handle, &db) {
+                        Ok(recovered) if !recovered.is_empty() => println!("Recovered {} interrupted recordings", recovered.len()),
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Failed to recover interrupted recordings: {}", e),
+                    }
+                }
                 Err(e) => eprintln!("Failed to initialize database: {}", e),
             }
+            
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    let ai = app.state::<ai_models::AdvancedAI>();
+                    ai.set_cache_dir(dir.join("ai_cache"));
+                    ai.set_embedding_model_dir(dir.join("embedding_models"));
+                }
+                Err(e) => eprintln!("AI response cache is memory-only: {}", e),
+            }
+            transcript_index::start_indexer(app_handle);
+            scheduler::start_scheduler(app_handle);
+            watch_folders::start_watcher(app_handle);
+            transcription_queue::start_queue(app_handle);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Whisper transcription
             whisper::transcribe_audio,
             whisper::transcribe_audio_detailed,
+            whisper::transcribe_tracks,
+            whisper::find_transcript_phrase,
             whisper::analyze_audio_features,
             whisper::configure_whisper,
             whisper::get_whisper_status,
+            transcription::transcribe_recording,
+            transcription::retranscribe_region,
+            transcription::transcribe_file,
+            transcription::list_whisper_models,
+            acceleration::get_transcription_acceleration,
+            acceleration::benchmark_transcription,
+            transcription_queue::enqueue_transcriptions,
+            transcription_queue::list_transcription_jobs,
+            transcription_queue::pause_transcription_job,
+            transcription_queue::resume_transcription_job,
+            transcription_queue::cancel_transcription_job,
+            transcription_queue::clear_finished_transcription_jobs,
+            subtitles::export_transcript,
+            transcript_cleanup::clean_transcript,
+            transcript_cleanup::get_clean_transcript,
+            transcript_cleanup::add_replacement_rule,
+            transcript_cleanup::list_replacement_rules,
+            transcript_cleanup::remove_replacement_rule,
+            transcript_versions::list_transcript_versions,
+            transcript_versions::diff_transcript_versions,
+            transcript_versions::rollback_transcript,
+            transcript_audio::get_line_audio,
+            transcript_tracks::list_transcript_tracks,
+            transcript_tracks::get_transcript_track,
+            transcript_tracks::add_transcript_track,
+            transcript_tracks::select_transcript_track,
+            transcript_tracks::compare_transcript_tracks,
+            transcript_tracks::remove_transcript_track,
+            accuracy::compute_word_error_rate,
+            accuracy::benchmark_word_error_rate,
+            whisper_models::list_available_whisper_models,
+            whisper_models::download_whisper_model,
+            whisper_models::delete_whisper_model,
+            diarization::diarize_recording,
+            speaker_id::enroll_speaker,
+            speaker_id::list_enrolled_speakers,
+            speaker_id::remove_enrolled_speaker,
+            speaker_id::identify_speakers,
+            speaker_id::get_speaker_attributions,
+            vocabulary::add_vocabulary_term,
+            vocabulary::list_vocabulary_terms,
+            vocabulary::remove_vocabulary_term,
+            
+            // Audio capture
+            audio_devices::list_audio_devices,
+            audio_capture::start_recording,
+            audio_capture::stop_recording,
+            audio_capture::resume_recording,
+            recovery::get_resumable_recordings,
+            recovery::dismiss_resumable_recording,
+            audio_capture::start_monitoring,
+            audio_capture::stop_monitoring,
+            audio_capture::get_capture_status,
+            audio_capture::get_capture_settings,
+            audio_capture::set_capture_settings,
+            input_gain::set_input_gain,
+            input_gain::calibrate_input_gain,
+            channel_policy::set_channel_policy,
+            loudness::normalize_recording,
+            filters::filter_recording,
+            loudness::analyze_loudness,
+            audio_quality::analyze_recording_quality,
+            audio_quality::get_recording_quality,
+            fingerprint::find_duplicates,
+            sound_events::classify_sound_events,
+            dtmf::detect_dtmf,
+            dtmf::get_dtmf_digits,
+            inaudible::detect_inaudible_activity,
+            inaudible::get_inaudible_events,
+            clips::export_clip,
+            segmentation::extract_segment,
+            anonymize::export_anonymized_clip,
+            spectrogram::generate_spectrogram,
+            waveform::get_waveform_peaks,
+            scheduler::list_schedules,
+            scheduler::create_schedule,
+            scheduler::delete_schedule,
+            watch_folders::list_watch_folders,
+            watch_folders::add_watch_folder,
+            watch_folders::remove_watch_folder,
+            watch_folders::get_imported_files,
+            import::import_audio_files,
+            
+            // Playback
+            playback::play,
+            playback::pause,
+            playback::seek,
+            playback::set_speed,
+            playback::get_position,
+            playback::stop_playback,
             
             // Original AI chat
             ai::chat_with_dwight,
@@ -40,6 +240,78 @@ fn main() {
             ai_models::get_ai_models,
             ai_models::enhanced_dwight_chat,
             ai_models::ai_audio_analysis,
+            ai_models::get_model_configs,
+            ai_models::set_model_config,
+            ai_models::set_model_endpoint,
+            ai_models::test_model_endpoint,
+            ai_models::get_ollama_host,
+            ai_models::set_ollama_host,
+            ai_models::get_generation_defaults,
+            ai_models::set_generation_defaults,
+            ai_models::pull_ollama_model,
+            ai_models::delete_ollama_model,
+            ai_models::cancel_ai_request,
+            ai_models::get_active_ai_requests,
+            ai_models::get_retry_policy,
+            ai_models::set_retry_policy,
+            ai_models::get_demo_mode,
+            ai_models::set_demo_mode,
+            ai_models::get_model_fallback_chain,
+            ai_models::set_model_fallback_chain,
+            ai_models::get_circuit_breaker_status,
+            ai_models::clear_ai_cache,
+            ai_models::get_ai_cache_on_disk,
+            ai_models::set_ai_cache_on_disk,
+            ai_models::check_ai_health,
+            ai_models::compare_models,
+            benchmark::benchmark_model,
+            ai_models::get_guardrail_settings,
+            ai_models::set_guardrail_settings,
+            ai_models::get_rag_settings,
+            ai_models::set_rag_settings,
+            ai_models::embed_texts,
+            
+            // Persistent RAG vector store
+            vector_store::add_rag_chunks,
+            vector_store::delete_rag_chunks,
+            vector_store::search_rag_chunks,
+            vector_store::create_rag_index,
+            vector_store::list_rag_indexes,
+            vector_store::rebuild_index,
+            vector_store::delete_index,
+            vector_store::index_stats,
+            document_ingest::ingest_document,
+            transcript_index::index_transcripts,
+            
+            // Conversation sessions
+            chat_sessions::create_session,
+            chat_sessions::append_message,
+            chat_sessions::list_sessions,
+            chat_sessions::get_session_messages,
+            chat_sessions::delete_session,
+            chat_sessions::get_session_summary,
+            chat_sessions::session_chat,
+            
+            // Persona prompt profiles
+            system_prompts::get_system_prompt,
+            system_prompts::set_system_prompt,
+            system_prompts::list_system_prompt_profiles,
+            system_prompts::switch_system_prompt_profile,
+            system_prompts::delete_system_prompt_profile,
+            
+            // Prompt templates
+            prompt_templates::list_prompt_templates,
+            prompt_templates::set_prompt_template,
+            prompt_templates::reset_prompt_template,
+            
+            // Endpoint credentials
+            credentials::set_model_credentials,
+            credentials::get_model_credentials,
+            credentials::clear_model_credentials,
+            
+            // Tool calling
+            ai_tools::list_ai_tools,
+            ai_tools::dwight_agent_chat,
             
             // Python integration
             python_integration::execute_python_script,
@@ -50,16 +322,34 @@ fn main() {
             // Database operations
             database_commands::save_audio_record,
             database_commands::get_audio_records,
+            database_commands::update_audio_transcript,
+            database_commands::delete_audio_record,
+            database_commands::get_audio_versions,
+            database_commands::get_recording_segments,
+            database_commands::get_transcript_lines,
+            database_commands::get_recording_language,
+            database_commands::get_transcript_translation,
+            database_commands::search_audio_records,
+            database_commands::search_transcripts,
+            database_commands::get_schema_version,
+            database_commands::get_low_confidence_lines,
+            database_commands::get_recording_gaps,
+            database_commands::get_recording_metadata,
+            database_commands::update_recording_notes,
             database_commands::save_trigger,
             database_commands::get_triggers,
             
             // File operations
             file_commands::save_audio_file
         ])
-        .on_window_event(|_window, event| {
-            if let WindowEvent::CloseRequested { .. } = event {
+        .on_window_event(|window, event| match event {
+            WindowEvent::CloseRequested { .. } => {
                 // Handle cleanup if needed
             }
+            WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                import::import_dropped(window.app_handle(), paths.clone());
+            }
+            _ => {}
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri app");
@@ -67,7 +357,12 @@ fn main() {
 
 mod database_commands {
     use tauri::command;
-    use crate::database::{Database, AudioRecord, SoundTrigger};
+    use crate::database::{
+        Database, AudioRecord, AudioVersion, LowConfidenceLine, RecordingGap, RecordingMetadata, RecordingSegment, SchemaStatus, SoundTrigger,
+        TranscriptLine, TranscriptMatch, TranscriptSearchFilters, TranscriptTranslation,
+    };
+    use crate::transcript_cleanup;
+    use crate::transcript_index;
 
     #[command]
     pub async fn save_audio_record(
@@ -90,7 +385,13 @@ mod database_commands {
             triggers,
         };
         
-        db.save_audio_record(&record).map_err(|e| format!("Database error: {}", e))
+        let record_id = db.save_audio_record(&record).map_err(|e| format!("Database error: {}", e))?;
+        
+        // Make the transcript searchable by Dwight's RAG
+        if record.transcript.as_deref().is_some_and(|transcript| !transcript.trim().is_empty()) {
+            transcript_index::queue_recording_update(&app_handle, record_id, &[]);
+        }
+        Ok(record_id)
     }
 
     #[command]
@@ -100,6 +401,175 @@ mod database_commands {
         db.get_all_audio_records().map_err(|e| format!("Database error: {}", e))
     }
 
+    // Edited chunks are re-indexed in the background; unchanged ones keep their vectors
+    #[command]
+    pub async fn update_audio_transcript(
+        record_id: i64,
+        transcript: String,
+        app_handle: tauri::AppHandle,
+    ) -> Result<(), String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        if !db.update_audio_transcript(record_id, &transcript).map_err(|e| format!("Database error: {}", e))? {
+            return Err(format!("Recording {} not found", record_id));
+        }
+        transcript_cleanup::refresh(&app_handle, record_id)?;
+        transcript_index::queue_recording_update(&app_handle, record_id, &[]);
+        Ok(())
+    }
+
+    // Removes the record and its RAG chunks; the audio file is left alone
+    #[command]
+    pub async fn delete_audio_record(record_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        let deleted = db.delete_audio_record(record_id).map_err(|e| format!("Database error: {}", e))?;
+        if deleted {
+            transcript_index::queue_recording_removal(&app_handle, record_id);
+        }
+        Ok(deleted)
+    }
+
+    // Files derived from a recording, such as its denoised copy
+    #[command]
+    pub async fn get_audio_versions(record_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<AudioVersion>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_audio_versions(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The parts a recording was split into at its silences, if it was
+    #[command]
+    pub async fn get_recording_segments(record_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<RecordingSegment>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_recording_segments(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // How and where a recording was captured, or None for one that
+    // wasn't captured here, such as an import
+    #[command]
+    pub async fn get_recording_metadata(record_id: i64, app_handle: tauri::AppHandle) -> Result<Option<RecordingMetadata>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_recording_metadata(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    #[command]
+    pub async fn update_recording_notes(
+        record_id: i64,
+        location: Option<String>,
+        notes: Option<String>,
+        app_handle: tauri::AppHandle,
+    ) -> Result<(), String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+        let location = location.filter(|location| !location.trim().is_empty());
+        let notes = notes.filter(|notes| !notes.trim().is_empty());
+
+        if !db.update_recording_notes(record_id, location.as_deref(), notes.as_deref()).map_err(|e| format!("Database error: {}", e))? {
+            return Err(format!("No capture metadata for recording {}", record_id));
+        }
+        Ok(())
+    }
+
+    // Where a resumed recording was cut off, and for how long
+    #[command]
+    pub async fn get_recording_gaps(record_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<RecordingGap>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_recording_gaps(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The timed lines of a recording's transcript, from its last transcription
+    #[command]
+    pub async fn get_transcript_lines(record_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<TranscriptLine>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_transcript_lines(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The language most of a recording's transcript is in, once transcribed
+    #[command]
+    pub async fn get_recording_language(record_id: i64, app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_recording_language(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The English translation stored beside a recording's transcript, if it
+    // was transcribed with translation and wasn't in English
+    #[command]
+    pub async fn get_transcript_translation(record_id: i64, app_handle: tauri::AppHandle) -> Result<Option<TranscriptTranslation>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_transcript_translation(record_id).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Recordings matching the query, newest first, optionally only those
+    // with speech in a language
+    #[command]
+    pub async fn search_audio_records(
+        query: String,
+        language: Option<String>,
+        limit: Option<usize>,
+        app_handle: tauri::AppHandle,
+    ) -> Result<Vec<AudioRecord>, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.search_audio_records(&query, language.as_deref(), limit.unwrap_or(50).clamp(1, 500))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The database's schema version, which migrations have been applied and
+    // the backups taken before them
+    #[command]
+    pub async fn get_schema_version(app_handle: tauri::AppHandle) -> Result<SchemaStatus, String> {
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+        db.schema_status(&app_handle).map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Transcript and translation lines with all of the query's words across
+    // every recording, most relevant first, each with where it was said and
+    // a highlighted snippet. "Quoted words" match as a phrase and word* as a prefix.
+    #[command]
+    pub async fn search_transcripts(
+        query: String,
+        filters: Option<TranscriptSearchFilters>,
+        limit: Option<usize>,
+        app_handle: tauri::AppHandle,
+    ) -> Result<Vec<TranscriptMatch>, String> {
+        if query.trim().is_empty() {
+            return Err("No words to search for".to_string());
+        }
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+        db.search_transcripts(&query, &filters.unwrap_or_default(), limit.unwrap_or(50).clamp(1, 500))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // exp(-1): Whisper takes a window whose mean log-probability is under -1
+    // as a failed decode
+    const DEFAULT_LOW_CONFIDENCE: f32 = 0.37;
+
+    // Transcript lines Whisper was less sure of than `below` (0 to 1), least
+    // sure first, for a reviewer to listen to. All recordings without
+    // `record_id`.
+    #[command]
+    pub async fn get_low_confidence_lines(
+        below: Option<f32>,
+        record_id: Option<i64>,
+        limit: Option<usize>,
+        app_handle: tauri::AppHandle,
+    ) -> Result<Vec<LowConfidenceLine>, String> {
+        let below = below.unwrap_or(DEFAULT_LOW_CONFIDENCE);
+        if !(0.0..=1.0).contains(&below) {
+            return Err("below must be between 0.0 and 1.0".to_string());
+        }
+        let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+
+        db.get_low_confidence_lines(record_id, below, limit.unwrap_or(50).clamp(1, 500))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
     #[command]
     pub async fn save_trigger(
         trigger_type: String,
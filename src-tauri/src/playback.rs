@@ -0,0 +1,453 @@
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{command, Emitter, State};
+use tokio::sync::oneshot;
+use crate::audio_capture::{AudioCapture, StreamFormat};
+use crate::audio_file;
+use crate::database::Database;
+use crate::resampler::{FormatConverter, ResampleQuality};
+use crate::timestretch::{TimeStretcher, MAX_STRETCH_SPEED, MIN_STRETCH_SPEED};
+
+pub const PLAYBACK_POSITION_EVENT: &str = "dwight://playback-position";
+
+// How often the thread tops up the output and how often position is sent
+const TICK: Duration = Duration::from_millis(20);
+const POSITION_INTERVAL: Duration = Duration::from_millis(100);
+
+// Audio kept ready for the output, enough to ride out a slow read
+const BUFFER_SECONDS: f64 = 0.5;
+
+// Decoded chunks read ahead of the output
+const DECODE_AHEAD_CHUNKS: usize = 2;
+
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 4.0;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlaybackStatus {
+    pub recording_id: Option<i64>,
+    pub position_seconds: f64,
+    pub duration_seconds: f64,
+    pub playing: bool,
+    pub speed: f32,
+    // Whether a speed other than 1.0 keeps the voices at their own pitch
+    pub preserve_pitch: bool,
+    // Played through to the end; playing again starts over
+    pub ended: bool,
+}
+
+enum PlaybackMessage {
+    Resume,
+    Pause,
+    Seek(f64),
+    SetSpeed(f32, bool),
+    Stop,
+    StreamError(String),
+}
+
+// Feeds the device from the shared buffer, with silence while paused or empty
+fn build_typed_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    paused: Arc<AtomicBool>,
+    errors: mpsc::Sender<PlaybackMessage>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut buffer = buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let paused = paused.load(Ordering::Relaxed);
+            for sample in data.iter_mut() {
+                let value = if paused { 0.0 } else { buffer.pop_front().unwrap_or(0.0) };
+                *sample = T::from_sample(value);
+            }
+        },
+        move |e| {
+            let _ = errors.send(PlaybackMessage::StreamError(e.to_string()));
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn open_output(
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    paused: Arc<AtomicBool>,
+    errors: mpsc::Sender<PlaybackMessage>,
+) -> Result<(cpal::Stream, StreamFormat)> {
+    let device = cpal::default_host().default_output_device().ok_or_else(|| anyhow::anyhow!("No default audio output device"))?;
+    let supported = device.default_output_config()?;
+    let format = StreamFormat { sample_rate: supported.sample_rate().0, channels: supported.channels() };
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_typed_stream::<f32>(&device, &config, buffer, paused, errors),
+        SampleFormat::F64 => build_typed_stream::<f64>(&device, &config, buffer, paused, errors),
+        SampleFormat::I8 => build_typed_stream::<i8>(&device, &config, buffer, paused, errors),
+        SampleFormat::I16 => build_typed_stream::<i16>(&device, &config, buffer, paused, errors),
+        SampleFormat::I32 => build_typed_stream::<i32>(&device, &config, buffer, paused, errors),
+        SampleFormat::U8 => build_typed_stream::<u8>(&device, &config, buffer, paused, errors),
+        SampleFormat::U16 => build_typed_stream::<u16>(&device, &config, buffer, paused, errors),
+        SampleFormat::U32 => build_typed_stream::<u32>(&device, &config, buffer, paused, errors),
+        other => Err(anyhow::anyhow!("Unsupported sample format {:?}", other)),
+    }?;
+    stream.play()?;
+    Ok((stream, format))
+}
+
+// Owns the output stream, which can't leave the thread that made it, and
+// decodes the file ahead of it on a thread of its own
+struct PlaybackThread {
+    app_handle: tauri::AppHandle,
+    recording_id: i64,
+    path: PathBuf,
+    source: StreamFormat,
+    output: StreamFormat,
+    duration: f64,
+    quality: ResampleQuality,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    paused: Arc<AtomicBool>,
+    status: Arc<Mutex<PlaybackStatus>>,
+    decoder: Option<mpsc::Receiver<Vec<f32>>>,
+    converter: FormatConverter,
+    stretcher: Option<TimeStretcher>,
+    // Source time at the end of what has gone into the buffer
+    decoded_until: f64,
+    speed: f32,
+    preserve_pitch: bool,
+    ended: bool,
+}
+
+impl PlaybackThread {
+    // Without the stretcher, speed changes the rate the source is read at,
+    // so the converter plays it back faster or slower, pitch and all
+    fn new_converter(&self) -> FormatConverter {
+        let speed = if self.stretcher.is_some() { 1.0 } else { self.speed as f64 };
+        let rate = ((self.source.sample_rate as f64 * speed).round() as u32).max(1);
+        FormatConverter::new(StreamFormat { sample_rate: rate, ..self.source }, self.output, self.quality)
+    }
+
+    // Starts decoding at `position`, dropping whatever was buffered
+    fn start_at(&mut self, position: f64) {
+        let position = position.clamp(0.0, self.duration);
+        let (sender, receiver) = mpsc::sync_channel(DECODE_AHEAD_CHUNKS);
+        let (path, end) = (self.path.clone(), self.duration);
+        let spawned = std::thread::Builder::new().name("playback-decoder".to_string()).spawn(move || {
+            // Sending fails once playback moves on, which ends the read
+            let _ = audio_file::for_each_chunk_between(&path, position, end, |chunk| {
+                sender.send(chunk.to_vec()).map_err(|_| anyhow::anyhow!("Playback moved on"))
+            });
+        });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start playback decoder: {}", e);
+        }
+        self.decoder = Some(receiver);
+        self.stretcher = (self.preserve_pitch && self.speed != 1.0).then(|| TimeStretcher::new(self.source, self.speed));
+        self.converter = self.new_converter();
+        self.decoded_until = position;
+        self.ended = false;
+        self.lock_buffer().clear();
+    }
+
+    fn lock_buffer(&self) -> std::sync::MutexGuard<'_, VecDeque<f32>> {
+        self.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Source time of the next sample the device plays
+    fn position(&self) -> f64 {
+        let buffered = self.lock_buffer().len() / self.output.channels.max(1) as usize;
+        let mut behind = buffered as f64 * self.speed as f64 / self.output.sample_rate as f64;
+        if let Some(stretcher) = self.stretcher.as_ref() {
+            behind += stretcher.delay_seconds(self.source.sample_rate);
+        }
+        (self.decoded_until - behind).clamp(0.0, self.duration)
+    }
+
+    fn fill(&mut self) {
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        let target = (self.output.sample_rate as f64 * BUFFER_SECONDS) as usize * self.output.channels as usize;
+        while self.lock_buffer().len() < target {
+            let Some(decoder) = self.decoder.as_ref() else {
+                break;
+            };
+            match decoder.try_recv() {
+                Ok(chunk) => {
+                    self.decoded_until += (chunk.len() / self.source.channels.max(1) as usize) as f64 / self.source.sample_rate as f64;
+                    let stretched = match self.stretcher.as_mut() {
+                        Some(stretcher) => stretcher.process(&chunk),
+                        None => chunk,
+                    };
+                    let converted = self.converter.convert(&stretched);
+                    self.lock_buffer().extend(converted);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let mut rest = match self.stretcher.as_mut() {
+                        Some(stretcher) => {
+                            let stretched = stretcher.flush();
+                            self.converter.convert(&stretched)
+                        }
+                        None => Vec::new(),
+                    };
+                    rest.extend(self.converter.flush());
+                    self.lock_buffer().extend(rest);
+                    self.decoder = None;
+                }
+            }
+        }
+        if self.decoder.is_none() && !self.ended && self.lock_buffer().is_empty() {
+            self.ended = true;
+            self.paused.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn update_status(&self) -> PlaybackStatus {
+        let status = PlaybackStatus {
+            recording_id: Some(self.recording_id),
+            position_seconds: if self.ended { self.duration } else { self.position() },
+            duration_seconds: self.duration,
+            playing: !self.paused.load(Ordering::Relaxed),
+            speed: self.speed,
+            preserve_pitch: self.preserve_pitch,
+            ended: self.ended,
+        };
+        *self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = status.clone();
+        status
+    }
+
+    fn emit_status(&self) {
+        let status = self.update_status();
+        if let Err(e) = self.app_handle.emit(PLAYBACK_POSITION_EVENT, status) {
+            eprintln!("Failed to emit playback position: {}", e);
+        }
+    }
+
+    // Runs until stopped, the device fails or the session is dropped
+    fn run(mut self, receiver: mpsc::Receiver<PlaybackMessage>) {
+        let mut position_due = Instant::now();
+        loop {
+            match receiver.recv_timeout(TICK) {
+                Ok(PlaybackMessage::Resume) => {
+                    if self.ended {
+                        self.start_at(0.0);
+                    }
+                    self.paused.store(false, Ordering::Relaxed);
+                }
+                Ok(PlaybackMessage::Pause) => {
+                    // Keep the place the listener heard up to, not what was read ahead
+                    let position = self.position();
+                    self.paused.store(true, Ordering::Relaxed);
+                    self.start_at(position);
+                }
+                Ok(PlaybackMessage::Seek(position)) => self.start_at(position),
+                Ok(PlaybackMessage::SetSpeed(speed, preserve_pitch)) => {
+                    let position = self.position();
+                    self.speed = speed;
+                    self.preserve_pitch = preserve_pitch;
+                    self.start_at(position);
+                }
+                Ok(PlaybackMessage::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                Ok(PlaybackMessage::StreamError(error)) => {
+                    eprintln!("Playback stopped: {}", error);
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            let was_ended = self.ended;
+            self.fill();
+            if Instant::now() >= position_due || self.ended != was_ended {
+                self.emit_status();
+                position_due = Instant::now() + POSITION_INTERVAL;
+            }
+        }
+        self.paused.store(true, Ordering::Relaxed);
+        self.emit_status();
+    }
+}
+
+struct PlaybackSession {
+    recording_id: i64,
+    sender: mpsc::Sender<PlaybackMessage>,
+    status: Arc<Mutex<PlaybackStatus>>,
+}
+
+impl PlaybackSession {
+    fn send(&self, message: PlaybackMessage) -> Result<(), String> {
+        self.sender.send(message).map_err(|_| "Playback has stopped".to_string())
+    }
+
+    fn status(&self) -> PlaybackStatus {
+        self.status.lock().map(|status| status.clone()).unwrap_or_default()
+    }
+}
+
+// Plays one recording at a time through the default output
+pub struct Player {
+    session: Mutex<Option<PlaybackSession>>,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Player { session: Mutex::new(None) }
+    }
+
+    fn with_session<T>(&self, action: impl FnOnce(&PlaybackSession) -> Result<T, String>) -> Result<T, String> {
+        let session = self.session.lock().map_err(|_| "Playback state is unavailable".to_string())?;
+        let session = session.as_ref().ok_or_else(|| "Nothing is playing".to_string())?;
+        action(session)
+    }
+}
+
+async fn spawn_playback(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    path: PathBuf,
+    position: f64,
+    quality: ResampleQuality,
+) -> Result<PlaybackSession, String> {
+    let (source, duration) = audio_file::audio_info(&path).map_err(|e| format!("Playback error: {}", e))?;
+    let (sender, receiver) = mpsc::channel();
+    let status = Arc::new(Mutex::new(PlaybackStatus::default()));
+    let (ready, started) = oneshot::channel();
+    let app_handle = app_handle.clone();
+    let thread_sender = sender.clone();
+    let thread_status = status.clone();
+
+    std::thread::Builder::new()
+        .name("playback".to_string())
+        .spawn(move || {
+            let buffer = Arc::new(Mutex::new(VecDeque::new()));
+            let paused = Arc::new(AtomicBool::new(false));
+            let (_stream, output) = match open_output(buffer.clone(), paused.clone(), thread_sender) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    let _ = ready.send(Err(format!("Playback error: {}", e)));
+                    return;
+                }
+            };
+            let mut thread = PlaybackThread {
+                app_handle,
+                recording_id,
+                path,
+                source,
+                output,
+                duration,
+                quality,
+                buffer,
+                paused,
+                status: thread_status,
+                decoder: None,
+                converter: FormatConverter::new(source, output, quality),
+                stretcher: None,
+                decoded_until: 0.0,
+                speed: 1.0,
+                preserve_pitch: false,
+                ended: false,
+            };
+            thread.start_at(position);
+            thread.update_status();
+            let _ = ready.send(Ok(()));
+            thread.run(receiver);
+        })
+        .map_err(|e| format!("Failed to start playback thread: {}", e))?;
+
+    started.await.map_err(|_| "Playback thread exited".to_string())??;
+    Ok(PlaybackSession { recording_id, sender, status })
+}
+
+// Plays a recording from `position` seconds, or resumes it where it was
+// paused; any other recording playing is stopped
+#[command]
+pub async fn play(
+    recording_id: i64,
+    position: Option<f64>,
+    app_handle: tauri::AppHandle,
+    player: State<'_, Player>,
+    capture: State<'_, AudioCapture>,
+) -> Result<PlaybackStatus, String> {
+    if position.is_some_and(|position| !position.is_finite() || position < 0.0) {
+        return Err("position must be a time in seconds from 0".to_string());
+    }
+    let resumed = {
+        let session = player.session.lock().map_err(|_| "Playback state is unavailable".to_string())?;
+        match session.as_ref().filter(|session| session.recording_id == recording_id) {
+            Some(session) => {
+                if let Some(position) = position {
+                    session.send(PlaybackMessage::Seek(position))?;
+                }
+                Some(session.send(PlaybackMessage::Resume))
+            }
+            None => None,
+        }
+    };
+    // A session whose thread has gone is replaced
+    if let Some(Ok(())) = resumed {
+        return player.with_session(|session| Ok(session.status()));
+    }
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    if let Some(previous) = player.session.lock().map_err(|_| "Playback state is unavailable".to_string())?.take() {
+        let _ = previous.send(PlaybackMessage::Stop);
+    }
+    let quality = capture.get_settings().resample_quality;
+    let session = spawn_playback(&app_handle, recording_id, PathBuf::from(&record.file_path), position.unwrap_or(0.0), quality).await?;
+    let status = session.status();
+    *player.session.lock().map_err(|_| "Playback state is unavailable".to_string())? = Some(session);
+    Ok(status)
+}
+
+#[command]
+pub async fn pause(player: State<'_, Player>) -> Result<(), String> {
+    player.with_session(|session| session.send(PlaybackMessage::Pause))
+}
+
+#[command]
+pub async fn seek(position: f64, player: State<'_, Player>) -> Result<(), String> {
+    if !position.is_finite() || position < 0.0 {
+        return Err("position must be a time in seconds from 0".to_string());
+    }
+    player.with_session(|session| session.send(PlaybackMessage::Seek(position)))
+}
+
+// 1.0 is normal speed; others change the pitch with it unless
+// `preserve_pitch` time-stretches instead, which suits slowing down speech
+#[command]
+pub async fn set_speed(speed: f32, preserve_pitch: Option<bool>, player: State<'_, Player>) -> Result<(), String> {
+    let preserve_pitch = preserve_pitch.unwrap_or(false);
+    let (min, max) = if preserve_pitch { (MIN_STRETCH_SPEED, MAX_STRETCH_SPEED) } else { (MIN_SPEED, MAX_SPEED) };
+    if !(min..=max).contains(&speed) {
+        return Err(format!("speed must be between {} and {}", min, max));
+    }
+    player.with_session(|session| session.send(PlaybackMessage::SetSpeed(speed, preserve_pitch)))
+}
+
+#[command]
+pub async fn get_position(player: State<'_, Player>) -> Result<PlaybackStatus, String> {
+    let session = player.session.lock().map_err(|_| "Playback state is unavailable".to_string())?;
+    Ok(session.as_ref().map(PlaybackSession::status).unwrap_or_default())
+}
+
+// Stops playback and releases the output device
+#[command]
+pub async fn stop_playback(player: State<'_, Player>) -> Result<(), String> {
+    if let Some(session) = player.session.lock().map_err(|_| "Playback state is unavailable".to_string())?.take() {
+        let _ = session.send(PlaybackMessage::Stop);
+    }
+    Ok(())
+}
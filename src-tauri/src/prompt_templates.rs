@@ -0,0 +1,261 @@
+use tauri::{command, State};
+use serde::Serialize;
+use anyhow::Result;
+use minijinja::{Environment, UndefinedBehavior};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use crate::ai_models::AdvancedAI;
+use crate::database::Database;
+
+const PROMPT_TEMPLATES_KEY: &str = "prompt_templates";
+
+pub const ENHANCED_CHAT_TEMPLATE: &str = "enhanced_chat";
+pub const RAG_QUERY_TEMPLATE: &str = "rag_query";
+pub const AUDIO_ANALYSIS_TEMPLATE: &str = "audio_analysis";
+pub const AUDIO_ANALYSIS_JSON_TEMPLATE: &str = "audio_analysis_json";
+pub const CONVERSATION_SUMMARY_TEMPLATE: &str = "conversation_summary";
+pub const RERANK_TEMPLATE: &str = "rerank";
+pub const QUERY_EXPANSION_TEMPLATE: &str = "query_expansion";
+
+fn audio_analysis_sample() -> serde_json::Value {
+    serde_json::json!({
+        "avg_amplitude": 0.1,
+        "peak_amplitude": 0.5,
+        "rms_energy": 0.12,
+        "zero_crossings": 10,
+        "zero_crossing_rate": 0.1,
+        "spectral_centroid": 1500.0,
+        "spectral_rolloff": 4000.0,
+        "spectral_flatness": 0.2,
+        "band_energies": [{ "name": "mid", "low_hz": 500.0, "high_hz": 2000.0, "energy_db": -30.0 }],
+        "mfcc": [-200.0, 10.0],
+        "sample_count": 100,
+        "duration": 0.01,
+        "metadata": "{}",
+    })
+}
+
+struct BuiltInTemplate {
+    name: &'static str,
+    description: &'static str,
+    source: &'static str,
+    // Sample values used to check that an edited template still renders
+    sample: fn() -> serde_json::Value,
+}
+
+const BUILT_IN_TEMPLATES: [BuiltInTemplate; 7] = [
+    BuiltInTemplate {
+        name: ENHANCED_CHAT_TEMPLATE,
+        description: "Single-turn Dwight chat. Variables: system_prompt, user_input",
+        source: "{{ system_prompt }}\n\nUser input: {{ user_input }}\n\nRespond as Dwight with technical expertise and helpful guidance:",
+        sample: || serde_json::json!({ "system_prompt": "You are Dwight.", "user_input": "Hello" }),
+    },
+    BuiltInTemplate {
+        name: RAG_QUERY_TEMPLATE,
+        description: "Question answering over numbered context documents. Variables: documents (list), query",
+        source: "Context documents:\n{% for doc in documents %}[{{ loop.index }}] {{ doc }}\n{% endfor %}\nQuery: {{ query }}\n\n\
+            Please answer the query based on the provided context. After each statement, cite the documents \
+            that support it by number in square brackets, like [1] or [2][3].",
+        sample: || serde_json::json!({ "documents": ["First document"], "query": "What happened?" }),
+    },
+    BuiltInTemplate {
+        name: AUDIO_ANALYSIS_TEMPLATE,
+        description: "Audio feature analysis. Variables: avg_amplitude, peak_amplitude, rms_energy, zero_crossings, \
+            zero_crossing_rate, spectral_centroid, spectral_rolloff, spectral_flatness, band_energies (list of name, low_hz, \
+            high_hz, energy_db), mfcc (list), sample_count, duration, metadata",
+        source: "Analyze this audio data:\n\
+            - Average amplitude: {{ avg_amplitude | round(3) }}\n\
+            - Peak amplitude: {{ peak_amplitude | round(3) }}\n\
+            - RMS energy: {{ rms_energy | round(4) }}\n\
+            - Zero crossings: {{ zero_crossings }} ({{ zero_crossing_rate | round(4) }} per sample)\n\
+            - Spectral centroid: {{ spectral_centroid | round(0) }} Hz\n\
+            - Spectral rolloff (85%): {{ spectral_rolloff | round(0) }} Hz\n\
+            - Spectral flatness: {{ spectral_flatness | round(3) }}\n\
+            - Band energies: {% for band in band_energies %}{{ band.name }} {{ band.energy_db | round(1) }} dB\
+            {% if not loop.last %}, {% endif %}{% endfor %}\n\
+            - MFCCs: {% for value in mfcc %}{{ value | round(2) }}{% if not loop.last %}, {% endif %}{% endfor %}\n\
+            - Sample count: {{ sample_count }} ({{ duration | round(2) }} s)\n\
+            - Metadata: {{ metadata }}\n\n\
+            Provide a detailed analysis of what this audio might contain, \
+            potential sounds or speech patterns, and any security-relevant observations.",
+        sample: audio_analysis_sample,
+    },
+    BuiltInTemplate {
+        name: AUDIO_ANALYSIS_JSON_TEMPLATE,
+        description: "Audio feature analysis returning an AudioAnalysisReport JSON object. Same variables as audio_analysis",
+        source: "Analyze this audio data:\n\
+            - Average amplitude: {{ avg_amplitude | round(3) }}\n\
+            - Peak amplitude: {{ peak_amplitude | round(3) }}\n\
+            - RMS energy: {{ rms_energy | round(4) }}\n\
+            - Zero crossings: {{ zero_crossings }} ({{ zero_crossing_rate | round(4) }} per sample)\n\
+            - Spectral centroid: {{ spectral_centroid | round(0) }} Hz\n\
+            - Spectral rolloff (85%): {{ spectral_rolloff | round(0) }} Hz\n\
+            - Spectral flatness: {{ spectral_flatness | round(3) }}\n\
+            - Band energies: {% for band in band_energies %}{{ band.name }} {{ band.energy_db | round(1) }} dB\
+            {% if not loop.last %}, {% endif %}{% endfor %}\n\
+            - MFCCs: {% for value in mfcc %}{{ value | round(2) }}{% if not loop.last %}, {% endif %}{% endfor %}\n\
+            - Sample count: {{ sample_count }} ({{ duration | round(2) }} s)\n\
+            - Metadata: {{ metadata }}\n\n\
+            Respond with only a JSON object of this form:\n\
+            {\"summary\": string, \"speech_present\": boolean, \
+            \"detected_sounds\": [{\"label\": string, \"confidence\": number between 0 and 1}], \
+            \"security_concerns\": [string], \"recommended_actions\": [string]}",
+        sample: audio_analysis_sample,
+    },
+    BuiltInTemplate {
+        name: CONVERSATION_SUMMARY_TEMPLATE,
+        description: "Folds older session turns into the running summary. Variables: summary (may be empty), conversation",
+        source: "Summarize this conversation in a few sentences. Keep names, facts, decisions and open questions; \
+            leave out greetings and small talk.\n\n\
+            {% if summary %}Summary of the conversation before this:\n{{ summary }}\n\n{% endif %}\
+            {{ conversation }}\nUpdated summary:",
+        sample: || serde_json::json!({ "summary": "The user said hello.", "conversation": "User: Hello\nDwight: Hello." }),
+    },
+    BuiltInTemplate {
+        name: RERANK_TEMPLATE,
+        description: "Scores retrieved passages for relevance before answering. Variables: query, passages (list)",
+        source: "Query: {{ query }}\n\nPassages:\n{% for passage in passages %}[{{ loop.index }}] {{ passage }}\n{% endfor %}\n\
+            Rate how useful each passage is for answering the query, from 0 (unrelated) to 10 (answers it directly). \
+            Respond with only a JSON object of this form, with one score per passage in the order given:\n\
+            {\"scores\": [number, ...]}",
+        sample: || serde_json::json!({ "query": "What happened?", "passages": ["First passage"] }),
+    },
+    BuiltInTemplate {
+        name: QUERY_EXPANSION_TEMPLATE,
+        description: "Rephrases a question to widen retrieval. Variables: query, count",
+        source: "Write {{ count }} different ways of asking this question, as someone searching recorded \
+            conversations for the answer might. Use other words and likely specifics, but keep the meaning.\n\n\
+            Question: {{ query }}\n\n\
+            Respond with only a JSON object of this form:\n\
+            {\"queries\": [string, ...]}",
+        sample: || serde_json::json!({ "query": "What happened?", "count": 3 }),
+    },
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub description: String,
+    pub source: String,
+    pub default_source: String,
+    pub customized: bool,
+}
+
+fn built_in(name: &str) -> Option<&'static BuiltInTemplate> {
+    BUILT_IN_TEMPLATES.iter().find(|template| template.name == name)
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    // A misspelled variable should fail loudly instead of rendering as empty
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env
+}
+
+// Prompt text for the AI commands, as minijinja templates. Users can override
+// the built-in source of any template; overrides are persisted in app settings.
+pub struct PromptTemplates {
+    overrides: RwLock<HashMap<String, String>>,
+}
+
+impl PromptTemplates {
+    pub fn new() -> Self {
+        PromptTemplates {
+            overrides: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn load(&self, db: &Database) {
+        match db.get_setting(PROMPT_TEMPLATES_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<HashMap<String, String>>(&json) {
+                Ok(saved) => {
+                    if let Ok(mut overrides) = self.overrides.write() {
+                        *overrides = saved;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid prompt templates: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load prompt templates: {}", e),
+        }
+    }
+
+    fn source(&self, name: &str) -> Option<String> {
+        let overridden = self.overrides.read().ok().and_then(|overrides| overrides.get(name).cloned());
+        overridden.or_else(|| built_in(name).map(|template| template.source.to_string()))
+    }
+
+    pub fn render(&self, name: &str, context: serde_json::Value) -> Result<String> {
+        let source = self.source(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown prompt template '{}'", name))?;
+
+        environment()
+            .render_str(&source, context)
+            .map_err(|e| anyhow::anyhow!("Failed to render prompt template '{}': {}", name, e))
+    }
+
+    pub fn list(&self) -> Vec<PromptTemplate> {
+        BUILT_IN_TEMPLATES.iter()
+            .map(|template| {
+                let source = self.source(template.name).unwrap_or_else(|| template.source.to_string());
+                PromptTemplate {
+                    name: template.name.to_string(),
+                    description: template.description.to_string(),
+                    customized: source != template.source,
+                    source,
+                    default_source: template.source.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    // Pass None to go back to the built-in source
+    fn update(&self, name: &str, source: Option<String>, db: &Database) -> Result<(), String> {
+        let template = built_in(name).ok_or_else(|| format!("Unknown prompt template '{}'", name))?;
+
+        if let Some(source) = &source {
+            environment()
+                .render_str(source, (template.sample)())
+                .map_err(|e| format!("Invalid template: {}", e))?;
+        }
+
+        let mut overrides = self.overrides.write().map_err(|_| "Prompt templates unavailable".to_string())?;
+        let mut updated = overrides.clone();
+        match source {
+            Some(source) => updated.insert(name.to_string(), source),
+            None => updated.remove(name),
+        };
+
+        let json = serde_json::to_string(&updated).map_err(|e| format!("Failed to save prompt templates: {}", e))?;
+        db.set_setting(PROMPT_TEMPLATES_KEY, &json).map_err(|e| format!("Database error: {}", e))?;
+
+        *overrides = updated;
+        Ok(())
+    }
+}
+
+#[command]
+pub async fn list_prompt_templates(ai: State<'_, AdvancedAI>) -> Result<Vec<PromptTemplate>, String> {
+    Ok(ai.prompt_templates().list())
+}
+
+#[command]
+pub async fn set_prompt_template(
+    name: String,
+    source: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.prompt_templates().update(&name, Some(source), &db)
+}
+
+#[command]
+pub async fn reset_prompt_template(
+    name: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    ai.prompt_templates().update(&name, None, &db)
+}
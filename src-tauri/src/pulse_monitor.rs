@@ -0,0 +1,156 @@
+use anyhow::Result;
+use libpulse_binding::callbacks::ListResult;
+use libpulse_binding::context::{self, Context, FlagSet};
+use libpulse_binding::def::BufferAttr;
+use libpulse_binding::mainloop::standard::{IterateResult, Mainloop};
+use libpulse_binding::operation::{self, Operation};
+use libpulse_binding::sample::{Format, Spec};
+use libpulse_binding::stream::Direction;
+use libpulse_simple_binding::Simple;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use crate::audio_capture::StreamFormat;
+
+// How the app names itself to the sound server
+const CLIENT_NAME: &str = "Dwight";
+
+// Audio is read from the server in blocks this long
+const READ_MILLISECONDS: u32 = 20;
+
+// A monitor source of PulseAudio, or of PipeWire through pipewire-pulse:
+// what one of its sinks is playing
+#[derive(Debug, Clone)]
+pub struct MonitorSource {
+    pub name: String,
+    pub description: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // It monitors the default sink
+    pub is_default: bool,
+}
+
+// Runs the mainloop until the operation is done
+fn wait<T: ?Sized>(mainloop: &mut Mainloop, operation: Operation<T>) -> Result<()> {
+    while operation.get_state() == operation::State::Running {
+        if let IterateResult::Err(e) = mainloop.iterate(true) {
+            return Err(anyhow::anyhow!("PulseAudio error: {}", e));
+        }
+    }
+    Ok(())
+}
+
+// The sound server's monitor sources; none when no server is running
+pub fn monitor_sources() -> Result<Vec<MonitorSource>> {
+    let mut mainloop = Mainloop::new().ok_or_else(|| anyhow::anyhow!("Failed to create a PulseAudio mainloop"))?;
+    let mut context = Context::new(&mainloop, CLIENT_NAME).ok_or_else(|| anyhow::anyhow!("Failed to create a PulseAudio context"))?;
+    if context.connect(None, FlagSet::NOAUTOSPAWN, None).is_err() {
+        return Ok(Vec::new());
+    }
+    loop {
+        if let IterateResult::Err(e) = mainloop.iterate(true) {
+            return Err(anyhow::anyhow!("PulseAudio error: {}", e));
+        }
+        match context.get_state() {
+            context::State::Ready => break,
+            context::State::Failed | context::State::Terminated => return Ok(Vec::new()),
+            _ => {}
+        }
+    }
+
+    let default_sink = Rc::new(RefCell::new(None));
+    let found = default_sink.clone();
+    let operation = context.introspect().get_server_info(move |info| {
+        *found.borrow_mut() = info.default_sink_name.as_ref().map(|name| name.to_string());
+    });
+    wait(&mut mainloop, operation)?;
+    let default_sink = default_sink.take();
+
+    let sources = Rc::new(RefCell::new(Vec::new()));
+    let found = sources.clone();
+    let operation = context.introspect().get_source_info_list(move |result| {
+        let ListResult::Item(info) = result else { return };
+        let (Some(name), Some(sink)) = (info.name.as_ref(), info.monitor_of_sink_name.as_ref()) else { return };
+        found.borrow_mut().push(MonitorSource {
+            name: name.to_string(),
+            description: info.description.as_ref().map_or_else(|| name.to_string(), |description| description.to_string()),
+            sample_rate: info.sample_spec.rate,
+            channels: info.sample_spec.channels as u16,
+            is_default: default_sink.as_deref() == Some(sink.as_ref()),
+        });
+    });
+    wait(&mut mainloop, operation)?;
+    context.disconnect();
+    Ok(sources.take())
+}
+
+// A monitor source read on a thread of its own until this is dropped
+pub struct MonitorStream {
+    stopped: Arc<AtomicBool>,
+}
+
+impl MonitorStream {
+    // Opens the source at its own rate, in `channels` or its own count; the
+    // server remixes. Audio goes to `on_samples` interleaved, and a failed
+    // read to `on_error`, which ends the stream.
+    pub fn open<S, E>(source: &MonitorSource, channels: Option<u16>, mut on_samples: S, on_error: E) -> Result<(MonitorStream, StreamFormat)>
+    where
+        S: FnMut(Vec<f32>) + Send + 'static,
+        E: FnOnce(String) + Send + 'static,
+    {
+        let channels = channels.unwrap_or(source.channels);
+        let spec = Spec {
+            format: Format::FLOAT32NE,
+            rate: source.sample_rate,
+            channels: u8::try_from(channels).unwrap_or(u8::MAX),
+        };
+        if !spec.is_valid() {
+            return Err(anyhow::anyhow!("PulseAudio can't capture {} channels at {} Hz", channels, source.sample_rate));
+        }
+        let block_bytes = (spec.rate * READ_MILLISECONDS / 1000) as usize * spec.channels as usize * size_of::<f32>();
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let name = source.name.clone();
+        let (opened, result) = mpsc::channel();
+        // The connection stays on the thread that reads it
+        std::thread::Builder::new()
+            .name("pulse-monitor".to_string())
+            .spawn(move || {
+                let attr = BufferAttr {
+                    maxlength: u32::MAX,
+                    tlength: u32::MAX,
+                    prebuf: u32::MAX,
+                    minreq: u32::MAX,
+                    fragsize: block_bytes as u32,
+                };
+                let simple = match Simple::new(None, CLIENT_NAME, Direction::Record, Some(&name), "System audio", &spec, None, Some(&attr)) {
+                    Ok(simple) => simple,
+                    Err(e) => {
+                        let _ = opened.send(Err(format!("{}", e)));
+                        return;
+                    }
+                };
+                let _ = opened.send(Ok(()));
+                let mut bytes = vec![0u8; block_bytes];
+                while !thread_stopped.load(Ordering::Relaxed) {
+                    if let Err(e) = simple.read(&mut bytes) {
+                        on_error(format!("{}", e));
+                        return;
+                    }
+                    on_samples(bytes.chunks_exact(size_of::<f32>()).map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]])).collect());
+                }
+            })?;
+        result.recv()
+            .map_err(|_| anyhow::anyhow!("PulseAudio source '{}' stopped before it opened", source.name))?
+            .map_err(|e| anyhow::anyhow!("PulseAudio source '{}': {}", source.name, e))?;
+        Ok((MonitorStream { stopped }, StreamFormat { sample_rate: spec.rate, channels }))
+    }
+}
+
+impl Drop for MonitorStream {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -134,8 +134,10 @@ def classify_audio_event(features):
             features.get('rms_energy', 0),
             features.get('zero_crossing_rate', 0),
             features.get('spectral_centroid', 0),
+            features.get('spectral_rolloff', 0),
+            features.get('spectral_flatness', 0),
             features.get('duration', 0),
-        ]).reshape(1, -1)
+        ] + list(features.get('mfcc', []))).reshape(1, -1)
         
         # Simulate classification (replace with real model)
         # Generate realistic probabilities
@@ -179,7 +181,7 @@ output = json.dumps(result)
 "#.to_string(),
             description: "Machine learning classification of audio events".to_string(),
             input_schema: serde_json::json!({
-                "features": "object with audio features (rms_energy, zero_crossing_rate, etc.)"
+                "features": "object with audio features, as ai_audio_analysis returns them (rms_energy, zero_crossing_rate, spectral_centroid, mfcc, etc.)"
             }),
             output_schema: serde_json::json!({
                 "success": "boolean",
@@ -0,0 +1,55 @@
+use serde::Deserialize;
+use anyhow::Result;
+use crate::ai_models::{AdvancedAI, GenerationOptions};
+use crate::prompt_templates::QUERY_EXPANSION_TEMPLATE;
+use crate::structured_output::{query_structured, StructuredOutput};
+
+#[derive(Debug, Deserialize)]
+struct Paraphrases {
+    queries: Vec<String>,
+}
+
+impl StructuredOutput for Paraphrases {
+    fn validate(&self) -> Result<(), String> {
+        if self.queries.iter().all(|query| query.trim().is_empty()) {
+            return Err("queries must contain at least one non-empty query".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Has the model rephrase a question `count` ways, so vague wording ("the
+// argument about money") still finds chunks that say it differently.
+// Returns only the paraphrases, without repeats of the question.
+pub async fn expand_query(
+    ai: &AdvancedAI,
+    model: &str,
+    local_only: Option<bool>,
+    query: &str,
+    count: usize,
+) -> Result<Vec<String>> {
+    let options = GenerationOptions {
+        // Some variety is the point, but the paraphrases should stay on topic
+        temperature: Some(0.7),
+        local_only,
+        ..Default::default()
+    };
+    let prompt = ai.prompt_templates().render(QUERY_EXPANSION_TEMPLATE, serde_json::json!({
+        "query": query,
+        "count": count,
+    }))?;
+
+    let (paraphrases, _) = query_structured::<Paraphrases>(ai, &prompt, model, &options).await?;
+    let mut queries: Vec<String> = Vec::new();
+    for paraphrase in paraphrases.queries {
+        let paraphrase = paraphrase.trim();
+        if paraphrase.is_empty()
+            || paraphrase.eq_ignore_ascii_case(query.trim())
+            || queries.iter().any(|existing| existing.eq_ignore_ascii_case(paraphrase)) {
+            continue;
+        }
+        queries.push(paraphrase.to_string());
+    }
+    queries.truncate(count);
+    Ok(queries)
+}
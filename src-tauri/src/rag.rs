@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::sync::OnceLock;
+use crate::embeddings::cosine_similarity;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RagSettings {
+    // Ollama model used to embed queries and documents
+    pub embedding_model: String,
+    // Documents kept for the prompt after ranking by similarity
+    pub top_k: usize,
+    // Ingested documents are split into chunks of this many characters,
+    // each repeating the last `chunk_overlap` characters of the one before
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+    // Share of keyword (BM25) vs. semantic ranking when searching the vector
+    // store: 0.0 is vectors only, 1.0 keywords only
+    pub keyword_weight: f32,
+    // Have the model rescore the best `rerank_candidates` chunks and keep
+    // the top_k best of those
+    pub rerank: bool,
+    pub rerank_candidates: usize,
+    // Chunks less similar to the query than this are left out, trading recall
+    // for less noise. Keyword-only matches have no similarity and are kept.
+    pub min_similarity: f32,
+    // Also search with `expansion_queries` paraphrases of the question from
+    // the model and merge the results, for better recall on vague questions
+    pub query_expansion: bool,
+    pub expansion_queries: usize,
+}
+
+impl Default for RagSettings {
+    fn default() -> Self {
+        RagSettings {
+            embedding_model: "nomic-embed-text".to_string(),
+            top_k: 5,
+            chunk_size: 1000,
+            chunk_overlap: 200,
+            keyword_weight: 0.3,
+            rerank: false,
+            rerank_candidates: 50,
+            min_similarity: 0.0,
+            query_expansion: false,
+            expansion_queries: 3,
+        }
+    }
+}
+
+impl RagSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.embedding_model.trim().is_empty() {
+            return Err("embedding_model must not be empty".to_string());
+        }
+        if self.top_k == 0 {
+            return Err("top_k must be greater than 0".to_string());
+        }
+        if self.chunk_size < 100 {
+            return Err("chunk_size must be at least 100 characters".to_string());
+        }
+        if self.chunk_overlap >= self.chunk_size {
+            return Err("chunk_overlap must be smaller than chunk_size".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.keyword_weight) {
+            return Err("keyword_weight must be between 0.0 and 1.0".to_string());
+        }
+        if self.rerank_candidates < self.top_k {
+            return Err("rerank_candidates must be at least top_k".to_string());
+        }
+        if !(-1.0..=1.0).contains(&self.min_similarity) {
+            return Err("min_similarity must be between -1.0 and 1.0".to_string());
+        }
+        if !(3..=5).contains(&self.expansion_queries) {
+            return Err("expansion_queries must be between 3 and 5".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Where a RAG query gets its context from
+pub enum ContextSource<'a> {
+    // Documents sent with the request
+    Documents(Vec<String>),
+    // Chunks retrieved from a vector store index by `query`
+    Index { name: &'a str, query: &'a str },
+}
+
+// Documents of one RAG query with their embeddings and similarity to the query
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RAGContext {
+    pub query: String,
+    pub documents: Vec<String>,
+    pub embeddings: Vec<Vec<f32>>,
+    pub similarity_scores: Vec<f32>,
+}
+
+impl RAGContext {
+    pub fn new(query: &str, documents: Vec<String>) -> Self {
+        RAGContext {
+            query: query.to_string(),
+            documents,
+            embeddings: Vec::new(),
+            similarity_scores: Vec::new(),
+        }
+    }
+
+    pub fn score(&mut self, query_embedding: &[f32]) {
+        self.similarity_scores = self.embeddings.iter()
+            .map(|embedding| cosine_similarity(query_embedding, embedding))
+            .collect();
+    }
+
+    // Indexes of the `k` most similar documents, best first. Unscored documents
+    // (no embeddings available) are all kept, in their original order.
+    pub fn top_k(&self, k: usize) -> Vec<usize> {
+        if self.similarity_scores.len() != self.documents.len() {
+            return (0..self.documents.len()).collect();
+        }
+
+        let mut ranked: Vec<usize> = (0..self.documents.len()).collect();
+        ranked.sort_by(|&a, &b| self.similarity_scores[b].total_cmp(&self.similarity_scores[a]));
+        ranked.truncate(k);
+        ranked
+    }
+}
+
+// Rank offset from the reciprocal rank fusion paper; dampens the lead of the top ranks
+const RRF_K: f32 = 60.0;
+
+// Merges several best-first rankings of ids into one. Each ranking adds
+// weight / (RRF_K + rank) to the score of every id it contains.
+pub fn reciprocal_rank_fusion(rankings: &[(&[i64], f32)]) -> Vec<(i64, f32)> {
+    let mut scores: Vec<(i64, f32)> = Vec::new();
+
+    for (ranking, weight) in rankings {
+        if *weight <= 0.0 {
+            continue;
+        }
+        for (rank, &id) in ranking.iter().enumerate() {
+            let contribution = weight / (RRF_K + rank as f32 + 1.0);
+            match scores.iter_mut().find(|(existing, _)| *existing == id) {
+                Some((_, score)) => *score += contribution,
+                None => scores.push((id, contribution)),
+            }
+        }
+    }
+
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores
+}
+
+// A context chunk the answer cited, by its [n] marker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    // The number shown to the model, starting at 1
+    pub number: usize,
+    // Index into the context documents of the request
+    pub document: usize,
+    // Byte range of the document text that was in the prompt
+    pub start: usize,
+    pub end: usize,
+    // Byte offsets in the answer where the chunk is cited
+    pub answer_offsets: Vec<usize>,
+    // Set when the chunk came from the vector store rather than the request
+    #[serde(default)]
+    pub chunk_id: Option<i64>,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+// A numbered piece of a context document, as it goes into the prompt
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub document: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub chunk_id: Option<i64>,
+    pub source: Option<String>,
+}
+
+fn citation_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    // [2], [1, 3] and [1][3]; the last form is just several markers in a row
+    PATTERN.get_or_init(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("citation pattern is valid"))
+}
+
+// Resolves the [n] markers in `answer` to chunks, in order of first citation.
+// Numbers that don't match a chunk are ignored.
+pub fn parse_citations(answer: &str, chunks: &[Chunk]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+
+    for captures in citation_pattern().captures_iter(answer) {
+        let offset = captures.get(0).map_or(0, |m| m.start());
+        let numbers = captures[1].split(',').filter_map(|n| n.trim().parse::<usize>().ok());
+
+        for number in numbers {
+            let Some(chunk) = number.checked_sub(1).and_then(|index| chunks.get(index)) else {
+                continue;
+            };
+            match citations.iter_mut().find(|citation| citation.number == number) {
+                Some(citation) => citation.answer_offsets.push(offset),
+                None => citations.push(Citation {
+                    number,
+                    document: chunk.document,
+                    start: chunk.start,
+                    end: chunk.end,
+                    answer_offsets: vec![offset],
+                    chunk_id: chunk.chunk_id,
+                    source: chunk.source.clone(),
+                }),
+            }
+        }
+    }
+    citations
+}
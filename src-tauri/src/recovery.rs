@@ -0,0 +1,306 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::{command, Manager};
+use crate::audio_capture::{self, AudioCapture, RECORDING_FILE_PREFIX, RECORDING_TIME_FORMAT};
+use crate::audio_file::{self, WavOutput};
+use crate::database::{AudioRecord, Database, RecordingGap, RecordingSession, ResumableRecording};
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+// What a recording is encoded to once it stops
+const ENCODED_EXTENSIONS: [&str; 2] = ["flac", "opus"];
+
+// Opus pads the last packet, so a full encode can be a frame longer or shorter
+const ENCODED_LENGTH_TOLERANCE: f64 = 0.1;
+
+// Numbered onto the name of a recording each time a continuation is
+// appended to it
+const STITCHED_SUFFIX: &str = "-stitched-";
+
+// When a recording's WAV was started, from its file name. Denoised and
+// normalized copies have a suffix after the time and don't match.
+fn recording_started(path: &Path) -> Option<chrono::NaiveDateTime> {
+    if path.extension()? != "wav" {
+        return None;
+    }
+    let time = path.file_stem()?.to_str()?.strip_prefix(RECORDING_FILE_PREFIX)?;
+    chrono::NaiveDateTime::parse_from_str(time, RECORDING_TIME_FORMAT).ok()
+}
+
+// An encoded copy next to a leftover WAV is kept when the database points at
+// it and it decodes to the WAV's full length: it is the stored recording, and
+// the crash came before the WAV was removed. Any other was cut off part way
+// and is removed. Returns whether a stored copy was found.
+fn remove_partial_encodes(path: &Path, known: &HashSet<String>) -> bool {
+    let length = audio_file::audio_info(path).ok().map(|(_, duration)| duration);
+    let mut stored = false;
+    for extension in ENCODED_EXTENSIONS {
+        let encoded = path.with_extension(extension);
+        if !encoded.exists() {
+            continue;
+        }
+        let complete = || {
+            let full_length = match (audio_file::audio_info(&encoded), length) {
+                (Ok((_, duration)), Some(length)) => (duration - length).abs() < ENCODED_LENGTH_TOLERANCE,
+                (result, _) => result.is_ok(),
+            };
+            full_length && audio_file::for_each_chunk(&encoded, |_| Ok(())).is_ok()
+        };
+        if known.contains(encoded.to_string_lossy().as_ref()) && complete() {
+            stored = true;
+            continue;
+        }
+        if let Err(e) = std::fs::remove_file(&encoded) {
+            eprintln!("Failed to remove partly encoded {}: {}", encoded.display(), e);
+        }
+    }
+    stored
+}
+
+// The time the last audio reached the file, as RFC 3339
+fn modified_at(path: &Path) -> Result<String> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339())
+}
+
+// The original's name with the next stitch number, so each append has a
+// name the file it replaces isn't using, and one recording_started never
+// takes for an interrupted recording
+fn stitched_path(original: &Path) -> PathBuf {
+    let stem = original.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let (base, stitches) = stem.rsplit_once(STITCHED_SUFFIX)
+        .and_then(|(base, stitches)| Some((base, stitches.parse::<u32>().ok()?)))
+        .unwrap_or((stem.as_str(), 0));
+    original.with_file_name(format!("{}{}{}.wav", base, STITCHED_SUFFIX, stitches + 1))
+}
+
+// A stitched WAV the database doesn't point at was cut off by a crash
+// before the resume that wrote it was saved, and the files it would have
+// replaced are still whole
+fn is_abandoned_stitch(path: &Path, known: &HashSet<String>) -> bool {
+    path.extension().is_some_and(|extension| extension == "wav")
+        && path.file_stem().is_some_and(|stem| stem.to_string_lossy().contains(STITCHED_SUFFIX))
+        && !known.contains(path.to_string_lossy().as_ref())
+}
+
+// Writes `original` with `continuation` after it, converted to the
+// original's format, to a new WAV. Both are left for the caller to remove
+// once the record points at the new file. Returns the WAV and where the
+// continuation starts.
+fn append_audio(original: &Path, continuation: &Path, quality: ResampleQuality) -> Result<(PathBuf, f64)> {
+    let spec = audio_file::audio_spec(original)?;
+    let format = audio_file::spec_format(&spec);
+    let (_, at_seconds) = audio_file::audio_info(original)?;
+    let (continuation_format, _) = audio_file::audio_info(continuation)?;
+    let mut converter = FormatConverter::new(continuation_format, format, quality);
+
+    let stitched = stitched_path(original);
+    let mut output = WavOutput::create(&stitched, spec)?;
+    let written = audio_file::for_each_chunk(original, |chunk| output.write(chunk))
+        .and_then(|_| audio_file::for_each_chunk(continuation, |chunk| output.write(&converter.convert(chunk))))
+        .and_then(|_| output.write(&converter.flush()))
+        .and_then(|_| output.finish());
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&stitched);
+        return Err(e);
+    }
+    Ok((stitched, at_seconds))
+}
+
+// An interrupted recording with the audio that resumed it appended
+pub struct Stitched {
+    // WAVs, not yet stored
+    pub file_path: PathBuf,
+    pub denoised_file_path: Option<PathBuf>,
+    // Where the resumed audio starts, in seconds into the file
+    pub at_seconds: f64,
+    // The files the stitched ones replace, for `remove_replaced` once the
+    // database points at the new ones
+    pub replaced: Vec<PathBuf>,
+}
+
+pub fn remove_replaced(paths: &[PathBuf]) {
+    for path in paths.iter().filter(|path| path.exists()) {
+        if let Err(e) = std::fs::remove_file(path) {
+            eprintln!("Failed to remove {} after appending to it: {}", path.display(), e);
+        }
+    }
+}
+
+// Appends a finished continuation WAV, and its denoised copy when the
+// recording has one too, to the recording it resumed. Nothing is removed
+// here: a crash before the database is updated leaves the old files in place.
+pub fn stitch(db: &Database, record_id: i64, continuation: &Path, quality: ResampleQuality) -> Result<Stitched> {
+    let record = db.get_audio_record(record_id)?
+        .ok_or_else(|| anyhow::anyhow!("Recording {} not found", record_id))?;
+    let (file_path, at_seconds) = append_audio(Path::new(&record.file_path), continuation, quality)?;
+    let mut replaced = vec![PathBuf::from(&record.file_path), continuation.to_path_buf()];
+
+    let denoised = audio_capture::denoised_path(continuation);
+    let versions = db.get_audio_versions(record_id)?;
+    let denoised_file_path = match versions.iter().find(|version| version.kind == "denoised") {
+        Some(version) if denoised.exists() => match append_audio(Path::new(&version.file_path), &denoised, quality) {
+            Ok((path, _)) => {
+                replaced.push(PathBuf::from(&version.file_path));
+                Some(path)
+            }
+            Err(e) => {
+                eprintln!("Failed to append to denoised copy {}: {}", version.file_path, e);
+                None
+            }
+        },
+        _ => None,
+    };
+    replaced.push(denoised);
+    Ok(Stitched { file_path, denoised_file_path, at_seconds, replaced })
+}
+
+// A resumed recording cut off in turn is appended to the one it resumed,
+// which can then be resumed again
+fn recover_continuation(db: &Database, path: &Path, session: &RecordingSession, record: AudioRecord, quality: ResampleQuality) -> Result<AudioRecord> {
+    let record_id = record.id.unwrap_or_default() as i64;
+    let denoised = audio_capture::denoised_path(path);
+    if denoised.exists() {
+        if let Err(e) = audio_file::repair_wav_header(&denoised) {
+            eprintln!("Failed to recover denoised copy {}: {}", denoised.display(), e);
+        }
+    }
+    let stitched = stitch(db, record_id, path, quality)?;
+    let (_, duration) = audio_file::audio_info(&stitched.file_path)?;
+    let file_path = stitched.file_path.to_string_lossy().to_string();
+    db.update_audio_file(record_id, &file_path, duration, record.triggers.as_deref())?;
+    if let Some(denoised) = stitched.denoised_file_path {
+        db.save_audio_version(record_id, "denoised", &denoised.to_string_lossy())?;
+    }
+    db.save_recording_gap(&RecordingGap { record_id, at_seconds: stitched.at_seconds, gap_seconds: session.gap_seconds })?;
+    remove_replaced(&stitched.replaced);
+    Ok(AudioRecord { file_path, duration, ..record })
+}
+
+fn recover(
+    db: &Database,
+    path: &Path,
+    started: chrono::NaiveDateTime,
+    known: &HashSet<String>,
+    quality: ResampleQuality,
+) -> Result<Option<AudioRecord>> {
+    audio_file::repair_wav_header(path)?;
+    let (_, duration) = audio_file::audio_info(path)?;
+    let stored = remove_partial_encodes(path, known);
+    let denoised = audio_capture::denoised_path(path);
+    let denoised_stored = remove_partial_encodes(&denoised, known);
+    let session_path = path.to_string_lossy().to_string();
+
+    // Saved in full but for removing the WAVs, which are left over
+    if stored {
+        std::fs::remove_file(path)?;
+        if denoised_stored {
+            let _ = std::fs::remove_file(&denoised);
+        }
+        db.delete_recording_session(&session_path)?;
+        return Ok(None);
+    }
+    let session = db.get_recording_session(&session_path)?;
+
+    // Cut off before any audio was written; there is nothing to keep
+    if duration == 0.0 {
+        std::fs::remove_file(path)?;
+        let _ = std::fs::remove_file(&denoised);
+        db.delete_recording_session(&session_path)?;
+        return Ok(None);
+    }
+
+    let interrupted_at = modified_at(path)?;
+    let resumed = match session.as_ref().and_then(|session| session.resumes_record_id) {
+        Some(record_id) => db.get_audio_record(record_id)?,
+        None => None,
+    };
+    let record = match (session.as_ref(), resumed) {
+        (Some(session), Some(resumed)) => recover_continuation(db, path, session, resumed, quality)?,
+        _ => {
+            let mut record = AudioRecord {
+                id: None,
+                title: session.as_ref()
+                    .map(|session| session.title.clone())
+                    .unwrap_or_else(|| format!("Recovered recording {}", started.format("%Y-%m-%d %H:%M"))),
+                file_path: session_path.clone(),
+                transcript: None,
+                duration,
+                created_at: String::new(),
+                triggers: None,
+            };
+            let record_id = db.save_audio_record(&record)?;
+            record.id = Some(record_id as i32);
+            if denoised.exists() {
+                match audio_file::repair_wav_header(&denoised) {
+                    Ok(_) => {
+                        db.save_audio_version(record_id, "denoised", &denoised.to_string_lossy())?;
+                    }
+                    Err(e) => eprintln!("Failed to recover denoised copy {}: {}", denoised.display(), e),
+                }
+            }
+            record
+        }
+    };
+    db.save_resumable_recording(record.id.unwrap_or_default() as i64, &interrupted_at)?;
+    db.delete_recording_session(&session_path)?;
+    Ok(Some(record))
+}
+
+// Recordings cut off by a crash or power loss are left as WAV files the
+// database never heard of, their headers last brought up to date at a
+// checkpoint. Each one is repaired to its full length on disk, any half
+// encoded copy is dropped, and it is added back as a recording along with
+// its denoised copy, under the title it was started with. It can then be
+// resumed, carrying on as the same recording. A WAV whose stored copy was
+// saved in full is only what the crash kept from being removed.
+pub fn recover_recordings(app_handle: &tauri::AppHandle, db: &Database) -> Result<Vec<AudioRecord>> {
+    let dir = audio_capture::recordings_dir(app_handle)?;
+    let quality = app_handle.state::<AudioCapture>().get_settings().resample_quality;
+    let known: HashSet<String> = db.get_audio_file_paths()?.into_iter().collect();
+
+    // Listed before anything is recovered, as recovering writes new files
+    let paths: Vec<PathBuf> = std::fs::read_dir(&dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+
+    let mut recovered = Vec::new();
+    for path in paths {
+        if is_abandoned_stitch(&path, &known) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("Failed to remove unsaved stitched recording {}: {}", path.display(), e);
+            }
+            continue;
+        }
+        let Some(started) = recording_started(&path) else {
+            continue;
+        };
+        if known.contains(path.to_string_lossy().as_ref()) {
+            continue;
+        }
+        match recover(db, &path, started, &known, quality) {
+            Ok(Some(record)) => recovered.push(record),
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to recover {}: {}", path.display(), e),
+        }
+    }
+    Ok(recovered)
+}
+
+// Recordings cut off by the app closing or crashing that can be carried on
+// with resume_recording
+#[command]
+pub async fn get_resumable_recordings(app_handle: tauri::AppHandle) -> Result<Vec<ResumableRecording>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_resumable_recordings().map_err(|e| format!("Database error: {}", e))
+}
+
+// Stops offering to resume the recording; it is kept as it is
+#[command]
+pub async fn dismiss_resumable_recording(record_id: i64, app_handle: tauri::AppHandle) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    if !db.delete_resumable_recording(record_id).map_err(|e| format!("Database error: {}", e))? {
+        return Err(format!("Recording {} can't be resumed", record_id));
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use serde::Deserialize;
+use anyhow::Result;
+use crate::ai_models::{AdvancedAI, GenerationOptions};
+use crate::prompt_templates::RERANK_TEMPLATE;
+use crate::structured_output::{query_structured, StructuredOutput};
+use crate::tokenizer;
+
+// Passages scored per model call; small enough to fit a 4k window
+const RERANK_BATCH_SIZE: usize = 10;
+
+// Passages are cut down for scoring; the full text still goes into the answer prompt
+const PASSAGE_MAX_TOKENS: usize = 150;
+
+#[derive(Debug, Deserialize)]
+struct RelevanceScores {
+    scores: Vec<f32>,
+}
+
+impl StructuredOutput for RelevanceScores {
+    fn validate(&self) -> Result<(), String> {
+        match self.scores.iter().find(|score| !(0.0..=10.0).contains(*score)) {
+            Some(score) => Err(format!("scores must be between 0 and 10, got {}", score)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Has the model rate each passage's relevance to the query and returns the
+// indexes of the `keep` best passages, best first. Ties keep the retrieval order.
+pub async fn rerank(
+    ai: &AdvancedAI,
+    model: &str,
+    local_only: Option<bool>,
+    query: &str,
+    passages: &[String],
+    keep: usize,
+) -> Result<Vec<usize>> {
+    let options = GenerationOptions {
+        temperature: Some(0.0),
+        local_only,
+        ..Default::default()
+    };
+
+    let mut scores = Vec::with_capacity(passages.len());
+    for batch in passages.chunks(RERANK_BATCH_SIZE) {
+        let prompt = ai.prompt_templates().render(RERANK_TEMPLATE, serde_json::json!({
+            "query": query,
+            "passages": batch.iter().map(|passage| tokenizer::truncate_to_tokens(passage, PASSAGE_MAX_TOKENS)).collect::<Vec<_>>(),
+        }))?;
+
+        let (relevance, _) = query_structured::<RelevanceScores>(ai, &prompt, model, &options).await?;
+        // A short list scores the missing passages as irrelevant
+        scores.extend((0..batch.len()).map(|i| relevance.scores.get(i).copied().unwrap_or(0.0)));
+    }
+
+    let mut ranked: Vec<usize> = (0..passages.len()).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    ranked.truncate(keep);
+    Ok(ranked)
+}
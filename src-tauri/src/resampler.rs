@@ -0,0 +1,154 @@
+use rubato::{
+    calculate_cutoff, FastFixedIn, PolynomialDegree, ResamplerConstructionError, SincFixedIn,
+    SincInterpolationParameters, SincInterpolationType, VecResampler, WindowFunction,
+};
+use serde::{Deserialize, Serialize};
+use crate::audio_capture::StreamFormat;
+
+// Input frames the resampler takes per call; about 20 ms at 48 kHz
+const CHUNK_FRAMES: usize = 1024;
+
+// How carefully rates are converted. Fast is a cubic polynomial, fine for
+// detectors that only look at speech; the sinc modes keep recordings clean.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResampleQuality {
+    Fast,
+    #[default]
+    Balanced,
+    High,
+}
+
+fn sinc_parameters(sinc_len: usize, oversampling_factor: usize, interpolation: SincInterpolationType) -> SincInterpolationParameters {
+    let window = WindowFunction::BlackmanHarris2;
+    SincInterpolationParameters {
+        sinc_len,
+        f_cutoff: calculate_cutoff(sinc_len, window),
+        interpolation,
+        oversampling_factor,
+        window,
+    }
+}
+
+fn resampler(ratio: f64, quality: ResampleQuality, channels: usize) -> Result<Box<dyn VecResampler<f32>>, ResamplerConstructionError> {
+    Ok(match quality {
+        ResampleQuality::Fast => Box::new(FastFixedIn::new(ratio, 1.0, PolynomialDegree::Cubic, CHUNK_FRAMES, channels)?),
+        ResampleQuality::Balanced => Box::new(SincFixedIn::new(
+            ratio, 1.0, sinc_parameters(64, 128, SincInterpolationType::Linear), CHUNK_FRAMES, channels,
+        )?),
+        ResampleQuality::High => Box::new(SincFixedIn::new(
+            ratio, 1.0, sinc_parameters(256, 256, SincInterpolationType::Cubic), CHUNK_FRAMES, channels,
+        )?),
+    })
+}
+
+// Maps audio from one format onto another: channels are averaged down or
+// repeated up, then the rate is converted with rubato. State carries across
+// blocks so a stream can be converted piece by piece; `flush` ends it.
+pub struct FormatConverter {
+    from: StreamFormat,
+    to: StreamFormat,
+    // None when only the channels change
+    resampler: Option<Box<dyn VecResampler<f32>>>,
+    // Channel-mapped input waiting for a full chunk, one Vec per channel
+    pending: Vec<Vec<f32>>,
+    input_frames: u64,
+    output_frames: u64,
+}
+
+impl FormatConverter {
+    pub fn new(from: StreamFormat, to: StreamFormat, quality: ResampleQuality) -> Self {
+        let channels = to.channels.max(1) as usize;
+        let resampler = if from.sample_rate == to.sample_rate {
+            None
+        } else {
+            let ratio = to.sample_rate as f64 / from.sample_rate as f64;
+            resampler(ratio, quality, channels)
+                .map_err(|e| eprintln!("Failed to create resampler for {} Hz to {} Hz: {}", from.sample_rate, to.sample_rate, e))
+                .ok()
+        };
+        FormatConverter {
+            from,
+            to,
+            resampler,
+            pending: vec![Vec::with_capacity(CHUNK_FRAMES); channels],
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    fn push_mapped(&mut self, samples: &[f32]) {
+        let (from, to) = (self.from.channels.max(1) as usize, self.pending.len());
+        for frame in samples.chunks_exact(from) {
+            for (channel, pending) in self.pending.iter_mut().enumerate() {
+                let sample = if from == to {
+                    frame[channel]
+                } else if to == 1 {
+                    frame.iter().sum::<f32>() / from as f32
+                } else {
+                    frame[channel.min(from - 1)]
+                };
+                pending.push(sample);
+            }
+            self.input_frames += 1;
+        }
+    }
+
+    fn interleave(&mut self, channels: Vec<Vec<f32>>, output: &mut Vec<f32>) {
+        let frames = channels.first().map_or(0, Vec::len);
+        for index in 0..frames {
+            output.extend(channels.iter().map(|channel| channel[index]));
+        }
+        self.output_frames += frames as u64;
+    }
+
+    pub fn convert(&mut self, samples: &[f32]) -> Vec<f32> {
+        if self.from == self.to {
+            return samples.to_vec();
+        }
+        self.push_mapped(samples);
+
+        let mut output = Vec::new();
+        let Some(mut resampler) = self.resampler.take() else {
+            let pending = std::mem::take(&mut self.pending);
+            self.pending = vec![Vec::with_capacity(CHUNK_FRAMES); pending.len()];
+            self.interleave(pending, &mut output);
+            return output;
+        };
+        while self.pending[0].len() >= resampler.input_frames_next() {
+            let needed = resampler.input_frames_next();
+            let chunk: Vec<Vec<f32>> = self.pending.iter_mut().map(|pending| pending.drain(..needed).collect()).collect();
+            match resampler.process(&chunk, None) {
+                Ok(resampled) => self.interleave(resampled, &mut output),
+                Err(e) => eprintln!("Resampling failed: {}", e),
+            }
+        }
+        self.resampler = Some(resampler);
+        output
+    }
+
+    // Converts what is still buffered at the end of a stream, so the output
+    // is as long as the input at the new rate
+    pub fn flush(&mut self) -> Vec<f32> {
+        let mut output = Vec::new();
+        let Some(mut resampler) = self.resampler.take() else {
+            return output;
+        };
+        let expected = (self.input_frames * self.to.sample_rate as u64).div_ceil(self.from.sample_rate as u64);
+        let channels = self.pending.len();
+        let mut rest = Some(std::mem::replace(&mut self.pending, vec![Vec::new(); channels]));
+        while self.output_frames < expected {
+            match resampler.process_partial(rest.take().as_deref(), None) {
+                Ok(resampled) => self.interleave(resampled, &mut output),
+                Err(e) => {
+                    eprintln!("Resampling failed: {}", e);
+                    break;
+                }
+            }
+        }
+        let excess = (self.output_frames - expected.min(self.output_frames)) as usize;
+        output.truncate(output.len().saturating_sub(excess * channels));
+        self.resampler = Some(resampler);
+        output
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::llm_backend::is_transient;
+
+// How transient backend failures are retried, and when to stop trying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    // Extra attempts after the first one
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    // Consecutive transient failures before the breaker opens
+    pub failure_threshold: u32,
+    // How long an open breaker rejects requests before allowing a trial call
+    pub cooldown_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 5000,
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.failure_threshold == 0 {
+            return Err("failure_threshold must be greater than 0".to_string());
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            return Err("initial_backoff_ms must not exceed max_backoff_ms".to_string());
+        }
+        Ok(())
+    }
+
+    // Exponential backoff: initial, 2x, 4x, ... capped at max_backoff_ms
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt);
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(factor).min(self.max_backoff_ms))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    // Cooldown has passed; one trial call decides whether to close again,
+    // and the rest are rejected until it does
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub backend: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub retry_after_secs: Option<u64>,
+}
+
+#[derive(Default)]
+struct BreakerEntry {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // A half-open trial call is running
+    probing: bool,
+}
+
+// Lets the next call be the trial once a half-open one ends, however it ends,
+// a dropped future included
+struct Probe<'a> {
+    breaker: &'a CircuitBreaker,
+    backend: &'a str,
+}
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut entries) = self.breaker.entries.lock() {
+            if let Some(entry) = entries.get_mut(self.backend) {
+                entry.probing = false;
+            }
+        }
+    }
+}
+
+// One breaker per backend (endpoint URL or local model path), so a dead
+// Ollama server doesn't block a working OpenAI-compatible one.
+pub struct CircuitBreaker {
+    entries: Mutex<HashMap<String, BreakerEntry>>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        CircuitBreaker {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Whether the call may go ahead as the half-open trial; an error when it
+    // may not go ahead at all
+    fn check(&self, backend: &str, policy: &RetryPolicy) -> Result<bool> {
+        let mut entries = self.entries.lock().map_err(|_| anyhow::anyhow!("Circuit breaker lock poisoned"))?;
+        let Some(entry) = entries.get_mut(backend) else { return Ok(false) };
+        let Some(opened_at) = entry.opened_at else { return Ok(false) };
+
+        let cooldown = Duration::from_secs(policy.cooldown_secs);
+        let elapsed = opened_at.elapsed();
+        if elapsed < cooldown {
+            return Err(anyhow::anyhow!(
+                "Backend {} unavailable after repeated failures. Retrying in {} seconds",
+                backend,
+                (cooldown - elapsed).as_secs().max(1)
+            ));
+        }
+        if entry.probing {
+            return Err(anyhow::anyhow!("Backend {} unavailable after repeated failures. A trial request is checking it", backend));
+        }
+        entry.probing = true;
+        Ok(true)
+    }
+
+    fn record(&self, backend: &str, policy: &RetryPolicy, transient_failure: bool) {
+        let Ok(mut entries) = self.entries.lock() else { return };
+        let entry = entries.entry(backend.to_string()).or_default();
+
+        if transient_failure {
+            entry.consecutive_failures += 1;
+            if entry.consecutive_failures >= policy.failure_threshold {
+                // Also restarts the cooldown when a half-open trial fails
+                entry.opened_at = Some(Instant::now());
+            }
+        } else {
+            *entry = BreakerEntry::default();
+        }
+    }
+
+    // Runs a single attempt through the breaker
+    pub async fn call<T, Fut>(&self, backend: &str, policy: &RetryPolicy, future: Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        let _probe = self.check(backend, policy)?.then_some(Probe { breaker: self, backend });
+
+        let result = future.await;
+        self.record(backend, policy, matches!(&result, Err(e) if is_transient(e)));
+        result
+    }
+
+    // Retries transient failures with exponential backoff. Stops early once the
+    // breaker opens, since further attempts would be rejected anyway.
+    pub async fn call_with_retry<T, F, Fut>(&self, backend: &str, policy: &RetryPolicy, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.call(backend, policy, operation()).await {
+                Err(e) if is_transient(&e) && attempt < policy.max_retries => {
+                    let delay = policy.backoff(attempt);
+                    println!("Retrying {} in {} ms after error: {}", backend, delay.as_millis(), e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub fn status(&self, policy: &RetryPolicy) -> Vec<BreakerStatus> {
+        let Ok(entries) = self.entries.lock() else { return Vec::new() };
+        let cooldown = Duration::from_secs(policy.cooldown_secs);
+
+        let mut statuses: Vec<BreakerStatus> = entries.iter()
+            .map(|(backend, entry)| {
+                let (state, retry_after_secs) = match entry.opened_at {
+                    Some(opened_at) if opened_at.elapsed() < cooldown => {
+                        (BreakerState::Open, Some((cooldown - opened_at.elapsed()).as_secs()))
+                    }
+                    Some(_) => (BreakerState::HalfOpen, None),
+                    None => (BreakerState::Closed, None),
+                };
+                BreakerStatus {
+                    backend: backend.clone(),
+                    state,
+                    consecutive_failures: entry.consecutive_failures,
+                    retry_after_secs,
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.backend.cmp(&b.backend));
+        statuses
+    }
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{command, Emitter, Manager, State};
+use tokio::sync::Notify;
+use crate::audio_capture::{AudioCapture, RecordingNotes};
+use crate::database::{Database, RecordingSchedule};
+
+pub const SCHEDULED_RECORDING_EVENT: &str = "dwight://scheduled-recording";
+
+// Schedules are checked this often, and whenever they change
+const TICK: Duration = Duration::from_secs(10);
+
+const TIME_FORMAT: &str = "%H:%M";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledRecording {
+    pub schedule_id: i64,
+    pub name: String,
+    // "started", "stopped" or "failed"
+    pub action: String,
+    pub file_path: Option<String>,
+    pub error: Option<String>,
+}
+
+// Wakes the background task; the schedules themselves are in the database
+pub struct Scheduler {
+    wake: Notify,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler { wake: Notify::new() }
+    }
+}
+
+// The window the running scheduled recording belongs to
+struct ActiveWindow {
+    schedule_id: i64,
+    name: String,
+    ends_at: DateTime<Local>,
+    // None when the recording failed to start; the window is left alone then
+    // rather than retried on every tick
+    file_path: Option<String>,
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time.trim(), TIME_FORMAT).map_err(|_| format!("'{}' is not a time as HH:MM", time))
+}
+
+fn validate(schedule: &RecordingSchedule) -> Result<(), String> {
+    if schedule.name.trim().is_empty() {
+        return Err("Schedule name is required".to_string());
+    }
+    if schedule.days.is_empty() {
+        return Err("A schedule needs at least one day".to_string());
+    }
+    if parse_time(&schedule.start_time)? == parse_time(&schedule.end_time)? {
+        return Err("start_time and end_time must differ".to_string());
+    }
+    Ok(())
+}
+
+// When the schedule's window that `now` falls in ends, if it falls in one.
+// A window past midnight may have started yesterday.
+fn window_end(schedule: &RecordingSchedule, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let (start, end) = (parse_time(&schedule.start_time).ok()?, parse_time(&schedule.end_time).ok()?);
+    let today = now.date_naive();
+    for date in [today, today.pred_opt()?] {
+        if !schedule.days.contains(&date.weekday()) {
+            continue;
+        }
+        let end_date = if end > start { date } else { date.succ_opt()? };
+        // Times skipped by a clock change have no window that day
+        let (Some(starts_at), Some(ends_at)) = (
+            date.and_time(start).and_local_timezone(Local).earliest(),
+            end_date.and_time(end).and_local_timezone(Local).earliest(),
+        ) else {
+            continue;
+        };
+        if starts_at <= now && now < ends_at {
+            return Some(ends_at);
+        }
+    }
+    None
+}
+
+fn emit(app_handle: &tauri::AppHandle, event: ScheduledRecording) {
+    if let Err(e) = app_handle.emit(SCHEDULED_RECORDING_EVENT, event) {
+        eprintln!("Failed to emit scheduled recording event: {}", e);
+    }
+}
+
+// Ends the active window once it is over or its schedule is gone, then
+// starts the window `now` falls in. Recordings someone started by hand are
+// never stopped, and a window doesn't start while one is running.
+async fn tick(app_handle: &tauri::AppHandle, active: Option<ActiveWindow>) -> Option<ActiveWindow> {
+    let schedules = match Database::new(app_handle).and_then(|db| db.get_schedules()) {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            eprintln!("Failed to load recording schedules: {}", e);
+            return active;
+        }
+    };
+    let now = Local::now();
+    let capture = app_handle.state::<AudioCapture>();
+
+    if let Some(window) = active {
+        let scheduled = schedules.iter().any(|schedule| schedule.id == Some(window.schedule_id) && schedule.enabled);
+        if scheduled && now < window.ends_at {
+            return Some(window);
+        }
+        let status = capture.status().await;
+        if status.recording && window.file_path.is_some() && status.file_path == window.file_path {
+            let (action, error) = match capture.stop_recording().await {
+                Ok(_) => ("stopped", None),
+                Err(e) => ("failed", Some(e)),
+            };
+            emit(app_handle, ScheduledRecording {
+                schedule_id: window.schedule_id,
+                name: window.name,
+                action: action.to_string(),
+                file_path: window.file_path,
+                error,
+            });
+        }
+    }
+
+    for schedule in schedules.iter().filter(|schedule| schedule.enabled) {
+        let (Some(schedule_id), Some(ends_at)) = (schedule.id, window_end(schedule, now)) else {
+            continue;
+        };
+        if capture.status().await.recording {
+            return None;
+        }
+        let title = format!("{} {}", schedule.name, now.format("%Y-%m-%d %H:%M"));
+        let started = capture.start_recording(app_handle, schedule.device_id.as_deref(), Some(title), None, None, RecordingNotes::default()).await;
+        let file_path = started.as_ref().ok().and_then(|status| status.file_path.clone());
+        emit(app_handle, ScheduledRecording {
+            schedule_id,
+            name: schedule.name.clone(),
+            action: if started.is_ok() { "started" } else { "failed" }.to_string(),
+            file_path: file_path.clone(),
+            error: started.err(),
+        });
+        return Some(ActiveWindow { schedule_id, name: schedule.name.clone(), ends_at, file_path });
+    }
+    None
+}
+
+// Started once from setup and runs for the life of the app
+pub fn start_scheduler(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut active = None;
+        loop {
+            active = tick(&app_handle, active).await;
+            // Wake right as the window ends rather than up to a tick late
+            let until_end = active.as_ref()
+                .and_then(|window: &ActiveWindow| (window.ends_at - Local::now()).to_std().ok())
+                .unwrap_or(TICK);
+            let scheduler = app_handle.state::<Scheduler>();
+            let _ = tokio::time::timeout(until_end.min(TICK), scheduler.wake.notified()).await;
+        }
+    });
+}
+
+#[command]
+pub async fn list_schedules(app_handle: tauri::AppHandle) -> Result<Vec<RecordingSchedule>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_schedules().map_err(|e| format!("Database error: {}", e))
+}
+
+// e.g. days ["Mon", "Tue", "Wed", "Thu", "Fri"] from "22:00" to "06:00"
+#[command]
+pub async fn create_schedule(
+    schedule: RecordingSchedule,
+    app_handle: tauri::AppHandle,
+    scheduler: State<'_, Scheduler>,
+) -> Result<RecordingSchedule, String> {
+    validate(&schedule)?;
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let id = db.save_schedule(&schedule).map_err(|e| format!("Database error: {}", e))?;
+    scheduler.wake.notify_one();
+    db.get_schedules()
+        .map_err(|e| format!("Database error: {}", e))?
+        .into_iter()
+        .find(|saved| saved.id == Some(id))
+        .ok_or_else(|| format!("Schedule {} not found", id))
+}
+
+// A recording the schedule has running is stopped
+#[command]
+pub async fn delete_schedule(schedule_id: i64, app_handle: tauri::AppHandle, scheduler: State<'_, Scheduler>) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    if !db.delete_schedule(schedule_id).map_err(|e| format!("Database error: {}", e))? {
+        return Err(format!("Schedule {} not found", schedule_id));
+    }
+    scheduler.wake.notify_one();
+    Ok(())
+}
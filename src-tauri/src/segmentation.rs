@@ -0,0 +1,285 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+use crate::audio_capture::AudioCapture;
+use crate::audio_file::{self, WavOutput};
+use crate::database::{AudioRecord, Database, RecordingSegment, TranscriptLine, TranscriptWord};
+use crate::resampler::ResampleQuality;
+use crate::transcript_index;
+use crate::whisper::{self, TranscriptionSegment};
+
+// Levels are measured over blocks this long
+const BLOCK_SECONDS: f64 = 0.1;
+
+// Silence kept at either end of a segment, so it doesn't start or stop mid-sound
+const PAD_SECONDS: f64 = 0.5;
+
+// Where a recording is split: at every stretch quieter than `threshold_db`
+// for at least `min_silence_seconds`
+#[derive(Debug, Clone, Copy)]
+pub struct SilenceSplit {
+    pub min_silence_seconds: f64,
+    pub threshold_db: f32,
+}
+
+// A segment written next to the recording it was cut from
+pub struct SplitSegment {
+    pub path: PathBuf,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+// Whether each block of the file is quieter than the threshold, from its RMS
+// level across all channels
+fn silent_blocks(path: &Path, block_frames: u64, threshold_db: f32) -> Result<Vec<bool>> {
+    let channels = audio_file::audio_spec(path)?.channels.max(1) as usize;
+    let block_samples = block_frames as usize * channels;
+    let threshold = 10f64.powf(threshold_db as f64 / 10.0);
+    let mut blocks = Vec::new();
+    let (mut power, mut filled) = (0.0f64, 0);
+    audio_file::for_each_chunk(path, |chunk| {
+        for &sample in chunk {
+            power += (sample as f64) * (sample as f64);
+            filled += 1;
+            if filled == block_samples {
+                blocks.push(power / (filled as f64) < threshold);
+                (power, filled) = (0.0, 0);
+            }
+        }
+        Ok(())
+    })?;
+    if filled > 0 {
+        blocks.push(power / (filled as f64) < threshold);
+    }
+    Ok(blocks)
+}
+
+// Spans of blocks between the long silences, each padded into the silence
+// around it. A recording without at least two of them isn't worth splitting.
+fn sound_spans(silent: &[bool], min_silence_blocks: usize, pad_blocks: usize) -> Vec<(usize, usize)> {
+    let mut gaps = Vec::new();
+    let mut run_start = None;
+    for (index, &quiet) in silent.iter().chain(std::iter::once(&false)).enumerate() {
+        match (quiet, run_start) {
+            (true, None) => run_start = Some(index),
+            (false, Some(start)) => {
+                if index - start >= min_silence_blocks {
+                    gaps.push((start, index));
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut spans = Vec::new();
+    let mut from = 0;
+    for &(gap_start, gap_end) in gaps.iter().chain(std::iter::once(&(silent.len(), silent.len()))) {
+        if gap_start > from {
+            let padded_from = if from == 0 { 0 } else { from.saturating_sub(pad_blocks) };
+            spans.push((padded_from, (gap_start + pad_blocks).min(gap_end)));
+        }
+        from = gap_end;
+    }
+    if spans.len() < 2 {
+        return Vec::new();
+    }
+    spans
+}
+
+fn segment_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}-part{:03}.wav", stem, index + 1))
+}
+
+// Copies each span of frames to a WAV of its own, in one pass over the file
+fn write_segments(path: &Path, spans: &[(u64, u64)], paths: &[PathBuf]) -> Result<()> {
+    let spec = audio_file::audio_spec(path)?;
+    let channels = spec.channels.max(1) as usize;
+    let mut current: Option<WavOutput> = None;
+    let (mut index, mut frame) = (0, 0u64);
+    audio_file::for_each_chunk(path, |chunk| {
+        let chunk_frames = (chunk.len() / channels) as u64;
+        let mut offset = 0u64;
+        while offset < chunk_frames && index < spans.len() {
+            let (start, end) = spans[index];
+            if frame + offset < start {
+                offset = (start - frame).min(chunk_frames);
+                continue;
+            }
+            let until = (end - frame).min(chunk_frames);
+            let output = match current.as_mut() {
+                Some(output) => output,
+                None => current.insert(WavOutput::create(&paths[index], spec)?),
+            };
+            output.write(&chunk[offset as usize * channels..until as usize * channels])?;
+            offset = until;
+            if frame + until == end {
+                if let Some(output) = current.take() {
+                    output.finish()?;
+                }
+                index += 1;
+            }
+        }
+        frame += chunk_frames;
+        Ok(())
+    })?;
+    if let Some(output) = current.take() {
+        output.finish()?;
+    }
+    Ok(())
+}
+
+// Splits a finished WAV recording at its long silences into WAVs named
+// after it, leaving the recording itself untouched. Nothing is written when
+// there is only one stretch of sound.
+pub fn split_recording(path: &Path, split: SilenceSplit) -> Result<Vec<SplitSegment>> {
+    let (format, duration) = audio_file::audio_info(path)?;
+    let sample_rate = format.sample_rate.max(1) as f64;
+    let block_frames = ((sample_rate * BLOCK_SECONDS) as u64).max(1);
+    let silent = silent_blocks(path, block_frames, split.threshold_db)?;
+    let min_silence_blocks = ((split.min_silence_seconds / BLOCK_SECONDS).ceil() as usize).max(1);
+    let pad_blocks = (PAD_SECONDS / BLOCK_SECONDS).round() as usize;
+
+    let spans: Vec<(u64, u64)> = sound_spans(&silent, min_silence_blocks, pad_blocks)
+        .into_iter()
+        .map(|(from, to)| (from as u64 * block_frames, to as u64 * block_frames))
+        .collect();
+    let paths: Vec<PathBuf> = (0..spans.len()).map(|index| segment_path(path, index)).collect();
+    if let Err(e) = write_segments(path, &spans, &paths) {
+        for segment_path in &paths {
+            let _ = std::fs::remove_file(segment_path);
+        }
+        return Err(e);
+    }
+
+    Ok(spans
+        .into_iter()
+        .zip(paths)
+        .map(|((start, end), path)| SplitSegment {
+            path,
+            start_seconds: start as f64 / sample_rate,
+            // The last block can run past the end of the file
+            end_seconds: (end as f64 / sample_rate).min(duration),
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedSegment {
+    #[serde(flatten)]
+    pub segment: RecordingSegment,
+    pub file_path: String,
+    // The parent's transcript lines within the segment, timed from its start
+    pub transcript_lines: Vec<TranscriptLine>,
+}
+
+// Copies the frames between two times to a file next to the recording, in
+// the recording's own format
+fn write_extract(path: &Path, start_seconds: f64, end_seconds: f64, opus_bitrate_kbps: u32, quality: ResampleQuality) -> Result<PathBuf> {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let wav_path = path.with_file_name(format!("{}-extract-{:.3}s-{:.3}s.wav", stem, start_seconds, end_seconds));
+    let mut output = WavOutput::create(&wav_path, audio_file::audio_spec(path)?)?;
+    audio_file::for_each_chunk_between(path, start_seconds, end_seconds, |chunk| output.write(chunk))?;
+    output.finish()?;
+
+    let encoded = if audio_file::is_flac(path) {
+        audio_file::encode_flac(&wav_path)
+    } else if audio_file::is_opus(path) {
+        audio_file::encode_opus(&wav_path, opus_bitrate_kbps, quality)
+    } else {
+        return Ok(wav_path);
+    };
+    let _ = std::fs::remove_file(&wav_path);
+    encoded
+}
+
+// The lines overlapping the span, cut to it and moved to start with it
+fn lines_within(lines: &[TranscriptLine], start_seconds: f64, end_seconds: f64) -> Vec<TranscriptLine> {
+    lines.iter()
+        .filter(|line| line.end_seconds > start_seconds && line.start_seconds < end_seconds)
+        .map(|line| TranscriptLine {
+            start_seconds: line.start_seconds.max(start_seconds) - start_seconds,
+            end_seconds: line.end_seconds.min(end_seconds) - start_seconds,
+            words: line.words.iter()
+                .filter(|word| word.end_seconds > start_seconds && word.start_seconds < end_seconds)
+                .map(|word| TranscriptWord {
+                    start_seconds: word.start_seconds.max(start_seconds) - start_seconds,
+                    end_seconds: word.end_seconds.min(end_seconds) - start_seconds,
+                    ..word.clone()
+                })
+                .collect(),
+            ..line.clone()
+        })
+        .collect()
+}
+
+// Cuts the span between two times out of a recording as a recording of its
+// own, linked to it as a segment and carrying the transcript lines spoken in
+// it. The recording itself is untouched.
+#[command]
+pub async fn extract_segment(
+    recording_id: i64,
+    start_ms: u64,
+    end_ms: u64,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<ExtractedSegment, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (_, duration) = audio_file::audio_info(&source).map_err(|e| format!("Extract error: {}", e))?;
+    let (start, end) = (start_ms as f64 / 1000.0, (end_ms as f64 / 1000.0).min(duration));
+    if start >= end {
+        return Err(format!("Segment must start before it ends, within the recording's {:.1} seconds", duration));
+    }
+
+    let settings = capture.get_settings();
+    let (bitrate, quality) = (settings.opus_bitrate_kbps, settings.resample_quality);
+    let path = tokio::task::spawn_blocking(move || write_extract(&source, start, end, bitrate, quality))
+        .await
+        .map_err(|e| format!("Extract error: {}", e))?
+        .map_err(|e| format!("Extract error: {}", e))?;
+
+    let lines = lines_within(&db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?, start, end);
+    let transcript = lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join(" ");
+    let file_path = path.to_string_lossy().to_string();
+    let segment_id = db.save_audio_record(&AudioRecord {
+            id: None,
+            title: format!("{} ({} to {})", record.title, transcript_index::format_timestamp(start), transcript_index::format_timestamp(end)),
+            file_path: file_path.clone(),
+            transcript: (!transcript.is_empty()).then_some(transcript),
+            duration: end - start,
+            created_at: String::new(),
+            triggers: record.triggers.clone(),
+        })
+        .map_err(|e| format!("Database error: {}", e))?;
+    let segment_index = db.get_recording_segments(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .iter()
+        .map(|segment| segment.segment_index + 1)
+        .max()
+        .unwrap_or(0);
+    let segment = RecordingSegment {
+        record_id: segment_id,
+        parent_id: recording_id,
+        segment_index,
+        start_seconds: start,
+        end_seconds: end,
+    };
+    db.save_recording_segment(&segment).map_err(|e| format!("Database error: {}", e))?;
+    if !lines.is_empty() {
+        db.save_transcript_lines(segment_id, &lines).map_err(|e| format!("Database error: {}", e))?;
+        let segments: Vec<TranscriptionSegment> = lines.iter().map(TranscriptionSegment::from_line).collect();
+        if let Some(language) = whisper::main_language(&segments) {
+            db.save_recording_language(segment_id, &language).map_err(|e| format!("Database error: {}", e))?;
+        }
+        transcript_index::queue_recording_update(&app_handle, segment_id, &segments);
+    }
+
+    Ok(ExtractedSegment { segment, file_path, transcript_lines: lines })
+}
@@ -0,0 +1,270 @@
+#[cfg(feature = "sound-events")]
+use ort::session::Session;
+#[cfg(feature = "sound-events")]
+use ort::value::{Tensor, ValueType};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{command, State};
+use crate::audio_capture::{AudioCapture, StreamFormat};
+use crate::audio_file;
+use crate::database::Database;
+#[cfg(feature = "sound-events")]
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+pub const SOUND_EVENT_DETECTED_EVENT: &str = "dwight://sound-event-detected";
+
+// Windows are scored every half second, so an event is heard at most that late
+#[cfg(feature = "sound-events")]
+const HOP_SECONDS: f64 = 0.5;
+
+// What YAMNet takes when the model doesn't fix a window length: 0.975 s at 16 kHz
+#[cfg(feature = "sound-events")]
+const DEFAULT_WINDOW_SAMPLES: usize = 15600;
+
+// One window of audio and the labels that scored at least the minimum in it
+#[derive(Debug, Clone)]
+pub struct ClassifiedWindow {
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub labels: Vec<(String, f32)>,
+}
+
+// A sound heard over one or more consecutive windows
+#[derive(Debug, Clone, Serialize)]
+pub struct SoundEvent {
+    pub label: String,
+    // Highest score of any window in the event
+    pub confidence: f32,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoundEventDetection {
+    pub label: String,
+    pub confidence: f32,
+    // When the sound started, in seconds since capture started
+    pub at_seconds: f64,
+    // Whether an active sound trigger matched it
+    pub triggered: bool,
+    // File of the recording the detection started or was tagged onto
+    pub recording_file: Option<String>,
+}
+
+// Labels one per line, or YAMNet's class_map.csv with its index,mid,display_name columns
+#[cfg(feature = "sound-events")]
+fn read_labels(path: &str) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read sound labels '{}': {}", path, e))?;
+    let mut lines = text.lines().peekable();
+    let csv = lines.peek().is_some_and(|header| header.starts_with("index,"));
+    if csv {
+        lines.next();
+    }
+    Ok(lines
+        .map(|line| {
+            if csv {
+                line.splitn(3, ',').nth(2).unwrap_or_default().trim().trim_matches('"').to_string()
+            } else {
+                line.trim().to_string()
+            }
+        })
+        .collect())
+}
+
+// Scores audio against the classes of an ONNX sound event model such as
+// YAMNet or PANNs, one window at a time
+pub struct SoundEventClassifier {
+    #[cfg(feature = "sound-events")]
+    session: Session,
+    #[cfg(feature = "sound-events")]
+    labels: Vec<String>,
+    #[cfg(feature = "sound-events")]
+    converter: FormatConverter,
+    #[cfg(feature = "sound-events")]
+    sample_rate: u32,
+    // Input shape without the samples, e.g. [1] for a [1, samples] batch
+    #[cfg(feature = "sound-events")]
+    leading_dims: Vec<i64>,
+    #[cfg(feature = "sound-events")]
+    window_samples: usize,
+    // Model-rate mono audio not yet scored, and where it starts
+    #[cfg(feature = "sound-events")]
+    pending: Vec<f32>,
+    #[cfg(feature = "sound-events")]
+    pending_start: u64,
+    #[cfg(feature = "sound-events")]
+    min_confidence: f32,
+}
+
+impl SoundEventClassifier {
+    #[cfg(feature = "sound-events")]
+    pub fn new(model_path: &str, labels_path: &str, sample_rate: u32, format: StreamFormat, min_confidence: f32) -> Result<Self> {
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(model_path))
+            .map_err(|e| anyhow::anyhow!("Failed to load sound event model from '{}': {}", model_path, e))?;
+        let labels = read_labels(labels_path)?;
+
+        let dimensions = session.inputs.first()
+            .and_then(|input| match &input.input_type {
+                ValueType::Tensor { dimensions, .. } => Some(dimensions.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("Sound event model has no tensor input"))?;
+        let (&samples, leading) = dimensions.split_last().ok_or_else(|| anyhow::anyhow!("Sound event model input has no dimensions"))?;
+        let window_samples = if samples > 0 { samples as usize } else { DEFAULT_WINDOW_SAMPLES };
+
+        Ok(SoundEventClassifier {
+            session,
+            labels,
+            converter: FormatConverter::new(format, StreamFormat { sample_rate, channels: 1 }, ResampleQuality::Fast),
+            sample_rate,
+            // Dynamic batch dimensions get a batch of one
+            leading_dims: leading.iter().map(|&dim| dim.max(1)).collect(),
+            window_samples,
+            pending: Vec::new(),
+            pending_start: 0,
+            min_confidence,
+        })
+    }
+
+    #[cfg(not(feature = "sound-events"))]
+    pub fn new(_model_path: &str, _labels_path: &str, _sample_rate: u32, _format: StreamFormat, _min_confidence: f32) -> Result<Self> {
+        Err(anyhow::anyhow!("Sound event classification not enabled. Please compile with 'sound-events' feature."))
+    }
+
+    // Scores of every class for one window. Models that score several
+    // frames per window report each class at its highest.
+    #[cfg(feature = "sound-events")]
+    fn score(&self, window: &[f32]) -> Result<Vec<f32>> {
+        let mut shape = self.leading_dims.clone();
+        shape.push(window.len() as i64);
+        let input = Tensor::from_array((shape, window.to_vec()))?;
+        let outputs = self.session.run(ort::inputs![input]?)?;
+        let (dimensions, values) = outputs[0].try_extract_raw_tensor::<f32>()?;
+        let classes = dimensions.last().copied().unwrap_or(0).max(1) as usize;
+        let mut scores = vec![0.0f32; classes];
+        for frame in values.chunks_exact(classes) {
+            for (score, &value) in scores.iter_mut().zip(frame) {
+                *score = score.max(value);
+            }
+        }
+        Ok(scores)
+    }
+
+    // Feeds capture audio and returns the windows completed by it
+    #[cfg(feature = "sound-events")]
+    pub fn process(&mut self, samples: &[f32]) -> Vec<ClassifiedWindow> {
+        self.pending.extend(self.converter.convert(samples));
+        let hop = ((self.sample_rate as f64 * HOP_SECONDS) as usize).clamp(1, self.window_samples);
+        let mut windows = Vec::new();
+        while self.pending.len() >= self.window_samples {
+            let start_seconds = self.pending_start as f64 / self.sample_rate as f64;
+            match self.score(&self.pending[..self.window_samples]) {
+                Ok(scores) => windows.push(ClassifiedWindow {
+                    start_seconds,
+                    end_seconds: start_seconds + self.window_samples as f64 / self.sample_rate as f64,
+                    labels: scores.iter()
+                        .zip(&self.labels)
+                        .filter(|(&score, _)| score >= self.min_confidence)
+                        .map(|(&score, label)| (label.clone(), score))
+                        .collect(),
+                }),
+                Err(e) => eprintln!("Sound event classification failed: {}", e),
+            }
+            self.pending.drain(..hop);
+            self.pending_start += hop as u64;
+        }
+        windows
+    }
+
+    #[cfg(not(feature = "sound-events"))]
+    pub fn process(&mut self, _samples: &[f32]) -> Vec<ClassifiedWindow> {
+        Vec::new()
+    }
+}
+
+// Joins each label's run of consecutive windows into one event
+#[derive(Default)]
+pub struct EventTracker {
+    open: Vec<SoundEvent>,
+}
+
+impl EventTracker {
+    // Events that started in the window, and those that ended before it
+    pub fn update(&mut self, window: &ClassifiedWindow) -> (Vec<SoundEvent>, Vec<SoundEvent>) {
+        let (continuing, ended): (Vec<SoundEvent>, Vec<SoundEvent>) = self.open
+            .drain(..)
+            .partition(|event| window.labels.iter().any(|(label, _)| *label == event.label));
+        self.open = continuing;
+
+        let mut started = Vec::new();
+        for (label, confidence) in &window.labels {
+            match self.open.iter_mut().find(|event| event.label == *label) {
+                Some(event) => {
+                    event.confidence = event.confidence.max(*confidence);
+                    event.end_seconds = window.end_seconds;
+                }
+                None => {
+                    let event = SoundEvent {
+                        label: label.clone(),
+                        confidence: *confidence,
+                        start_seconds: window.start_seconds,
+                        end_seconds: window.end_seconds,
+                    };
+                    started.push(event.clone());
+                    self.open.push(event);
+                }
+            }
+        }
+        (started, ended)
+    }
+
+    pub fn finish(&mut self) -> Vec<SoundEvent> {
+        self.open.drain(..).collect()
+    }
+}
+
+// Sound events heard through a stored recording, in order, using the model
+// from the capture settings
+#[command]
+pub async fn classify_sound_events(
+    recording_id: i64,
+    min_confidence: Option<f32>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Vec<SoundEvent>, String> {
+    let settings = capture.get_settings();
+    let min_confidence = min_confidence.unwrap_or(settings.sound_event_min_confidence);
+    if !(0.0..=1.0).contains(&min_confidence) {
+        return Err("min_confidence must be between 0.0 and 1.0".to_string());
+    }
+    let model_path = settings.sound_event_model_path.ok_or_else(|| "No sound event model is configured".to_string())?;
+    let labels_path = settings.sound_event_labels_path.ok_or_else(|| "No sound event labels are configured".to_string())?;
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let sample_rate = settings.sound_event_sample_rate;
+    tokio::task::spawn_blocking(move || -> Result<Vec<SoundEvent>> {
+        let format = audio_file::spec_format(&audio_file::audio_spec(&source)?);
+        let mut classifier = SoundEventClassifier::new(&model_path, &labels_path, sample_rate, format, min_confidence)?;
+        let mut tracker = EventTracker::default();
+        let mut events = Vec::new();
+        audio_file::for_each_chunk(&source, |chunk| {
+            for window in classifier.process(chunk) {
+                events.extend(tracker.update(&window).1);
+            }
+            Ok(())
+        })?;
+        events.extend(tracker.finish());
+        events.sort_by(|a, b| a.start_seconds.total_cmp(&b.start_seconds));
+        Ok(events)
+    })
+    .await
+    .map_err(|e| format!("Sound event error: {}", e))?
+    .map_err(|e| format!("Sound event error: {}", e))
+}
@@ -0,0 +1,258 @@
+#[cfg(feature = "speaker-embeddings")]
+use ort::session::Session;
+#[cfg(feature = "speaker-embeddings")]
+use ort::value::{Tensor, ValueType};
+#[cfg(feature = "speaker-embeddings")]
+use rustfft::{num_complex::Complex, FftPlanner};
+use anyhow::Result;
+use crate::audio_capture::CaptureSettings;
+#[cfg(feature = "speaker-embeddings")]
+use crate::diarization::SAMPLE_RATE;
+
+// Confidence in a match rises from a half at the threshold along a logistic
+// curve this steep, to 0.9 about 0.11 above it
+const CONFIDENCE_SLOPE: f32 = 20.0;
+
+// Matches less sure than this are reported as low confidence
+pub const HIGH_CONFIDENCE: f32 = 0.9;
+
+// Long audio is embedded in pieces of at most this long, whose prints are
+// averaged, so memory doesn't grow with it
+#[cfg(feature = "speaker-embeddings")]
+const MAX_PIECE_SECONDS: f64 = 10.0;
+
+// Shorter audio is repeated to this length, as speaker verification does,
+// so the model has enough frames to embed
+#[cfg(feature = "speaker-embeddings")]
+const MIN_PIECE_SECONDS: f64 = 1.0;
+
+// Kaldi's filterbank, which ECAPA-TDNN and x-vector models are trained on:
+// 25 ms frames every 10 ms, padded to 512 for the FFT, from 20 Hz up
+#[cfg(feature = "speaker-embeddings")]
+const FRAME_SAMPLES: usize = 400;
+#[cfg(feature = "speaker-embeddings")]
+const HOP_SAMPLES: usize = 160;
+#[cfg(feature = "speaker-embeddings")]
+const FFT_SIZE: usize = 512;
+#[cfg(feature = "speaker-embeddings")]
+const LOW_HZ: f32 = 20.0;
+#[cfg(feature = "speaker-embeddings")]
+const PREEMPHASIS: f32 = 0.97;
+
+// What the model takes: the waveform, [batch, samples], or filterbank
+// features, [batch, frames, mels] or [batch, mels, frames]
+#[cfg(feature = "speaker-embeddings")]
+#[derive(Debug, Clone, Copy)]
+enum ModelInput {
+    Waveform,
+    Features { mels: usize, mels_first: bool },
+}
+
+// An ONNX speaker-embedding model, such as ECAPA-TDNN or an x-vector
+// network, with the threshold its prints are calibrated to match at
+pub struct SpeakerEncoder {
+    #[cfg(feature = "speaker-embeddings")]
+    session: Session,
+    #[cfg(feature = "speaker-embeddings")]
+    input: ModelInput,
+    #[cfg(feature = "speaker-embeddings")]
+    filterbank: Vec<Vec<f32>>,
+    // The model's path, which prints are stored with; prints from different
+    // models can't be compared
+    model: String,
+    threshold: f32,
+}
+
+impl SpeakerEncoder {
+    // The model in the capture settings
+    pub fn load(settings: &CaptureSettings) -> Result<Self> {
+        let model = settings.speaker_model_path.as_deref()
+            .filter(|path| !path.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("No speaker embedding model is configured"))?;
+        Self::new(model, settings.speaker_match_threshold)
+    }
+
+    #[cfg(feature = "speaker-embeddings")]
+    pub fn new(model_path: &str, threshold: f32) -> Result<Self> {
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(model_path))
+            .map_err(|e| anyhow::anyhow!("Failed to load speaker embedding model from '{}': {}", model_path, e))?;
+        let dimensions = session.inputs.first()
+            .and_then(|input| match &input.input_type {
+                ValueType::Tensor { dimensions, .. } => Some(dimensions.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("Speaker embedding model has no tensor input"))?;
+        let input = match dimensions.as_slice() {
+            [_, _] => ModelInput::Waveform,
+            [_, _, mels] if *mels > 0 => ModelInput::Features { mels: *mels as usize, mels_first: false },
+            [_, mels, _] if *mels > 0 => ModelInput::Features { mels: *mels as usize, mels_first: true },
+            _ => return Err(anyhow::anyhow!("Speaker embedding model input {:?} is neither a waveform nor filterbank features", dimensions)),
+        };
+        let filterbank = match input {
+            ModelInput::Features { mels, .. } => mel_filterbank(mels),
+            ModelInput::Waveform => Vec::new(),
+        };
+        Ok(SpeakerEncoder { session, input, filterbank, model: model_path.to_string(), threshold })
+    }
+
+    #[cfg(not(feature = "speaker-embeddings"))]
+    pub fn new(_model_path: &str, _threshold: f32) -> Result<Self> {
+        Err(anyhow::anyhow!("Speaker embeddings not enabled. Please compile with 'speaker-embeddings' feature."))
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    // Two prints at least this cosine distance apart are different voices
+    pub fn max_distance(&self) -> f32 {
+        1.0 - self.threshold
+    }
+
+    pub fn matches(&self, similarity: f32) -> bool {
+        similarity >= self.threshold
+    }
+
+    // How sure a match of this cosine similarity is
+    pub fn confidence(&self, similarity: f32) -> f32 {
+        1.0 / (1.0 + (-CONFIDENCE_SLOPE * (similarity - self.threshold)).exp())
+    }
+
+    // The unit-length print of 16 kHz mono speech; empty for no audio
+    #[cfg(feature = "speaker-embeddings")]
+    pub fn embed(&self, samples: &[f32]) -> Result<Vec<f32>> {
+        if samples.is_empty() {
+            return Ok(Vec::new());
+        }
+        let max_piece = (MAX_PIECE_SECONDS * SAMPLE_RATE as f64) as usize;
+        let pieces = samples.len().div_ceil(max_piece);
+        let piece_len = samples.len().div_ceil(pieces);
+        let mut total: Vec<f32> = Vec::new();
+        for piece in samples.chunks(piece_len) {
+            let print = self.embed_piece(piece)?;
+            let weight = piece.len() as f32;
+            total.resize(print.len(), 0.0);
+            for (sum, value) in total.iter_mut().zip(print) {
+                *sum += value * weight;
+            }
+        }
+        Ok(normalized(total))
+    }
+
+    #[cfg(not(feature = "speaker-embeddings"))]
+    pub fn embed(&self, _samples: &[f32]) -> Result<Vec<f32>> {
+        Err(anyhow::anyhow!("Speaker embeddings not enabled. Please compile with 'speaker-embeddings' feature."))
+    }
+
+    #[cfg(feature = "speaker-embeddings")]
+    fn embed_piece(&self, samples: &[f32]) -> Result<Vec<f32>> {
+        let min_piece = (MIN_PIECE_SECONDS * SAMPLE_RATE as f64) as usize;
+        let samples: Vec<f32> = samples.iter().copied().cycle().take(samples.len().max(min_piece)).collect();
+        let input = match self.input {
+            ModelInput::Waveform => Tensor::from_array((vec![1, samples.len() as i64], samples))?,
+            ModelInput::Features { mels, mels_first } => {
+                let features = self.features(&samples);
+                let frames = features.len();
+                let (shape, values): (Vec<i64>, Vec<f32>) = if mels_first {
+                    (vec![1, mels as i64, frames as i64], (0..mels).flat_map(|mel| features.iter().map(move |frame| frame[mel])).collect())
+                } else {
+                    (vec![1, frames as i64, mels as i64], features.into_iter().flatten().collect())
+                };
+                Tensor::from_array((shape, values))?
+            }
+        };
+        let outputs = self.session.run(ort::inputs![input]?)?;
+        let (_, values) = outputs[0].try_extract_raw_tensor::<f32>()?;
+        Ok(normalized(values.to_vec()))
+    }
+
+    // Log mel filterbank energies Kaldi's way, on the waveform at 16 bit
+    // scale, with each mel's mean over the frames taken off
+    #[cfg(feature = "speaker-embeddings")]
+    fn features(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let window: Vec<f32> = (0..FRAME_SAMPLES)
+            .map(|index| (0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / (FRAME_SAMPLES - 1) as f32).cos()).powf(0.85))
+            .collect();
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+        let frames = 1 + samples.len().saturating_sub(FRAME_SAMPLES) / HOP_SAMPLES;
+        let mut features: Vec<Vec<f32>> = (0..frames)
+            .map(|frame| {
+                let start = frame * HOP_SAMPLES;
+                let mut values: Vec<f32> = (0..FRAME_SAMPLES)
+                    .map(|index| samples.get(start + index).copied().unwrap_or(0.0) * 32768.0)
+                    .collect();
+                let mean = values.iter().sum::<f32>() / FRAME_SAMPLES as f32;
+                values.iter_mut().for_each(|value| *value -= mean);
+                for index in (1..FRAME_SAMPLES).rev() {
+                    values[index] -= PREEMPHASIS * values[index - 1];
+                }
+                values[0] -= PREEMPHASIS * values[0];
+                for (index, slot) in buffer.iter_mut().enumerate() {
+                    *slot = Complex::new(values.get(index).map_or(0.0, |value| value * window[index]), 0.0);
+                }
+                fft.process(&mut buffer);
+                let power: Vec<f32> = buffer[..FFT_SIZE / 2 + 1].iter().map(|bin| bin.norm_sqr()).collect();
+                self.filterbank.iter()
+                    .map(|weights| weights.iter().zip(&power).map(|(weight, power)| weight * power).sum::<f32>().max(f32::EPSILON).ln())
+                    .collect()
+            })
+            .collect();
+        let mels = self.filterbank.len();
+        for mel in 0..mels {
+            let mean = features.iter().map(|frame| frame[mel]).sum::<f32>() / frames as f32;
+            features.iter_mut().for_each(|frame| frame[mel] -= mean);
+        }
+        features
+    }
+}
+
+// Cosine similarity of unit-length prints
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(feature = "speaker-embeddings")]
+fn normalized(mut values: Vec<f32>) -> Vec<f32> {
+    let norm = values.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|value| *value /= norm);
+    }
+    values
+}
+
+// Kaldi's mel scale
+#[cfg(feature = "speaker-embeddings")]
+fn mel(hz: f32) -> f32 {
+    1127.0 * (1.0 + hz / 700.0).ln()
+}
+
+// Triangles evenly spaced on the mel scale from LOW_HZ to Nyquist, as a
+// weight for every FFT bin
+#[cfg(feature = "speaker-embeddings")]
+fn mel_filterbank(mels: usize) -> Vec<Vec<f32>> {
+    let nyquist = SAMPLE_RATE as f32 / 2.0;
+    let (low, high) = (mel(LOW_HZ), mel(nyquist));
+    let step = (high - low) / (mels + 1) as f32;
+    (0..mels)
+        .map(|filter| {
+            let (left, centre, right) = (low + step * filter as f32, low + step * (filter + 1) as f32, low + step * (filter + 2) as f32);
+            (0..=FFT_SIZE / 2)
+                .map(|bin| {
+                    let at = mel(bin as f32 * SAMPLE_RATE as f32 / FFT_SIZE as f32);
+                    if at <= left || at >= right {
+                        0.0
+                    } else if at <= centre {
+                        (at - left) / (centre - left)
+                    } else {
+                        (right - at) / (right - centre)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::{command, Emitter, State};
+use crate::audio_capture::{AudioCapture, CaptureSettings};
+use crate::audio_file;
+use crate::database::{Database, EnrolledSpeaker, SpeakerAttribution, TranscriptLine};
+use crate::diarization::{self, SAMPLE_RATE};
+use crate::resampler::ResampleQuality;
+use crate::speaker_embedding::{self, SpeakerEncoder, HIGH_CONFIDENCE};
+
+pub const SPEAKER_IDENTIFIED_EVENT: &str = "dwight://speaker-identified";
+
+// A print is only as good as the speech it was taken from
+const MIN_ENROLLMENT_SECONDS: f64 = 5.0;
+
+// A stretch of a file with the voice in it; the whole file without a span
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoiceSample {
+    pub path: String,
+    pub start_seconds: Option<f64>,
+    pub end_seconds: Option<f64>,
+}
+
+// Sent when a speaker on the watchlist, an active "speaker" trigger, is
+// heard in a recording
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerIdentified {
+    pub recording_id: i64,
+    pub speaker_name: String,
+    pub confidence: f32,
+    // The match is near the model's threshold, a hint to check rather than
+    // an identification
+    pub low_confidence: bool,
+    // Where they first speak, in seconds into the file
+    pub start_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerIdentification {
+    pub attributions: Vec<SpeakerAttribution>,
+    // Enrolled speakers on the watchlist who were heard
+    pub watchlisted: Vec<String>,
+    // Any attribution is low confidence, as on SpeakerIdentified
+    pub low_confidence: bool,
+}
+
+fn sample_audio(sample: &VoiceSample, quality: ResampleQuality) -> anyhow::Result<Vec<f32>> {
+    let start = sample.start_seconds.unwrap_or(0.0).max(0.0);
+    let end = sample.end_seconds.unwrap_or(f64::INFINITY).max(start);
+    audio_file::read_mono_between(Path::new(&sample.path), start, end, SAMPLE_RATE, quality)
+}
+
+// The enrolled speaker each line sounds like, if any, with how sure the
+// match is. Lines a diarized speaker shares are matched together, which
+// takes more of their voice into account; undiarized lines are matched one
+// by one. Only prints the encoder's model took are compared.
+fn attribute(encoder: &SpeakerEncoder, audio: &[Vec<f32>], lines: &[TranscriptLine], speakers: &[EnrolledSpeaker]) -> anyhow::Result<Vec<(usize, usize, f32)>> {
+    let mut groups: Vec<(Option<u32>, Vec<usize>)> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        match groups.iter_mut().find(|(speaker, _)| speaker.is_some() && *speaker == line.speaker) {
+            Some((_, members)) => members.push(index),
+            None => groups.push((line.speaker, vec![index])),
+        }
+    }
+
+    let min_samples = (diarization::MIN_LINE_SECONDS * SAMPLE_RATE as f64) as usize;
+    let mut attributions = Vec::new();
+    for (_, members) in groups {
+        let audio: Vec<f32> = members.iter().flat_map(|&index| audio[index].iter().copied()).collect();
+        if audio.len() < min_samples {
+            continue;
+        }
+        let embedding = encoder.embed(&audio)?;
+        let best = speakers.iter()
+            .enumerate()
+            .filter(|(_, enrolled)| enrolled.model == encoder.model())
+            .map(|(speaker, enrolled)| (speaker, speaker_embedding::similarity(&embedding, &enrolled.embedding)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((speaker, similarity)) = best.filter(|(_, similarity)| encoder.matches(*similarity)) {
+            let confidence = encoder.confidence(similarity);
+            attributions.extend(members.iter().map(|&index| (index, speaker, confidence)));
+        }
+    }
+    attributions.sort_by_key(|(index, _, _)| *index);
+    Ok(attributions)
+}
+
+// Attributes a transcribed recording's lines to enrolled speakers and stores
+// it. Watchlisted speakers who are heard are sent as events and added to the
+// recording's triggers. Voices are matched by the speaker model in
+// `settings`.
+pub async fn identify(app_handle: &tauri::AppHandle, recording_id: i64, settings: CaptureSettings) -> Result<SpeakerIdentification, String> {
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    if lines.is_empty() {
+        return Err(format!("Recording {} has no timed transcript to identify speakers in; transcribe it first", recording_id));
+    }
+    let speakers = db.get_enrolled_speakers().map_err(|e| format!("Database error: {}", e))?;
+    if speakers.is_empty() {
+        return Ok(SpeakerIdentification { attributions: Vec::new(), watchlisted: Vec::new(), low_confidence: false });
+    }
+
+    let path = record.file_path.clone();
+    let (lines, audio, encoder) = tokio::task::spawn_blocking(move || {
+        let encoder = SpeakerEncoder::load(&settings)?;
+        let audio = diarization::read_lines(Path::new(&path), &lines, settings.resample_quality)?;
+        Ok::<_, anyhow::Error>((lines, audio, encoder))
+    })
+    .await
+    .map_err(|e| format!("Speaker identification failed: {}", e))?
+    .map_err(|e| format!("Speaker identification failed: {}", e))?;
+    identify_lines(app_handle, recording_id, 0, &lines, audio, speakers, encoder).await
+}
+
+// As identify, for the recording's lines from `first` on, with their audio
+// already read by diarization::read_lines and the speaker model loaded.
+// Only those lines' attributions are replaced.
+pub async fn identify_lines(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    first: usize,
+    lines: &[TranscriptLine],
+    audio: Vec<Vec<f32>>,
+    speakers: Vec<EnrolledSpeaker>,
+    encoder: SpeakerEncoder,
+) -> Result<SpeakerIdentification, String> {
+    let matched = lines.to_vec();
+    let (speakers, found) = tokio::task::spawn_blocking(move || {
+        let found = attribute(&encoder, &audio, &matched, &speakers)?;
+        Ok::<_, anyhow::Error>((speakers, found))
+    })
+    .await
+    .map_err(|e| format!("Speaker identification failed: {}", e))?
+    .map_err(|e| format!("Speaker identification failed: {}", e))?;
+
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let attributions: Vec<SpeakerAttribution> = found.iter()
+        .filter_map(|&(line_index, speaker, confidence)| {
+            Some(SpeakerAttribution {
+                record_id: recording_id,
+                line_index: (first + line_index) as i64,
+                speaker_id: speakers[speaker].id?,
+                speaker_name: speakers[speaker].name.clone(),
+                confidence,
+            })
+        })
+        .collect();
+    db.save_speaker_attributions(recording_id, first..first + lines.len(), &attributions).map_err(|e| format!("Database error: {}", e))?;
+
+    let watchlist: Vec<String> = db.get_active_triggers()
+        .map_err(|e| format!("Database error: {}", e))?
+        .into_iter()
+        .filter(|trigger| trigger.trigger_type == "speaker")
+        .map(|trigger| trigger.trigger_value.to_lowercase())
+        .collect();
+    let mut watchlisted: Vec<String> = Vec::new();
+    for attribution in &attributions {
+        if !watchlist.contains(&attribution.speaker_name.to_lowercase()) || watchlisted.contains(&attribution.speaker_name) {
+            continue;
+        }
+        watchlisted.push(attribution.speaker_name.clone());
+        let identified = SpeakerIdentified {
+            recording_id,
+            speaker_name: attribution.speaker_name.clone(),
+            confidence: attribution.confidence,
+            low_confidence: attribution.confidence < HIGH_CONFIDENCE,
+            start_seconds: lines[attribution.line_index as usize - first].start_seconds,
+        };
+        if let Err(e) = app_handle.emit(SPEAKER_IDENTIFIED_EVENT, identified) {
+            eprintln!("Failed to emit speaker identification: {}", e);
+        }
+    }
+    if !watchlisted.is_empty() {
+        let record = db.get_audio_record(recording_id)
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+        let mut triggers: Vec<String> = record.triggers.iter()
+            .flat_map(|triggers| triggers.split(", ").map(str::to_string))
+            .collect();
+        for name in &watchlisted {
+            if !triggers.contains(name) {
+                triggers.push(name.clone());
+            }
+        }
+        db.update_audio_triggers(recording_id, Some(&triggers.join(", "))).map_err(|e| format!("Database error: {}", e))?;
+    }
+    let low_confidence = attributions.iter().any(|attribution| attribution.confidence < HIGH_CONFIDENCE);
+    Ok(SpeakerIdentification { attributions, watchlisted, low_confidence })
+}
+
+// Enrolls a known voice from a few clips of it, replacing any print already
+// enrolled under the name. Add a "speaker" trigger with the name to be
+// alerted when they are heard. A print is only compared with voices the
+// same speaker model embeds, so speakers are enrolled again after it
+// changes.
+#[command]
+pub async fn enroll_speaker(
+    name: String,
+    samples: Vec<VoiceSample>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<EnrolledSpeaker, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("A speaker needs a name".to_string());
+    }
+    if samples.is_empty() {
+        return Err("Enrolling a speaker needs at least one sample of their voice".to_string());
+    }
+    if let Some(missing) = samples.iter().find(|sample| !Path::new(&sample.path).exists()) {
+        return Err(format!("Audio file not found: {}", missing.path));
+    }
+    let settings = capture.get_settings();
+    let (embedding, model, sample_seconds) = tokio::task::spawn_blocking(move || {
+        let encoder = SpeakerEncoder::load(&settings)?;
+        let mut audio = Vec::new();
+        for sample in &samples {
+            audio.extend(sample_audio(sample, settings.resample_quality)?);
+        }
+        let sample_seconds = audio.len() as f64 / SAMPLE_RATE as f64;
+        if sample_seconds < MIN_ENROLLMENT_SECONDS {
+            return Err(anyhow::anyhow!(
+                "The samples hold {:.1} seconds of audio; enrolling needs at least {} seconds of the voice",
+                sample_seconds, MIN_ENROLLMENT_SECONDS
+            ));
+        }
+        Ok((encoder.embed(&audio)?, encoder.model().to_string(), sample_seconds))
+    })
+    .await
+    .map_err(|e| format!("Enrollment failed: {}", e))?
+    .map_err(|e| format!("Enrollment failed: {}", e))?;
+
+    let mut speaker = EnrolledSpeaker {
+        id: None,
+        name,
+        embedding,
+        model,
+        sample_seconds,
+        created_at: String::new(),
+    };
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    speaker.id = Some(db.save_enrolled_speaker(&speaker).map_err(|e| format!("Database error: {}", e))?);
+    Ok(speaker)
+}
+
+#[command]
+pub async fn list_enrolled_speakers(app_handle: tauri::AppHandle) -> Result<Vec<EnrolledSpeaker>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_enrolled_speakers().map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn remove_enrolled_speaker(speaker_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.delete_enrolled_speaker(speaker_id).map_err(|e| format!("Database error: {}", e))
+}
+
+// Works out which lines of a recording enrolled speakers said, with how
+// sure it is of each. Diarize first to match by whole speakers.
+#[command]
+pub async fn identify_speakers(
+    recording_id: i64,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<SpeakerIdentification, String> {
+    identify(&app_handle, recording_id, capture.get_settings()).await
+}
+
+#[command]
+pub async fn get_speaker_attributions(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<SpeakerAttribution>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_speaker_attributions(recording_id).map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,282 @@
+use anyhow::Result;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_capture;
+use crate::audio_file;
+use crate::database::Database;
+
+// Wider images than this are refused; a larger hop or a shorter range fits
+const MAX_COLUMNS: usize = 16384;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowFunction {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    // No tapering, for the sharpest time resolution at the cost of leakage
+    Rectangular,
+}
+
+impl WindowFunction {
+    fn coefficients(self, size: usize) -> Vec<f32> {
+        let n = (size.max(2) - 1) as f32;
+        (0..size)
+            .map(|index| {
+                let phase = 2.0 * PI * index as f32 / n;
+                match self {
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+                    WindowFunction::Rectangular => 1.0,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMap {
+    #[default]
+    Viridis,
+    Magma,
+    Inferno,
+    Grayscale,
+}
+
+impl ColorMap {
+    // Evenly spaced colours from quiet to loud, interpolated between
+    fn stops(self) -> &'static [[f32; 3]] {
+        match self {
+            ColorMap::Viridis => &[[68.0, 1.0, 84.0], [59.0, 82.0, 139.0], [33.0, 145.0, 140.0], [94.0, 201.0, 98.0], [253.0, 231.0, 37.0]],
+            ColorMap::Magma => &[[0.0, 0.0, 4.0], [81.0, 18.0, 124.0], [183.0, 55.0, 121.0], [252.0, 137.0, 97.0], [252.0, 253.0, 191.0]],
+            ColorMap::Inferno => &[[0.0, 0.0, 4.0], [87.0, 16.0, 110.0], [188.0, 55.0, 84.0], [249.0, 142.0, 9.0], [252.0, 255.0, 164.0]],
+            ColorMap::Grayscale => &[[0.0, 0.0, 0.0], [255.0, 255.0, 255.0]],
+        }
+    }
+
+    fn color(self, level: f32) -> [u8; 3] {
+        let stops = self.stops();
+        let position = level.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let fraction = position - index as f32;
+        let (from, to) = (stops[index], stops[index + 1]);
+        [0, 1, 2].map(|channel| (from[channel] + (to[channel] - from[channel]) * fraction).round() as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectrogramOutput {
+    // An image file, time across and frequency up
+    #[default]
+    Png,
+    // Magnitudes in dB, one row of frequency bins per time step
+    Matrix,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrogramParams {
+    // Samples per FFT; bigger resolves frequency more finely and time less
+    pub window_size: usize,
+    pub hop_size: usize,
+    pub window: WindowFunction,
+    pub color_map: ColorMap,
+    pub output: SpectrogramOutput,
+    // Levels mapped to the bottom and top of the colour map
+    pub min_db: f32,
+    pub max_db: f32,
+    // One channel only; the channels are mixed without it
+    pub channel: Option<u16>,
+    pub start_seconds: Option<f64>,
+    pub end_seconds: Option<f64>,
+}
+
+impl Default for SpectrogramParams {
+    fn default() -> Self {
+        SpectrogramParams {
+            window_size: 1024,
+            hop_size: 256,
+            window: WindowFunction::default(),
+            color_map: ColorMap::default(),
+            output: SpectrogramOutput::default(),
+            min_db: -100.0,
+            max_db: 0.0,
+            channel: None,
+            start_seconds: None,
+            end_seconds: None,
+        }
+    }
+}
+
+impl SpectrogramParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(64..=32768).contains(&self.window_size) {
+            return Err("window_size must be between 64 and 32768".to_string());
+        }
+        if !(1..=self.window_size).contains(&self.hop_size) {
+            return Err("hop_size must be between 1 and window_size".to_string());
+        }
+        if self.min_db >= self.max_db {
+            return Err("min_db must be below max_db".to_string());
+        }
+        if self.start_seconds.zip(self.end_seconds).is_some_and(|(start, end)| start >= end) {
+            return Err("start_seconds must be before end_seconds".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Spectrogram {
+    pub recording_id: i64,
+    pub sample_rate: u32,
+    pub window_size: usize,
+    pub hop_size: usize,
+    // Time steps across and frequency bins up, from 0 Hz to Nyquist
+    pub columns: usize,
+    pub bins: usize,
+    pub seconds_per_column: f64,
+    pub hz_per_bin: f64,
+    pub start_seconds: f64,
+    pub file_path: Option<String>,
+    pub magnitudes_db: Option<Vec<Vec<f32>>>,
+}
+
+// Short-time Fourier transform of one channel or the mix, in dB relative to
+// a full-scale sine
+fn compute(path: &Path, params: &SpectrogramParams, start_seconds: f64, end_seconds: f64) -> Result<Vec<Vec<f32>>> {
+    let spec = audio_file::audio_spec(path)?;
+    let channels = spec.channels.max(1) as usize;
+    if let Some(channel) = params.channel.filter(|&channel| channel >= spec.channels) {
+        return Err(anyhow::anyhow!("Channel {} out of range, the file has {} channels", channel, spec.channels));
+    }
+
+    let window = params.window.coefficients(params.window_size);
+    let scale = 2.0 / window.iter().sum::<f32>();
+    let fft = FftPlanner::new().plan_fft_forward(params.window_size);
+    let bins = params.window_size / 2 + 1;
+    let mut buffer = vec![Complex::new(0.0, 0.0); params.window_size];
+    let mut pending: Vec<f32> = Vec::new();
+    let mut columns = Vec::new();
+
+    audio_file::for_each_chunk_between(path, start_seconds, end_seconds, |chunk| {
+        pending.extend(chunk.chunks_exact(channels).map(|frame| match params.channel {
+            Some(channel) => frame[channel as usize],
+            None => frame.iter().sum::<f32>() / channels as f32,
+        }));
+        while pending.len() >= params.window_size {
+            if columns.len() == MAX_COLUMNS {
+                return Err(anyhow::anyhow!(
+                    "Spectrogram would be over {} columns wide; use a larger hop_size or a shorter range",
+                    MAX_COLUMNS
+                ));
+            }
+            for ((value, &sample), &weight) in buffer.iter_mut().zip(&pending).zip(&window) {
+                *value = Complex::new(sample * weight, 0.0);
+            }
+            fft.process(&mut buffer);
+            columns.push(buffer[..bins].iter().map(|value| {
+                let magnitude = value.norm() * scale;
+                if magnitude > 0.0 { 20.0 * magnitude.log10() } else { f32::NEG_INFINITY }
+            }).map(|db| db.max(params.min_db)).collect());
+            pending.drain(..params.hop_size);
+        }
+        Ok(())
+    })?;
+    Ok(columns)
+}
+
+fn write_png(output_path: &Path, columns: &[Vec<f32>], params: &SpectrogramParams) -> Result<()> {
+    let bins = params.window_size / 2 + 1;
+    let range = params.max_db - params.min_db;
+    let mut pixels = Vec::with_capacity(columns.len() * bins * 3);
+    // Rows run from the top of the image, so the highest frequency comes first
+    for bin in (0..bins).rev() {
+        for column in columns {
+            pixels.extend(params.color_map.color((column[bin] - params.min_db) / range));
+        }
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(output_path)?), columns.len() as u32, bins as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+// Images are named after the parameters, so asking again overwrites the
+// same file rather than piling up copies
+fn spectrogram_path(app_handle: &tauri::AppHandle, source: &Path, params: &SpectrogramParams) -> Result<PathBuf> {
+    let dir = audio_capture::recordings_dir(app_handle)?.join("spectrograms");
+    std::fs::create_dir_all(&dir)?;
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(params)?.as_bytes());
+    let key = format!("{:x}", hasher.finalize());
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(dir.join(format!("{}-spectrogram-{}.png", stem, &key[..12])))
+}
+
+#[command]
+pub async fn generate_spectrogram(
+    recording_id: i64,
+    params: Option<SpectrogramParams>,
+    app_handle: tauri::AppHandle,
+) -> Result<Spectrogram, String> {
+    let params = params.unwrap_or_default();
+    params.validate()?;
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let source = PathBuf::from(&record.file_path);
+    let (format, duration) = audio_file::audio_info(&source).map_err(|e| format!("Spectrogram error: {}", e))?;
+    let start_seconds = params.start_seconds.unwrap_or(0.0).max(0.0);
+    let end_seconds = params.end_seconds.unwrap_or(duration).min(duration);
+
+    let output_path = match params.output {
+        SpectrogramOutput::Png => Some(spectrogram_path(&app_handle, &source, &params).map_err(|e| format!("Spectrogram error: {}", e))?),
+        SpectrogramOutput::Matrix => None,
+    };
+    let (task_params, task_output) = (params.clone(), output_path.clone());
+    let columns = tokio::task::spawn_blocking(move || -> Result<Vec<Vec<f32>>> {
+        let columns = compute(&source, &task_params, start_seconds, end_seconds)?;
+        if columns.is_empty() {
+            return Err(anyhow::anyhow!("The range is shorter than one window of {} samples", task_params.window_size));
+        }
+        if let Some(output_path) = task_output {
+            write_png(&output_path, &columns, &task_params)?;
+        }
+        Ok(columns)
+    })
+    .await
+    .map_err(|e| format!("Spectrogram error: {}", e))?
+    .map_err(|e| format!("Spectrogram error: {}", e))?;
+
+    Ok(Spectrogram {
+        recording_id,
+        sample_rate: format.sample_rate,
+        window_size: params.window_size,
+        hop_size: params.hop_size,
+        columns: columns.len(),
+        bins: params.window_size / 2 + 1,
+        seconds_per_column: params.hop_size as f64 / format.sample_rate as f64,
+        hz_per_bin: format.sample_rate as f64 / params.window_size as f64,
+        start_seconds,
+        file_path: output_path.map(|path| path.to_string_lossy().to_string()),
+        magnitudes_db: (params.output == SpectrogramOutput::Matrix).then_some(columns),
+    })
+}
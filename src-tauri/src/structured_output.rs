@@ -0,0 +1,99 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use crate::ai_models::{AdvancedAI, GenerationOptions, LlamaResponse};
+
+// Attempts per structured query, including the first one
+pub const MAX_STRUCTURED_ATTEMPTS: usize = 3;
+
+// A typed model answer. Deserializing checks the shape; `validate` checks the
+// values serde can't (ranges, non-empty fields).
+pub trait StructuredOutput: DeserializeOwned {
+    fn validate(&self) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedSound {
+    pub label: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioAnalysisReport {
+    pub summary: String,
+    pub speech_present: bool,
+    #[serde(default)]
+    pub detected_sounds: Vec<DetectedSound>,
+    #[serde(default)]
+    pub security_concerns: Vec<String>,
+    #[serde(default)]
+    pub recommended_actions: Vec<String>,
+}
+
+impl StructuredOutput for AudioAnalysisReport {
+    fn validate(&self) -> Result<(), String> {
+        if self.summary.trim().is_empty() {
+            return Err("summary must not be empty".to_string());
+        }
+        for sound in &self.detected_sounds {
+            if !(0.0..=1.0).contains(&sound.confidence) {
+                return Err(format!("confidence for '{}' must be between 0 and 1, got {}", sound.label, sound.confidence));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Models often wrap JSON in markdown fences or add a sentence around it, so
+// parse the outermost {...} span
+pub fn extract_json(text: &str) -> &str {
+    match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text.trim(),
+    }
+}
+
+pub fn parse_structured<T: StructuredOutput>(text: &str) -> Result<T, String> {
+    let value: T = serde_json::from_str(extract_json(text)).map_err(|e| e.to_string())?;
+    value.validate()?;
+    Ok(value)
+}
+
+// Runs `prompt` in JSON mode until the reply parses into `T`. Each retry tells
+// the model what was wrong with its previous reply. Without a temperature
+// it runs at 0, so a reply that parses is cached.
+pub async fn query_structured<T: StructuredOutput>(
+    ai: &AdvancedAI,
+    prompt: &str,
+    model: &str,
+    options: &GenerationOptions,
+) -> Result<(T, LlamaResponse)> {
+    let options = GenerationOptions {
+        json: Some(true),
+        temperature: options.temperature.or(Some(0.0)),
+        ..options.clone()
+    };
+
+    let mut attempt_prompt = prompt.to_string();
+    let mut last_error = String::new();
+
+    for attempt in 1..=MAX_STRUCTURED_ATTEMPTS {
+        let response = ai.query_llama_accepting(&attempt_prompt, model, &options, |text| parse_structured::<T>(text).is_ok()).await?;
+        match parse_structured::<T>(&response.text) {
+            Ok(value) => return Ok((value, response)),
+            Err(e) => {
+                println!("Structured output attempt {} from '{}' was invalid: {}", attempt, model, e);
+                attempt_prompt = format!(
+                    "{}\n\nYour previous reply was rejected: {}. Reply again with only a JSON object in the requested format.",
+                    prompt, e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Model '{}' did not return valid JSON after {} attempts. Last error: {}",
+        model, MAX_STRUCTURED_ATTEMPTS, last_error
+    ))
+}
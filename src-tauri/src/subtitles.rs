@@ -0,0 +1,303 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_capture;
+use crate::database::{Database, TranscriptLine};
+use crate::diarization::speaker_label;
+use crate::transcript_tracks::{self, TrackSelector};
+
+// The usual broadcast limit, which players show without shrinking the text
+const DEFAULT_LINE_LENGTH: usize = 42;
+const MIN_LINE_LENGTH: usize = 10;
+const MAX_LINE_LENGTH: usize = 200;
+
+// More than two lines on screen at once is hard to read in time
+const LINES_PER_CUE: usize = 2;
+
+// Cues shorter than this flash past; they're lengthened into any gap after
+const MIN_CUE_SECONDS: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedTranscript {
+    pub record_id: i64,
+    pub file_path: String,
+    pub format: SubtitleFormat,
+    pub track: TrackSelector,
+    pub secondary_track: Option<TrackSelector>,
+    pub cues: usize,
+}
+
+struct Cue {
+    start: f64,
+    end: f64,
+    speaker: Option<String>,
+    // Already wrapped
+    lines: Vec<String>,
+    // The other track's words said at the same time, for bilingual subtitles
+    secondary: Vec<String>,
+}
+
+pub fn subtitles_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = audio_capture::recordings_dir(app_handle)?.join("subtitles");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn timestamp(seconds: f64, format: SubtitleFormat) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::Vtt => '.',
+    };
+    format!("{:02}:{:02}:{:02}{}{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, separator, millis % 1000)
+}
+
+// Greedy word wrap; a word longer than a line gets a line of its own
+fn wrap(words: &[&str], width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in words {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+// Splits a transcript line into cues of at most two wrapped lines. Each cue
+// is timed by its words when they were timed, otherwise by its share of the
+// line's characters.
+fn line_cues(line: &TranscriptLine, speaker: Option<String>, width: usize) -> Vec<Cue> {
+    let words: Vec<(&str, Option<(f64, f64)>)> = if line.words.is_empty() {
+        line.text.split_whitespace().map(|word| (word, None)).collect()
+    } else {
+        line.words.iter()
+            .map(|word| (word.word.trim(), Some((word.start_seconds, word.end_seconds))))
+            .filter(|(word, _)| !word.is_empty())
+            .collect()
+    };
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let texts: Vec<&str> = words.iter().map(|(word, _)| *word).collect();
+    let total_chars = texts.iter().map(|word| word.chars().count()).sum::<usize>().max(1) as f64;
+    let duration = line.end_seconds - line.start_seconds;
+
+    let mut cues = Vec::new();
+    let mut first_word = 0;
+    let mut chars_before = 0;
+    for lines in wrap(&texts, width).chunks(LINES_PER_CUE) {
+        let count: usize = lines.iter().map(|text| text.split_whitespace().count()).sum();
+        let spoken = &words[first_word..first_word + count];
+        let chars: usize = spoken.iter().map(|(word, _)| word.chars().count()).sum();
+        let (start, end) = match (spoken[0].1, spoken[count - 1].1) {
+            (Some((start, _)), Some((_, end))) => (start, end),
+            _ => (
+                line.start_seconds + duration * chars_before as f64 / total_chars,
+                line.start_seconds + duration * (chars_before + chars) as f64 / total_chars,
+            ),
+        };
+        cues.push(Cue { start, end, speaker: speaker.clone(), lines: lines.to_vec(), secondary: Vec::new() });
+        first_word += count;
+        chars_before += chars;
+    }
+    cues
+}
+
+fn distance(line: &TranscriptLine, time: f64) -> f64 {
+    if time < line.start_seconds {
+        line.start_seconds - time
+    } else {
+        (time - line.end_seconds).max(0.0)
+    }
+}
+
+// The text of the other track's lines that goes with each of these: a line
+// goes with the one it is said in the middle of, or the nearest
+fn align(lines: &[TranscriptLine], other: &[TranscriptLine]) -> Vec<String> {
+    let mut aligned = vec![String::new(); lines.len()];
+    for line in other {
+        let middle = (line.start_seconds + line.end_seconds) / 2.0;
+        let nearest = lines.iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| distance(a, middle).total_cmp(&distance(b, middle)))
+            .map(|(index, _)| index);
+        if let Some(index) = nearest {
+            if !aligned[index].is_empty() {
+                aligned[index].push(' ');
+            }
+            aligned[index].push_str(line.text.trim());
+        }
+    }
+    aligned
+}
+
+// Shares the other track's words out over a line's cues, as many to each as
+// its share of the line's words
+fn attach(cues: &mut [Cue], text: &str, width: usize) {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let counts: Vec<usize> = cues.iter().map(|cue| cue.lines.iter().map(|line| line.split_whitespace().count()).sum()).collect();
+    let total = counts.iter().sum::<usize>().max(1);
+    let mut before = 0;
+    for (cue, count) in cues.iter_mut().zip(counts) {
+        let from = words.len() * before / total;
+        let to = words.len() * (before + count) / total;
+        cue.secondary = wrap(&words[from..to], width);
+        before += count;
+    }
+}
+
+// Cues never overlap, and short ones are held on screen for longer when
+// nothing comes straight after
+fn settle(cues: &mut [Cue]) {
+    for index in 0..cues.len() {
+        let next_start = cues.get(index + 1).map(|next| next.start);
+        let cue = &mut cues[index];
+        if cue.end - cue.start < MIN_CUE_SECONDS {
+            cue.end = cue.start + MIN_CUE_SECONDS;
+        }
+        if let Some(next_start) = next_start {
+            cue.end = cue.end.min(next_start).max(cue.start);
+        }
+    }
+}
+
+fn render(cues: &[Cue], format: SubtitleFormat) -> String {
+    let mut output = String::new();
+    if format == SubtitleFormat::Vtt {
+        output.push_str("WEBVTT\n\n");
+    }
+    for (index, cue) in cues.iter().enumerate() {
+        if format == SubtitleFormat::Srt {
+            output.push_str(&format!("{}\n", index + 1));
+        }
+        output.push_str(&format!("{} --> {}\n", timestamp(cue.start, format), timestamp(cue.end, format)));
+        let text = cue.lines.join("\n");
+        match (&cue.speaker, format) {
+            // A voice span, which players can style or show as a label
+            (Some(speaker), SubtitleFormat::Vtt) => output.push_str(&format!("<v {}>{}\n", escape_vtt(speaker), escape_vtt(&text))),
+            (None, SubtitleFormat::Vtt) => output.push_str(&format!("{}\n", escape_vtt(&text))),
+            (Some(speaker), SubtitleFormat::Srt) => output.push_str(&format!("{}: {}\n", speaker, text)),
+            (None, SubtitleFormat::Srt) => output.push_str(&format!("{}\n", text)),
+        }
+        // In italics below, which both formats show
+        for line in &cue.secondary {
+            match format {
+                SubtitleFormat::Vtt => output.push_str(&format!("<i>{}</i>\n", escape_vtt(line))),
+                SubtitleFormat::Srt => output.push_str(&format!("<i>{}</i>\n", line)),
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Cue text can't hold "<", "&" or "-->" as they are, so they go in as
+// entities
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Added to the file name for tracks other than the recording's own
+fn track_suffix(track: TrackSelector) -> String {
+    match track {
+        TrackSelector::Original => "original".to_string(),
+        TrackSelector::Translation => "en".to_string(),
+        TrackSelector::Clean => "clean".to_string(),
+        TrackSelector::Stored(id) => format!("track{}", id),
+    }
+}
+
+// Writes a recording's timed transcript as SRT or WebVTT subtitles into the
+// subtitles folder. Lines are labelled with the enrolled speaker they were
+// attributed to, or their diarized speaker, and wrapped to
+// `max_line_length` characters, two lines to a cue. `track` picks which of
+// its transcripts, its own by default; a `secondary_track`, e.g. the
+// English translation, goes in italics beneath for bilingual subtitles.
+#[command]
+pub async fn export_transcript(
+    recording_id: i64,
+    format: SubtitleFormat,
+    max_line_length: Option<usize>,
+    track: Option<TrackSelector>,
+    secondary_track: Option<TrackSelector>,
+    app_handle: tauri::AppHandle,
+) -> Result<ExportedTranscript, String> {
+    let width = max_line_length.unwrap_or(DEFAULT_LINE_LENGTH);
+    if !(MIN_LINE_LENGTH..=MAX_LINE_LENGTH).contains(&width) {
+        return Err(format!("max_line_length must be between {} and {}", MIN_LINE_LENGTH, MAX_LINE_LENGTH));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let track = track.unwrap_or(TrackSelector::Original);
+    let lines = transcript_tracks::load(&db, recording_id, track)?.map(|track| track.lines).unwrap_or_default();
+    if lines.is_empty() {
+        return Err(format!("Recording {} has no timed transcript to export; transcribe it first", recording_id));
+    }
+    let secondary = match secondary_track {
+        Some(secondary_track) => align(&lines, &transcript_tracks::require(&db, recording_id, secondary_track)?.lines),
+        None => Vec::new(),
+    };
+    // Attributions are by line of the recording's own transcript
+    let attributions = match track {
+        TrackSelector::Original | TrackSelector::Clean => db.get_speaker_attributions(recording_id).map_err(|e| format!("Database error: {}", e))?,
+        _ => Vec::new(),
+    };
+
+    let mut cues: Vec<Cue> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let speaker = attributions.iter()
+            .find(|attribution| attribution.line_index == index as i64)
+            .map(|attribution| attribution.speaker_name.clone())
+            .or_else(|| line.speaker.map(speaker_label));
+        let mut line_cues = line_cues(line, speaker, width);
+        if let Some(text) = secondary.get(index) {
+            attach(&mut line_cues, text, width);
+        }
+        cues.extend(line_cues);
+    }
+    settle(&mut cues);
+
+    let mut stem = Path::new(&record.file_path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    if track != TrackSelector::Original {
+        stem = format!("{}-{}", stem, track_suffix(track));
+    }
+    if let Some(secondary_track) = secondary_track {
+        stem = format!("{}+{}", stem, track_suffix(secondary_track));
+    }
+    let output_path = subtitles_dir(&app_handle)
+        .map_err(|e| format!("Export error: {}", e))?
+        .join(format!("{}.{}", stem, format.extension()));
+    std::fs::write(&output_path, render(&cues, format)).map_err(|e| format!("Export error: {}", e))?;
+
+    Ok(ExportedTranscript {
+        record_id: recording_id,
+        file_path: output_path.to_string_lossy().to_string(),
+        format,
+        track,
+        secondary_track,
+        cues: cues.len(),
+    })
+}
@@ -0,0 +1,197 @@
+use tauri::{command, State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use crate::ai_models::AdvancedAI;
+use crate::database::Database;
+
+const SYSTEM_PROMPTS_KEY: &str = "system_prompts";
+
+// The built-in profile always exists and can't be deleted, only edited
+pub const DEFAULT_PROFILE: &str = "dwight";
+
+// Dwight's persona, used until the user edits or switches profiles
+const DEFAULT_SYSTEM_PROMPT: &str = "You are Dwight, an advanced AI assistant specialized in audio analysis, surveillance, and security systems. \
+    You are brilliant, analytical, loyal, and technically proficient. You help users with:\n\
+    - Audio transcription and analysis\n\
+    - Sound pattern recognition\n\
+    - Security monitoring and alerts\n\
+    - Forensic audio investigation\n\
+    - Real-time audio processing";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemPrompt {
+    pub profile: String,
+    pub prompt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemPromptProfiles {
+    pub active: String,
+    pub profiles: BTreeMap<String, String>,
+}
+
+impl Default for SystemPromptProfiles {
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE.to_string(), DEFAULT_SYSTEM_PROMPT.to_string());
+        SystemPromptProfiles {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles,
+        }
+    }
+}
+
+impl SystemPromptProfiles {
+    fn active_prompt(&self) -> SystemPrompt {
+        match self.profiles.get(&self.active) {
+            Some(prompt) => SystemPrompt {
+                profile: self.active.clone(),
+                prompt: prompt.clone(),
+            },
+            None => SystemPrompt {
+                profile: DEFAULT_PROFILE.to_string(),
+                prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+            },
+        }
+    }
+}
+
+// Named persona prompts, persisted in app settings. Every command that talks
+// to the model as Dwight reads the active one.
+pub struct SystemPrompts {
+    profiles: RwLock<SystemPromptProfiles>,
+}
+
+impl SystemPrompts {
+    pub fn new() -> Self {
+        SystemPrompts {
+            profiles: RwLock::new(SystemPromptProfiles::default()),
+        }
+    }
+
+    pub fn load(&self, db: &Database) {
+        match db.get_setting(SYSTEM_PROMPTS_KEY) {
+            Ok(Some(json)) => match serde_json::from_str::<SystemPromptProfiles>(&json) {
+                Ok(mut saved) => {
+                    saved.profiles.entry(DEFAULT_PROFILE.to_string())
+                        .or_insert_with(|| DEFAULT_SYSTEM_PROMPT.to_string());
+                    if let Ok(mut profiles) = self.profiles.write() {
+                        *profiles = saved;
+                    }
+                }
+                Err(e) => eprintln!("Ignoring invalid system prompts: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load system prompts: {}", e),
+        }
+    }
+
+    pub fn active(&self) -> SystemPrompt {
+        self.profiles.read()
+            .map(|profiles| profiles.active_prompt())
+            .unwrap_or_else(|_| SystemPromptProfiles::default().active_prompt())
+    }
+
+    pub fn snapshot(&self) -> SystemPromptProfiles {
+        self.profiles.read()
+            .map(|profiles| profiles.clone())
+            .unwrap_or_default()
+    }
+
+    // Applies `change` to a copy, persists it, and only then makes it current
+    fn update<F>(&self, db: &Database, change: F) -> Result<SystemPromptProfiles, String>
+    where
+        F: FnOnce(&mut SystemPromptProfiles) -> Result<(), String>,
+    {
+        let mut profiles = self.profiles.write().map_err(|_| "System prompts unavailable".to_string())?;
+        let mut updated = profiles.clone();
+        change(&mut updated)?;
+
+        let json = serde_json::to_string(&updated).map_err(|e| format!("Failed to save system prompts: {}", e))?;
+        db.set_setting(SYSTEM_PROMPTS_KEY, &json).map_err(|e| format!("Database error: {}", e))?;
+
+        *profiles = updated.clone();
+        Ok(updated)
+    }
+}
+
+fn open_db(app_handle: &tauri::AppHandle) -> Result<Database, String> {
+    Database::new(app_handle).map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn get_system_prompt(ai: State<'_, AdvancedAI>) -> Result<SystemPrompt, String> {
+    Ok(ai.system_prompts().active())
+}
+
+// Edits a profile's prompt (the active one by default), creating it if needed
+#[command]
+pub async fn set_system_prompt(
+    prompt: String,
+    profile: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<SystemPrompt, String> {
+    if prompt.trim().is_empty() {
+        return Err("System prompt must not be empty".to_string());
+    }
+    if profile.as_ref().is_some_and(|name| name.trim().is_empty()) {
+        return Err("Profile name must not be empty".to_string());
+    }
+
+    let db = open_db(&app_handle)?;
+    let updated = ai.system_prompts().update(&db, |profiles| {
+        let name = profile.unwrap_or_else(|| profiles.active.clone());
+        profiles.profiles.insert(name, prompt);
+        Ok(())
+    })?;
+
+    Ok(updated.active_prompt())
+}
+
+#[command]
+pub async fn list_system_prompt_profiles(ai: State<'_, AdvancedAI>) -> Result<SystemPromptProfiles, String> {
+    Ok(ai.system_prompts().snapshot())
+}
+
+#[command]
+pub async fn switch_system_prompt_profile(
+    profile: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<SystemPrompt, String> {
+    let db = open_db(&app_handle)?;
+    let updated = ai.system_prompts().update(&db, |profiles| {
+        if !profiles.profiles.contains_key(&profile) {
+            return Err(format!("System prompt profile '{}' not found", profile));
+        }
+        profiles.active = profile;
+        Ok(())
+    })?;
+
+    Ok(updated.active_prompt())
+}
+
+// Deleting the active profile switches back to the built-in one
+#[command]
+pub async fn delete_system_prompt_profile(
+    profile: String,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<SystemPromptProfiles, String> {
+    if profile == DEFAULT_PROFILE {
+        return Err(format!("The built-in '{}' profile can't be deleted", DEFAULT_PROFILE));
+    }
+
+    let db = open_db(&app_handle)?;
+    ai.system_prompts().update(&db, |profiles| {
+        if profiles.profiles.remove(&profile).is_none() {
+            return Err(format!("System prompt profile '{}' not found", profile));
+        }
+        if profiles.active == profile {
+            profiles.active = DEFAULT_PROFILE.to_string();
+        }
+        Ok(())
+    })
+}
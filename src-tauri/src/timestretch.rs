@@ -0,0 +1,162 @@
+use std::f64::consts::PI;
+use crate::audio_capture::StreamFormat;
+
+// Speeds the stretcher keeps sounding natural at
+pub const MIN_STRETCH_SPEED: f32 = 0.5;
+pub const MAX_STRETCH_SPEED: f32 = 2.0;
+
+// Segments about the length of a pitch period or three of speech, laid down
+// at half-segment steps
+const SEGMENT_SECONDS: f64 = 0.03;
+
+// How far a segment may move from where the speed puts it, to line up
+// with the one before; a little over the longest voice period
+const TOLERANCE_SECONDS: f64 = 0.012;
+
+// Every other sample is enough to line the waveforms up
+const SEARCH_STEP: usize = 2;
+
+// Changes the speed of audio without changing its pitch with WSOLA: short
+// windowed segments are copied at the new rate, each moved a little so its
+// waveform carries on from the last, and overlapped back together
+pub struct TimeStretcher {
+    channels: usize,
+    speed: f64,
+    segment: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    // Interleaved input not yet passed, and the frame it starts at
+    input: Vec<f32>,
+    input_start: usize,
+    // Where the speed places the next segment, in input frames
+    next_position: f64,
+    // Where the last segment would have carried on, the shape the next matches
+    continuation: Option<usize>,
+    // Overlapped output, the first hop of it finished
+    output: Vec<f32>,
+    input_frames: u64,
+    output_frames: u64,
+}
+
+impl TimeStretcher {
+    pub fn new(format: StreamFormat, speed: f32) -> Self {
+        let sample_rate = format.sample_rate.max(1) as f64;
+        let channels = format.channels.max(1) as usize;
+        let segment = ((sample_rate * SEGMENT_SECONDS) as usize / 2 * 2).max(4);
+        // A periodic Hann window, whose halves add up to one when overlapped
+        let window = (0..segment).map(|index| (0.5 - 0.5 * (2.0 * PI * index as f64 / segment as f64).cos()) as f32).collect();
+        TimeStretcher {
+            channels,
+            speed: speed.clamp(MIN_STRETCH_SPEED, MAX_STRETCH_SPEED) as f64,
+            segment,
+            hop: segment / 2,
+            tolerance: (sample_rate * TOLERANCE_SECONDS) as usize,
+            window,
+            input: Vec::new(),
+            input_start: 0,
+            next_position: 0.0,
+            continuation: None,
+            output: vec![0.0; segment * channels],
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    // Mono sample of the buffered input at an absolute frame
+    fn mono(&self, frame: usize) -> f32 {
+        let start = (frame - self.input_start) * self.channels;
+        self.input[start..start + self.channels].iter().sum::<f32>() / self.channels as f32
+    }
+
+    // The start near `target` whose overlap best matches the continuation
+    fn best_start(&self, target: usize, continuation: usize) -> usize {
+        let overlap = self.segment - self.hop;
+        let template: Vec<f32> = (0..overlap).step_by(SEARCH_STEP).map(|index| self.mono(continuation + index)).collect();
+        let first = target.saturating_sub(self.tolerance).max(self.input_start);
+        let mut best = (target, f32::MIN);
+        for start in first..=target + self.tolerance {
+            let (mut correlation, mut energy) = (0.0f32, 0.0f32);
+            for (index, &expected) in template.iter().enumerate() {
+                let sample = self.mono(start + index * SEARCH_STEP);
+                correlation += expected * sample;
+                energy += sample * sample;
+            }
+            let score = correlation / energy.sqrt().max(1e-9);
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+
+    fn input_end(&self) -> usize {
+        self.input_start + self.input.len() / self.channels
+    }
+
+    // Lays down every segment the buffered input allows
+    fn stretch(&mut self, output: &mut Vec<f32>) {
+        loop {
+            let target = self.next_position.round() as usize;
+            let needed = match self.continuation {
+                Some(continuation) => (target + self.tolerance).max(continuation) + self.segment,
+                None => target + self.segment,
+            };
+            if needed > self.input_end() {
+                break;
+            }
+            let start = match self.continuation {
+                Some(continuation) => self.best_start(target, continuation),
+                None => target,
+            };
+
+            let offset = (start - self.input_start) * self.channels;
+            for (index, (sum, &sample)) in self.output.iter_mut().zip(&self.input[offset..offset + self.segment * self.channels]).enumerate() {
+                *sum += sample * self.window[index / self.channels];
+            }
+            let finished = self.hop * self.channels;
+            output.extend(self.output.drain(..finished));
+            self.output.resize(self.segment * self.channels, 0.0);
+            self.output_frames += self.hop as u64;
+
+            self.continuation = Some(start + self.hop);
+            self.next_position += self.hop as f64 * self.speed;
+            let keep_from = (self.next_position.round() as usize).saturating_sub(self.tolerance).min(start + self.hop);
+            if keep_from > self.input_start {
+                self.input.drain(..(keep_from - self.input_start) * self.channels);
+                self.input_start = keep_from;
+            }
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        self.input_frames += (samples.len() / self.channels) as u64;
+        let mut output = Vec::new();
+        self.stretch(&mut output);
+        output
+    }
+
+    // Stretches what is still buffered at the end of a stream, so the
+    // output is as long as the input at the new speed
+    pub fn flush(&mut self) -> Vec<f32> {
+        let expected = (self.input_frames as f64 / self.speed).round() as u64;
+        // Silence past the end lets the last segments be laid down
+        self.input.resize(self.input.len() + (self.segment + self.tolerance * 2) * self.channels, 0.0);
+        let mut output = Vec::new();
+        self.stretch(&mut output);
+        output.append(&mut self.output);
+        let total = self.output_frames + self.segment as u64;
+        let excess = total.saturating_sub(expected) as usize * self.channels;
+        output.truncate(output.len().saturating_sub(excess));
+        self.output_frames = expected.min(total);
+        self.output = vec![0.0; self.segment * self.channels];
+        output
+    }
+
+    // Input time held back that the output hasn't reached yet
+    pub fn delay_seconds(&self, sample_rate: u32) -> f64 {
+        let passed = self.output_frames as f64 * self.speed;
+        (self.input_frames as f64 - passed).max(0.0) / sample_rate.max(1) as f64
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+// Backends that report token usage (Ollama eval counts, OpenAI usage, llama.cpp)
+// are always preferred. This is the fallback estimate: cl100k_base is close to
+// the Llama/Mistral BPE vocabularies and far better than counting words.
+fn bpe() -> Option<&'static CoreBPE> {
+    static BPE: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    BPE.get_or_init(|| match tiktoken_rs::cl100k_base() {
+        Ok(bpe) => Some(bpe),
+        Err(e) => {
+            eprintln!("Failed to load tokenizer, falling back to word counts: {}", e);
+            None
+        }
+    })
+    .as_ref()
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    match bpe() {
+        Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+        None => text.split_whitespace().count(),
+    }
+}
+
+// Keeps the first `max_tokens` tokens of `text`
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    match bpe() {
+        Some(bpe) => {
+            let tokens = bpe.encode_with_special_tokens(text);
+            if tokens.len() <= max_tokens {
+                return text.to_string();
+            }
+            // A cut inside a multi-byte character fails to decode; back off a token at a time
+            (0..max_tokens.min(4))
+                .find_map(|back_off| bpe.decode(tokens[..max_tokens - back_off].to_vec()).ok())
+                .unwrap_or_default()
+        }
+        None => text.split_whitespace().take(max_tokens).collect::<Vec<_>>().join(" "),
+    }
+}
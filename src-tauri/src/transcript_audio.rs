@@ -0,0 +1,123 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::audio_file;
+use crate::clips;
+use crate::database::Database;
+
+// Whisper tends to start a line just after its first sound, so a little
+// before is played too
+const DEFAULT_PADDING_SECONDS: f64 = 0.2;
+const MAX_PADDING_SECONDS: f64 = 5.0;
+
+// Everything needed to play one transcript line: where it is in the
+// recording, in seconds, frames and (for WAV files) bytes, and a file of
+// just that part, ready to hand to an audio element
+#[derive(Debug, Clone, Serialize)]
+pub struct LineAudio {
+    pub record_id: i64,
+    pub line_index: i64,
+    pub text: String,
+    // The line as transcribed
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    // With the padding, within the recording
+    pub clip_start_seconds: f64,
+    pub clip_end_seconds: f64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Frame offsets of the padded part, counting from the recording's first
+    pub start_frame: u64,
+    pub end_frame: u64,
+    // Byte offsets of those frames in the recording; None unless it's a WAV
+    pub start_byte: Option<u64>,
+    pub end_byte: Option<u64>,
+    pub file_path: String,
+    // The padded part on its own as a WAV
+    pub clip_path: String,
+    // Neighbouring lines, for stepping through the transcript
+    pub previous_line: Option<i64>,
+    pub next_line: Option<i64>,
+}
+
+fn line_clips_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = clips::clips_dir(app_handle)?.join("lines");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// A clip made before the recording last changed, e.g. by appending, may not
+// hold the same audio
+fn is_fresh(clip: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    matches!((modified(clip), modified(source)), (Some(clip), Some(source)) if clip >= source)
+}
+
+// Where in the recording one of its transcript lines is, with the audio of
+// just that line cut out ahead of time, so clicking a sentence can play it
+// straight away. The clip is kept and reused while the line and recording
+// stay the same.
+#[command]
+pub async fn get_line_audio(
+    recording_id: i64,
+    line_index: i64,
+    padding_seconds: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<LineAudio, String> {
+    let padding = padding_seconds.unwrap_or(DEFAULT_PADDING_SECONDS);
+    if !(0.0..=MAX_PADDING_SECONDS).contains(&padding) {
+        return Err(format!("padding_seconds must be between 0 and {}", MAX_PADDING_SECONDS));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    let line = usize::try_from(line_index)
+        .ok()
+        .and_then(|index| lines.get(index))
+        .ok_or_else(|| format!("Recording {} has no transcript line {}", recording_id, line_index))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (format, duration) = audio_file::audio_info(&source).map_err(|e| format!("Audio error: {}", e))?;
+    let clip_start = (line.start_seconds - padding).max(0.0);
+    let clip_end = (line.end_seconds + padding).min(duration);
+    if clip_start >= clip_end {
+        return Err(format!("Transcript line {} is past the end of recording {}'s audio", line_index, recording_id));
+    }
+    let start_frame = (clip_start * format.sample_rate as f64).round() as u64;
+    let end_frame = (clip_end * format.sample_rate as f64).round() as u64;
+    let layout = audio_file::wav_layout(&source).map_err(|e| format!("Audio error: {}", e))?;
+
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let name = format!("{}-line{}-{}-{}.wav", stem, line_index, start_frame, end_frame);
+    let clip_path = line_clips_dir(&app_handle).map_err(|e| format!("Audio error: {}", e))?.join(name);
+    if !is_fresh(&clip_path, &source) {
+        let (source, written) = (source.clone(), clip_path.clone());
+        tokio::task::spawn_blocking(move || clips::write_wav(&source, &written, clip_start, clip_end, 1.0, false))
+            .await
+            .map_err(|e| format!("Audio error: {}", e))?
+            .map_err(|e| format!("Audio error: {}", e))?;
+    }
+
+    Ok(LineAudio {
+        record_id: recording_id,
+        line_index,
+        text: line.text.clone(),
+        start_seconds: line.start_seconds,
+        end_seconds: line.end_seconds,
+        clip_start_seconds: clip_start,
+        clip_end_seconds: clip_end,
+        sample_rate: format.sample_rate,
+        channels: format.channels,
+        start_frame,
+        end_frame,
+        start_byte: layout.map(|(offset, frame_bytes)| offset + start_frame * frame_bytes),
+        end_byte: layout.map(|(offset, frame_bytes)| offset + end_frame * frame_bytes),
+        file_path: record.file_path,
+        clip_path: clip_path.to_string_lossy().to_string(),
+        previous_line: (line_index > 0).then(|| line_index - 1),
+        next_line: ((line_index as usize) + 1 < lines.len()).then(|| line_index + 1),
+    })
+}
@@ -0,0 +1,330 @@
+use regex::Regex;
+use tauri::{command, Manager};
+use crate::audio_capture::{AudioCapture, CaptureSettings};
+use crate::database::{CleanTranscript, Database, ReplacementRule, TranscriptLine};
+
+// Hesitations with no meaning of their own. "Uh-huh" and "mm-hmm" are left,
+// since they answer yes.
+const FILLERS: [&str; 12] = ["um", "umm", "uh", "uhh", "er", "erm", "ah", "ahh", "hmm", "hm", "mm", "mmm"];
+
+// Masked only as whole words, so "Pissarro" and "Scunthorpe" are left; each
+// inflection that should be masked is listed
+const PROFANITY: &[&str] = &[
+    "fuck", "fucks", "fucked", "fucking", "fuckin", "fucker", "fuckers",
+    "motherfucker", "motherfuckers", "motherfucking",
+    "shit", "shits", "shitty", "shitting", "bullshit",
+    "bitch", "bitches", "bitching", "bastard", "bastards",
+    "asshole", "assholes", "arsehole", "arseholes", "cunt", "cunts", "dickhead", "dickheads",
+    "piss", "pissed", "pissing", "wanker", "wankers", "twat", "twats", "bollocks",
+    "slut", "sluts", "whore", "whores", "goddamn", "goddamned", "damn", "damned", "dammit",
+];
+
+// What is stripped off a word before it is checked against the lists
+const WORD_PUNCTUATION: &[char] = &['.', ',', '?', '!', ';', ':', '"', '\'', '(', ')', '…'];
+
+const SENTENCE_ENDS: [char; 4] = ['.', '?', '!', '…'];
+
+// A pause at least this long after a word ends its sentence
+const SENTENCE_PAUSE_SECONDS: f64 = 0.7;
+
+// How far ahead a word of the text is looked for among the timed words, past
+// ones the text doesn't have
+const ALIGN_WORDS: usize = 4;
+
+const MAX_RULE_LENGTH: usize = 200;
+
+// The stages that are on, from the capture settings
+struct Stages {
+    punctuation: bool,
+    fillers: bool,
+    profanity: bool,
+}
+
+impl Stages {
+    fn from_settings(settings: &CaptureSettings) -> Self {
+        Stages {
+            punctuation: settings.restore_punctuation,
+            fillers: settings.remove_filler_words,
+            profanity: settings.mask_profanity,
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.punctuation || self.fillers || self.profanity
+    }
+}
+
+fn ends_sentence(word: &str) -> bool {
+    word.ends_with(SENTENCE_ENDS)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Drops filler words. A sentence-ending mark on one moves to the word
+// before, and a sentence a filler started starts with the next word.
+fn remove_fillers(words: Vec<String>) -> Vec<String> {
+    let mut kept: Vec<String> = Vec::new();
+    let mut capitalize_next = false;
+    for word in words {
+        let core = word.trim_matches(WORD_PUNCTUATION).to_lowercase();
+        if FILLERS.contains(&core.as_str()) {
+            let starts_sentence = kept.last().is_none_or(|last| ends_sentence(last));
+            capitalize_next |= starts_sentence && word.starts_with(char::is_uppercase);
+            if let Some(mark) = word.chars().last().filter(|mark| SENTENCE_ENDS.contains(mark)) {
+                if let Some(last) = kept.last_mut().filter(|last| !ends_sentence(last)) {
+                    last.push(mark);
+                }
+            }
+            continue;
+        }
+        kept.push(if capitalize_next { capitalize(&word) } else { word });
+        capitalize_next = false;
+    }
+    kept
+}
+
+// Keeps the first letter, so the word can still be told, and stars the rest
+fn mask_profanity(words: Vec<String>) -> Vec<String> {
+    words.into_iter()
+        .map(|word| {
+            let core = word.trim_matches(WORD_PUNCTUATION);
+            let lower = core.to_lowercase();
+            if !PROFANITY.contains(&lower.as_str()) {
+                return word;
+            }
+            let masked: String = core.chars().enumerate().map(|(index, c)| if index == 0 { c } else { '*' }).collect();
+            word.replacen(core, &masked, 1)
+        })
+        .collect()
+}
+
+// Lowercase, without punctuation, for lining the text up with its timed words
+fn match_form(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric() || *c == '\'').flat_map(char::to_lowercase).collect()
+}
+
+// Each line's words with whether a sentence-length pause comes after, from
+// the word timings when the line has them and from the gap to the next line
+// at its end
+fn timed_words(lines: &[TranscriptLine]) -> Vec<Vec<(String, bool)>> {
+    lines.iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let next_start = lines.get(index + 1).map(|next| next.start_seconds);
+            let paused = |end: f64, next: Option<f64>| next.is_some_and(|next| next - end >= SENTENCE_PAUSE_SECONDS);
+            let words: Vec<(String, bool)> = match line.words.is_empty() {
+                true => {
+                    let words: Vec<&str> = line.text.split_whitespace().collect();
+                    words.iter()
+                        .enumerate()
+                        .map(|(position, word)| (match_form(word), position + 1 == words.len() && paused(line.end_seconds, next_start)))
+                        .collect()
+                }
+                false => line.words.iter()
+                    .enumerate()
+                    .map(|(position, word)| {
+                        let next = line.words.get(position + 1).map(|next| next.start_seconds).or(next_start);
+                        (match_form(&word.word), paused(word.end_seconds, next))
+                    })
+                    .collect(),
+            };
+            words.into_iter().filter(|(form, _)| !form.is_empty()).collect()
+        })
+        .collect()
+}
+
+// Whether a pause ends a sentence after each of `words`, walking them
+// alongside the timed words from `cursor` on. Words the timing doesn't have,
+// such as speaker labels, have none.
+fn pauses_after(words: &[String], timed: &[(String, bool)], cursor: &mut usize) -> Vec<bool> {
+    words.iter()
+        .map(|word| {
+            let form = match_form(word);
+            if form.is_empty() {
+                return false;
+            }
+            match timed[*cursor..].iter().take(ALIGN_WORDS).position(|(timed, _)| *timed == form) {
+                Some(offset) => {
+                    *cursor += offset + 1;
+                    timed[*cursor - 1].1
+                }
+                None => false,
+            }
+        })
+        .collect()
+}
+
+// Without a punctuation model this goes by rule and by timing: a pause long
+// enough after a word ends the sentence there, sentences start with a
+// capital, "i" is "I", and text that stops without a mark ends with a full
+// stop
+fn restore_punctuation(words: Vec<String>, pauses: &[bool]) -> Vec<String> {
+    let count = words.len();
+    let mut sentence_start = true;
+    words.into_iter()
+        .enumerate()
+        .map(|(index, word)| {
+            let lower = word.trim_matches(WORD_PUNCTUATION).to_lowercase();
+            let mut word = if sentence_start || lower == "i" || lower.starts_with("i'") { capitalize(&word) } else { word };
+            let paused = pauses.get(index).copied().unwrap_or(false);
+            if (index == count - 1 || paused) && word.ends_with(|c: char| c.is_alphanumeric()) {
+                word.push('.');
+            }
+            sentence_start = ends_sentence(&word);
+            word
+        })
+        .collect()
+}
+
+fn compile(rule: &ReplacementRule) -> Option<Regex> {
+    let mut pattern = regex::escape(&rule.find);
+    if rule.whole_word {
+        pattern = format!(r"\b{}\b", pattern);
+    }
+    if !rule.case_sensitive {
+        pattern = format!("(?i){}", pattern);
+    }
+    Regex::new(&pattern).ok()
+}
+
+// One line or paragraph of transcript through each stage that is on, then
+// the custom rules in the order they were added. Punctuation goes first, so
+// a mark it puts on a filler moves to the word before with the filler.
+fn clean_text(text: &str, pauses: impl FnOnce(&[String]) -> Vec<bool>, stages: &Stages, rules: &[(Regex, String)]) -> String {
+    let mut words: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+    if stages.punctuation && !words.is_empty() {
+        let pauses = pauses(&words);
+        words = restore_punctuation(words, &pauses);
+    }
+    if stages.fillers {
+        words = remove_fillers(words);
+    }
+    if stages.profanity {
+        words = mask_profanity(words);
+    }
+    let mut text = words.join(" ");
+    for (pattern, replacement) in rules {
+        text = pattern.replace_all(&text, regex::NoExpand(replacement)).into_owned();
+    }
+    text
+}
+
+// Derives the clean transcript of a recording from its verbatim one, with the
+// stages in the settings and the custom rules. The verbatim transcript and
+// lines are never changed.
+pub fn clean(app_handle: &tauri::AppHandle, recording_id: i64) -> Result<CleanTranscript, String> {
+    let stages = Stages::from_settings(&app_handle.state::<AudioCapture>().get_settings());
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let transcript = record.transcript.unwrap_or_default();
+    if transcript.trim().is_empty() {
+        return Err(format!("Recording {} has no transcript to clean; transcribe it first", recording_id));
+    }
+    let rules: Vec<(Regex, String)> = db.get_replacement_rules()
+        .map_err(|e| format!("Database error: {}", e))?
+        .iter()
+        .filter_map(|rule| Some((compile(rule)?, rule.replacement.clone())))
+        .collect();
+
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    let timed = timed_words(&lines);
+    let all_timed: Vec<(String, bool)> = timed.iter().flatten().cloned().collect();
+    // Labelled transcripts have a turn to a line, which stays that way
+    let mut cursor = 0;
+    let text = transcript.lines()
+        .map(|paragraph| clean_text(paragraph, |words| pauses_after(words, &all_timed, &mut cursor), &stages, &rules))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let lines = lines.iter()
+        .zip(&timed)
+        .map(|(line, timed)| clean_text(&line.text, |words| pauses_after(words, timed, &mut 0), &stages, &rules))
+        .collect();
+    let cleaned = CleanTranscript { record_id: recording_id, text, lines, created_at: String::new() };
+    db.save_clean_transcript(&cleaned).map_err(|e| format!("Database error: {}", e))?;
+    db.get_clean_transcript(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))
+}
+
+// Brings the clean transcript in line after the verbatim one changes: derived
+// again when a stage is on or there are rules, dropped otherwise or when
+// nothing was said
+pub fn refresh(app_handle: &tauri::AppHandle, recording_id: i64) -> Result<(), String> {
+    let stages = Stages::from_settings(&app_handle.state::<AudioCapture>().get_settings());
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let has_rules = !db.get_replacement_rules().map_err(|e| format!("Database error: {}", e))?.is_empty();
+    let transcribed = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .and_then(|record| record.transcript)
+        .is_some_and(|transcript| !transcript.trim().is_empty());
+    if transcribed && (stages.any() || has_rules) {
+        clean(app_handle, recording_id)?;
+    } else {
+        db.delete_clean_transcript(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    }
+    Ok(())
+}
+
+// Cleans a recording's transcript again with the current settings and rules,
+// e.g. after changing them
+#[command]
+pub async fn clean_transcript(recording_id: i64, app_handle: tauri::AppHandle) -> Result<CleanTranscript, String> {
+    clean(&app_handle, recording_id)
+}
+
+// The clean transcript, if one was derived; the verbatim one is the
+// recording's own
+#[command]
+pub async fn get_clean_transcript(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Option<CleanTranscript>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_clean_transcript(recording_id).map_err(|e| format!("Database error: {}", e))
+}
+
+// Adds a find/replace rule for clean transcripts, e.g. a name Whisper keeps
+// getting wrong. Applies to transcripts cleaned after.
+#[command]
+pub async fn add_replacement_rule(
+    find: String,
+    replacement: String,
+    whole_word: Option<bool>,
+    case_sensitive: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<ReplacementRule, String> {
+    if find.trim().is_empty() {
+        return Err("A replacement rule needs something to find".to_string());
+    }
+    if find.chars().count() > MAX_RULE_LENGTH || replacement.chars().count() > MAX_RULE_LENGTH {
+        return Err(format!("Replacement rules can be at most {} characters each way", MAX_RULE_LENGTH));
+    }
+    let mut rule = ReplacementRule {
+        id: None,
+        find,
+        replacement,
+        whole_word: whole_word.unwrap_or(true),
+        case_sensitive: case_sensitive.unwrap_or(false),
+        created_at: String::new(),
+    };
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    rule.id = Some(db.save_replacement_rule(&rule).map_err(|e| format!("Database error: {}", e))?);
+    Ok(rule)
+}
+
+#[command]
+pub async fn list_replacement_rules(app_handle: tauri::AppHandle) -> Result<Vec<ReplacementRule>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_replacement_rules().map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn remove_replacement_rule(rule_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.delete_replacement_rule(rule_id).map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,334 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{command, Emitter, Manager};
+use tokio::sync::Notify;
+use crate::ai_models::AdvancedAI;
+use crate::database::{AudioRecord, Database, TranscriptChange};
+use crate::document_ingest::chunk_text;
+use crate::vector_store::{self, NewChunk, VectorStore};
+use crate::whisper::TranscriptionSegment;
+
+// Transcripts get their own index so case files and recordings don't crowd each other out
+pub const TRANSCRIPTS_INDEX: &str = "transcripts";
+
+pub const TRANSCRIPT_INDEX_PROGRESS_EVENT: &str = "dwight://transcript-index-progress";
+
+const CHANGE_UPSERT: &str = "upsert";
+const CHANGE_DELETE: &str = "delete";
+
+// Wait before retrying changes that failed, e.g. while Ollama is down
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TranscriptIndexProgress {
+    pub recording_id: i64,
+    pub change: String,
+    // "started", "embedding", "completed" or "failed"
+    pub stage: String,
+    // Changes still queued behind this one
+    pub pending: usize,
+    pub chunks_total: usize,
+    pub chunks_embedded: usize,
+    pub chunks_to_embed: usize,
+    // Chunks whose text didn't change and kept their vectors
+    pub chunks_kept: usize,
+    pub chunks_removed: usize,
+    pub error: Option<String>,
+}
+
+// What one re-index changed in the vector store
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexUpdate {
+    pub chunks: usize,
+    pub embedded: usize,
+    pub kept: usize,
+    pub removed: usize,
+}
+
+pub fn recording_source(recording_id: i64) -> String {
+    format!("recording:{}", recording_id)
+}
+
+pub fn format_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60)
+}
+
+// The header goes into the chunk text itself so the model can say which
+// recording and when, not just what was said
+fn chunk_header(record: &AudioRecord, span: Option<(f64, f64)>) -> String {
+    let mut header = format!("Recording #{} \"{}\" (recorded {})", record.id.unwrap_or_default(), record.title, record.created_at);
+    if let Some((start, end)) = span {
+        header.push_str(&format!(", {} to {}", format_timestamp(start), format_timestamp(end)));
+    }
+    header.push_str(":\n");
+    header
+}
+
+// Groups consecutive segments into chunks of about `chunk_size` characters,
+// each starting with the last segment of the chunk before it. Without
+// segments the plain transcript is chunked and carries no timestamps.
+pub fn transcript_chunks(record: &AudioRecord, segments: &[TranscriptionSegment], chunk_size: usize, overlap: usize) -> Vec<NewChunk> {
+    let recording_id = i64::from(record.id.unwrap_or_default());
+    let mut chunks = Vec::new();
+
+    if segments.is_empty() {
+        let transcript = record.transcript.as_deref().unwrap_or("");
+        for (start, end) in chunk_text(transcript, chunk_size, overlap) {
+            chunks.push(NewChunk {
+                text: format!("{}{}", chunk_header(record, None), &transcript[start..end]),
+                source: recording_source(recording_id),
+                metadata: serde_json::json!({ "recording_id": recording_id, "title": record.title }),
+            });
+        }
+        return chunks;
+    }
+
+    let mut first = 0;
+    while first < segments.len() {
+        let mut last = first;
+        let mut length = segments[first].text.len();
+        while last + 1 < segments.len() && length + segments[last + 1].text.len() <= chunk_size {
+            last += 1;
+            length += segments[last].text.len() + 1;
+        }
+
+        let group = &segments[first..=last];
+        let text: Vec<&str> = group.iter().map(|segment| segment.text.trim()).collect();
+        let (start, end) = (group[0].start, group[group.len() - 1].end);
+        chunks.push(NewChunk {
+            text: format!("{}{}", chunk_header(record, Some((start, end))), text.join(" ")),
+            source: recording_source(recording_id),
+            metadata: serde_json::json!({
+                "recording_id": recording_id,
+                "title": record.title,
+                "start": start,
+                "end": end,
+            }),
+        });
+
+        // Overlap by one segment, unless that segment was the whole chunk
+        first = if overlap > 0 && last > first { last } else { last + 1 };
+    }
+    chunks
+}
+
+// Brings a recording's chunks in line with its transcript. Chunks whose text
+// is unchanged keep their vectors; only new text is embedded and chunks that
+// no longer match are removed. `on_embedded` gets (embedded, to embed) after
+// each embedding batch.
+pub async fn update_recording(
+    ai: &AdvancedAI,
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    segments: &[TranscriptionSegment],
+    mut on_embedded: impl FnMut(usize, usize),
+) -> Result<IndexUpdate> {
+    let Some(record) = Database::new(app_handle)?.get_audio_record(recording_id)? else {
+        // Deleted since the change was queued
+        return remove_recording(app_handle, recording_id);
+    };
+
+    let settings = ai.get_rag_settings();
+    let chunks = transcript_chunks(&record, segments, settings.chunk_size, settings.chunk_overlap);
+    let source = recording_source(recording_id);
+
+    let existing = {
+        let store = VectorStore::new(app_handle)?;
+        store.ensure_index(TRANSCRIPTS_INDEX)?;
+        store.source_texts(TRANSCRIPTS_INDEX, &source)?
+    };
+    let mut unmatched: HashMap<String, Vec<i64>> = HashMap::new();
+    for (id, text) in existing {
+        unmatched.entry(text).or_default().push(id);
+    }
+
+    let total = chunks.len();
+    let mut added = Vec::new();
+    for chunk in chunks {
+        if unmatched.get_mut(&chunk.text).and_then(Vec::pop).is_none() {
+            added.push(chunk);
+        }
+    }
+    let stale: Vec<i64> = unmatched.into_values().flatten().collect();
+
+    let embedded = if added.is_empty() {
+        None
+    } else {
+        let texts: Vec<String> = added.iter().map(|chunk| chunk.text.clone()).collect();
+        Some(vector_store::embed_in_batches_with_progress(ai, &texts, |done| on_embedded(done, texts.len())).await?)
+    };
+
+    let mut store = VectorStore::new(app_handle)?;
+    let removed = store.delete(&stale)?;
+    if let Some((model, embeddings)) = embedded {
+        store.insert(TRANSCRIPTS_INDEX, &model, &added, &embeddings)?;
+    }
+    Ok(IndexUpdate {
+        chunks: total,
+        embedded: added.len(),
+        kept: total - added.len(),
+        removed,
+    })
+}
+
+pub fn remove_recording(app_handle: &tauri::AppHandle, recording_id: i64) -> Result<IndexUpdate> {
+    let mut store = VectorStore::new(app_handle)?;
+    store.ensure_index(TRANSCRIPTS_INDEX)?;
+    let removed = store.delete_source(TRANSCRIPTS_INDEX, &recording_source(recording_id))?;
+    Ok(IndexUpdate { removed, ..Default::default() })
+}
+
+// Wakes the background indexer; the changes themselves are queued in the
+// database so they survive a restart
+pub struct TranscriptIndexer {
+    wake: Notify,
+}
+
+impl TranscriptIndexer {
+    pub fn new() -> Self {
+        TranscriptIndexer { wake: Notify::new() }
+    }
+}
+
+fn queue_change(app_handle: &tauri::AppHandle, recording_id: i64, change: &str, segments: &[TranscriptionSegment]) {
+    let queued = (|| -> Result<i64> {
+        let segments = if segments.is_empty() { None } else { Some(serde_json::to_string(segments)?) };
+        Ok(Database::new(app_handle)?.queue_transcript_change(recording_id, change, segments.as_deref())?)
+    })();
+    match queued {
+        Ok(_) => app_handle.state::<TranscriptIndexer>().wake.notify_one(),
+        Err(e) => eprintln!("Failed to queue transcript of recording {} for indexing: {}", recording_id, e),
+    }
+}
+
+// Re-indexes in the background so saving, editing or transcribing doesn't
+// wait on the embedding model. Segments give the chunks timestamps.
+pub fn queue_recording_update(app_handle: &tauri::AppHandle, recording_id: i64, segments: &[TranscriptionSegment]) {
+    queue_change(app_handle, recording_id, CHANGE_UPSERT, segments);
+}
+
+pub fn queue_recording_removal(app_handle: &tauri::AppHandle, recording_id: i64) {
+    queue_change(app_handle, recording_id, CHANGE_DELETE, &[]);
+}
+
+fn emit_progress(app_handle: &tauri::AppHandle, progress: TranscriptIndexProgress) {
+    if let Err(e) = app_handle.emit(TRANSCRIPT_INDEX_PROGRESS_EVENT, progress) {
+        eprintln!("Failed to emit transcript index progress: {}", e);
+    }
+}
+
+async fn apply_change(
+    ai: &AdvancedAI,
+    app_handle: &tauri::AppHandle,
+    change: &TranscriptChange,
+    on_embedded: impl FnMut(usize, usize),
+) -> Result<IndexUpdate> {
+    if change.change == CHANGE_DELETE {
+        return remove_recording(app_handle, change.recording_id);
+    }
+    let segments: Vec<TranscriptionSegment> = match change.segments.as_deref() {
+        Some(segments) => serde_json::from_str(segments)?,
+        None => Vec::new(),
+    };
+    update_recording(ai, app_handle, change.recording_id, &segments, on_embedded).await
+}
+
+// Works through the queued changes once. Failed changes stay queued for the
+// next pass; returns whether there were any.
+async fn process_pending(app_handle: &tauri::AppHandle) -> Result<bool> {
+    let changes = Database::new(app_handle)?.pending_transcript_changes()?;
+    let ai = app_handle.state::<AdvancedAI>();
+    let mut failed = false;
+
+    for (position, change) in changes.iter().enumerate() {
+        let progress = TranscriptIndexProgress {
+            recording_id: change.recording_id,
+            change: change.change.clone(),
+            stage: "started".to_string(),
+            pending: changes.len() - position - 1,
+            ..Default::default()
+        };
+        emit_progress(app_handle, progress.clone());
+
+        let result = apply_change(&ai, app_handle, change, |embedded, total| {
+            emit_progress(app_handle, TranscriptIndexProgress {
+                stage: "embedding".to_string(),
+                chunks_embedded: embedded,
+                chunks_to_embed: total,
+                ..progress.clone()
+            });
+        }).await;
+
+        match result.and_then(|update| {
+            Database::new(app_handle)?.clear_transcript_change(change.id)?;
+            Ok(update)
+        }) {
+            Ok(update) => emit_progress(app_handle, TranscriptIndexProgress {
+                stage: "completed".to_string(),
+                chunks_total: update.chunks,
+                chunks_embedded: update.embedded,
+                chunks_to_embed: update.embedded,
+                chunks_kept: update.kept,
+                chunks_removed: update.removed,
+                ..progress
+            }),
+            Err(e) => {
+                eprintln!("Failed to index transcript of recording {}: {}", change.recording_id, e);
+                failed = true;
+                emit_progress(app_handle, TranscriptIndexProgress {
+                    stage: "failed".to_string(),
+                    error: Some(e.to_string()),
+                    ..progress
+                });
+            }
+        }
+    }
+    Ok(failed)
+}
+
+// Started once from setup. Picks up changes left over from the last run, then
+// waits for new ones; after a failure it retries on its own after a while.
+pub fn start_indexer(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let failed = process_pending(&app_handle).await.unwrap_or_else(|e| {
+                eprintln!("Failed to read queued transcript changes: {}", e);
+                true
+            });
+
+            let indexer = app_handle.state::<TranscriptIndexer>();
+            if failed {
+                let _ = tokio::time::timeout(RETRY_DELAY, indexer.wake.notified()).await;
+            } else {
+                indexer.wake.notified().await;
+            }
+        }
+    });
+}
+
+// Queues every stored transcript, e.g. recordings saved before transcripts
+// were indexed automatically. Unchanged chunks aren't embedded again.
+// Returns the number of recordings queued.
+#[command]
+pub async fn index_transcripts(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    let records = Database::new(&app_handle)
+        .and_then(|db| db.get_all_audio_records())
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut queued = 0;
+    for record in records {
+        let (Some(recording_id), Some(transcript)) = (record.id, record.transcript.as_deref()) else {
+            continue;
+        };
+        if transcript.trim().is_empty() {
+            continue;
+        }
+        queue_recording_update(&app_handle, i64::from(recording_id), &[]);
+        queued += 1;
+    }
+    Ok(queued)
+}
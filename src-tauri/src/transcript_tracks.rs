@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::{command, State};
+use crate::audio_capture::AudioCapture;
+use crate::database::{Database, TranscriptLine, TranscriptTrack};
+use crate::transcript_versions::{self, TextDiff};
+use crate::transcription::{self, Transcription, DEFAULT_MODEL_SIZE};
+use crate::whisper::{TranscriptSource, TranscriptionResult, TranscriptionSegment, WhisperEngine};
+
+// One of a recording's transcripts. Its own, its English translation and
+// its clean transcript are always there once made; the rest are kept
+// beside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "id")]
+pub enum TrackSelector {
+    Original,
+    Translation,
+    Clean,
+    Stored(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackEngine {
+    // The built-in model, on whisper.cpp or candle as the build has it
+    Whisper,
+    // The external whisper.cpp binary, with its configured model
+    WhisperCpp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptTrackContent {
+    pub track: TrackSelector,
+    pub name: String,
+    pub language: Option<String>,
+    // Unknown for transcripts from before versions were kept
+    pub engine: Option<String>,
+    pub model: Option<String>,
+    pub text: String,
+    pub lines: Vec<TranscriptLine>,
+}
+
+// A track without its text and lines, for listing
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptTrackSummary {
+    pub track: TrackSelector,
+    pub name: String,
+    pub language: Option<String>,
+    pub engine: Option<String>,
+    pub model: Option<String>,
+    pub words: usize,
+    pub lines: usize,
+}
+
+impl TranscriptTrackContent {
+    fn summary(&self) -> TranscriptTrackSummary {
+        TranscriptTrackSummary {
+            track: self.track,
+            name: self.name.clone(),
+            language: self.language.clone(),
+            engine: self.engine.clone(),
+            model: self.model.clone(),
+            words: self.text.split_whitespace().count(),
+            lines: self.lines.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackComparison {
+    pub record_id: i64,
+    pub from_track: TrackSelector,
+    pub to_track: TrackSelector,
+    #[serde(flatten)]
+    pub diff: TextDiff,
+}
+
+fn stored(track: TranscriptTrack) -> TranscriptTrackContent {
+    TranscriptTrackContent {
+        track: TrackSelector::Stored(track.id.unwrap_or_default()),
+        name: track.name,
+        language: track.language,
+        engine: Some(track.engine),
+        model: Some(track.model),
+        text: track.text,
+        lines: track.lines,
+    }
+}
+
+// A track of the recording, or None when it hasn't got that one, e.g. no
+// translation
+pub fn load(db: &Database, recording_id: i64, track: TrackSelector) -> Result<Option<TranscriptTrackContent>, String> {
+    let lines = || db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e));
+    let content = match track {
+        TrackSelector::Original => {
+            let record = db.get_audio_record(recording_id)
+                .map_err(|e| format!("Database error: {}", e))?
+                .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+            let Some(text) = record.transcript.filter(|text| !text.trim().is_empty()) else {
+                return Ok(None);
+            };
+            let latest = db.get_transcript_versions(recording_id).map_err(|e| format!("Database error: {}", e))?.pop();
+            TranscriptTrackContent {
+                track,
+                name: "Original".to_string(),
+                language: db.get_recording_language(recording_id).map_err(|e| format!("Database error: {}", e))?,
+                engine: latest.as_ref().map(|version| version.engine.clone()),
+                model: latest.map(|version| version.model),
+                text,
+                lines: lines()?,
+            }
+        }
+        TrackSelector::Translation => {
+            let Some(translation) = db.get_transcript_translation(recording_id).map_err(|e| format!("Database error: {}", e))? else {
+                return Ok(None);
+            };
+            TranscriptTrackContent {
+                track,
+                name: "English translation".to_string(),
+                language: Some("en".to_string()),
+                engine: Some("whisper".to_string()),
+                model: None,
+                text: translation.text,
+                lines: translation.lines,
+            }
+        }
+        // Clean lines are the recording's own, cleaned, so they keep its times
+        TrackSelector::Clean => {
+            let Some(cleaned) = db.get_clean_transcript(recording_id).map_err(|e| format!("Database error: {}", e))? else {
+                return Ok(None);
+            };
+            let lines = lines()?
+                .into_iter()
+                .zip(cleaned.lines)
+                .map(|(line, text)| TranscriptLine { text, words: Vec::new(), ..line })
+                .collect();
+            TranscriptTrackContent {
+                track,
+                name: "Clean".to_string(),
+                language: db.get_recording_language(recording_id).map_err(|e| format!("Database error: {}", e))?,
+                engine: None,
+                model: None,
+                text: cleaned.text,
+                lines,
+            }
+        }
+        TrackSelector::Stored(track_id) => {
+            let Some(track) = db.get_transcript_track(track_id)
+                .map_err(|e| format!("Database error: {}", e))?
+                .filter(|track| track.record_id == recording_id)
+            else {
+                return Ok(None);
+            };
+            stored(track)
+        }
+    };
+    Ok(Some(content))
+}
+
+pub fn require(db: &Database, recording_id: i64, track: TrackSelector) -> Result<TranscriptTrackContent, String> {
+    load(db, recording_id, track)?.ok_or_else(|| format!("Recording {} has no {:?} transcript track", recording_id, track))
+}
+
+fn save_track(
+    db: &Database,
+    recording_id: i64,
+    name: String,
+    source: &TranscriptSource,
+    result: &TranscriptionResult,
+    translated: bool,
+) -> Result<TranscriptTrackContent, String> {
+    let mut track = TranscriptTrack {
+        id: None,
+        record_id: recording_id,
+        name,
+        engine: source.engine.clone(),
+        model: source.model.clone(),
+        language: Some(result.language.clone()),
+        translated,
+        text: result.text.clone(),
+        lines: result.segments.iter().map(TranscriptionSegment::to_line).collect(),
+        created_at: String::new(),
+    };
+    track.id = Some(db.save_transcript_track(&track).map_err(|e| format!("Database error: {}", e))?);
+    Ok(stored(track))
+}
+
+// The recording's transcripts, its own first
+#[command]
+pub async fn list_transcript_tracks(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<TranscriptTrackSummary>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let mut tracks = Vec::new();
+    for track in [TrackSelector::Original, TrackSelector::Translation, TrackSelector::Clean] {
+        if let Some(content) = load(&db, recording_id, track)? {
+            tracks.push(content.summary());
+        }
+    }
+    let stored_tracks = db.get_transcript_tracks(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    tracks.extend(stored_tracks.into_iter().map(|track| stored(track).summary()));
+    Ok(tracks)
+}
+
+#[command]
+pub async fn get_transcript_track(recording_id: i64, track: TrackSelector, app_handle: tauri::AppHandle) -> Result<TranscriptTrackContent, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    require(&db, recording_id, track)
+}
+
+// Transcribes a recording again into a track of its own, leaving its
+// transcript as it is, e.g. to compare a bigger model or another engine, or
+// to keep it in a language other than the one detected. With `translate`
+// the English translation is kept as a second track.
+#[command]
+pub async fn add_transcript_track(
+    recording_id: i64,
+    engine: TrackEngine,
+    model_size: Option<String>,
+    language: Option<String>,
+    translate: Option<bool>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Vec<TranscriptTrackContent>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let quality = capture.get_settings().resample_quality;
+    let translate = translate.unwrap_or(false);
+
+    let (transcription, source) = match engine {
+        TrackEngine::Whisper => {
+            let size = model_size.unwrap_or_else(|| DEFAULT_MODEL_SIZE.to_string());
+            let source = transcription::source(&app_handle, &size, language.as_deref(), translate, quality)?;
+            let handle = app_handle.clone();
+            let transcription = tokio::task::spawn_blocking(move || {
+                transcription::transcribe_path(&handle, Path::new(&record.file_path), &size, language.as_deref(), translate, quality)
+            })
+            .await
+            .map_err(|e| format!("Transcription failed: {}", e))?
+            .map_err(|e| format!("Transcription failed: {}", e))?;
+            (transcription, source)
+        }
+        TrackEngine::WhisperCpp => {
+            if model_size.is_some() || language.is_some() || translate {
+                return Err("The whisper.cpp engine runs with its own configuration; leave out model_size, language and translate".to_string());
+            }
+            let engine = WhisperEngine::strict();
+            let result = engine.transcribe_channel(&record.file_path, None, quality)
+                .await
+                .map_err(|e| format!("Transcription failed: {}", e))?;
+            (Transcription { result, translation: None }, engine.source())
+        }
+    };
+
+    let name = format!("{} {} ({})", source.engine, source.model, transcription.result.language);
+    let mut tracks = vec![save_track(&db, recording_id, name, &source, &transcription.result, false)?];
+    if let Some(translation) = &transcription.translation {
+        let name = format!("{} {} (English translation)", source.engine, source.model);
+        tracks.push(save_track(&db, recording_id, name, &source, translation, true)?);
+    }
+    Ok(tracks)
+}
+
+// Makes a kept track the recording's transcript, which is then diarized and
+// indexed as a new transcript would be. What it replaces stays among the
+// transcript versions.
+#[command]
+pub async fn select_transcript_track(recording_id: i64, track_id: i64, app_handle: tauri::AppHandle) -> Result<TranscriptTrackContent, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let track = db.get_transcript_track(track_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .filter(|track| track.record_id == recording_id)
+        .ok_or_else(|| format!("Recording {} has no transcript track {}", recording_id, track_id))?;
+    let source = TranscriptSource {
+        engine: track.engine.clone(),
+        model: track.model.clone(),
+        settings: serde_json::json!({ "track": track_id, "translated": track.translated }),
+        restored_from: None,
+    };
+    transcription::restore_transcript(&app_handle, recording_id, &track.text, track.language.clone(), &track.lines, &source).await?;
+    require(&db, recording_id, TrackSelector::Original)
+}
+
+// What one track says that another doesn't, word by word
+#[command]
+pub async fn compare_transcript_tracks(
+    recording_id: i64,
+    from_track: TrackSelector,
+    to_track: TrackSelector,
+    app_handle: tauri::AppHandle,
+) -> Result<TrackComparison, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let from = require(&db, recording_id, from_track)?;
+    let to = require(&db, recording_id, to_track)?;
+    Ok(TrackComparison { record_id: recording_id, from_track, to_track, diff: transcript_versions::compare(&from.text, &to.text) })
+}
+
+#[command]
+pub async fn remove_transcript_track(track_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.delete_transcript_track(track_id).map_err(|e| format!("Database error: {}", e))
+}
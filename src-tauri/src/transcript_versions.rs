@@ -0,0 +1,226 @@
+use serde::Serialize;
+use tauri::command;
+use crate::database::{Database, TranscriptVersion};
+use crate::transcription;
+use crate::whisper::TranscriptSource;
+
+// Past this many word pairs the diff goes by line instead, so comparing two
+// long transcripts that share little stays within a few megabytes
+const MAX_WORD_PAIRS: usize = 40_000_000;
+
+// A version without its text and lines, for listing
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptVersionSummary {
+    pub id: i64,
+    pub engine: String,
+    pub model: String,
+    pub settings: serde_json::Value,
+    pub language: Option<String>,
+    pub words: usize,
+    pub lines: usize,
+    pub restored_from: Option<i64>,
+    pub created_at: String,
+    // Whether it is the recording's transcript now
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+}
+
+// A run of words in one version, the other or both
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffChunk {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+// What changed from one text to another, a run of words at a time
+#[derive(Debug, Clone, Serialize)]
+pub struct TextDiff {
+    pub chunks: Vec<DiffChunk>,
+    pub words_added: usize,
+    pub words_removed: usize,
+    pub words_same: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptDiff {
+    pub record_id: i64,
+    pub from_version: i64,
+    // None for the recording's transcript as it is now, diarization labels
+    // and edits included
+    pub to_version: Option<i64>,
+    #[serde(flatten)]
+    pub diff: TextDiff,
+}
+
+// Longest common subsequence of the two, walked from the start. Only one bit
+// per pair is kept, whether to step past the old token when they differ;
+// the lengths are two rows at a time.
+fn diff_tokens<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(DiffKind, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    let mut skip_old = vec![0u64; (n * m).div_ceil(64)];
+    let mut below = vec![0u32; m + 1];
+    let mut row = vec![0u32; m + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            row[j] = if old[i] == new[j] {
+                below[j + 1] + 1
+            } else if below[j] >= row[j + 1] {
+                let bit = i * m + j;
+                skip_old[bit / 64] |= 1 << (bit % 64);
+                below[j]
+            } else {
+                row[j + 1]
+            };
+        }
+        std::mem::swap(&mut below, &mut row);
+    }
+
+    let mut ops = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        let bit = i * m + j;
+        if old[i] == new[j] {
+            ops.push((DiffKind::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if skip_old[bit / 64] & (1 << (bit % 64)) != 0 {
+            ops.push((DiffKind::Removed, old[i]));
+            i += 1;
+        } else {
+            ops.push((DiffKind::Added, new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|token| (DiffKind::Removed, *token)));
+    ops.extend(new[j..].iter().map(|token| (DiffKind::Added, *token)));
+    ops
+}
+
+// Word by word, after setting aside what the two start and end with alike.
+// What's left of two transcripts too long to pair every word is compared a
+// line at a time.
+fn diff(old: &str, new: &str) -> Vec<(DiffKind, String)> {
+    let old_words: Vec<&str> = old.split_whitespace().collect();
+    let new_words: Vec<&str> = new.split_whitespace().collect();
+    let prefix = old_words.iter().zip(&new_words).take_while(|(a, b)| a == b).count();
+    let suffix = old_words[prefix..].iter().rev().zip(new_words[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_rest, new_rest) = (&old_words[prefix..old_words.len() - suffix], &new_words[prefix..new_words.len() - suffix]);
+
+    let mut ops: Vec<(DiffKind, String)> = old_words[..prefix].iter().map(|word| (DiffKind::Same, word.to_string())).collect();
+    if old_rest.len() * new_rest.len() <= MAX_WORD_PAIRS {
+        ops.extend(diff_tokens(old_rest, new_rest).into_iter().map(|(kind, word)| (kind, word.to_string())));
+    } else {
+        let (old_rest, new_rest) = (old_rest.join(" "), new_rest.join(" "));
+        let old_lines: Vec<&str> = old_rest.split_inclusive(['.', '?', '!']).map(str::trim).collect();
+        let new_lines: Vec<&str> = new_rest.split_inclusive(['.', '?', '!']).map(str::trim).collect();
+        ops.extend(diff_tokens(&old_lines, &new_lines).into_iter().map(|(kind, line)| (kind, line.to_string())));
+    }
+    ops.extend(old_words[old_words.len() - suffix..].iter().map(|word| (DiffKind::Same, word.to_string())));
+    ops
+}
+
+// The diff merged into runs, with how many words each way
+pub fn compare(old: &str, new: &str) -> TextDiff {
+    let mut chunks: Vec<DiffChunk> = Vec::new();
+    let (mut words_added, mut words_removed, mut words_same) = (0, 0, 0);
+    for (kind, text) in diff(old, new) {
+        let words = text.split_whitespace().count();
+        match kind {
+            DiffKind::Same => words_same += words,
+            DiffKind::Added => words_added += words,
+            DiffKind::Removed => words_removed += words,
+        }
+        match chunks.last_mut() {
+            Some(chunk) if chunk.kind == kind => {
+                chunk.text.push(' ');
+                chunk.text.push_str(&text);
+            }
+            _ => chunks.push(DiffChunk { kind, text }),
+        }
+    }
+    TextDiff { chunks, words_added, words_removed, words_same }
+}
+
+fn summary(version: &TranscriptVersion, current: bool) -> TranscriptVersionSummary {
+    TranscriptVersionSummary {
+        id: version.id.unwrap_or_default(),
+        engine: version.engine.clone(),
+        model: version.model.clone(),
+        settings: version.settings.clone(),
+        language: version.language.clone(),
+        words: version.text.split_whitespace().count(),
+        lines: version.lines.len(),
+        restored_from: version.restored_from,
+        created_at: version.created_at.clone(),
+        current,
+    }
+}
+
+fn version_of(db: &Database, recording_id: i64, version_id: i64) -> Result<TranscriptVersion, String> {
+    db.get_transcript_version(version_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .filter(|version| version.record_id == recording_id)
+        .ok_or_else(|| format!("Recording {} has no transcript version {}", recording_id, version_id))
+}
+
+// Every transcript the recording has had, oldest first, with the model and
+// settings that produced each. The newest is the current one.
+#[command]
+pub async fn list_transcript_versions(recording_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<TranscriptVersionSummary>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let versions = db.get_transcript_versions(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    let latest = versions.last().and_then(|version| version.id);
+    Ok(versions.iter().map(|version| summary(version, version.id == latest)).collect())
+}
+
+// What changed from one version of a recording's transcript to another, or
+// to the transcript as it is now without `to_version`
+#[command]
+pub async fn diff_transcript_versions(
+    recording_id: i64,
+    from_version: i64,
+    to_version: Option<i64>,
+    app_handle: tauri::AppHandle,
+) -> Result<TranscriptDiff, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let from = version_of(&db, recording_id, from_version)?;
+    let to_text = match to_version {
+        Some(to_version) => version_of(&db, recording_id, to_version)?.text,
+        None => db.get_audio_record(recording_id)
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| format!("Recording {} not found", recording_id))?
+            .transcript
+            .unwrap_or_default(),
+    };
+
+    Ok(TranscriptDiff { record_id: recording_id, from_version, to_version, diff: compare(&from.text, &to_text) })
+}
+
+// Puts an earlier version back as the recording's transcript, kept as a new
+// version that notes which it restored. Its translation is dropped, and it
+// is diarized and attributed again as a new transcript would be.
+#[command]
+pub async fn rollback_transcript(recording_id: i64, version_id: i64, app_handle: tauri::AppHandle) -> Result<TranscriptVersionSummary, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let version = version_of(&db, recording_id, version_id)?;
+    let source = TranscriptSource {
+        engine: version.engine.clone(),
+        model: version.model.clone(),
+        settings: version.settings.clone(),
+        restored_from: Some(version_id),
+    };
+    transcription::restore_transcript(&app_handle, recording_id, &version.text, version.language.clone(), &version.lines, &source).await?;
+
+    let restored = db.get_transcript_versions(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .pop()
+        .ok_or_else(|| format!("Recording {} has no transcript versions", recording_id))?;
+    Ok(summary(&restored, true))
+}
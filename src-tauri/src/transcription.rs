@@ -0,0 +1,526 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{command, Manager, State};
+use crate::acceleration::{Acceleration, AccelerationSupport};
+use crate::audio_capture::{AudioCapture, CaptureSettings};
+use crate::audio_file;
+use crate::diarization;
+use crate::database::{Database, TranscriptLine, TranscriptTranslation, TranscriptWord};
+use crate::resampler::ResampleQuality;
+use crate::speaker_embedding::SpeakerEncoder;
+use crate::speaker_id;
+use crate::vocabulary;
+use crate::whisper::{self, TranscriptSource, TranscriptionResult, TranscriptionSegment};
+
+// Builds with the whisper-cpp feature run Whisper through whisper.cpp, with
+// ggml models; the rest on candle with the Hugging Face weights
+#[cfg(feature = "whisper-cpp")]
+pub use crate::whisper_cpp_model::{model_url, WhisperModel, ENGINE, MODEL_FILES};
+#[cfg(not(feature = "whisper-cpp"))]
+pub use crate::candle_whisper::{model_url, WhisperModel, ENGINE, MODEL_FILES};
+
+pub const DEFAULT_MODEL_SIZE: &str = "base";
+// What every Whisper model takes its audio at
+pub const SAMPLE_RATE: u32 = 16_000;
+
+// Codes of the languages multilingual models know; models only have some
+pub const LANGUAGES: [&str; 100] = [
+    "en", "zh", "de", "es", "ru", "ko", "fr", "ja", "pt", "tr", "pl", "ca", "nl", "ar", "sv", "it", "id", "hi", "fi", "vi",
+    "he", "uk", "el", "ms", "cs", "ro", "da", "hu", "ta", "no", "th", "ur", "hr", "bg", "lt", "la", "mi", "ml", "cy", "sk",
+    "te", "fa", "lv", "bn", "sr", "az", "sl", "kn", "et", "mk", "br", "eu", "is", "hy", "ne", "mn", "bs", "kk", "sq", "sw",
+    "gl", "mr", "pa", "si", "km", "sn", "yo", "so", "af", "oc", "ka", "be", "tg", "sd", "gu", "am", "yi", "lo", "uz", "fo",
+    "ht", "ps", "tk", "nn", "mt", "sa", "lb", "my", "bo", "tl", "mg", "as", "tt", "haw", "ln", "ha", "ba", "jw", "su", "yue",
+];
+
+// Keeps the last model used loaded, as loading takes longer than
+// transcribing a short recording
+pub struct Transcriber {
+    loaded: Arc<Mutex<Option<WhisperModel>>>,
+    pub acceleration: AccelerationSupport,
+}
+
+impl Transcriber {
+    pub fn new() -> Self {
+        Transcriber { loaded: Arc::new(Mutex::new(None)), acceleration: AccelerationSupport::detect() }
+    }
+}
+
+pub fn models_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle.path().app_data_dir()
+        .map_err(|e| anyhow::anyhow!("Failed to get app data directory: {}", e))?
+        .join("models")
+        .join("whisper");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// A size is a folder name such as "base" or "small.en", never a path
+pub fn is_model_size(size: &str) -> bool {
+    !size.is_empty() && !size.starts_with('.') && size.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+pub fn is_language(code: &str) -> bool {
+    LANGUAGES.contains(&code)
+}
+
+fn model_dir(app_handle: &tauri::AppHandle, size: &str) -> Result<PathBuf> {
+    if !is_model_size(size) {
+        return Err(anyhow::anyhow!("'{}' is not a Whisper model size", size));
+    }
+    let dir = models_dir(app_handle)?.join(size);
+    if let Some(missing) = MODEL_FILES.iter().find(|file| !dir.join(file).exists()) {
+        return Err(anyhow::anyhow!(
+            "The Whisper {} model is missing {} in {}; download it, or put it there from {}",
+            size,
+            missing,
+            dir.display(),
+            model_url(size, missing)
+        ));
+    }
+    Ok(dir)
+}
+
+// Loads a model of its own on the backend the transcription_acceleration
+// setting picks, for work that runs beside the shared one
+pub fn load_model(app_handle: &tauri::AppHandle, model_size: &str) -> Result<WhisperModel> {
+    let backend = current_backend(app_handle)?;
+    WhisperModel::load(&model_dir(app_handle, model_size)?, model_size, backend)
+}
+
+pub fn current_backend(app_handle: &tauri::AppHandle) -> Result<Acceleration> {
+    let setting = app_handle.state::<AudioCapture>().get_settings().transcription_acceleration;
+    app_handle.state::<Transcriber>().acceleration.resolve(setting).map_err(|e| anyhow::anyhow!(e))
+}
+
+// Runs `f` on the model of that size, loading it first if it isn't the one
+// already loaded, on the backend the transcription_acceleration setting
+// picks. Transcriptions take turns with the one model.
+pub fn with_model<T>(app_handle: &tauri::AppHandle, model_size: &str, f: impl FnOnce(&mut WhisperModel) -> Result<T>) -> Result<T> {
+    with_model_on(app_handle, model_size, current_backend(app_handle)?, f)
+}
+
+// As with_model, on a backend `AccelerationSupport::resolve` returned
+pub fn with_model_on<T>(
+    app_handle: &tauri::AppHandle,
+    model_size: &str,
+    backend: Acceleration,
+    f: impl FnOnce(&mut WhisperModel) -> Result<T>,
+) -> Result<T> {
+    let transcriber = app_handle.state::<Transcriber>();
+    let mut loaded = transcriber.loaded.lock().map_err(|_| anyhow::anyhow!("The Whisper model is poisoned"))?;
+    if loaded.as_ref().is_none_or(|model| model.size() != model_size || model.backend() != backend) {
+        // The old model is dropped first, so two are never in memory at once
+        *loaded = None;
+        *loaded = Some(WhisperModel::load(&model_dir(app_handle, model_size)?, model_size, backend)?);
+    }
+    let model = loaded.as_mut().ok_or_else(|| anyhow::anyhow!("No Whisper model loaded"))?;
+    f(model)
+}
+
+// Frees the model if it's the one loaded, waiting for a transcription using
+// it to finish
+pub fn unload(app_handle: &tauri::AppHandle, model_size: &str) -> Result<()> {
+    let transcriber = app_handle.state::<Transcriber>();
+    let mut loaded = transcriber.loaded.lock().map_err(|_| anyhow::anyhow!("The Whisper model is poisoned"))?;
+    if loaded.as_ref().is_some_and(|model| model.size() == model_size) {
+        *loaded = None;
+    }
+    Ok(())
+}
+
+// A transcript, and its English translation when one was asked for and the
+// speech wasn't all English
+#[derive(Debug, Serialize)]
+pub struct Transcription {
+    #[serde(flatten)]
+    pub result: TranscriptionResult,
+    pub translation: Option<TranscriptionResult>,
+}
+
+// Decodes the file to 16 kHz mono and transcribes it with the custom
+// vocabulary, translating it too with `translate`. Nothing leaves the
+// machine.
+pub fn transcribe_path(
+    app_handle: &tauri::AppHandle,
+    path: &Path,
+    model_size: &str,
+    language: Option<&str>,
+    translate: bool,
+    quality: ResampleQuality,
+) -> Result<Transcription> {
+    let vocabulary = vocabulary::terms(&Database::new(app_handle)?)?;
+    let samples = audio_file::read_mono(path, None, SAMPLE_RATE, quality)?;
+    with_model(app_handle, model_size, |model| {
+        let result = model.transcribe(&samples, language, &vocabulary)?;
+        let translation = match translate && is_foreign(&result) {
+            true => Some(model.translate(&samples, language, &vocabulary)?),
+            false => None,
+        };
+        Ok(Transcription { result, translation })
+    })
+}
+
+// Whether any of it was spoken in a language other than English, so there
+// is something to translate
+pub fn is_foreign(result: &TranscriptionResult) -> bool {
+    result.segments.iter().any(|segment| segment.language.as_deref().unwrap_or(&result.language) != "en")
+}
+
+// Keeps the translation alongside the recording's transcript, searchable
+// with it
+fn store_translation(app_handle: &tauri::AppHandle, recording_id: i64, translation: &TranscriptionResult) -> Result<(), String> {
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.save_transcript_translation(&TranscriptTranslation {
+        record_id: recording_id,
+        source_language: translation.language.clone(),
+        text: translation.text.clone(),
+        lines: translation.segments.iter()
+            .map(|segment| TranscriptLine { words: Vec::new(), ..segment.to_line() })
+            .collect(),
+    })
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+// Transcribes a recording offline and stores the transcript on it with its
+// timed segments, indexed for RAG like any other. With `translate`, or the
+// translate_to_english setting without it, speech not in English is
+// translated too and the translation stored beside the transcript.
+#[command]
+pub async fn transcribe_recording(
+    id: i64,
+    model_size: Option<String>,
+    language: Option<String>,
+    translate: Option<bool>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Transcription, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", id))?;
+    let settings = capture.get_settings();
+    let (quality, translate) = (settings.resample_quality, translate.unwrap_or(settings.translate_to_english));
+    let size = model_size.unwrap_or_else(|| DEFAULT_MODEL_SIZE.to_string());
+    let source = source(&app_handle, &size, language.as_deref(), translate, quality)?;
+    let handle = app_handle.clone();
+    let transcription = tokio::task::spawn_blocking(move || {
+        transcribe_path(&handle, Path::new(&record.file_path), &size, language.as_deref(), translate, quality)
+    })
+    .await
+    .map_err(|e| format!("Transcription failed: {}", e))?
+    .map_err(|e| format!("Transcription failed: {}", e))?;
+    store_transcription(&app_handle, id, &transcription, &source, &settings).await?;
+    Ok(transcription)
+}
+
+// What re-transcribing part of a recording put in place of what was there
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionTranscription {
+    pub record_id: i64,
+    // Widened to the whole of the lines the region touched
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub model_size: String,
+    pub replaced_lines: usize,
+    pub lines: Vec<TranscriptLine>,
+}
+
+// The segment as if transcribed with the rest of the file, `offset` seconds in
+fn shifted(segment: TranscriptionSegment, offset: f64) -> TranscriptionSegment {
+    TranscriptionSegment {
+        start: segment.start + offset,
+        end: segment.end + offset,
+        words: segment.words
+            .into_iter()
+            .map(|word| TranscriptWord { start_seconds: word.start_seconds + offset, end_seconds: word.end_seconds + offset, ..word })
+            .collect(),
+        ..segment
+    }
+}
+
+// Transcribes just part of a recording again, e.g. with a bigger model for a
+// sentence the first pass garbled, and splices it into the transcript in
+// place of the lines it overlaps. Only those lines' rows are replaced; the
+// new ones take the speakers of the diarized lines around them and are
+// attributed to enrolled speakers on their own. The transcript text is
+// rebuilt from the lines and stored as a new version; its translation no
+// longer matches and is dropped.
+#[command]
+pub async fn retranscribe_region(
+    recording_id: i64,
+    start_ms: u64,
+    end_ms: u64,
+    model: Option<String>,
+    language: Option<String>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<RegionTranscription, String> {
+    if start_ms >= end_ms {
+        return Err("start_ms must be before end_ms".to_string());
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    if lines.is_empty() {
+        return Err(format!("Recording {} has no timed transcript; transcribe all of it first", recording_id));
+    }
+    let (_, duration) = audio_file::audio_info(Path::new(&record.file_path)).map_err(|e| format!("Transcription failed: {}", e))?;
+    let (mut start, mut end) = (start_ms as f64 / 1000.0, (end_ms as f64 / 1000.0).min(duration));
+    if start >= end {
+        return Err(format!("The region must be within the recording's {:.1} seconds", duration));
+    }
+
+    // Lines the region only partly covers are transcribed again whole
+    let touches = |line: &TranscriptLine| line.end_seconds > start && line.start_seconds < end;
+    let first = lines.iter().position(touches);
+    let replaced = first.map_or(0, |first| lines[first..].iter().take_while(|line| touches(line)).count());
+    if let Some(first) = first {
+        start = start.min(lines[first].start_seconds);
+        end = end.max(lines[first + replaced - 1].end_seconds);
+    }
+    let insert_at = first.unwrap_or_else(|| lines.iter().position(|line| line.start_seconds >= end).unwrap_or(lines.len()));
+    // Detection has little to go on in a few seconds, so the language the
+    // replaced lines were in is kept when they agree
+    let language = language.or_else(|| {
+        let mut languages = lines[insert_at..insert_at + replaced].iter().map(|line| line.language.clone());
+        let first = languages.next().flatten()?;
+        languages.all(|language| language.as_deref() == Some(first.as_str())).then_some(first)
+    });
+
+    let settings = capture.get_settings();
+    let quality = settings.resample_quality;
+    let size = model.unwrap_or_else(|| DEFAULT_MODEL_SIZE.to_string());
+    let mut source = source(&app_handle, &size, language.as_deref(), false, quality)?;
+    source.settings["region"] = serde_json::json!({ "start_ms": start_ms, "end_ms": end_ms });
+    let handle = app_handle.clone();
+    let (path, model_size, region_language) = (record.file_path.clone(), size.clone(), language.clone());
+    let region = tokio::task::spawn_blocking(move || {
+        let vocabulary = vocabulary::terms(&Database::new(&handle)?)?;
+        let samples = audio_file::read_mono_between(Path::new(&path), start, end, SAMPLE_RATE, quality)?;
+        with_model(&handle, &model_size, |model| model.transcribe(&samples, region_language.as_deref(), &vocabulary))
+    })
+    .await
+    .map_err(|e| format!("Transcription failed: {}", e))?
+    .map_err(|e| format!("Transcription failed: {}", e))?;
+
+    let mut new_lines: Vec<TranscriptLine> = region.segments.into_iter().map(|segment| shifted(segment, start).to_line()).collect();
+    let speakers = db.get_enrolled_speakers().map_err(|e| format!("Database error: {}", e))?;
+    // The region's lines are given the speakers diarization found around
+    // them, rather than the whole recording being diarized again
+    let diarized = settings.diarize_speakers && lines.iter().any(|line| line.speaker.is_some());
+    let mut region_audio = Vec::new();
+    let mut encoder = None;
+    if (diarized || !speakers.is_empty()) && !new_lines.is_empty() {
+        let nearby: Vec<TranscriptLine> = lines[..insert_at].iter()
+            .chain(&lines[insert_at + replaced..])
+            .filter(|line| diarized && line.speaker.is_some())
+            .filter(|line| line.end_seconds > start - diarization::CONTEXT_SECONDS && line.start_seconds < end + diarization::CONTEXT_SECONDS)
+            .cloned()
+            .collect();
+        let (path, context, encoder_settings) = (record.file_path.clone(), nearby.len(), settings.clone());
+        let (assigned, audio, loaded) = tokio::task::spawn_blocking(move || {
+            // Without the speaker model the region can't be diarized, but
+            // is still stored when it was only to be identified
+            let encoder = SpeakerEncoder::load(&encoder_settings);
+            let mut matched: Vec<TranscriptLine> = nearby.into_iter().chain(new_lines).collect();
+            let mut audio = diarization::read_lines(Path::new(&path), &matched, quality)?;
+            if diarized {
+                let unlabelled: Vec<usize> = (context..matched.len()).collect();
+                let loaded = encoder.as_ref().map_err(|e| anyhow::anyhow!("{}", e))?;
+                diarization::assign_speakers(loaded, &audio, &mut matched, &unlabelled)?;
+            }
+            Ok::<_, anyhow::Error>((matched.split_off(context), audio.split_off(context), encoder))
+        })
+        .await
+        .map_err(|e| format!("Diarization failed: {}", e))?
+        .map_err(|e| format!("Diarization failed: {}", e))?;
+        new_lines = assigned;
+        region_audio = audio;
+        encoder = Some(loaded);
+    }
+    whisper::store_transcript_region(&app_handle, recording_id, insert_at, replaced, &new_lines, &source)?;
+    match encoder {
+        Some(Ok(encoder)) if !speakers.is_empty() => {
+            if let Err(e) = speaker_id::identify_lines(&app_handle, recording_id, insert_at, &new_lines, region_audio, speakers, encoder).await {
+                eprintln!("Speaker identification failed for recording {}: {}", recording_id, e);
+            }
+        }
+        Some(Err(e)) if !speakers.is_empty() => eprintln!("Speaker identification failed for recording {}: {}", recording_id, e),
+        _ => {}
+    }
+
+    Ok(RegionTranscription {
+        record_id: recording_id,
+        start_seconds: start,
+        end_seconds: end,
+        model_size: size,
+        replaced_lines: replaced,
+        lines: new_lines,
+    })
+}
+
+// What a transcription by the built-in model is stored as coming from: the
+// model and what else it is run with
+pub fn source(
+    app_handle: &tauri::AppHandle,
+    model_size: &str,
+    language: Option<&str>,
+    translate: bool,
+    quality: ResampleQuality,
+) -> Result<TranscriptSource, String> {
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let vocabulary = vocabulary::terms(&db).map_err(|e| format!("Database error: {}", e))?;
+    Ok(TranscriptSource {
+        engine: ENGINE.to_string(),
+        model: model_size.to_string(),
+        settings: serde_json::json!({
+            "language": language,
+            "translate": translate,
+            "vocabulary": vocabulary,
+            "acceleration": current_backend(app_handle).ok(),
+            "resample_quality": quality,
+        }),
+        restored_from: None,
+    })
+}
+
+// Stores a recording's transcript and translation, then diarizes it when
+// the settings say to and attributes its lines to enrolled speakers, if any
+// are, from one read of the lines' audio and one load of the speaker model.
+// Identification failing leaves the transcript stored and is only logged.
+pub async fn store_transcription(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    transcription: &Transcription,
+    source: &TranscriptSource,
+    settings: &CaptureSettings,
+) -> Result<(), String> {
+    let diarize = settings.diarize_speakers;
+    whisper::store_transcript(app_handle, recording_id, &transcription.result, source)?;
+    if let Some(translation) = &transcription.translation {
+        store_translation(app_handle, recording_id, translation)?;
+    }
+    if transcription.result.segments.is_empty() {
+        return Ok(());
+    }
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let speakers = db.get_enrolled_speakers().map_err(|e| format!("Database error: {}", e))?;
+    if !diarize && speakers.is_empty() {
+        return Ok(());
+    }
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    let encoder_settings = settings.clone();
+    let loaded = tokio::task::spawn_blocking(move || SpeakerEncoder::load(&encoder_settings))
+        .await
+        .map_err(|e| format!("Diarization failed: {}", e))?;
+    let encoder = match loaded {
+        Ok(encoder) => encoder,
+        Err(e) if diarize => return Err(format!("Diarization failed: {}", e)),
+        Err(e) => {
+            eprintln!("Speaker identification failed for recording {}: {}", recording_id, e);
+            return Ok(());
+        }
+    };
+    let quality = settings.resample_quality;
+    let (lines, audio, encoder) = tokio::task::spawn_blocking(move || {
+        let mut lines = lines;
+        let audio = diarization::read_lines(Path::new(&record.file_path), &lines, quality)?;
+        if diarize {
+            diarization::diarize_lines(&encoder, &audio, &mut lines, None)?;
+        }
+        Ok::<_, anyhow::Error>((lines, audio, encoder))
+    })
+    .await
+    .map_err(|e| format!("Diarization failed: {}", e))?
+    .map_err(|e| format!("Diarization failed: {}", e))?;
+    if diarize {
+        diarization::store(app_handle, &db, recording_id, &lines)?;
+    }
+    if !speakers.is_empty() {
+        if let Err(e) = speaker_id::identify_lines(app_handle, recording_id, 0, &lines, audio, speakers, encoder).await {
+            eprintln!("Speaker identification failed for recording {}: {}", recording_id, e);
+        }
+    }
+    Ok(())
+}
+
+// Makes a transcript kept earlier, a track or a version, the recording's
+// own, diarized and attributed as a new one would be
+pub async fn restore_transcript(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    text: &str,
+    language: Option<String>,
+    lines: &[TranscriptLine],
+    source: &TranscriptSource,
+) -> Result<(), String> {
+    let segments: Vec<TranscriptionSegment> = lines.iter().map(TranscriptionSegment::from_line).collect();
+    let confidence = match segments.is_empty() {
+        true => 0.0,
+        false => segments.iter().map(|segment| segment.confidence).sum::<f32>() / segments.len() as f32,
+    };
+    let transcription = Transcription {
+        result: TranscriptionResult {
+            text: text.to_string(),
+            segments,
+            language: language.unwrap_or_default(),
+            processing_time_ms: 0,
+            confidence,
+        },
+        translation: None,
+    };
+    let settings = app_handle.state::<AudioCapture>().get_settings();
+    store_transcription(app_handle, recording_id, &transcription, source, &settings).await
+}
+
+// Transcribes any audio file offline without storing anything, translating
+// it as transcribe_recording does
+#[command]
+pub async fn transcribe_file(
+    path: String,
+    model_size: Option<String>,
+    language: Option<String>,
+    translate: Option<bool>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<Transcription, String> {
+    if !Path::new(&path).exists() {
+        return Err(format!("Audio file not found: {}", path));
+    }
+    let settings = capture.get_settings();
+    let (quality, translate) = (settings.resample_quality, translate.unwrap_or(settings.translate_to_english));
+    tokio::task::spawn_blocking(move || {
+        let size = model_size.as_deref().unwrap_or(DEFAULT_MODEL_SIZE);
+        transcribe_path(&app_handle, Path::new(&path), size, language.as_deref(), translate, quality)
+    })
+    .await
+    .map_err(|e| format!("Transcription failed: {}", e))?
+    .map_err(|e| format!("Transcription failed: {}", e))
+}
+
+// The model sizes with every file in place, and the one loaded
+#[command]
+pub async fn list_whisper_models(app_handle: tauri::AppHandle, transcriber: State<'_, Transcriber>) -> Result<serde_json::Value, String> {
+    let dir = models_dir(&app_handle).map_err(|e| format!("Whisper model error: {}", e))?;
+    let mut installed: Vec<String> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Whisper model error: {}", e))?
+        .flatten()
+        .filter(|entry| MODEL_FILES.iter().all(|file| entry.path().join(file).exists()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    installed.sort();
+    let loaded = transcriber.loaded.lock().ok().and_then(|loaded| loaded.as_ref().map(|model| model.size().to_string()));
+    Ok(serde_json::json!({
+        "models_dir": dir.to_string_lossy(),
+        "installed": installed,
+        "loaded": loaded,
+        "default": DEFAULT_MODEL_SIZE,
+    }))
+}
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::{command, Emitter, Manager, State};
+use tokio::sync::Notify;
+use crate::audio_capture::AudioCapture;
+use crate::audio_file;
+use crate::database::{Database, TranscriptionJob};
+use crate::transcription::{self, Transcription, WhisperModel, DEFAULT_MODEL_SIZE, SAMPLE_RATE};
+use crate::vocabulary;
+
+pub const TRANSCRIPTION_JOB_PROGRESS_EVENT: &str = "dwight://transcription-job-progress";
+
+// Each running job holds a Whisper model of its own
+pub const MAX_CONCURRENT_JOBS: u32 = 4;
+
+const QUEUED: &str = "queued";
+const RUNNING: &str = "running";
+const PAUSED: &str = "paused";
+const COMPLETED: &str = "completed";
+const FAILED: &str = "failed";
+const CANCELLED: &str = "cancelled";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionJobProgress {
+    pub job_id: i64,
+    pub recording_id: i64,
+    pub status: String,
+    pub progress: f64,
+    // From how fast the job has gone so far; None until it has got anywhere
+    pub eta_seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+// What a running job was asked to stop for, paused or cancelled
+type Stop = Arc<Mutex<Option<&'static str>>>;
+
+// Runs the queued jobs, which are kept in the database so they survive a
+// restart. Models are kept between jobs while they match.
+pub struct TranscriptionQueue {
+    wake: Notify,
+    running: Mutex<HashMap<i64, Stop>>,
+    idle_models: Mutex<Vec<WhisperModel>>,
+}
+
+impl TranscriptionQueue {
+    pub fn new() -> Self {
+        TranscriptionQueue {
+            wake: Notify::new(),
+            running: Mutex::new(HashMap::new()),
+            idle_models: Mutex::new(Vec::new()),
+        }
+    }
+
+    // An idle model of the size on the current backend, or a new one. A
+    // mismatched idle model is dropped first to make room.
+    fn take_model(&self, app_handle: &tauri::AppHandle, model_size: &str) -> Result<WhisperModel> {
+        let backend = transcription::current_backend(app_handle)?;
+        {
+            let mut idle = self.idle_models.lock().map_err(|_| anyhow::anyhow!("The transcription queue is poisoned"))?;
+            if let Some(position) = idle.iter().position(|model| model.size() == model_size && model.backend() == backend) {
+                return Ok(idle.swap_remove(position));
+            }
+            if !idle.is_empty() {
+                idle.remove(0);
+            }
+        }
+        transcription::load_model(app_handle, model_size)
+    }
+
+    fn return_model(&self, model: WhisperModel, limit: usize) {
+        if let Ok(mut idle) = self.idle_models.lock() {
+            idle.push(model);
+            while idle.len() > limit {
+                idle.remove(0);
+            }
+        }
+    }
+
+    fn running_count(&self) -> usize {
+        self.running.lock().map(|running| running.len()).unwrap_or(0)
+    }
+
+    fn stop(&self, job_id: i64, status: &'static str) -> bool {
+        let running = self.running.lock().ok().and_then(|running| running.get(&job_id).cloned());
+        running.and_then(|stop| stop.lock().ok().map(|mut stop| *stop = Some(status))).is_some()
+    }
+}
+
+fn emit_progress(app_handle: &tauri::AppHandle, progress: TranscriptionJobProgress) {
+    if let Err(e) = app_handle.emit(TRANSCRIPTION_JOB_PROGRESS_EVENT, progress) {
+        eprintln!("Failed to emit transcription job progress: {}", e);
+    }
+}
+
+fn emit_status(app_handle: &tauri::AppHandle, job: &TranscriptionJob, status: &str, progress: f64, error: Option<String>) {
+    emit_progress(app_handle, TranscriptionJobProgress {
+        job_id: job.id,
+        recording_id: job.record_id,
+        status: status.to_string(),
+        progress,
+        eta_seconds: None,
+        error,
+    });
+}
+
+// Decodes and transcribes the job's recording on a pooled model, sending
+// progress after each window. Translating takes the second half.
+fn transcribe_job(app_handle: &tauri::AppHandle, job: &TranscriptionJob, stop: &Stop, translate: bool) -> Result<Transcription> {
+    let queue = app_handle.state::<TranscriptionQueue>();
+    let settings = app_handle.state::<AudioCapture>().get_settings();
+    let db = Database::new(app_handle)?;
+    let record = db.get_audio_record(job.record_id)?.ok_or_else(|| anyhow::anyhow!("Recording {} not found", job.record_id))?;
+    let vocabulary = vocabulary::terms(&db)?;
+    let samples = audio_file::read_mono(Path::new(&record.file_path), None, SAMPLE_RATE, settings.resample_quality)?;
+
+    let started = Instant::now();
+    let report = |from: f64, share: f64| {
+        let started = &started;
+        move |done: f64| -> Result<()> {
+            if let Some(status) = stop.lock().ok().and_then(|stop| *stop) {
+                return Err(anyhow::anyhow!("Transcription job {} was {}", job.id, status));
+            }
+            let progress = (from + done * share).clamp(0.0, 1.0);
+            if let Err(e) = Database::new(app_handle).and_then(|db| db.set_transcription_job_progress(job.id, progress)) {
+                eprintln!("Failed to save progress of transcription job {}: {}", job.id, e);
+            }
+            let elapsed = started.elapsed().as_secs_f64();
+            emit_progress(app_handle, TranscriptionJobProgress {
+                job_id: job.id,
+                recording_id: job.record_id,
+                status: RUNNING.to_string(),
+                progress,
+                eta_seconds: (progress > 0.0).then(|| elapsed * (1.0 - progress) / progress),
+                error: None,
+            });
+            Ok(())
+        }
+    };
+
+    let mut model = queue.take_model(app_handle, &job.model_size)?;
+    let transcribe_share = if translate { 0.5 } else { 1.0 };
+    let result = model.transcribe_with_progress(&samples, job.language.as_deref(), &vocabulary, &mut report(0.0, transcribe_share));
+    let transcription = result.and_then(|result| {
+        let translation = match translate && transcription::is_foreign(&result) {
+            true => Some(model.translate_with_progress(&samples, job.language.as_deref(), &vocabulary, &mut report(0.5, 0.5))?),
+            false => None,
+        };
+        Ok(Transcription { result, translation })
+    });
+    queue.return_model(model, settings.transcription_concurrency as usize);
+    transcription
+}
+
+async fn run_job(app_handle: tauri::AppHandle, job: TranscriptionJob, stop: Stop) {
+    emit_status(&app_handle, &job, RUNNING, 0.0, None);
+    let settings = app_handle.state::<AudioCapture>().get_settings();
+    let translate = job.translate.unwrap_or(settings.translate_to_english);
+
+    let handle = app_handle.clone();
+    let running = job.clone();
+    let stopping = stop.clone();
+    let transcribed = tokio::task::spawn_blocking(move || transcribe_job(&handle, &running, &stopping, translate))
+        .await
+        .map_err(|e| format!("Transcription failed: {}", e))
+        .and_then(|result| result.map_err(|e| format!("Transcription failed: {}", e)));
+    let stopped = stop.lock().ok().and_then(|stop| *stop);
+    let (status, error) = match (stopped, transcribed) {
+        (Some(status), _) => (status, None),
+        (None, Ok(transcription)) => {
+            let quality = settings.resample_quality;
+            let stored = match transcription::source(&app_handle, &job.model_size, job.language.as_deref(), translate, quality) {
+                Ok(source) => transcription::store_transcription(&app_handle, job.record_id, &transcription, &source, &settings).await,
+                Err(e) => Err(e),
+            };
+            match stored {
+                Ok(()) => (COMPLETED, None),
+                Err(e) => (FAILED, Some(e)),
+            }
+        }
+        (None, Err(e)) => (FAILED, Some(e)),
+    };
+    if let Some(error) = &error {
+        eprintln!("Transcription job {} failed: {}", job.id, error);
+    }
+    if let Err(e) = Database::new(&app_handle).and_then(|db| db.set_transcription_job_status(job.id, status, error.as_deref())) {
+        eprintln!("Failed to save transcription job {}: {}", job.id, e);
+    }
+    let progress = if status == COMPLETED { 1.0 } else { 0.0 };
+    emit_status(&app_handle, &job, status, progress, error);
+
+    let queue = app_handle.state::<TranscriptionQueue>();
+    if let Ok(mut running) = queue.running.lock() {
+        running.remove(&job.id);
+    }
+    queue.wake.notify_one();
+}
+
+// Starts queued jobs until the concurrency limit is reached
+fn dispatch(app_handle: &tauri::AppHandle) -> Result<()> {
+    let queue = app_handle.state::<TranscriptionQueue>();
+    let limit = app_handle.state::<AudioCapture>().get_settings().transcription_concurrency as usize;
+    while queue.running_count() < limit {
+        let Some(job) = Database::new(app_handle)?.claim_transcription_job()? else {
+            break;
+        };
+        let stop: Stop = Arc::new(Mutex::new(None));
+        queue.running.lock().map_err(|_| anyhow::anyhow!("The transcription queue is poisoned"))?.insert(job.id, stop.clone());
+        tauri::async_runtime::spawn(run_job(app_handle.clone(), job, stop));
+    }
+    Ok(())
+}
+
+// Started once from setup. Jobs cut off by the last shutdown start over;
+// then queued jobs are run as slots free up.
+pub fn start_queue(app_handle: &tauri::AppHandle) {
+    match Database::new(app_handle).and_then(|db| db.requeue_running_transcription_jobs()) {
+        Ok(requeued) if requeued > 0 => println!("Requeued {} interrupted transcription jobs", requeued),
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to requeue interrupted transcription jobs: {}", e),
+    }
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = dispatch(&app_handle) {
+                eprintln!("Failed to start transcription jobs: {}", e);
+            }
+            app_handle.state::<TranscriptionQueue>().wake.notified().await;
+        }
+    });
+}
+
+// Queues a recording with the default model, its language detected, for
+// callers in the app such as watch folders. Returns the job's id.
+pub fn enqueue(app_handle: &tauri::AppHandle, record_id: i64) -> Result<i64> {
+    let job_id = Database::new(app_handle)?.queue_transcription_job(record_id, DEFAULT_MODEL_SIZE, None, None)?;
+    app_handle.state::<TranscriptionQueue>().wake.notify_one();
+    Ok(job_id)
+}
+
+fn job(db: &Database, job_id: i64) -> Result<TranscriptionJob, String> {
+    db.get_transcription_job(job_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Transcription job {} not found", job_id))
+}
+
+// Queues recordings to be transcribed in the background, in order, as
+// transcribe_recording would. Progress comes as transcription job events.
+#[command]
+pub async fn enqueue_transcriptions(
+    recording_ids: Vec<i64>,
+    model_size: Option<String>,
+    language: Option<String>,
+    translate: Option<bool>,
+    app_handle: tauri::AppHandle,
+    queue: State<'_, TranscriptionQueue>,
+) -> Result<Vec<TranscriptionJob>, String> {
+    let model_size = model_size.unwrap_or_else(|| DEFAULT_MODEL_SIZE.to_string());
+    if !transcription::is_model_size(&model_size) {
+        return Err(format!("'{}' is not a Whisper model size", model_size));
+    }
+    if language.as_deref().is_some_and(|language| !transcription::is_language(language)) {
+        return Err(format!("'{}' is not a language Whisper knows", language.unwrap_or_default()));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    for &recording_id in &recording_ids {
+        db.get_audio_record(recording_id)
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    }
+    let mut jobs = Vec::new();
+    for recording_id in recording_ids {
+        let job_id = db.queue_transcription_job(recording_id, &model_size, language.as_deref(), translate)
+            .map_err(|e| format!("Database error: {}", e))?;
+        jobs.push(job(&db, job_id)?);
+    }
+    queue.wake.notify_one();
+    Ok(jobs)
+}
+
+#[command]
+pub async fn list_transcription_jobs(app_handle: tauri::AppHandle) -> Result<Vec<TranscriptionJob>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_transcription_jobs().map_err(|e| format!("Database error: {}", e))
+}
+
+// Holds a job back from running; a running one is stopped and will start
+// over when resumed
+#[command]
+pub async fn pause_transcription_job(
+    job_id: i64,
+    app_handle: tauri::AppHandle,
+    queue: State<'_, TranscriptionQueue>,
+) -> Result<TranscriptionJob, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let current = job(&db, job_id)?;
+    match current.status.as_str() {
+        QUEUED => db.set_transcription_job_status(job_id, PAUSED, None).map_err(|e| format!("Database error: {}", e))?,
+        RUNNING if queue.stop(job_id, PAUSED) => {}
+        status => return Err(format!("Transcription job {} is {}; only queued or running jobs can be paused", job_id, status)),
+    }
+    job(&db, job_id)
+}
+
+#[command]
+pub async fn resume_transcription_job(
+    job_id: i64,
+    app_handle: tauri::AppHandle,
+    queue: State<'_, TranscriptionQueue>,
+) -> Result<TranscriptionJob, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let current = job(&db, job_id)?;
+    if current.status != PAUSED {
+        return Err(format!("Transcription job {} is {}; only paused jobs can be resumed", job_id, current.status));
+    }
+    db.set_transcription_job_status(job_id, QUEUED, None).map_err(|e| format!("Database error: {}", e))?;
+    queue.wake.notify_one();
+    job(&db, job_id)
+}
+
+// A running job stops after the window it is on
+#[command]
+pub async fn cancel_transcription_job(
+    job_id: i64,
+    app_handle: tauri::AppHandle,
+    queue: State<'_, TranscriptionQueue>,
+) -> Result<TranscriptionJob, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let current = job(&db, job_id)?;
+    match current.status.as_str() {
+        QUEUED | PAUSED => db.set_transcription_job_status(job_id, CANCELLED, None).map_err(|e| format!("Database error: {}", e))?,
+        RUNNING if queue.stop(job_id, CANCELLED) => {}
+        status => return Err(format!("Transcription job {} is already {}", job_id, status)),
+    }
+    job(&db, job_id)
+}
+
+// Removes completed, failed and cancelled jobs from the list
+#[command]
+pub async fn clear_finished_transcription_jobs(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.delete_finished_transcription_jobs().map_err(|e| format!("Database error: {}", e))
+}
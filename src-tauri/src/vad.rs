@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use webrtc_vad::{SampleRate, Vad, VadMode};
+use crate::audio_capture::StreamFormat;
+use crate::resampler::{FormatConverter, ResampleQuality};
+
+pub const VOICE_ACTIVITY_EVENT: &str = "dwight://voice-activity";
+
+const VAD_SAMPLE_RATE: u32 = 16000;
+// webrtc-vad takes 10, 20 or 30 ms frames
+const FRAME_MS: u32 = 30;
+const FRAME_SAMPLES: usize = (VAD_SAMPLE_RATE * FRAME_MS / 1000) as usize;
+
+// Voiced frames in a row before speech counts as started, so a door click doesn't
+const ONSET_FRAMES: u32 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceActivity {
+    pub speaking: bool,
+    // When speech started or ended, in seconds since capture started
+    pub at_seconds: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpeechRegion {
+    pub start: f64,
+    pub end: f64,
+}
+
+// Tells speech from silence in capture audio, 30 ms at a time
+pub struct VoiceActivityDetector {
+    vad: Vad,
+    // The VAD wants 16 kHz mono; speech survives the fast resampler fine
+    converter: FormatConverter,
+    pending: Vec<i16>,
+    frames: u64,
+    voiced_run: u32,
+    silent_ms: u32,
+    hangover_ms: u32,
+    speaking: bool,
+}
+
+impl VoiceActivityDetector {
+    // `aggressiveness` goes from 0, which calls most things speech, to 3
+    pub fn new(format: StreamFormat, aggressiveness: u8, hangover_ms: u32) -> Self {
+        let mode = match aggressiveness {
+            0 => VadMode::Quality,
+            1 => VadMode::LowBitrate,
+            2 => VadMode::Aggressive,
+            _ => VadMode::VeryAggressive,
+        };
+        VoiceActivityDetector {
+            vad: Vad::new_with_rate_and_mode(SampleRate::Rate16kHz, mode),
+            converter: FormatConverter::new(format, StreamFormat { sample_rate: VAD_SAMPLE_RATE, channels: 1 }, ResampleQuality::Fast),
+            pending: Vec::with_capacity(FRAME_SAMPLES),
+            frames: 0,
+            voiced_run: 0,
+            silent_ms: 0,
+            hangover_ms,
+            speaking: false,
+        }
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    fn seconds_at(frame: u64) -> f64 {
+        frame as f64 * FRAME_MS as f64 / 1000.0
+    }
+
+    // Feeds capture audio and returns where speech started or ended in it
+    pub fn process(&mut self, samples: &[f32]) -> Vec<VoiceActivity> {
+        let mut changes = Vec::new();
+        for sample in self.converter.convert(samples) {
+            self.pending.push((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+            if self.pending.len() < FRAME_SAMPLES {
+                continue;
+            }
+
+            let voiced = self.vad.is_voice_segment(&self.pending).unwrap_or(false);
+            self.pending.clear();
+            self.frames += 1;
+
+            if voiced {
+                self.voiced_run += 1;
+                self.silent_ms = 0;
+                if !self.speaking && self.voiced_run >= ONSET_FRAMES {
+                    self.speaking = true;
+                    changes.push(VoiceActivity { speaking: true, at_seconds: Self::seconds_at(self.frames - ONSET_FRAMES as u64) });
+                }
+            } else {
+                self.voiced_run = 0;
+                self.silent_ms += FRAME_MS;
+                if self.speaking && self.silent_ms >= self.hangover_ms {
+                    self.speaking = false;
+                    let silent_frames = (self.silent_ms / FRAME_MS) as u64;
+                    changes.push(VoiceActivity { speaking: false, at_seconds: Self::seconds_at(self.frames - silent_frames) });
+                }
+            }
+        }
+        changes
+    }
+}
@@ -0,0 +1,728 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::os::raw::{c_char, c_int};
+use std::sync::Once;
+use tauri::{command, State};
+use crate::ai_models::AdvancedAI;
+use crate::database;
+use crate::rag;
+
+// vec0 rejects larger k in a single KNN query
+const MAX_SEARCH_RESULTS: usize = 4096;
+
+// Always present; chunks go here unless the caller names another index
+pub const DEFAULT_INDEX: &str = "default";
+const DEFAULT_INDEX_ID: i64 = 1;
+
+// Chunks sent to the embedding model per request
+const EMBED_BATCH_SIZE: usize = 32;
+
+type ExtensionEntryPoint = unsafe extern "C" fn(
+    *mut rusqlite::ffi::sqlite3,
+    *mut *const c_char,
+    *const rusqlite::ffi::sqlite3_api_routines,
+) -> c_int;
+
+static REGISTER_SQLITE_VEC: Once = Once::new();
+
+// Makes the vec0 virtual table available on every connection opened afterwards
+fn register_sqlite_vec() {
+    REGISTER_SQLITE_VEC.call_once(|| unsafe {
+        let entry_point = std::mem::transmute::<*const (), ExtensionEntryPoint>(sqlite_vec::sqlite3_vec_init as *const ());
+        rusqlite::ffi::sqlite3_auto_extension(Some(entry_point));
+    });
+}
+
+// A piece of text with its embedding, as stored in the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredChunk {
+    pub id: i64,
+    pub text: String,
+    // Where the text came from, e.g. a file path or "recording:12"
+    pub source: String,
+    pub metadata: serde_json::Value,
+    // Vectors are only comparable with vectors from the same model
+    pub embedding_model: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewChunk {
+    pub text: String,
+    pub source: String,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub chunk: StoredChunk,
+    // Cosine similarity to the query, 1.0 being identical. None for chunks
+    // that only the keyword search found.
+    pub similarity: Option<f32>,
+    // What hits are ordered by: the similarity for vector search, the fused
+    // reciprocal-rank score for hybrid search
+    pub score: f32,
+}
+
+// A named collection of chunks, e.g. one per case or project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagIndex {
+    pub name: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub name: String,
+    // Distinct sources, e.g. ingested files or recordings
+    pub document_count: usize,
+    pub chunk_count: usize,
+    // Estimated from the stored text and vectors; SQLite doesn't report per-table sizes
+    pub disk_size_bytes: u64,
+    pub embedding_models: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// Embeddings on disk in dwight.db. Chunk text and metadata live in rag_chunks;
+// each index and vector dimension gets its own vec0 table keyed by the chunk id.
+pub struct VectorStore {
+    connection: Connection,
+}
+
+impl VectorStore {
+    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self> {
+        register_sqlite_vec();
+        let connection = Connection::open(database::database_path(app_handle)?)?;
+
+        let store = VectorStore { connection };
+        store.initialize_tables()?;
+        Ok(store)
+    }
+
+    fn initialize_tables(&self) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "CREATE TABLE IF NOT EXISTS rag_indexes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.connection.execute(
+            "INSERT OR IGNORE INTO rag_indexes (id, name, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+            rusqlite::params![DEFAULT_INDEX_ID, DEFAULT_INDEX, now],
+        )?;
+
+        self.connection.execute(
+            "CREATE TABLE IF NOT EXISTS rag_chunks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                index_id INTEGER NOT NULL DEFAULT 1,
+                text TEXT NOT NULL,
+                source TEXT NOT NULL,
+                metadata TEXT NOT NULL,
+                embedding_model TEXT NOT NULL,
+                dimensions INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Chunks stored before there were named indexes belong to the default one
+        let has_index_column = self.connection
+            .query_row("SELECT 1 FROM pragma_table_info('rag_chunks') WHERE name = 'index_id'", [], |_| Ok(()))
+            .optional()?
+            .is_some();
+        if !has_index_column {
+            self.connection.execute("ALTER TABLE rag_chunks ADD COLUMN index_id INTEGER NOT NULL DEFAULT 1", [])?;
+        }
+        self.migrate_unindexed_vectors()?;
+
+        self.connection.execute(
+            "CREATE INDEX IF NOT EXISTS idx_rag_chunks_source ON rag_chunks (index_id, source)",
+            [],
+        )?;
+
+        // BM25 keyword index over the chunk text, kept in sync by triggers
+        let has_keyword_index = self.has_table("rag_chunks_fts")?;
+        self.connection.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS rag_chunks_fts USING fts5(text, content='rag_chunks', content_rowid='id');
+             CREATE TRIGGER IF NOT EXISTS rag_chunks_fts_insert AFTER INSERT ON rag_chunks BEGIN
+                 INSERT INTO rag_chunks_fts (rowid, text) VALUES (new.id, new.text);
+             END;
+             CREATE TRIGGER IF NOT EXISTS rag_chunks_fts_delete AFTER DELETE ON rag_chunks BEGIN
+                 INSERT INTO rag_chunks_fts (rag_chunks_fts, rowid, text) VALUES ('delete', old.id, old.text);
+             END;
+             CREATE TRIGGER IF NOT EXISTS rag_chunks_fts_update AFTER UPDATE OF text ON rag_chunks BEGIN
+                 INSERT INTO rag_chunks_fts (rag_chunks_fts, rowid, text) VALUES ('delete', old.id, old.text);
+                 INSERT INTO rag_chunks_fts (rowid, text) VALUES (new.id, new.text);
+             END;",
+        )?;
+        // Chunks stored before the keyword index existed
+        if !has_keyword_index {
+            self.connection.execute("INSERT INTO rag_chunks_fts (rag_chunks_fts) VALUES ('rebuild')", [])?;
+        }
+        Ok(())
+    }
+
+    // Vector tables used to be per dimension only (rag_vectors_768); move their
+    // rows into the default index's tables
+    fn migrate_unindexed_vectors(&self) -> Result<()> {
+        let legacy_tables = self.tables_matching("rag_vectors_[0-9]*")?;
+        for table in legacy_tables {
+            let Some(dimensions) = table.strip_prefix("rag_vectors_").and_then(|dims| dims.parse::<usize>().ok()) else {
+                continue;
+            };
+            let target = self.create_vector_table(DEFAULT_INDEX_ID, dimensions)?;
+            self.connection.execute_batch(&format!(
+                "INSERT INTO {target} (rowid, embedding, embedding_model) SELECT rowid, embedding, embedding_model FROM {table};
+                 DROP TABLE {table};"
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn vector_table(index_id: i64, dimensions: usize) -> String {
+        format!("rag_vectors_{}_{}", index_id, dimensions)
+    }
+
+    fn create_vector_table(&self, index_id: i64, dimensions: usize) -> Result<String> {
+        let table = Self::vector_table(index_id, dimensions);
+        self.connection.execute(
+            &format!(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING vec0(embedding float[{}] distance_metric=cosine, embedding_model text)",
+                table, dimensions
+            ),
+            [],
+        )?;
+        Ok(table)
+    }
+
+    // vec0 tables named like `pattern`, leaving out their shadow tables (rag_vectors_1_768_chunks etc.)
+    fn tables_matching(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name GLOB ?1 AND name NOT GLOB ?1 || '_*'",
+        )?;
+        let tables = stmt.query_map([pattern], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(tables)
+    }
+
+    fn index_vector_tables(&self, index_id: i64) -> Result<Vec<String>> {
+        self.tables_matching(&format!("rag_vectors_{}_[0-9]*", index_id))
+    }
+
+    fn has_table(&self, table: &str) -> Result<bool> {
+        let exists = self.connection
+            .query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |_| Ok(()))
+            .optional()?;
+        Ok(exists.is_some())
+    }
+
+    pub fn has_index(&self, name: &str) -> Result<bool> {
+        let exists = self.connection
+            .query_row("SELECT 1 FROM rag_indexes WHERE name = ?1", [name], |_| Ok(()))
+            .optional()?;
+        Ok(exists.is_some())
+    }
+
+    fn index_id(&self, name: &str) -> Result<i64> {
+        self.connection
+            .query_row("SELECT id FROM rag_indexes WHERE name = ?1", [name], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| anyhow::anyhow!("RAG index '{}' does not exist", name))
+    }
+
+    fn touch_index(&self, index_id: i64) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute("UPDATE rag_indexes SET updated_at = ?1 WHERE id = ?2", rusqlite::params![now, index_id])?;
+        Ok(())
+    }
+
+    pub fn create_index(&self, name: &str) -> Result<RagIndex> {
+        if self.has_index(name)? {
+            return Err(anyhow::anyhow!("RAG index '{}' already exists", name));
+        }
+        let now = chrono::Utc::now().to_rfc3339();
+        self.connection.execute(
+            "INSERT INTO rag_indexes (name, created_at, updated_at) VALUES (?1, ?2, ?2)",
+            rusqlite::params![name, now],
+        )?;
+        Ok(RagIndex { name: name.to_string(), created_at: now.clone(), updated_at: now })
+    }
+
+    // Creates the index unless it exists, for indexes the app manages itself
+    pub fn ensure_index(&self, name: &str) -> Result<()> {
+        if !self.has_index(name)? {
+            self.create_index(name)?;
+        }
+        Ok(())
+    }
+
+    pub fn list_indexes(&self) -> Result<Vec<RagIndex>> {
+        let mut stmt = self.connection.prepare("SELECT name, created_at, updated_at FROM rag_indexes ORDER BY id")?;
+        let indexes = stmt
+            .query_map([], |row| Ok(RagIndex { name: row.get(0)?, created_at: row.get(1)?, updated_at: row.get(2)? }))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(indexes)
+    }
+
+    // Drops an index with all of its chunks and returns how many chunks were removed
+    pub fn delete_index(&mut self, name: &str) -> Result<usize> {
+        if name == DEFAULT_INDEX {
+            return Err(anyhow::anyhow!("The default RAG index can't be deleted"));
+        }
+        let index_id = self.index_id(name)?;
+        let tables = self.index_vector_tables(index_id)?;
+
+        let tx = self.connection.transaction()?;
+        for table in tables {
+            tx.execute(&format!("DROP TABLE {}", table), [])?;
+        }
+        let deleted = tx.execute("DELETE FROM rag_chunks WHERE index_id = ?1", [index_id])?;
+        tx.execute("DELETE FROM rag_indexes WHERE id = ?1", [index_id])?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    pub fn index_stats(&self, name: &str) -> Result<IndexStats> {
+        let index_id = self.index_id(name)?;
+        let (created_at, updated_at): (String, String) = self.connection.query_row(
+            "SELECT created_at, updated_at FROM rag_indexes WHERE id = ?1",
+            [index_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let (document_count, chunk_count, disk_size_bytes): (usize, usize, i64) = self.connection.query_row(
+            "SELECT COUNT(DISTINCT source), COUNT(*),
+                    COALESCE(SUM(LENGTH(CAST(text AS BLOB)) + LENGTH(CAST(metadata AS BLOB)) + dimensions * 4), 0)
+             FROM rag_chunks WHERE index_id = ?1",
+            [index_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let mut stmt = self.connection.prepare("SELECT DISTINCT embedding_model FROM rag_chunks WHERE index_id = ?1")?;
+        let embedding_models = stmt.query_map([index_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        Ok(IndexStats {
+            name: name.to_string(),
+            document_count,
+            chunk_count,
+            // Text is counted twice: once for the row, once for the keyword index
+            disk_size_bytes: disk_size_bytes.max(0) as u64 * 2,
+            embedding_models,
+            created_at,
+            updated_at,
+        })
+    }
+
+    // Stores the chunks with their embeddings and returns the new chunk ids, in order
+    pub fn insert(&mut self, index: &str, embedding_model: &str, chunks: &[NewChunk], embeddings: &[Vec<f32>]) -> Result<Vec<i64>> {
+        if chunks.len() != embeddings.len() {
+            return Err(anyhow::anyhow!("Got {} embeddings for {} chunks", embeddings.len(), chunks.len()));
+        }
+        if embeddings.iter().any(|embedding| embedding.is_empty()) {
+            return Err(anyhow::anyhow!("Cannot store an empty embedding"));
+        }
+
+        let index_id = self.index_id(index)?;
+        let mut dimensions: Vec<usize> = embeddings.iter().map(Vec::len).collect();
+        dimensions.dedup();
+        for &dims in &dimensions {
+            self.create_vector_table(index_id, dims)?;
+        }
+
+        let now = chrono::Utc::now().to_rfc3339();
+        let tx = self.connection.transaction()?;
+        let mut ids = Vec::with_capacity(chunks.len());
+
+        for (chunk, embedding) in chunks.iter().zip(embeddings) {
+            let metadata = if chunk.metadata.is_null() { serde_json::json!({}) } else { chunk.metadata.clone() };
+            tx.execute(
+                "INSERT INTO rag_chunks (index_id, text, source, metadata, embedding_model, dimensions, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![index_id, chunk.text, chunk.source, metadata.to_string(), embedding_model, embedding.len(), now],
+            )?;
+            let id = tx.last_insert_rowid();
+
+            tx.execute(
+                &format!("INSERT INTO {} (rowid, embedding, embedding_model) VALUES (?1, ?2, ?3)", Self::vector_table(index_id, embedding.len())),
+                rusqlite::params![id, vector_blob(embedding), embedding_model],
+            )?;
+            ids.push(id);
+        }
+
+        tx.commit()?;
+        self.touch_index(index_id)?;
+        Ok(ids)
+    }
+
+    // Removes chunks and their vectors; ids that don't exist are skipped
+    pub fn delete(&mut self, ids: &[i64]) -> Result<usize> {
+        let tx = self.connection.transaction()?;
+        let mut deleted = 0;
+        let mut touched = Vec::new();
+
+        for &id in ids {
+            let location: Option<(i64, usize)> = tx
+                .query_row("SELECT index_id, dimensions FROM rag_chunks WHERE id = ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            let Some((index_id, dimensions)) = location else {
+                continue;
+            };
+
+            tx.execute(&format!("DELETE FROM {} WHERE rowid = ?1", Self::vector_table(index_id, dimensions)), [id])?;
+            deleted += tx.execute("DELETE FROM rag_chunks WHERE id = ?1", [id])?;
+            if !touched.contains(&index_id) {
+                touched.push(index_id);
+            }
+        }
+
+        tx.commit()?;
+        for index_id in touched {
+            self.touch_index(index_id)?;
+        }
+        Ok(deleted)
+    }
+
+    // Removes everything stored for a source, e.g. before re-ingesting a file
+    pub fn delete_source(&mut self, index: &str, source: &str) -> Result<usize> {
+        let index_id = self.index_id(index)?;
+        let ids = {
+            let mut stmt = self.connection.prepare("SELECT id FROM rag_chunks WHERE index_id = ?1 AND source = ?2")?;
+            let ids = stmt.query_map(rusqlite::params![index_id, source], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<i64>>>()?;
+            ids
+        };
+        self.delete(&ids)
+    }
+
+    // Id and text of each chunk stored for a source, for updating it in place
+    pub fn source_texts(&self, index: &str, source: &str) -> Result<Vec<(i64, String)>> {
+        let index_id = self.index_id(index)?;
+        let mut stmt = self.connection.prepare("SELECT id, text FROM rag_chunks WHERE index_id = ?1 AND source = ?2 ORDER BY id")?;
+        let texts = stmt.query_map(rusqlite::params![index_id, source], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(texts)
+    }
+
+    // Id and text of every chunk in an index, for re-embedding
+    pub fn index_texts(&self, index: &str) -> Result<Vec<(i64, String)>> {
+        let index_id = self.index_id(index)?;
+        let mut stmt = self.connection.prepare("SELECT id, text FROM rag_chunks WHERE index_id = ?1 ORDER BY id")?;
+        let texts = stmt.query_map([index_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(texts)
+    }
+
+    // Swaps the vectors of the given chunks for new ones, e.g. after changing
+    // the embedding model. Chunks that were deleted in the meantime are skipped.
+    pub fn replace_embeddings(&mut self, embedding_model: &str, ids: &[i64], embeddings: &[Vec<f32>]) -> Result<()> {
+        if ids.len() != embeddings.len() {
+            return Err(anyhow::anyhow!("Got {} embeddings for {} chunks", embeddings.len(), ids.len()));
+        }
+
+        let mut updates = Vec::with_capacity(ids.len());
+        let mut touched: Vec<i64> = Vec::new();
+        for (&id, embedding) in ids.iter().zip(embeddings) {
+            let location: Option<(i64, usize)> = self.connection
+                .query_row("SELECT index_id, dimensions FROM rag_chunks WHERE id = ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            let Some((index_id, old_dimensions)) = location else {
+                continue;
+            };
+            self.create_vector_table(index_id, embedding.len())?;
+            if !touched.contains(&index_id) {
+                touched.push(index_id);
+            }
+            updates.push((id, index_id, old_dimensions, embedding));
+        }
+
+        let tx = self.connection.transaction()?;
+        for (id, index_id, old_dimensions, embedding) in updates {
+            tx.execute(&format!("DELETE FROM {} WHERE rowid = ?1", Self::vector_table(index_id, old_dimensions)), [id])?;
+            tx.execute(
+                &format!("INSERT INTO {} (rowid, embedding, embedding_model) VALUES (?1, ?2, ?3)", Self::vector_table(index_id, embedding.len())),
+                rusqlite::params![id, vector_blob(embedding), embedding_model],
+            )?;
+            tx.execute(
+                "UPDATE rag_chunks SET embedding_model = ?1, dimensions = ?2 WHERE id = ?3",
+                rusqlite::params![embedding_model, embedding.len(), id],
+            )?;
+        }
+        tx.commit()?;
+
+        for index_id in touched {
+            self.touch_index(index_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_chunk(&self, id: i64) -> Result<Option<StoredChunk>> {
+        let chunk = self.connection
+            .query_row(
+                "SELECT id, text, source, metadata, embedding_model, created_at FROM rag_chunks WHERE id = ?1",
+                [id],
+                |row| {
+                    let metadata: String = row.get(3)?;
+                    Ok(StoredChunk {
+                        id: row.get(0)?,
+                        text: row.get(1)?,
+                        source: row.get(2)?,
+                        metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                        embedding_model: row.get(4)?,
+                        created_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(chunk)
+    }
+
+    // The `k` chunks embedded with `embedding_model` that are closest to the query, best first
+    pub fn search(&self, index: &str, embedding_model: &str, query_embedding: &[f32], k: usize) -> Result<Vec<SearchHit>> {
+        let table = Self::vector_table(self.index_id(index)?, query_embedding.len());
+        if k == 0 || query_embedding.is_empty() || !self.has_table(&table)? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT rowid, distance FROM {} WHERE embedding MATCH ?1 AND k = ?2 AND embedding_model = ?3 ORDER BY distance",
+            table
+        ))?;
+        let neighbours = stmt
+            .query_map(
+                rusqlite::params![vector_blob(query_embedding), k.min(MAX_SEARCH_RESULTS), embedding_model],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut hits = Vec::with_capacity(neighbours.len());
+        for (id, distance) in neighbours {
+            if let Some(chunk) = self.get_chunk(id)? {
+                let similarity = 1.0 - distance as f32;
+                hits.push(SearchHit { chunk, similarity: Some(similarity), score: similarity });
+            }
+        }
+        Ok(hits)
+    }
+
+    // Ids of the `k` chunks that best match the query's words by BM25, best first
+    pub fn keyword_search(&self, index: &str, query: &str, k: usize) -> Result<Vec<i64>> {
+        let index_id = self.index_id(index)?;
+        let query = keyword_query(query);
+        if k == 0 || query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.connection.prepare(
+            "SELECT rag_chunks_fts.rowid FROM rag_chunks_fts JOIN rag_chunks ON rag_chunks.id = rag_chunks_fts.rowid
+             WHERE rag_chunks_fts MATCH ?1 AND rag_chunks.index_id = ?2 ORDER BY rank LIMIT ?3",
+        )?;
+        let ids = stmt.query_map(rusqlite::params![query, index_id, k], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
+    // Vector and keyword results merged by reciprocal rank fusion. `keyword_weight`
+    // runs from 0.0 (vectors only) to 1.0 (keywords only); without an embedding
+    // only the keyword search is used.
+    pub fn hybrid_search(
+        &self,
+        index: &str,
+        query: &str,
+        embedding: Option<(&str, &[f32])>,
+        k: usize,
+        keyword_weight: f32,
+    ) -> Result<Vec<SearchHit>> {
+        // Both searches look deeper than k so fusion has overlap to work with
+        let depth = (k * 4).max(20);
+        let keyword_weight = if embedding.is_some() { keyword_weight } else { 1.0 };
+
+        let semantic = match embedding {
+            Some((model, query_embedding)) if keyword_weight < 1.0 => self.search(index, model, query_embedding, depth)?,
+            _ => Vec::new(),
+        };
+        let keyword = if keyword_weight > 0.0 { self.keyword_search(index, query, depth)? } else { Vec::new() };
+
+        let semantic_ids: Vec<i64> = semantic.iter().map(|hit| hit.chunk.id).collect();
+        let fused = rag::reciprocal_rank_fusion(&[(&semantic_ids, 1.0 - keyword_weight), (&keyword, keyword_weight)]);
+
+        let mut hits = Vec::with_capacity(k);
+        for (id, score) in fused.into_iter().take(k) {
+            let hit = match semantic.iter().find(|hit| hit.chunk.id == id) {
+                Some(hit) => SearchHit { score, ..hit.clone() },
+                None => match self.get_chunk(id)? {
+                    Some(chunk) => SearchHit { chunk, similarity: None, score },
+                    None => continue,
+                },
+            };
+            hits.push(hit);
+        }
+        Ok(hits)
+    }
+}
+
+// FTS5 query matching any of the query's words. Each word is quoted so that
+// punctuation in serial numbers and the like isn't read as query syntax.
+fn keyword_query(query: &str) -> String {
+    query.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"", word.replace('"', "")))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+// vec0 takes vectors as little-endian f32 blobs
+fn vector_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn open_store(app_handle: &tauri::AppHandle) -> Result<VectorStore, String> {
+    VectorStore::new(app_handle).map_err(|e| format!("Vector store error: {}", e))
+}
+
+// Checked before embedding so a typo doesn't cost a full embedding run
+pub fn require_index(app_handle: &tauri::AppHandle, index: &str) -> Result<(), String> {
+    match open_store(app_handle)?.has_index(index) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("RAG index '{}' does not exist", index)),
+        Err(e) => Err(format!("Vector store error: {}", e)),
+    }
+}
+
+// Embeds texts in batches, all with the same model
+pub async fn embed_in_batches(ai: &AdvancedAI, texts: &[String]) -> Result<(String, Vec<Vec<f32>>)> {
+    embed_in_batches_with_progress(ai, texts, |_| {}).await
+}
+
+// Same, calling `on_batch` with the number of texts embedded so far after each batch
+pub async fn embed_in_batches_with_progress(
+    ai: &AdvancedAI,
+    texts: &[String],
+    mut on_batch: impl FnMut(usize),
+) -> Result<(String, Vec<Vec<f32>>)> {
+    let mut embedding_model = String::new();
+    let mut embeddings = Vec::with_capacity(texts.len());
+
+    for batch in texts.chunks(EMBED_BATCH_SIZE) {
+        let (model, batch_embeddings) = ai.embed_with_model(batch).await?;
+        // A fallback to the local model partway through would mix vector spaces
+        if !embedding_model.is_empty() && model != embedding_model {
+            return Err(anyhow::anyhow!("Embedding model changed from '{}' to '{}' partway through; please retry", embedding_model, model));
+        }
+        embedding_model = model;
+        embeddings.extend(batch_embeddings);
+        on_batch(embeddings.len());
+    }
+    Ok((embedding_model, embeddings))
+}
+
+#[command]
+pub async fn add_rag_chunks(
+    chunks: Vec<NewChunk>,
+    index: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<Vec<i64>, String> {
+    if chunks.iter().any(|chunk| chunk.text.trim().is_empty()) {
+        return Err("Chunk text must not be empty".to_string());
+    }
+    let index = index.unwrap_or_else(|| DEFAULT_INDEX.to_string());
+    require_index(&app_handle, &index)?;
+
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+    let (model, embeddings) = embed_in_batches(&ai, &texts).await
+        .map_err(|e| format!("Embedding error: {}", e))?;
+
+    open_store(&app_handle)?
+        .insert(&index, &model, &chunks, &embeddings)
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn delete_rag_chunks(ids: Vec<i64>, app_handle: tauri::AppHandle) -> Result<usize, String> {
+    open_store(&app_handle)?
+        .delete(&ids)
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn search_rag_chunks(
+    query: String,
+    k: Option<usize>,
+    index: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<Vec<SearchHit>, String> {
+    let k = k.unwrap_or_else(|| ai.get_rag_settings().top_k);
+    let index = index.unwrap_or_else(|| DEFAULT_INDEX.to_string());
+    ai.retrieve(&query, &index, k, &app_handle).await
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn create_rag_index(name: String, app_handle: tauri::AppHandle) -> Result<RagIndex, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Index name must not be empty".to_string());
+    }
+    open_store(&app_handle)?
+        .create_index(name)
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn list_rag_indexes(app_handle: tauri::AppHandle) -> Result<Vec<RagIndex>, String> {
+    open_store(&app_handle)?
+        .list_indexes()
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn delete_index(name: String, app_handle: tauri::AppHandle) -> Result<usize, String> {
+    open_store(&app_handle)?
+        .delete_index(&name)
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+#[command]
+pub async fn index_stats(name: Option<String>, app_handle: tauri::AppHandle) -> Result<IndexStats, String> {
+    open_store(&app_handle)?
+        .index_stats(name.as_deref().unwrap_or(DEFAULT_INDEX))
+        .map_err(|e| format!("Vector store error: {}", e))
+}
+
+// Re-embeds every chunk of an index with the current embedding model, e.g.
+// after switching models; search only compares vectors from the same model
+#[command]
+pub async fn rebuild_index(
+    name: Option<String>,
+    app_handle: tauri::AppHandle,
+    ai: State<'_, AdvancedAI>,
+) -> Result<IndexStats, String> {
+    let name = name.unwrap_or_else(|| DEFAULT_INDEX.to_string());
+    let (ids, texts): (Vec<i64>, Vec<String>) = open_store(&app_handle)?
+        .index_texts(&name)
+        .map_err(|e| format!("Vector store error: {}", e))?
+        .into_iter()
+        .unzip();
+
+    if !texts.is_empty() {
+        let (model, embeddings) = embed_in_batches(&ai, &texts).await
+            .map_err(|e| format!("Embedding error: {}", e))?;
+        open_store(&app_handle)?
+            .replace_embeddings(&model, &ids, &embeddings)
+            .map_err(|e| format!("Vector store error: {}", e))?;
+    }
+
+    open_store(&app_handle)?
+        .index_stats(&name)
+        .map_err(|e| format!("Vector store error: {}", e))
+}
@@ -0,0 +1,43 @@
+use tauri::command;
+use crate::database::{Database, VocabularyTerm};
+
+// Whisper's prompt has room for about this much text anyway
+const MAX_TERM_LENGTH: usize = 100;
+
+// The terms every transcription is biased towards, oldest first
+pub fn terms(db: &Database) -> rusqlite::Result<Vec<String>> {
+    Ok(db.get_vocabulary_terms()?.into_iter().map(|term| term.term).collect())
+}
+
+// Adds a name, place or bit of jargon for Whisper to expect, so it is
+// spelled the way the case spells it. Applies to transcriptions started
+// after, and to live transcription the next time capture starts.
+#[command]
+pub async fn add_vocabulary_term(term: String, app_handle: tauri::AppHandle) -> Result<VocabularyTerm, String> {
+    let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+    if term.is_empty() {
+        return Err("A vocabulary term can't be empty".to_string());
+    }
+    if term.chars().count() > MAX_TERM_LENGTH {
+        return Err(format!("Vocabulary terms can be at most {} characters", MAX_TERM_LENGTH));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let id = db.save_vocabulary_term(&term).map_err(|e| format!("Database error: {}", e))?;
+    db.get_vocabulary_terms()
+        .map_err(|e| format!("Database error: {}", e))?
+        .into_iter()
+        .find(|saved| saved.id == Some(id))
+        .ok_or_else(|| format!("Vocabulary term {} not found", id))
+}
+
+#[command]
+pub async fn list_vocabulary_terms(app_handle: tauri::AppHandle) -> Result<Vec<VocabularyTerm>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_vocabulary_terms().map_err(|e| format!("Database error: {}", e))
+}
+
+#[command]
+pub async fn remove_vocabulary_term(term_id: i64, app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.delete_vocabulary_term(term_id).map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,291 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tauri::{command, Emitter, Manager, State};
+use tokio::sync::Notify;
+use crate::audio_capture::{self, AudioCapture};
+use crate::database::{Database, ImportedFile, WatchFolder};
+use crate::import;
+use crate::transcription_queue;
+
+pub const FILE_IMPORTED_EVENT: &str = "dwight://file-imported";
+
+// Folders are scanned when the OS says they changed, once changes have
+// stopped for SETTLE. A file is only imported once it is the same size on
+// two scans in a row, so one still being copied in isn't taken half-written.
+const SETTLE: Duration = Duration::from_secs(3);
+
+// Also scanned this often, for changes the OS watch missed or couldn't see,
+// such as on network shares, or every TICK when there is no OS watch at all
+const RESCAN: Duration = Duration::from_secs(600);
+const TICK: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileImported {
+    pub folder_id: i64,
+    pub source_path: String,
+    pub record_id: Option<i64>,
+    pub file_path: Option<String>,
+    pub error: Option<String>,
+}
+
+// Wakes the background task; the folders themselves are in the database
+pub struct FolderWatcher {
+    wake: Notify,
+}
+
+impl FolderWatcher {
+    pub fn new() -> Self {
+        FolderWatcher { wake: Notify::new() }
+    }
+}
+
+// Size and modification time, in seconds since the epoch
+type Stamp = (i64, i64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+    Some((metadata.len() as i64, modified))
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| import::AUDIO_EXTENSIONS.iter().any(|wanted| extension.eq_ignore_ascii_case(wanted)))
+}
+
+// Audio files in the folder and its subfolders, leaving out hidden ones
+fn audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => audio_files(&path, files),
+            Ok(kind) if kind.is_file() && is_audio(&path) => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+// Named after the folder it came from, so searching by folder finds it
+fn import_title(folder: &Path, source: &Path) -> String {
+    let folder = folder.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    format!("{}: {}", folder, stem)
+}
+
+async fn import(app_handle: &tauri::AppHandle, folder: &WatchFolder, folder_id: i64, source: PathBuf, (source_len, source_modified): Stamp) {
+    let settings = app_handle.state::<AudioCapture>().get_settings();
+    let title = import_title(Path::new(&folder.path), &source);
+    let handle = app_handle.clone();
+    let path = source.clone();
+    let imported = tokio::task::spawn_blocking(move || import::import_file(&handle, &path, &title, &settings))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|imported| imported.map_err(|e| e.to_string()));
+
+    let source_path = source.to_string_lossy().to_string();
+    let record = ImportedFile {
+        source_path: source_path.clone(),
+        source_len,
+        source_modified,
+        folder_id: Some(folder_id),
+        record_id: imported.as_ref().ok().map(|imported| imported.record_id),
+        error: imported.as_ref().err().cloned(),
+        imported_at: String::new(),
+    };
+    if let Err(e) = Database::new(app_handle).and_then(|db| db.save_imported_file(&record)) {
+        eprintln!("Failed to log import of {}: {}", source.display(), e);
+    }
+    let event = FileImported {
+        folder_id,
+        source_path,
+        record_id: record.record_id,
+        file_path: imported.as_ref().ok().map(|imported| imported.file_path.clone()),
+        error: record.error,
+    };
+    if let Err(e) = app_handle.emit(FILE_IMPORTED_EVENT, event) {
+        eprintln!("Failed to emit file imported event: {}", e);
+    }
+
+    let Ok(imported) = imported else {
+        return;
+    };
+    if folder.transcribe {
+        if let Err(e) = transcription_queue::enqueue(app_handle, imported.record_id) {
+            eprintln!("Failed to queue transcription of imported {}: {}", source.display(), e);
+        }
+    }
+}
+
+// Imports the files that are new or changed since their last import and
+// have settled since the last scan. Returns what hasn't settled yet.
+async fn tick(app_handle: &tauri::AppHandle, unsettled: HashMap<PathBuf, Stamp>) -> HashMap<PathBuf, Stamp> {
+    let folders = match Database::new(app_handle).and_then(|db| db.get_watch_folders()) {
+        Ok(folders) => folders,
+        Err(e) => {
+            eprintln!("Failed to load watch folders: {}", e);
+            return unsettled;
+        }
+    };
+
+    let mut waiting = HashMap::new();
+    for folder in folders.iter().filter(|folder| folder.enabled) {
+        let Some(folder_id) = folder.id else {
+            continue;
+        };
+        let dir = PathBuf::from(&folder.path);
+        let files = tokio::task::spawn_blocking(move || {
+            let mut files = Vec::new();
+            audio_files(&dir, &mut files);
+            files
+        })
+        .await
+        .unwrap_or_default();
+
+        for source in files {
+            let Some(current) = stamp(&source) else {
+                continue;
+            };
+            let known = Database::new(app_handle)
+                .and_then(|db| db.get_imported_file(&source.to_string_lossy()))
+                .ok()
+                .flatten()
+                .is_some_and(|file| (file.source_len, file.source_modified) == current);
+            if known {
+                continue;
+            }
+            if unsettled.get(&source) != Some(&current) {
+                waiting.insert(source, current);
+                continue;
+            }
+            import(app_handle, folder, folder_id, source, current).await;
+        }
+    }
+    waiting
+}
+
+// An OS watch that wakes the task whenever something in a folder changes
+fn os_watcher(app_handle: &tauri::AppHandle) -> Option<RecommendedWatcher> {
+    let handle = app_handle.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if event.kind.is_access() => {}
+        Ok(_) => handle.state::<FolderWatcher>().wake.notify_one(),
+        Err(e) => eprintln!("Watch folder event error: {}", e),
+    });
+    watcher.map_err(|e| eprintln!("Watch folders fall back to scanning every {} seconds: {}", TICK.as_secs(), e)).ok()
+}
+
+// Watches the enabled folders and stops watching the rest
+fn update_watches(app_handle: &tauri::AppHandle, watcher: &mut RecommendedWatcher, watched: &mut HashSet<PathBuf>) {
+    let Ok(folders) = Database::new(app_handle).and_then(|db| db.get_watch_folders()) else {
+        return;
+    };
+    let wanted: HashSet<PathBuf> = folders.iter().filter(|folder| folder.enabled).map(|folder| PathBuf::from(&folder.path)).collect();
+    for dir in watched.difference(&wanted) {
+        let _ = watcher.unwatch(dir);
+    }
+    watched.retain(|dir| wanted.contains(dir));
+    for dir in wanted {
+        if !watched.contains(&dir) {
+            match watcher.watch(&dir, RecursiveMode::Recursive) {
+                Ok(()) => {
+                    watched.insert(dir);
+                }
+                Err(e) => eprintln!("Failed to watch {}, it is only rescanned: {}", dir.display(), e),
+            }
+        }
+    }
+}
+
+// Started once from setup and runs for the life of the app
+pub fn start_watcher(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut os_watcher = os_watcher(&app_handle);
+        let mut watched = HashSet::new();
+        let mut unsettled = HashMap::new();
+        loop {
+            if let Some(os_watcher) = os_watcher.as_mut() {
+                update_watches(&app_handle, os_watcher, &mut watched);
+            }
+            unsettled = tick(&app_handle, unsettled).await;
+            let wait = match (&os_watcher, unsettled.is_empty()) {
+                (_, false) => SETTLE,
+                (Some(_), true) => RESCAN,
+                (None, true) => TICK,
+            };
+            let watcher = app_handle.state::<FolderWatcher>();
+            if tokio::time::timeout(wait, watcher.wake.notified()).await.is_ok() {
+                // Let a burst of changes finish before scanning
+                tokio::time::sleep(SETTLE).await;
+            }
+        }
+    });
+}
+
+#[command]
+pub async fn list_watch_folders(app_handle: tauri::AppHandle) -> Result<Vec<WatchFolder>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_watch_folders().map_err(|e| format!("Database error: {}", e))
+}
+
+// Audio already in the folder is imported too
+#[command]
+pub async fn add_watch_folder(
+    path: String,
+    transcribe: Option<bool>,
+    app_handle: tauri::AppHandle,
+    watcher: State<'_, FolderWatcher>,
+) -> Result<WatchFolder, String> {
+    let dir = std::fs::canonicalize(path.trim()).map_err(|e| format!("Can't watch '{}': {}", path, e))?;
+    if !dir.is_dir() {
+        return Err(format!("'{}' is not a folder", path));
+    }
+    let recordings = audio_capture::recordings_dir(&app_handle).map_err(|e| format!("Watch folder error: {}", e))?;
+    // Imports go there, so watching it would import them again
+    if std::fs::canonicalize(&recordings).is_ok_and(|recordings| recordings.starts_with(&dir) || dir.starts_with(&recordings)) {
+        return Err("The recordings folder can't be watched".to_string());
+    }
+
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let path = dir.to_string_lossy().to_string();
+    if db.get_watch_folders().map_err(|e| format!("Database error: {}", e))?.iter().any(|folder| folder.path == path) {
+        return Err(format!("'{}' is already watched", path));
+    }
+    let folder = WatchFolder { id: None, path, transcribe: transcribe.unwrap_or(true), enabled: true, created_at: String::new() };
+    let id = db.save_watch_folder(&folder).map_err(|e| format!("Database error: {}", e))?;
+    watcher.wake.notify_one();
+    db.get_watch_folders()
+        .map_err(|e| format!("Database error: {}", e))?
+        .into_iter()
+        .find(|saved| saved.id == Some(id))
+        .ok_or_else(|| format!("Watch folder {} not found", id))
+}
+
+// Recordings already imported from it are kept
+#[command]
+pub async fn remove_watch_folder(folder_id: i64, app_handle: tauri::AppHandle, watcher: State<'_, FolderWatcher>) -> Result<(), String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    if !db.delete_watch_folder(folder_id).map_err(|e| format!("Database error: {}", e))? {
+        return Err(format!("Watch folder {} not found", folder_id));
+    }
+    watcher.wake.notify_one();
+    Ok(())
+}
+
+// The files found in a watch folder, newest first, with the recordings
+// they became or why they couldn't be imported
+#[command]
+pub async fn get_imported_files(folder_id: i64, app_handle: tauri::AppHandle) -> Result<Vec<ImportedFile>, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    db.get_imported_files(folder_id).map_err(|e| format!("Database error: {}", e))
+}
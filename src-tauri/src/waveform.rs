@@ -0,0 +1,155 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::command;
+use crate::audio_capture;
+use crate::audio_file;
+use crate::database::Database;
+
+// Peaks are cached at this many frames per pair, and every resolution the UI
+// asks for is merged from them rather than read from the audio again
+const FRAMES_PER_PEAK: u64 = 256;
+const MAX_RESOLUTION: usize = 100_000;
+
+const CACHE_MAGIC: &[u8; 8] = b"DWPEAKS1";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WaveformPeaks {
+    pub recording_id: i64,
+    pub duration_seconds: f64,
+    // Fewer than asked for when the recording is too short to fill them
+    pub resolution: usize,
+    // Lowest and highest sample in each bucket, across all channels
+    pub peaks: Vec<(f32, f32)>,
+}
+
+// What the cache was made from; a different size or time means a new file
+#[derive(PartialEq)]
+struct SourceStamp {
+    len: u64,
+    modified: u64,
+}
+
+fn source_stamp(path: &Path) -> Result<SourceStamp> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    Ok(SourceStamp { len: metadata.len(), modified })
+}
+
+fn compute_peaks(path: &Path) -> Result<Vec<(f32, f32)>> {
+    let channels = audio_file::audio_spec(path)?.channels.max(1) as usize;
+    let samples_per_peak = FRAMES_PER_PEAK as usize * channels;
+    let mut peaks = Vec::new();
+    let mut current = (f32::MAX, f32::MIN);
+    let mut filled = 0;
+    audio_file::for_each_chunk(path, |chunk| {
+        for &sample in chunk {
+            current = (current.0.min(sample), current.1.max(sample));
+            filled += 1;
+            if filled == samples_per_peak {
+                peaks.push(current);
+                current = (f32::MAX, f32::MIN);
+                filled = 0;
+            }
+        }
+        Ok(())
+    })?;
+    if filled > 0 {
+        peaks.push(current);
+    }
+    Ok(peaks)
+}
+
+fn read_cache(cache_path: &Path, stamp: &SourceStamp) -> Option<Vec<(f32, f32)>> {
+    let mut reader = BufReader::new(File::open(cache_path).ok()?);
+    let mut header = [0u8; 24];
+    reader.read_exact(&mut header).ok()?;
+    let word = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap_or_default());
+    if &header[..8] != CACHE_MAGIC || (SourceStamp { len: word(8), modified: word(16) }) != *stamp {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).ok()?;
+    Some(bytes.chunks_exact(8).map(|pair| {
+        (f32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]), f32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]))
+    }).collect())
+}
+
+fn write_cache(cache_path: &Path, stamp: &SourceStamp, peaks: &[(f32, f32)]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(cache_path)?);
+    writer.write_all(CACHE_MAGIC)?;
+    writer.write_all(&stamp.len.to_le_bytes())?;
+    writer.write_all(&stamp.modified.to_le_bytes())?;
+    for &(low, high) in peaks {
+        writer.write_all(&low.to_le_bytes())?;
+        writer.write_all(&high.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// The cached peaks of a file, computed and cached first if need be. A
+// cache that can't be written only costs speed next time.
+fn cached_peaks(source: &Path, cache_dir: &Path) -> Result<Vec<(f32, f32)>> {
+    let stamp = source_stamp(source)?;
+    let name = source.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let cache_path = cache_dir.join(format!("{}.peaks", name));
+    if let Some(peaks) = read_cache(&cache_path, &stamp) {
+        return Ok(peaks);
+    }
+    let peaks = compute_peaks(source)?;
+    if let Err(e) = write_cache(&cache_path, &stamp, &peaks) {
+        eprintln!("Failed to cache waveform peaks for {}: {}", source.display(), e);
+    }
+    Ok(peaks)
+}
+
+fn merge(peaks: &[(f32, f32)], resolution: usize) -> Vec<(f32, f32)> {
+    if peaks.len() <= resolution {
+        return peaks.to_vec();
+    }
+    (0..resolution)
+        .map(|bucket| {
+            let (from, to) = (bucket * peaks.len() / resolution, (bucket + 1) * peaks.len() / resolution);
+            peaks[from..to].iter().fold((f32::MAX, f32::MIN), |(low, high), &(min, max)| (low.min(min), high.max(max)))
+        })
+        .collect()
+}
+
+fn peaks_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = audio_capture::recordings_dir(app_handle)?.join("peaks");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Min/max pairs for drawing a recording `resolution` pixels wide, so the UI
+// never has to be sent the samples themselves
+#[command]
+pub async fn get_waveform_peaks(recording_id: i64, resolution: usize, app_handle: tauri::AppHandle) -> Result<WaveformPeaks, String> {
+    if !(1..=MAX_RESOLUTION).contains(&resolution) {
+        return Err(format!("resolution must be between 1 and {}", MAX_RESOLUTION));
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+
+    let source = PathBuf::from(&record.file_path);
+    let (_, duration_seconds) = audio_file::audio_info(&source).map_err(|e| format!("Waveform error: {}", e))?;
+    let cache_dir = peaks_dir(&app_handle).map_err(|e| format!("Waveform error: {}", e))?;
+    let peaks = tokio::task::spawn_blocking(move || cached_peaks(&source, &cache_dir))
+        .await
+        .map_err(|e| format!("Waveform error: {}", e))?
+        .map_err(|e| format!("Waveform error: {}", e))?;
+
+    let peaks = merge(&peaks, resolution);
+    Ok(WaveformPeaks {
+        recording_id,
+        duration_seconds,
+        resolution: peaks.len(),
+        peaks,
+    })
+}
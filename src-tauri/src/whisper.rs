@@ -1,8 +1,17 @@
-use tauri::command;
+use tauri::{command, State};
 use std::path::Path;
 use std::process::Command;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
+use crate::audio_capture::AudioCapture;
+use crate::audio_file;
+use crate::database::{Database, TranscriptLine, TranscriptVersion, TranscriptWord};
+use crate::diarization;
+use crate::resampler::ResampleQuality;
+use crate::transcript_cleanup;
+use crate::transcript_index;
+
+const WHISPER_SAMPLE_RATE: u32 = 16000;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WhisperConfig {
@@ -13,6 +22,17 @@ pub struct WhisperConfig {
     pub use_gpu: bool,
 }
 
+// What produced a transcript, kept with each version of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSource {
+    pub engine: String,
+    pub model: String,
+    pub settings: serde_json::Value,
+    // Set when a rollback stores an old version again
+    #[serde(default)]
+    pub restored_from: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TranscriptionResult {
     pub text: String,
@@ -22,12 +42,78 @@ pub struct TranscriptionResult {
     pub confidence: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TranscriptionSegment {
     pub start: f64,
     pub end: f64,
     pub text: String,
     pub confidence: f32,
+    // Empty when the transcriber doesn't time words
+    #[serde(default)]
+    pub words: Vec<TranscriptWord>,
+    // Detected for each segment when no language was asked for
+    #[serde(default)]
+    pub language: Option<String>,
+    // Mean log-probability of the segment's tokens; confidence is its exp.
+    // None when the transcriber doesn't report it.
+    #[serde(default)]
+    pub avg_logprob: Option<f64>,
+}
+
+impl TranscriptionSegment {
+    pub fn to_line(&self) -> TranscriptLine {
+        TranscriptLine {
+            start_seconds: self.start,
+            end_seconds: self.end,
+            text: self.text.trim().to_string(),
+            confidence: self.confidence,
+            words: self.words.clone(),
+            language: self.language.clone(),
+            speaker: None,
+            avg_logprob: self.avg_logprob,
+        }
+    }
+
+    pub fn from_line(line: &TranscriptLine) -> Self {
+        TranscriptionSegment {
+            start: line.start_seconds,
+            end: line.end_seconds,
+            text: line.text.clone(),
+            confidence: line.confidence,
+            words: line.words.clone(),
+            language: line.language.clone(),
+            avg_logprob: line.avg_logprob,
+        }
+    }
+}
+
+// The language the segments spend the longest in, if any were tagged
+pub fn main_language(segments: &[TranscriptionSegment]) -> Option<String> {
+    let mut spoken: Vec<(&str, f64)> = Vec::new();
+    for segment in segments {
+        let Some(language) = segment.language.as_deref() else { continue };
+        let seconds = (segment.end - segment.start).max(0.0);
+        match spoken.iter_mut().find(|(code, _)| *code == language) {
+            Some((_, total)) => *total += seconds,
+            None => spoken.push((language, seconds)),
+        }
+    }
+    spoken.into_iter().max_by(|(_, a), (_, b)| a.total_cmp(b)).map(|(code, _)| code.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackTranscript {
+    pub channel: i64,
+    // "microphone" or "system_audio"
+    pub source: String,
+    pub result: TranscriptionResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackTranscription {
+    pub tracks: Vec<TrackTranscript>,
+    // Every track's segments in order, each text led by whose side it is
+    pub segments: Vec<TranscriptionSegment>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -75,6 +161,8 @@ pub struct EmotionalTone {
 
 pub struct WhisperEngine {
     config: WhisperConfig,
+    // A made-up transcript stands in when whisper.cpp can't run
+    simulate: bool,
 }
 
 impl WhisperEngine {
@@ -86,13 +174,42 @@ impl WhisperEngine {
                 language: None,
                 use_cpp: true,
                 use_gpu: false,
-            }
+            },
+            simulate: true,
+        }
+    }
+
+    // Fails when whisper.cpp can't run rather than simulating a transcript,
+    // for anything that stores what it returns
+    pub fn strict() -> Self {
+        WhisperEngine { simulate: false, ..Self::new() }
+    }
+
+    // What this engine's transcripts are stored as coming from
+    pub fn source(&self) -> TranscriptSource {
+        TranscriptSource {
+            engine: "whisper.cpp".to_string(),
+            model: self.config.model_size.clone(),
+            settings: serde_json::json!({
+                "language": self.config.language,
+                "use_gpu": self.config.use_gpu,
+            }),
+            restored_from: None,
         }
     }
     
     pub async fn transcribe_with_whisper_cpp(&self, file_path: &str) -> Result<TranscriptionResult> {
         let start_time = std::time::Instant::now();
-        
+        match self.run_whisper_cpp(file_path, start_time) {
+            Err(e) if self.simulate => {
+                eprintln!("{}; simulating the transcription", e);
+                self.simulate_transcription(file_path, start_time).await
+            }
+            result => result,
+        }
+    }
+
+    fn run_whisper_cpp(&self, file_path: &str, start_time: std::time::Instant) -> Result<TranscriptionResult> {
         // Check if whisper.cpp is available
         let whisper_cpp_path = "whisper"; // Assumes whisper.cpp is in PATH
         
@@ -110,25 +227,17 @@ impl WhisperEngine {
             cmd.arg("-ngl").arg("1");
         }
         
-        match cmd.output() {
-            Ok(output) => {
-                if output.status.success() {
-                    // Parse whisper.cpp JSON output
-                    if let Ok(json_content) = std::fs::read_to_string("/tmp/whisper_output.json") {
-                        if let Ok(whisper_result) = serde_json::from_str::<serde_json::Value>(&json_content) {
-                            return self.parse_whisper_output(whisper_result, start_time);
-                        }
-                    }
-                }
-            }
-            Err(_) => {
-                // Fallback to simulated transcription if whisper.cpp is not available
-                return self.simulate_transcription(file_path, start_time).await;
-            }
+        let output = cmd.output()
+            .map_err(|e| anyhow::anyhow!("whisper.cpp isn't available: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("whisper.cpp failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
         }
-        
-        // Fallback
-        self.simulate_transcription(file_path, start_time).await
+        // Parse whisper.cpp JSON output
+        let json_content = std::fs::read_to_string("/tmp/whisper_output.json")
+            .map_err(|e| anyhow::anyhow!("whisper.cpp left no output: {}", e))?;
+        let whisper_result = serde_json::from_str::<serde_json::Value>(&json_content)
+            .map_err(|e| anyhow::anyhow!("whisper.cpp output isn't JSON: {}", e))?;
+        self.parse_whisper_output(whisper_result, start_time)
     }
     
     fn parse_whisper_output(&self, whisper_result: serde_json::Value, start_time: std::time::Instant) -> Result<TranscriptionResult> {
@@ -146,6 +255,8 @@ impl WhisperEngine {
                     end: segment["end"].as_f64().unwrap_or(0.0),
                     text: segment["text"].as_str().unwrap_or("").to_string(),
                     confidence: segment["confidence"].as_f64().unwrap_or(0.8) as f32,
+                    avg_logprob: segment["avg_logprob"].as_f64(),
+                    ..Default::default()
                 });
             }
         }
@@ -177,24 +288,28 @@ impl WhisperEngine {
                     end: 2.5,
                     text: "Hello, how are you today?".to_string(),
                     confidence: 0.92,
+                    ..Default::default()
                 },
                 TranscriptionSegment {
                     start: 3.0,
                     end: 6.8,
                     text: "I'm doing well, thanks for asking. How about you?".to_string(),
                     confidence: 0.88,
+                    ..Default::default()
                 },
                 TranscriptionSegment {
                     start: 7.2,
                     end: 11.1,
                     text: "Pretty good, just working on some audio analysis projects.".to_string(),
                     confidence: 0.90,
+                    ..Default::default()
                 },
                 TranscriptionSegment {
                     start: 11.5,
                     end: 14.8,
                     text: "That sounds interesting. What kind of analysis are you doing?".to_string(),
                     confidence: 0.87,
+                    ..Default::default()
                 },
             ];
             (text.to_string(), segments)
@@ -206,12 +321,14 @@ impl WhisperEngine {
                     end: 5.2,
                     text: "Radio chatter detected. Multiple voices discussing checkpoint procedures.".to_string(),
                     confidence: 0.79,
+                    ..Default::default()
                 },
                 TranscriptionSegment {
                     start: 5.5,
                     end: 9.8,
                     text: "Keywords: security, perimeter, all clear, proceed with caution.".to_string(),
                     confidence: 0.82,
+                    ..Default::default()
                 },
             ];
             (text.to_string(), segments)
@@ -223,6 +340,7 @@ impl WhisperEngine {
                     end: 3.0,
                     text: "Transcription of audio file".to_string(),
                     confidence: 0.85,
+                    ..Default::default()
                 },
             ];
             (text, segments)
@@ -247,7 +365,7 @@ impl WhisperEngine {
             .unwrap_or("unknown");
         
         // Create context-appropriate analysis
-        let analysis = if file_name.to_lowercase().contains("security") {
+        let mut analysis = if file_name.to_lowercase().contains("security") {
             AudioAnalysis {
                 duration_seconds: 125.6,
                 sample_rate: 44100,
@@ -336,12 +454,46 @@ impl WhisperEngine {
             }
         };
         
+        // Whatever the analysis, the format comes from the file itself
+        if let Ok((format, duration)) = audio_file::audio_info(Path::new(file_path)) {
+            analysis.duration_seconds = duration;
+            analysis.sample_rate = format.sample_rate;
+            analysis.channels = format.channels;
+        }
         Ok(analysis)
     }
+
+    // whisper.cpp only reads 16 kHz mono WAV, so anything else, FLAC and Opus included,
+    // is converted to a temporary file first. With `channel` only that channel is transcribed,
+    // such as one speaker's mic on an interface; otherwise channels are mixed.
+    pub async fn transcribe_channel(&self, file_path: &str, channel: Option<u16>, quality: ResampleQuality) -> Result<TranscriptionResult> {
+        let source = Path::new(file_path).to_path_buf();
+        let ready = match audio_file::audio_info(&source) {
+            Ok((format, _)) => {
+                !audio_file::is_flac(&source) && format.sample_rate == WHISPER_SAMPLE_RATE && format.channels == 1 && channel.unwrap_or(0) == 0
+            }
+            // Neither WAV nor FLAC; whisper.cpp can have a go at it as it is
+            Err(_) if channel.is_none() => true,
+            Err(e) => return Err(e),
+        };
+        if ready {
+            return self.transcribe_with_whisper_cpp(file_path).await;
+        }
+
+        let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let suffix = channel.map(|channel| format!("-channel{}", channel)).unwrap_or_default();
+        let converted = std::env::temp_dir().join(format!("{}{}-{}-16k.wav", stem, suffix, std::process::id()));
+        let output = converted.clone();
+        tokio::task::spawn_blocking(move || audio_file::export_mono(&source, &output, channel, WHISPER_SAMPLE_RATE, quality)).await??;
+
+        let result = self.transcribe_with_whisper_cpp(&converted.to_string_lossy()).await;
+        let _ = std::fs::remove_file(&converted);
+        result
+    }
 }
 
 #[command]
-pub async fn transcribe_audio(file_path: String) -> Result<String, String> {
+pub async fn transcribe_audio(file_path: String, channel: Option<u16>, capture: State<'_, AudioCapture>) -> Result<String, String> {
     let engine = WhisperEngine::new();
     
     // Validate file exists
@@ -349,23 +501,262 @@ pub async fn transcribe_audio(file_path: String) -> Result<String, String> {
         return Err(format!("Audio file not found: {}", file_path));
     }
     
-    match engine.transcribe_with_whisper_cpp(&file_path).await {
+    match engine.transcribe_channel(&file_path, channel, capture.get_settings().resample_quality).await {
         Ok(result) => Ok(result.text),
         Err(e) => Err(format!("Transcription failed: {}", e)),
     }
 }
 
+// With a recording id, the transcript is also stored on the recording and
+// indexed for RAG with its segment timestamps
 #[command]
-pub async fn transcribe_audio_detailed(file_path: String) -> Result<TranscriptionResult, String> {
-    let engine = WhisperEngine::new();
+pub async fn transcribe_audio_detailed(
+    file_path: String,
+    recording_id: Option<i64>,
+    channel: Option<u16>,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<TranscriptionResult, String> {
+    let engine = match recording_id {
+        Some(_) => WhisperEngine::strict(),
+        None => WhisperEngine::new(),
+    };
     
     if !Path::new(&file_path).exists() {
         return Err(format!("Audio file not found: {}", file_path));
     }
     
-    engine.transcribe_with_whisper_cpp(&file_path)
+    let result = engine.transcribe_channel(&file_path, channel, capture.get_settings().resample_quality)
         .await
-        .map_err(|e| format!("Detailed transcription failed: {}", e))
+        .map_err(|e| format!("Detailed transcription failed: {}", e))?;
+    
+    if let Some(recording_id) = recording_id {
+        store_transcript(&app_handle, recording_id, &result, &engine.source())?;
+    }
+    Ok(result)
+}
+
+// Keeps a transcript as a version of the recording's, with what produced it
+fn save_version(
+    db: &Database,
+    recording_id: i64,
+    text: &str,
+    language: Option<String>,
+    lines: &[TranscriptLine],
+    source: &TranscriptSource,
+) -> Result<(), String> {
+    db.save_transcript_version(&TranscriptVersion {
+        id: None,
+        record_id: recording_id,
+        engine: source.engine.clone(),
+        model: source.model.clone(),
+        settings: source.settings.clone(),
+        text: text.to_string(),
+        language,
+        lines: lines.to_vec(),
+        restored_from: source.restored_from,
+        created_at: String::new(),
+    })
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+// A transcript from before versions were kept is kept as the first of them,
+// dated with its recording, so the new one doesn't replace it for good
+fn keep_legacy_version(db: &Database, recording_id: i64) -> Result<(), String> {
+    if db.has_transcript_versions(recording_id).map_err(|e| format!("Database error: {}", e))? {
+        return Ok(());
+    }
+    let Some(record) = db.get_audio_record(recording_id).map_err(|e| format!("Database error: {}", e))? else {
+        return Ok(());
+    };
+    let text = record.transcript.unwrap_or_default();
+    if text.trim().is_empty() {
+        return Ok(());
+    }
+    db.save_transcript_version(&TranscriptVersion {
+        id: None,
+        record_id: recording_id,
+        engine: "legacy".to_string(),
+        model: String::new(),
+        settings: serde_json::json!({}),
+        text,
+        language: db.get_recording_language(recording_id).map_err(|e| format!("Database error: {}", e))?,
+        lines: db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?,
+        restored_from: None,
+        created_at: record.created_at,
+    })
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+// Stores a transcription on its recording with its timed lines, keeps it as
+// a new version of the transcript, and queues it for RAG indexing
+pub fn store_transcript(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    result: &TranscriptionResult,
+    source: &TranscriptSource,
+) -> Result<(), String> {
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    keep_legacy_version(&db, recording_id)?;
+    if !db.update_audio_transcript(recording_id, &result.text).map_err(|e| format!("Database error: {}", e))? {
+        return Err(format!("Recording {} not found", recording_id));
+    }
+    let lines: Vec<TranscriptLine> = result.segments.iter().map(TranscriptionSegment::to_line).collect();
+    db.save_transcript_lines(recording_id, &lines).map_err(|e| format!("Database error: {}", e))?;
+    save_version(&db, recording_id, &result.text, Some(result.language.clone()), &lines, source)?;
+    db.save_recording_language(recording_id, &result.language).map_err(|e| format!("Database error: {}", e))?;
+    // A translation of the old transcript would no longer match it
+    db.delete_transcript_translation(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    transcript_cleanup::refresh(app_handle, recording_id)?;
+    transcript_index::queue_recording_update(app_handle, recording_id, &result.segments);
+    Ok(())
+}
+
+// Stores re-transcribed lines in place of the `removed` lines from `first`,
+// leaving the rest of the transcript's rows, speakers and attributions as
+// they are, and keeps the result as a new version. Returns all of the
+// recording's lines.
+pub fn store_transcript_region(
+    app_handle: &tauri::AppHandle,
+    recording_id: i64,
+    first: usize,
+    removed: usize,
+    lines: &[TranscriptLine],
+    source: &TranscriptSource,
+) -> Result<Vec<TranscriptLine>, String> {
+    let db = Database::new(app_handle).map_err(|e| format!("Database error: {}", e))?;
+    keep_legacy_version(&db, recording_id)?;
+    db.replace_transcript_lines(recording_id, first, removed, lines).map_err(|e| format!("Database error: {}", e))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    let text = diarization::labelled_text(&lines);
+    if !db.update_audio_transcript(recording_id, &text).map_err(|e| format!("Database error: {}", e))? {
+        return Err(format!("Recording {} not found", recording_id));
+    }
+    let language = db.get_recording_language(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    save_version(&db, recording_id, &text, language, &lines, source)?;
+    // The translation was of the lines that were replaced
+    db.delete_transcript_translation(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    transcript_cleanup::refresh(app_handle, recording_id)?;
+    transcript_index::queue_recording_update(app_handle, recording_id, &diarization::labelled_segments(&lines));
+    Ok(lines)
+}
+
+// Where a phrase was said, from the first word of it to the last
+#[derive(Debug, Clone, Serialize)]
+pub struct PhraseMatch {
+    // Line the phrase starts in
+    pub line_index: usize,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    // The words as transcribed, punctuation and all
+    pub text: String,
+}
+
+// Lowercase, without the punctuation a word was transcribed with
+fn match_form(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric() || *c == '\'').flat_map(char::to_lowercase).collect()
+}
+
+// Every place the phrase's words were said in a row, even across lines.
+// Lines transcribed without word timings can't be searched.
+pub fn find_phrase(lines: &[TranscriptLine], phrase: &str) -> Vec<PhraseMatch> {
+    let wanted: Vec<String> = phrase.split_whitespace().map(match_form).filter(|word| !word.is_empty()).collect();
+    if wanted.is_empty() {
+        return Vec::new();
+    }
+    let words: Vec<(usize, &TranscriptWord, String)> = lines.iter()
+        .enumerate()
+        .flat_map(|(index, line)| line.words.iter().map(move |word| (index, word, match_form(&word.word))))
+        .filter(|(_, _, form)| !form.is_empty())
+        .collect();
+    words.windows(wanted.len())
+        .filter(|run| run.iter().zip(&wanted).all(|((_, _, form), wanted)| form == wanted))
+        .map(|run| PhraseMatch {
+            line_index: run[0].0,
+            start_seconds: run[0].1.start_seconds,
+            end_seconds: run[run.len() - 1].1.end_seconds,
+            text: run.iter().map(|(_, word, _)| word.word.as_str()).collect::<Vec<_>>().join(" "),
+        })
+        .collect()
+}
+
+// Times of a phrase in a recording's transcript, to jump playback to or
+// pass to export_clip
+#[command]
+pub async fn find_transcript_phrase(recording_id: i64, phrase: String, app_handle: tauri::AppHandle) -> Result<Vec<PhraseMatch>, String> {
+    if phrase.trim().is_empty() {
+        return Err("No phrase to find".to_string());
+    }
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let lines = db.get_transcript_lines(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    if !lines.is_empty() && lines.iter().all(|line| line.words.is_empty()) {
+        return Err(format!("Recording {}'s transcript has no word timings; transcribe it again to search by phrase", recording_id));
+    }
+    Ok(find_phrase(&lines, &phrase))
+}
+
+fn track_label(source: &str) -> &str {
+    match source {
+        "microphone" => "Microphone",
+        "system_audio" => "System audio",
+        other => other,
+    }
+}
+
+// Transcribes each track of a recording made with system audio on its own
+// track, so both sides of a call are told apart. The interleaved transcript
+// is stored on the recording and indexed for RAG.
+#[command]
+pub async fn transcribe_tracks(
+    recording_id: i64,
+    app_handle: tauri::AppHandle,
+    capture: State<'_, AudioCapture>,
+) -> Result<TrackTranscription, String> {
+    let db = Database::new(&app_handle).map_err(|e| format!("Database error: {}", e))?;
+    let record = db.get_audio_record(recording_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Recording {} not found", recording_id))?;
+    let tracks = db.get_recording_tracks(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    if tracks.is_empty() {
+        return Err(format!("Recording {} has no separate tracks", recording_id));
+    }
+
+    let engine = WhisperEngine::strict();
+    let quality = capture.get_settings().resample_quality;
+    let mut transcripts = Vec::new();
+    for track in tracks {
+        let result = engine.transcribe_channel(&record.file_path, Some(track.channel as u16), quality)
+            .await
+            .map_err(|e| format!("Transcription of the {} track failed: {}", track_label(&track.source).to_lowercase(), e))?;
+        transcripts.push(TrackTranscript { channel: track.channel, source: track.source, result });
+    }
+
+    let mut segments: Vec<TranscriptionSegment> = transcripts
+        .iter()
+        .flat_map(|track| track.result.segments.iter().map(|segment| TranscriptionSegment {
+            text: format!("{}: {}", track_label(&track.source), segment.text.trim()),
+            ..segment.clone()
+        }))
+        .collect();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join("\n");
+    keep_legacy_version(&db, recording_id)?;
+    db.update_audio_transcript(recording_id, &text).map_err(|e| format!("Database error: {}", e))?;
+    let lines: Vec<TranscriptLine> = segments.iter().map(TranscriptionSegment::to_line).collect();
+    db.save_transcript_lines(recording_id, &lines).map_err(|e| format!("Database error: {}", e))?;
+    let language = main_language(&segments).or_else(|| transcripts.first().map(|track| track.result.language.clone()));
+    if let Some(language) = &language {
+        db.save_recording_language(recording_id, language).map_err(|e| format!("Database error: {}", e))?;
+    }
+    let mut source = engine.source();
+    source.settings["tracks"] = serde_json::json!(transcripts.len());
+    save_version(&db, recording_id, &text, language, &lines, &source)?;
+    db.delete_transcript_translation(recording_id).map_err(|e| format!("Database error: {}", e))?;
+    transcript_cleanup::refresh(&app_handle, recording_id)?;
+    transcript_index::queue_recording_update(&app_handle, recording_id, &segments);
+    Ok(TrackTranscription { tracks: transcripts, segments })
 }
 
 #[command]
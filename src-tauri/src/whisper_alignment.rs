@@ -0,0 +1,273 @@
+use anyhow::Result;
+use candle_core::{DType, IndexOp, Tensor};
+use candle_nn::{Embedding, LayerNorm, Linear, Module, VarBuilder};
+use candle_transformers::models::whisper::Config;
+use serde::Deserialize;
+use std::path::Path;
+
+// Where the alignment heads are listed, next to the model's weights
+pub const GENERATION_CONFIG: &str = "generation_config.json";
+
+// The encoder gives one frame of audio features every 20 ms
+const FRAMES_PER_SECOND: f64 = 50.0;
+
+// Each head's attention is median filtered over this many frames, as openai's
+// whisper does, so one stray frame doesn't pull a word's time off
+const MEDIAN_WIDTH: usize = 7;
+
+#[derive(Deserialize)]
+struct GenerationConfig {
+    #[serde(default)]
+    alignment_heads: Vec<(usize, usize)>,
+}
+
+// The decoder heads, as (layer, head), whose attention on the audio follows
+// what is being said. Hugging Face lists them for each openai model; a model
+// without them uses every head of the upper half of its layers, as openai's
+// whisper does.
+pub fn alignment_heads(dir: &Path, config: &Config) -> Vec<(usize, usize)> {
+    let listed = std::fs::read_to_string(dir.join(GENERATION_CONFIG))
+        .ok()
+        .and_then(|json| serde_json::from_str::<GenerationConfig>(&json).ok())
+        .map(|generation| generation.alignment_heads)
+        .unwrap_or_default();
+    let heads: Vec<(usize, usize)> = listed.into_iter()
+        .filter(|&(layer, head)| layer < config.decoder_layers && head < config.decoder_attention_heads)
+        .collect();
+    if !heads.is_empty() {
+        return heads;
+    }
+    (config.decoder_layers / 2..config.decoder_layers)
+        .flat_map(|layer| (0..config.decoder_attention_heads).map(move |head| (layer, head)))
+        .collect()
+}
+
+// Splits [batch, tokens, state] into [batch, heads, tokens, state / heads]
+fn split_heads(x: &Tensor, heads: usize) -> Result<Tensor> {
+    let (batch, length, state) = x.dims3()?;
+    Ok(x.reshape((batch, length, heads, state / heads))?.transpose(1, 2)?)
+}
+
+struct Attention {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    out: Linear,
+}
+
+impl Attention {
+    fn load(state: usize, vb: VarBuilder) -> Result<Self> {
+        Ok(Attention {
+            query: candle_nn::linear(state, state, vb.pp("q_proj"))?,
+            key: candle_nn::linear_no_bias(state, state, vb.pp("k_proj"))?,
+            value: candle_nn::linear(state, state, vb.pp("v_proj"))?,
+            out: candle_nn::linear(state, state, vb.pp("out_proj"))?,
+        })
+    }
+
+    // The attention's output for `x` over `source`, with the scores before
+    // the softmax, [batch, heads, tokens, source length]
+    fn forward(&self, x: &Tensor, source: &Tensor, mask: Option<&Tensor>, heads: usize) -> Result<(Tensor, Tensor)> {
+        let (_, _, state) = x.dims3()?;
+        let scale = ((state / heads) as f64).powf(-0.25);
+        let q = (split_heads(&self.query.forward(x)?, heads)? * scale)?;
+        let k = (split_heads(&self.key.forward(source)?, heads)?.transpose(2, 3)? * scale)?;
+        let v = split_heads(&self.value.forward(source)?, heads)?.contiguous()?;
+        let scores = q.matmul(&k)?;
+        let masked = match mask {
+            Some(mask) => scores.broadcast_add(mask)?,
+            None => scores.clone(),
+        };
+        let weights = candle_nn::ops::softmax_last_dim(&masked)?;
+        let output = weights.matmul(&v)?.transpose(1, 2)?.flatten_from(2)?;
+        Ok((self.out.forward(&output)?, scores))
+    }
+}
+
+struct Block {
+    attn: Attention,
+    attn_ln: LayerNorm,
+    cross_attn: Attention,
+    cross_attn_ln: LayerNorm,
+    fc1: Linear,
+    fc2: Linear,
+    mlp_ln: LayerNorm,
+    // Its alignment heads
+    heads: Vec<usize>,
+}
+
+// The decoder again, up to its last layer with an alignment head, run once
+// over a window's text to read where each token attends in the audio. It is
+// built over the weights the model itself holds, so they aren't loaded twice.
+pub struct Aligner {
+    token_embedding: Embedding,
+    positional_embedding: Tensor,
+    blocks: Vec<Block>,
+    heads: usize,
+}
+
+impl Aligner {
+    // `vb` is at the decoder's weights, model.decoder
+    pub fn load(vb: VarBuilder, config: &Config, alignment_heads: &[(usize, usize)]) -> Result<Self> {
+        let state = config.d_model;
+        let layers = alignment_heads.iter().map(|&(layer, _)| layer + 1).max().unwrap_or(0);
+        let blocks = (0..layers)
+            .map(|layer| {
+                let vb = vb.pp(format!("layers.{}", layer));
+                Ok(Block {
+                    attn: Attention::load(state, vb.pp("self_attn"))?,
+                    attn_ln: candle_nn::layer_norm(state, 1e-5, vb.pp("self_attn_layer_norm"))?,
+                    cross_attn: Attention::load(state, vb.pp("encoder_attn"))?,
+                    cross_attn_ln: candle_nn::layer_norm(state, 1e-5, vb.pp("encoder_attn_layer_norm"))?,
+                    fc1: candle_nn::linear(state, state * 4, vb.pp("fc1"))?,
+                    fc2: candle_nn::linear(state * 4, state, vb.pp("fc2"))?,
+                    mlp_ln: candle_nn::layer_norm(state, 1e-5, vb.pp("final_layer_norm"))?,
+                    heads: alignment_heads.iter().filter(|&&(at, _)| at == layer).map(|&(_, head)| head).collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Aligner {
+            token_embedding: candle_nn::embedding(config.vocab_size, state, vb.pp("embed_tokens"))?,
+            positional_embedding: vb.get((config.max_target_positions, state), "embed_positions.weight")?,
+            blocks,
+            heads: config.decoder_attention_heads,
+        })
+    }
+
+    // Each alignment head's scores for every token against every frame of
+    // the encoded window, [head][token][frame]
+    fn cross_attention(&self, tokens: &[u32], audio_features: &Tensor) -> Result<Vec<Vec<Vec<f32>>>> {
+        let device = audio_features.device();
+        let length = tokens.len();
+        let input = Tensor::new(tokens, device)?.unsqueeze(0)?;
+        let mut x = self.token_embedding.forward(&input)?.broadcast_add(&self.positional_embedding.narrow(0, 0, length)?)?;
+        let mask: Vec<f32> = (0..length)
+            .flat_map(|row| (0..length).map(move |column| if column > row { f32::NEG_INFINITY } else { 0.0 }))
+            .collect();
+        let mask = Tensor::from_vec(mask, (length, length), device)?;
+
+        let mut scores = Vec::new();
+        for block in &self.blocks {
+            let normed = block.attn_ln.forward(&x)?;
+            let (attn, _) = block.attn.forward(&normed, &normed, Some(&mask), self.heads)?;
+            x = (x + attn)?;
+            let (cross, cross_scores) = block.cross_attn.forward(&block.cross_attn_ln.forward(&x)?, audio_features, None, self.heads)?;
+            for &head in &block.heads {
+                scores.push(cross_scores.i((0, head))?.to_dtype(DType::F32)?.to_vec2::<f32>()?);
+            }
+            x = (x + cross)?;
+            let mlp = block.fc2.forward(&block.fc1.forward(&block.mlp_ln.forward(&x)?)?.gelu()?)?;
+            x = (x + mlp)?;
+        }
+        Ok(scores)
+    }
+
+    // When each text token starts, in seconds into the window, and when the
+    // text ends, by dynamic time warping of the text over the alignment
+    // heads' attention, as openai's whisper times words. `tokens` are the
+    // prompt, <|notimestamps|> at `first`, the text and <|endoftext|>;
+    // `frames` is how many mel frames of the window hold audio.
+    pub fn token_times(&self, tokens: &[u32], first: usize, audio_features: &Tensor, frames: usize) -> Result<Vec<f64>> {
+        let (_, encoded, _) = audio_features.dims3()?;
+        let audio_frames = (frames / 2).clamp(1, encoded);
+        let scores = self.cross_attention(tokens, audio_features)?;
+        let head_count = scores.len().max(1) as f32;
+
+        let mut matrix = vec![vec![0.0f32; audio_frames]; tokens.len()];
+        for head in scores {
+            let mut weights: Vec<Vec<f32>> = head.iter().map(|row| softmax(&row[..audio_frames])).collect();
+            // Each frame standardized over the tokens
+            for frame in 0..audio_frames {
+                let mean = weights.iter().map(|row| row[frame]).sum::<f32>() / tokens.len() as f32;
+                let deviation = (weights.iter().map(|row| (row[frame] - mean).powi(2)).sum::<f32>() / tokens.len() as f32).sqrt();
+                for row in weights.iter_mut() {
+                    row[frame] = (row[frame] - mean) / deviation.max(f32::EPSILON);
+                }
+            }
+            for (total, row) in matrix.iter_mut().zip(&weights) {
+                for (sum, value) in total.iter_mut().zip(median_filter(row, MEDIAN_WIDTH)) {
+                    *sum += value / head_count;
+                }
+            }
+        }
+
+        // From <|notimestamps|>, whose row is where the first text token is
+        // read from, to the last text token
+        let rows = &matrix[first..tokens.len() - 1];
+        let mut times = vec![0.0; rows.len()];
+        let mut reached = None;
+        for (row, frame) in dtw(rows) {
+            if reached != Some(row) {
+                times[row] = frame as f64 / FRAMES_PER_SECOND;
+                reached = Some(row);
+            }
+        }
+        Ok(times)
+    }
+}
+
+fn softmax(values: &[f32]) -> Vec<f32> {
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = values.iter().map(|&value| (value - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|value| value / sum).collect()
+}
+
+// Median over `width` values centred on each, with the ends reflected
+fn median_filter(values: &[f32], width: usize) -> Vec<f32> {
+    let pad = width / 2;
+    if values.len() <= pad {
+        return values.to_vec();
+    }
+    let last = values.len() as isize - 1;
+    let reflected = |index: isize| values[index.abs().min(2 * last - index) as usize];
+    (0..values.len() as isize)
+        .map(|centre| {
+            let mut window: Vec<f32> = (centre - pad as isize..=centre + pad as isize).map(reflected).collect();
+            window.sort_by(f32::total_cmp);
+            window[pad]
+        })
+        .collect()
+}
+
+// The cheapest monotonic path through the rows of tokens against frames of
+// audio, where more attention is cheaper, as (token, frame) from the start
+fn dtw(rows: &[Vec<f32>]) -> Vec<(usize, usize)> {
+    let tokens = rows.len();
+    let frames = rows.first().map_or(0, Vec::len);
+    let at = |token: usize, frame: usize| token * (frames + 1) + frame;
+    let mut cost = vec![f64::INFINITY; (tokens + 1) * (frames + 1)];
+    // 0 steps diagonally, 1 to the next token, 2 to the next frame
+    let mut trace = vec![2u8; (tokens + 1) * (frames + 1)];
+    cost[0] = 0.0;
+    for frame in 1..=frames {
+        for token in 1..=tokens {
+            let (diagonal, up, left) = (cost[at(token - 1, frame - 1)], cost[at(token - 1, frame)], cost[at(token, frame - 1)]);
+            let (previous, step) = if diagonal < up && diagonal < left {
+                (diagonal, 0)
+            } else if up < diagonal && up < left {
+                (up, 1)
+            } else {
+                (left, 2)
+            };
+            cost[at(token, frame)] = previous - rows[token - 1][frame - 1] as f64;
+            trace[at(token, frame)] = step;
+        }
+    }
+
+    let mut path = Vec::new();
+    let (mut token, mut frame) = (tokens, frames);
+    while token > 0 && frame > 0 {
+        path.push((token - 1, frame - 1));
+        match trace[at(token, frame)] {
+            0 => {
+                token -= 1;
+                frame -= 1;
+            }
+            1 => token -= 1,
+            _ => frame -= 1,
+        }
+    }
+    path.reverse();
+    path
+}
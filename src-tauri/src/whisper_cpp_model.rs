@@ -0,0 +1,353 @@
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use whisper_rs::{
+    DtwMode, DtwModelPreset, DtwParameters, FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+    WhisperTokenData,
+};
+use crate::acceleration::Acceleration;
+use crate::database::TranscriptWord;
+use crate::transcription::SAMPLE_RATE;
+use crate::whisper::{main_language, TranscriptionResult, TranscriptionSegment};
+
+// What transcripts from these models are stored as coming from
+pub const ENGINE: &str = "whisper-rs";
+
+// A model is a folder holding the one whisper.cpp ggml file
+pub const MODEL_FILES: [&str; 1] = ["ggml-model.bin"];
+
+// Where a size's ggml file is published; it is saved as MODEL_FILES' one
+pub fn model_url(size: &str, _file: &str) -> String {
+    format!("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-{}.bin", size)
+}
+
+// Language is detected a window at a time, as Whisper hears the audio
+const WINDOW_MS: usize = 30_000;
+
+// How often progress is passed on while whisper.cpp works
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// whisper.cpp's times are in hundredths of a second
+const SECONDS_PER_TICK: f64 = 0.01;
+
+// What Whisper is asked to write down: the speech as spoken, or its
+// English translation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Task {
+    Transcribe,
+    Translate,
+}
+
+// A whisper.cpp model loaded on the CPU or the GPU backend it was built with
+pub struct WhisperModel {
+    size: String,
+    backend: Acceleration,
+    context: WhisperContext,
+    // Whether words are timed by DTW over the model's alignment heads, which
+    // whisper.cpp knows for OpenAI's sizes, rather than by the timestamp
+    // tokens' probabilities alone
+    aligned: bool,
+}
+
+fn alignment_heads(size: &str) -> Option<DtwModelPreset> {
+    let preset = match size {
+        "tiny.en" => DtwModelPreset::TinyEn,
+        "tiny" => DtwModelPreset::Tiny,
+        "base.en" => DtwModelPreset::BaseEn,
+        "base" => DtwModelPreset::Base,
+        "small.en" => DtwModelPreset::SmallEn,
+        "small" => DtwModelPreset::Small,
+        "medium.en" => DtwModelPreset::MediumEn,
+        "medium" => DtwModelPreset::Medium,
+        "large-v1" => DtwModelPreset::LargeV1,
+        "large-v2" => DtwModelPreset::LargeV2,
+        "large-v3" => DtwModelPreset::LargeV3,
+        "large-v3-turbo" => DtwModelPreset::LargeV3Turbo,
+        _ => return None,
+    };
+    Some(preset)
+}
+
+fn threads() -> usize {
+    std::thread::available_parallelism().map_or(4, |count| count.get()).min(8)
+}
+
+// Geometric mean of the tokens' probabilities
+fn mean_probability(logprobs: &[f64]) -> f32 {
+    if logprobs.is_empty() {
+        return 0.0;
+    }
+    (logprobs.iter().sum::<f64>() / logprobs.len() as f64).exp() as f32
+}
+
+impl WhisperModel {
+    pub fn load(dir: &Path, size: &str, backend: Acceleration) -> Result<Self> {
+        let heads = alignment_heads(size);
+        let mut params = WhisperContextParameters::default();
+        // whisper.cpp has the one GPU backend it was built with
+        params.use_gpu(backend != Acceleration::Cpu);
+        if let Some(model_preset) = heads.clone() {
+            params.dtw_parameters(DtwParameters { mode: DtwMode::ModelPreset { model_preset }, ..DtwParameters::default() });
+        }
+        let path = dir.join(MODEL_FILES[0]);
+        let context = WhisperContext::new_with_params(&path.to_string_lossy(), params)
+            .map_err(|e| anyhow::anyhow!("Failed to load the Whisper {} model: {}", size, e))?;
+        Ok(WhisperModel { size: size.to_string(), backend, context, aligned: heads.is_some() })
+    }
+
+    pub fn size(&self) -> &str {
+        &self.size
+    }
+
+    pub fn backend(&self) -> Acceleration {
+        self.backend
+    }
+
+    // The vocabulary as Whisper's initial prompt, text said before the
+    // audio, so the decoder leans towards writing the terms when it hears
+    // them. Terms past what the prompt has room for are left out.
+    fn prompt(&self, vocabulary: &[String]) -> String {
+        let room = (self.context.n_text_ctx() / 2 - 1).max(0) as usize;
+        (1..=vocabulary.len())
+            .rev()
+            .map(|count| format!(" {}", vocabulary[..count].join(", ")))
+            .find(|text| self.context.tokenize(text, room).is_ok())
+            .unwrap_or_default()
+    }
+
+    // The language of each window, with neighbouring windows in the same
+    // language joined, as (language, first sample, end sample)
+    fn language_runs(&self, state: &mut WhisperState, samples: &[f32]) -> Result<Vec<(String, usize, usize)>> {
+        state.pcm_to_mel(samples, threads()).map_err(|e| anyhow::anyhow!("whisper.cpp failed: {}", e))?;
+        let window = WINDOW_MS * SAMPLE_RATE as usize / 1000;
+        let mut runs: Vec<(String, usize, usize)> = Vec::new();
+        for start in (0..samples.len()).step_by(window) {
+            let (id, _) = state.lang_detect(start * 1000 / SAMPLE_RATE as usize, threads())
+                .map_err(|e| anyhow::anyhow!("Language detection failed: {}", e))?;
+            let language = whisper_rs::get_lang_str(id).unwrap_or("en").to_string();
+            let end = (start + window).min(samples.len());
+            match runs.last_mut() {
+                Some((last, _, run_end)) if *last == language => *run_end = end,
+                _ => runs.push((language, start, end)),
+            }
+        }
+        Ok(runs)
+    }
+
+    // Runs whisper.cpp over audio in one language on a thread of its own,
+    // so `on_progress` hears how far it has got and can stop it
+    fn decode(
+        &self,
+        state: &mut WhisperState,
+        samples: &[f32],
+        language: &str,
+        task: Task,
+        prompt: &str,
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<()> {
+        let percent = Arc::new(AtomicI32::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_n_threads(threads() as i32);
+        params.set_language(Some(language));
+        params.set_translate(task == Task::Translate);
+        params.set_token_timestamps(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+        if !prompt.is_empty() {
+            params.set_initial_prompt(prompt);
+        }
+        let reported = percent.clone();
+        params.set_progress_callback_safe(move |progress: i32| reported.store(progress, Ordering::Relaxed));
+        let stopping = stop.clone();
+        params.set_abort_callback_safe(move || stopping.load(Ordering::Relaxed));
+
+        let worker_state = &mut *state;
+        std::thread::scope(|scope| {
+            let (finished, done) = mpsc::channel();
+            let worker = scope.spawn(move || {
+                let decoded = worker_state.full(params, samples);
+                let _ = finished.send(());
+                decoded
+            });
+            let mut stopped = Ok(());
+            while let Err(RecvTimeoutError::Timeout) = done.recv_timeout(PROGRESS_INTERVAL) {
+                if stopped.is_ok() {
+                    stopped = on_progress(percent.load(Ordering::Relaxed) as f64 / 100.0);
+                    stop.store(stopped.is_err(), Ordering::Relaxed);
+                }
+            }
+            let decoded = worker.join().map_err(|_| anyhow::anyhow!("whisper.cpp panicked"))?;
+            stopped?;
+            decoded.map(|_| ()).map_err(|e| anyhow::anyhow!("whisper.cpp failed: {}", e))
+        })
+    }
+
+    // A word starts with every token that starts with a space. With the
+    // alignment heads, each word runs until the next one starts; otherwise
+    // it spans its tokens' timestamps.
+    fn words(&self, tokens: &[(String, WhisperTokenData)], offset: f64, start: f64, end: f64) -> Vec<TranscriptWord> {
+        let at = |ticks: i64| offset + ticks as f64 * SECONDS_PER_TICK;
+        let mut grouped: Vec<(String, f64, f64, Vec<f64>)> = Vec::new();
+        for (piece, data) in tokens {
+            let token_start = match self.aligned && data.t_dtw >= 0 {
+                true => at(data.t_dtw),
+                false => at(data.t0),
+            };
+            match grouped.last_mut() {
+                Some((word, _, word_end, logprobs)) if !piece.starts_with(' ') => {
+                    word.push_str(piece);
+                    *word_end = at(data.t1);
+                    logprobs.push(data.plog as f64);
+                }
+                _ => grouped.push((piece.clone(), token_start, at(data.t1), vec![data.plog as f64])),
+            }
+        }
+        let starts: Vec<f64> = grouped.iter().map(|(_, word_start, _, _)| *word_start).collect();
+        grouped.into_iter()
+            .enumerate()
+            .filter_map(|(index, (word, word_start, word_end, logprobs))| {
+                let word = word.trim().to_string();
+                if word.is_empty() {
+                    return None;
+                }
+                let start_seconds = word_start.clamp(start, end);
+                let end_seconds = match self.aligned {
+                    true => starts.get(index + 1).copied().unwrap_or(end),
+                    false => word_end,
+                };
+                Some(TranscriptWord { start_seconds, end_seconds: end_seconds.clamp(start_seconds, end), word, confidence: mean_probability(&logprobs) })
+            })
+            .collect()
+    }
+
+    // The segments of the last decode, `offset` seconds into the audio
+    fn segments(&self, state: &WhisperState, offset: f64) -> Result<Vec<TranscriptionSegment>> {
+        let failed = |e: whisper_rs::WhisperError| anyhow::anyhow!("whisper.cpp failed: {}", e);
+        let eot = self.context.token_eot();
+        let mut segments = Vec::new();
+        for index in 0..state.full_n_segments().map_err(failed)? {
+            let text = state.full_get_segment_text_lossy(index).map_err(failed)?.trim().to_string();
+            if text.is_empty() {
+                continue;
+            }
+            let start = offset + state.full_get_segment_t0(index).map_err(failed)? as f64 * SECONDS_PER_TICK;
+            let end = offset + state.full_get_segment_t1(index).map_err(failed)? as f64 * SECONDS_PER_TICK;
+            let mut tokens = Vec::new();
+            for token in 0..state.full_n_tokens(index).map_err(failed)? {
+                let data = state.full_get_token_data(index, token).map_err(failed)?;
+                // Timestamps and the other special tokens come after <|endoftext|>
+                if data.id < eot {
+                    tokens.push((state.full_get_token_text_lossy(index, token).map_err(failed)?, data));
+                }
+            }
+            let logprobs: Vec<f64> = tokens.iter().map(|(_, data)| data.plog as f64).collect();
+            segments.push(TranscriptionSegment {
+                start,
+                end,
+                text,
+                confidence: mean_probability(&logprobs),
+                words: self.words(&tokens, offset, start, end),
+                language: None,
+                avg_logprob: Some(logprobs.iter().sum::<f64>() / logprobs.len().max(1) as f64),
+            });
+        }
+        Ok(segments)
+    }
+
+    // Transcribes 16 kHz mono audio in `language` or, on multilingual
+    // models, the one detected for each 30 second window, so audio that
+    // switches language is decoded in each. The result's language is the one
+    // spoken longest. `vocabulary` biases decoding towards those terms.
+    pub fn transcribe(&mut self, samples: &[f32], language: Option<&str>, vocabulary: &[String]) -> Result<TranscriptionResult> {
+        self.run(samples, language, vocabulary, Task::Transcribe, &mut |_| Ok(()))
+    }
+
+    // Like transcribe, but the text is an English translation of the speech.
+    // The result's language is still the one spoken.
+    pub fn translate(&mut self, samples: &[f32], language: Option<&str>, vocabulary: &[String]) -> Result<TranscriptionResult> {
+        self.translate_with_progress(samples, language, vocabulary, &mut |_| Ok(()))
+    }
+
+    // As transcribe, with `on_progress` given the fraction of the audio done
+    // as it goes; an error from it stops the transcription
+    pub fn transcribe_with_progress(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        self.run(samples, language, vocabulary, Task::Transcribe, on_progress)
+    }
+
+    pub fn translate_with_progress(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        if !self.context.is_multilingual() {
+            return Err(anyhow::anyhow!("The {} model is English-only and can't translate; use a multilingual model", self.size));
+        }
+        self.run(samples, language, vocabulary, Task::Translate, on_progress)
+    }
+
+    fn run(
+        &mut self,
+        samples: &[f32],
+        language: Option<&str>,
+        vocabulary: &[String],
+        task: Task,
+        on_progress: &mut dyn FnMut(f64) -> Result<()>,
+    ) -> Result<TranscriptionResult> {
+        let started = Instant::now();
+        let fixed = match (language, self.context.is_multilingual()) {
+            (Some(language), false) if language != "en" => {
+                return Err(anyhow::anyhow!("The {} model only transcribes English; use a multilingual model for '{}'", self.size, language));
+            }
+            (_, false) => Some("en".to_string()),
+            (Some(language), true) if whisper_rs::get_lang_id(language).is_none() => {
+                return Err(anyhow::anyhow!("The {} model doesn't know the language '{}'", self.size, language));
+            }
+            (Some(language), true) => Some(language.to_string()),
+            (None, true) => None,
+        };
+        let prompt = self.prompt(vocabulary);
+        let mut state = self.context.create_state().map_err(|e| anyhow::anyhow!("whisper.cpp failed: {}", e))?;
+        let runs = match &fixed {
+            Some(language) => vec![(language.clone(), 0, samples.len())],
+            None => self.language_runs(&mut state, samples)?,
+        };
+
+        let mut segments: Vec<TranscriptionSegment> = Vec::new();
+        for (run_language, start, end) in runs.into_iter().filter(|(_, start, end)| end > start) {
+            let offset = start as f64 / SAMPLE_RATE as f64;
+            let covered = |fraction: f64| (start as f64 + fraction * (end - start) as f64) / samples.len() as f64;
+            self.decode(&mut state, &samples[start..end], &run_language, task, &prompt, &mut |fraction| on_progress(covered(fraction)))?;
+            let found = self.segments(&state, offset)?;
+            segments.extend(found.into_iter().map(|segment| TranscriptionSegment { language: Some(run_language.clone()), ..segment }));
+            on_progress(end as f64 / samples.len() as f64)?;
+        }
+        let language = fixed.or_else(|| main_language(&segments)).unwrap_or_else(|| "en".to_string());
+
+        let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ");
+        let confidence = match segments.is_empty() {
+            true => 0.0,
+            false => segments.iter().map(|segment| segment.confidence).sum::<f32>() / segments.len() as f32,
+        };
+        Ok(TranscriptionResult {
+            text,
+            segments,
+            language,
+            processing_time_ms: started.elapsed().as_millis() as u64,
+            confidence,
+        })
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{command, Emitter, State};
+use tokio::io::AsyncWriteExt;
+use crate::transcription::{self, MODEL_FILES};
+
+pub const WHISPER_DOWNLOAD_PROGRESS_EVENT: &str = "dwight://whisper-download-progress";
+
+// The sizes OpenAI publishes, which Hugging Face has for either engine
+const AVAILABLE_MODELS: [&str; 11] = [
+    "tiny", "tiny.en", "base", "base.en", "small", "small.en", "medium", "medium.en", "large-v2", "large-v3", "large-v3-turbo",
+];
+
+// Progress is sent each time this much more has arrived
+const PROGRESS_STEP_BYTES: u64 = 1 << 20;
+
+// Which sizes are downloading, so one isn't fetched twice at once
+pub struct WhisperDownloads {
+    active: Mutex<HashSet<String>>,
+}
+
+impl WhisperDownloads {
+    pub fn new() -> Self {
+        WhisperDownloads { active: Mutex::new(HashSet::new()) }
+    }
+
+    fn is_active(&self, size: &str) -> bool {
+        self.active.lock().map(|active| active.contains(size)).unwrap_or(false)
+    }
+}
+
+// Takes the size off the active downloads however the download ends
+struct ActiveDownload<'a> {
+    downloads: &'a WhisperDownloads,
+    size: String,
+}
+
+impl Drop for ActiveDownload<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.downloads.active.lock() {
+            active.remove(&self.size);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WhisperModelInfo {
+    pub size: String,
+    // English-only models end in ".en"
+    pub multilingual: bool,
+    pub installed: bool,
+    pub downloading: bool,
+    // What the model's folder holds, finished files or not
+    pub bytes_on_disk: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WhisperDownloadProgress {
+    pub size: String,
+    pub file: String,
+    // "downloading", "verifying", "done" for a file, or "complete" once the
+    // whole model is in place
+    pub status: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+}
+
+// What Hugging Face says a file should be. The weights are in LFS, whose
+// etag is their SHA-256; the small JSON files only come with a size.
+struct Expected {
+    sha256: Option<String>,
+    bytes: Option<u64>,
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(|value| value.trim_matches('"').to_string())
+}
+
+// Asks without following the redirect to the CDN, since the LFS headers are
+// on the redirect itself
+async fn expected(size: &str, file: &str) -> Result<Expected> {
+    let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build()?;
+    let response = client.head(transcription::model_url(size, file)).send().await?;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return Err(anyhow::anyhow!("Hugging Face has no {} for whisper-{} ({})", file, size, response.status()));
+    }
+    let sha256 = header(&response, "x-linked-etag").filter(|etag| etag.len() == 64 && etag.chars().all(|c| c.is_ascii_hexdigit()));
+    let bytes = header(&response, "x-linked-size")
+        .or_else(|| header(&response, "content-length").filter(|_| response.status().is_success()))
+        .and_then(|length| length.parse().ok());
+    Ok(Expected { sha256, bytes })
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn emit_progress(app_handle: &tauri::AppHandle, progress: WhisperDownloadProgress) {
+    if let Err(e) = app_handle.emit(WHISPER_DOWNLOAD_PROGRESS_EVENT, progress) {
+        eprintln!("Failed to emit Whisper download progress: {}", e);
+    }
+}
+
+// Fetches one file into `<file>.part`, carrying on from what an earlier
+// attempt left there, and moves it into place once it checks out
+async fn download_file(app_handle: &tauri::AppHandle, dir: &Path, size: &str, file: &str) -> Result<()> {
+    let target = dir.join(file);
+    let partial = dir.join(format!("{}.part", file));
+    let expected = expected(size, file).await?;
+    let progress = |status: &str, downloaded_bytes: u64| WhisperDownloadProgress {
+        size: size.to_string(),
+        file: file.to_string(),
+        status: status.to_string(),
+        downloaded_bytes,
+        total_bytes: expected.bytes,
+    };
+
+    let mut downloaded = tokio::fs::metadata(&partial).await.map(|metadata| metadata.len()).unwrap_or(0);
+    if expected.bytes.is_some_and(|bytes| downloaded > bytes) {
+        tokio::fs::remove_file(&partial).await?;
+        downloaded = 0;
+    }
+    if expected.bytes != Some(downloaded) || downloaded == 0 {
+        let mut request = reqwest::Client::new().get(transcription::model_url(size, file));
+        if downloaded > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", downloaded));
+        }
+        let mut response = request.send().await?.error_for_status()?;
+        // A server that ignores the range sends the whole file again
+        let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if !resumed {
+            downloaded = 0;
+        }
+        let mut output = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&partial)
+            .await?;
+        let mut reported = downloaded;
+        emit_progress(app_handle, progress("downloading", downloaded));
+        while let Some(chunk) = response.chunk().await? {
+            output.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+            if downloaded - reported >= PROGRESS_STEP_BYTES {
+                reported = downloaded;
+                emit_progress(app_handle, progress("downloading", downloaded));
+            }
+        }
+        output.flush().await?;
+    }
+
+    emit_progress(app_handle, progress("verifying", downloaded));
+    if let Some(bytes) = expected.bytes.filter(|&bytes| bytes != downloaded) {
+        return Err(anyhow::anyhow!("{} came to {} bytes instead of {}; download it again to resume", file, downloaded, bytes));
+    }
+    if let Some(sha256) = expected.sha256 {
+        let path = partial.clone();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(&path)).await??;
+        if !actual.eq_ignore_ascii_case(&sha256) {
+            // Corrupt, so resuming it would only add to the damage
+            tokio::fs::remove_file(&partial).await?;
+            return Err(anyhow::anyhow!("{} failed its checksum: expected SHA-256 {}, got {}", file, sha256, actual));
+        }
+    }
+    tokio::fs::rename(&partial, &target).await?;
+    emit_progress(app_handle, progress("done", downloaded));
+    Ok(())
+}
+
+fn folder_bytes(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().filter_map(|entry| entry.metadata().ok()).map(|metadata| metadata.len()).sum())
+        .unwrap_or(0)
+}
+
+fn model_folder(app_handle: &tauri::AppHandle, size: &str) -> Result<PathBuf, String> {
+    if !transcription::is_model_size(size) {
+        return Err(format!("'{}' is not a Whisper model size", size));
+    }
+    let dir = transcription::models_dir(app_handle).map_err(|e| format!("Whisper model error: {}", e))?;
+    Ok(dir.join(size))
+}
+
+// The sizes that can be downloaded, and which are already here
+#[command]
+pub async fn list_available_whisper_models(
+    app_handle: tauri::AppHandle,
+    downloads: State<'_, WhisperDownloads>,
+) -> Result<Vec<WhisperModelInfo>, String> {
+    AVAILABLE_MODELS.iter()
+        .map(|&size| {
+            let dir = model_folder(&app_handle, size)?;
+            Ok(WhisperModelInfo {
+                size: size.to_string(),
+                multilingual: !size.ends_with(".en"),
+                installed: MODEL_FILES.iter().all(|file| dir.join(file).exists()),
+                downloading: downloads.is_active(size),
+                bytes_on_disk: folder_bytes(&dir),
+            })
+        })
+        .collect()
+}
+
+// Downloads a model size from Hugging Face into the models folder, sending
+// progress events as it goes. Files already in place are kept, and a
+// download that was cut off carries on where it stopped.
+#[command]
+pub async fn download_whisper_model(
+    size: String,
+    app_handle: tauri::AppHandle,
+    downloads: State<'_, WhisperDownloads>,
+) -> Result<(), String> {
+    if !AVAILABLE_MODELS.contains(&size.as_str()) {
+        return Err(format!("'{}' is not a Whisper model size that can be downloaded; try one of {}", size, AVAILABLE_MODELS.join(", ")));
+    }
+    let dir = model_folder(&app_handle, &size)?;
+    {
+        let mut active = downloads.active.lock().map_err(|_| "Whisper downloads are poisoned".to_string())?;
+        if !active.insert(size.clone()) {
+            return Err(format!("The Whisper {} model is already downloading", size));
+        }
+    }
+    let _active = ActiveDownload { downloads: &downloads, size: size.clone() };
+
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| format!("Whisper model error: {}", e))?;
+    for file in MODEL_FILES {
+        if dir.join(file).exists() {
+            continue;
+        }
+        download_file(&app_handle, &dir, &size, file)
+            .await
+            .map_err(|e| format!("Failed to download the Whisper {} model: {}", size, e))?;
+    }
+    emit_progress(&app_handle, WhisperDownloadProgress {
+        size: size.clone(),
+        file: String::new(),
+        status: "complete".to_string(),
+        downloaded_bytes: folder_bytes(&dir),
+        total_bytes: None,
+    });
+    Ok(())
+}
+
+// Deletes a model's folder, unloading it first if it is loaded
+#[command]
+pub async fn delete_whisper_model(
+    size: String,
+    app_handle: tauri::AppHandle,
+    downloads: State<'_, WhisperDownloads>,
+) -> Result<bool, String> {
+    let dir = model_folder(&app_handle, &size)?;
+    if downloads.is_active(&size) {
+        return Err(format!("The Whisper {} model is still downloading", size));
+    }
+    if !dir.exists() {
+        return Ok(false);
+    }
+    let handle = app_handle.clone();
+    let unload_size = size.clone();
+    tokio::task::spawn_blocking(move || transcription::unload(&handle, &unload_size))
+        .await
+        .map_err(|e| format!("Whisper model error: {}", e))?
+        .map_err(|e| format!("Whisper model error: {}", e))?;
+    tokio::fs::remove_dir_all(&dir).await.map_err(|e| format!("Failed to delete the Whisper {} model: {}", size, e))?;
+    Ok(true)
+}